@@ -0,0 +1,146 @@
+//! Runs shaders over batches of invocations, filling all SIMD lanes of the compiled code.
+use std::cmp;
+
+use spirv_llvm::EntryPoint;
+use spirv_llvm::abi::{Invocation, Resources};
+
+/// Number of vertices shaded at once, unless the shader has even more lanes.
+pub const VERTEX_BATCH: usize = 8;
+
+/// Mask of the first `count` invocations of a batch.
+pub fn first(count: usize) -> u32 {
+    ((1u64 << count) - 1) as u32
+}
+
+/// Runs the vertex shader for all `invocations`, whose inputs are already filled in.
+///
+/// Unsafe because the shader accesses whatever memory its resources point to.
+pub unsafe fn shade_vertices(entry_point: &EntryPoint, invocations: &mut [Invocation]) {
+    let batch = cmp::max(VERTEX_BATCH, entry_point.lanes() as usize);
+    for vertices in invocations.chunks_mut(batch) {
+        let mask = first(vertices.len());
+        entry_point.call_batch(vertices, mask);
+    }
+}
+
+/// A 2x2 block of fragments. Fragment shaders run on whole quads, so that neighbouring
+/// fragments end up in neighbouring lanes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quad {
+    /// The top left pixel.
+    pub x: u32,
+    pub y: u32,
+    /// The covered fragments, bit 0 is the top left one, followed by top right, bottom left and
+    /// bottom right.
+    pub coverage: u8,
+}
+
+impl Quad {
+    /// Returns the pixel of fragment `index` of the quad.
+    pub fn fragment(&self, index: usize) -> (u32, u32) {
+        (self.x + index as u32 % 2, self.y + index as u32 / 2)
+    }
+
+    pub fn covers(&self, index: usize) -> bool {
+        self.coverage & (1 << index) != 0
+    }
+}
+
+/// Runs the fragment shader for `quads` and returns their invocations, four per quad in the
+/// order of `Quad::coverage`. `setup` fills in the inputs of the fragment at the given pixel.
+///
+/// Fragments that are not covered do not run, their invocations only fill up the lanes. Check
+/// `Invocation::killed` of the others before writing their outputs.
+///
+/// Unsafe because the shader accesses whatever memory its resources point to.
+pub unsafe fn shade_quads<F>(
+    entry_point: &EntryPoint,
+    resources: &Resources,
+    quads: &[Quad],
+    mut setup: F,
+) -> Vec<Invocation>
+where
+    F: FnMut(&mut Invocation, u32, u32),
+{
+    let mut invocations = Vec::with_capacity(quads.len() * 4);
+    for quad in quads {
+        for index in 0..4 {
+            let (x, y) = quad.fragment(index);
+            let mut invocation = Invocation::new(resources);
+            invocation.builtins.frag_coord.0 = [x as f32 + 0.5, y as f32 + 0.5, 0.0, 1.0];
+            invocation.builtins.helper_invocation = !quad.covers(index) as u32;
+            setup(&mut invocation, x, y);
+            invocations.push(invocation);
+        }
+    }
+
+    // The mask has room for 8 quads, which is a multiple of every lane width
+    for (quads, invocations) in quads.chunks(8).zip(invocations.chunks_mut(32)) {
+        let mask = quads.iter().enumerate().fold(0, |mask, (index, quad)| {
+            mask | u32::from(quad.coverage & 0xf) << (index * 4)
+        });
+        if mask != 0 {
+            entry_point.call_batch(invocations, mask);
+        }
+    }
+    invocations
+}
+
+#[cfg(test)]
+mod tests {
+    use rspirv::mr;
+    use spirv_llvm::{self, JitModule};
+    use spirv_llvm::abi::Slot;
+    use super::*;
+
+    /// Kills fragments right of x = 10, the others write `color * 2` when front facing.
+    const SHADER: &[u8] = include_bytes!("../../spirv_llvm/tests/shaders/abi.frag.spv");
+
+    #[test]
+    fn runs_covered_fragments_of_quads() {
+        let module = mr::load_bytes(SHADER).expect("invalid shader");
+        let llvm = match spirv_llvm::spirv_to_llvm_simd(&module, spirv_llvm::simd_lanes()) {
+            Ok(llvm) => llvm,
+            Err(_) => panic!("could not translate shader"),
+        };
+        let jit = match JitModule::new(llvm) {
+            Ok(jit) => jit,
+            Err(_) => panic!("could not compile shader"),
+        };
+        let entry_point = jit.entry_point("main").unwrap();
+        let resources = Resources::default();
+        let quads = [
+            Quad { x: 0, y: 0, coverage: 0b0110 },
+            Quad { x: 12, y: 2, coverage: 0b0001 },
+            Quad { x: 4, y: 0, coverage: 0 },
+        ];
+        let invocations = unsafe {
+            shade_quads(&entry_point, &resources, &quads, |invocation, x, y| {
+                invocation.builtins.front_facing = 1;
+                invocation.inputs[0] = Slot::from_f32([x as f32, y as f32, 1.0, 1.0]);
+            })
+        };
+        assert_eq!(invocations.len(), 12);
+
+        let helpers: Vec<u32> = invocations
+            .iter()
+            .map(|invocation| invocation.builtins.helper_invocation)
+            .collect();
+        assert_eq!(helpers, [1, 0, 0, 1, 0, 1, 1, 1, 1, 1, 1, 1]);
+        assert_eq!(invocations[1].builtins.frag_coord.0, [1.5, 0.5, 0.0, 1.0]);
+        assert_eq!(invocations[7].builtins.frag_coord.0, [13.5, 3.5, 0.0, 1.0]);
+
+        let outputs: Vec<[f32; 4]> = invocations
+            .iter()
+            .map(|invocation| invocation.outputs[0].to_f32())
+            .collect();
+        // Helper invocations are masked off and keep their outputs
+        assert_eq!(outputs[0], [0.0; 4]);
+        assert_eq!(outputs[1], [2.0, 0.0, 2.0, 2.0]);
+        assert_eq!(outputs[2], [0.0, 2.0, 2.0, 2.0]);
+        assert_eq!(outputs[3], [0.0; 4]);
+        assert_eq!(invocations[4].killed, 1);
+        assert!(invocations[5..].iter().all(|invocation| invocation.killed == 0));
+        assert!(outputs[4..].iter().all(|&output| output == [0.0; 4]));
+    }
+}
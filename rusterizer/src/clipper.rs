@@ -0,0 +1,439 @@
+//! Clipping of triangles in clip coordinates, before the perspective divide.
+//!
+//! Triangles are clipped against the near and far planes `0 <= z <= w`, unless depth clamping
+//! is on, against `w > 0` and against the ClipDistance outputs of the vertex shader, and culled
+//! if one of their CullDistance outputs is negative at all vertices. Instead of clipping x and y
+//! to the viewport, triangles are only clipped to a guard band around it, which is small enough
+//! for the rasterizer and large enough that almost no triangles need to be split. The pixels of
+//! the viewport are left to the scissor.
+//!
+//! Vertices made by clipping are described by the weights of the original vertices, which
+//! interpolate their outputs.
+use std::mem;
+
+use ffi_types as vk;
+use pipeline::RasterizationState;
+use rasterizer::MAX_COORDINATE;
+use spirv_llvm::abi::{BuiltIns, MAX_CLIP_DISTANCES, MAX_CULL_DISTANCES};
+
+/// Window coordinates of vertices are clipped to this distance of the origin, well within
+/// `MAX_COORDINATE`.
+pub const GUARD_BAND: f32 = MAX_COORDINATE / 2.0;
+
+/// The `viewportBoundsRange`, which keeps viewports inside the guard band.
+pub const VIEWPORT_BOUNDS: [f32; 2] = [-32768.0, 32767.0];
+
+/// Vertices closer to `w = 0` are clipped, so that the perspective divide is finite.
+const MIN_W: f32 = 1.0e-6;
+
+/// A vertex of a clipped triangle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Vertex {
+    /// Clip coordinates.
+    pub position: [f32; 4],
+    /// The weights of the vertices of the original triangle. For its own vertices, one of them
+    /// is 1.
+    pub weights: [f32; 3],
+}
+
+impl Vertex {
+    fn original(position: [f32; 4], index: usize) -> Self {
+        let mut weights = [0.0; 3];
+        weights[index] = 1.0;
+        Vertex {
+            position: position,
+            weights: weights,
+        }
+    }
+
+    /// The vertex at `t` on the way to `other`.
+    fn lerp(&self, other: &Vertex, t: f32) -> Vertex {
+        let mut vertex = *self;
+        for (value, &other) in vertex.position.iter_mut().zip(&other.position) {
+            *value += (other - *value) * t;
+        }
+        for (value, &other) in vertex.weights.iter_mut().zip(&other.weights) {
+            *value += (other - *value) * t;
+        }
+        vertex
+    }
+
+    /// Writes the outputs of the vertex to `outputs`, interpolated from the `values` of the
+    /// vertices of the original triangle. Flat outputs cannot be interpolated, they are the
+    /// caller's to take from the provoking vertex.
+    pub fn interpolate(&self, values: [&[f32]; 3], outputs: &mut [f32]) {
+        for (index, output) in outputs.iter_mut().enumerate() {
+            *output = (0..3)
+                .map(|vertex| self.weights[vertex] * values[vertex][index])
+                .sum();
+        }
+    }
+}
+
+/// A plane that triangles are clipped against, vertices at negative distances are outside.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Plane {
+    Near,
+    Far,
+    W,
+    Left,
+    Right,
+    Top,
+    Bottom,
+    /// The ClipDistance output with this index.
+    Distance(usize),
+}
+
+/// Clips the triangles of a draw.
+#[derive(Clone, Copy, Debug)]
+pub struct Clipper {
+    depth_clamp: bool,
+    viewport: vk::Viewport,
+    /// The guard band in normalized device coordinates: the least and greatest x and y.
+    guard_band: [f32; 4],
+    /// How many ClipDistance and CullDistance outputs the vertex shader writes.
+    clip_distances: usize,
+    cull_distances: usize,
+}
+
+impl Clipper {
+    pub fn new(
+        state: &RasterizationState,
+        viewport: &vk::Viewport,
+        clip_distances: usize,
+        cull_distances: usize,
+    ) -> Self {
+        debug_assert!(clip_distances <= MAX_CLIP_DISTANCES);
+        debug_assert!(cull_distances <= MAX_CULL_DISTANCES);
+        // The device coordinates that window coordinates -GUARD_BAND and GUARD_BAND come from,
+        // the viewport height may be negative
+        let bounds = |offset: f32, extent: f32| {
+            let scale = extent / 2.0;
+            let center = offset + scale;
+            let (first, second) = ((-GUARD_BAND - center) / scale, (GUARD_BAND - center) / scale);
+            (first.min(second), first.max(second))
+        };
+        let (left, right) = bounds(viewport.x, viewport.width);
+        let (top, bottom) = bounds(viewport.y, viewport.height);
+        Clipper {
+            depth_clamp: state.depth_clamp,
+            viewport: *viewport,
+            guard_band: [left, right, top, bottom],
+            clip_distances: clip_distances,
+            cull_distances: cull_distances,
+        }
+    }
+
+    /// The planes to clip against, at most 32.
+    fn planes(&self) -> impl Iterator<Item = Plane> {
+        let depth: &[Plane] = if self.depth_clamp {
+            &[]
+        } else {
+            &[Plane::Near, Plane::Far]
+        };
+        depth
+            .iter()
+            .cloned()
+            .chain([Plane::W, Plane::Left, Plane::Right, Plane::Top, Plane::Bottom].iter().cloned())
+            .chain((0..self.clip_distances).map(Plane::Distance))
+    }
+
+    fn distance(&self, plane: Plane, vertex: &Vertex, vertices: &[&BuiltIns; 3]) -> f32 {
+        let [x, y, z, w] = vertex.position;
+        let guard_band = &self.guard_band;
+        match plane {
+            Plane::Near => z,
+            Plane::Far => w - z,
+            Plane::W => w - MIN_W,
+            Plane::Left => x - guard_band[0] * w,
+            Plane::Right => guard_band[1] * w - x,
+            Plane::Top => y - guard_band[2] * w,
+            Plane::Bottom => guard_band[3] * w - y,
+            // Clip distances are linear like the outputs
+            Plane::Distance(index) => {
+                (0..3)
+                    .map(|original| {
+                        vertex.weights[original] * vertices[original].clip_distance[index]
+                    })
+                    .sum()
+            }
+        }
+    }
+
+    /// Clips the triangle of the vertex shader outputs `vertices` and calls `emit` for each of
+    /// the triangles its remains are split into, in the winding order of the original. A
+    /// triangle that needs no clipping is emitted as it is, one that is outside or culled is not
+    /// emitted at all.
+    pub fn clip<F>(&self, vertices: [&BuiltIns; 3], mut emit: F)
+    where
+        F: FnMut([Vertex; 3]),
+    {
+        let culled = (0..self.cull_distances).any(|index| {
+            vertices.iter().all(|vertex| vertex.cull_distance[index] < 0.0)
+        });
+        if culled {
+            return;
+        }
+
+        let mut polygon: Vec<Vertex> = (0..3)
+            .map(|index| Vertex::original(vertices[index].position.0, index))
+            .collect();
+        // The planes any and all of the vertices are outside of, NaN is outside of everything
+        let (mut any, mut all) = (0u32, !0u32);
+        for vertex in &polygon {
+            let outside = self.planes().enumerate().fold(0, |outside, (index, plane)| {
+                if self.distance(plane, vertex, &vertices) >= 0.0 {
+                    outside
+                } else {
+                    outside | 1 << index
+                }
+            });
+            any |= outside;
+            all &= outside;
+        }
+        if all != 0 {
+            return;
+        }
+        if any == 0 {
+            emit([polygon[0], polygon[1], polygon[2]]);
+            return;
+        }
+
+        let mut clipped = Vec::with_capacity(polygon.len() + 1);
+        for (_, plane) in self.planes().enumerate().filter(|&(index, _)| any & 1 << index != 0) {
+            clipped.clear();
+            for (index, from) in polygon.iter().enumerate() {
+                let to = &polygon[(index + 1) % polygon.len()];
+                let from_distance = self.distance(plane, from, &vertices);
+                let to_distance = self.distance(plane, to, &vertices);
+                let (from_inside, to_inside) = (from_distance >= 0.0, to_distance >= 0.0);
+                if from_inside {
+                    clipped.push(*from);
+                }
+                // Always interpolate from the inside, so that triangles sharing the edge get
+                // the same vertex
+                if from_inside && !to_inside {
+                    let t = from_distance / (from_distance - to_distance);
+                    clipped.push(from.lerp(to, t));
+                } else if !from_inside && to_inside {
+                    let t = to_distance / (to_distance - from_distance);
+                    clipped.push(to.lerp(from, t));
+                }
+            }
+            if clipped.len() < 3 {
+                return;
+            }
+            mem::swap(&mut polygon, &mut clipped);
+        }
+        for index in 1..polygon.len() - 1 {
+            emit([polygon[0], polygon[index], polygon[index + 1]]);
+        }
+    }
+
+    /// The window coordinates and depth of `vertex`, after the perspective divide and the
+    /// viewport transform.
+    pub fn window(&self, vertex: &Vertex) -> [f32; 3] {
+        let [x, y, z, w] = vertex.position;
+        let viewport = &self.viewport;
+        let (width, height) = (viewport.width / 2.0, viewport.height / 2.0);
+        [
+            viewport.x + width + x / w * width,
+            viewport.y + height + y / w * height,
+            viewport.minDepth + z / w * (viewport.maxDepth - viewport.minDepth),
+        ]
+    }
+
+    /// The depth of a fragment at the interpolated `depth`, which is clamped to the depth range
+    /// of the viewport with depth clamping.
+    pub fn fragment_depth(&self, depth: f32) -> f32 {
+        if self.depth_clamp {
+            let (min, max) = (self.viewport.minDepth, self.viewport.maxDepth);
+            depth.clamp(min.min(max), min.max(max))
+        } else {
+            depth
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(depth_clamp: bool) -> RasterizationState {
+        RasterizationState {
+            depth_clamp: depth_clamp,
+            discard: false,
+            cull_mode: vk::CULL_MODE_NONE,
+            front_face: vk::FRONT_FACE_COUNTER_CLOCKWISE,
+            depth_bias: None,
+            line_width: 1.0,
+        }
+    }
+
+    const VIEWPORT: vk::Viewport = vk::Viewport {
+        x: 0.0,
+        y: 0.0,
+        width: 100.0,
+        height: 50.0,
+        minDepth: 0.0,
+        maxDepth: 1.0,
+    };
+
+    fn clipper(depth_clamp: bool, clip_distances: usize, cull_distances: usize) -> Clipper {
+        Clipper::new(&state(depth_clamp), &VIEWPORT, clip_distances, cull_distances)
+    }
+
+    fn vertex(position: [f32; 4]) -> BuiltIns {
+        let mut vertex = BuiltIns::default();
+        vertex.position.0 = position;
+        vertex
+    }
+
+    fn clip(clipper: &Clipper, vertices: &[BuiltIns; 3]) -> Vec<[Vertex; 3]> {
+        let mut triangles = Vec::new();
+        clipper.clip(
+            [&vertices[0], &vertices[1], &vertices[2]],
+            |triangle| triangles.push(triangle),
+        );
+        // Every vertex is where its weights put it
+        for vertex in triangles.iter().flat_map(|triangle| triangle.iter()) {
+            let positions: Vec<&[f32]> =
+                vertices.iter().map(|vertex| &vertex.position.0[..]).collect();
+            let mut position = [0.0; 4];
+            vertex.interpolate([positions[0], positions[1], positions[2]], &mut position);
+            for (&expected, &actual) in vertex.position.iter().zip(&position) {
+                let tolerance = 1.0e-4 * expected.abs().max(1.0);
+                assert!((expected - actual).abs() < tolerance, "{:?}", vertex);
+            }
+        }
+        triangles
+    }
+
+    /// Twice the area of the triangle in normalized device coordinates, positive if counter
+    /// clockwise.
+    fn area(triangle: &[Vertex; 3]) -> f32 {
+        let device = |vertex: &Vertex| {
+            let [x, y, _, w] = vertex.position;
+            (x / w, y / w)
+        };
+        let (a, b, c) = (device(&triangle[0]), device(&triangle[1]), device(&triangle[2]));
+        (b.0 - a.0) * (c.1 - a.1) - (c.0 - a.0) * (b.1 - a.1)
+    }
+
+    #[test]
+    fn keeps_triangles_within_the_guard_band() {
+        // Reaches far beyond the viewport, but not beyond the guard band
+        let vertices = [
+            vertex([-30.0, -2.0, 0.5, 1.0]),
+            vertex([40.0, -2.0, 0.5, 1.0]),
+            vertex([0.0, 3.0, 0.5, 1.0]),
+        ];
+        let triangles = clip(&clipper(false, 0, 0), &vertices);
+        assert_eq!(triangles.len(), 1);
+        assert_eq!(triangles[0][2].weights, [0.0, 0.0, 1.0]);
+        assert_eq!(triangles[0][1].position, vertices[1].position.0);
+        assert_eq!(clipper(false, 0, 0).window(&triangles[0][2]), [50.0, 100.0, 0.5]);
+    }
+
+    #[test]
+    fn clips_to_the_guard_band() {
+        let vertices = [
+            vertex([-1.0e5, -1.0e5, 0.5, 1.0]),
+            vertex([1.0e5, 0.0, 0.5, 1.0]),
+            vertex([0.0, 1.0e5, 0.5, 1.0]),
+        ];
+        let clipper = clipper(false, 0, 0);
+        let triangles = clip(&clipper, &vertices);
+        assert!(triangles.len() > 1);
+        for triangle in &triangles {
+            assert!(area(triangle) > 0.0);
+            for vertex in triangle {
+                let window = clipper.window(vertex);
+                assert!(window[0].abs() <= GUARD_BAND * 1.001, "{:?}", window);
+                assert!(window[1].abs() <= GUARD_BAND * 1.001, "{:?}", window);
+            }
+        }
+    }
+
+    #[test]
+    fn clips_to_the_near_and_far_planes() {
+        // Vertex 0 is behind the eye, vertex 2 beyond the far plane
+        let vertices = [
+            vertex([0.0, 0.0, -1.0, -0.5]),
+            vertex([1.0, 0.0, 0.5, 2.0]),
+            vertex([0.0, 1.0, 3.0, 2.0]),
+        ];
+        let triangles = clip(&clipper(false, 0, 0), &vertices);
+        assert_eq!(triangles.len(), 3);
+        for vertex in triangles.iter().flat_map(|triangle| triangle.iter()) {
+            let [_, _, z, w] = vertex.position;
+            assert!(z >= -1.0e-6 && z <= w + 1.0e-6, "{:?}", vertex);
+        }
+        // The fan keeps the winding order of the visible part
+        assert!(triangles.iter().all(|triangle| area(triangle) < 0.0));
+
+        // Nothing in front of the eye
+        let behind = [
+            vertex([0.0, 0.0, -1.0, -1.0]),
+            vertex([1.0, 0.0, -1.0, -1.0]),
+            vertex([0.0, 1.0, -1.0, -1.0]),
+        ];
+        assert!(clip(&clipper(false, 0, 0), &behind).is_empty());
+    }
+
+    #[test]
+    fn clamps_depth_instead() {
+        let clipper = clipper(true, 0, 0);
+        let vertices = [
+            vertex([0.0, 0.0, -1.0, 1.0]),
+            vertex([1.0, 0.0, 0.5, 1.0]),
+            vertex([0.0, 1.0, 3.0, 2.0]),
+        ];
+        let triangles = clip(&clipper, &vertices);
+        assert_eq!(triangles.len(), 1);
+        assert_eq!(clipper.window(&triangles[0][0])[2], -1.0);
+        assert_eq!(clipper.fragment_depth(-1.0), 0.0);
+        assert_eq!(clipper.fragment_depth(1.5), 1.0);
+        assert_eq!(self::clipper(false, 0, 0).fragment_depth(1.5), 1.5);
+
+        // Still clipped at w = 0
+        let vertices = [
+            vertex([0.0, 0.0, 0.0, -1.0]),
+            vertex([1.0, 0.0, 0.5, 1.0]),
+            vertex([0.0, 1.0, 0.5, 1.0]),
+        ];
+        let triangles = clip(&clipper, &vertices);
+        assert!(!triangles.is_empty());
+        for vertex in triangles.iter().flat_map(|triangle| triangle.iter()) {
+            assert!(vertex.position[3] > 0.0);
+        }
+    }
+
+    #[test]
+    fn clips_and_culls_by_distances() {
+        let mut vertices = [
+            vertex([0.0, 0.0, 0.5, 1.0]),
+            vertex([1.0, 0.0, 0.5, 1.0]),
+            vertex([0.0, 1.0, 0.5, 1.0]),
+        ];
+        // The second clip distance is x - 0.5
+        for vertex in &mut vertices {
+            vertex.clip_distance[1] = vertex.position.0[0] - 0.5;
+            vertex.cull_distance[0] = -1.0;
+        }
+        // Ignored beyond the outputs the shader writes
+        assert_eq!(clip(&clipper(false, 1, 0), &vertices).len(), 1);
+        let triangles = clip(&clipper(false, 2, 0), &vertices);
+        assert_eq!(triangles.len(), 1);
+        for vertex in &triangles[0] {
+            assert!(vertex.position[0] >= 0.5, "{:?}", vertex);
+        }
+        let mut outputs = [0.0];
+        triangles[0][0].interpolate([&[2.0], &[4.0], &[8.0]], &mut outputs);
+        assert_eq!(outputs, [3.0]);
+
+        assert!(clip(&clipper(false, 0, 1), &vertices).is_empty());
+        vertices[2].cull_distance[0] = 0.0;
+        assert_eq!(clip(&clipper(false, 0, 1), &vertices).len(), 1);
+    }
+}
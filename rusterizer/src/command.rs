@@ -0,0 +1,319 @@
+//! Commands recorded into command buffers, which run when the command buffer is submitted.
+use std::ptr;
+
+use spirv_llvm::abi::Resources;
+use ffi_types as vk;
+use compute;
+use descriptor::{BoundSet, DescriptorSet, MAX_BOUND_DESCRIPTOR_SETS};
+use draw::{self, DynamicState, IndexBuffer, VertexBuffer, Vertices};
+use memory::Buffer;
+use pipeline::{DepthBias, Pipeline, MAX_PUSH_CONSTANTS_SIZE};
+use render_pass::{self, Framebuffer, RenderPass, RenderPassInstance};
+
+/// A recorded command. Objects are referred to by their handles, which the application has to
+/// keep alive until the command buffer is done executing.
+#[derive(Clone, Debug)]
+pub enum Command {
+    BindPipeline(vk::PipelineBindPoint, *const Pipeline),
+    /// Binds `sets` from `first_set` on, with an offset for every dynamic descriptor of them.
+    BindDescriptorSets {
+        bind_point: vk::PipelineBindPoint,
+        first_set: u32,
+        sets: Vec<*const DescriptorSet>,
+        dynamic_offsets: Vec<u32>,
+    },
+    /// Sets the push constants from `offset` on to `values`.
+    PushConstants { offset: u32, values: Vec<u8> },
+    /// Starts the first subpass of `render_pass` within `area` of `framebuffer`, with the
+    /// encoded clear values of its attachments.
+    BeginRenderPass {
+        render_pass: *const RenderPass,
+        framebuffer: *const Framebuffer,
+        area: vk::Rect2D,
+        clear_texels: Vec<Vec<u8>>,
+    },
+    NextSubpass,
+    EndRenderPass,
+    Dispatch { base: [u32; 3], count: [u32; 3] },
+    /// Dispatches the workgroups of the `vk::DispatchIndirectCommand` at `offset` of `buffer`.
+    DispatchIndirect {
+        buffer: *const Buffer,
+        offset: vk::DeviceSize,
+    },
+    /// Binds `buffers` to the vertex input bindings from `first_binding` on.
+    BindVertexBuffers {
+        first_binding: u32,
+        buffers: Vec<VertexBuffer>,
+    },
+    BindIndexBuffer(IndexBuffer),
+    SetViewport(vk::Viewport),
+    SetScissor(vk::Rect2D),
+    SetDepthBias(DepthBias),
+    SetBlendConstants([f32; 4]),
+    /// Sets the stencil value of the faces of `vk::StencilFaceFlags`.
+    SetStencilCompareMask(vk::StencilFaceFlags, u32),
+    SetStencilWriteMask(vk::StencilFaceFlags, u32),
+    SetStencilReference(vk::StencilFaceFlags, u32),
+    Draw {
+        vertex_count: u32,
+        instance_count: u32,
+        first_vertex: u32,
+        first_instance: u32,
+    },
+    DrawIndexed {
+        index_count: u32,
+        instance_count: u32,
+        first_index: u32,
+        vertex_offset: i32,
+        first_instance: u32,
+    },
+}
+
+/// The state that commands set for the commands after them in the same command buffer.
+struct State {
+    compute: *const Pipeline,
+    graphics: *const Pipeline,
+    /// Descriptor sets bound for the compute pipeline, indexed by set number.
+    compute_sets: Vec<Option<BoundSet>>,
+    /// Descriptor sets bound for the graphics pipeline.
+    graphics_sets: Vec<Option<BoundSet>>,
+    /// Shared by all pipelines, undefined until they are pushed.
+    push_constants: [u8; MAX_PUSH_CONSTANTS_SIZE as usize],
+    render_pass: Option<RenderPassInstance>,
+    /// Vertex buffers, indexed by binding.
+    vertex_buffers: Vec<Option<VertexBuffer>>,
+    index_buffer: Option<IndexBuffer>,
+    dynamic: DynamicState,
+}
+
+impl State {
+    /// The resources of a dispatch or draw of the pipeline at `bind_point`.
+    fn resources(&self, bind_point: vk::PipelineBindPoint) -> Resources {
+        let sets = if bind_point == vk::PIPELINE_BIND_POINT_COMPUTE {
+            &self.compute_sets
+        } else {
+            &self.graphics_sets
+        };
+        let mut resources = Resources::default();
+        for (table, set) in resources.descriptor_sets.iter_mut().zip(sets) {
+            if let Some(ref set) = *set {
+                *table = set.table();
+            }
+        }
+        resources.push_constants = self.push_constants.as_ptr();
+        resources
+    }
+}
+
+/// Runs `commands` in order and returns once they are done.
+///
+/// Unsafe because the commands refer to objects by their handles, which have to be valid.
+pub unsafe fn execute(commands: &[Command]) {
+    let mut state = State {
+        compute: ptr::null(),
+        graphics: ptr::null(),
+        compute_sets: (0..MAX_BOUND_DESCRIPTOR_SETS).map(|_| None).collect(),
+        graphics_sets: (0..MAX_BOUND_DESCRIPTOR_SETS).map(|_| None).collect(),
+        push_constants: [0; MAX_PUSH_CONSTANTS_SIZE as usize],
+        render_pass: None,
+        vertex_buffers: Vec::new(),
+        index_buffer: None,
+        dynamic: DynamicState::default(),
+    };
+    for command in commands {
+        match *command {
+            Command::BindPipeline(vk::PIPELINE_BIND_POINT_COMPUTE, pipeline) => {
+                state.compute = pipeline
+            }
+            Command::BindPipeline(_, pipeline) => state.graphics = pipeline,
+            Command::BindDescriptorSets {
+                bind_point,
+                first_set,
+                ref sets,
+                ref dynamic_offsets,
+            } => {
+                let bound = if bind_point == vk::PIPELINE_BIND_POINT_COMPUTE {
+                    &mut state.compute_sets
+                } else {
+                    &mut state.graphics_sets
+                };
+                // Every set takes the dynamic offsets of its descriptors in turn
+                let mut offsets = &dynamic_offsets[..];
+                for (slot, &set) in bound[first_set as usize..].iter_mut().zip(sets) {
+                    let (own, rest) = offsets.split_at((*set).dynamic_count());
+                    *slot = Some((*set).bind(own));
+                    offsets = rest;
+                }
+            }
+            Command::PushConstants { offset, ref values } => {
+                let offset = offset as usize;
+                state.push_constants[offset..offset + values.len()].copy_from_slice(values);
+            }
+            Command::BeginRenderPass {
+                render_pass,
+                framebuffer,
+                area,
+                ref clear_texels,
+            } => {
+                if let Some(instance) = state.render_pass.take() {
+                    warn!("Ending a render pass that was not ended before the next one");
+                    instance.end();
+                }
+                state.render_pass = Some(RenderPassInstance::begin(
+                    render_pass,
+                    framebuffer,
+                    area,
+                    clear_texels.clone(),
+                    render_pass::fill_garbage(),
+                ));
+            }
+            Command::NextSubpass => {
+                match state.render_pass {
+                    Some(ref mut instance) => instance.next_subpass(),
+                    None => warn!("Skipping next subpass outside of a render pass"),
+                }
+            }
+            Command::EndRenderPass => {
+                match state.render_pass.take() {
+                    Some(instance) => instance.end(),
+                    None => warn!("Skipping end of a render pass that did not begin"),
+                }
+            }
+            Command::Dispatch { base, count } => {
+                let resources = state.resources(vk::PIPELINE_BIND_POINT_COMPUTE);
+                dispatch(&state, &resources, base, count)
+            }
+            Command::DispatchIndirect { buffer, offset } => {
+                let data = (*buffer).at(offset) as *const vk::DispatchIndirectCommand;
+                if data.is_null() {
+                    warn!("Skipping indirect dispatch from a buffer without memory");
+                    continue;
+                }
+                let command = ptr::read_unaligned(data);
+                let count = [command.x, command.y, command.z];
+                if exceeds_workgroup_count(count) {
+                    warn!("Skipping indirect dispatch of {:?} workgroups", count);
+                    continue;
+                }
+                let resources = state.resources(vk::PIPELINE_BIND_POINT_COMPUTE);
+                dispatch(&state, &resources, [0; 3], count)
+            }
+            Command::BindVertexBuffers {
+                first_binding,
+                ref buffers,
+            } => {
+                let first_binding = first_binding as usize;
+                let end = first_binding + buffers.len();
+                if state.vertex_buffers.len() < end {
+                    state.vertex_buffers.resize(end, None);
+                }
+                for (slot, &buffer) in state.vertex_buffers[first_binding..].iter_mut().zip(
+                    buffers,
+                )
+                {
+                    *slot = Some(buffer);
+                }
+            }
+            Command::BindIndexBuffer(buffer) => state.index_buffer = Some(buffer),
+            Command::SetViewport(viewport) => state.dynamic.viewport = Some(viewport),
+            Command::SetScissor(scissor) => state.dynamic.scissor = Some(scissor),
+            Command::SetDepthBias(bias) => state.dynamic.depth_bias = bias,
+            Command::SetBlendConstants(constants) => state.dynamic.blend_constants = constants,
+            Command::SetStencilCompareMask(faces, value) => {
+                set_faces(&mut state.dynamic.stencil_compare_mask, faces, value)
+            }
+            Command::SetStencilWriteMask(faces, value) => {
+                set_faces(&mut state.dynamic.stencil_write_mask, faces, value)
+            }
+            Command::SetStencilReference(faces, value) => {
+                set_faces(&mut state.dynamic.stencil_reference, faces, value)
+            }
+            Command::Draw {
+                vertex_count,
+                instance_count,
+                first_vertex,
+                first_instance,
+            } => {
+                let vertices = Vertices::Sequential {
+                    first: first_vertex,
+                    count: vertex_count,
+                };
+                draw(&state, &vertices, first_instance, instance_count)
+            }
+            Command::DrawIndexed {
+                index_count,
+                instance_count,
+                first_index,
+                vertex_offset,
+                first_instance,
+            } => {
+                let buffer = match state.index_buffer {
+                    Some(buffer) => buffer,
+                    None => {
+                        warn!("Skipping indexed draw without an index buffer");
+                        continue;
+                    }
+                };
+                let vertices = Vertices::Indexed {
+                    buffer: buffer,
+                    first: first_index,
+                    count: index_count,
+                    vertex_offset: vertex_offset,
+                };
+                draw(&state, &vertices, first_instance, instance_count)
+            }
+        }
+    }
+}
+
+/// Sets the front and back `values` of the faces of `vk::StencilFaceFlags` `faces` to `value`.
+fn set_faces(values: &mut [u32; 2], faces: vk::StencilFaceFlags, value: u32) {
+    if faces & vk::STENCIL_FACE_FRONT_BIT != 0 {
+        values[0] = value;
+    }
+    if faces & vk::STENCIL_FACE_BACK_BIT != 0 {
+        values[1] = value;
+    }
+}
+
+unsafe fn draw(state: &State, vertices: &Vertices, first_instance: u32, instance_count: u32) {
+    let pipeline = match state.graphics.as_ref() {
+        Some(&Pipeline::Graphics(ref pipeline)) => pipeline,
+        _ => {
+            warn!("Skipping draw without a graphics pipeline");
+            return;
+        }
+    };
+    let pass = match state.render_pass {
+        Some(ref pass) => pass,
+        None => {
+            warn!("Skipping draw outside of a render pass");
+            return;
+        }
+    };
+    let resources = state.resources(vk::PIPELINE_BIND_POINT_GRAPHICS);
+    let instances = first_instance..first_instance.saturating_add(instance_count);
+    draw::draw(
+        pipeline,
+        &state.dynamic,
+        &state.vertex_buffers,
+        &resources,
+        pass,
+        vertices,
+        instances,
+    )
+}
+
+unsafe fn dispatch(state: &State, resources: &Resources, base: [u32; 3], count: [u32; 3]) {
+    match state.compute.as_ref() {
+        Some(&Pipeline::Compute(ref pipeline)) => {
+            compute::dispatch(pipeline, resources, base, count)
+        }
+        _ => warn!("Skipping dispatch without a compute pipeline"),
+    }
+}
+
+/// Whether `count` workgroups are more than `maxComputeWorkGroupCount` allows.
+pub fn exceeds_workgroup_count(count: [u32; 3]) -> bool {
+    count.iter().zip(&compute::MAX_WORKGROUP_COUNT).any(|(count, max)| count > max)
+}
@@ -0,0 +1,372 @@
+//! Runs dispatches of compute pipelines.
+//!
+//! Workgroups are spread over one thread per CPU. The invocations of a workgroup run in batches
+//! of as many invocations as the shader has lanes. If the shader has barriers and the workgroup
+//! does not fit into a single batch, the batches run as fibers on the thread of the workgroup:
+//! a batch that reaches OpControlBarrier switches to the next one, and the batches continue
+//! past the barrier once all of them got there. Each thread of a dispatch keeps its fibers and
+//! their stacks for all the workgroups it runs.
+use std::cell::Cell;
+use std::cmp;
+use std::io;
+use std::mem;
+use std::os::raw::c_void;
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use libc;
+use spirv_llvm::EntryPoint;
+use spirv_llvm::abi::{Invocation, Resources};
+use batch;
+use pipeline::ComputePipeline;
+
+/// `maxComputeSharedMemorySize`, bytes of Workgroup variables a shader may have.
+pub const MAX_SHARED_MEMORY: u32 = 32 * 1024;
+/// `maxComputeWorkGroupCount`, workgroups a dispatch may have in x, y and z.
+pub const MAX_WORKGROUP_COUNT: [u32; 3] = [65535, 65535, 65535];
+/// `maxComputeWorkGroupInvocations`, invocations a workgroup may have.
+pub const MAX_WORKGROUP_INVOCATIONS: u32 = 1024;
+/// `maxComputeWorkGroupSize`, invocations a workgroup may have in x, y and z.
+pub const MAX_WORKGROUP_SIZE: [u32; 3] = [1024, 1024, 64];
+
+/// Bytes of stack a fiber has, including the guard page at the bottom.
+const STACK_SIZE: usize = 1 << 20;
+
+/// What the threads of a dispatch share. The resources point to memory the application keeps
+/// alive and synchronizes until the dispatch is done.
+struct Dispatch<'a> {
+    entry_point: EntryPoint<'a>,
+    resources: &'a Resources,
+    local_size: [u32; 3],
+    base: [u32; 3],
+    count: [u32; 3],
+    barriers: bool,
+}
+
+unsafe impl<'a> Sync for Dispatch<'a> {}
+
+/// Runs the workgroups `base` up to `base + count` of `pipeline`, and returns once all of them
+/// are done.
+///
+/// Unsafe because the shader accesses whatever memory `resources` point to.
+pub unsafe fn dispatch(
+    pipeline: &ComputePipeline,
+    resources: &Resources,
+    base: [u32; 3],
+    count: [u32; 3],
+) {
+    let dispatch = Dispatch {
+        entry_point: pipeline.stage.entry_point(),
+        resources: resources,
+        local_size: pipeline.local_size,
+        base: base,
+        count: count,
+        barriers: pipeline.stage.info().barriers,
+    };
+    dispatch.run();
+}
+
+impl<'a> Dispatch<'a> {
+    unsafe fn run(&self) {
+        let workgroups = self.count.iter().fold(1usize, |product, &count| {
+            product * count as usize
+        });
+        let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+        let threads = cmp::min(threads, workgroups);
+        let next = AtomicUsize::new(0);
+        if threads <= 1 {
+            self.work(&next, workgroups);
+            return;
+        }
+
+        thread::scope(|scope| for _ in 0..threads {
+            let next = &next;
+            scope.spawn(move || self.work(next, workgroups));
+        });
+    }
+
+    /// Runs workgroups until `next` reaches `workgroups`.
+    unsafe fn work(&self, next: &AtomicUsize, workgroups: usize) {
+        let [size_x, size_y, size_z] = self.local_size;
+        let size = (size_x * size_y * size_z) as usize;
+        let lanes = self.entry_point.lanes() as usize;
+        // Without barriers nothing has to wait, so the batches run one after the other
+        let mut fibers = if self.barriers && size > lanes {
+            match Fibers::new(self.entry_point, size.div_ceil(lanes)) {
+                Ok(fibers) => Some(fibers),
+                Err(err) => {
+                    warn!("Skipping workgroups, could not allocate fiber stacks: {}", err);
+                    return;
+                }
+            }
+        } else {
+            None
+        };
+
+        let mut memory = Vec::new();
+        loop {
+            let index = next.fetch_add(1, Ordering::Relaxed);
+            if index >= workgroups {
+                break;
+            }
+            self.run_workgroup(index, &mut memory, fibers.as_mut());
+        }
+    }
+
+    /// Runs the `index`th workgroup in x, then y, then z order. `memory` is reused for the
+    /// Workgroup variables of all workgroups of a thread. With `fibers`, every batch runs on a
+    /// fiber of its own.
+    unsafe fn run_workgroup(
+        &self,
+        index: usize,
+        memory: &mut Vec<[u64; 2]>,
+        fibers: Option<&mut Fibers<'a>>,
+    ) {
+        let [count_x, count_y, _] = self.count;
+        let id = [
+            (index % count_x as usize) as u32,
+            (index / count_x as usize % count_y as usize) as u32,
+            (index / (count_x as usize * count_y as usize)) as u32,
+        ];
+
+        memory.clear();
+        memory.resize(self.entry_point.workgroup_memory().div_ceil(16), [0; 2]);
+        let mut resources = *self.resources;
+        resources.workgroup_memory = memory.as_mut_ptr() as *mut u8;
+        if fibers.is_some() {
+            resources.barrier = Some(wait);
+        }
+
+        let [size_x, size_y, size_z] = self.local_size;
+        let size = (size_x * size_y * size_z) as usize;
+        let mut invocations = Vec::with_capacity(size);
+        for z in 0..size_z {
+            for y in 0..size_y {
+                for x in 0..size_x {
+                    let local = [x, y, z];
+                    let mut invocation = Invocation::new(&resources);
+                    let builtins = &mut invocation.builtins;
+                    for axis in 0..3 {
+                        let workgroup = self.base[axis] + id[axis];
+                        builtins.num_workgroups.0[axis] = self.count[axis];
+                        builtins.workgroup_id.0[axis] = workgroup;
+                        builtins.local_invocation_id.0[axis] = local[axis];
+                        builtins.global_invocation_id.0[axis] =
+                            workgroup * self.local_size[axis] + local[axis];
+                    }
+                    builtins.local_invocation_index = (z * size_y + y) * size_x + x;
+                    invocations.push(invocation);
+                }
+            }
+        }
+
+        match fibers {
+            Some(fibers) => fibers.run(&mut invocations),
+            None => {
+                for invocations in invocations.chunks_mut(32) {
+                    let mask = batch::first(invocations.len());
+                    self.entry_point.call_batch(invocations, mask);
+                }
+            }
+        }
+    }
+}
+
+thread_local! {
+    /// The fiber the running thread switched to, see `start` and `wait`.
+    static RUNNING: Cell<*mut c_void> = const { Cell::new(ptr::null_mut()) };
+}
+
+/// The batches of a workgroup with barriers, each with a context and a stack of its own.
+struct Fibers<'a> {
+    /// Where the thread continues whenever a fiber waits at a barrier or finishes.
+    scheduler: Box<libc::ucontext_t>,
+    fibers: Vec<Fiber<'a>>,
+}
+
+/// A batch of invocations and where it continues. Contexts point into themselves, so they stay
+/// in their boxes.
+struct Fiber<'a> {
+    entry_point: EntryPoint<'a>,
+    context: Box<libc::ucontext_t>,
+    scheduler: *mut libc::ucontext_t,
+    stack: Stack,
+    batch: (*mut Invocation, usize),
+    finished: bool,
+}
+
+impl<'a> Fibers<'a> {
+    /// Allocates `count` fibers.
+    fn new(entry_point: EntryPoint<'a>, count: usize) -> io::Result<Self> {
+        let mut scheduler: Box<libc::ucontext_t> = Box::new(unsafe { mem::zeroed() });
+        let mut fibers = Vec::with_capacity(count);
+        for _ in 0..count {
+            fibers.push(Fiber {
+                entry_point: entry_point,
+                context: Box::new(unsafe { mem::zeroed() }),
+                scheduler: &mut *scheduler,
+                stack: Stack::new(STACK_SIZE)?,
+                batch: (ptr::null_mut(), 0),
+                finished: true,
+            });
+        }
+        Ok(Fibers {
+            scheduler: scheduler,
+            fibers: fibers,
+        })
+    }
+
+    /// Runs `invocations` in as many batches as there are fibers. Every round runs each batch
+    /// up to its next barrier or its end, until all of them are done.
+    unsafe fn run(&mut self, invocations: &mut [Invocation]) {
+        let lanes = invocations.len().div_ceil(self.fibers.len());
+        for (fiber, batch) in self.fibers.iter_mut().zip(invocations.chunks_mut(lanes)) {
+            let context: *mut libc::ucontext_t = &mut *fiber.context;
+            libc::getcontext(context);
+            (*context).uc_stack.ss_sp = fiber.stack.bottom();
+            (*context).uc_stack.ss_size = fiber.stack.size();
+            (*context).uc_link = fiber.scheduler;
+            libc::makecontext(context, start, 0);
+            fiber.batch = (batch.as_mut_ptr(), batch.len());
+            fiber.finished = false;
+        }
+
+        let scheduler: *mut libc::ucontext_t = &mut *self.scheduler;
+        while self.fibers.iter().any(|fiber| !fiber.finished) {
+            for fiber in self.fibers.iter_mut().filter(|fiber| !fiber.finished) {
+                let fiber: *mut Fiber = fiber;
+                RUNNING.with(|running| running.set(fiber as *mut c_void));
+                libc::swapcontext(scheduler, &*(*fiber).context);
+            }
+        }
+        RUNNING.with(|running| running.set(ptr::null_mut()));
+    }
+}
+
+/// Runs the batch of the fiber the thread switched to. Returning continues with the scheduler.
+extern "C" fn start() {
+    unsafe {
+        let fiber = RUNNING.with(Cell::get) as *mut Fiber;
+        let (invocations, count) = (*fiber).batch;
+        let invocations = slice::from_raw_parts_mut(invocations, count);
+        (*fiber).entry_point.call_batch(invocations, batch::first(count));
+        (*fiber).finished = true;
+    }
+}
+
+/// Switches from the running fiber back to the scheduler until the next round, see
+/// `Resources::barrier`.
+unsafe extern "C" fn wait(_: *mut c_void) {
+    let fiber = RUNNING.with(Cell::get) as *mut Fiber;
+    libc::swapcontext(&mut *(*fiber).context, (*fiber).scheduler);
+}
+
+/// The memory of a fiber stack, with a guard page at the bottom so that overflows fault.
+struct Stack {
+    memory: *mut c_void,
+    size: usize,
+}
+
+impl Stack {
+    fn new(size: usize) -> io::Result<Self> {
+        unsafe {
+            let memory = libc::mmap(
+                ptr::null_mut(),
+                size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE | libc::MAP_STACK,
+                -1,
+                0,
+            );
+            if memory == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+            let stack = Stack {
+                memory: memory,
+                size: size,
+            };
+            if libc::mprotect(memory, page_size(), libc::PROT_NONE) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(stack)
+        }
+    }
+
+    /// The lowest address the fiber may use.
+    fn bottom(&self) -> *mut c_void {
+        unsafe { (self.memory as *mut u8).add(page_size()) as *mut c_void }
+    }
+
+    fn size(&self) -> usize {
+        self.size - page_size()
+    }
+}
+
+impl Drop for Stack {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.memory, self.size) };
+    }
+}
+
+fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+#[cfg(all(test, feature = "interpreter"))]
+mod tests {
+    use std::sync::Arc;
+    use rspirv::mr;
+    use spirv_llvm::{Interpreter, ShaderCode};
+    use super::*;
+
+    /// Writes `vec4(values[i], values[(i + 1) % 4], values[(i + 3) % 4], 2.5 + global)` for
+    /// every invocation, where `values` are the inputs of its workgroup in Workgroup memory.
+    const SHADER: &[u8] = include_bytes!("../../spirv_llvm/tests/corpus/shared.comp.spv");
+
+    fn dispatch(base: [u32; 3], count: [u32; 3]) -> Vec<[f32; 4]> {
+        let module = mr::load_bytes(SHADER).expect("invalid shader");
+        // The interpreter runs one invocation at a time, so the batches have to run side by side
+        let code = ShaderCode::Interpreted(Interpreter::new(Arc::new(module)).unwrap());
+
+        let mut inputs: Vec<f32> = (1..9).map(|value| value as f32).collect();
+        let mut results = vec![[0.0; 4]; 8];
+        let mut input_buffers = [inputs.as_mut_ptr() as *mut u8];
+        let mut result_buffers = [results.as_mut_ptr() as *mut u8];
+        let set = [
+            input_buffers.as_mut_ptr() as *const *mut u8,
+            result_buffers.as_mut_ptr() as *const *mut u8,
+        ];
+        let mut resources = Resources::default();
+        resources.descriptor_sets[0] = set.as_ptr();
+        let dispatch = Dispatch {
+            entry_point: code.entry_point("main").unwrap(),
+            resources: &resources,
+            local_size: [4, 1, 1],
+            base: base,
+            count: count,
+            barriers: true,
+        };
+        unsafe { dispatch.run() };
+        results
+    }
+
+    #[test]
+    fn shares_memory_within_workgroups() {
+        let first = [
+            [1.0, 2.0, 4.0, 2.5],
+            [2.0, 3.0, 1.0, 3.5],
+            [3.0, 4.0, 2.0, 4.5],
+            [4.0, 1.0, 3.0, 5.5],
+        ];
+        let second = [
+            [5.0, 6.0, 8.0, 6.5],
+            [6.0, 7.0, 5.0, 7.5],
+            [7.0, 8.0, 6.0, 8.5],
+            [8.0, 5.0, 7.0, 9.5],
+        ];
+        assert_eq!(dispatch([0; 3], [2, 1, 1]), [first, second].concat());
+        assert_eq!(dispatch([1, 0, 0], [1, 1, 1]), [[[0.0; 4]; 4], second].concat());
+    }
+}
@@ -0,0 +1,121 @@
+//! VK_EXT_debug_report: passes messages of the driver on to callbacks of the application.
+use std::ffi::{CStr, CString};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use libc;
+use ffi_types as vk;
+use dispatch::Instance;
+
+/// A callback registered with vkCreateDebugReportCallbackEXT.
+#[derive(Debug, Clone, Copy)]
+struct DebugReportCallback {
+    handle: vk::DebugReportCallbackEXT,
+    flags: vk::DebugReportFlagsEXT,
+    callback: vk::PFN_vkDebugReportCallbackEXT,
+    user_data: *mut libc::c_void,
+}
+
+/// The callbacks of an instance, shared with the devices created from it.
+#[derive(Debug, Default)]
+pub struct DebugReport {
+    callbacks: Mutex<Vec<DebugReportCallback>>,
+    last_handle: AtomicUsize,
+}
+
+// The user data is never touched, only handed back to the application
+unsafe impl Send for DebugReport {}
+unsafe impl Sync for DebugReport {}
+
+impl DebugReport {
+    fn add(&self, create_info: &vk::DebugReportCallbackCreateInfoEXT) -> vk::DebugReportCallbackEXT {
+        // Handles start at 1, 0 is VK_NULL_HANDLE
+        let handle = self.last_handle.fetch_add(1, Ordering::Relaxed) as u64 + 1;
+        self.callbacks.lock().unwrap().push(DebugReportCallback {
+            handle: handle,
+            flags: create_info.flags,
+            callback: create_info.pfnCallback,
+            user_data: create_info.pUserData,
+        });
+        handle
+    }
+
+    fn remove(&self, handle: vk::DebugReportCallbackEXT) {
+        self.callbacks.lock().unwrap().retain(
+            |callback| callback.handle != handle,
+        );
+    }
+
+    /// Passes `message` to every callback that wants messages of kind `flags`.
+    pub fn message(
+        &self,
+        flags: vk::DebugReportFlagsEXT,
+        object_type: vk::DebugReportObjectTypeEXT,
+        object: u64,
+        prefix: &str,
+        message: &str,
+    ) {
+        let prefix = CString::new(prefix).unwrap_or_default();
+        let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+        // Callbacks may register or remove callbacks themselves, so do not hold the lock
+        let callbacks = self.callbacks.lock().unwrap().clone();
+        for callback in callbacks {
+            if callback.flags & flags != 0 {
+                (callback.callback)(
+                    flags,
+                    object_type,
+                    object,
+                    0,
+                    0,
+                    prefix.as_ptr(),
+                    message.as_ptr(),
+                    callback.user_data,
+                );
+            }
+        }
+    }
+}
+
+pub fn create_debug_report_callback(
+    instance: &Instance,
+    create_info: &vk::DebugReportCallbackCreateInfoEXT,
+    alloc: *const vk::AllocationCallbacks,
+) -> Result<vk::DebugReportCallbackEXT, vk::Result> {
+    debug!("Calling create_debug_report_callback");
+    debug_assert_eq!(
+        create_info.sType,
+        vk::STRUCTURE_TYPE_DEBUG_REPORT_CREATE_INFO_EXT
+    );
+    if !alloc.is_null() {
+        warn!("Custom allocators not supported by driver");
+        return Err(vk::ERROR_INITIALIZATION_FAILED);
+    }
+    Ok(instance.debug_report().add(create_info))
+}
+
+pub fn destroy_debug_report_callback(
+    instance: &Instance,
+    callback: vk::DebugReportCallbackEXT,
+    alloc: *const vk::AllocationCallbacks,
+) {
+    debug!("Calling destroy_debug_report_callback");
+    debug_assert!(alloc.is_null());
+    instance.debug_report().remove(callback);
+}
+
+/// Injects a message of the application into the callbacks.
+pub fn debug_report_message(
+    instance: &Instance,
+    flags: vk::DebugReportFlagsEXT,
+    object_type: vk::DebugReportObjectTypeEXT,
+    object: u64,
+    prefix: &CStr,
+    message: &CStr,
+) {
+    instance.debug_report().message(
+        flags,
+        object_type,
+        object,
+        &prefix.to_string_lossy(),
+        &message.to_string_lossy(),
+    );
+}
@@ -0,0 +1,842 @@
+//! Descriptor set layouts, descriptor pools and the descriptor sets allocated from them.
+//!
+//! Descriptor sets are laid out the way compiled shaders read them, see
+//! `spirv_llvm::abi::Resources`: a table with the address of the first descriptor of every
+//! binding, indexed by binding number, and the descriptors the table points into. A descriptor
+//! is one pointer sized word:
+//!
+//! * Uniform and storage buffers: the address of the byte at the offset of the descriptor.
+//! * Samplers, images, texel buffers and input attachments: their handle, which is opaque to
+//!   the shader.
+//! * Combined image samplers: two words, the handle of the image view and the one of the
+//!   sampler, as shaders store a sampled image.
+//!
+//! Binding a set with dynamic descriptors makes a copy of its descriptors with the dynamic
+//! offsets added, so that the set can be bound with other offsets at the same time.
+use std::ptr;
+use std::sync::Arc;
+
+use spirv_llvm::abi;
+use ffi_types as vk;
+use memory::Buffer;
+use pipeline::array;
+
+/// Number of descriptor types, which are numbered from 0.
+const DESCRIPTOR_TYPE_COUNT: usize = vk::DESCRIPTOR_TYPE_INPUT_ATTACHMENT as usize + 1;
+
+/// `maxBoundDescriptorSets`, the sets compiled shaders can reach.
+pub const MAX_BOUND_DESCRIPTOR_SETS: u32 = abi::MAX_DESCRIPTOR_SETS as u32;
+
+/// Alignment of the offsets of uniform, storage and texel buffer descriptors, enough for any
+/// scalar or vector a shader accesses.
+pub const MIN_OFFSET_ALIGNMENT: u64 = 16;
+
+/// Words a descriptor of `descriptor_type` takes.
+fn words(descriptor_type: vk::DescriptorType) -> usize {
+    match descriptor_type {
+        vk::DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER => 2,
+        _ => 1,
+    }
+}
+
+fn is_dynamic(descriptor_type: vk::DescriptorType) -> bool {
+    match descriptor_type {
+        vk::DESCRIPTOR_TYPE_UNIFORM_BUFFER_DYNAMIC |
+        vk::DESCRIPTOR_TYPE_STORAGE_BUFFER_DYNAMIC => true,
+        _ => false,
+    }
+}
+
+/// One binding of a descriptor set layout.
+#[derive(Clone, Debug)]
+struct BindingLayout {
+    descriptor_type: vk::DescriptorType,
+    count: u32,
+    /// Index of the first word of the binding in the descriptors of a set.
+    offset: usize,
+    /// Samplers that are part of the layout, empty if the samplers are written to the set.
+    immutable_samplers: Vec<vk::Sampler>,
+}
+
+/// The bindings of a descriptor set.
+#[derive(Debug)]
+pub struct DescriptorSetLayout {
+    /// Indexed by binding number, `None` for numbers without a binding. Shared with the sets
+    /// allocated with the layout, which may outlive it.
+    bindings: Arc<Vec<Option<BindingLayout>>>,
+    /// Words the descriptors of a set take.
+    words: usize,
+    /// Descriptors of every type a set takes from its pool.
+    counts: [u32; DESCRIPTOR_TYPE_COUNT],
+}
+
+impl DescriptorSetLayout {
+    pub fn from_create_info(
+        create_info: &vk::DescriptorSetLayoutCreateInfo,
+        alloc: *const vk::AllocationCallbacks,
+    ) -> Result<Self, vk::Result> {
+        debug_assert_eq!(
+            create_info.sType,
+            vk::STRUCTURE_TYPE_DESCRIPTOR_SET_LAYOUT_CREATE_INFO
+        );
+        if !alloc.is_null() {
+            warn!("Custom allocators not supported by driver");
+            return Err(vk::ERROR_INITIALIZATION_FAILED);
+        }
+        let create_bindings = unsafe { array(create_info.pBindings, create_info.bindingCount) };
+        let numbers = create_bindings.iter().map(|binding| binding.binding as usize + 1);
+        let mut bindings = vec![None; numbers.max().unwrap_or(0)];
+        for binding in create_bindings {
+            if binding.descriptorType as usize >= DESCRIPTOR_TYPE_COUNT {
+                warn!("Descriptor type {} not supported", binding.descriptorType);
+                return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+            }
+            let slot = &mut bindings[binding.binding as usize];
+            if slot.is_some() {
+                warn!("Binding {} is in the layout twice", binding.binding);
+                return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+            }
+            let immutable = match binding.descriptorType {
+                vk::DESCRIPTOR_TYPE_SAMPLER |
+                vk::DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER
+                    if !binding.pImmutableSamplers.is_null() => unsafe {
+                    array(binding.pImmutableSamplers, binding.descriptorCount).to_vec()
+                },
+                _ => Vec::new(),
+            };
+            *slot = Some(BindingLayout {
+                descriptor_type: binding.descriptorType,
+                count: binding.descriptorCount,
+                offset: 0,
+                immutable_samplers: immutable,
+            });
+        }
+
+        // Descriptors are laid out in the order of the binding numbers
+        let mut words = 0;
+        let mut counts = [0; DESCRIPTOR_TYPE_COUNT];
+        for binding in bindings.iter_mut().filter_map(Option::as_mut) {
+            binding.offset = words;
+            words += binding.count as usize * self::words(binding.descriptor_type);
+            counts[binding.descriptor_type as usize] += binding.count;
+        }
+        Ok(DescriptorSetLayout {
+            bindings: Arc::new(bindings),
+            words: words,
+            counts: counts,
+        })
+    }
+}
+
+/// Descriptors the sets of a pool share.
+#[derive(Debug)]
+pub struct DescriptorPool {
+    flags: vk::DescriptorPoolCreateFlags,
+    /// Sets that can still be allocated.
+    sets_left: u32,
+    /// Descriptors of every type that can still be allocated.
+    counts_left: [u32; DESCRIPTOR_TYPE_COUNT],
+    max_sets: u32,
+    max_counts: [u32; DESCRIPTOR_TYPE_COUNT],
+    /// The sets allocated from the pool, which it frees when it is reset or destroyed.
+    sets: Vec<*mut DescriptorSet>,
+}
+
+impl DescriptorPool {
+    pub fn from_create_info(
+        create_info: &vk::DescriptorPoolCreateInfo,
+        alloc: *const vk::AllocationCallbacks,
+    ) -> Result<Self, vk::Result> {
+        debug_assert_eq!(
+            create_info.sType,
+            vk::STRUCTURE_TYPE_DESCRIPTOR_POOL_CREATE_INFO
+        );
+        if !alloc.is_null() {
+            warn!("Custom allocators not supported by driver");
+            return Err(vk::ERROR_INITIALIZATION_FAILED);
+        }
+        if create_info.maxSets == 0 {
+            warn!("Descriptor pool has no room for sets");
+            return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+        }
+        let mut counts = [0u32; DESCRIPTOR_TYPE_COUNT];
+        for size in unsafe { array(create_info.pPoolSizes, create_info.poolSizeCount) } {
+            let count = match counts.get_mut(size.ty as usize) {
+                Some(count) => count,
+                None => {
+                    warn!("Ignoring descriptors of unknown type {}", size.ty);
+                    continue;
+                }
+            };
+            *count = match count.checked_add(size.descriptorCount) {
+                Some(count) => count,
+                None => {
+                    warn!("Descriptor pool has too many descriptors of type {}", size.ty);
+                    return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+                }
+            };
+        }
+        Ok(DescriptorPool {
+            flags: create_info.flags,
+            sets_left: create_info.maxSets,
+            counts_left: counts,
+            max_sets: create_info.maxSets,
+            max_counts: counts,
+            sets: Vec::new(),
+        })
+    }
+
+    /// Allocates a set for every layout, or none at all if the pool has no room for all of
+    /// them.
+    pub fn allocate(
+        &mut self,
+        layouts: &[&DescriptorSetLayout],
+    ) -> Result<Vec<*mut DescriptorSet>, vk::Result> {
+        let mut counts = self.counts_left;
+        for layout in layouts {
+            for (left, count) in counts.iter_mut().zip(&layout.counts) {
+                *left = left.checked_sub(*count).ok_or(vk::ERROR_OUT_OF_POOL_MEMORY_KHR)?;
+            }
+        }
+        if (layouts.len() as u64) > u64::from(self.sets_left) {
+            return Err(vk::ERROR_OUT_OF_POOL_MEMORY_KHR);
+        }
+        self.sets_left -= layouts.len() as u32;
+        self.counts_left = counts;
+        let first = self.sets.len();
+        self.sets.extend(layouts.iter().map(|layout| {
+            Box::into_raw(Box::new(DescriptorSet::new(layout)))
+        }));
+        Ok(self.sets[first..].to_vec())
+    }
+
+    /// Frees `sets`, which have to be allocated from the pool. Null handles are ignored.
+    pub fn free(&mut self, sets: &[*mut DescriptorSet]) -> Result<(), vk::Result> {
+        if self.flags & vk::DESCRIPTOR_POOL_CREATE_FREE_DESCRIPTOR_SET_BIT == 0 {
+            warn!("Descriptor sets can only be freed with their pool");
+            return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+        }
+        for &set in sets.iter().filter(|set| !set.is_null()) {
+            let index = match self.sets.iter().position(|&allocated| allocated == set) {
+                Some(index) => index,
+                None => {
+                    warn!("Descriptor set {:?} is not from this pool", set);
+                    return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+                }
+            };
+            self.sets.swap_remove(index);
+            let set = unsafe { Box::from_raw(set) };
+            self.sets_left += 1;
+            for (left, count) in self.counts_left.iter_mut().zip(&set.counts()) {
+                *left += count;
+            }
+        }
+        Ok(())
+    }
+
+    /// Frees all sets of the pool.
+    pub fn reset(&mut self) {
+        for set in self.sets.drain(..) {
+            drop(unsafe { Box::from_raw(set) });
+        }
+        self.sets_left = self.max_sets;
+        self.counts_left = self.max_counts;
+    }
+}
+
+impl Drop for DescriptorPool {
+    fn drop(&mut self) {
+        self.reset();
+    }
+}
+
+/// Descriptors for the bindings of a layout.
+#[derive(Debug)]
+pub struct DescriptorSet {
+    bindings: Arc<Vec<Option<BindingLayout>>>,
+    /// Address of the first descriptor of every binding, null for numbers without a binding.
+    table: Vec<*const *mut u8>,
+    /// Null until they are written.
+    descriptors: Vec<*mut u8>,
+    /// Bytes of the buffer after the range of every buffer descriptor, which a dynamic offset
+    /// may move the range into. Indexed like `descriptors`.
+    room: Vec<u64>,
+}
+
+impl DescriptorSet {
+    fn new(layout: &DescriptorSetLayout) -> Self {
+        let mut descriptors = vec![ptr::null_mut(); layout.words];
+        for binding in layout.bindings.iter().filter_map(Option::as_ref) {
+            let stride = words(binding.descriptor_type);
+            for (index, &sampler) in binding.immutable_samplers.iter().enumerate() {
+                descriptors[binding.offset + index * stride + stride - 1] = sampler as *mut u8;
+            }
+        }
+        let table = table(&layout.bindings, &descriptors);
+        DescriptorSet {
+            bindings: layout.bindings.clone(),
+            table: table,
+            descriptors: descriptors,
+            room: vec![0; layout.words],
+        }
+    }
+
+    /// Descriptors of every type the set takes from its pool.
+    fn counts(&self) -> [u32; DESCRIPTOR_TYPE_COUNT] {
+        let mut counts = [0; DESCRIPTOR_TYPE_COUNT];
+        for binding in self.bindings.iter().filter_map(Option::as_ref) {
+            counts[binding.descriptor_type as usize] += binding.count;
+        }
+        counts
+    }
+
+    /// Returns where `count` descriptors from `element` of `binding` on are. Descriptors past
+    /// the end of a binding continue with the next one, like updates do.
+    fn locate(
+        &self,
+        mut binding: u32,
+        mut element: u32,
+        count: u32,
+    ) -> Result<Vec<Location>, vk::Result> {
+        let mut locations = Vec::with_capacity(count as usize);
+        while locations.len() < count as usize {
+            let layout = match self.bindings.get(binding as usize) {
+                Some(&Some(ref layout)) => layout,
+                Some(&None) => {
+                    binding += 1;
+                    continue;
+                }
+                None => {
+                    warn!("Descriptor update goes past the last binding");
+                    return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+                }
+            };
+            if element >= layout.count {
+                element -= layout.count;
+                binding += 1;
+                continue;
+            }
+            locations.push(Location {
+                descriptor_type: layout.descriptor_type,
+                word: layout.offset + element as usize * words(layout.descriptor_type),
+                immutable_sampler: (element as usize) < layout.immutable_samplers.len(),
+            });
+            element += 1;
+        }
+        Ok(locations)
+    }
+
+    /// Writes the descriptors of `write`. The pointers of `write` have to be valid, as Vulkan
+    /// requires of the application.
+    pub unsafe fn write(&mut self, write: &vk::WriteDescriptorSet) -> Result<(), vk::Result> {
+        debug_assert_eq!(write.sType, vk::STRUCTURE_TYPE_WRITE_DESCRIPTOR_SET);
+        let locations =
+            self.locate(write.dstBinding, write.dstArrayElement, write.descriptorCount)?;
+        for (index, location) in locations.into_iter().enumerate() {
+            if location.descriptor_type != write.descriptorType {
+                warn!(
+                    "Writing descriptors of type {} to a binding of type {}",
+                    write.descriptorType,
+                    location.descriptor_type
+                );
+                return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+            }
+            let word = location.word;
+            match location.descriptor_type {
+                vk::DESCRIPTOR_TYPE_SAMPLER => {
+                    // Samplers of the layout cannot be replaced
+                    if !location.immutable_sampler {
+                        let info = &*write.pImageInfo.add(index);
+                        self.descriptors[word] = info.sampler as *mut u8;
+                    }
+                }
+                vk::DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER => {
+                    let info = &*write.pImageInfo.add(index);
+                    self.descriptors[word] = info.imageView as *mut u8;
+                    if !location.immutable_sampler {
+                        self.descriptors[word + 1] = info.sampler as *mut u8;
+                    }
+                }
+                vk::DESCRIPTOR_TYPE_SAMPLED_IMAGE |
+                vk::DESCRIPTOR_TYPE_STORAGE_IMAGE |
+                vk::DESCRIPTOR_TYPE_INPUT_ATTACHMENT => {
+                    self.descriptors[word] = (*write.pImageInfo.add(index)).imageView as *mut u8;
+                }
+                vk::DESCRIPTOR_TYPE_UNIFORM_TEXEL_BUFFER |
+                vk::DESCRIPTOR_TYPE_STORAGE_TEXEL_BUFFER => {
+                    self.descriptors[word] = *write.pTexelBufferView.add(index) as *mut u8;
+                }
+                _ => {
+                    let (address, room) = buffer_address(&*write.pBufferInfo.add(index))?;
+                    self.descriptors[word] = address;
+                    self.room[word] = room;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies the descriptors of `copy` from `source` to `destination`, which may be the same
+    /// set.
+    pub unsafe fn copy(
+        source: *const DescriptorSet,
+        destination: *mut DescriptorSet,
+        copy: &vk::CopyDescriptorSet,
+    ) -> Result<(), vk::Result> {
+        debug_assert_eq!(copy.sType, vk::STRUCTURE_TYPE_COPY_DESCRIPTOR_SET);
+        let values: Vec<(vk::DescriptorType, Vec<*mut u8>, u64)> = {
+            let source = &*source;
+            let locations =
+                source.locate(copy.srcBinding, copy.srcArrayElement, copy.descriptorCount)?;
+            locations
+                .into_iter()
+                .map(|location| {
+                    let word = location.word;
+                    let end = word + words(location.descriptor_type);
+                    let value = source.descriptors[word..end].to_vec();
+                    (location.descriptor_type, value, source.room[word])
+                })
+                .collect()
+        };
+        let destination = &mut *destination;
+        let locations =
+            destination.locate(copy.dstBinding, copy.dstArrayElement, copy.descriptorCount)?;
+        for (location, (source_type, mut value, room)) in locations.into_iter().zip(values) {
+            if location.descriptor_type != source_type {
+                warn!(
+                    "Copying descriptors of type {} to a binding of type {}",
+                    source_type,
+                    location.descriptor_type
+                );
+                return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+            }
+            if location.immutable_sampler {
+                // The sampler is always the last word
+                value.pop();
+            }
+            let word = location.word;
+            destination.descriptors[word..word + value.len()].copy_from_slice(&value);
+            destination.room[word] = room;
+        }
+        Ok(())
+    }
+
+    /// Number of dynamic offsets binding the set takes.
+    pub fn dynamic_count(&self) -> usize {
+        self.bindings
+            .iter()
+            .filter_map(Option::as_ref)
+            .filter(|binding| is_dynamic(binding.descriptor_type))
+            .map(|binding| binding.count as usize)
+            .sum()
+    }
+
+    /// Whether `offsets`, as `bind` takes them, keep the range of every dynamic descriptor
+    /// within its buffer.
+    pub fn fits(&self, offsets: &[u32]) -> bool {
+        debug_assert_eq!(offsets.len(), self.dynamic_count());
+        let mut offsets = offsets.iter();
+        for binding in self.bindings.iter().filter_map(Option::as_ref) {
+            if !is_dynamic(binding.descriptor_type) {
+                continue;
+            }
+            let range = binding.offset..binding.offset + binding.count as usize;
+            let descriptors = self.descriptors[range.clone()].iter().zip(&self.room[range]);
+            for ((descriptor, &room), &offset) in descriptors.zip(&mut offsets) {
+                if !descriptor.is_null() && u64::from(offset) > room {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// The set as it is bound with `offsets`, one for every dynamic descriptor in the order of
+    /// the binding numbers.
+    pub fn bind(&self, offsets: &[u32]) -> BoundSet {
+        debug_assert_eq!(offsets.len(), self.dynamic_count());
+        if offsets.is_empty() {
+            return BoundSet::Shared(self.table.as_ptr());
+        }
+        let mut descriptors = self.descriptors.clone();
+        let mut offsets = offsets.iter();
+        for binding in self.bindings.iter().filter_map(Option::as_ref) {
+            if !is_dynamic(binding.descriptor_type) {
+                continue;
+            }
+            let range = binding.offset..binding.offset + binding.count as usize;
+            for (descriptor, offset) in descriptors[range].iter_mut().zip(&mut offsets) {
+                if !descriptor.is_null() {
+                    *descriptor = unsafe { descriptor.add(*offset as usize) };
+                }
+            }
+        }
+        BoundSet::Patched {
+            table: table(&self.bindings, &descriptors),
+            descriptors: descriptors,
+        }
+    }
+}
+
+/// Where a descriptor is in the words of a set.
+struct Location {
+    descriptor_type: vk::DescriptorType,
+    word: usize,
+    /// Whether the sampler of the descriptor is part of the layout, and cannot change.
+    immutable_sampler: bool,
+}
+
+/// A descriptor set as a command buffer binds it.
+#[derive(Debug)]
+pub enum BoundSet {
+    /// The table of a set without dynamic descriptors.
+    Shared(*const *const *mut u8),
+    /// A copy of the descriptors of a set, with the dynamic offsets added.
+    Patched {
+        table: Vec<*const *mut u8>,
+        descriptors: Vec<*mut u8>,
+    },
+}
+
+impl BoundSet {
+    /// What `Resources::descriptor_sets` holds for the set.
+    pub fn table(&self) -> *const *const *mut u8 {
+        match *self {
+            BoundSet::Shared(table) => table,
+            BoundSet::Patched { ref table, .. } => table.as_ptr(),
+        }
+    }
+}
+
+/// Returns the address of the first descriptor of every binding in `descriptors`.
+fn table(
+    bindings: &[Option<BindingLayout>],
+    descriptors: &[*mut u8],
+) -> Vec<*const *mut u8> {
+    bindings
+        .iter()
+        .map(|binding| match *binding {
+            Some(ref binding) if binding.count != 0 => unsafe {
+                descriptors.as_ptr().add(binding.offset)
+            },
+            _ => ptr::null(),
+        })
+        .collect()
+}
+
+/// Returns the address of the first byte `info` refers to, and the bytes of the buffer after
+/// its range.
+unsafe fn buffer_address(info: &vk::DescriptorBufferInfo) -> Result<(*mut u8, u64), vk::Result> {
+    let buffer = &*(info.buffer as *const Buffer);
+    let range = if info.range == vk::WHOLE_SIZE {
+        buffer.size.saturating_sub(info.offset)
+    } else {
+        info.range
+    };
+    let room = info.offset.checked_add(range).and_then(|end| buffer.size.checked_sub(end));
+    let room = match room {
+        Some(room) if info.offset & (MIN_OFFSET_ALIGNMENT - 1) == 0 => room,
+        _ => {
+            warn!(
+                "Range of {} bytes at offset {} does not fit into buffer of {} bytes",
+                range,
+                info.offset,
+                buffer.size
+            );
+            return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+        }
+    };
+    let address = buffer.at(info.offset);
+    if address.is_null() {
+        warn!("Buffer of descriptor is not bound to memory");
+        return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+    }
+    Ok((address, room))
+}
+
+#[cfg(test)]
+mod tests {
+    use memory::DeviceMemory;
+    use super::*;
+
+    fn layout(
+        bindings: &[(u32, vk::DescriptorType, u32)],
+        samplers: &[vk::Sampler],
+    ) -> DescriptorSetLayout {
+        let bindings: Vec<vk::DescriptorSetLayoutBinding> = bindings
+            .iter()
+            .map(|&(binding, descriptor_type, count)| {
+                vk::DescriptorSetLayoutBinding {
+                    binding: binding,
+                    descriptorType: descriptor_type,
+                    descriptorCount: count,
+                    stageFlags: vk::SHADER_STAGE_COMPUTE_BIT,
+                    pImmutableSamplers: if descriptor_type == vk::DESCRIPTOR_TYPE_SAMPLER {
+                        samplers.as_ptr()
+                    } else {
+                        ptr::null()
+                    },
+                }
+            })
+            .collect();
+        let create_info = vk::DescriptorSetLayoutCreateInfo {
+            sType: vk::STRUCTURE_TYPE_DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
+            pNext: ptr::null(),
+            flags: 0,
+            bindingCount: bindings.len() as u32,
+            pBindings: bindings.as_ptr(),
+        };
+        DescriptorSetLayout::from_create_info(&create_info, ptr::null()).unwrap()
+    }
+
+    fn pool(
+        flags: vk::DescriptorPoolCreateFlags,
+        max_sets: u32,
+        sizes: &[(vk::DescriptorType, u32)],
+    ) -> Result<DescriptorPool, vk::Result> {
+        let sizes: Vec<vk::DescriptorPoolSize> = sizes
+            .iter()
+            .map(|&(ty, count)| {
+                vk::DescriptorPoolSize {
+                    ty: ty,
+                    descriptorCount: count,
+                }
+            })
+            .collect();
+        let create_info = vk::DescriptorPoolCreateInfo {
+            sType: vk::STRUCTURE_TYPE_DESCRIPTOR_POOL_CREATE_INFO,
+            pNext: ptr::null(),
+            flags: flags,
+            maxSets: max_sets,
+            poolSizeCount: sizes.len() as u32,
+            pPoolSizes: sizes.as_ptr(),
+        };
+        DescriptorPool::from_create_info(&create_info, ptr::null())
+    }
+
+    /// A buffer of `size` bytes, bound to `memory` of its own.
+    fn buffer(size: vk::DeviceSize) -> (Buffer, DeviceMemory) {
+        let create_info = vk::BufferCreateInfo {
+            sType: vk::STRUCTURE_TYPE_BUFFER_CREATE_INFO,
+            pNext: ptr::null(),
+            flags: 0,
+            size: size,
+            usage: vk::BUFFER_USAGE_STORAGE_BUFFER_BIT,
+            sharingMode: vk::SHARING_MODE_EXCLUSIVE,
+            queueFamilyIndexCount: 0,
+            pQueueFamilyIndices: ptr::null(),
+        };
+        let mut buffer = Buffer::from_create_info(&create_info, ptr::null()).unwrap();
+        let allocate_info = vk::MemoryAllocateInfo {
+            sType: vk::STRUCTURE_TYPE_MEMORY_ALLOCATE_INFO,
+            pNext: ptr::null(),
+            allocationSize: size,
+            memoryTypeIndex: 0,
+        };
+        let memory = DeviceMemory::from_allocate_info(&allocate_info, ptr::null()).unwrap();
+        buffer.bind(&memory, 0).unwrap();
+        (buffer, memory)
+    }
+
+    fn write_buffers(
+        set: *mut DescriptorSet,
+        binding: u32,
+        descriptor_type: vk::DescriptorType,
+        infos: &[vk::DescriptorBufferInfo],
+    ) -> Result<(), vk::Result> {
+        let write = vk::WriteDescriptorSet {
+            sType: vk::STRUCTURE_TYPE_WRITE_DESCRIPTOR_SET,
+            pNext: ptr::null(),
+            dstSet: set as vk::DescriptorSet,
+            dstBinding: binding,
+            dstArrayElement: 0,
+            descriptorCount: infos.len() as u32,
+            descriptorType: descriptor_type,
+            pImageInfo: ptr::null(),
+            pBufferInfo: infos.as_ptr(),
+            pTexelBufferView: ptr::null(),
+        };
+        unsafe { (*set).write(&write) }
+    }
+
+    /// Reads element `element` of `binding` of the set as a shader does.
+    fn read(table: *const *const *mut u8, binding: usize, element: usize) -> *mut u8 {
+        unsafe { *(*table.add(binding)).add(element) }
+    }
+
+    #[test]
+    fn accounts_for_pool_memory() {
+        let storage = layout(&[(0, vk::DESCRIPTOR_TYPE_STORAGE_BUFFER, 2)], &[]);
+        let uniform = layout(&[(1, vk::DESCRIPTOR_TYPE_UNIFORM_BUFFER, 1)], &[]);
+        let mut pool = pool(
+            vk::DESCRIPTOR_POOL_CREATE_FREE_DESCRIPTOR_SET_BIT,
+            3,
+            &[(vk::DESCRIPTOR_TYPE_STORAGE_BUFFER, 3), (vk::DESCRIPTOR_TYPE_UNIFORM_BUFFER, 1)],
+        ).unwrap();
+        let first = pool.allocate(&[&storage, &uniform]).unwrap();
+        // Allocations that do not fit as a whole take nothing from the pool
+        assert_eq!(
+            pool.allocate(&[&storage, &storage]),
+            Err(vk::ERROR_OUT_OF_POOL_MEMORY_KHR)
+        );
+        assert_eq!(pool.allocate(&[&uniform]), Err(vk::ERROR_OUT_OF_POOL_MEMORY_KHR));
+        pool.free(&[first[0], ptr::null_mut()]).unwrap();
+        let second = pool.allocate(&[&storage]).unwrap();
+        assert_eq!(pool.allocate(&[&storage]), Err(vk::ERROR_OUT_OF_POOL_MEMORY_KHR));
+        pool.free(&second).unwrap();
+        pool.allocate(&[&storage]).unwrap();
+        // Only sets are left to run out of
+        let empty = layout(&[], &[]);
+        assert_eq!(
+            pool.allocate(&[&empty, &empty]),
+            Err(vk::ERROR_OUT_OF_POOL_MEMORY_KHR)
+        );
+        pool.reset();
+        pool.allocate(&[&storage, &uniform, &empty]).unwrap();
+
+        let mut fixed = self::pool(0, 1, &[(vk::DESCRIPTOR_TYPE_STORAGE_BUFFER, 2)]).unwrap();
+        let sets = fixed.allocate(&[&storage]).unwrap();
+        assert_eq!(fixed.free(&sets), Err(vk::ERROR_OUT_OF_HOST_MEMORY));
+
+        let storage = vk::DESCRIPTOR_TYPE_STORAGE_BUFFER;
+        assert_eq!(
+            self::pool(0, 0, &[(storage, 1)]).err(),
+            Some(vk::ERROR_OUT_OF_HOST_MEMORY)
+        );
+        assert_eq!(
+            self::pool(0, 1, &[(storage, u32::max_value()), (storage, 1)]).err(),
+            Some(vk::ERROR_OUT_OF_HOST_MEMORY)
+        );
+    }
+
+    #[test]
+    fn allows_immutable_samplers_to_be_left_out() {
+        let combined = vk::DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER;
+        let layout = layout(&[(0, combined, 2)], &[]);
+        let mut pool = pool(0, 1, &[(combined, 2)]).unwrap();
+        let set = pool.allocate(&[&layout]).unwrap()[0];
+        let table = unsafe { &*set }.bind(&[]).table();
+        for word in 0..4 {
+            assert!(read(table, 0, word).is_null());
+        }
+    }
+
+    #[test]
+    fn writes_and_copies_descriptors() {
+        let layout = layout(
+            &[
+                (0, vk::DESCRIPTOR_TYPE_SAMPLER, 2),
+                (2, vk::DESCRIPTOR_TYPE_STORAGE_BUFFER, 2),
+                (3, vk::DESCRIPTOR_TYPE_STORAGE_BUFFER, 1),
+            ],
+            &[7, 8],
+        );
+        let mut pool = pool(
+            0,
+            2,
+            &[(vk::DESCRIPTOR_TYPE_SAMPLER, 4), (vk::DESCRIPTOR_TYPE_STORAGE_BUFFER, 6)],
+        ).unwrap();
+        let sets = pool.allocate(&[&layout, &layout]).unwrap();
+        let (buffer, _memory) = buffer(256);
+        let info = |offset| {
+            vk::DescriptorBufferInfo {
+                buffer: &buffer as *const Buffer as vk::Buffer,
+                offset: offset,
+                range: vk::WHOLE_SIZE,
+            }
+        };
+        // Writes past the end of a binding continue with the next one
+        write_buffers(
+            sets[0],
+            2,
+            vk::DESCRIPTOR_TYPE_STORAGE_BUFFER,
+            &[info(0), info(16), info(32)],
+        ).unwrap();
+        assert_eq!(
+            write_buffers(sets[0], 2, vk::DESCRIPTOR_TYPE_UNIFORM_BUFFER, &[info(0)]),
+            Err(vk::ERROR_OUT_OF_HOST_MEMORY)
+        );
+        assert_eq!(
+            write_buffers(sets[0], 3, vk::DESCRIPTOR_TYPE_STORAGE_BUFFER, &[info(8)]),
+            Err(vk::ERROR_OUT_OF_HOST_MEMORY)
+        );
+
+        let table = unsafe { &*sets[0] }.bind(&[]).table();
+        assert_eq!(read(table, 0, 0), 7 as *mut u8);
+        assert_eq!(read(table, 0, 1), 8 as *mut u8);
+        // Numbers without a binding have no descriptors
+        assert!(unsafe { *table.add(1) }.is_null());
+        assert_eq!(read(table, 2, 0), buffer.at(0));
+        assert_eq!(read(table, 2, 1), buffer.at(16));
+        assert_eq!(read(table, 3, 0), buffer.at(32));
+
+        let copy = vk::CopyDescriptorSet {
+            sType: vk::STRUCTURE_TYPE_COPY_DESCRIPTOR_SET,
+            pNext: ptr::null(),
+            srcSet: sets[0] as vk::DescriptorSet,
+            srcBinding: 2,
+            srcArrayElement: 1,
+            dstSet: sets[1] as vk::DescriptorSet,
+            dstBinding: 2,
+            dstArrayElement: 0,
+            descriptorCount: 2,
+        };
+        unsafe { DescriptorSet::copy(sets[0], sets[1], &copy) }.unwrap();
+        let table = unsafe { &*sets[1] }.bind(&[]).table();
+        assert_eq!(read(table, 0, 0), 7 as *mut u8);
+        assert_eq!(read(table, 2, 0), buffer.at(16));
+        assert_eq!(read(table, 2, 1), buffer.at(32));
+        assert!(read(table, 3, 0).is_null());
+    }
+
+    #[test]
+    fn adds_dynamic_offsets() {
+        let layout = layout(
+            &[
+                (0, vk::DESCRIPTOR_TYPE_UNIFORM_BUFFER_DYNAMIC, 1),
+                (1, vk::DESCRIPTOR_TYPE_STORAGE_BUFFER, 1),
+                (2, vk::DESCRIPTOR_TYPE_STORAGE_BUFFER_DYNAMIC, 2),
+            ],
+            &[],
+        );
+        let mut pool = pool(
+            0,
+            1,
+            &[
+                (vk::DESCRIPTOR_TYPE_UNIFORM_BUFFER_DYNAMIC, 1),
+                (vk::DESCRIPTOR_TYPE_STORAGE_BUFFER, 1),
+                (vk::DESCRIPTOR_TYPE_STORAGE_BUFFER_DYNAMIC, 2),
+            ],
+        ).unwrap();
+        let set = pool.allocate(&[&layout]).unwrap()[0];
+        let (buffer, _memory) = buffer(256);
+        let info = |offset| {
+            vk::DescriptorBufferInfo {
+                buffer: &buffer as *const Buffer as vk::Buffer,
+                offset: offset,
+                range: 64,
+            }
+        };
+        let dynamic = vk::DESCRIPTOR_TYPE_STORAGE_BUFFER_DYNAMIC;
+        write_buffers(set, 0, vk::DESCRIPTOR_TYPE_UNIFORM_BUFFER_DYNAMIC, &[info(16)]).unwrap();
+        write_buffers(set, 1, vk::DESCRIPTOR_TYPE_STORAGE_BUFFER, &[info(32)]).unwrap();
+        write_buffers(set, 2, dynamic, &[info(32), info(32)]).unwrap();
+
+        let set = unsafe { &*set };
+        assert_eq!(set.dynamic_count(), 3);
+        // The same set bound twice keeps the offsets of each binding apart
+        let first = set.bind(&[0, 16, 32]);
+        let second = set.bind(&[64, 0, 0]);
+        assert_eq!(read(first.table(), 0, 0), buffer.at(16));
+        assert_eq!(read(first.table(), 1, 0), buffer.at(32));
+        assert_eq!(read(first.table(), 2, 0), buffer.at(48));
+        assert_eq!(read(first.table(), 2, 1), buffer.at(64));
+        assert_eq!(read(second.table(), 0, 0), buffer.at(80));
+        assert_eq!(read(second.table(), 2, 0), buffer.at(32));
+
+        // Ranges of 64 bytes at 16 and 32 can move up to the end of the buffer of 256 bytes
+        assert!(set.fits(&[176, 0, 160]));
+        assert!(!set.fits(&[192, 0, 0]));
+        assert!(!set.fits(&[0, 0, 176]));
+    }
+}
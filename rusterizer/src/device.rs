@@ -1,5 +1,22 @@
+// Functions keep the parameters of the commands they implement, whether they use them or not
+#![allow(unused_variables)]
+
+use std::mem;
+use std::ptr;
+use std::slice;
+
+use libc;
 use ffi_types as vk;
+use command::{self, Command};
 use dispatch::{Device, Queue, CommandPool, CommandBuffer};
+use descriptor::{DescriptorPool, DescriptorSet, DescriptorSetLayout, MIN_OFFSET_ALIGNMENT};
+use draw::IndexBuffer;
+use image::{Image, ImageView};
+use memory::{Buffer, DeviceMemory};
+use pipeline::{array, Pipeline, PipelineLayout, GraphicsPipeline, ComputePipeline, DepthBias};
+use pipeline_cache::PipelineCache;
+use render_pass::{Framebuffer, RenderPass};
+use shader::ShaderModule;
 
 pub fn destroy_device(device: Box<Device>, alloc: *const vk::AllocationCallbacks) {
     debug!("Calling destroy_device");
@@ -22,9 +39,12 @@ pub fn create_command_pool(
     CommandPool::from_create_info(create_info, alloc).map(|command_pool| Box::new(command_pool))
 }
 
+/// Allocates `command_buffers.len()` command buffers from the pool of `allocate_info` and
+/// stores their handles in `command_buffers`.
 pub fn allocate_command_buffers(
     device: &Device,
     allocate_info: &mut vk::CommandBufferAllocateInfo,
+    command_buffers: &mut [*const CommandBuffer],
 ) -> vk::Result {
     debug!("Calling allocate_command_buffers");
     debug_assert_eq!(
@@ -32,10 +52,825 @@ pub fn allocate_command_buffers(
         vk::STRUCTURE_TYPE_COMMAND_BUFFER_ALLOCATE_INFO
     );
     debug_assert!(allocate_info.pNext.is_null());
-    for i in 0..allocate_info.commandBufferCount {
+    for handle in command_buffers {
         let buffer = Box::new(CommandBuffer::new(allocate_info.level));
+        // The box keeps the command buffer in place while the pool grows
+        *handle = &*buffer as *const _;
         let buffers = allocate_info.commandPool.buffers_mut();
         buffers.push(buffer);
     }
     vk::SUCCESS
 }
+
+pub fn begin_command_buffer(
+    command_buffer: &mut CommandBuffer,
+    begin_info: &vk::CommandBufferBeginInfo,
+) -> vk::Result {
+    debug!("Calling begin_command_buffer");
+    debug_assert_eq!(begin_info.sType, vk::STRUCTURE_TYPE_COMMAND_BUFFER_BEGIN_INFO);
+    command_buffer.begin();
+    vk::SUCCESS
+}
+
+pub fn end_command_buffer(command_buffer: &mut CommandBuffer) -> vk::Result {
+    debug!("Calling end_command_buffer");
+    vk::SUCCESS
+}
+
+pub fn cmd_bind_pipeline(
+    command_buffer: &mut CommandBuffer,
+    bind_point: vk::PipelineBindPoint,
+    pipeline: *const Pipeline,
+) {
+    debug!("Calling cmd_bind_pipeline");
+    command_buffer.record(Command::BindPipeline(bind_point, pipeline));
+}
+
+/// Records binding `sets` to the sets from `first_set` on. `dynamic_offsets` has an offset
+/// for every dynamic descriptor of the sets.
+pub fn cmd_bind_descriptor_sets(
+    command_buffer: &mut CommandBuffer,
+    bind_point: vk::PipelineBindPoint,
+    layout: &PipelineLayout,
+    first_set: u32,
+    sets: &[*const DescriptorSet],
+    dynamic_offsets: &[u32],
+) {
+    debug!("Calling cmd_bind_descriptor_sets");
+    if first_set as usize + sets.len() > layout.set_layouts.len() {
+        warn!(
+            "Ignoring binding of {} sets from set {} on, the layout only has {}",
+            sets.len(),
+            first_set,
+            layout.set_layouts.len()
+        );
+        return;
+    }
+    let dynamic_count: usize = sets.iter().map(|&set| unsafe { (*set).dynamic_count() }).sum();
+    if dynamic_offsets.len() != dynamic_count {
+        warn!(
+            "Ignoring binding of sets with {} dynamic descriptors and {} dynamic offsets",
+            dynamic_count,
+            dynamic_offsets.len()
+        );
+        return;
+    }
+    if let Some(offset) = dynamic_offsets.iter().find(|&&offset| {
+        u64::from(offset) & (MIN_OFFSET_ALIGNMENT - 1) != 0
+    })
+    {
+        warn!("Ignoring binding of sets with unaligned dynamic offset {}", offset);
+        return;
+    }
+    let mut offsets = dynamic_offsets;
+    for &set in sets {
+        let set = unsafe { &*set };
+        let (own, rest) = offsets.split_at(set.dynamic_count());
+        if !set.fits(own) {
+            warn!("Ignoring binding of sets with dynamic offsets past the end of their buffers");
+            return;
+        }
+        offsets = rest;
+    }
+    command_buffer.record(Command::BindDescriptorSets {
+        bind_point: bind_point,
+        first_set: first_set,
+        sets: sets.to_vec(),
+        dynamic_offsets: dynamic_offsets.to_vec(),
+    });
+}
+
+/// Records setting the push constants of `stages` from `offset` on to `values`.
+pub fn cmd_push_constants(
+    command_buffer: &mut CommandBuffer,
+    layout: &PipelineLayout,
+    stages: vk::ShaderStageFlags,
+    offset: u32,
+    values: &[u8],
+) {
+    debug!("Calling cmd_push_constants");
+    if values.len() > u32::MAX as usize ||
+        !layout.updates_push_constants(stages, offset, values.len() as u32)
+    {
+        warn!(
+            "Ignoring push of {} bytes at offset {} for stages {:#x} the layout has no range for",
+            values.len(),
+            offset,
+            stages
+        );
+        return;
+    }
+    command_buffer.record(Command::PushConstants {
+        offset: offset,
+        values: values.to_vec(),
+    });
+}
+
+/// Records the start of an instance of the render pass of `begin_info`. Its subpasses are
+/// always recorded inline, secondary command buffers do not exist.
+pub fn cmd_begin_render_pass(
+    command_buffer: &mut CommandBuffer,
+    begin_info: &vk::RenderPassBeginInfo,
+    contents: vk::SubpassContents,
+) {
+    debug!("Calling cmd_begin_render_pass");
+    debug_assert_eq!(begin_info.sType, vk::STRUCTURE_TYPE_RENDER_PASS_BEGIN_INFO);
+    let render_pass = begin_info.renderPass as *const RenderPass;
+    let framebuffer = begin_info.framebuffer as *const Framebuffer;
+    let area = begin_info.renderArea;
+    let fits = {
+        let framebuffer = unsafe { &*framebuffer };
+        area.offset.x >= 0 && area.offset.y >= 0 &&
+            area.offset.x as u64 + u64::from(area.extent.width) <= u64::from(framebuffer.width) &&
+            area.offset.y as u64 + u64::from(area.extent.height) <= u64::from(framebuffer.height)
+    };
+    if !fits {
+        warn!("Ignoring render pass with a render area outside of its framebuffer");
+        return;
+    }
+    let values = unsafe { array(begin_info.pClearValues, begin_info.clearValueCount) };
+    let clear_texels = match unsafe { &*render_pass }.clear_texels(values) {
+        Some(clear_texels) => clear_texels,
+        None => {
+            warn!("Ignoring render pass without clear values for all cleared attachments");
+            return;
+        }
+    };
+    command_buffer.record(Command::BeginRenderPass {
+        render_pass: render_pass,
+        framebuffer: framebuffer,
+        area: area,
+        clear_texels: clear_texels,
+    });
+}
+
+pub fn cmd_next_subpass(command_buffer: &mut CommandBuffer, contents: vk::SubpassContents) {
+    debug!("Calling cmd_next_subpass");
+    command_buffer.record(Command::NextSubpass);
+}
+
+pub fn cmd_end_render_pass(command_buffer: &mut CommandBuffer) {
+    debug!("Calling cmd_end_render_pass");
+    command_buffer.record(Command::EndRenderPass);
+}
+
+/// Records a dispatch of the workgroups `base` up to `base + count`.
+pub fn cmd_dispatch_base(command_buffer: &mut CommandBuffer, base: [u32; 3], count: [u32; 3]) {
+    debug!("Calling cmd_dispatch_base");
+    if command::exceeds_workgroup_count(count) {
+        warn!("Ignoring dispatch of {:?} workgroups", count);
+        return;
+    }
+    command_buffer.record(Command::Dispatch {
+        base: base,
+        count: count,
+    });
+}
+
+pub fn cmd_dispatch_indirect(
+    command_buffer: &mut CommandBuffer,
+    buffer: *const Buffer,
+    offset: vk::DeviceSize,
+) {
+    debug!("Calling cmd_dispatch_indirect");
+    if offset & 3 != 0 {
+        warn!("Ignoring indirect dispatch at unaligned offset {}", offset);
+        return;
+    }
+    let size = unsafe { (*buffer).size };
+    match offset.checked_add(mem::size_of::<vk::DispatchIndirectCommand>() as vk::DeviceSize) {
+        Some(end) if end <= size => (),
+        _ => {
+            warn!("Ignoring indirect dispatch at offset {} of a {} byte buffer", offset, size);
+            return;
+        }
+    }
+    command_buffer.record(Command::DispatchIndirect {
+        buffer: buffer,
+        offset: offset,
+    });
+}
+
+/// Records binding `buffers` from `offsets` on to the vertex input bindings from
+/// `first_binding` on.
+pub fn cmd_bind_vertex_buffers(
+    command_buffer: &mut CommandBuffer,
+    first_binding: u32,
+    buffers: &[*const Buffer],
+    offsets: &[vk::DeviceSize],
+) {
+    debug!("Calling cmd_bind_vertex_buffers");
+    if let Some((_, &offset)) = buffers.iter().zip(offsets).find(|&(&buffer, &offset)| {
+        offset >= unsafe { (*buffer).size }
+    })
+    {
+        warn!("Ignoring binding of vertex buffers at offset {} past their end", offset);
+        return;
+    }
+    command_buffer.record(Command::BindVertexBuffers {
+        first_binding: first_binding,
+        buffers: buffers.iter().cloned().zip(offsets.iter().cloned()).collect(),
+    });
+}
+
+pub fn cmd_bind_index_buffer(
+    command_buffer: &mut CommandBuffer,
+    buffer: *const Buffer,
+    offset: vk::DeviceSize,
+    index_type: vk::IndexType,
+) {
+    debug!("Calling cmd_bind_index_buffer");
+    let size = match index_type {
+        vk::INDEX_TYPE_UINT16 => 2,
+        vk::INDEX_TYPE_UINT32 => 4,
+        _ => {
+            warn!("Ignoring index buffer of unknown index type {}", index_type);
+            return;
+        }
+    };
+    if offset % size != 0 || offset >= unsafe { (*buffer).size } {
+        warn!("Ignoring index buffer at offset {}", offset);
+        return;
+    }
+    command_buffer.record(Command::BindIndexBuffer(IndexBuffer {
+        buffer: buffer,
+        offset: offset,
+        index_type: index_type,
+    }));
+}
+
+/// Records setting the viewports from `first_viewport` on, of which there is only one.
+pub fn cmd_set_viewport(
+    command_buffer: &mut CommandBuffer,
+    first_viewport: u32,
+    viewports: &[vk::Viewport],
+) {
+    debug!("Calling cmd_set_viewport");
+    let viewport = match viewports.first() {
+        Some(viewport) if first_viewport == 0 && viewports.len() == 1 => *viewport,
+        _ => {
+            warn!("Ignoring {} viewports from viewport {} on", viewports.len(), first_viewport);
+            return;
+        }
+    };
+    if viewport.width <= 0.0 || viewport.height == 0.0 {
+        warn!("Ignoring empty viewport {:?}", viewport);
+        return;
+    }
+    command_buffer.record(Command::SetViewport(viewport));
+}
+
+/// Records setting the scissors from `first_scissor` on, of which there is only one.
+pub fn cmd_set_scissor(
+    command_buffer: &mut CommandBuffer,
+    first_scissor: u32,
+    scissors: &[vk::Rect2D],
+) {
+    debug!("Calling cmd_set_scissor");
+    let scissor = match scissors.first() {
+        Some(scissor) if first_scissor == 0 && scissors.len() == 1 => *scissor,
+        _ => {
+            warn!("Ignoring {} scissors from scissor {} on", scissors.len(), first_scissor);
+            return;
+        }
+    };
+    if scissor.offset.x < 0 || scissor.offset.y < 0 {
+        warn!("Ignoring scissor {:?} that starts off screen", scissor);
+        return;
+    }
+    command_buffer.record(Command::SetScissor(scissor));
+}
+
+/// Records nothing, lines are not rasterized and 1 is the only line width pipelines accept.
+pub fn cmd_set_line_width(command_buffer: &mut CommandBuffer, line_width: f32) {
+    debug!("Calling cmd_set_line_width");
+    if line_width != 1.0 {
+        warn!("Ignoring line width {}", line_width);
+    }
+}
+
+pub fn cmd_set_depth_bias(command_buffer: &mut CommandBuffer, bias: DepthBias) {
+    debug!("Calling cmd_set_depth_bias");
+    command_buffer.record(Command::SetDepthBias(bias));
+}
+
+pub fn cmd_set_blend_constants(command_buffer: &mut CommandBuffer, constants: [f32; 4]) {
+    debug!("Calling cmd_set_blend_constants");
+    command_buffer.record(Command::SetBlendConstants(constants));
+}
+
+/// Records nothing, pipelines with a depth bounds test are not supported.
+pub fn cmd_set_depth_bounds(command_buffer: &mut CommandBuffer, min: f32, max: f32) {
+    debug!("Calling cmd_set_depth_bounds");
+}
+
+pub fn cmd_set_stencil_compare_mask(
+    command_buffer: &mut CommandBuffer,
+    faces: vk::StencilFaceFlags,
+    compare_mask: u32,
+) {
+    debug!("Calling cmd_set_stencil_compare_mask");
+    command_buffer.record(Command::SetStencilCompareMask(faces, compare_mask));
+}
+
+pub fn cmd_set_stencil_write_mask(
+    command_buffer: &mut CommandBuffer,
+    faces: vk::StencilFaceFlags,
+    write_mask: u32,
+) {
+    debug!("Calling cmd_set_stencil_write_mask");
+    command_buffer.record(Command::SetStencilWriteMask(faces, write_mask));
+}
+
+pub fn cmd_set_stencil_reference(
+    command_buffer: &mut CommandBuffer,
+    faces: vk::StencilFaceFlags,
+    reference: u32,
+) {
+    debug!("Calling cmd_set_stencil_reference");
+    command_buffer.record(Command::SetStencilReference(faces, reference));
+}
+
+pub fn cmd_draw(
+    command_buffer: &mut CommandBuffer,
+    vertex_count: u32,
+    instance_count: u32,
+    first_vertex: u32,
+    first_instance: u32,
+) {
+    debug!("Calling cmd_draw");
+    command_buffer.record(Command::Draw {
+        vertex_count: vertex_count,
+        instance_count: instance_count,
+        first_vertex: first_vertex,
+        first_instance: first_instance,
+    });
+}
+
+pub fn cmd_draw_indexed(
+    command_buffer: &mut CommandBuffer,
+    index_count: u32,
+    instance_count: u32,
+    first_index: u32,
+    vertex_offset: i32,
+    first_instance: u32,
+) {
+    debug!("Calling cmd_draw_indexed");
+    command_buffer.record(Command::DrawIndexed {
+        index_count: index_count,
+        instance_count: instance_count,
+        first_index: first_index,
+        vertex_offset: vertex_offset,
+        first_instance: first_instance,
+    });
+}
+
+/// Runs the command buffers of `submits` in order. Everything is done once this returns, so
+/// semaphores and fences have nothing to wait for.
+pub fn queue_submit(queue: &Queue, submits: &[vk::SubmitInfo], fence: vk::Fence) -> vk::Result {
+    debug!("Calling queue_submit");
+    for submit in submits {
+        debug_assert_eq!(submit.sType, vk::STRUCTURE_TYPE_SUBMIT_INFO);
+        if submit.commandBufferCount == 0 {
+            continue;
+        }
+        let command_buffers = unsafe {
+            slice::from_raw_parts(submit.pCommandBuffers, submit.commandBufferCount as usize)
+        };
+        for &command_buffer in command_buffers {
+            let command_buffer = unsafe { &*(command_buffer as *const CommandBuffer) };
+            unsafe { command::execute(command_buffer.commands()) };
+        }
+    }
+    vk::SUCCESS
+}
+
+pub fn queue_wait_idle(queue: &Queue) -> vk::Result {
+    debug!("Calling queue_wait_idle");
+    vk::SUCCESS
+}
+
+pub fn allocate_memory(
+    device: &Device,
+    allocate_info: &vk::MemoryAllocateInfo,
+    alloc: *const vk::AllocationCallbacks,
+) -> Result<Box<DeviceMemory>, vk::Result> {
+    debug!("Calling allocate_memory");
+    DeviceMemory::from_allocate_info(allocate_info, alloc).map(|memory| Box::new(memory))
+}
+
+pub fn free_memory(
+    device: &Device,
+    memory: Box<DeviceMemory>,
+    alloc: *const vk::AllocationCallbacks,
+) {
+    debug!("Calling free_memory");
+    debug_assert!(alloc.is_null());
+    drop(memory);
+}
+
+/// Returns the address of `memory` at `offset`. Memory is always host visible, so mapping it
+/// cannot fail.
+pub fn map_memory(
+    device: &Device,
+    memory: &DeviceMemory,
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+) -> *mut libc::c_void {
+    debug!("Calling map_memory");
+    debug_assert!(
+        size == vk::WHOLE_SIZE || offset + size <= memory.size() as u64,
+        "mapped range exceeds the allocation"
+    );
+    memory.at(offset) as *mut libc::c_void
+}
+
+pub fn create_buffer(
+    device: &Device,
+    create_info: &vk::BufferCreateInfo,
+    alloc: *const vk::AllocationCallbacks,
+) -> Result<Box<Buffer>, vk::Result> {
+    debug!("Calling create_buffer");
+    Buffer::from_create_info(create_info, alloc).map(|buffer| Box::new(buffer))
+}
+
+pub fn destroy_buffer(device: &Device, buffer: Box<Buffer>, alloc: *const vk::AllocationCallbacks) {
+    debug!("Calling destroy_buffer");
+    debug_assert!(alloc.is_null());
+    drop(buffer);
+}
+
+pub fn bind_buffer_memory(
+    device: &Device,
+    buffer: &mut Buffer,
+    memory: &DeviceMemory,
+    offset: vk::DeviceSize,
+) -> vk::Result {
+    debug!("Calling bind_buffer_memory");
+    match buffer.bind(memory, offset) {
+        Ok(()) => vk::SUCCESS,
+        Err(err) => err,
+    }
+}
+
+pub fn create_image(
+    device: &Device,
+    create_info: &vk::ImageCreateInfo,
+    alloc: *const vk::AllocationCallbacks,
+) -> Result<Box<Image>, vk::Result> {
+    debug!("Calling create_image");
+    Image::from_create_info(create_info, alloc).map(|image| Box::new(image))
+}
+
+pub fn destroy_image(device: &Device, image: Box<Image>, alloc: *const vk::AllocationCallbacks) {
+    debug!("Calling destroy_image");
+    debug_assert!(alloc.is_null());
+    drop(image);
+}
+
+pub fn bind_image_memory(
+    device: &Device,
+    image: &mut Image,
+    memory: &DeviceMemory,
+    offset: vk::DeviceSize,
+) -> vk::Result {
+    debug!("Calling bind_image_memory");
+    match image.bind(memory, offset) {
+        Ok(()) => vk::SUCCESS,
+        Err(err) => err,
+    }
+}
+
+pub fn create_image_view(
+    device: &Device,
+    create_info: &vk::ImageViewCreateInfo,
+    alloc: *const vk::AllocationCallbacks,
+) -> Result<Box<ImageView>, vk::Result> {
+    debug!("Calling create_image_view");
+    ImageView::from_create_info(create_info, alloc).map(|view| Box::new(view))
+}
+
+pub fn destroy_image_view(
+    device: &Device,
+    view: Box<ImageView>,
+    alloc: *const vk::AllocationCallbacks,
+) {
+    debug!("Calling destroy_image_view");
+    debug_assert!(alloc.is_null());
+    drop(view);
+}
+
+pub fn create_render_pass(
+    device: &Device,
+    create_info: &vk::RenderPassCreateInfo,
+    alloc: *const vk::AllocationCallbacks,
+) -> Result<Box<RenderPass>, vk::Result> {
+    debug!("Calling create_render_pass");
+    RenderPass::from_create_info(create_info, alloc).map(|render_pass| Box::new(render_pass))
+}
+
+pub fn destroy_render_pass(
+    device: &Device,
+    render_pass: Box<RenderPass>,
+    alloc: *const vk::AllocationCallbacks,
+) {
+    debug!("Calling destroy_render_pass");
+    debug_assert!(alloc.is_null());
+    drop(render_pass);
+}
+
+pub fn create_framebuffer(
+    device: &Device,
+    create_info: &vk::FramebufferCreateInfo,
+    alloc: *const vk::AllocationCallbacks,
+) -> Result<Box<Framebuffer>, vk::Result> {
+    debug!("Calling create_framebuffer");
+    Framebuffer::from_create_info(create_info, alloc).map(|framebuffer| Box::new(framebuffer))
+}
+
+pub fn destroy_framebuffer(
+    device: &Device,
+    framebuffer: Box<Framebuffer>,
+    alloc: *const vk::AllocationCallbacks,
+) {
+    debug!("Calling destroy_framebuffer");
+    debug_assert!(alloc.is_null());
+    drop(framebuffer);
+}
+
+pub fn create_shader_module(
+    device: &Device,
+    create_info: &vk::ShaderModuleCreateInfo,
+    alloc: *const vk::AllocationCallbacks,
+) -> Result<Box<ShaderModule>, vk::Result> {
+    debug!("Calling create_shader_module");
+    let debug_report = device.debug_report().clone();
+    ShaderModule::from_create_info(create_info, alloc, debug_report)
+        .map(|shader_module| Box::new(shader_module))
+}
+
+pub fn destroy_shader_module(
+    device: &Device,
+    shader_module: Box<ShaderModule>,
+    alloc: *const vk::AllocationCallbacks,
+) {
+    debug!("Calling destroy_shader_module");
+    debug_assert!(alloc.is_null());
+    drop(shader_module);
+}
+
+pub fn create_pipeline_cache(
+    device: &Device,
+    create_info: &vk::PipelineCacheCreateInfo,
+    alloc: *const vk::AllocationCallbacks,
+) -> Result<Box<PipelineCache>, vk::Result> {
+    debug!("Calling create_pipeline_cache");
+    PipelineCache::from_create_info(create_info, alloc).map(|cache| Box::new(cache))
+}
+
+pub fn destroy_pipeline_cache(
+    device: &Device,
+    pipeline_cache: Box<PipelineCache>,
+    alloc: *const vk::AllocationCallbacks,
+) {
+    debug!("Calling destroy_pipeline_cache");
+    debug_assert!(alloc.is_null());
+    drop(pipeline_cache);
+}
+
+/// Writes the data of `pipeline_cache` to `data`, or its size to `data_size` if `data` is
+/// `None`. Returns `INCOMPLETE` if `data` is too small for all of it.
+pub fn get_pipeline_cache_data(
+    device: &Device,
+    pipeline_cache: &PipelineCache,
+    data_size: &mut usize,
+    data: Option<&mut [u8]>,
+) -> vk::Result {
+    debug!("Calling get_pipeline_cache_data");
+    let max_size = data.as_ref().map_or(usize::MAX, |data| data.len());
+    let (cache_data, complete) = pipeline_cache.data(max_size);
+    *data_size = cache_data.len();
+    if let Some(data) = data {
+        data[..cache_data.len()].copy_from_slice(&cache_data);
+    }
+    if complete {
+        vk::SUCCESS
+    } else {
+        vk::INCOMPLETE
+    }
+}
+
+pub fn merge_pipeline_caches(
+    device: &Device,
+    dst_cache: &PipelineCache,
+    src_caches: &[*const PipelineCache],
+) -> vk::Result {
+    debug!("Calling merge_pipeline_caches");
+    for &src_cache in src_caches {
+        dst_cache.merge(unsafe { &*src_cache });
+    }
+    vk::SUCCESS
+}
+
+pub fn create_descriptor_set_layout(
+    device: &Device,
+    create_info: &vk::DescriptorSetLayoutCreateInfo,
+    alloc: *const vk::AllocationCallbacks,
+) -> Result<Box<DescriptorSetLayout>, vk::Result> {
+    debug!("Calling create_descriptor_set_layout");
+    DescriptorSetLayout::from_create_info(create_info, alloc).map(|layout| Box::new(layout))
+}
+
+pub fn destroy_descriptor_set_layout(
+    device: &Device,
+    layout: Box<DescriptorSetLayout>,
+    alloc: *const vk::AllocationCallbacks,
+) {
+    debug!("Calling destroy_descriptor_set_layout");
+    debug_assert!(alloc.is_null());
+    drop(layout);
+}
+
+pub fn create_descriptor_pool(
+    device: &Device,
+    create_info: &vk::DescriptorPoolCreateInfo,
+    alloc: *const vk::AllocationCallbacks,
+) -> Result<Box<DescriptorPool>, vk::Result> {
+    debug!("Calling create_descriptor_pool");
+    DescriptorPool::from_create_info(create_info, alloc).map(|pool| Box::new(pool))
+}
+
+/// Destroys `pool` and frees the sets allocated from it.
+pub fn destroy_descriptor_pool(
+    device: &Device,
+    pool: Box<DescriptorPool>,
+    alloc: *const vk::AllocationCallbacks,
+) {
+    debug!("Calling destroy_descriptor_pool");
+    debug_assert!(alloc.is_null());
+    drop(pool);
+}
+
+pub fn reset_descriptor_pool(
+    device: &Device,
+    pool: &mut DescriptorPool,
+    flags: vk::DescriptorPoolResetFlags,
+) -> vk::Result {
+    debug!("Calling reset_descriptor_pool");
+    pool.reset();
+    vk::SUCCESS
+}
+
+/// Allocates a set for every layout of `allocate_info`. If that fails, all sets are set to
+/// null.
+pub fn allocate_descriptor_sets(
+    device: &Device,
+    allocate_info: &vk::DescriptorSetAllocateInfo,
+    sets: &mut [*mut DescriptorSet],
+) -> vk::Result {
+    debug!("Calling allocate_descriptor_sets");
+    debug_assert_eq!(
+        allocate_info.sType,
+        vk::STRUCTURE_TYPE_DESCRIPTOR_SET_ALLOCATE_INFO
+    );
+    let pool = unsafe { &mut *(allocate_info.descriptorPool as *mut DescriptorPool) };
+    let layouts: Vec<&DescriptorSetLayout> = unsafe {
+        slice::from_raw_parts(allocate_info.pSetLayouts, sets.len())
+            .iter()
+            .map(|&layout| &*(layout as *const DescriptorSetLayout))
+            .collect()
+    };
+    match pool.allocate(&layouts) {
+        Ok(allocated) => {
+            sets.copy_from_slice(&allocated);
+            vk::SUCCESS
+        }
+        Err(err) => {
+            for set in sets {
+                *set = ptr::null_mut();
+            }
+            err
+        }
+    }
+}
+
+pub fn free_descriptor_sets(
+    device: &Device,
+    pool: &mut DescriptorPool,
+    sets: &[*mut DescriptorSet],
+) -> vk::Result {
+    debug!("Calling free_descriptor_sets");
+    match pool.free(sets) {
+        Ok(()) => vk::SUCCESS,
+        Err(err) => err,
+    }
+}
+
+/// Applies `writes`, then `copies`. Updates that do not fit their sets are skipped.
+pub fn update_descriptor_sets(
+    device: &Device,
+    writes: &[vk::WriteDescriptorSet],
+    copies: &[vk::CopyDescriptorSet],
+) {
+    debug!("Calling update_descriptor_sets");
+    for write in writes {
+        let set = unsafe { &mut *(write.dstSet as *mut DescriptorSet) };
+        if unsafe { set.write(write) }.is_err() {
+            warn!("Skipping descriptor write to binding {}", write.dstBinding);
+        }
+    }
+    for copy in copies {
+        let source = copy.srcSet as *const DescriptorSet;
+        let destination = copy.dstSet as *mut DescriptorSet;
+        if unsafe { DescriptorSet::copy(source, destination, copy) }.is_err() {
+            warn!("Skipping descriptor copy to binding {}", copy.dstBinding);
+        }
+    }
+}
+
+pub fn create_pipeline_layout(
+    device: &Device,
+    create_info: &vk::PipelineLayoutCreateInfo,
+    alloc: *const vk::AllocationCallbacks,
+) -> Result<Box<PipelineLayout>, vk::Result> {
+    debug!("Calling create_pipeline_layout");
+    PipelineLayout::from_create_info(create_info, alloc, device.debug_report())
+        .map(|layout| Box::new(layout))
+}
+
+pub fn destroy_pipeline_layout(
+    device: &Device,
+    pipeline_layout: Box<PipelineLayout>,
+    alloc: *const vk::AllocationCallbacks,
+) {
+    debug!("Calling destroy_pipeline_layout");
+    debug_assert!(alloc.is_null());
+    drop(pipeline_layout);
+}
+
+/// Creates a pipeline for every create info. Pipelines that fail are set to null, and the
+/// error of the last of them is returned.
+pub fn create_graphics_pipelines(
+    device: &Device,
+    pipeline_cache: Option<&PipelineCache>,
+    create_infos: &[vk::GraphicsPipelineCreateInfo],
+    alloc: *const vk::AllocationCallbacks,
+    pipelines: &mut [*mut Pipeline],
+) -> vk::Result {
+    debug!("Calling create_graphics_pipelines");
+    let mut result = vk::SUCCESS;
+    for (create_info, pipeline) in create_infos.iter().zip(pipelines.iter_mut()) {
+        let graphics = GraphicsPipeline::from_create_info(
+            create_info,
+            pipeline_cache,
+            alloc,
+            device.debug_report(),
+        );
+        *pipeline = match graphics {
+            Ok(graphics) => Box::into_raw(Box::new(Pipeline::Graphics(graphics))),
+            Err(err) => {
+                result = err;
+                ptr::null_mut()
+            }
+        };
+    }
+    result
+}
+
+/// Creates a compute pipeline for every create info, see `create_graphics_pipelines`.
+pub fn create_compute_pipelines(
+    device: &Device,
+    pipeline_cache: Option<&PipelineCache>,
+    create_infos: &[vk::ComputePipelineCreateInfo],
+    alloc: *const vk::AllocationCallbacks,
+    pipelines: &mut [*mut Pipeline],
+) -> vk::Result {
+    debug!("Calling create_compute_pipelines");
+    let mut result = vk::SUCCESS;
+    for (create_info, pipeline) in create_infos.iter().zip(pipelines.iter_mut()) {
+        let compute = ComputePipeline::from_create_info(
+            create_info,
+            pipeline_cache,
+            alloc,
+            device.debug_report(),
+        );
+        *pipeline = match compute {
+            Ok(compute) => Box::into_raw(Box::new(Pipeline::Compute(compute))),
+            Err(err) => {
+                result = err;
+                ptr::null_mut()
+            }
+        };
+    }
+    result
+}
+
+pub fn destroy_pipeline(
+    device: &Device,
+    pipeline: Box<Pipeline>,
+    alloc: *const vk::AllocationCallbacks,
+) {
+    debug!("Calling destroy_pipeline");
+    debug_assert!(alloc.is_null());
+    drop(pipeline);
+}
@@ -5,10 +5,13 @@
 //! /LoaderAndLayerInterface.md#icd-dispatchable-object-creation
 use std::ffi::CStr;
 use std::default::Default;
+use std::sync::Arc;
 use libc;
 use ffi_types as vk;
 use version::Version;
 use extension::AVAILABLE_EXTENSIONS;
+use debug_report::DebugReport;
+use command::Command;
 
 static ICD_LOADER_MAGIC: usize = 0x01CDC0DE;
 
@@ -20,20 +23,6 @@ pub struct VkLoaderDataUnion {
     data: usize,
 }
 
-impl VkLoaderDataUnion {
-    fn as_loader_magic(&self) -> usize {
-        self.data
-    }
-
-    fn as_loader_data(&self) -> *const libc::c_void {
-        &self.data as *const _ as *const libc::c_void
-    }
-
-    fn set_loader_magic(&mut self, val: usize) {
-        self.data = val;
-    }
-}
-
 impl Default for VkLoaderDataUnion {
     fn default() -> Self {
         VkLoaderDataUnion { data: ICD_LOADER_MAGIC }
@@ -50,6 +39,7 @@ pub struct Instance {
     enabled_extensions: Vec<String>,
     // Only one physical device per instance, the CPU
     phys_device: Option<PhysicalDevice>,
+    debug_report: Arc<DebugReport>,
 }
 
 impl Instance {
@@ -100,6 +90,7 @@ impl Instance {
             enabled_layers: Vec::new(),
             enabled_extensions: requested_extensions,
             phys_device: None,
+            debug_report: Arc::default(),
         })
     }
 
@@ -107,8 +98,15 @@ impl Instance {
         self.phys_device.as_ref()
     }
 
+    pub fn debug_report(&self) -> &DebugReport {
+        &self.debug_report
+    }
+
     fn init_phys_device(&mut self) {
-        self.phys_device = Some(PhysicalDevice::default());
+        self.phys_device = Some(PhysicalDevice {
+            _loader_data: VkLoaderDataUnion::default(),
+            debug_report: self.debug_report.clone(),
+        });
     }
 }
 
@@ -145,6 +143,14 @@ pub fn destroy_instance(instance: Box<Instance>, alloc: *const vk::AllocationCal
 #[repr(C)]
 pub struct PhysicalDevice {
     _loader_data: VkLoaderDataUnion,
+    debug_report: Arc<DebugReport>,
+}
+
+impl PhysicalDevice {
+    /// The callbacks of the instance, which devices report to as well.
+    pub fn debug_report(&self) -> &Arc<DebugReport> {
+        &self.debug_report
+    }
 }
 
 pub fn enumerate_physical_devices<'a>(
@@ -176,12 +182,14 @@ pub fn enumerate_physical_devices<'a>(
 pub struct Device {
     _loader_data: VkLoaderDataUnion,
     queue: Queue,
+    debug_report: Arc<DebugReport>,
 }
 
 impl Device {
     pub fn from_create_info(
         create_info: &vk::DeviceCreateInfo,
         alloc: *const vk::AllocationCallbacks,
+        debug_report: Arc<DebugReport>,
     ) -> Result<Self, vk::Result> {
         debug_assert_eq!(create_info.sType, vk::STRUCTURE_TYPE_DEVICE_CREATE_INFO);
         // TODO why does this fail?
@@ -194,12 +202,17 @@ impl Device {
         Ok(Device {
             _loader_data: VkLoaderDataUnion::default(),
             queue: Queue::default(),
+            debug_report: debug_report,
         })
     }
 
     pub fn queue(&self) -> &Queue {
         &self.queue
     }
+
+    pub fn debug_report(&self) -> &Arc<DebugReport> {
+        &self.debug_report
+    }
 }
 
 #[derive(Debug, Default)]
@@ -241,6 +254,7 @@ impl CommandPool {
 pub struct CommandBuffer {
     _loader_data: VkLoaderDataUnion,
     level: vk::CommandBufferLevel,
+    commands: Vec<Command>,
 }
 
 impl CommandBuffer {
@@ -248,6 +262,20 @@ impl CommandBuffer {
         CommandBuffer {
             _loader_data: VkLoaderDataUnion::default(),
             level: level,
+            commands: Vec::new(),
         }
     }
+
+    /// Starts recording, dropping the commands recorded before.
+    pub fn begin(&mut self) {
+        self.commands.clear();
+    }
+
+    pub fn record(&mut self, command: Command) {
+        self.commands.push(command);
+    }
+
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
 }
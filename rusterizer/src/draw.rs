@@ -0,0 +1,895 @@
+//! Draws of triangles: the vertices are fetched and shaded, assembled into triangles and
+//! rasterized, and the fragment shader and the per-sample operations run on what they cover.
+//! Triangles are clipped before they are set up, and binned into tiles, which are shaded in
+//! parallel.
+//!
+//! Every vertex of a draw is shaded once for each time it is used. Only the first layer of the
+//! attachments is drawn to, and points and lines are not rasterized.
+use std::ops::Range;
+use std::slice;
+
+use spirv_llvm::{Interpolation, ScalarType};
+use spirv_llvm::abi::{Invocation, Resources, Slot, MAX_CLIP_DISTANCES, MAX_CULL_DISTANCES};
+use ffi_types as vk;
+use batch::{self, Quad};
+use clipper::{Clipper, Vertex};
+use format::{Depth, Format, Numeric};
+use image::ImageView;
+use memory::Buffer;
+use pipeline::{DepthBias, GraphicsPipeline};
+use rasterizer::{Rect, Triangle};
+use render_pass::RenderPassInstance;
+use tiler::{self, Bins};
+
+/// A buffer bound to a vertex input binding, and the offset of the binding in it.
+pub type VertexBuffer = (*const Buffer, vk::DeviceSize);
+
+/// The buffer indexed draws take their indices from.
+#[derive(Clone, Copy, Debug)]
+pub struct IndexBuffer {
+    pub buffer: *const Buffer,
+    pub offset: vk::DeviceSize,
+    pub index_type: vk::IndexType,
+}
+
+/// The vertices of a draw.
+#[derive(Clone, Copy, Debug)]
+pub enum Vertices {
+    /// `count` vertices from `first` on.
+    Sequential { first: u32, count: u32 },
+    /// The vertices of `count` indices from index `first` on, plus `vertex_offset`.
+    Indexed {
+        buffer: IndexBuffer,
+        first: u32,
+        count: u32,
+        vertex_offset: i32,
+    },
+}
+
+/// The state set by commands, which draws use instead of that of the pipeline where it is
+/// dynamic.
+#[derive(Clone, Copy, Debug)]
+pub struct DynamicState {
+    pub viewport: Option<vk::Viewport>,
+    pub scissor: Option<vk::Rect2D>,
+    pub depth_bias: DepthBias,
+    pub blend_constants: [f32; 4],
+    /// The stencil values of front and back facing triangles.
+    pub stencil_compare_mask: [u32; 2],
+    pub stencil_write_mask: [u32; 2],
+    pub stencil_reference: [u32; 2],
+}
+
+impl Default for DynamicState {
+    fn default() -> Self {
+        DynamicState {
+            viewport: None,
+            scissor: None,
+            depth_bias: DepthBias {
+                constant_factor: 0.0,
+                clamp: 0.0,
+                slope_factor: 0.0,
+            },
+            blend_constants: [0.0; 4],
+            stencil_compare_mask: [0; 2],
+            stencil_write_mask: [0; 2],
+            stencil_reference: [0; 2],
+        }
+    }
+}
+
+/// Draws the `instances` of `vertices` with `pipeline` to the current subpass of `pass`.
+///
+/// Unsafe because the buffers, the attachments and whatever the resources point to have to be
+/// alive.
+pub unsafe fn draw(
+    pipeline: &GraphicsPipeline,
+    dynamic: &DynamicState,
+    vertex_buffers: &[Option<VertexBuffer>],
+    resources: &Resources,
+    pass: &RenderPassInstance,
+    vertices: &Vertices,
+    instances: Range<u32>,
+) {
+    let rasterization = if pipeline.rasterization.discard {
+        None
+    } else {
+        match pipeline.topology {
+            vk::PRIMITIVE_TOPOLOGY_TRIANGLE_LIST |
+            vk::PRIMITIVE_TOPOLOGY_TRIANGLE_STRIP |
+            vk::PRIMITIVE_TOPOLOGY_TRIANGLE_FAN => {
+                match Rasterization::new(pipeline, dynamic, resources, pass) {
+                    Some(rasterization) => Some(rasterization),
+                    None => return,
+                }
+            }
+            topology => {
+                warn!("Skipping rasterization of primitive topology {}", topology);
+                None
+            }
+        }
+    };
+
+    let indices = indices(vertices, pipeline.primitive_restart);
+    let base_vertex = match *vertices {
+        Vertices::Sequential { first, .. } => first as i32,
+        Vertices::Indexed { vertex_offset, .. } => vertex_offset,
+    };
+    let entry_point = pipeline.vertex.entry_point();
+    let mut bins = rasterization.as_ref().map(|rasterization| {
+        Bins::new(rasterization.clip, rasterization.samples, tiler::config())
+    });
+    for instance in instances.clone() {
+        // The shaded vertex of every index, `None` where primitive restart splits the draw
+        let mut invocations = Vec::with_capacity(indices.len());
+        let mut shaded = Vec::with_capacity(indices.len());
+        for &index in &indices {
+            shaded.push(index.map(|index| {
+                let mut invocation = Invocation::new(resources);
+                let builtins = &mut invocation.builtins;
+                builtins.vertex_index = index as i32;
+                builtins.instance_index = instance as i32;
+                builtins.base_vertex = base_vertex;
+                builtins.base_instance = instances.start as i32;
+                fetch(pipeline, vertex_buffers, &mut invocation, index, instance);
+                invocations.push(invocation);
+                invocations.len() - 1
+            }));
+        }
+        batch::shade_vertices(&entry_point, &mut invocations);
+
+        if let (Some(rasterization), Some(bins)) = (rasterization.as_ref(), bins.as_mut()) {
+            for corners in triangles(pipeline.topology, &shaded) {
+                rasterization.setup(&invocations, corners, |triangle, primitive| {
+                    bins.push(triangle, primitive)
+                });
+            }
+        }
+    }
+    if let (Some(rasterization), Some(bins)) = (rasterization, bins) {
+        bins.draw(|triangle, primitive, quads| {
+            rasterization.shade(triangle, primitive, quads)
+        });
+    }
+}
+
+/// The vertex indices of the draw, `None` for primitive restarts.
+unsafe fn indices(vertices: &Vertices, restart: bool) -> Vec<Option<u32>> {
+    match *vertices {
+        Vertices::Sequential { first, count } => {
+            (0..count).map(|index| Some(first.wrapping_add(index))).collect()
+        }
+        Vertices::Indexed {
+            buffer,
+            first,
+            count,
+            vertex_offset,
+        } => {
+            let (size, restart_index) = if buffer.index_type == vk::INDEX_TYPE_UINT16 {
+                (2, 0xffff)
+            } else {
+                (4, !0)
+            };
+            (0..count)
+                .map(|index| {
+                    let offset = buffer.offset + (u64::from(first) + u64::from(index)) * size;
+                    // Indices outside of the buffer are 0, as robust buffer access allows
+                    let index = match read(&*buffer.buffer, offset, size) {
+                        Some(bytes) if size == 2 => {
+                            u32::from(u16::from_le_bytes([bytes[0], bytes[1]]))
+                        }
+                        Some(bytes) => {
+                            u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+                        }
+                        None => 0,
+                    };
+                    if restart && index == restart_index {
+                        None
+                    } else {
+                        Some((index as i32).wrapping_add(vertex_offset) as u32)
+                    }
+                })
+                .collect()
+        }
+    }
+}
+
+/// The `size` bytes at `offset` of `buffer`, `None` if they are not all in it or it has no
+/// memory.
+unsafe fn read(buffer: &Buffer, offset: vk::DeviceSize, size: u64) -> Option<&[u8]> {
+    match offset.checked_add(size) {
+        Some(end) if end <= buffer.size => (),
+        _ => return None,
+    }
+    let data = buffer.at(offset);
+    if data.is_null() {
+        None
+    } else {
+        Some(slice::from_raw_parts(data, size as usize))
+    }
+}
+
+/// Fills in the inputs of `invocation` from the vertex attributes of vertex `index`.
+unsafe fn fetch(
+    pipeline: &GraphicsPipeline,
+    vertex_buffers: &[Option<VertexBuffer>],
+    invocation: &mut Invocation,
+    index: u32,
+    instance: u32,
+) {
+    for attribute in &pipeline.attributes {
+        let binding = match pipeline.bindings.iter().find(
+            |binding| binding.binding == attribute.binding,
+        ) {
+            Some(binding) => binding,
+            None => continue,
+        };
+        let element = if binding.per_instance { instance } else { index };
+        let size = u64::from(attribute.format.size());
+        let bytes = match vertex_buffers.get(binding.binding as usize) {
+            Some(&Some((buffer, offset))) => {
+                let offset = offset + u64::from(element) * u64::from(binding.stride) +
+                    u64::from(attribute.offset);
+                read(&*buffer, offset, size)
+            }
+            _ => None,
+        };
+        // Attributes outside of their buffer read as zero, as robust buffer access allows
+        let value = attribute.format.decode(bytes.unwrap_or(&[0; 16]));
+        if let Some(input) = invocation.inputs.get_mut(attribute.location as usize) {
+            *input = value;
+        }
+    }
+}
+
+/// The triangles of the primitive `topology` of the shaded vertices, as their indices in the
+/// order they are set up in, which starts at the provoking vertex. A `None` vertex restarts the
+/// strip or fan.
+fn triangles(topology: vk::PrimitiveTopology, vertices: &[Option<usize>]) -> Vec<[usize; 3]> {
+    let mut triangles = Vec::new();
+    for strip in vertices.split(|vertex| vertex.is_none()) {
+        let strip: Vec<usize> = strip.iter().filter_map(|&vertex| vertex).collect();
+        let count = strip.len();
+        match topology {
+            vk::PRIMITIVE_TOPOLOGY_TRIANGLE_LIST => {
+                for triangle in strip.chunks(3).filter(|triangle| triangle.len() == 3) {
+                    triangles.push([triangle[0], triangle[1], triangle[2]]);
+                }
+            }
+            // Every other triangle is flipped to keep the winding order
+            vk::PRIMITIVE_TOPOLOGY_TRIANGLE_STRIP => {
+                for index in 0..count.saturating_sub(2) {
+                    triangles.push([
+                        strip[index],
+                        strip[index + 1 + index % 2],
+                        strip[index + 2 - index % 2],
+                    ]);
+                }
+            }
+            vk::PRIMITIVE_TOPOLOGY_TRIANGLE_FAN => {
+                for index in 0..count.saturating_sub(2) {
+                    triangles.push([strip[index + 1], strip[index + 2], strip[0]]);
+                }
+            }
+            _ => (),
+        }
+    }
+    triangles
+}
+
+/// A triangle as its fragments see it.
+struct Primitive {
+    /// The window coordinates, depth and 1 / w of the vertices.
+    corners: [[f32; 4]; 3],
+    /// The components of the vertices that are interpolated, in the order of
+    /// `Rasterization::interpolated`.
+    values: [Vec<f32>; 3],
+    /// The components that are not interpolated, from the provoking vertex.
+    flat: Vec<u32>,
+    /// The depth bias of all fragments.
+    depth_offset: f32,
+}
+
+/// What the fragments of a draw need: the pipeline, the state it leaves to commands and the
+/// attachments of the subpass. The resources and attachments point to memory the application
+/// keeps alive and synchronizes until the draw is done, and every tile writes its own pixels.
+struct Rasterization<'a> {
+    pipeline: &'a GraphicsPipeline,
+    resources: &'a Resources,
+    clipper: Clipper,
+    /// The pixels of both the scissor and the render area.
+    clip: Rect,
+    samples: u32,
+    colors: Vec<Option<&'a ImageView>>,
+    depth_stencil: Option<&'a ImageView>,
+    depth_bias: Option<DepthBias>,
+    blend_constants: [f32; 4],
+    /// The stencil state of front and back facing triangles.
+    stencil: [vk::StencilOpState; 2],
+    /// The Location and component of each interpolated input of the fragment shader, and
+    /// whether it is perspective correct.
+    interpolated: Vec<(usize, usize, bool)>,
+    /// The Location and component of each flat input.
+    flat: Vec<(usize, usize)>,
+}
+
+unsafe impl<'a> Sync for Rasterization<'a> {}
+
+impl<'a> Rasterization<'a> {
+    /// `None` if the draw has no viewport or scissor to rasterize with.
+    unsafe fn new(
+        pipeline: &'a GraphicsPipeline,
+        dynamic: &DynamicState,
+        resources: &'a Resources,
+        pass: &'a RenderPassInstance,
+    ) -> Option<Self> {
+        let viewport = if pipeline.dynamic.contains(vk::DYNAMIC_STATE_VIEWPORT) {
+            dynamic.viewport
+        } else {
+            pipeline.viewports.first().cloned()
+        };
+        let scissor = if pipeline.dynamic.contains(vk::DYNAMIC_STATE_SCISSOR) {
+            dynamic.scissor
+        } else {
+            pipeline.scissors.first().cloned()
+        };
+        let (viewport, scissor) = match (viewport, scissor) {
+            (Some(viewport), Some(scissor)) => (viewport, scissor),
+            _ => {
+                warn!("Skipping draw without a viewport and scissor");
+                return None;
+            }
+        };
+        let (colors, depth_stencil) = pass.targets();
+
+        let depth_bias = pipeline.rasterization.depth_bias.map(|bias| {
+            if pipeline.dynamic.contains(vk::DYNAMIC_STATE_DEPTH_BIAS) {
+                dynamic.depth_bias
+            } else {
+                bias
+            }
+        });
+        let blend_constants = if pipeline.dynamic.contains(vk::DYNAMIC_STATE_BLEND_CONSTANTS) {
+            dynamic.blend_constants
+        } else {
+            pipeline.color_blend.blend_constants
+        };
+        // Without a depth stencil state, there is no stencil test to use these
+        let keep = vk::StencilOpState {
+            failOp: vk::STENCIL_OP_KEEP,
+            passOp: vk::STENCIL_OP_KEEP,
+            depthFailOp: vk::STENCIL_OP_KEEP,
+            compareOp: vk::COMPARE_OP_ALWAYS,
+            compareMask: 0,
+            writeMask: 0,
+            reference: 0,
+        };
+        let mut stencil = match pipeline.depth_stencil {
+            Some(ref state) => [state.front, state.back],
+            None => [keep; 2],
+        };
+        for (face, state) in stencil.iter_mut().enumerate() {
+            if pipeline.dynamic.contains(vk::DYNAMIC_STATE_STENCIL_COMPARE_MASK) {
+                state.compareMask = dynamic.stencil_compare_mask[face];
+            }
+            if pipeline.dynamic.contains(vk::DYNAMIC_STATE_STENCIL_WRITE_MASK) {
+                state.writeMask = dynamic.stencil_write_mask[face];
+            }
+            if pipeline.dynamic.contains(vk::DYNAMIC_STATE_STENCIL_REFERENCE) {
+                state.reference = dynamic.stencil_reference[face];
+            }
+        }
+
+        // Only 32 bit floats are interpolated, the bits of everything else are passed on
+        let (mut interpolated, mut flat) = (Vec::new(), Vec::new());
+        for varying in &pipeline.varyings {
+            let perspective = match (varying.scalar, varying.interpolation) {
+                (_, Interpolation::Flat) => None,
+                (Some(ScalarType::Float(32)), Interpolation::Smooth) => Some(true),
+                (Some(ScalarType::Float(32)), Interpolation::NoPerspective) => Some(false),
+                _ => None,
+            };
+            for location in varying.location..varying.location + varying.locations {
+                for component in varying.component..varying.component + varying.components {
+                    let input = (location as usize, component as usize);
+                    match perspective {
+                        Some(perspective) => interpolated.push((input.0, input.1, perspective)),
+                        None => flat.push(input),
+                    }
+                }
+            }
+        }
+
+        Some(Rasterization {
+            pipeline: pipeline,
+            resources: resources,
+            // The shaders do not tell how many distances they write, the others stay 0, which
+            // neither clips nor culls
+            clipper: Clipper::new(
+                &pipeline.rasterization,
+                &viewport,
+                MAX_CLIP_DISTANCES,
+                MAX_CULL_DISTANCES,
+            ),
+            clip: Rect::from_vk(&scissor).intersect(&Rect::from_vk(&pass.area())),
+            samples: pipeline.multisample.samples,
+            colors: colors,
+            depth_stencil: depth_stencil,
+            depth_bias: depth_bias,
+            blend_constants: blend_constants,
+            stencil: stencil,
+            interpolated: interpolated,
+            flat: flat,
+        })
+    }
+
+    /// Clips the triangle of the shaded vertices `corners` and calls `emit` for each of the
+    /// triangles it is split into that covers any pixels.
+    fn setup<F>(&self, invocations: &[Invocation], corners: [usize; 3], mut emit: F)
+    where
+        F: FnMut(Triangle, Primitive),
+    {
+        let vertices = [
+            &invocations[corners[0]],
+            &invocations[corners[1]],
+            &invocations[corners[2]],
+        ];
+        let values = |vertex: &Invocation| -> Vec<f32> {
+            self.interpolated
+                .iter()
+                .map(|&(location, component, _)| {
+                    f32::from_bits(vertex.outputs[location].0[component])
+                })
+                .collect()
+        };
+        let values = [values(vertices[0]), values(vertices[1]), values(vertices[2])];
+        let flat: Vec<u32> = self.flat
+            .iter()
+            .map(|&(location, component)| vertices[0].outputs[location].0[component])
+            .collect();
+
+        let builtins = [&vertices[0].builtins, &vertices[1].builtins, &vertices[2].builtins];
+        self.clipper.clip(builtins, |clipped| {
+            let window = |index: usize| {
+                let [x, y, depth] = self.clipper.window(&clipped[index]);
+                [x, y, depth, 1.0 / clipped[index].position[3]]
+            };
+            let windows = [window(0), window(1), window(2)];
+            let triangle = match Triangle::setup(
+                [
+                    [windows[0][0], windows[0][1]],
+                    [windows[1][0], windows[1][1]],
+                    [windows[2][0], windows[2][1]],
+                ],
+                &self.pipeline.rasterization,
+            ) {
+                Some(triangle) => triangle,
+                None => return,
+            };
+            let original = [&values[0][..], &values[1][..], &values[2][..]];
+            let interpolate = |vertex: &Vertex| {
+                let mut outputs = vec![0.0; self.interpolated.len()];
+                vertex.interpolate(original, &mut outputs);
+                outputs
+            };
+            let primitive = Primitive {
+                corners: windows,
+                values: [
+                    interpolate(&clipped[0]),
+                    interpolate(&clipped[1]),
+                    interpolate(&clipped[2]),
+                ],
+                // Also for the vertices made by clipping
+                flat: flat.clone(),
+                depth_offset: self.depth_offset(&windows),
+            };
+            emit(triangle, primitive);
+        });
+    }
+
+    /// The depth bias of a triangle with the window coordinates and depths `corners`.
+    fn depth_offset(&self, corners: &[[f32; 4]; 3]) -> f32 {
+        let bias = match self.depth_bias {
+            Some(bias) => bias,
+            None => return 0.0,
+        };
+        let [[x0, y0, z0, _], [x1, y1, z1, _], [x2, y2, z2, _]] = *corners;
+        let area = (x1 - x0) * (y2 - y0) - (x2 - x0) * (y1 - y0);
+        let dz_dx = ((z1 - z0) * (y2 - y0) - (z2 - z0) * (y1 - y0)) / area;
+        let dz_dy = ((x1 - x0) * (z2 - z0) - (x2 - x0) * (z1 - z0)) / area;
+        let slope = dz_dx.abs().max(dz_dy.abs());
+        let slope = if slope.is_finite() { slope } else { 0.0 };
+        // The least difference of depths the format keeps apart
+        let resolution = match self.depth_stencil.map(|view| view.format) {
+            Some(Format::DepthStencil { depth: Some(Depth::Unorm16), .. }) => 2.0f32.powi(-16),
+            Some(Format::DepthStencil { depth: Some(Depth::Sfloat32), .. }) => {
+                let max = z0.abs().max(z1.abs()).max(z2.abs());
+                let exponent = ((max.to_bits() >> 23) & 0xff) as i32 - 127;
+                2.0f32.powi(exponent - 23)
+            }
+            _ => 2.0f32.powi(-24),
+        };
+        let offset = slope * bias.slope_factor + resolution * bias.constant_factor;
+        if bias.clamp > 0.0 {
+            offset.min(bias.clamp)
+        } else if bias.clamp < 0.0 {
+            offset.max(bias.clamp)
+        } else {
+            offset
+        }
+    }
+
+    /// Runs the fragment shader and the per-sample operations on the `quads` of `triangle`.
+    unsafe fn shade(&self, triangle: &Triangle, primitive: &Primitive, quads: &[Quad]) {
+        let fragment = self.pipeline.fragment.as_ref();
+        let early = match fragment {
+            Some(fragment) => fragment.info().modes.early_fragment_tests,
+            None => true,
+        };
+        // The covered samples of every fragment, which the tests may take away
+        let mut quads = quads.to_vec();
+        let mut masks = vec![0; quads.len() * 4];
+        for (quad, masks) in quads.iter_mut().zip(masks.chunks_mut(4)) {
+            for (index, mask) in masks.iter_mut().enumerate() {
+                if !quad.covers(index) {
+                    continue;
+                }
+                let (x, y) = quad.fragment(index);
+                *mask = triangle.sample_mask(x, y, self.samples) &
+                    self.pipeline.multisample.sample_mask;
+                if early {
+                    *mask = self.depth_stencil_test(triangle, primitive, x, y, *mask, None);
+                }
+                if *mask == 0 {
+                    quad.coverage &= !(1 << index);
+                }
+            }
+        }
+        let fragment = match fragment {
+            Some(fragment) => fragment,
+            None => return,
+        };
+
+        let invocations = batch::shade_quads(
+            &fragment.entry_point(),
+            self.resources,
+            &quads,
+            |invocation, x, y| self.setup_fragment(invocation, triangle, primitive, x, y),
+        );
+        let depth_replacing = fragment.info().modes.depth_replacing;
+        for (index, invocation) in invocations.iter().enumerate() {
+            let mut mask = masks[index];
+            if mask == 0 || invocation.killed != 0 {
+                continue;
+            }
+            let (x, y) = quads[index / 4].fragment(index % 4);
+            if self.pipeline.multisample.alpha_to_coverage {
+                let alpha = invocation.outputs[0].to_f32()[3];
+                let samples = (alpha.clamp(0.0, 1.0) * self.samples as f32).round();
+                mask &= batch::first(samples as usize);
+            }
+            if !early {
+                let depth = if depth_replacing {
+                    Some(self.clipper.fragment_depth(invocation.builtins.frag_depth))
+                } else {
+                    None
+                };
+                mask = self.depth_stencil_test(triangle, primitive, x, y, mask, depth);
+            }
+            if mask != 0 {
+                self.write_colors(x, y, mask, invocation);
+            }
+        }
+    }
+
+    /// Fills in the inputs of the fragment shader at pixel `(x, y)` of `triangle`.
+    fn setup_fragment(
+        &self,
+        invocation: &mut Invocation,
+        triangle: &Triangle,
+        primitive: &Primitive,
+        x: u32,
+        y: u32,
+    ) {
+        let weights = triangle.barycentrics(x, y);
+        let corners = &primitive.corners;
+        let interpolate = |weights: &[f32; 3], component: usize| {
+            (0..3).map(|corner| weights[corner] * corners[corner][component]).sum::<f32>()
+        };
+        let inverse_w = interpolate(&weights, 3);
+        let builtins = &mut invocation.builtins;
+        builtins.frag_coord.0[2] =
+            self.clipper.fragment_depth(interpolate(&weights, 2) + primitive.depth_offset);
+        builtins.frag_coord.0[3] = inverse_w;
+        builtins.front_facing = triangle.front_facing as u32;
+
+        let perspective = [
+            weights[0] * corners[0][3] / inverse_w,
+            weights[1] * corners[1][3] / inverse_w,
+            weights[2] * corners[2][3] / inverse_w,
+        ];
+        for (index, &(location, component, correct)) in self.interpolated.iter().enumerate() {
+            let weights = if correct { &perspective } else { &weights };
+            let value: f32 = (0..3)
+                .map(|corner| weights[corner] * primitive.values[corner][index])
+                .sum();
+            invocation.inputs[location].0[component] = value.to_bits();
+        }
+        for (&(location, component), &value) in self.flat.iter().zip(&primitive.flat) {
+            invocation.inputs[location].0[component] = value;
+        }
+    }
+
+    /// The depth of sample `sample` of pixel `(x, y)` of `triangle`.
+    fn sample_depth(
+        &self,
+        triangle: &Triangle,
+        primitive: &Primitive,
+        x: u32,
+        y: u32,
+        sample: usize,
+    ) -> f32 {
+        let weights = if self.samples == 1 {
+            triangle.barycentrics(x, y)
+        } else {
+            triangle.sample_barycentrics(x, y, sample)
+        };
+        let depth: f32 = (0..3).map(|corner| weights[corner] * primitive.corners[corner][2]).sum();
+        self.clipper.fragment_depth(depth + primitive.depth_offset)
+    }
+
+    /// Runs the stencil and depth tests of the samples `mask` of pixel `(x, y)`, updates the
+    /// depth/stencil attachment and returns the samples that pass. `depth` replaces the depth of
+    /// the samples if the fragment shader writes one.
+    unsafe fn depth_stencil_test(
+        &self,
+        triangle: &Triangle,
+        primitive: &Primitive,
+        x: u32,
+        y: u32,
+        mask: u32,
+        depth: Option<f32>,
+    ) -> u32 {
+        let (view, state) = match (self.depth_stencil, self.pipeline.depth_stencil.as_ref()) {
+            (Some(view), Some(state)) => (view, state),
+            _ => return mask,
+        };
+        let format = view.format;
+        let (depth_format, has_stencil) = match format {
+            Format::DepthStencil { depth, stencil } => (depth, stencil),
+            Format::Color { .. } => return mask,
+        };
+        let texel = (*view.image).texel(view.base_level, view.base_layer, x, y, 0);
+        if texel.is_null() {
+            return mask;
+        }
+        let size = format.size();
+        let depth_bytes = format.aspect_bytes(vk::IMAGE_ASPECT_DEPTH_BIT);
+        let stencil_byte = format.aspect_bytes(vk::IMAGE_ASPECT_STENCIL_BIT).start;
+        let stencil = &self.stencil[if triangle.front_facing { 0 } else { 1 }];
+        let stencil_test = state.stencil_test && has_stencil;
+        let depth_test = state.depth_test && depth_format.is_some();
+
+        let mut passed = 0;
+        for sample in (0..self.samples as usize).filter(|&sample| mask & 1 << sample != 0) {
+            let bytes = slice::from_raw_parts_mut(texel.add(sample * size), size);
+            let stored_stencil = u32::from(bytes[stencil_byte]);
+            let stencil_passed = !stencil_test ||
+                compare(
+                    stencil.compareOp,
+                    stencil.reference & stencil.compareMask,
+                    stored_stencil & stencil.compareMask,
+                );
+            let depth_passed = stencil_passed &&
+                (!depth_test || {
+                    let mut value = depth.unwrap_or_else(|| {
+                        self.sample_depth(triangle, primitive, x, y, sample)
+                    });
+                    if depth_format != Some(Depth::Sfloat32) {
+                        value = value.clamp(0.0, 1.0);
+                    }
+                    let passed = compare(state.depth_compare, value, format.decode_depth(bytes));
+                    if passed && state.depth_write {
+                        let texel = format.encode_depth_stencil(value, 0);
+                        bytes[depth_bytes.clone()].copy_from_slice(&texel[depth_bytes.clone()]);
+                    }
+                    passed
+                });
+            if stencil_test {
+                let op = if !stencil_passed {
+                    stencil.failOp
+                } else if !depth_passed {
+                    stencil.depthFailOp
+                } else {
+                    stencil.passOp
+                };
+                let value = stencil_op(op, stored_stencil, stencil.reference);
+                let written = stored_stencil & !stencil.writeMask | value & stencil.writeMask;
+                bytes[stencil_byte] = written as u8;
+            }
+            if depth_passed {
+                passed |= 1 << sample;
+            }
+        }
+        passed
+    }
+
+    /// Writes the outputs of `invocation` to the samples `mask` of pixel `(x, y)` of the color
+    /// attachments, blending them with what is there.
+    unsafe fn write_colors(&self, x: u32, y: u32, mask: u32, invocation: &Invocation) {
+        for (attachment, view) in self.colors.iter().enumerate() {
+            let view = match *view {
+                Some(view) => view,
+                None => continue,
+            };
+            let blend = &self.pipeline.color_blend.attachments[attachment];
+            let texel = (*view.image).texel(view.base_level, view.base_layer, x, y, 0);
+            if blend.colorWriteMask == 0 || texel.is_null() {
+                continue;
+            }
+            let format = view.format;
+            let size = format.size();
+            let output = invocation.outputs[attachment];
+            for sample in (0..self.samples as usize).filter(|&sample| mask & 1 << sample != 0) {
+                let bytes = slice::from_raw_parts_mut(texel.add(sample * size), size);
+                let value = if blend.blendEnable != vk::FALSE && format.blends() {
+                    let color = self.blend(blend, format, output.to_f32(), bytes);
+                    format.encode_color(Slot::from_f32(color).0)
+                } else {
+                    format.encode_color(output.0)
+                };
+                for component in (0..4).filter(|&component| {
+                    blend.colorWriteMask & 1 << component != 0
+                })
+                {
+                    if let Some(range) = format.component_bytes(component) {
+                        bytes[range.clone()].copy_from_slice(&value[range]);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The blend of the color `source` and the texel `destination` of `format`.
+    fn blend(
+        &self,
+        state: &vk::PipelineColorBlendAttachmentState,
+        format: Format,
+        source: [f32; 4],
+        destination: &[u8],
+    ) -> [f32; 4] {
+        // Fixed point formats clamp the values before blending
+        let range = match format {
+            Format::Color { numeric: Numeric::Unorm, .. } |
+            Format::Color { numeric: Numeric::Srgb, .. } => Some((0.0, 1.0)),
+            Format::Color { numeric: Numeric::Snorm, .. } => Some((-1.0, 1.0)),
+            _ => None,
+        };
+        let clamp = |mut color: [f32; 4]| {
+            if let Some((min, max)) = range {
+                for value in &mut color {
+                    *value = value.clamp(min, max);
+                }
+            }
+            color
+        };
+        let (source, constants) = (clamp(source), clamp(self.blend_constants));
+        let destination = format.decode_color(destination);
+
+        let factor = |factor: vk::BlendFactor, component: usize| match factor {
+            vk::BLEND_FACTOR_ONE => 1.0,
+            vk::BLEND_FACTOR_SRC_COLOR => source[component],
+            vk::BLEND_FACTOR_ONE_MINUS_SRC_COLOR => 1.0 - source[component],
+            vk::BLEND_FACTOR_DST_COLOR => destination[component],
+            vk::BLEND_FACTOR_ONE_MINUS_DST_COLOR => 1.0 - destination[component],
+            vk::BLEND_FACTOR_SRC_ALPHA => source[3],
+            vk::BLEND_FACTOR_ONE_MINUS_SRC_ALPHA => 1.0 - source[3],
+            vk::BLEND_FACTOR_DST_ALPHA => destination[3],
+            vk::BLEND_FACTOR_ONE_MINUS_DST_ALPHA => 1.0 - destination[3],
+            vk::BLEND_FACTOR_CONSTANT_COLOR => constants[component],
+            vk::BLEND_FACTOR_ONE_MINUS_CONSTANT_COLOR => 1.0 - constants[component],
+            vk::BLEND_FACTOR_CONSTANT_ALPHA => constants[3],
+            vk::BLEND_FACTOR_ONE_MINUS_CONSTANT_ALPHA => 1.0 - constants[3],
+            vk::BLEND_FACTOR_SRC_ALPHA_SATURATE if component < 3 => {
+                source[3].min(1.0 - destination[3])
+            }
+            vk::BLEND_FACTOR_SRC_ALPHA_SATURATE => 1.0,
+            // Pipelines have no factors of a second source
+            _ => 0.0,
+        };
+        let mut color = [0.0; 4];
+        for (component, value) in color.iter_mut().enumerate() {
+            let (source_factor, destination_factor, op) = if component < 3 {
+                (state.srcColorBlendFactor, state.dstColorBlendFactor, state.colorBlendOp)
+            } else {
+                (state.srcAlphaBlendFactor, state.dstAlphaBlendFactor, state.alphaBlendOp)
+            };
+            let (source, destination) = (source[component], destination[component]);
+            let weighted_source = source * factor(source_factor, component);
+            let weighted_destination = destination * factor(destination_factor, component);
+            *value = match op {
+                vk::BLEND_OP_SUBTRACT => weighted_source - weighted_destination,
+                vk::BLEND_OP_REVERSE_SUBTRACT => weighted_destination - weighted_source,
+                vk::BLEND_OP_MIN => source.min(destination),
+                vk::BLEND_OP_MAX => source.max(destination),
+                _ => weighted_source + weighted_destination,
+            };
+        }
+        color
+    }
+}
+
+/// Whether `reference` passes the comparison `op` with the `stored` value.
+fn compare<T: PartialOrd>(op: vk::CompareOp, reference: T, stored: T) -> bool {
+    match op {
+        vk::COMPARE_OP_NEVER => false,
+        vk::COMPARE_OP_LESS => reference < stored,
+        vk::COMPARE_OP_EQUAL => reference == stored,
+        vk::COMPARE_OP_LESS_OR_EQUAL => reference <= stored,
+        vk::COMPARE_OP_GREATER => reference > stored,
+        vk::COMPARE_OP_NOT_EQUAL => reference != stored,
+        vk::COMPARE_OP_GREATER_OR_EQUAL => reference >= stored,
+        _ => true,
+    }
+}
+
+/// The stencil value that `op` makes of the 8 bit `value`.
+fn stencil_op(op: vk::StencilOp, value: u32, reference: u32) -> u32 {
+    match op {
+        vk::STENCIL_OP_ZERO => 0,
+        vk::STENCIL_OP_REPLACE => reference,
+        vk::STENCIL_OP_INCREMENT_AND_CLAMP => (value + 1).min(0xff),
+        vk::STENCIL_OP_DECREMENT_AND_CLAMP => value.saturating_sub(1),
+        vk::STENCIL_OP_INVERT => !value,
+        vk::STENCIL_OP_INCREMENT_AND_WRAP => (value + 1) & 0xff,
+        vk::STENCIL_OP_DECREMENT_AND_WRAP => value.wrapping_sub(1) & 0xff,
+        _ => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assembles_triangles_with_the_provoking_vertex_first() {
+        let vertices: Vec<Option<usize>> = (0..5).map(Some).collect();
+        assert_eq!(
+            triangles(vk::PRIMITIVE_TOPOLOGY_TRIANGLE_LIST, &vertices),
+            [[0, 1, 2]]
+        );
+        assert_eq!(
+            triangles(vk::PRIMITIVE_TOPOLOGY_TRIANGLE_STRIP, &vertices),
+            [[0, 1, 2], [1, 3, 2], [2, 3, 4]]
+        );
+        assert_eq!(
+            triangles(vk::PRIMITIVE_TOPOLOGY_TRIANGLE_FAN, &vertices),
+            [[1, 2, 0], [2, 3, 0], [3, 4, 0]]
+        );
+    }
+
+    #[test]
+    fn restarts_strips() {
+        let vertices = [Some(0), Some(1), Some(2), None, Some(3), Some(4), None, Some(5)];
+        assert_eq!(
+            triangles(vk::PRIMITIVE_TOPOLOGY_TRIANGLE_STRIP, &vertices),
+            [[0, 1, 2]]
+        );
+        let vertices = [Some(0), Some(1), Some(2), Some(3), None, Some(4), Some(5), Some(6)];
+        assert_eq!(
+            triangles(vk::PRIMITIVE_TOPOLOGY_TRIANGLE_FAN, &vertices),
+            [[1, 2, 0], [2, 3, 0], [5, 6, 4]]
+        );
+    }
+
+    #[test]
+    fn runs_stencil_ops_on_bytes() {
+        assert_eq!(stencil_op(vk::STENCIL_OP_INCREMENT_AND_CLAMP, 0xff, 0), 0xff);
+        assert_eq!(stencil_op(vk::STENCIL_OP_INCREMENT_AND_WRAP, 0xff, 0), 0);
+        assert_eq!(stencil_op(vk::STENCIL_OP_DECREMENT_AND_CLAMP, 0, 0), 0);
+        assert_eq!(stencil_op(vk::STENCIL_OP_DECREMENT_AND_WRAP, 0, 0), 0xff);
+        assert_eq!(stencil_op(vk::STENCIL_OP_REPLACE, 3, 7), 7);
+        assert!(compare(vk::COMPARE_OP_LESS, 0.25, 0.5));
+        assert!(!compare(vk::COMPARE_OP_GREATER_OR_EQUAL, 1, 2));
+    }
+}
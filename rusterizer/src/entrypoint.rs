@@ -1,6 +1,5 @@
 use std::ptr;
 use std::ffi::CStr;
-use libc;
 
 use loader_interface as api;
 use ffi_types as vk;
@@ -68,6 +67,9 @@ pub fn lookup_entrypoint_instance(
             "vkGetPhysicalDeviceSurfacePresentModesKHR" => {
                 api::vkGetPhysicalDeviceSurfacePresentModesKHR as *const _
             }
+            "vkCreateDebugReportCallbackEXT" => api::vkCreateDebugReportCallbackEXT as *const _,
+            "vkDestroyDebugReportCallbackEXT" => api::vkDestroyDebugReportCallbackEXT as *const _,
+            "vkDebugReportMessageEXT" => api::vkDebugReportMessageEXT as *const _,
             function_name => {
                 warn!("Returning null pointer for function {}", function_name);
                 ptr::null()
@@ -96,8 +98,67 @@ pub fn lookup_entrypoint_device(device: &Device, name: &CStr) -> *const vk::PFN_
         "vkUnmapMemory" => api::vkUnmapMemory as *const _,
         "vkFlushMappedMemoryRanges" => api::vkFlushMappedMemoryRanges as *const _,
         "vkInvalidateMappedMemoryRanges" => api::vkInvalidateMappedMemoryRanges as *const _,
+        "vkCreateBuffer" => api::vkCreateBuffer as *const _,
+        "vkDestroyBuffer" => api::vkDestroyBuffer as *const _,
+        "vkGetBufferMemoryRequirements" => api::vkGetBufferMemoryRequirements as *const _,
+        "vkBindBufferMemory" => api::vkBindBufferMemory as *const _,
+        "vkCreateImage" => api::vkCreateImage as *const _,
+        "vkDestroyImage" => api::vkDestroyImage as *const _,
+        "vkGetImageMemoryRequirements" => api::vkGetImageMemoryRequirements as *const _,
+        "vkBindImageMemory" => api::vkBindImageMemory as *const _,
+        "vkGetImageSubresourceLayout" => api::vkGetImageSubresourceLayout as *const _,
+        "vkCreateImageView" => api::vkCreateImageView as *const _,
+        "vkDestroyImageView" => api::vkDestroyImageView as *const _,
+        "vkCreateRenderPass" => api::vkCreateRenderPass as *const _,
+        "vkDestroyRenderPass" => api::vkDestroyRenderPass as *const _,
+        "vkGetRenderAreaGranularity" => api::vkGetRenderAreaGranularity as *const _,
+        "vkCreateFramebuffer" => api::vkCreateFramebuffer as *const _,
+        "vkDestroyFramebuffer" => api::vkDestroyFramebuffer as *const _,
         "vkCreateCommandPool" => api::vkCreateCommandPool as *const _,
         "vkAllocateCommandBuffers" => api::vkAllocateCommandBuffers as *const _,
+        "vkBeginCommandBuffer" => api::vkBeginCommandBuffer as *const _,
+        "vkEndCommandBuffer" => api::vkEndCommandBuffer as *const _,
+        "vkCmdBindPipeline" => api::vkCmdBindPipeline as *const _,
+        "vkCmdBindDescriptorSets" => api::vkCmdBindDescriptorSets as *const _,
+        "vkCmdPushConstants" => api::vkCmdPushConstants as *const _,
+        "vkCmdBeginRenderPass" => api::vkCmdBeginRenderPass as *const _,
+        "vkCmdNextSubpass" => api::vkCmdNextSubpass as *const _,
+        "vkCmdEndRenderPass" => api::vkCmdEndRenderPass as *const _,
+        "vkCmdDispatch" => api::vkCmdDispatch as *const _,
+        "vkCmdDispatchBase" | "vkCmdDispatchBaseKHR" => api::vkCmdDispatchBase as *const _,
+        "vkCmdDispatchIndirect" => api::vkCmdDispatchIndirect as *const _,
+        "vkCmdBindVertexBuffers" => api::vkCmdBindVertexBuffers as *const _,
+        "vkCmdBindIndexBuffer" => api::vkCmdBindIndexBuffer as *const _,
+        "vkCmdSetViewport" => api::vkCmdSetViewport as *const _,
+        "vkCmdSetScissor" => api::vkCmdSetScissor as *const _,
+        "vkCmdSetLineWidth" => api::vkCmdSetLineWidth as *const _,
+        "vkCmdSetDepthBias" => api::vkCmdSetDepthBias as *const _,
+        "vkCmdSetBlendConstants" => api::vkCmdSetBlendConstants as *const _,
+        "vkCmdSetDepthBounds" => api::vkCmdSetDepthBounds as *const _,
+        "vkCmdSetStencilCompareMask" => api::vkCmdSetStencilCompareMask as *const _,
+        "vkCmdSetStencilWriteMask" => api::vkCmdSetStencilWriteMask as *const _,
+        "vkCmdSetStencilReference" => api::vkCmdSetStencilReference as *const _,
+        "vkCmdDraw" => api::vkCmdDraw as *const _,
+        "vkCmdDrawIndexed" => api::vkCmdDrawIndexed as *const _,
+        "vkCreateShaderModule" => api::vkCreateShaderModule as *const _,
+        "vkDestroyShaderModule" => api::vkDestroyShaderModule as *const _,
+        "vkCreatePipelineCache" => api::vkCreatePipelineCache as *const _,
+        "vkDestroyPipelineCache" => api::vkDestroyPipelineCache as *const _,
+        "vkGetPipelineCacheData" => api::vkGetPipelineCacheData as *const _,
+        "vkMergePipelineCaches" => api::vkMergePipelineCaches as *const _,
+        "vkCreatePipelineLayout" => api::vkCreatePipelineLayout as *const _,
+        "vkDestroyPipelineLayout" => api::vkDestroyPipelineLayout as *const _,
+        "vkCreateDescriptorSetLayout" => api::vkCreateDescriptorSetLayout as *const _,
+        "vkDestroyDescriptorSetLayout" => api::vkDestroyDescriptorSetLayout as *const _,
+        "vkCreateDescriptorPool" => api::vkCreateDescriptorPool as *const _,
+        "vkDestroyDescriptorPool" => api::vkDestroyDescriptorPool as *const _,
+        "vkResetDescriptorPool" => api::vkResetDescriptorPool as *const _,
+        "vkAllocateDescriptorSets" => api::vkAllocateDescriptorSets as *const _,
+        "vkFreeDescriptorSets" => api::vkFreeDescriptorSets as *const _,
+        "vkUpdateDescriptorSets" => api::vkUpdateDescriptorSets as *const _,
+        "vkCreateGraphicsPipelines" => api::vkCreateGraphicsPipelines as *const _,
+        "vkCreateComputePipelines" => api::vkCreateComputePipelines as *const _,
+        "vkDestroyPipeline" => api::vkDestroyPipeline as *const _,
         "vkCreateSwapchainKHR" => api::vkCreateSwapchainKHR as *const _,
         //"vkGetSwapchainImagesKHR" => api::vkGetSwapchainImagesKHR as *const _,
         function_name => {
@@ -1,7 +1,5 @@
 use std::ffi::{CStr, CString};
 use std::cmp;
-use std::ptr;
-use libc;
 use ffi_types as vk;
 
 
@@ -20,6 +18,10 @@ pub const AVAILABLE_EXTENSIONS: &'static [ExtensionProperties] = &[
         extension_name: "VK_KHR_win32_surface",
         spec_version: 5,
     },
+    ExtensionProperties {
+        extension_name: "VK_EXT_debug_report",
+        spec_version: 6,
+    },
 ];
 
 pub const AVAILABLE_DEVICE_EXTENSIONS: &'static [ExtensionProperties] = &[
@@ -74,12 +76,7 @@ pub fn enumerate_extension_properties(
         AVAILABLE_EXTENSIONS
     };
 
-    if properties.is_none() {
-        // TODO deal with layer_name
-        *property_count = available_extensions.len() as u32;
-        vk::SUCCESS
-    } else {
-        let properties = properties.unwrap();
+    if let Some(properties) = properties {
         if layer_name.is_none() {
             for i in 0..cmp::min(*property_count as usize, available_extensions.len()) {
                 // We don't have clone, so we do the dirty memcpy hack.
@@ -101,5 +98,9 @@ pub fn enumerate_extension_properties(
             );
             unimplemented!()
         }
+    } else {
+        // TODO deal with layer_name
+        *property_count = available_extensions.len() as u32;
+        vk::SUCCESS
     }
 }
@@ -10,11 +10,9 @@
 #![allow(non_upper_case_globals)]
 #![allow(non_snake_case)]
 #![allow(non_camel_case_types)]
+#![allow(clippy::missing_transmute_annotations)]
 
 use std::mem;
-use std::ffi::CStr;
-use std::ffi::CString;
-use std::fmt;
 use std::os::raw::c_char;
 use std::os::raw::c_void;
 use dispatch::CommandPool;
@@ -93,12 +91,15 @@ pub const ERROR_FEATURE_NOT_PRESENT: u32 = -8i32 as u32;
 pub const ERROR_INCOMPATIBLE_DRIVER: u32 = -9i32 as u32;
 pub const ERROR_TOO_MANY_OBJECTS: u32 = -10i32 as u32;
 pub const ERROR_FORMAT_NOT_SUPPORTED: u32 = -11i32 as u32;
+pub const ERROR_FRAGMENTED_POOL: u32 = -12i32 as u32;
 pub const ERROR_SURFACE_LOST_KHR: u32 = -1000000000i32 as u32;
 pub const ERROR_NATIVE_WINDOW_IN_USE_KHR: u32 = -1000000001i32 as u32;
 pub const SUBOPTIMAL_KHR: u32 = 1000001003;
 pub const ERROR_OUT_OF_DATE_KHR: u32 = -1000001004i32 as u32;
 pub const ERROR_INCOMPATIBLE_DISPLAY_KHR: u32 = -1000003001i32 as u32;
 pub const ERROR_VALIDATION_FAILED_EXT: u32 = -1000011001i32 as u32;
+pub const ERROR_INVALID_SHADER_NV: u32 = -1000012000i32 as u32;
+pub const ERROR_OUT_OF_POOL_MEMORY_KHR: u32 = -1000069000i32 as u32;
 
 pub type StructureType = u32;
 pub const STRUCTURE_TYPE_APPLICATION_INFO: u32 = 0;
@@ -1626,6 +1627,7 @@ pub struct PipelineTessellationStateCreateInfo {
     pub patchControlPoints: u32,
 }
 
+#[derive(Clone, Copy, Debug)]
 #[repr(C)]
 pub struct Viewport {
     pub x: f32,
@@ -1636,18 +1638,21 @@ pub struct Viewport {
     pub maxDepth: f32,
 }
 
+#[derive(Clone, Copy, Debug)]
 #[repr(C)]
 pub struct Offset2D {
     pub x: i32,
     pub y: i32,
 }
 
+#[derive(Clone, Copy, Debug)]
 #[repr(C)]
 pub struct Extent2D {
     pub width: u32,
     pub height: u32,
 }
 
+#[derive(Clone, Copy, Debug)]
 #[repr(C)]
 pub struct Rect2D {
     pub offset: Offset2D,
@@ -1695,6 +1700,7 @@ pub struct PipelineMultisampleStateCreateInfo {
     pub alphaToOneEnable: Bool32,
 }
 
+#[derive(Clone, Copy, Debug)]
 #[repr(C)]
 pub struct StencilOpState {
     pub failOp: StencilOp,
@@ -1722,6 +1728,7 @@ pub struct PipelineDepthStencilStateCreateInfo {
     pub maxDepthBounds: f32,
 }
 
+#[derive(Clone, Copy, Debug)]
 #[repr(C)]
 pub struct PipelineColorBlendAttachmentState {
     pub blendEnable: Bool32,
@@ -0,0 +1,485 @@
+//! The formats of images, and how their texels are stored.
+//!
+//! Color formats store their components one after the other, each in the same number of bits,
+//! least significant byte first. Depth/stencil formats store depth first and the stencil value
+//! in the byte after it, e.g. `D24_UNORM_S8_UINT` in 24 bits of depth and a byte of stencil.
+use std::ops::Range;
+
+use ffi_types as vk;
+
+/// How the components of a color format represent their values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Numeric {
+    Unorm,
+    Snorm,
+    Uint,
+    Sint,
+    /// Unorm in sRGB encoding, except for alpha, which is linear.
+    Srgb,
+    Sfloat,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Depth {
+    Unorm16,
+    /// 24 bits in the first three bytes of the texel.
+    Unorm24,
+    Sfloat32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Color {
+        numeric: Numeric,
+        /// Bits of every component, 8, 16 or 32.
+        bits: u32,
+        components: usize,
+        /// Whether red and blue are swapped, as in `B8G8R8A8_UNORM`.
+        bgra: bool,
+    },
+    DepthStencil { depth: Option<Depth>, stencil: bool },
+}
+
+impl Format {
+    /// The format `format` is stored as, `None` if it is not supported.
+    pub fn from_vk(format: vk::Format) -> Option<Format> {
+        use self::Numeric::*;
+        let color = |numeric, bits, components, bgra| {
+            Some(Format::Color {
+                numeric: numeric,
+                bits: bits,
+                components: components,
+                bgra: bgra,
+            })
+        };
+        let depth_stencil = |depth, stencil| {
+            Some(Format::DepthStencil {
+                depth: depth,
+                stencil: stencil,
+            })
+        };
+        match format {
+            vk::FORMAT_R8_UNORM => color(Unorm, 8, 1, false),
+            vk::FORMAT_R8_SNORM => color(Snorm, 8, 1, false),
+            vk::FORMAT_R8_UINT => color(Uint, 8, 1, false),
+            vk::FORMAT_R8_SINT => color(Sint, 8, 1, false),
+            vk::FORMAT_R8_SRGB => color(Srgb, 8, 1, false),
+            vk::FORMAT_R8G8_UNORM => color(Unorm, 8, 2, false),
+            vk::FORMAT_R8G8_SNORM => color(Snorm, 8, 2, false),
+            vk::FORMAT_R8G8_UINT => color(Uint, 8, 2, false),
+            vk::FORMAT_R8G8_SINT => color(Sint, 8, 2, false),
+            vk::FORMAT_R8G8_SRGB => color(Srgb, 8, 2, false),
+            // The packed formats of 8 bit components have the same bytes as the others
+            vk::FORMAT_R8G8B8A8_UNORM |
+            vk::FORMAT_A8B8G8R8_UNORM_PACK32 => color(Unorm, 8, 4, false),
+            vk::FORMAT_R8G8B8A8_SNORM |
+            vk::FORMAT_A8B8G8R8_SNORM_PACK32 => color(Snorm, 8, 4, false),
+            vk::FORMAT_R8G8B8A8_UINT |
+            vk::FORMAT_A8B8G8R8_UINT_PACK32 => color(Uint, 8, 4, false),
+            vk::FORMAT_R8G8B8A8_SINT |
+            vk::FORMAT_A8B8G8R8_SINT_PACK32 => color(Sint, 8, 4, false),
+            vk::FORMAT_R8G8B8A8_SRGB |
+            vk::FORMAT_A8B8G8R8_SRGB_PACK32 => color(Srgb, 8, 4, false),
+            vk::FORMAT_B8G8R8A8_UNORM => color(Unorm, 8, 4, true),
+            vk::FORMAT_B8G8R8A8_SNORM => color(Snorm, 8, 4, true),
+            vk::FORMAT_B8G8R8A8_UINT => color(Uint, 8, 4, true),
+            vk::FORMAT_B8G8R8A8_SINT => color(Sint, 8, 4, true),
+            vk::FORMAT_B8G8R8A8_SRGB => color(Srgb, 8, 4, true),
+            vk::FORMAT_R16_UNORM => color(Unorm, 16, 1, false),
+            vk::FORMAT_R16_SNORM => color(Snorm, 16, 1, false),
+            vk::FORMAT_R16_UINT => color(Uint, 16, 1, false),
+            vk::FORMAT_R16_SINT => color(Sint, 16, 1, false),
+            vk::FORMAT_R16_SFLOAT => color(Sfloat, 16, 1, false),
+            vk::FORMAT_R16G16_UNORM => color(Unorm, 16, 2, false),
+            vk::FORMAT_R16G16_SNORM => color(Snorm, 16, 2, false),
+            vk::FORMAT_R16G16_UINT => color(Uint, 16, 2, false),
+            vk::FORMAT_R16G16_SINT => color(Sint, 16, 2, false),
+            vk::FORMAT_R16G16_SFLOAT => color(Sfloat, 16, 2, false),
+            vk::FORMAT_R16G16B16A16_UNORM => color(Unorm, 16, 4, false),
+            vk::FORMAT_R16G16B16A16_SNORM => color(Snorm, 16, 4, false),
+            vk::FORMAT_R16G16B16A16_UINT => color(Uint, 16, 4, false),
+            vk::FORMAT_R16G16B16A16_SINT => color(Sint, 16, 4, false),
+            vk::FORMAT_R16G16B16A16_SFLOAT => color(Sfloat, 16, 4, false),
+            vk::FORMAT_R32_UINT => color(Uint, 32, 1, false),
+            vk::FORMAT_R32_SINT => color(Sint, 32, 1, false),
+            vk::FORMAT_R32_SFLOAT => color(Sfloat, 32, 1, false),
+            vk::FORMAT_R32G32_UINT => color(Uint, 32, 2, false),
+            vk::FORMAT_R32G32_SINT => color(Sint, 32, 2, false),
+            vk::FORMAT_R32G32_SFLOAT => color(Sfloat, 32, 2, false),
+            vk::FORMAT_R32G32B32A32_UINT => color(Uint, 32, 4, false),
+            vk::FORMAT_R32G32B32A32_SINT => color(Sint, 32, 4, false),
+            vk::FORMAT_R32G32B32A32_SFLOAT => color(Sfloat, 32, 4, false),
+            vk::FORMAT_D16_UNORM => depth_stencil(Some(Depth::Unorm16), false),
+            vk::FORMAT_X8_D24_UNORM_PACK32 => depth_stencil(Some(Depth::Unorm24), false),
+            vk::FORMAT_D32_SFLOAT => depth_stencil(Some(Depth::Sfloat32), false),
+            vk::FORMAT_S8_UINT => depth_stencil(None, true),
+            vk::FORMAT_D16_UNORM_S8_UINT => depth_stencil(Some(Depth::Unorm16), true),
+            vk::FORMAT_D24_UNORM_S8_UINT => depth_stencil(Some(Depth::Unorm24), true),
+            vk::FORMAT_D32_SFLOAT_S8_UINT => depth_stencil(Some(Depth::Sfloat32), true),
+            _ => None,
+        }
+    }
+
+    /// Bytes of a texel.
+    pub fn size(&self) -> usize {
+        match *self {
+            Format::Color {
+                bits, components, ..
+            } => bits as usize / 8 * components,
+            Format::DepthStencil { depth: None, .. } => 1,
+            Format::DepthStencil {
+                depth: Some(Depth::Unorm16),
+                stencil: false,
+            } => 2,
+            Format::DepthStencil { depth: Some(Depth::Sfloat32), stencil: true } => 8,
+            // The stencil byte of D16_UNORM_S8_UINT is followed by one of padding
+            Format::DepthStencil { .. } => 4,
+        }
+    }
+
+    pub fn aspects(&self) -> vk::ImageAspectFlags {
+        match *self {
+            Format::Color { .. } => vk::IMAGE_ASPECT_COLOR_BIT,
+            Format::DepthStencil { depth, stencil } => {
+                let depth = if depth.is_some() { vk::IMAGE_ASPECT_DEPTH_BIT } else { 0 };
+                let stencil = if stencil { vk::IMAGE_ASPECT_STENCIL_BIT } else { 0 };
+                depth | stencil
+            }
+        }
+    }
+
+    /// The bytes of a texel that hold `aspect`, which is one of the aspects of the format.
+    pub fn aspect_bytes(&self, aspect: vk::ImageAspectFlags) -> Range<usize> {
+        let depth = match *self {
+            Format::DepthStencil { depth: Some(depth), .. } => {
+                match depth {
+                    Depth::Unorm16 => 2,
+                    Depth::Unorm24 => 3,
+                    Depth::Sfloat32 => 4,
+                }
+            }
+            _ => 0,
+        };
+        match aspect {
+            vk::IMAGE_ASPECT_DEPTH_BIT => 0..depth,
+            vk::IMAGE_ASPECT_STENCIL_BIT => depth..depth + 1,
+            _ => 0..self.size(),
+        }
+    }
+
+    /// Whether a resolve averages the samples of a texel, rather than picking one of them.
+    fn averages(&self) -> bool {
+        match *self {
+            Format::Color { numeric, .. } => numeric != Numeric::Uint && numeric != Numeric::Sint,
+            Format::DepthStencil { .. } => false,
+        }
+    }
+
+    /// A texel of the color in the words of a `VkClearColorValue`, which are floats, signed or
+    /// unsigned integers depending on the format.
+    pub fn encode_color(&self, value: [u32; 4]) -> Vec<u8> {
+        match *self {
+            Format::Color { bgra: true, .. } => {
+                self.encode_components([value[2], value[1], value[0], value[3]])
+            }
+            Format::Color { .. } => self.encode_components(value),
+            Format::DepthStencil { .. } => vec![0; self.size()],
+        }
+    }
+
+    /// A texel of the depth and stencil values. Aspects the format does not have are ignored.
+    pub fn encode_depth_stencil(&self, depth_value: f32, stencil_value: u32) -> Vec<u8> {
+        let mut texel = vec![0; self.size()];
+        if let Format::DepthStencil { depth, stencil } = *self {
+            let bytes = match depth {
+                Some(Depth::Unorm16) => unorm(depth_value, 16).to_le_bytes(),
+                Some(Depth::Unorm24) => unorm(depth_value, 24).to_le_bytes(),
+                Some(Depth::Sfloat32) => u64::from(depth_value.to_bits()).to_le_bytes(),
+                None => [0; 8],
+            };
+            let depth_bytes = self.aspect_bytes(vk::IMAGE_ASPECT_DEPTH_BIT);
+            texel[depth_bytes.clone()].copy_from_slice(&bytes[..depth_bytes.len()]);
+            if stencil {
+                texel[depth_bytes.end] = stencil_value as u8;
+            }
+        }
+        texel
+    }
+
+    /// Whether blending applies to the format, which it does to all but integer formats.
+    pub fn blends(&self) -> bool {
+        self.averages()
+    }
+
+    /// The color of a texel of a format that `blends`, in RGBA order. Components the format
+    /// does not have are 0, alpha is 1.
+    pub fn decode_color(&self, texel: &[u8]) -> [f32; 4] {
+        let mut color = [0.0, 0.0, 0.0, 1.0];
+        if let Format::Color {
+            components, bgra, ..
+        } = *self
+        {
+            color[..components].copy_from_slice(&self.decode_components(texel)[..components]);
+            if bgra {
+                color.swap(0, 2);
+            }
+        }
+        color
+    }
+
+    /// The bytes of a texel that hold component `component` of RGBA, `None` if the format does
+    /// not have it.
+    pub fn component_bytes(&self, component: usize) -> Option<Range<usize>> {
+        match *self {
+            Format::Color {
+                bits,
+                components,
+                bgra,
+                ..
+            } if component < components => {
+                let index = if bgra && component < 3 { 2 - component } else { component };
+                let size = bits as usize / 8;
+                Some(index * size..(index + 1) * size)
+            }
+            _ => None,
+        }
+    }
+
+    /// The depth value of a texel of a depth/stencil format, 0 if it has no depth.
+    pub fn decode_depth(&self, texel: &[u8]) -> f32 {
+        let bytes = &texel[self.aspect_bytes(vk::IMAGE_ASPECT_DEPTH_BIT)];
+        let mut word = [0; 4];
+        word[..bytes.len()].copy_from_slice(bytes);
+        let word = u32::from_le_bytes(word);
+        match *self {
+            Format::DepthStencil { depth: Some(Depth::Unorm16), .. } => {
+                word as f32 / f32::from(u16::MAX)
+            }
+            Format::DepthStencil { depth: Some(Depth::Unorm24), .. } => {
+                word as f32 / ((1 << 24) - 1) as f32
+            }
+            Format::DepthStencil { depth: Some(Depth::Sfloat32), .. } => f32::from_bits(word),
+            _ => 0.0,
+        }
+    }
+
+    /// Writes the texel that `samples`, the texels of all samples of a pixel, resolve to.
+    pub fn resolve(&self, samples: &[u8], texel: &mut [u8]) {
+        let size = self.size();
+        if !self.averages() {
+            texel.copy_from_slice(&samples[..size]);
+            return;
+        }
+        let count = samples.len() / size;
+        let mut sum = [0.0; 4];
+        for sample in samples.chunks(size) {
+            for (sum, value) in sum.iter_mut().zip(&self.decode_components(sample)) {
+                *sum += value;
+            }
+        }
+        let words = [
+            (sum[0] / count as f32).to_bits(),
+            (sum[1] / count as f32).to_bits(),
+            (sum[2] / count as f32).to_bits(),
+            (sum[3] / count as f32).to_bits(),
+        ];
+        texel.copy_from_slice(&self.encode_components(words));
+    }
+
+    /// Encodes the components in the order they are stored in.
+    fn encode_components(&self, words: [u32; 4]) -> Vec<u8> {
+        let (numeric, bits, components) = match *self {
+            Format::Color {
+                numeric,
+                bits,
+                components,
+                ..
+            } => (numeric, bits, components),
+            Format::DepthStencil { .. } => return vec![0; self.size()],
+        };
+        let mut texel = Vec::with_capacity(self.size());
+        for (index, &word) in words[..components].iter().enumerate() {
+            let float = f32::from_bits(word);
+            let encoded = match numeric {
+                Numeric::Unorm => unorm(float, bits),
+                Numeric::Srgb if index == 3 => unorm(float, bits),
+                Numeric::Srgb => unorm(linear_to_srgb(float), bits),
+                Numeric::Snorm => {
+                    let max = ((1u64 << (bits - 1)) - 1) as f32;
+                    (float.clamp(-1.0, 1.0) * max).round() as i64 as u64
+                }
+                Numeric::Sfloat if bits == 16 => u64::from(f32_to_f16(float)),
+                Numeric::Sfloat => u64::from(word),
+                // Integers are truncated to the bits of the component
+                Numeric::Uint => u64::from(word),
+                Numeric::Sint => word as i32 as i64 as u64,
+            };
+            texel.extend_from_slice(&encoded.to_le_bytes()[..bits as usize / 8]);
+        }
+        texel
+    }
+
+    /// Decodes the components of a texel of a format that `averages`, in the order they are
+    /// stored in.
+    fn decode_components(&self, texel: &[u8]) -> [f32; 4] {
+        let (numeric, bits) = match *self {
+            Format::Color { numeric, bits, .. } => (numeric, bits),
+            Format::DepthStencil { .. } => return [0.0; 4],
+        };
+        let mut values = [0.0; 4];
+        for (index, (value, bytes)) in values
+            .iter_mut()
+            .zip(texel.chunks(bits as usize / 8))
+            .enumerate()
+        {
+            let mut word = [0; 8];
+            word[..bytes.len()].copy_from_slice(bytes);
+            let word = u64::from_le_bytes(word);
+            let unorm = word as f32 / ((1u64 << bits) - 1) as f32;
+            *value = match numeric {
+                Numeric::Srgb if index != 3 => srgb_to_linear(unorm),
+                Numeric::Snorm => {
+                    let signed = (word << (64 - bits)) as i64 >> (64 - bits);
+                    (signed as f32 / ((1u64 << (bits - 1)) - 1) as f32).max(-1.0)
+                }
+                Numeric::Sfloat if bits == 16 => f16_to_f32(word as u16),
+                Numeric::Sfloat => f32::from_bits(word as u32),
+                _ => unorm,
+            };
+        }
+        values
+    }
+}
+
+/// Converts `value` to an unsigned normalized integer of `bits`.
+fn unorm(value: f32, bits: u32) -> u64 {
+    let max = ((1u64 << bits) - 1) as f64;
+    (f64::from(value.clamp(0.0, 1.0)) * max).round() as u64
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Rounds `value` to the nearest half float, ties to even.
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent == 0xff {
+        // Infinities stay infinite, NaNs stay NaNs
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    // Denormal halfs keep the bits of the mantissa with its implicit one
+    let (mantissa, shift) = if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+        (mantissa | 0x80_0000, (14 - exponent) as u32)
+    } else {
+        (mantissa, 13)
+    };
+    let rest = mantissa & ((1 << shift) - 1);
+    let half = 1 << (shift - 1);
+    let mut rounded = mantissa >> shift;
+    if rest > half || (rest == half && rounded & 1 != 0) {
+        rounded += 1;
+    }
+    // Rounding up may carry into the exponent, which is exactly right
+    let exponent = if exponent <= 0 { 0 } else { exponent as u32 };
+    sign | ((exponent << 10) + rounded) as u16
+}
+
+fn f16_to_f32(value: u16) -> f32 {
+    let sign = if value & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = i32::from((value >> 10) & 0x1f);
+    let mantissa = f32::from(value & 0x3ff);
+    match exponent {
+        0 => sign * mantissa * (2.0f32).powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * (2.0f32).powi(exponent - 15),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_clear_values() {
+        let rgba = Format::from_vk(vk::FORMAT_R8G8B8A8_UNORM).unwrap();
+        let color = [1.0f32.to_bits(), 0.5f32.to_bits(), (-1.0f32).to_bits(), 0.2f32.to_bits()];
+        assert_eq!(rgba.encode_color(color), [255, 128, 0, 51]);
+        let bgra = Format::from_vk(vk::FORMAT_B8G8R8A8_UNORM).unwrap();
+        assert_eq!(bgra.encode_color(color), [0, 128, 255, 51]);
+        let srgb = Format::from_vk(vk::FORMAT_R8G8B8A8_SRGB).unwrap();
+        assert_eq!(srgb.encode_color(color), [255, 188, 0, 51]);
+        let snorm = Format::from_vk(vk::FORMAT_R16_SNORM).unwrap();
+        assert_eq!(snorm.encode_color(color), (0x7fffu16).to_le_bytes());
+        let half = Format::from_vk(vk::FORMAT_R16G16_SFLOAT).unwrap();
+        assert_eq!(half.encode_color(color), [0x00, 0x3c, 0x00, 0x38]);
+        let int = Format::from_vk(vk::FORMAT_R8G8_SINT).unwrap();
+        assert_eq!(int.encode_color([-2i32 as u32, 300, 0, 0]), [0xfe, 44]);
+
+        let d24s8 = Format::from_vk(vk::FORMAT_D24_UNORM_S8_UINT).unwrap();
+        assert_eq!(d24s8.size(), 4);
+        assert_eq!(d24s8.encode_depth_stencil(1.0, 7), [0xff, 0xff, 0xff, 7]);
+        assert_eq!(d24s8.aspect_bytes(vk::IMAGE_ASPECT_STENCIL_BIT), 3..4);
+        let d32s8 = Format::from_vk(vk::FORMAT_D32_SFLOAT_S8_UINT).unwrap();
+        assert_eq!(
+            d32s8.encode_depth_stencil(0.5, 1),
+            [0, 0, 0, 0x3f, 1, 0, 0, 0]
+        );
+        assert_eq!(Format::from_vk(vk::FORMAT_B10G11R11_UFLOAT_PACK32), None);
+    }
+
+    #[test]
+    fn converts_half_floats() {
+        let smallest = 2.0f32.powi(-24);
+        for &(float, half) in &[
+            (0.0, 0x0000),
+            (-2.0, 0xc000),
+            (65504.0, 0x7bff),
+            (1e6, 0x7c00),
+            (smallest, 0x0001),
+            (1.0 + 1.0 / 2048.0, 0x3c00),
+            (1.0 + 3.0 / 2048.0, 0x3c02),
+        ] {
+            assert_eq!(f32_to_f16(float), half, "{}", float);
+        }
+        assert_eq!(f16_to_f32(0x3555), 0.33325195);
+        assert_eq!(f16_to_f32(0x0001), smallest);
+        assert!(f16_to_f32(f32_to_f16(f32::NAN)).is_nan());
+    }
+
+    #[test]
+    fn resolves_samples() {
+        let rgba = Format::from_vk(vk::FORMAT_R8G8B8A8_UNORM).unwrap();
+        let mut texel = [0; 4];
+        rgba.resolve(&[255, 0, 10, 0, 0, 0, 20, 255], &mut texel);
+        assert_eq!(texel, [128, 0, 15, 128]);
+        // Integers take the first sample
+        let uint = Format::from_vk(vk::FORMAT_R8G8B8A8_UINT).unwrap();
+        uint.resolve(&[255, 0, 10, 0, 0, 0, 20, 255], &mut texel);
+        assert_eq!(texel, [255, 0, 10, 0]);
+    }
+}
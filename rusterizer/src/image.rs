@@ -0,0 +1,346 @@
+//! Images, the device memory bound to them and the views that render passes and shaders use.
+//!
+//! Every image is linear, whatever tiling it is created with: the array layers follow each
+//! other, each with its mip levels from the largest on, and the texels of a level are stored row
+//! by row, slice by slice. The samples of a texel are next to each other, so that a resolve reads
+//! a pixel in one go.
+use std::ops::Range;
+use std::ptr;
+use std::slice;
+
+use ffi_types as vk;
+use format::Format;
+use memory::{DeviceMemory, MEMORY_TYPE_BITS};
+
+/// Alignment that images have to be bound with.
+pub const IMAGE_ALIGNMENT: u64 = 16;
+
+/// The sample counts of images, and of the framebuffers that render to them.
+pub const SAMPLE_COUNTS: vk::SampleCountFlags = vk::SAMPLE_COUNT_1_BIT | vk::SAMPLE_COUNT_4_BIT;
+
+#[derive(Debug)]
+pub struct Image {
+    pub format: Format,
+    pub vk_format: vk::Format,
+    /// Width, height and depth of the largest mip level.
+    pub extent: [u32; 3],
+    pub mip_levels: u32,
+    pub array_layers: u32,
+    pub samples: u32,
+    pub usage: vk::ImageUsageFlags,
+    /// Bytes of the image in memory.
+    size: vk::DeviceSize,
+    /// The first byte of the image, null until memory is bound.
+    data: *mut u8,
+}
+
+impl Image {
+    pub fn from_create_info(
+        create_info: &vk::ImageCreateInfo,
+        alloc: *const vk::AllocationCallbacks,
+    ) -> Result<Self, vk::Result> {
+        debug_assert_eq!(create_info.sType, vk::STRUCTURE_TYPE_IMAGE_CREATE_INFO);
+        if !alloc.is_null() {
+            warn!("Custom allocators not supported by driver");
+            return Err(vk::ERROR_INITIALIZATION_FAILED);
+        }
+        let sparse = vk::IMAGE_CREATE_SPARSE_BINDING_BIT | vk::IMAGE_CREATE_SPARSE_RESIDENCY_BIT |
+            vk::IMAGE_CREATE_SPARSE_ALIASED_BIT;
+        if create_info.flags & sparse != 0 {
+            warn!("Sparse images not supported by driver");
+            return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+        }
+        let format = match Format::from_vk(create_info.format) {
+            Some(format) => format,
+            None => {
+                warn!("Format {} not supported by driver", create_info.format);
+                return Err(vk::ERROR_FORMAT_NOT_SUPPORTED);
+            }
+        };
+        let extent = [
+            create_info.extent.width,
+            create_info.extent.height,
+            create_info.extent.depth,
+        ];
+        let largest = *extent.iter().max().unwrap();
+        let max_levels = 32 - largest.leading_zeros();
+        let samples = create_info.samples;
+        if extent.contains(&0) || create_info.mipLevels == 0 ||
+            create_info.mipLevels > max_levels || create_info.arrayLayers == 0
+        {
+            warn!(
+                "Image of {:?} texels, {} mip levels and {} layers is invalid",
+                extent,
+                create_info.mipLevels,
+                create_info.arrayLayers
+            );
+            return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+        }
+        if samples & SAMPLE_COUNTS == 0 || samples & (samples - 1) != 0 ||
+            (samples != vk::SAMPLE_COUNT_1_BIT &&
+                 (create_info.imageType != vk::IMAGE_TYPE_2D || create_info.mipLevels != 1))
+        {
+            warn!("Images with {} samples not supported by driver", samples);
+            return Err(vk::ERROR_FORMAT_NOT_SUPPORTED);
+        }
+        let mut image = Image {
+            format: format,
+            vk_format: create_info.format,
+            extent: extent,
+            mip_levels: create_info.mipLevels,
+            array_layers: create_info.arrayLayers,
+            samples: samples,
+            usage: create_info.usage,
+            size: 0,
+            data: ptr::null_mut(),
+        };
+        image.size = image.layer_size().checked_mul(u64::from(image.array_layers)).ok_or(
+            vk::ERROR_OUT_OF_DEVICE_MEMORY,
+        )?;
+        Ok(image)
+    }
+
+    /// Width, height and depth of mip level `level`.
+    pub fn level_extent(&self, level: u32) -> [u32; 3] {
+        [
+            (self.extent[0] >> level).max(1),
+            (self.extent[1] >> level).max(1),
+            (self.extent[2] >> level).max(1),
+        ]
+    }
+
+    /// Bytes of a texel with all of its samples.
+    fn texel_size(&self) -> u64 {
+        self.format.size() as u64 * u64::from(self.samples)
+    }
+
+    fn level_size(&self, level: u32) -> u64 {
+        let [width, height, depth] = self.level_extent(level);
+        u64::from(width) * u64::from(height) * u64::from(depth) * self.texel_size()
+    }
+
+    fn layer_size(&self) -> u64 {
+        (0..self.mip_levels).map(|level| self.level_size(level)).sum()
+    }
+
+    /// Offset of mip level `level` of array layer `layer` in the image.
+    fn level_offset(&self, level: u32, layer: u32) -> u64 {
+        let levels: u64 = (0..level).map(|level| self.level_size(level)).sum();
+        u64::from(layer) * self.layer_size() + levels
+    }
+
+    pub fn memory_requirements(&self) -> vk::MemoryRequirements {
+        vk::MemoryRequirements {
+            size: (self.size + IMAGE_ALIGNMENT - 1) / IMAGE_ALIGNMENT * IMAGE_ALIGNMENT,
+            alignment: IMAGE_ALIGNMENT,
+            memoryTypeBits: MEMORY_TYPE_BITS,
+        }
+    }
+
+    /// Binds the image to `memory` at `offset`, which has to leave room for the whole image.
+    pub fn bind(
+        &mut self,
+        memory: &DeviceMemory,
+        offset: vk::DeviceSize,
+    ) -> Result<(), vk::Result> {
+        if !self.data.is_null() {
+            warn!("Image is already bound to memory");
+            return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+        }
+        if offset & (IMAGE_ALIGNMENT - 1) != 0 || offset + self.size > memory.size() as u64 {
+            warn!(
+                "Image of {} bytes does not fit at offset {} of {} bytes of memory",
+                self.size,
+                offset,
+                memory.size()
+            );
+            return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+        }
+        self.data = memory.at(offset);
+        Ok(())
+    }
+
+    /// Where mip level `level` of array layer `layer` is, as `vkGetImageSubresourceLayout`
+    /// returns it.
+    pub fn subresource_layout(&self, level: u32, layer: u32) -> vk::SubresourceLayout {
+        let [width, height, _] = self.level_extent(level);
+        let row_pitch = u64::from(width) * self.texel_size();
+        vk::SubresourceLayout {
+            offset: self.level_offset(level, layer),
+            size: self.level_size(level),
+            rowPitch: row_pitch,
+            arrayPitch: self.layer_size(),
+            depthPitch: row_pitch * u64::from(height),
+        }
+    }
+
+    /// Returns the address of the first sample of texel `(x, y, z)` of mip level `level` of
+    /// array layer `layer`, null if no memory is bound.
+    pub fn texel(&self, level: u32, layer: u32, x: u32, y: u32, z: u32) -> *mut u8 {
+        if self.data.is_null() {
+            return ptr::null_mut();
+        }
+        let [width, height, _] = self.level_extent(level);
+        let index = (u64::from(z) * u64::from(height) + u64::from(y)) * u64::from(width) +
+            u64::from(x);
+        let offset = self.level_offset(level, layer) + index * self.texel_size();
+        debug_assert!(offset < self.size);
+        unsafe { self.data.offset(offset as isize) }
+    }
+}
+
+/// A range of the mip levels and array layers of an image, seen as a format of the same size.
+#[derive(Debug)]
+pub struct ImageView {
+    pub image: *const Image,
+    pub format: Format,
+    pub vk_format: vk::Format,
+    pub aspects: vk::ImageAspectFlags,
+    pub base_level: u32,
+    pub levels: u32,
+    pub base_layer: u32,
+    pub layers: u32,
+}
+
+impl ImageView {
+    pub fn from_create_info(
+        create_info: &vk::ImageViewCreateInfo,
+        alloc: *const vk::AllocationCallbacks,
+    ) -> Result<Self, vk::Result> {
+        debug_assert_eq!(create_info.sType, vk::STRUCTURE_TYPE_IMAGE_VIEW_CREATE_INFO);
+        if !alloc.is_null() {
+            warn!("Custom allocators not supported by driver");
+            return Err(vk::ERROR_INITIALIZATION_FAILED);
+        }
+        let image = unsafe { &*(create_info.image as *const Image) };
+        let format = match Format::from_vk(create_info.format) {
+            Some(format) => format,
+            None => {
+                warn!("Format {} not supported by driver", create_info.format);
+                return Err(vk::ERROR_FORMAT_NOT_SUPPORTED);
+            }
+        };
+        // Depth/stencil formats cannot be reinterpreted
+        let compatible = match (format, image.format) {
+            (Format::Color { .. }, Format::Color { .. }) => format.size() == image.format.size(),
+            _ => format == image.format,
+        };
+        let range = &create_info.subresourceRange;
+        let levels = remaining(range.levelCount, range.baseMipLevel, image.mip_levels);
+        let layers = remaining(range.layerCount, range.baseArrayLayer, image.array_layers);
+        if !compatible || range.aspectMask == 0 || range.aspectMask & !format.aspects() != 0 ||
+            levels == 0 || layers == 0 ||
+            u64::from(range.baseMipLevel) + u64::from(levels) > u64::from(image.mip_levels) ||
+            u64::from(range.baseArrayLayer) + u64::from(layers) > u64::from(image.array_layers)
+        {
+            warn!(
+                "View of format {} and aspects {:#x} does not fit image of format {}",
+                create_info.format,
+                range.aspectMask,
+                image.vk_format
+            );
+            return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+        }
+        // Swizzles only change what shaders sample
+        Ok(ImageView {
+            image: image,
+            format: format,
+            vk_format: create_info.format,
+            aspects: range.aspectMask,
+            base_level: range.baseMipLevel,
+            levels: levels,
+            base_layer: range.baseArrayLayer,
+            layers: layers,
+        })
+    }
+
+    /// Width and height of the first mip level of the view.
+    pub fn extent(&self) -> (u32, u32) {
+        let [width, height, _] = unsafe { &*self.image }.level_extent(self.base_level);
+        (width, height)
+    }
+
+    pub fn samples(&self) -> u32 {
+        unsafe { &*self.image }.samples
+    }
+
+    /// Writes `bytes` of `texel` to all samples of the pixels of `area` of the first `layers`
+    /// layers of the first mip level of the view.
+    ///
+    /// Unsafe because the image of the view has to be alive.
+    pub unsafe fn fill(
+        &self,
+        area: &vk::Rect2D,
+        layers: u32,
+        bytes: Range<usize>,
+        texel: &[u8],
+    ) {
+        let image = &*self.image;
+        let size = self.format.size();
+        for layer in 0..layers {
+            for y in area_range(area.offset.y, area.extent.height) {
+                for x in area_range(area.offset.x, area.extent.width) {
+                    let data = image.texel(self.base_level, self.base_layer + layer, x, y, 0);
+                    if data.is_null() {
+                        warn!("Skipping fill of an image without memory");
+                        return;
+                    }
+                    for sample in 0..image.samples as usize {
+                        let sample = data.add(sample * size + bytes.start);
+                        let value = &texel[bytes.clone()];
+                        ptr::copy_nonoverlapping(value.as_ptr(), sample, value.len());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Resolves the samples of the pixels of `area` of the first `layers` layers of the view to
+    /// `destination`, which has a single sample.
+    ///
+    /// Unsafe because the images of both views have to be alive.
+    pub unsafe fn resolve(&self, destination: &ImageView, area: &vk::Rect2D, layers: u32) {
+        let (source_image, destination_image) = (&*self.image, &*destination.image);
+        let size = self.format.size();
+        let samples = size * source_image.samples as usize;
+        for layer in 0..layers {
+            for y in area_range(area.offset.y, area.extent.height) {
+                for x in area_range(area.offset.x, area.extent.width) {
+                    let layer_index = self.base_layer + layer;
+                    let source = source_image.texel(self.base_level, layer_index, x, y, 0);
+                    let texel = destination_image.texel(
+                        destination.base_level,
+                        destination.base_layer + layer,
+                        x,
+                        y,
+                        0,
+                    );
+                    if source.is_null() || texel.is_null() {
+                        warn!("Skipping resolve of an image without memory");
+                        return;
+                    }
+                    self.format.resolve(
+                        slice::from_raw_parts(source, samples),
+                        slice::from_raw_parts_mut(texel, size),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// The count of a subresource range, which may be `REMAINING_MIP_LEVELS` or
+/// `REMAINING_ARRAY_LAYERS` for all from `base` on.
+fn remaining(count: u32, base: u32, total: u32) -> u32 {
+    if count == vk::REMAINING_MIP_LEVELS {
+        total.saturating_sub(base)
+    } else {
+        count
+    }
+}
+
+/// The coordinates from `offset` on for `extent` pixels. Render areas never start at negative
+/// coordinates.
+fn area_range(offset: i32, extent: u32) -> Range<u32> {
+    offset as u32..offset as u32 + extent
+}
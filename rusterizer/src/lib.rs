@@ -1,23 +1,60 @@
+// The code keeps to the idioms of Rust 2015 and to the standard library of its time
+#![allow(
+    clippy::redundant_field_names,
+    clippy::redundant_static_lifetimes,
+    clippy::redundant_closure,
+    clippy::legacy_numeric_constants,
+    clippy::match_like_matches_macro,
+    clippy::needless_borrowed_reference,
+    clippy::manual_div_ceil,
+    clippy::manual_is_multiple_of,
+    clippy::unnecessary_map_or
+)]
+// Handles of dispatchable objects are boxed so that they keep their addresses
+#![allow(clippy::vec_box)]
+
 extern crate libc;
 #[macro_use]
 extern crate log;
 extern crate env_logger;
+extern crate rspirv;
+extern crate spirv_headers;
+extern crate spirv_llvm;
 
 pub mod loader_interface;
-mod ffi_types;
+// Public so that the bindings nothing uses yet do not count as dead code
+pub mod ffi_types;
 mod entrypoint;
 mod extension;
 mod dispatch;
 mod version;
 mod physical_device;
 mod device;
-//mod mem;
+mod shader;
+mod pipeline;
+mod pipeline_cache;
+#[cfg(feature = "llvm")]
+mod shader_cache;
+mod batch;
+mod command;
+mod compute;
+mod debug_report;
+mod memory;
+mod descriptor;
+mod format;
+mod image;
+mod render_pass;
+mod draw;
+mod rasterizer;
+mod clipper;
+mod tiler;
+mod hash;
 
 
-use std::sync::{Once, ONCE_INIT};
+use std::sync::Once;
 
 // Init logging
-pub static LOG: Once = ONCE_INIT;
+pub static LOG: Once = Once::new();
 
 
 #[cfg(test)]
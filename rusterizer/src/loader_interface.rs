@@ -7,7 +7,9 @@
 //!    be done by the vulkan validation layer during development)
 //! 2. Call rustic function, which is located in a specialized module.
 //! 3. Optionally convert the return types back to raw c pointers.
-use std::ptr;
+// Entry points keep the names and the raw pointer parameters of the Vulkan API
+#![allow(non_snake_case, unused_variables, clippy::not_unsafe_ptr_arg_deref)]
+
 use std::ffi::CStr;
 use std::slice;
 
@@ -27,7 +29,33 @@ use physical_device::{get_physical_device_properties, get_physical_device_queue_
                       get_physical_device_surface_capabilities_khr,
                       get_physical_device_surface_present_modes_khr};
 
-use device::{destroy_device, get_device_queue, create_command_pool, allocate_command_buffers};
+use device::{destroy_device, get_device_queue, create_command_pool, allocate_command_buffers,
+             create_shader_module, destroy_shader_module, create_pipeline_cache,
+             destroy_pipeline_cache, create_pipeline_layout, destroy_pipeline_layout,
+             create_graphics_pipelines, destroy_pipeline, begin_command_buffer,
+             end_command_buffer, cmd_bind_pipeline, cmd_dispatch_base, cmd_dispatch_indirect,
+             queue_submit, queue_wait_idle, allocate_memory, free_memory, map_memory,
+             create_buffer, destroy_buffer, bind_buffer_memory, create_compute_pipelines,
+             get_pipeline_cache_data, merge_pipeline_caches, create_descriptor_set_layout,
+             destroy_descriptor_set_layout, create_descriptor_pool, destroy_descriptor_pool,
+             reset_descriptor_pool, allocate_descriptor_sets, free_descriptor_sets,
+             update_descriptor_sets, cmd_bind_descriptor_sets, cmd_push_constants, create_image,
+             destroy_image, bind_image_memory, create_image_view, destroy_image_view,
+             create_render_pass, destroy_render_pass, create_framebuffer, destroy_framebuffer,
+             cmd_begin_render_pass, cmd_next_subpass, cmd_end_render_pass,
+             cmd_bind_vertex_buffers, cmd_bind_index_buffer, cmd_set_viewport, cmd_set_scissor,
+             cmd_set_line_width, cmd_set_depth_bias, cmd_set_blend_constants,
+             cmd_set_depth_bounds, cmd_set_stencil_compare_mask, cmd_set_stencil_write_mask,
+             cmd_set_stencil_reference, cmd_draw, cmd_draw_indexed};
+use descriptor::{DescriptorPool, DescriptorSet, DescriptorSetLayout};
+use image::{Image, ImageView};
+use memory::{Buffer, DeviceMemory};
+use shader::ShaderModule;
+use pipeline::{array, DepthBias, Pipeline, PipelineLayout};
+use pipeline_cache::PipelineCache;
+use render_pass::{Framebuffer, RenderPass};
+use debug_report::{create_debug_report_callback, destroy_debug_report_callback,
+                   debug_report_message};
 
 //TODO Globally change all .as_ref().unwrap() to &* for performance.
 
@@ -92,7 +120,7 @@ pub extern "system" fn vkEnumerateInstanceExtensionProperties(
         }
     };
     let property_count: &mut u32 = unsafe { property_count.as_mut().unwrap() };
-    let mut properties = unsafe {
+    let properties = unsafe {
         if properties.is_null() {
             None
         } else {
@@ -213,7 +241,7 @@ pub extern "system" fn vkGetPhysicalDeviceQueueFamilyProperties(
 ) -> vk::Result {
     let phys_device = unsafe { phys_device.as_ref().unwrap() };
     let property_count = unsafe { p_property_count.as_mut().unwrap() };
-    let mut properties = unsafe {
+    let properties = unsafe {
         if p_properties.is_null() {
             None
         } else {
@@ -240,7 +268,7 @@ pub extern "system" fn vkEnumerateDeviceExtensionProperties(
         }
     };
     let property_count: &mut u32 = unsafe { p_property_count.as_mut().unwrap() };
-    let mut properties = unsafe {
+    let properties = unsafe {
         if p_properties.is_null() {
             None
         } else {
@@ -339,6 +367,48 @@ pub extern "system" fn vkGetPhysicalDeviceSurfacePresentModesKHR(
     )
 }
 
+pub extern "system" fn vkCreateDebugReportCallbackEXT(
+    instance: *mut Instance,
+    p_create_info: *const vk::DebugReportCallbackCreateInfoEXT,
+    p_allocator: *const vk::AllocationCallbacks,
+    p_callback: *mut vk::DebugReportCallbackEXT,
+) -> vk::Result {
+    let instance = unsafe { instance.as_ref().unwrap() };
+    let create_info = unsafe { p_create_info.as_ref().unwrap() };
+    match create_debug_report_callback(instance, create_info, p_allocator) {
+        Err(err) => err,
+        Ok(callback) => {
+            unsafe { *p_callback = callback };
+            vk::SUCCESS
+        }
+    }
+}
+
+pub extern "system" fn vkDestroyDebugReportCallbackEXT(
+    instance: *mut Instance,
+    callback: vk::DebugReportCallbackEXT,
+    p_allocator: *const vk::AllocationCallbacks,
+) {
+    let instance = unsafe { instance.as_ref().unwrap() };
+    destroy_debug_report_callback(instance, callback, p_allocator);
+}
+
+pub extern "system" fn vkDebugReportMessageEXT(
+    instance: *mut Instance,
+    flags: vk::DebugReportFlagsEXT,
+    object_type: vk::DebugReportObjectTypeEXT,
+    object: u64,
+    _location: usize,
+    _message_code: i32,
+    p_layer_prefix: *const libc::c_char,
+    p_message: *const libc::c_char,
+) {
+    let instance = unsafe { instance.as_ref().unwrap() };
+    let prefix = unsafe { CStr::from_ptr(p_layer_prefix) };
+    let message = unsafe { CStr::from_ptr(p_message) };
+    debug_report_message(instance, flags, object_type, object, prefix, message);
+}
+
 // Device functions
 
 pub extern "system" fn vkDestroyDevice(
@@ -374,51 +444,72 @@ pub extern "system" fn vkQueueSubmit(
     p_submits: *const vk::SubmitInfo,
     fence: vk::Fence,
 ) -> vk::Result {
-    unimplemented!()
+    let queue = unsafe { &*queue };
+    let submits = unsafe {
+        if submit_count == 0 {
+            &[]
+        } else {
+            slice::from_raw_parts(p_submits, submit_count as usize)
+        }
+    };
+    queue_submit(queue, submits, fence)
 }
 
 pub extern "system" fn vkQueueWaitIdle(queue: *mut Queue) -> vk::Result {
-    unimplemented!()
+    let queue = unsafe { &*queue };
+    queue_wait_idle(queue)
 }
 
 pub extern "system" fn vkDeviceWaitIdle(device: *mut Device) -> vk::Result {
-    //TODO
+    // Queue submissions run to completion before they return
     vk::SUCCESS
 }
 
 pub extern "system" fn vkAllocateMemory(
     device: *mut Device,
-    p_allocate_info: vk::MemoryAllocateInfo,
+    p_allocate_info: *const vk::MemoryAllocateInfo,
     p_allocator: *const vk::AllocationCallbacks,
-    memory: *mut vk::DeviceMemory,
+    p_memory: *mut *mut DeviceMemory,
 ) -> vk::Result {
-    unimplemented!()
+    let device = unsafe { device.as_ref().unwrap() };
+    let allocate_info = unsafe { p_allocate_info.as_ref().unwrap() };
+    match allocate_memory(device, allocate_info, p_allocator) {
+        Err(err) => err,
+        Ok(memory) => {
+            unsafe { *p_memory = Box::into_raw(memory) };
+            vk::SUCCESS
+        }
+    }
 }
 
 pub extern "system" fn vkFreeMemory(
     device: *mut Device,
-    memory: *const vk::DeviceMemory,
+    memory: *mut DeviceMemory,
     p_allocator: *const vk::AllocationCallbacks,
 ) {
-    unimplemented!()
+    let device = unsafe { device.as_ref().unwrap() };
+    if !memory.is_null() {
+        let memory = unsafe { Box::from_raw(memory) };
+        free_memory(device, memory, p_allocator);
+    }
 }
 
 pub extern "system" fn vkMapMemory(
     device: *mut Device,
-    memory: *const vk::DeviceMemory,
+    memory: *mut DeviceMemory,
     offset: vk::DeviceSize,
     size: vk::DeviceSize,
     flags: vk::MemoryMapFlags,
     pp_data: *mut *mut libc::c_void,
 ) -> vk::Result {
-    unimplemented!()
+    let device = unsafe { device.as_ref().unwrap() };
+    let memory = unsafe { &*memory };
+    unsafe { *pp_data = map_memory(device, memory, offset, size) };
+    vk::SUCCESS
 }
 
-pub extern "system" fn vkUnmapMemory(
-    device: *mut Device,
-    memory: *const vk::DeviceMemory,
-) -> vk::Result {
-    unimplemented!()
+pub extern "system" fn vkUnmapMemory(device: *mut Device, memory: *mut DeviceMemory) {
+    // Mapping memory only handed out its address
 }
 
 pub extern "system" fn vkFlushMappedMemoryRanges(
@@ -426,7 +517,8 @@ pub extern "system" fn vkFlushMappedMemoryRanges(
     memory_range_count: u32,
     p_memory_ranges: *const vk::MappedMemoryRange,
 ) -> vk::Result {
-    unimplemented!()
+    // Memory is coherent
+    vk::SUCCESS
 }
 
 pub extern "system" fn vkInvalidateMappedMemoryRanges(
@@ -434,7 +526,222 @@ pub extern "system" fn vkInvalidateMappedMemoryRanges(
     memory_range_count: u32,
     p_memory_ranges: *const vk::MappedMemoryRange,
 ) -> vk::Result {
-    unimplemented!()
+    // Memory is coherent
+    vk::SUCCESS
+}
+
+pub extern "system" fn vkCreateBuffer(
+    device: *mut Device,
+    p_create_info: *const vk::BufferCreateInfo,
+    p_allocator: *const vk::AllocationCallbacks,
+    p_buffer: *mut *mut Buffer,
+) -> vk::Result {
+    let device = unsafe { device.as_ref().unwrap() };
+    let create_info = unsafe { p_create_info.as_ref().unwrap() };
+    match create_buffer(device, create_info, p_allocator) {
+        Err(err) => err,
+        Ok(buffer) => {
+            unsafe { *p_buffer = Box::into_raw(buffer) };
+            vk::SUCCESS
+        }
+    }
+}
+
+pub extern "system" fn vkDestroyBuffer(
+    device: *mut Device,
+    buffer: *mut Buffer,
+    p_allocator: *const vk::AllocationCallbacks,
+) {
+    let device = unsafe { device.as_ref().unwrap() };
+    if !buffer.is_null() {
+        let buffer = unsafe { Box::from_raw(buffer) };
+        destroy_buffer(device, buffer, p_allocator);
+    }
+}
+
+pub extern "system" fn vkGetBufferMemoryRequirements(
+    device: *mut Device,
+    buffer: *mut Buffer,
+    p_memory_requirements: *mut vk::MemoryRequirements,
+) {
+    let buffer = unsafe { &*buffer };
+    unsafe { *p_memory_requirements = buffer.memory_requirements() };
+}
+
+pub extern "system" fn vkBindBufferMemory(
+    device: *mut Device,
+    buffer: *mut Buffer,
+    memory: *mut DeviceMemory,
+    memory_offset: vk::DeviceSize,
+) -> vk::Result {
+    let device = unsafe { device.as_ref().unwrap() };
+    let buffer = unsafe { &mut *buffer };
+    let memory = unsafe { &*memory };
+    bind_buffer_memory(device, buffer, memory, memory_offset)
+}
+
+pub extern "system" fn vkCreateImage(
+    device: *mut Device,
+    p_create_info: *const vk::ImageCreateInfo,
+    p_allocator: *const vk::AllocationCallbacks,
+    p_image: *mut *mut Image,
+) -> vk::Result {
+    let device = unsafe { device.as_ref().unwrap() };
+    let create_info = unsafe { p_create_info.as_ref().unwrap() };
+    match create_image(device, create_info, p_allocator) {
+        Err(err) => err,
+        Ok(image) => {
+            unsafe { *p_image = Box::into_raw(image) };
+            vk::SUCCESS
+        }
+    }
+}
+
+pub extern "system" fn vkDestroyImage(
+    device: *mut Device,
+    image: *mut Image,
+    p_allocator: *const vk::AllocationCallbacks,
+) {
+    let device = unsafe { device.as_ref().unwrap() };
+    if !image.is_null() {
+        let image = unsafe { Box::from_raw(image) };
+        destroy_image(device, image, p_allocator);
+    }
+}
+
+pub extern "system" fn vkGetImageMemoryRequirements(
+    device: *mut Device,
+    image: *mut Image,
+    p_memory_requirements: *mut vk::MemoryRequirements,
+) {
+    let image = unsafe { &*image };
+    unsafe { *p_memory_requirements = image.memory_requirements() };
+}
+
+pub extern "system" fn vkBindImageMemory(
+    device: *mut Device,
+    image: *mut Image,
+    memory: *mut DeviceMemory,
+    memory_offset: vk::DeviceSize,
+) -> vk::Result {
+    let device = unsafe { device.as_ref().unwrap() };
+    let image = unsafe { &mut *image };
+    let memory = unsafe { &*memory };
+    bind_image_memory(device, image, memory, memory_offset)
+}
+
+pub extern "system" fn vkGetImageSubresourceLayout(
+    device: *mut Device,
+    image: *mut Image,
+    p_subresource: *const vk::ImageSubresource,
+    p_layout: *mut vk::SubresourceLayout,
+) {
+    let image = unsafe { &*image };
+    let subresource = unsafe { &*p_subresource };
+    unsafe {
+        *p_layout = image.subresource_layout(subresource.mipLevel, subresource.arrayLayer)
+    };
+}
+
+pub extern "system" fn vkCreateImageView(
+    device: *mut Device,
+    p_create_info: *const vk::ImageViewCreateInfo,
+    p_allocator: *const vk::AllocationCallbacks,
+    p_view: *mut *mut ImageView,
+) -> vk::Result {
+    let device = unsafe { device.as_ref().unwrap() };
+    let create_info = unsafe { p_create_info.as_ref().unwrap() };
+    match create_image_view(device, create_info, p_allocator) {
+        Err(err) => err,
+        Ok(view) => {
+            unsafe { *p_view = Box::into_raw(view) };
+            vk::SUCCESS
+        }
+    }
+}
+
+pub extern "system" fn vkDestroyImageView(
+    device: *mut Device,
+    view: *mut ImageView,
+    p_allocator: *const vk::AllocationCallbacks,
+) {
+    let device = unsafe { device.as_ref().unwrap() };
+    if !view.is_null() {
+        let view = unsafe { Box::from_raw(view) };
+        destroy_image_view(device, view, p_allocator);
+    }
+}
+
+pub extern "system" fn vkCreateRenderPass(
+    device: *mut Device,
+    p_create_info: *const vk::RenderPassCreateInfo,
+    p_allocator: *const vk::AllocationCallbacks,
+    p_render_pass: *mut *mut RenderPass,
+) -> vk::Result {
+    let device = unsafe { device.as_ref().unwrap() };
+    let create_info = unsafe { p_create_info.as_ref().unwrap() };
+    match create_render_pass(device, create_info, p_allocator) {
+        Err(err) => err,
+        Ok(render_pass) => {
+            unsafe { *p_render_pass = Box::into_raw(render_pass) };
+            vk::SUCCESS
+        }
+    }
+}
+
+pub extern "system" fn vkDestroyRenderPass(
+    device: *mut Device,
+    render_pass: *mut RenderPass,
+    p_allocator: *const vk::AllocationCallbacks,
+) {
+    let device = unsafe { device.as_ref().unwrap() };
+    if !render_pass.is_null() {
+        let render_pass = unsafe { Box::from_raw(render_pass) };
+        destroy_render_pass(device, render_pass, p_allocator);
+    }
+}
+
+/// Render areas of any size are as fast as any other.
+pub extern "system" fn vkGetRenderAreaGranularity(
+    device: *mut Device,
+    render_pass: *mut RenderPass,
+    p_granularity: *mut vk::Extent2D,
+) {
+    unsafe {
+        *p_granularity = vk::Extent2D {
+            width: 1,
+            height: 1,
+        }
+    };
+}
+
+pub extern "system" fn vkCreateFramebuffer(
+    device: *mut Device,
+    p_create_info: *const vk::FramebufferCreateInfo,
+    p_allocator: *const vk::AllocationCallbacks,
+    p_framebuffer: *mut *mut Framebuffer,
+) -> vk::Result {
+    let device = unsafe { device.as_ref().unwrap() };
+    let create_info = unsafe { p_create_info.as_ref().unwrap() };
+    match create_framebuffer(device, create_info, p_allocator) {
+        Err(err) => err,
+        Ok(framebuffer) => {
+            unsafe { *p_framebuffer = Box::into_raw(framebuffer) };
+            vk::SUCCESS
+        }
+    }
+}
+
+pub extern "system" fn vkDestroyFramebuffer(
+    device: *mut Device,
+    framebuffer: *mut Framebuffer,
+    p_allocator: *const vk::AllocationCallbacks,
+) {
+    let device = unsafe { device.as_ref().unwrap() };
+    if !framebuffer.is_null() {
+        let framebuffer = unsafe { Box::from_raw(framebuffer) };
+        destroy_framebuffer(device, framebuffer, p_allocator);
+    }
 }
 
 pub extern "system" fn vkCreateCommandPool(
@@ -464,7 +771,536 @@ pub extern "system" fn vkAllocateCommandBuffers(
 ) -> vk::Result {
     let device = unsafe { device.as_ref().unwrap() };
     let allocate_info = unsafe { p_allocate_info.as_mut().unwrap() };
-    allocate_command_buffers(device, allocate_info)
+    let count = allocate_info.commandBufferCount as usize;
+    let command_buffers = unsafe { slice::from_raw_parts_mut(p_command_buffers, count) };
+    allocate_command_buffers(device, allocate_info, command_buffers)
+}
+
+pub extern "system" fn vkBeginCommandBuffer(
+    command_buffer: *mut CommandBuffer,
+    p_begin_info: *const vk::CommandBufferBeginInfo,
+) -> vk::Result {
+    let command_buffer = unsafe { &mut *command_buffer };
+    let begin_info = unsafe { &*p_begin_info };
+    begin_command_buffer(command_buffer, begin_info)
+}
+
+pub extern "system" fn vkEndCommandBuffer(command_buffer: *mut CommandBuffer) -> vk::Result {
+    let command_buffer = unsafe { &mut *command_buffer };
+    end_command_buffer(command_buffer)
+}
+
+pub extern "system" fn vkCmdBindPipeline(
+    command_buffer: *mut CommandBuffer,
+    pipeline_bind_point: vk::PipelineBindPoint,
+    pipeline: *mut Pipeline,
+) {
+    let command_buffer = unsafe { &mut *command_buffer };
+    cmd_bind_pipeline(command_buffer, pipeline_bind_point, pipeline);
+}
+
+pub extern "system" fn vkCmdBindDescriptorSets(
+    command_buffer: *mut CommandBuffer,
+    pipeline_bind_point: vk::PipelineBindPoint,
+    layout: *mut PipelineLayout,
+    first_set: u32,
+    descriptor_set_count: u32,
+    p_descriptor_sets: *const *const DescriptorSet,
+    dynamic_offset_count: u32,
+    p_dynamic_offsets: *const u32,
+) {
+    let command_buffer = unsafe { &mut *command_buffer };
+    let layout = unsafe { &*layout };
+    let sets = unsafe { slice::from_raw_parts(p_descriptor_sets, descriptor_set_count as usize) };
+    let dynamic_offsets = unsafe { array(p_dynamic_offsets, dynamic_offset_count) };
+    cmd_bind_descriptor_sets(
+        command_buffer,
+        pipeline_bind_point,
+        layout,
+        first_set,
+        sets,
+        dynamic_offsets,
+    );
+}
+
+pub extern "system" fn vkCmdPushConstants(
+    command_buffer: *mut CommandBuffer,
+    layout: *mut PipelineLayout,
+    stage_flags: vk::ShaderStageFlags,
+    offset: u32,
+    size: u32,
+    p_values: *const libc::c_void,
+) {
+    let command_buffer = unsafe { &mut *command_buffer };
+    let layout = unsafe { &*layout };
+    let values = unsafe { array(p_values as *const u8, size) };
+    cmd_push_constants(command_buffer, layout, stage_flags, offset, values);
+}
+
+pub extern "system" fn vkCmdBeginRenderPass(
+    command_buffer: *mut CommandBuffer,
+    p_render_pass_begin: *const vk::RenderPassBeginInfo,
+    contents: vk::SubpassContents,
+) {
+    let command_buffer = unsafe { &mut *command_buffer };
+    let begin_info = unsafe { &*p_render_pass_begin };
+    cmd_begin_render_pass(command_buffer, begin_info, contents);
+}
+
+pub extern "system" fn vkCmdNextSubpass(
+    command_buffer: *mut CommandBuffer,
+    contents: vk::SubpassContents,
+) {
+    let command_buffer = unsafe { &mut *command_buffer };
+    cmd_next_subpass(command_buffer, contents);
+}
+
+pub extern "system" fn vkCmdEndRenderPass(command_buffer: *mut CommandBuffer) {
+    let command_buffer = unsafe { &mut *command_buffer };
+    cmd_end_render_pass(command_buffer);
+}
+
+pub extern "system" fn vkCmdDispatch(
+    command_buffer: *mut CommandBuffer,
+    group_count_x: u32,
+    group_count_y: u32,
+    group_count_z: u32,
+) {
+    let command_buffer = unsafe { &mut *command_buffer };
+    let count = [group_count_x, group_count_y, group_count_z];
+    cmd_dispatch_base(command_buffer, [0; 3], count);
+}
+
+pub extern "system" fn vkCmdDispatchBase(
+    command_buffer: *mut CommandBuffer,
+    base_group_x: u32,
+    base_group_y: u32,
+    base_group_z: u32,
+    group_count_x: u32,
+    group_count_y: u32,
+    group_count_z: u32,
+) {
+    let command_buffer = unsafe { &mut *command_buffer };
+    let base = [base_group_x, base_group_y, base_group_z];
+    let count = [group_count_x, group_count_y, group_count_z];
+    cmd_dispatch_base(command_buffer, base, count);
+}
+
+pub extern "system" fn vkCmdDispatchIndirect(
+    command_buffer: *mut CommandBuffer,
+    buffer: *mut Buffer,
+    offset: vk::DeviceSize,
+) {
+    let command_buffer = unsafe { &mut *command_buffer };
+    cmd_dispatch_indirect(command_buffer, buffer, offset);
+}
+
+pub extern "system" fn vkCmdBindVertexBuffers(
+    command_buffer: *mut CommandBuffer,
+    first_binding: u32,
+    binding_count: u32,
+    p_buffers: *const *const Buffer,
+    p_offsets: *const vk::DeviceSize,
+) {
+    let command_buffer = unsafe { &mut *command_buffer };
+    let buffers = unsafe { array(p_buffers, binding_count) };
+    let offsets = unsafe { array(p_offsets, binding_count) };
+    cmd_bind_vertex_buffers(command_buffer, first_binding, buffers, offsets);
+}
+
+pub extern "system" fn vkCmdBindIndexBuffer(
+    command_buffer: *mut CommandBuffer,
+    buffer: *mut Buffer,
+    offset: vk::DeviceSize,
+    index_type: vk::IndexType,
+) {
+    let command_buffer = unsafe { &mut *command_buffer };
+    cmd_bind_index_buffer(command_buffer, buffer, offset, index_type);
+}
+
+pub extern "system" fn vkCmdSetViewport(
+    command_buffer: *mut CommandBuffer,
+    first_viewport: u32,
+    viewport_count: u32,
+    p_viewports: *const vk::Viewport,
+) {
+    let command_buffer = unsafe { &mut *command_buffer };
+    let viewports = unsafe { array(p_viewports, viewport_count) };
+    cmd_set_viewport(command_buffer, first_viewport, viewports);
+}
+
+pub extern "system" fn vkCmdSetScissor(
+    command_buffer: *mut CommandBuffer,
+    first_scissor: u32,
+    scissor_count: u32,
+    p_scissors: *const vk::Rect2D,
+) {
+    let command_buffer = unsafe { &mut *command_buffer };
+    let scissors = unsafe { array(p_scissors, scissor_count) };
+    cmd_set_scissor(command_buffer, first_scissor, scissors);
+}
+
+pub extern "system" fn vkCmdSetLineWidth(command_buffer: *mut CommandBuffer, line_width: f32) {
+    let command_buffer = unsafe { &mut *command_buffer };
+    cmd_set_line_width(command_buffer, line_width);
+}
+
+pub extern "system" fn vkCmdSetDepthBias(
+    command_buffer: *mut CommandBuffer,
+    depth_bias_constant_factor: f32,
+    depth_bias_clamp: f32,
+    depth_bias_slope_factor: f32,
+) {
+    let command_buffer = unsafe { &mut *command_buffer };
+    let bias = DepthBias {
+        constant_factor: depth_bias_constant_factor,
+        clamp: depth_bias_clamp,
+        slope_factor: depth_bias_slope_factor,
+    };
+    cmd_set_depth_bias(command_buffer, bias);
+}
+
+pub extern "system" fn vkCmdSetBlendConstants(
+    command_buffer: *mut CommandBuffer,
+    blend_constants: *const [f32; 4],
+) {
+    let command_buffer = unsafe { &mut *command_buffer };
+    let constants = unsafe { *blend_constants };
+    cmd_set_blend_constants(command_buffer, constants);
+}
+
+pub extern "system" fn vkCmdSetDepthBounds(
+    command_buffer: *mut CommandBuffer,
+    min_depth_bounds: f32,
+    max_depth_bounds: f32,
+) {
+    let command_buffer = unsafe { &mut *command_buffer };
+    cmd_set_depth_bounds(command_buffer, min_depth_bounds, max_depth_bounds);
+}
+
+pub extern "system" fn vkCmdSetStencilCompareMask(
+    command_buffer: *mut CommandBuffer,
+    face_mask: vk::StencilFaceFlags,
+    compare_mask: u32,
+) {
+    let command_buffer = unsafe { &mut *command_buffer };
+    cmd_set_stencil_compare_mask(command_buffer, face_mask, compare_mask);
+}
+
+pub extern "system" fn vkCmdSetStencilWriteMask(
+    command_buffer: *mut CommandBuffer,
+    face_mask: vk::StencilFaceFlags,
+    write_mask: u32,
+) {
+    let command_buffer = unsafe { &mut *command_buffer };
+    cmd_set_stencil_write_mask(command_buffer, face_mask, write_mask);
+}
+
+pub extern "system" fn vkCmdSetStencilReference(
+    command_buffer: *mut CommandBuffer,
+    face_mask: vk::StencilFaceFlags,
+    reference: u32,
+) {
+    let command_buffer = unsafe { &mut *command_buffer };
+    cmd_set_stencil_reference(command_buffer, face_mask, reference);
+}
+
+pub extern "system" fn vkCmdDraw(
+    command_buffer: *mut CommandBuffer,
+    vertex_count: u32,
+    instance_count: u32,
+    first_vertex: u32,
+    first_instance: u32,
+) {
+    let command_buffer = unsafe { &mut *command_buffer };
+    cmd_draw(command_buffer, vertex_count, instance_count, first_vertex, first_instance);
+}
+
+pub extern "system" fn vkCmdDrawIndexed(
+    command_buffer: *mut CommandBuffer,
+    index_count: u32,
+    instance_count: u32,
+    first_index: u32,
+    vertex_offset: i32,
+    first_instance: u32,
+) {
+    let command_buffer = unsafe { &mut *command_buffer };
+    cmd_draw_indexed(
+        command_buffer,
+        index_count,
+        instance_count,
+        first_index,
+        vertex_offset,
+        first_instance,
+    );
+}
+
+pub extern "system" fn vkCreateShaderModule(
+    device: *mut Device,
+    p_create_info: *const vk::ShaderModuleCreateInfo,
+    p_allocator: *const vk::AllocationCallbacks,
+    p_shader_module: *mut *mut ShaderModule,
+) -> vk::Result {
+    let device = unsafe { device.as_ref().unwrap() };
+    let create_info = unsafe { p_create_info.as_ref().unwrap() };
+    match create_shader_module(device, create_info, p_allocator) {
+        Err(err) => err,
+        Ok(shader_module) => {
+            unsafe { *p_shader_module = Box::into_raw(shader_module) };
+            vk::SUCCESS
+        }
+    }
+}
+
+pub extern "system" fn vkDestroyShaderModule(
+    device: *mut Device,
+    shader_module: *mut ShaderModule,
+    p_allocator: *const vk::AllocationCallbacks,
+) {
+    let device = unsafe { device.as_ref().unwrap() };
+    // Destroying a null handle is allowed and does nothing
+    if !shader_module.is_null() {
+        let shader_module = unsafe { Box::from_raw(shader_module) };
+        destroy_shader_module(device, shader_module, p_allocator);
+    }
+}
+
+pub extern "system" fn vkCreatePipelineCache(
+    device: *mut Device,
+    p_create_info: *const vk::PipelineCacheCreateInfo,
+    p_allocator: *const vk::AllocationCallbacks,
+    p_pipeline_cache: *mut *mut PipelineCache,
+) -> vk::Result {
+    let device = unsafe { device.as_ref().unwrap() };
+    let create_info = unsafe { p_create_info.as_ref().unwrap() };
+    match create_pipeline_cache(device, create_info, p_allocator) {
+        Err(err) => err,
+        Ok(pipeline_cache) => {
+            unsafe { *p_pipeline_cache = Box::into_raw(pipeline_cache) };
+            vk::SUCCESS
+        }
+    }
+}
+
+pub extern "system" fn vkDestroyPipelineCache(
+    device: *mut Device,
+    pipeline_cache: *mut PipelineCache,
+    p_allocator: *const vk::AllocationCallbacks,
+) {
+    let device = unsafe { device.as_ref().unwrap() };
+    if !pipeline_cache.is_null() {
+        let pipeline_cache = unsafe { Box::from_raw(pipeline_cache) };
+        destroy_pipeline_cache(device, pipeline_cache, p_allocator);
+    }
+}
+
+pub extern "system" fn vkGetPipelineCacheData(
+    device: *mut Device,
+    pipeline_cache: *mut PipelineCache,
+    p_data_size: *mut usize,
+    p_data: *mut libc::c_void,
+) -> vk::Result {
+    let device = unsafe { device.as_ref().unwrap() };
+    let pipeline_cache = unsafe { pipeline_cache.as_ref().unwrap() };
+    let data_size = unsafe { p_data_size.as_mut().unwrap() };
+    let data = unsafe {
+        if p_data.is_null() {
+            None
+        } else {
+            Some(slice::from_raw_parts_mut(p_data as *mut u8, *data_size))
+        }
+    };
+    get_pipeline_cache_data(device, pipeline_cache, data_size, data)
+}
+
+pub extern "system" fn vkMergePipelineCaches(
+    device: *mut Device,
+    dst_cache: *mut PipelineCache,
+    src_cache_count: u32,
+    p_src_caches: *const *const PipelineCache,
+) -> vk::Result {
+    let device = unsafe { device.as_ref().unwrap() };
+    let dst_cache = unsafe { dst_cache.as_ref().unwrap() };
+    let src_caches = unsafe { slice::from_raw_parts(p_src_caches, src_cache_count as usize) };
+    merge_pipeline_caches(device, dst_cache, src_caches)
+}
+
+pub extern "system" fn vkCreatePipelineLayout(
+    device: *mut Device,
+    p_create_info: *const vk::PipelineLayoutCreateInfo,
+    p_allocator: *const vk::AllocationCallbacks,
+    p_pipeline_layout: *mut *mut PipelineLayout,
+) -> vk::Result {
+    let device = unsafe { device.as_ref().unwrap() };
+    let create_info = unsafe { p_create_info.as_ref().unwrap() };
+    match create_pipeline_layout(device, create_info, p_allocator) {
+        Err(err) => err,
+        Ok(pipeline_layout) => {
+            unsafe { *p_pipeline_layout = Box::into_raw(pipeline_layout) };
+            vk::SUCCESS
+        }
+    }
+}
+
+pub extern "system" fn vkDestroyPipelineLayout(
+    device: *mut Device,
+    pipeline_layout: *mut PipelineLayout,
+    p_allocator: *const vk::AllocationCallbacks,
+) {
+    let device = unsafe { device.as_ref().unwrap() };
+    if !pipeline_layout.is_null() {
+        let pipeline_layout = unsafe { Box::from_raw(pipeline_layout) };
+        destroy_pipeline_layout(device, pipeline_layout, p_allocator);
+    }
+}
+
+pub extern "system" fn vkCreateDescriptorSetLayout(
+    device: *mut Device,
+    p_create_info: *const vk::DescriptorSetLayoutCreateInfo,
+    p_allocator: *const vk::AllocationCallbacks,
+    p_set_layout: *mut *mut DescriptorSetLayout,
+) -> vk::Result {
+    let device = unsafe { device.as_ref().unwrap() };
+    let create_info = unsafe { p_create_info.as_ref().unwrap() };
+    match create_descriptor_set_layout(device, create_info, p_allocator) {
+        Err(err) => err,
+        Ok(set_layout) => {
+            unsafe { *p_set_layout = Box::into_raw(set_layout) };
+            vk::SUCCESS
+        }
+    }
+}
+
+pub extern "system" fn vkDestroyDescriptorSetLayout(
+    device: *mut Device,
+    set_layout: *mut DescriptorSetLayout,
+    p_allocator: *const vk::AllocationCallbacks,
+) {
+    let device = unsafe { device.as_ref().unwrap() };
+    if !set_layout.is_null() {
+        let set_layout = unsafe { Box::from_raw(set_layout) };
+        destroy_descriptor_set_layout(device, set_layout, p_allocator);
+    }
+}
+
+pub extern "system" fn vkCreateDescriptorPool(
+    device: *mut Device,
+    p_create_info: *const vk::DescriptorPoolCreateInfo,
+    p_allocator: *const vk::AllocationCallbacks,
+    p_descriptor_pool: *mut *mut DescriptorPool,
+) -> vk::Result {
+    let device = unsafe { device.as_ref().unwrap() };
+    let create_info = unsafe { p_create_info.as_ref().unwrap() };
+    match create_descriptor_pool(device, create_info, p_allocator) {
+        Err(err) => err,
+        Ok(descriptor_pool) => {
+            unsafe { *p_descriptor_pool = Box::into_raw(descriptor_pool) };
+            vk::SUCCESS
+        }
+    }
+}
+
+pub extern "system" fn vkDestroyDescriptorPool(
+    device: *mut Device,
+    descriptor_pool: *mut DescriptorPool,
+    p_allocator: *const vk::AllocationCallbacks,
+) {
+    let device = unsafe { device.as_ref().unwrap() };
+    if !descriptor_pool.is_null() {
+        let descriptor_pool = unsafe { Box::from_raw(descriptor_pool) };
+        destroy_descriptor_pool(device, descriptor_pool, p_allocator);
+    }
+}
+
+pub extern "system" fn vkResetDescriptorPool(
+    device: *mut Device,
+    descriptor_pool: *mut DescriptorPool,
+    flags: vk::DescriptorPoolResetFlags,
+) -> vk::Result {
+    let device = unsafe { device.as_ref().unwrap() };
+    let descriptor_pool = unsafe { &mut *descriptor_pool };
+    reset_descriptor_pool(device, descriptor_pool, flags)
+}
+
+pub extern "system" fn vkAllocateDescriptorSets(
+    device: *mut Device,
+    p_allocate_info: *const vk::DescriptorSetAllocateInfo,
+    p_descriptor_sets: *mut *mut DescriptorSet,
+) -> vk::Result {
+    let device = unsafe { device.as_ref().unwrap() };
+    let allocate_info = unsafe { p_allocate_info.as_ref().unwrap() };
+    let count = allocate_info.descriptorSetCount as usize;
+    let descriptor_sets = unsafe { slice::from_raw_parts_mut(p_descriptor_sets, count) };
+    allocate_descriptor_sets(device, allocate_info, descriptor_sets)
+}
+
+pub extern "system" fn vkFreeDescriptorSets(
+    device: *mut Device,
+    descriptor_pool: *mut DescriptorPool,
+    descriptor_set_count: u32,
+    p_descriptor_sets: *const *mut DescriptorSet,
+) -> vk::Result {
+    let device = unsafe { device.as_ref().unwrap() };
+    let descriptor_pool = unsafe { &mut *descriptor_pool };
+    let count = descriptor_set_count as usize;
+    let descriptor_sets = unsafe { slice::from_raw_parts(p_descriptor_sets, count) };
+    free_descriptor_sets(device, descriptor_pool, descriptor_sets)
+}
+
+pub extern "system" fn vkUpdateDescriptorSets(
+    device: *mut Device,
+    descriptor_write_count: u32,
+    p_descriptor_writes: *const vk::WriteDescriptorSet,
+    descriptor_copy_count: u32,
+    p_descriptor_copies: *const vk::CopyDescriptorSet,
+) {
+    let device = unsafe { device.as_ref().unwrap() };
+    let writes = unsafe { array(p_descriptor_writes, descriptor_write_count) };
+    let copies = unsafe { array(p_descriptor_copies, descriptor_copy_count) };
+    update_descriptor_sets(device, writes, copies);
+}
+
+pub extern "system" fn vkCreateGraphicsPipelines(
+    device: *mut Device,
+    pipeline_cache: *mut PipelineCache,
+    create_info_count: u32,
+    p_create_infos: *const vk::GraphicsPipelineCreateInfo,
+    p_allocator: *const vk::AllocationCallbacks,
+    p_pipelines: *mut *mut Pipeline,
+) -> vk::Result {
+    let device = unsafe { device.as_ref().unwrap() };
+    // The cache is optional
+    let pipeline_cache = unsafe { pipeline_cache.as_ref() };
+    let create_infos = unsafe { slice::from_raw_parts(p_create_infos, create_info_count as usize) };
+    let pipelines = unsafe { slice::from_raw_parts_mut(p_pipelines, create_info_count as usize) };
+    create_graphics_pipelines(device, pipeline_cache, create_infos, p_allocator, pipelines)
+}
+
+pub extern "system" fn vkCreateComputePipelines(
+    device: *mut Device,
+    pipeline_cache: *mut PipelineCache,
+    create_info_count: u32,
+    p_create_infos: *const vk::ComputePipelineCreateInfo,
+    p_allocator: *const vk::AllocationCallbacks,
+    p_pipelines: *mut *mut Pipeline,
+) -> vk::Result {
+    let device = unsafe { device.as_ref().unwrap() };
+    let pipeline_cache = unsafe { pipeline_cache.as_ref() };
+    let create_infos = unsafe { slice::from_raw_parts(p_create_infos, create_info_count as usize) };
+    let pipelines = unsafe { slice::from_raw_parts_mut(p_pipelines, create_info_count as usize) };
+    create_compute_pipelines(device, pipeline_cache, create_infos, p_allocator, pipelines)
+}
+
+pub extern "system" fn vkDestroyPipeline(
+    device: *mut Device,
+    pipeline: *mut Pipeline,
+    p_allocator: *const vk::AllocationCallbacks,
+) {
+    let device = unsafe { device.as_ref().unwrap() };
+    if !pipeline.is_null() {
+        let pipeline = unsafe { Box::from_raw(pipeline) };
+        destroy_pipeline(device, pipeline, p_allocator);
+    }
 }
 
 pub extern "system" fn vkCreateSwapchainKHR(
@@ -0,0 +1,144 @@
+//! Device memory and the buffers bound to it.
+//!
+//! Device memory is plain host memory, so mapping it only returns its address, and flushing or
+//! invalidating mapped ranges has nothing to do.
+use std::alloc::{self, Layout};
+use std::ptr;
+
+use ffi_types as vk;
+
+/// Alignment of every allocation, enough for any buffer bound at offset 0.
+pub const MEMORY_ALIGNMENT: usize = 64;
+
+/// Alignment that buffers have to be bound with, enough for any scalar or vector a shader
+/// accesses.
+pub const BUFFER_ALIGNMENT: u64 = 16;
+
+/// The only memory type, which is device local and host visible, coherent and cached.
+pub const MEMORY_TYPE_BITS: u32 = 1;
+
+/// Memory that the application allocated, zeroed on allocation.
+#[derive(Debug)]
+pub struct DeviceMemory {
+    data: *mut u8,
+    size: usize,
+}
+
+impl DeviceMemory {
+    pub fn from_allocate_info(
+        allocate_info: &vk::MemoryAllocateInfo,
+        alloc: *const vk::AllocationCallbacks,
+    ) -> Result<Self, vk::Result> {
+        debug_assert_eq!(allocate_info.sType, vk::STRUCTURE_TYPE_MEMORY_ALLOCATE_INFO);
+        if !alloc.is_null() {
+            warn!("Custom allocators not supported by driver");
+            return Err(vk::ERROR_INITIALIZATION_FAILED);
+        }
+        if MEMORY_TYPE_BITS & (1 << allocate_info.memoryTypeIndex) == 0 {
+            warn!("Memory type {} does not exist", allocate_info.memoryTypeIndex);
+            return Err(vk::ERROR_OUT_OF_DEVICE_MEMORY);
+        }
+        if allocate_info.allocationSize == 0 || allocate_info.allocationSize > usize::MAX as u64 {
+            return Err(vk::ERROR_OUT_OF_DEVICE_MEMORY);
+        }
+        let size = allocate_info.allocationSize as usize;
+        let layout = Layout::from_size_align(size, MEMORY_ALIGNMENT)
+            .map_err(|_| vk::ERROR_OUT_OF_DEVICE_MEMORY)?;
+        let data = unsafe { alloc::alloc_zeroed(layout) };
+        if data.is_null() {
+            return Err(vk::ERROR_OUT_OF_DEVICE_MEMORY);
+        }
+        Ok(DeviceMemory {
+            data: data,
+            size: size,
+        })
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the address of the byte at `offset`, which has to be within the allocation.
+    pub fn at(&self, offset: vk::DeviceSize) -> *mut u8 {
+        debug_assert!(offset <= self.size as u64);
+        unsafe { self.data.offset(offset as isize) }
+    }
+}
+
+impl Drop for DeviceMemory {
+    fn drop(&mut self) {
+        let layout = Layout::from_size_align(self.size, MEMORY_ALIGNMENT).unwrap();
+        unsafe { alloc::dealloc(self.data, layout) };
+    }
+}
+
+/// A range of device memory that shaders and transfers access once memory is bound to it.
+#[derive(Debug)]
+pub struct Buffer {
+    pub size: vk::DeviceSize,
+    pub usage: vk::BufferUsageFlags,
+    /// The first byte of the buffer, null until memory is bound.
+    data: *mut u8,
+}
+
+impl Buffer {
+    pub fn from_create_info(
+        create_info: &vk::BufferCreateInfo,
+        alloc: *const vk::AllocationCallbacks,
+    ) -> Result<Self, vk::Result> {
+        debug_assert_eq!(create_info.sType, vk::STRUCTURE_TYPE_BUFFER_CREATE_INFO);
+        if !alloc.is_null() {
+            warn!("Custom allocators not supported by driver");
+            return Err(vk::ERROR_INITIALIZATION_FAILED);
+        }
+        if create_info.flags != 0 {
+            warn!("Sparse buffers not supported by driver");
+            return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+        }
+        Ok(Buffer {
+            size: create_info.size,
+            usage: create_info.usage,
+            data: ptr::null_mut(),
+        })
+    }
+
+    pub fn memory_requirements(&self) -> vk::MemoryRequirements {
+        vk::MemoryRequirements {
+            size: (self.size + BUFFER_ALIGNMENT - 1) / BUFFER_ALIGNMENT * BUFFER_ALIGNMENT,
+            alignment: BUFFER_ALIGNMENT,
+            memoryTypeBits: MEMORY_TYPE_BITS,
+        }
+    }
+
+    /// Binds the buffer to `memory` at `offset`, which has to leave room for the whole buffer.
+    pub fn bind(
+        &mut self,
+        memory: &DeviceMemory,
+        offset: vk::DeviceSize,
+    ) -> Result<(), vk::Result> {
+        if !self.data.is_null() {
+            warn!("Buffer is already bound to memory");
+            return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+        }
+        if offset & (BUFFER_ALIGNMENT - 1) != 0 || offset + self.size > memory.size() as u64 {
+            warn!(
+                "Buffer of {} bytes does not fit at offset {} of {} bytes of memory",
+                self.size,
+                offset,
+                memory.size()
+            );
+            return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+        }
+        self.data = memory.at(offset);
+        Ok(())
+    }
+
+    /// Returns the address of the byte at `offset` of the buffer, null if no memory is bound.
+    pub fn at(&self, offset: vk::DeviceSize) -> *mut u8 {
+        if self.data.is_null() {
+            return ptr::null_mut();
+        }
+        debug_assert!(offset <= self.size);
+        unsafe { self.data.offset(offset as isize) }
+    }
+}
@@ -1,16 +1,33 @@
+// Functions keep the parameters of the commands they implement, whether they use them or not
+#![allow(unused_variables)]
+
 use std::ptr;
 use std::u32;
+use spirv_llvm::abi;
 use ffi_types as vk;
-use dispatch::{PhysicalDevice, Device, VkLoaderDataUnion};
+use clipper;
+use compute;
+use descriptor;
+use image;
+use pipeline;
+use pipeline_cache;
+use rasterizer;
+use render_pass;
+use dispatch::{PhysicalDevice, Device};
 use version::Version;
 
+/// The `vendorID` of the device, which has no PCI vendor to take one from.
+pub const VENDOR_ID: u32 = 0;
+/// The `deviceID` of the device.
+pub const DEVICE_ID: u32 = 0;
+
 impl PhysicalDevice {
     fn vendor_id(&self) -> u32 {
-        0
+        VENDOR_ID
     }
 
     fn device_id(&self) -> u32 {
-        0
+        DEVICE_ID
     }
 }
 
@@ -26,6 +43,7 @@ pub fn get_physical_device_properties(
     properties.vendorID = phys_device.vendor_id();
     properties.deviceID = phys_device.device_id();
     properties.deviceType = vk::PHYSICAL_DEVICE_TYPE_CPU;
+    properties.pipelineCacheUUID = pipeline_cache::uuid();
     // TODO we can probably return the real processor name here
     unsafe {
         ptr::copy_nonoverlapping(
@@ -46,12 +64,12 @@ pub fn get_physical_device_properties(
         maxTexelBufferElements: 128 * 1024 * 1024,
         maxUniformBufferRange: u32::MAX,
         maxStorageBufferRange: u32::MAX,
-        maxPushConstantsSize: 0, //MAX_PUSH_CONSTANTS_SIZE
+        maxPushConstantsSize: pipeline::MAX_PUSH_CONSTANTS_SIZE,
         maxMemoryAllocationCount: u32::MAX,
         maxSamplerAllocationCount: 64 * 1024,
         bufferImageGranularity: 64,
         sparseAddressSpaceSize: 0,
-        maxBoundDescriptorSets: 0, // MAX_SETS
+        maxBoundDescriptorSets: descriptor::MAX_BOUND_DESCRIPTOR_SETS,
         maxPerStageDescriptorSamplers: 64,
         maxPerStageDescriptorUniformBuffers: 64,
         maxPerStageDescriptorStorageBuffers: 64,
@@ -72,9 +90,33 @@ pub fn get_physical_device_properties(
         maxVertexInputAttributeOffset: 2047,
         maxVertexInputBindingStride: 2048,
         maxVertexOutputComponents: 128,
+        maxFragmentInputComponents: 128,
         maxTessellationGenerationLevel: 0,
         maxTessellationPatchSize: 0,
         maxTessellationControlPerVertexInputComponents: 0,
+        maxComputeSharedMemorySize: compute::MAX_SHARED_MEMORY,
+        maxComputeWorkGroupCount: compute::MAX_WORKGROUP_COUNT,
+        maxComputeWorkGroupInvocations: compute::MAX_WORKGROUP_INVOCATIONS,
+        maxComputeWorkGroupSize: compute::MAX_WORKGROUP_SIZE,
+        maxViewports: 1,
+        maxViewportDimensions: [(1 << 14), (1 << 14)],
+        viewportBoundsRange: clipper::VIEWPORT_BOUNDS,
+        subPixelPrecisionBits: rasterizer::SUB_PIXEL_BITS,
+        minTexelBufferOffsetAlignment: descriptor::MIN_OFFSET_ALIGNMENT,
+        minUniformBufferOffsetAlignment: descriptor::MIN_OFFSET_ALIGNMENT,
+        minStorageBufferOffsetAlignment: descriptor::MIN_OFFSET_ALIGNMENT,
+        maxFramebufferWidth: render_pass::MAX_FRAMEBUFFER_SIZE,
+        maxFramebufferHeight: render_pass::MAX_FRAMEBUFFER_SIZE,
+        maxFramebufferLayers: render_pass::MAX_FRAMEBUFFER_LAYERS,
+        framebufferColorSampleCounts: image::SAMPLE_COUNTS,
+        framebufferDepthSampleCounts: image::SAMPLE_COUNTS,
+        framebufferStencilSampleCounts: image::SAMPLE_COUNTS,
+        framebufferNoAttachmentsSampleCounts: image::SAMPLE_COUNTS,
+        maxColorAttachments: render_pass::MAX_COLOR_ATTACHMENTS,
+        maxClipDistances: abi::MAX_CLIP_DISTANCES as u32,
+        maxCullDistances: abi::MAX_CULL_DISTANCES as u32,
+        maxCombinedClipAndCullDistances: (abi::MAX_CLIP_DISTANCES +
+            abi::MAX_CULL_DISTANCES) as u32,
         ..Default::default()
     };
     properties.limits = limits;
@@ -90,12 +132,8 @@ pub fn get_physical_device_queue_family_properties(
         "Calling get_physical_device_queue_family_properties with count: {}",
         *property_count
     );
-    if properties.is_none() {
-        *property_count = 1;
-        vk::SUCCESS
-    } else {
-        let properties = properties.unwrap();
-        debug_assert!(properties.len() >= 1);
+    if let Some(properties) = properties {
+        debug_assert!(!properties.is_empty());
         properties[0] = vk::QueueFamilyProperties {
             queueFlags: vk::QUEUE_GRAPHICS_BIT | vk::QUEUE_COMPUTE_BIT | vk::QUEUE_TRANSFER_BIT,
             queueCount: 1,
@@ -107,6 +145,9 @@ pub fn get_physical_device_queue_family_properties(
             },
         };
         vk::SUCCESS
+    } else {
+        *property_count = 1;
+        vk::SUCCESS
     }
 }
 
@@ -118,6 +159,9 @@ pub fn get_physical_device_features(
     debug!("Calling get_physical_device_features");
     *features = vk::PhysicalDeviceFeatures {
         robustBufferAccess: vk::TRUE,
+        depthClamp: vk::TRUE,
+        shaderClipDistance: vk::TRUE,
+        shaderCullDistance: vk::TRUE,
         ..Default::default()
     };
     vk::SUCCESS
@@ -167,17 +211,16 @@ pub fn get_physical_device_surface_formats_khr(
         "Calling get_physical_device_surface_formats_khr with param: count: {}",
         *surface_format_count
     );
-    if surface_formats.is_none() {
-        *surface_format_count = 1;
-        vk::SUCCESS
-    } else {
-        let surface_formats = surface_formats.unwrap();
-        debug_assert!(surface_formats.len() >= 1);
+    if let Some(surface_formats) = surface_formats {
+        debug_assert!(!surface_formats.is_empty());
         surface_formats[0] = vk::SurfaceFormatKHR {
             format: vk::FORMAT_R64G64B64A64_UINT,
             colorSpace: vk::COLORSPACE_SRGB_NONLINEAR_KHR,
         };
         vk::SUCCESS
+    } else {
+        *surface_format_count = 1;
+        vk::SUCCESS
     }
 }
 
@@ -228,15 +271,12 @@ pub fn get_physical_device_surface_present_modes_khr(
     const SUPPORTED_PRESENT_MODES: &'static [vk::PresentModeKHR] =
         &[vk::PRESENT_MODE_FIFO_KHR, vk::PRESENT_MODE_MAILBOX_KHR];
 
-    if present_modes.is_none() {
+    if let Some(present_modes) = present_modes {
+        debug_assert!(*present_mode_count >= SUPPORTED_PRESENT_MODES.len() as u32);
+        present_modes[..SUPPORTED_PRESENT_MODES.len()].copy_from_slice(SUPPORTED_PRESENT_MODES);
         *present_mode_count = SUPPORTED_PRESENT_MODES.len() as u32;
         vk::SUCCESS
     } else {
-        debug_assert!(*present_mode_count >= SUPPORTED_PRESENT_MODES.len() as u32);
-        let present_modes = present_modes.unwrap();
-        for i in 0..SUPPORTED_PRESENT_MODES.len() {
-            present_modes[i] = SUPPORTED_PRESENT_MODES[i];
-        }
         *present_mode_count = SUPPORTED_PRESENT_MODES.len() as u32;
         vk::SUCCESS
     }
@@ -248,5 +288,6 @@ pub fn create_device(
     alloc: *const vk::AllocationCallbacks,
 ) -> Result<Box<Device>, vk::Result> {
     debug!("Calling create_device");
-    Device::from_create_info(create_info, alloc).map(|device| Box::new(device))
+    let debug_report = phys_device.debug_report().clone();
+    Device::from_create_info(create_info, alloc, debug_report).map(|device| Box::new(device))
 }
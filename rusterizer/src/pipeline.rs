@@ -0,0 +1,1072 @@
+//! Pipelines and their layouts.
+//!
+//! A pipeline copies all state the application passes on creation, so neither the create info
+//! nor the shader modules it refers to have to outlive it. Its shader stages are compiled right
+//! away, drawing only has to call them.
+use std::ffi::CStr;
+use std::slice;
+
+use spirv_llvm::{EntryPoint, EntryPointInfo, InterfaceVariable, ScalarType};
+use spirv_llvm::abi::Slot;
+use ffi_types as vk;
+use compute;
+use debug_report::DebugReport;
+use descriptor::MAX_BOUND_DESCRIPTOR_SETS;
+use image::SAMPLE_COUNTS;
+use pipeline_cache::PipelineCache;
+use render_pass::RenderPass;
+use shader::{Shader, ShaderModule};
+
+/// A compiled pipeline, bound to a command buffer for draws or dispatches.
+// Pipelines are boxed as handles, their size does not matter
+#[allow(clippy::large_enum_variant)]
+pub enum Pipeline {
+    Graphics(GraphicsPipeline),
+    Compute(ComputePipeline),
+}
+
+/// `maxPushConstantsSize`, the bytes of push constants a command buffer keeps.
+pub const MAX_PUSH_CONSTANTS_SIZE: u32 = 256;
+
+/// A push constant range of a pipeline layout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PushConstantRange {
+    pub stages: vk::ShaderStageFlags,
+    pub offset: u32,
+    pub size: u32,
+}
+
+/// The descriptor set layouts and push constant ranges the shaders of a pipeline can access.
+#[derive(Debug)]
+pub struct PipelineLayout {
+    pub set_layouts: Vec<vk::DescriptorSetLayout>,
+    pub push_constant_ranges: Vec<PushConstantRange>,
+}
+
+impl PipelineLayout {
+    pub fn from_create_info(
+        create_info: &vk::PipelineLayoutCreateInfo,
+        alloc: *const vk::AllocationCallbacks,
+        debug_report: &DebugReport,
+    ) -> Result<Self, vk::Result> {
+        debug_assert_eq!(
+            create_info.sType,
+            vk::STRUCTURE_TYPE_PIPELINE_LAYOUT_CREATE_INFO
+        );
+        debug_assert!(create_info.pNext.is_null());
+        if !alloc.is_null() {
+            warn!("Custom allocators not supported by driver");
+            return Err(vk::ERROR_INITIALIZATION_FAILED);
+        }
+        let errors = Errors::layout(debug_report);
+        if create_info.setLayoutCount > MAX_BOUND_DESCRIPTOR_SETS {
+            return Err(errors.invalid(&format!(
+                "{} descriptor sets, at most {} are supported",
+                create_info.setLayoutCount,
+                MAX_BOUND_DESCRIPTOR_SETS
+            )));
+        }
+        let set_layouts = unsafe { array(create_info.pSetLayouts, create_info.setLayoutCount) };
+        let ranges = unsafe {
+            array(
+                create_info.pPushConstantRanges,
+                create_info.pushConstantRangeCount,
+            )
+        };
+        let mut stages = 0;
+        for range in ranges {
+            if !push_constants_fit(range.offset, range.size) || range.stageFlags == 0 {
+                return Err(errors.invalid(&format!(
+                    "Push constant range of {} bytes at offset {} is invalid",
+                    range.size,
+                    range.offset
+                )));
+            }
+            if stages & range.stageFlags != 0 {
+                return Err(errors.invalid(&format!(
+                    "Stages {:#x} are in two push constant ranges",
+                    stages & range.stageFlags
+                )));
+            }
+            stages |= range.stageFlags;
+        }
+        Ok(PipelineLayout {
+            set_layouts: set_layouts.to_vec(),
+            push_constant_ranges: ranges
+                .iter()
+                .map(|range| {
+                    PushConstantRange {
+                        stages: range.stageFlags,
+                        offset: range.offset,
+                        size: range.size,
+                    }
+                })
+                .collect(),
+        })
+    }
+
+    /// Whether `stages` may update the push constants from `offset` to `offset + size`: every
+    /// stage has a range with all of the bytes, and the stages include those of every range
+    /// with any of them.
+    pub fn updates_push_constants(
+        &self,
+        stages: vk::ShaderStageFlags,
+        offset: u32,
+        size: u32,
+    ) -> bool {
+        if !push_constants_fit(offset, size) || stages == 0 {
+            return false;
+        }
+        let end = offset + size;
+        let mut covered = 0;
+        for range in &self.push_constant_ranges {
+            if range.offset >= end || range.offset + range.size <= offset {
+                continue;
+            }
+            if range.stages & !stages != 0 {
+                return false;
+            }
+            if range.offset <= offset && range.offset + range.size >= end {
+                covered |= range.stages;
+            }
+        }
+        covered == stages
+    }
+}
+
+/// Whether `size` bytes at `offset` are a valid range of push constants.
+fn push_constants_fit(offset: u32, size: u32) -> bool {
+    offset & 3 == 0 && size & 3 == 0 && size != 0 &&
+        u64::from(offset) + u64::from(size) <= u64::from(MAX_PUSH_CONSTANTS_SIZE)
+}
+
+/// The entry point of a compiled shader module that runs one stage of a pipeline.
+pub struct Stage {
+    shader: Shader,
+    name: String,
+    stage: vk::ShaderStageFlagBits,
+}
+
+impl Stage {
+    fn from_create_info(
+        create_info: &vk::PipelineShaderStageCreateInfo,
+        cache: Option<&PipelineCache>,
+        errors: &Errors,
+    ) -> Result<Self, vk::Result> {
+        debug_assert_eq!(
+            create_info.sType,
+            vk::STRUCTURE_TYPE_PIPELINE_SHADER_STAGE_CREATE_INFO
+        );
+        let module = unsafe { &*(create_info.module as *const ShaderModule) };
+        let name = unsafe { CStr::from_ptr(create_info.pName) };
+        let name = name.to_string_lossy().into_owned();
+        let specialization = unsafe { create_info.pSpecializationInfo.as_ref() };
+        let shader = module.compile(specialization, cache)?;
+        if shader.entry_point(&name, create_info.stage).is_none() {
+            return Err(errors.invalid_shader(&format!(
+                "Shader module has no entry point {} for stage {:#x}",
+                name,
+                create_info.stage
+            )));
+        }
+        Ok(Stage {
+            shader: shader,
+            name: name,
+            stage: create_info.stage,
+        })
+    }
+
+    /// The compiled entry point, which runs a batch of invocations.
+    pub fn entry_point(&self) -> EntryPoint<'_> {
+        self.shader.entry_point(&self.name, self.stage).expect(
+            "entry point disappeared after pipeline creation",
+        )
+    }
+
+    /// The execution modes and interface of the entry point.
+    pub fn info(&self) -> &EntryPointInfo {
+        self.shader.info(&self.name, self.stage).expect(
+            "entry point disappeared after pipeline creation",
+        )
+    }
+}
+
+/// How the components of a vertex attribute are stored in its vertex buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ComponentFormat {
+    Float32,
+    Sint32,
+    Uint32,
+    Unorm8,
+    Snorm8,
+    Uint8,
+    Sint8,
+}
+
+/// The formats vertex attributes can be fetched from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VertexFormat {
+    pub component: ComponentFormat,
+    pub components: u32,
+    /// Red and blue are swapped in memory.
+    pub bgra: bool,
+}
+
+impl VertexFormat {
+    pub fn from_format(format: vk::Format) -> Option<Self> {
+        use self::ComponentFormat::*;
+        let (component, components, bgra) = match format {
+            vk::FORMAT_R32_SFLOAT => (Float32, 1, false),
+            vk::FORMAT_R32G32_SFLOAT => (Float32, 2, false),
+            vk::FORMAT_R32G32B32_SFLOAT => (Float32, 3, false),
+            vk::FORMAT_R32G32B32A32_SFLOAT => (Float32, 4, false),
+            vk::FORMAT_R32_SINT => (Sint32, 1, false),
+            vk::FORMAT_R32G32_SINT => (Sint32, 2, false),
+            vk::FORMAT_R32G32B32_SINT => (Sint32, 3, false),
+            vk::FORMAT_R32G32B32A32_SINT => (Sint32, 4, false),
+            vk::FORMAT_R32_UINT => (Uint32, 1, false),
+            vk::FORMAT_R32G32_UINT => (Uint32, 2, false),
+            vk::FORMAT_R32G32B32_UINT => (Uint32, 3, false),
+            vk::FORMAT_R32G32B32A32_UINT => (Uint32, 4, false),
+            vk::FORMAT_R8G8B8A8_UNORM => (Unorm8, 4, false),
+            vk::FORMAT_R8G8B8A8_SNORM => (Snorm8, 4, false),
+            vk::FORMAT_R8G8B8A8_UINT => (Uint8, 4, false),
+            vk::FORMAT_R8G8B8A8_SINT => (Sint8, 4, false),
+            vk::FORMAT_B8G8R8A8_UNORM => (Unorm8, 4, true),
+            _ => return None,
+        };
+        Some(VertexFormat {
+            component: component,
+            components: components,
+            bgra: bgra,
+        })
+    }
+
+    /// Size of an attribute in bytes.
+    pub fn size(&self) -> u32 {
+        match self.component {
+            ComponentFormat::Float32 | ComponentFormat::Sint32 | ComponentFormat::Uint32 => {
+                4 * self.components
+            }
+            _ => self.components,
+        }
+    }
+
+    /// Whether the shader reads the attribute as floats rather than integers.
+    pub fn is_float(&self) -> bool {
+        match self.component {
+            ComponentFormat::Float32 | ComponentFormat::Unorm8 | ComponentFormat::Snorm8 => true,
+            _ => false,
+        }
+    }
+
+    /// The value the shader reads from the `size()` bytes of an attribute. Components the
+    /// format does not have are 0, except for the fourth, which is 1.
+    pub fn decode(&self, bytes: &[u8]) -> Slot {
+        let one = if self.is_float() { 1.0f32.to_bits() } else { 1 };
+        let mut slot = Slot([0, 0, 0, one]);
+        for (index, value) in slot.0[..self.components as usize].iter_mut().enumerate() {
+            *value = match self.component {
+                ComponentFormat::Float32 | ComponentFormat::Sint32 | ComponentFormat::Uint32 => {
+                    let word = [
+                        bytes[index * 4],
+                        bytes[index * 4 + 1],
+                        bytes[index * 4 + 2],
+                        bytes[index * 4 + 3],
+                    ];
+                    u32::from_le_bytes(word)
+                }
+                ComponentFormat::Unorm8 => (f32::from(bytes[index]) / 255.0).to_bits(),
+                ComponentFormat::Snorm8 => {
+                    (f32::from(bytes[index] as i8) / 127.0).max(-1.0).to_bits()
+                }
+                ComponentFormat::Uint8 => u32::from(bytes[index]),
+                ComponentFormat::Sint8 => bytes[index] as i8 as u32,
+            };
+        }
+        if self.bgra {
+            slot.0.swap(0, 2);
+        }
+        slot
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VertexBinding {
+    pub binding: u32,
+    pub stride: u32,
+    /// The binding advances once per instance instead of once per vertex.
+    pub per_instance: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VertexAttribute {
+    pub location: u32,
+    pub binding: u32,
+    pub format: VertexFormat,
+    pub offset: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DepthBias {
+    pub constant_factor: f32,
+    pub clamp: f32,
+    pub slope_factor: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RasterizationState {
+    pub depth_clamp: bool,
+    /// Primitives are discarded before rasterization, only the vertex shader runs.
+    pub discard: bool,
+    pub cull_mode: vk::CullModeFlags,
+    pub front_face: vk::FrontFace,
+    pub depth_bias: Option<DepthBias>,
+    pub line_width: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MultisampleState {
+    pub samples: vk::SampleCountFlagBits,
+    pub sample_mask: u32,
+    pub alpha_to_coverage: bool,
+}
+
+impl Default for MultisampleState {
+    fn default() -> Self {
+        MultisampleState {
+            samples: vk::SAMPLE_COUNT_1_BIT,
+            sample_mask: !0,
+            alpha_to_coverage: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DepthStencilState {
+    pub depth_test: bool,
+    pub depth_write: bool,
+    pub depth_compare: vk::CompareOp,
+    pub stencil_test: bool,
+    pub front: vk::StencilOpState,
+    pub back: vk::StencilOpState,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ColorBlendState {
+    /// One per color attachment of the subpass.
+    pub attachments: Vec<vk::PipelineColorBlendAttachmentState>,
+    pub blend_constants: [f32; 4],
+}
+
+/// The set of `vk::DynamicState`s that are set by commands instead of the pipeline.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DynamicStates(u32);
+
+impl DynamicStates {
+    pub fn contains(&self, state: vk::DynamicState) -> bool {
+        state < 32 && self.0 & (1 << state) != 0
+    }
+}
+
+/// A pipeline for draws: the vertex and fragment shaders and the fixed-function state around
+/// them.
+///
+/// State that is dynamic or ignored keeps its default, e.g. there are no viewports if they are
+/// set by `vkCmdSetViewport`, and depth and stencil tests are off without a depth stencil state.
+pub struct GraphicsPipeline {
+    pub vertex: Stage,
+    pub fragment: Option<Stage>,
+    pub bindings: Vec<VertexBinding>,
+    pub attributes: Vec<VertexAttribute>,
+    pub topology: vk::PrimitiveTopology,
+    pub primitive_restart: bool,
+    pub viewports: Vec<vk::Viewport>,
+    pub scissors: Vec<vk::Rect2D>,
+    pub rasterization: RasterizationState,
+    pub multisample: MultisampleState,
+    pub depth_stencil: Option<DepthStencilState>,
+    pub color_blend: ColorBlendState,
+    pub dynamic: DynamicStates,
+    /// The inputs of the fragment shader, which the rasterizer interpolates from the outputs of
+    /// the vertex shader in the same Locations.
+    pub varyings: Vec<InterfaceVariable>,
+}
+
+impl GraphicsPipeline {
+    pub fn from_create_info(
+        create_info: &vk::GraphicsPipelineCreateInfo,
+        cache: Option<&PipelineCache>,
+        alloc: *const vk::AllocationCallbacks,
+        debug_report: &DebugReport,
+    ) -> Result<Self, vk::Result> {
+        debug_assert_eq!(
+            create_info.sType,
+            vk::STRUCTURE_TYPE_GRAPHICS_PIPELINE_CREATE_INFO
+        );
+        if !alloc.is_null() {
+            warn!("Custom allocators not supported by driver");
+            return Err(vk::ERROR_INITIALIZATION_FAILED);
+        }
+        let errors = Errors::pipeline(debug_report);
+        if create_info.renderPass == 0 {
+            return Err(errors.invalid("Graphics pipelines need a render pass"));
+        }
+        let render_pass = unsafe { &*(create_info.renderPass as *const RenderPass) };
+        let (color_count, samples) = render_pass.subpass_targets(create_info.subpass).ok_or_else(
+            || errors.invalid(&format!("Render pass has no subpass {}", create_info.subpass)),
+        )?;
+
+        let dynamic = match unsafe { create_info.pDynamicState.as_ref() } {
+            Some(state) => {
+                let states = unsafe { array(state.pDynamicStates, state.dynamicStateCount) };
+                let mut dynamic = DynamicStates::default();
+                for &state in states {
+                    if state > vk::DYNAMIC_STATE_STENCIL_REFERENCE {
+                        return Err(errors.invalid(&format!("Unknown dynamic state {}", state)));
+                    }
+                    dynamic.0 |= 1 << state;
+                }
+                dynamic
+            }
+            None => DynamicStates::default(),
+        };
+
+        let mut vertex = None;
+        let mut fragment = None;
+        for stage in unsafe { array(create_info.pStages, create_info.stageCount) } {
+            let slot = match stage.stage {
+                vk::SHADER_STAGE_VERTEX_BIT => &mut vertex,
+                vk::SHADER_STAGE_FRAGMENT_BIT => &mut fragment,
+                _ => {
+                    return Err(errors.invalid(
+                        &format!("Shader stage {:#x} not supported", stage.stage),
+                    ))
+                }
+            };
+            if slot.is_some() {
+                return Err(errors.invalid(
+                    &format!("Shader stage {:#x} given twice", stage.stage),
+                ));
+            }
+            *slot = Some(Stage::from_create_info(stage, cache, &errors)?);
+        }
+        let vertex = vertex.ok_or_else(|| {
+            errors.invalid("Graphics pipelines need a vertex shader")
+        })?;
+
+        let vertex_input = unsafe { create_info.pVertexInputState.as_ref() }.ok_or_else(|| {
+            errors.invalid("Graphics pipelines need a vertex input state")
+        })?;
+        let (bindings, attributes) = vertex_input_state(vertex_input, &errors)?;
+        for input in &vertex.info().inputs {
+            check_vertex_input(input, &attributes).map_err(
+                |message| errors.invalid_shader(&message),
+            )?;
+        }
+
+        let input_assembly = unsafe { create_info.pInputAssemblyState.as_ref() }.ok_or_else(
+            || errors.invalid("Graphics pipelines need an input assembly state"),
+        )?;
+        let topology = input_assembly.topology;
+        let primitive_restart = input_assembly.primitiveRestartEnable != vk::FALSE;
+        match topology {
+            vk::PRIMITIVE_TOPOLOGY_POINT_LIST |
+            vk::PRIMITIVE_TOPOLOGY_LINE_LIST |
+            vk::PRIMITIVE_TOPOLOGY_TRIANGLE_LIST if primitive_restart => {
+                return Err(errors.invalid("Primitive restart is only allowed for strips and fans"))
+            }
+            vk::PRIMITIVE_TOPOLOGY_POINT_LIST |
+            vk::PRIMITIVE_TOPOLOGY_LINE_LIST |
+            vk::PRIMITIVE_TOPOLOGY_LINE_STRIP |
+            vk::PRIMITIVE_TOPOLOGY_TRIANGLE_LIST |
+            vk::PRIMITIVE_TOPOLOGY_TRIANGLE_STRIP |
+            vk::PRIMITIVE_TOPOLOGY_TRIANGLE_FAN => (),
+            // Adjacency needs geometry shaders and patches tessellation
+            _ => {
+                return Err(errors.invalid(
+                    &format!("Primitive topology {} not supported", topology),
+                ))
+            }
+        }
+
+        let rasterization = unsafe { create_info.pRasterizationState.as_ref() }.ok_or_else(
+            || errors.invalid("Graphics pipelines need a rasterization state"),
+        )?;
+        let rasterization = rasterization_state(rasterization, dynamic, &errors)?;
+
+        // Without rasterization, the state of the later stages is ignored and may be garbage
+        let rasterizing = !rasterization.discard;
+        let (viewports, scissors) = if rasterizing {
+            let state = unsafe { create_info.pViewportState.as_ref() }.ok_or_else(|| {
+                errors.invalid("Rasterizing pipelines need a viewport state")
+            })?;
+            viewport_state(state, dynamic, &errors)?
+        } else {
+            (Vec::new(), Vec::new())
+        };
+        let multisample = if rasterizing {
+            let state = unsafe { create_info.pMultisampleState.as_ref() }.ok_or_else(|| {
+                errors.invalid("Rasterizing pipelines need a multisample state")
+            })?;
+            multisample_state(state, &errors)?
+        } else {
+            MultisampleState::default()
+        };
+        let depth_stencil = match unsafe { create_info.pDepthStencilState.as_ref() } {
+            Some(state) if rasterizing => Some(depth_stencil_state(state, &errors)?),
+            _ => None,
+        };
+        let color_blend = match unsafe { create_info.pColorBlendState.as_ref() } {
+            Some(state) if rasterizing => color_blend_state(state, &errors)?,
+            // Subpasses without color attachments need none
+            _ => ColorBlendState::default(),
+        };
+        if rasterizing {
+            if color_blend.attachments.len() != color_count {
+                return Err(errors.invalid(&format!(
+                    "Subpass has {} color attachments but the color blend state {}",
+                    color_count,
+                    color_blend.attachments.len()
+                )));
+            }
+            if samples.map_or(false, |samples| samples != multisample.samples) {
+                return Err(errors.invalid(
+                    "Samples per pixel differ from those of the subpass attachments",
+                ));
+            }
+        }
+        let varyings = match fragment {
+            Some(ref fragment) if rasterizing => {
+                let inputs = &fragment.info().inputs;
+                link(&vertex.info().outputs, inputs).map_err(
+                    |message| errors.invalid_shader(&message),
+                )?;
+                inputs.clone()
+            }
+            _ => Vec::new(),
+        };
+
+        Ok(GraphicsPipeline {
+            vertex: vertex,
+            fragment: fragment,
+            bindings: bindings,
+            attributes: attributes,
+            topology: topology,
+            primitive_restart: primitive_restart,
+            viewports: viewports,
+            scissors: scissors,
+            rasterization: rasterization,
+            multisample: multisample,
+            depth_stencil: depth_stencil,
+            color_blend: color_blend,
+            dynamic: dynamic,
+            varyings: varyings,
+        })
+    }
+}
+
+/// A compute shader and the size of its workgroups.
+pub struct ComputePipeline {
+    pub stage: Stage,
+    pub local_size: [u32; 3],
+}
+
+impl ComputePipeline {
+    pub fn from_create_info(
+        create_info: &vk::ComputePipelineCreateInfo,
+        cache: Option<&PipelineCache>,
+        alloc: *const vk::AllocationCallbacks,
+        debug_report: &DebugReport,
+    ) -> Result<Self, vk::Result> {
+        debug_assert_eq!(
+            create_info.sType,
+            vk::STRUCTURE_TYPE_COMPUTE_PIPELINE_CREATE_INFO
+        );
+        if !alloc.is_null() {
+            warn!("Custom allocators not supported by driver");
+            return Err(vk::ERROR_INITIALIZATION_FAILED);
+        }
+        let errors = Errors::pipeline(debug_report);
+        if create_info.stage.stage != vk::SHADER_STAGE_COMPUTE_BIT {
+            return Err(errors.invalid(&format!(
+                "Shader stage {:#x} given for a compute pipeline",
+                create_info.stage.stage
+            )));
+        }
+        let stage = Stage::from_create_info(&create_info.stage, cache, &errors)?;
+
+        let local_size = match stage.info().modes.local_size {
+            Some(local_size) => local_size,
+            None => return Err(errors.invalid_shader("Compute shader has no LocalSize")),
+        };
+        let invocations = local_size.iter().fold(1u64, |product, &size| product * size as u64);
+        let too_large = local_size.iter().zip(&compute::MAX_WORKGROUP_SIZE).any(
+            |(size, max)| size > max,
+        );
+        if invocations == 0 || invocations > compute::MAX_WORKGROUP_INVOCATIONS as u64 ||
+            too_large
+        {
+            return Err(errors.invalid_shader(
+                &format!("Workgroup size {:?} not supported", local_size),
+            ));
+        }
+        let memory = stage.entry_point().workgroup_memory();
+        if memory > compute::MAX_SHARED_MEMORY as usize {
+            return Err(errors.invalid_shader(&format!(
+                "Workgroup variables take {} bytes, at most {} are supported",
+                memory,
+                compute::MAX_SHARED_MEMORY
+            )));
+        }
+
+        Ok(ComputePipeline {
+            stage: stage,
+            local_size: local_size,
+        })
+    }
+}
+
+fn vertex_input_state(
+    state: &vk::PipelineVertexInputStateCreateInfo,
+    errors: &Errors,
+) -> Result<(Vec<VertexBinding>, Vec<VertexAttribute>), vk::Result> {
+    debug_assert_eq!(
+        state.sType,
+        vk::STRUCTURE_TYPE_PIPELINE_VERTEX_INPUT_STATE_CREATE_INFO
+    );
+    let mut bindings = Vec::new();
+    for binding in unsafe {
+        array(
+            state.pVertexBindingDescriptions,
+            state.vertexBindingDescriptionCount,
+        )
+    }
+    {
+        if bindings.iter().any(|other: &VertexBinding| other.binding == binding.binding) {
+            return Err(errors.invalid(
+                &format!("Vertex binding {} described twice", binding.binding),
+            ));
+        }
+        bindings.push(VertexBinding {
+            binding: binding.binding,
+            stride: binding.stride,
+            per_instance: binding.inputRate == vk::VERTEX_INPUT_RATE_INSTANCE,
+        });
+    }
+
+    let mut attributes = Vec::new();
+    for attribute in unsafe {
+        array(
+            state.pVertexAttributeDescriptions,
+            state.vertexAttributeDescriptionCount,
+        )
+    }
+    {
+        let format = VertexFormat::from_format(attribute.format).ok_or_else(|| {
+            errors.invalid(&format!(
+                "Vertex attribute format {} not supported",
+                attribute.format
+            ))
+        })?;
+        if !bindings.iter().any(|binding| binding.binding == attribute.binding) {
+            return Err(errors.invalid(&format!(
+                "Vertex attribute {} uses undescribed binding {}",
+                attribute.location,
+                attribute.binding
+            )));
+        }
+        if attributes.iter().any(|other: &VertexAttribute| other.location == attribute.location) {
+            return Err(errors.invalid(
+                &format!("Vertex attribute {} described twice", attribute.location),
+            ));
+        }
+        attributes.push(VertexAttribute {
+            location: attribute.location,
+            binding: attribute.binding,
+            format: format,
+            offset: attribute.offset,
+        });
+    }
+    Ok((bindings, attributes))
+}
+
+/// Checks that the vertex attributes provide every Location of the vertex shader input
+/// `input`, as numbers of the kind it reads.
+fn check_vertex_input(
+    input: &InterfaceVariable,
+    attributes: &[VertexAttribute],
+) -> Result<(), String> {
+    for location in input.location..input.location + input.locations {
+        let attribute = attributes.iter().find(|attribute| attribute.location == location);
+        let float = match input.scalar {
+            Some(ScalarType::Float(32)) => true,
+            Some(ScalarType::Int(32, _)) => false,
+            _ => {
+                return Err(format!(
+                    "Vertex shader input at location {} has no 32 bit scalar type",
+                    location
+                ))
+            }
+        };
+        match attribute {
+            None => return Err(format!("No vertex attribute for location {}", location)),
+            Some(attribute) if attribute.format.is_float() != float => {
+                return Err(format!(
+                    "Vertex attribute {} does not have the numeric type of the shader input",
+                    location
+                ))
+            }
+            Some(_) => (),
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the vertex shader writes every component the fragment shader reads, with the
+/// same type. This is what connects the stages: inputs are read from the Locations the outputs
+/// of the same number are written to.
+fn link(outputs: &[InterfaceVariable], inputs: &[InterfaceVariable]) -> Result<(), String> {
+    for input in inputs {
+        for location in input.location..input.location + input.locations {
+            for component in input.component..input.component + input.components {
+                let output = outputs.iter().find(
+                    |output| output.covers(location, component),
+                );
+                let output = output.ok_or_else(|| {
+                    format!(
+                        "Fragment shader reads location {} component {}, which the vertex \
+                         shader does not write",
+                        location,
+                        component
+                    )
+                })?;
+                if !same_type(output.scalar, input.scalar) {
+                    return Err(format!(
+                        "Vertex shader output and fragment shader input at location {} \
+                         differ in type",
+                        location
+                    ));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether interface variables of scalar types `a` and `b` match. Signedness does not matter,
+/// the bits are passed on as they are.
+fn same_type(a: Option<ScalarType>, b: Option<ScalarType>) -> bool {
+    match (a, b) {
+        (Some(ScalarType::Float(a)), Some(ScalarType::Float(b))) |
+        (Some(ScalarType::Int(a, _)), Some(ScalarType::Int(b, _))) => a == b,
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+fn viewport_state(
+    state: &vk::PipelineViewportStateCreateInfo,
+    dynamic: DynamicStates,
+    errors: &Errors,
+) -> Result<(Vec<vk::Viewport>, Vec<vk::Rect2D>), vk::Result> {
+    debug_assert_eq!(
+        state.sType,
+        vk::STRUCTURE_TYPE_PIPELINE_VIEWPORT_STATE_CREATE_INFO
+    );
+    // Without the multiViewport feature, there is exactly one of each
+    if state.viewportCount != 1 || state.scissorCount != 1 {
+        return Err(errors.invalid("Exactly one viewport and scissor are supported"));
+    }
+    let viewports = if dynamic.contains(vk::DYNAMIC_STATE_VIEWPORT) {
+        Vec::new()
+    } else {
+        unsafe { array(state.pViewports, state.viewportCount) }.to_vec()
+    };
+    for viewport in &viewports {
+        if viewport.width <= 0.0 || viewport.height == 0.0 {
+            return Err(errors.invalid(&format!("Empty viewport {:?}", viewport)));
+        }
+    }
+    let scissors = if dynamic.contains(vk::DYNAMIC_STATE_SCISSOR) {
+        Vec::new()
+    } else {
+        unsafe { array(state.pScissors, state.scissorCount) }.to_vec()
+    };
+    for scissor in &scissors {
+        if scissor.offset.x < 0 || scissor.offset.y < 0 {
+            return Err(errors.invalid(&format!("Scissor {:?} starts off screen", scissor)));
+        }
+    }
+    Ok((viewports, scissors))
+}
+
+fn rasterization_state(
+    state: &vk::PipelineRasterizationStateCreateInfo,
+    dynamic: DynamicStates,
+    errors: &Errors,
+) -> Result<RasterizationState, vk::Result> {
+    debug_assert_eq!(
+        state.sType,
+        vk::STRUCTURE_TYPE_PIPELINE_RASTERIZATION_STATE_CREATE_INFO
+    );
+    // The features these need are not advertised
+    if state.polygonMode != vk::POLYGON_MODE_FILL {
+        return Err(errors.invalid(
+            &format!("Polygon mode {} not supported", state.polygonMode),
+        ));
+    }
+    if !dynamic.contains(vk::DYNAMIC_STATE_LINE_WIDTH) && state.lineWidth != 1.0 {
+        return Err(errors.invalid(
+            &format!("Line width {} not supported", state.lineWidth),
+        ));
+    }
+    let depth_bias = if state.depthBiasEnable != vk::FALSE {
+        Some(DepthBias {
+            constant_factor: state.depthBiasConstantFactor,
+            clamp: state.depthBiasClamp,
+            slope_factor: state.depthBiasSlopeFactor,
+        })
+    } else {
+        None
+    };
+    Ok(RasterizationState {
+        depth_clamp: state.depthClampEnable != vk::FALSE,
+        discard: state.rasterizerDiscardEnable != vk::FALSE,
+        cull_mode: state.cullMode,
+        front_face: state.frontFace,
+        depth_bias: depth_bias,
+        line_width: state.lineWidth,
+    })
+}
+
+fn multisample_state(
+    state: &vk::PipelineMultisampleStateCreateInfo,
+    errors: &Errors,
+) -> Result<MultisampleState, vk::Result> {
+    debug_assert_eq!(
+        state.sType,
+        vk::STRUCTURE_TYPE_PIPELINE_MULTISAMPLE_STATE_CREATE_INFO
+    );
+    let samples = state.rasterizationSamples;
+    if samples & SAMPLE_COUNTS == 0 || samples & (samples - 1) != 0 {
+        return Err(errors.invalid(&format!(
+            "{} samples per pixel not supported",
+            state.rasterizationSamples
+        )));
+    }
+    if state.sampleShadingEnable != vk::FALSE || state.alphaToOneEnable != vk::FALSE {
+        return Err(errors.invalid("Sample shading and alpha to one not supported"));
+    }
+    let sample_mask = match unsafe { state.pSampleMask.as_ref() } {
+        Some(&mask) => mask,
+        None => !0,
+    };
+    Ok(MultisampleState {
+        samples: state.rasterizationSamples,
+        sample_mask: sample_mask,
+        alpha_to_coverage: state.alphaToCoverageEnable != vk::FALSE,
+    })
+}
+
+fn depth_stencil_state(
+    state: &vk::PipelineDepthStencilStateCreateInfo,
+    errors: &Errors,
+) -> Result<DepthStencilState, vk::Result> {
+    debug_assert_eq!(
+        state.sType,
+        vk::STRUCTURE_TYPE_PIPELINE_DEPTH_STENCIL_STATE_CREATE_INFO
+    );
+    if state.depthBoundsTestEnable != vk::FALSE {
+        return Err(errors.invalid("Depth bounds test not supported"));
+    }
+    for &op in &[state.depthCompareOp, state.front.compareOp, state.back.compareOp] {
+        if op > vk::COMPARE_OP_ALWAYS {
+            return Err(errors.invalid(&format!("Unknown compare op {}", op)));
+        }
+    }
+    Ok(DepthStencilState {
+        depth_test: state.depthTestEnable != vk::FALSE,
+        depth_write: state.depthWriteEnable != vk::FALSE,
+        depth_compare: state.depthCompareOp,
+        stencil_test: state.stencilTestEnable != vk::FALSE,
+        front: state.front,
+        back: state.back,
+    })
+}
+
+fn color_blend_state(
+    state: &vk::PipelineColorBlendStateCreateInfo,
+    errors: &Errors,
+) -> Result<ColorBlendState, vk::Result> {
+    debug_assert_eq!(
+        state.sType,
+        vk::STRUCTURE_TYPE_PIPELINE_COLOR_BLEND_STATE_CREATE_INFO
+    );
+    if state.logicOpEnable != vk::FALSE {
+        return Err(errors.invalid("Logic ops not supported"));
+    }
+    let attachments = unsafe { array(state.pAttachments, state.attachmentCount) };
+    for attachment in attachments {
+        let factors = [
+            attachment.srcColorBlendFactor,
+            attachment.dstColorBlendFactor,
+            attachment.srcAlphaBlendFactor,
+            attachment.dstAlphaBlendFactor,
+        ];
+        // Factors of the second source need dual source blending
+        if factors.iter().any(|&factor| factor > vk::BLEND_FACTOR_SRC_ALPHA_SATURATE) {
+            return Err(errors.invalid(&format!("Blend factors {:?} not supported", factors)));
+        }
+        for &op in &[attachment.colorBlendOp, attachment.alphaBlendOp] {
+            if op > vk::BLEND_OP_MAX {
+                return Err(errors.invalid(&format!("Blend op {} not supported", op)));
+            }
+        }
+    }
+    Ok(ColorBlendState {
+        attachments: attachments.to_vec(),
+        blend_constants: state.blendConstants,
+    })
+}
+
+/// Reports why a pipeline or pipeline layout could not be created to the debug callbacks of
+/// the application.
+struct Errors<'a> {
+    debug_report: &'a DebugReport,
+    object: &'static str,
+    object_type: vk::DebugReportObjectTypeEXT,
+}
+
+impl<'a> Errors<'a> {
+    fn pipeline(debug_report: &'a DebugReport) -> Self {
+        Errors {
+            debug_report: debug_report,
+            object: "pipeline",
+            object_type: vk::DEBUG_REPORT_OBJECT_TYPE_PIPELINE_EXT,
+        }
+    }
+
+    fn layout(debug_report: &'a DebugReport) -> Self {
+        Errors {
+            debug_report: debug_report,
+            object: "pipeline layout",
+            object_type: vk::DEBUG_REPORT_OBJECT_TYPE_PIPELINE_LAYOUT_EXT,
+        }
+    }
+
+    /// Reports state that is invalid or that we do not support. Neither has an error code of
+    /// its own, so creation fails as if out of memory, which applications handle anyway.
+    fn invalid(&self, message: &str) -> vk::Result {
+        self.report(message);
+        vk::ERROR_OUT_OF_HOST_MEMORY
+    }
+
+    /// Reports shaders that do not fit together or to the vertex input state.
+    fn invalid_shader(&self, message: &str) -> vk::Result {
+        self.report(message);
+        vk::ERROR_INVALID_SHADER_NV
+    }
+
+    fn report(&self, message: &str) {
+        let message = format!("Could not create {}: {}", self.object, message);
+        warn!("{}", message);
+        self.debug_report.message(
+            vk::DEBUG_REPORT_ERROR_BIT_EXT,
+            self.object_type,
+            0,
+            "rusterizer",
+            &message,
+        );
+    }
+}
+
+/// Borrows the array of `count` elements at `pointer`, which may be null if `count` is 0.
+pub unsafe fn array<'a, T>(pointer: *const T, count: u32) -> &'a [T] {
+    if count == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(pointer, count as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+    use spirv_llvm::Interpolation;
+    use super::*;
+
+    /// A layout with push constant ranges of `(stages, offset, size)`.
+    fn layout(
+        ranges: &[(vk::ShaderStageFlags, u32, u32)],
+    ) -> Result<PipelineLayout, vk::Result> {
+        let ranges: Vec<vk::PushConstantRange> = ranges
+            .iter()
+            .map(|&(stages, offset, size)| {
+                vk::PushConstantRange {
+                    stageFlags: stages,
+                    offset: offset,
+                    size: size,
+                }
+            })
+            .collect();
+        let create_info = vk::PipelineLayoutCreateInfo {
+            sType: vk::STRUCTURE_TYPE_PIPELINE_LAYOUT_CREATE_INFO,
+            pNext: ptr::null(),
+            flags: 0,
+            setLayoutCount: 0,
+            pSetLayouts: ptr::null(),
+            pushConstantRangeCount: ranges.len() as u32,
+            pPushConstantRanges: ranges.as_ptr(),
+        };
+        PipelineLayout::from_create_info(&create_info, ptr::null(), &DebugReport::default())
+    }
+
+    fn variable(location: u32, component: u32, components: u32) -> InterfaceVariable {
+        InterfaceVariable {
+            location: location,
+            component: component,
+            locations: 1,
+            components: components,
+            scalar: Some(ScalarType::Float(32)),
+            interpolation: Interpolation::Smooth,
+        }
+    }
+
+    #[test]
+    fn links_by_location() {
+        let outputs = [variable(0, 0, 4), variable(1, 0, 2), variable(1, 2, 1)];
+        assert!(link(&outputs, &[variable(0, 0, 4), variable(1, 0, 3)]).is_ok());
+        // Fragment shaders may read less than the vertex shader writes
+        assert!(link(&outputs, &[variable(1, 1, 1)]).is_ok());
+        assert!(link(&outputs, &[variable(1, 0, 4)]).is_err());
+        assert!(link(&outputs, &[variable(2, 0, 1)]).is_err());
+
+        let mut int = variable(0, 0, 4);
+        int.scalar = Some(ScalarType::Int(32, true));
+        assert!(link(&outputs, &[int]).is_err());
+    }
+
+    #[test]
+    fn validates_push_constants() {
+        let vertex = vk::SHADER_STAGE_VERTEX_BIT;
+        let fragment = vk::SHADER_STAGE_FRAGMENT_BIT;
+        assert!(layout(&[(vertex, 0, MAX_PUSH_CONSTANTS_SIZE)]).is_ok());
+        assert!(layout(&[(vertex, 4, MAX_PUSH_CONSTANTS_SIZE)]).is_err());
+        assert!(layout(&[(vertex, 2, 8)]).is_err());
+        assert!(layout(&[(vertex, 0, 0)]).is_err());
+        assert!(layout(&[(0, 0, 4)]).is_err());
+        assert!(layout(&[(vertex, 0, 16), (vertex | fragment, 16, 16)]).is_err());
+
+        let layout = layout(&[(vertex, 0, 32), (fragment, 16, 32)]).unwrap();
+        assert!(layout.updates_push_constants(vertex, 0, 16));
+        assert!(layout.updates_push_constants(fragment, 32, 16));
+        // Bytes both ranges have need both stages
+        assert!(layout.updates_push_constants(vertex | fragment, 16, 16));
+        assert!(!layout.updates_push_constants(vertex, 16, 4));
+        assert!(!layout.updates_push_constants(vertex | fragment, 0, 16));
+        assert!(!layout.updates_push_constants(vertex, 28, 8));
+        assert!(!layout.updates_push_constants(fragment, 46, 2));
+        assert!(!layout.updates_push_constants(vertex, 0, 0));
+    }
+}
@@ -0,0 +1,288 @@
+//! Pipeline caches, which keep the native code of compiled shaders.
+//!
+//! Shaders are compiled the same no matter what else is in a pipeline, so the cache holds
+//! shaders rather than pipelines: the serialized `spirv_llvm::NativeCode` of every shader, keyed
+//! by a hash of everything that went into compiling it. The data of a cache starts with the
+//! header Vulkan requires and lists the entries after it, in the order of their keys:
+//!
+//! | Bytes | Content                                          |
+//! |-------|--------------------------------------------------|
+//! | 32    | Key                                              |
+//! | 32    | SHA-256 of the code, to notice corruption        |
+//! | 8     | Length of the code, least significant byte first |
+//! | n     | Code                                             |
+use std::collections::BTreeMap;
+use std::ffi::{CStr, OsStr};
+use std::fs;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::slice;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, UNIX_EPOCH};
+
+use libc;
+use ffi_types as vk;
+use hash::{self, Sha256};
+use physical_device::{DEVICE_ID, VENDOR_ID};
+
+/// Size of the header of the cache data, as it is in `headerSize`.
+const HEADER_SIZE: usize = 16 + vk::UUID_SIZE as usize;
+
+/// Changes whenever the way code is cached changes, so that old data is not misread.
+const FORMAT_VERSION: u64 = 1;
+
+/// The `pipelineCacheUUID` of the device. Data from another build of the driver may refer to
+/// runtime helpers or an ABI that changed, so the UUID changes whenever the driver is rebuilt.
+pub fn uuid() -> [u8; vk::UUID_SIZE as usize] {
+    static UUID: OnceLock<[u8; vk::UUID_SIZE as usize]> = OnceLock::new();
+    *UUID.get_or_init(|| {
+        let mut hasher = Sha256::new();
+        hasher.update(b"rusterizer ");
+        hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+        hasher.update_u64(FORMAT_VERSION);
+        match build_id() {
+            Some((size, modified)) => {
+                hasher.update_u64(size);
+                hasher.update_u64(modified.as_secs());
+                hasher.update_u64(u64::from(modified.subsec_nanos()));
+            }
+            None => warn!("Could not find the driver library, caches may outlive its builds"),
+        }
+        let mut uuid = [0; vk::UUID_SIZE as usize];
+        uuid.copy_from_slice(&hasher.finish()[..vk::UUID_SIZE as usize]);
+        uuid
+    })
+}
+
+/// Identifies the build of the driver by the size and modification time of the library it
+/// was loaded from, both of which change when it is rebuilt.
+fn build_id() -> Option<(u64, Duration)> {
+    let mut info: libc::Dl_info = unsafe { mem::zeroed() };
+    if unsafe { libc::dladdr(uuid as *const libc::c_void, &mut info) } == 0 ||
+        info.dli_fname.is_null()
+    {
+        return None;
+    }
+    let path = unsafe { CStr::from_ptr(info.dli_fname) };
+    let metadata = fs::metadata(OsStr::from_bytes(path.to_bytes())).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((metadata.len(), modified))
+}
+
+/// Compiled shaders, shared by all pipelines created with the cache. Pipelines can be created
+/// on several threads at once, so the entries are behind a lock.
+#[derive(Debug, Default)]
+pub struct PipelineCache {
+    entries: Mutex<BTreeMap<[u8; 32], Vec<u8>>>,
+}
+
+impl PipelineCache {
+    pub fn from_create_info(
+        create_info: &vk::PipelineCacheCreateInfo,
+        alloc: *const vk::AllocationCallbacks,
+    ) -> Result<Self, vk::Result> {
+        debug_assert_eq!(
+            create_info.sType,
+            vk::STRUCTURE_TYPE_PIPELINE_CACHE_CREATE_INFO
+        );
+        if !alloc.is_null() {
+            warn!("Custom allocators not supported by driver");
+            return Err(vk::ERROR_INITIALIZATION_FAILED);
+        }
+        let cache = PipelineCache::default();
+        if create_info.initialDataSize != 0 {
+            let data = unsafe {
+                slice::from_raw_parts(
+                    create_info.pInitialData as *const u8,
+                    create_info.initialDataSize,
+                )
+            };
+            cache.load(data);
+        }
+        Ok(cache)
+    }
+
+    /// Adds the entries of `data`, which `data` returned before. Data of other devices or
+    /// driver versions is ignored, as are entries that do not match their checksum.
+    fn load(&self, data: &[u8]) {
+        let mut reader = Reader(data);
+        let expected = uuid();
+        let header = (
+            reader.u32(),
+            reader.u32(),
+            reader.u32(),
+            reader.u32(),
+            reader.bytes(vk::UUID_SIZE as usize),
+        );
+        match header {
+            (Some(size), Some(vk::PIPELINE_CACHE_HEADER_VERSION_ONE), Some(VENDOR_ID),
+             Some(DEVICE_ID), Some(uuid))
+                if size as usize == HEADER_SIZE && uuid == &expected[..] => (),
+            _ => {
+                info!("Ignoring pipeline cache data of another device or driver version");
+                return;
+            }
+        }
+        let mut entries = self.entries.lock().unwrap();
+        while !reader.0.is_empty() {
+            let (key, checksum, code) = match reader.entry() {
+                Some(entry) => entry,
+                None => {
+                    warn!("Pipeline cache data is truncated");
+                    return;
+                }
+            };
+            if hash::sha256(code) != checksum {
+                warn!("Skipping corrupt entry of pipeline cache data");
+                continue;
+            }
+            let mut array = [0; 32];
+            array.copy_from_slice(key);
+            entries.insert(array, code.to_vec());
+        }
+    }
+
+    /// Returns the code cached for `key`.
+    pub fn get(&self, key: &[u8; 32]) -> Option<Vec<u8>> {
+        self.entries.lock().unwrap().get(key).cloned()
+    }
+
+    pub fn insert(&self, key: [u8; 32], code: Vec<u8>) {
+        self.entries.lock().unwrap().insert(key, code);
+    }
+
+    /// Adds the entries of `other` that this cache does not have yet.
+    pub fn merge(&self, other: &PipelineCache) {
+        let other = other.entries.lock().unwrap().clone();
+        let mut entries = self.entries.lock().unwrap();
+        for (key, code) in other {
+            entries.entry(key).or_insert(code);
+        }
+    }
+
+    /// Serializes the header and as many whole entries as fit into `max_size` bytes. Returns
+    /// whether all entries fit, no data at all if not even the header does.
+    pub fn data(&self, max_size: usize) -> (Vec<u8>, bool) {
+        if max_size < HEADER_SIZE {
+            return (Vec::new(), false);
+        }
+        let mut data = Vec::new();
+        data.extend_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+        data.extend_from_slice(&vk::PIPELINE_CACHE_HEADER_VERSION_ONE.to_le_bytes());
+        data.extend_from_slice(&VENDOR_ID.to_le_bytes());
+        data.extend_from_slice(&DEVICE_ID.to_le_bytes());
+        data.extend_from_slice(&uuid());
+        for (key, code) in self.entries.lock().unwrap().iter() {
+            if data.len() + 72 + code.len() > max_size {
+                return (data, false);
+            }
+            data.extend_from_slice(key);
+            data.extend_from_slice(&hash::sha256(code));
+            data.extend_from_slice(&(code.len() as u64).to_le_bytes());
+            data.extend_from_slice(code);
+        }
+        (data, true)
+    }
+}
+
+/// Reads cache data from the front of a byte string.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Option<&'a [u8]> {
+        if self.0.len() < count {
+            return None;
+        }
+        let (bytes, rest) = self.0.split_at(count);
+        self.0 = rest;
+        Some(bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let bytes = self.bytes(4)?;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        Some(u64::from_le_bytes(bytes))
+    }
+
+    /// Reads the key, checksum and code of an entry.
+    fn entry(&mut self) -> Option<(&'a [u8], &'a [u8], &'a [u8])> {
+        let key = self.bytes(32)?;
+        let checksum = self.bytes(32)?;
+        let length = self.u64()?;
+        if length > self.0.len() as u64 {
+            return None;
+        }
+        Some((key, checksum, self.bytes(length as usize)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+    use super::*;
+
+    fn load(data: &[u8]) -> PipelineCache {
+        let create_info = vk::PipelineCacheCreateInfo {
+            sType: vk::STRUCTURE_TYPE_PIPELINE_CACHE_CREATE_INFO,
+            pNext: ptr::null(),
+            flags: 0,
+            initialDataSize: data.len(),
+            pInitialData: data.as_ptr() as *const _,
+        };
+        PipelineCache::from_create_info(&create_info, ptr::null()).unwrap()
+    }
+
+    #[test]
+    fn round_trips_data() {
+        let cache = PipelineCache::default();
+        cache.insert([1; 32], vec![1, 2, 3]);
+        cache.insert([2; 32], vec![4; 100]);
+        let (data, complete) = cache.data(usize::MAX);
+        assert!(complete);
+        assert_eq!(&data[..4], &[32, 0, 0, 0]);
+        assert_eq!(&data[16..32], &uuid());
+
+        let loaded = load(&data);
+        assert_eq!(loaded.get(&[1; 32]), Some(vec![1, 2, 3]));
+        assert_eq!(loaded.get(&[2; 32]), Some(vec![4; 100]));
+        assert_eq!(loaded.data(usize::MAX), (data.clone(), true));
+
+        // Only whole entries are returned
+        let (partial, complete) = cache.data(data.len() - 1);
+        assert!(!complete);
+        assert_eq!(partial.len(), 32 + 72 + 3);
+        assert_eq!(cache.data(31), (Vec::new(), false));
+
+        let merged = PipelineCache::default();
+        merged.merge(&load(&partial));
+        assert_eq!(merged.get(&[1; 32]), Some(vec![1, 2, 3]));
+        assert_eq!(merged.get(&[2; 32]), None);
+    }
+
+    #[test]
+    fn ignores_bad_data() {
+        let cache = PipelineCache::default();
+        cache.insert([1; 32], vec![1, 2, 3]);
+        cache.insert([2; 32], vec![4; 100]);
+        let (data, _) = cache.data(usize::MAX);
+
+        let mut other_driver = data.clone();
+        other_driver[20] ^= 1;
+        assert_eq!(load(&other_driver).get(&[1; 32]), None);
+
+        let mut corrupt = data.clone();
+        corrupt[32 + 72] ^= 1;
+        let loaded = load(&corrupt);
+        assert_eq!(loaded.get(&[1; 32]), None);
+        assert_eq!(loaded.get(&[2; 32]), Some(vec![4; 100]));
+
+        let truncated = load(&data[..data.len() - 1]);
+        assert_eq!(truncated.get(&[1; 32]), Some(vec![1, 2, 3]));
+        assert_eq!(truncated.get(&[2; 32]), None);
+    }
+}
@@ -0,0 +1,474 @@
+//! Rasterization of triangles in window coordinates into the quads of fragments they cover.
+//!
+//! Vertices are snapped to a fixed point grid with `SUB_PIXEL_BITS` fractional bits, so that
+//! coverage is exact integer arithmetic. A pixel is covered if its center is inside the three
+//! edge functions of the triangle. Centers right on an edge belong to the triangle only if it is
+//! a top or a left edge, so that triangles sharing an edge cover every pixel along it exactly
+//! once. With 4x multisampling, the same goes for the standard sample positions instead of the
+//! centers, and a pixel is covered if any of its samples is.
+use std::cmp;
+
+use ffi_types as vk;
+use batch::Quad;
+use pipeline::RasterizationState;
+
+/// `subPixelPrecisionBits`, the fractional bits of the fixed point window coordinates.
+pub const SUB_PIXEL_BITS: u32 = 8;
+
+/// Window coordinates of vertices have to be within this distance of the origin, or the edge
+/// functions could overflow.
+pub const MAX_COORDINATE: f32 = (1 << 18) as f32;
+
+/// The standard positions of the samples of pixels with 4 samples, in the order of their
+/// indices.
+pub const SAMPLE_POSITIONS: [[f32; 2]; 4] = [
+    [0.375, 0.125],
+    [0.875, 0.375],
+    [0.125, 0.625],
+    [0.625, 0.875],
+];
+
+const ONE: i64 = 1 << SUB_PIXEL_BITS;
+
+/// The fixed point position of sample `sample` of pixel `(x, y)`, which has `samples` of them.
+/// The sample of a pixel with one is its center.
+fn sample_position(x: u32, y: u32, sample: usize, samples: u32) -> (i64, i64) {
+    debug_assert!(samples == 1 || samples == 4);
+    let offset = if samples == 1 {
+        [0.5, 0.5]
+    } else {
+        SAMPLE_POSITIONS[sample]
+    };
+    // Every offset is a multiple of the grid
+    let fixed = |value: f32| (value * ONE as f32) as i64;
+    (i64::from(x) * ONE + fixed(offset[0]), i64::from(y) * ONE + fixed(offset[1]))
+}
+
+/// A rectangle of pixels, from `left` and `top` up to but excluding `right` and `bottom`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub left: u32,
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+}
+
+impl Rect {
+    /// The pixels of `rect` that are not left of or above the origin.
+    pub fn from_vk(rect: &vk::Rect2D) -> Self {
+        // The far edges are where the extent reaches from the offset, even if that is negative
+        let end = |offset: i32, extent: u32| {
+            cmp::min(cmp::max(i64::from(offset) + i64::from(extent), 0), i64::from(u32::MAX)) as u32
+        };
+        Rect {
+            left: cmp::max(rect.offset.x, 0) as u32,
+            top: cmp::max(rect.offset.y, 0) as u32,
+            right: end(rect.offset.x, rect.extent.width),
+            bottom: end(rect.offset.y, rect.extent.height),
+        }
+    }
+
+    /// The pixels in both rectangles.
+    pub fn intersect(&self, other: &Rect) -> Rect {
+        Rect {
+            left: cmp::max(self.left, other.left),
+            top: cmp::max(self.top, other.top),
+            right: cmp::min(self.right, other.right),
+            bottom: cmp::min(self.bottom, other.bottom),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.left >= self.right || self.top >= self.bottom
+    }
+}
+
+/// The function `a * x + b * y + c` of a fixed point position, which is zero on an edge and
+/// positive on the side of the triangle.
+#[derive(Clone, Copy, Debug)]
+struct Edge {
+    a: i64,
+    b: i64,
+    c: i64,
+    /// Subtracted before comparing with zero, 1 if positions on the edge are outside.
+    bias: i64,
+}
+
+impl Edge {
+    fn new(from: [i64; 2], to: [i64; 2]) -> Self {
+        let (dx, dy) = (to[0] - from[0], to[1] - from[1]);
+        // The triangle is below top edges and right of left ones, y points down
+        let top_left = (dy == 0 && dx > 0) || dy < 0;
+        Edge {
+            a: -dy,
+            b: dx,
+            c: dy * from[0] - dx * from[1],
+            bias: if top_left { 0 } else { 1 },
+        }
+    }
+
+    fn at(&self, x: i64, y: i64) -> i64 {
+        self.a * x + self.b * y + self.c
+    }
+}
+
+/// A triangle set up for rasterization.
+#[derive(Clone, Copy, Debug)]
+pub struct Triangle {
+    /// The edges from vertex 0 to 1, 1 to 2 and 2 to 0, in an order with a positive area.
+    edges: [Edge; 3],
+    /// Twice the area in fixed point units.
+    area: i64,
+    /// Whether vertices 1 and 2 were swapped to make the area positive.
+    swapped: bool,
+    /// The pixels the triangle may cover.
+    bounds: Rect,
+    pub front_facing: bool,
+}
+
+impl Triangle {
+    /// Sets up the triangle of the window coordinates `positions`. `None` if it covers no
+    /// pixels because it has no area or is culled by `state`.
+    pub fn setup(positions: [[f32; 2]; 3], state: &RasterizationState) -> Option<Triangle> {
+        let inside = |value: f32| value.abs() <= MAX_COORDINATE;
+        if !positions.iter().all(|position| position.iter().all(|&value| inside(value))) {
+            warn!("Skipping triangle with vertices at {:?}", positions);
+            return None;
+        }
+        let snap = |value: f32| (value * ONE as f32).round() as i64;
+        let mut vertices = [[0; 2]; 3];
+        for (vertex, position) in vertices.iter_mut().zip(&positions) {
+            *vertex = [snap(position[0]), snap(position[1])];
+        }
+        let area = Edge::new(vertices[0], vertices[1]).at(vertices[2][0], vertices[2][1]);
+        if area == 0 {
+            return None;
+        }
+        // Counter-clockwise triangles have a negative area in window coordinates
+        let counter_clockwise = area < 0;
+        let front_facing =
+            counter_clockwise == (state.front_face == vk::FRONT_FACE_COUNTER_CLOCKWISE);
+        let culled = if front_facing {
+            vk::CULL_MODE_FRONT_BIT
+        } else {
+            vk::CULL_MODE_BACK_BIT
+        };
+        if state.cull_mode & culled != 0 {
+            return None;
+        }
+        if counter_clockwise {
+            vertices.swap(1, 2);
+        }
+
+        let min = |axis: usize| vertices.iter().map(|vertex| vertex[axis]).min().unwrap();
+        let max = |axis: usize| vertices.iter().map(|vertex| vertex[axis]).max().unwrap();
+        // Conservative, pixels whose centers are outside fail the edge functions
+        let pixel = |value: i64| cmp::max(value >> SUB_PIXEL_BITS, 0) as u32;
+        Some(Triangle {
+            edges: [
+                Edge::new(vertices[0], vertices[1]),
+                Edge::new(vertices[1], vertices[2]),
+                Edge::new(vertices[2], vertices[0]),
+            ],
+            area: area.abs(),
+            swapped: counter_clockwise,
+            bounds: Rect {
+                left: pixel(min(0)),
+                top: pixel(min(1)),
+                right: pixel(max(0)) + 1,
+                bottom: pixel(max(1)) + 1,
+            },
+            front_facing: front_facing,
+        })
+    }
+
+    /// The pixels the triangle may cover. Pixels outside of them are not covered, but not all
+    /// pixels inside are.
+    pub fn bounds(&self) -> Rect {
+        self.bounds
+    }
+
+    /// The mask of the samples of pixel `(x, y)` in the triangle, which has `samples` of them.
+    pub fn sample_mask(&self, x: u32, y: u32, samples: u32) -> u32 {
+        (0..samples as usize).fold(0, |mask, sample| {
+            let (x, y) = sample_position(x, y, sample, samples);
+            if self.edges.iter().all(|edge| edge.at(x, y) - edge.bias >= 0) {
+                mask | 1 << sample
+            } else {
+                mask
+            }
+        })
+    }
+
+    /// The barycentric coordinates of the center of pixel `(x, y)`, the weights of the
+    /// vertices in the order they were set up in.
+    pub fn barycentrics(&self, x: u32, y: u32) -> [f32; 3] {
+        let (x, y) = sample_position(x, y, 0, 1);
+        self.weights(x, y)
+    }
+
+    /// The barycentric coordinates of sample `sample` of pixel `(x, y)`, which has 4 samples.
+    pub fn sample_barycentrics(&self, x: u32, y: u32, sample: usize) -> [f32; 3] {
+        let (x, y) = sample_position(x, y, sample, 4);
+        self.weights(x, y)
+    }
+
+    fn weights(&self, x: i64, y: i64) -> [f32; 3] {
+        // The weight of a vertex is the edge function of the edge across from it
+        let weight = |edge: &Edge| edge.at(x, y) as f32 / self.area as f32;
+        let [first, second, third] = [
+            weight(&self.edges[1]),
+            weight(&self.edges[2]),
+            weight(&self.edges[0]),
+        ];
+        if self.swapped {
+            [first, third, second]
+        } else {
+            [first, second, third]
+        }
+    }
+
+    /// Calls `emit` for every quad with pixels in `clip` that the triangle covers, row by row.
+    /// Quads are aligned to even pixels, pixels outside of `clip` are not covered. Pixels have
+    /// `samples` samples, any of which covers them.
+    pub fn rasterize<F>(&self, clip: &Rect, samples: u32, mut emit: F)
+    where
+        F: FnMut(Quad),
+    {
+        let area = self.bounds.intersect(clip);
+        if area.is_empty() {
+            return;
+        }
+        for y in (area.top & !1..area.bottom).step_by(2) {
+            for x in (area.left & !1..area.right).step_by(2) {
+                let mut quad = Quad {
+                    x: x,
+                    y: y,
+                    coverage: 0,
+                };
+                for index in 0..4 {
+                    let (x, y) = quad.fragment(index);
+                    if x >= area.left && x < area.right && y >= area.top && y < area.bottom &&
+                        self.sample_mask(x, y, samples) != 0
+                    {
+                        quad.coverage |= 1 << index;
+                    }
+                }
+                if quad.coverage != 0 {
+                    emit(quad);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use super::*;
+
+    fn state(cull_mode: vk::CullModeFlags, front_face: vk::FrontFace) -> RasterizationState {
+        RasterizationState {
+            depth_clamp: false,
+            discard: false,
+            cull_mode: cull_mode,
+            front_face: front_face,
+            depth_bias: None,
+            line_width: 1.0,
+        }
+    }
+
+    fn setup(positions: [[f32; 2]; 3]) -> Option<Triangle> {
+        let state = state(vk::CULL_MODE_NONE, vk::FRONT_FACE_COUNTER_CLOCKWISE);
+        Triangle::setup(positions, &state)
+    }
+
+    const EVERYWHERE: Rect = Rect {
+        left: 0,
+        top: 0,
+        right: 1 << 16,
+        bottom: 1 << 16,
+    };
+
+    /// Counts how often the triangles cover every pixel within `clip`.
+    fn coverage(triangles: &[[[f32; 2]; 3]], clip: &Rect) -> BTreeMap<(u32, u32), u32> {
+        let mut pixels = BTreeMap::new();
+        for &positions in triangles {
+            let triangle = match setup(positions) {
+                Some(triangle) => triangle,
+                None => continue,
+            };
+            triangle.rasterize(clip, 1, |quad| {
+                for index in (0..4).filter(|&index| quad.covers(index)) {
+                    *pixels.entry(quad.fragment(index)).or_insert(0) += 1;
+                }
+            });
+        }
+        pixels
+    }
+
+    fn pixels(coverage: &BTreeMap<(u32, u32), u32>) -> Vec<(u32, u32)> {
+        coverage.keys().cloned().collect()
+    }
+
+    #[test]
+    fn follows_top_left_rule() {
+        // Two halves of a square whose edges run through pixel centers
+        let upper = [[0.5, 0.5], [4.5, 0.5], [0.5, 4.5]];
+        let lower = [[4.5, 0.5], [4.5, 4.5], [0.5, 4.5]];
+        let expected: Vec<(u32, u32)> = (0..4)
+            .flat_map(|x| (0..4).map(move |y| (x, y)))
+            .filter(|&(x, y)| x + y <= 3)
+            .collect();
+        assert_eq!(pixels(&coverage(&[upper], &EVERYWHERE)), expected);
+        // The other half has the right and bottom edges, which are outside
+        assert_eq!(
+            pixels(&coverage(&[lower], &EVERYWHERE)),
+            vec![(1, 3), (2, 2), (2, 3), (3, 1), (3, 2), (3, 3)]
+        );
+        // The order of the vertices does not matter
+        let reversed = [upper[0], upper[2], upper[1]];
+        assert_eq!(coverage(&[reversed], &EVERYWHERE), coverage(&[upper], &EVERYWHERE));
+
+        let square = coverage(&[upper, lower], &EVERYWHERE);
+        assert_eq!(square.len(), 16);
+        assert!(square.values().all(|&count| count == 1));
+    }
+
+    #[test]
+    fn shares_edges_without_gaps_or_overlaps() {
+        // A 4x4 grid of points with the inner ones off the pixel grid, split into triangles
+        // along alternating diagonals
+        let offsets = [0.0, 5.3, 9.5, 16.0];
+        let jitter = [0.0, 0.37, -0.5 + 1.0 / 1024.0, 0.0];
+        let point = |column: usize, row: usize| {
+            let inner = |index| index != 0 && index != 3;
+            [
+                offsets[column] + if inner(column) { jitter[row] } else { 0.0 },
+                offsets[row] + if inner(row) { jitter[column] * 0.5 } else { 0.0 },
+            ]
+        };
+        let mut triangles = Vec::new();
+        for row in 0..3 {
+            for column in 0..3 {
+                let corners = [
+                    point(column, row),
+                    point(column + 1, row),
+                    point(column + 1, row + 1),
+                    point(column, row + 1),
+                ];
+                if (row + column) & 1 == 0 {
+                    triangles.push([corners[0], corners[1], corners[2]]);
+                    triangles.push([corners[0], corners[2], corners[3]]);
+                } else {
+                    triangles.push([corners[0], corners[1], corners[3]]);
+                    triangles.push([corners[3], corners[2], corners[1]]);
+                }
+            }
+        }
+        let covered = coverage(&triangles, &EVERYWHERE);
+        assert_eq!(covered.len(), 16 * 16);
+        assert!(covered.iter().all(|(&(x, y), &count)| x < 16 && y < 16 && count == 1));
+    }
+
+    #[test]
+    fn snaps_to_sub_pixel_grid() {
+        // A left edge through the centers of column 0 covers it, unless it is right of them by
+        // at least half a step of the grid
+        let column = |offset: f32| {
+            let left = 0.5 + offset;
+            let covered = coverage(&[[[left, 0.5], [4.5, 0.5], [left, 4.5]]], &EVERYWHERE);
+            covered.contains_key(&(0, 0))
+        };
+        assert!(column(0.0));
+        assert!(column(-1.0 / 1024.0));
+        assert!(column(1.0 / 1024.0));
+        assert!(!column(1.0 / 256.0));
+
+        assert!(setup([[0.0, 0.0], [1.0, 1.0], [2.0, 2.0]]).is_none());
+        // Too thin to survive snapping
+        assert!(setup([[0.0, 0.0], [8.0, 1.0 / 1024.0], [16.0, 0.0]]).is_none());
+        assert!(setup([[0.0, 0.0], [MAX_COORDINATE * 2.0, 0.0], [0.0, 1.0]]).is_none());
+        assert!(setup([[0.0, 0.0], [1.0, 0.0], [0.0, f32::NAN]]).is_none());
+    }
+
+    #[test]
+    fn culls_by_facing() {
+        // Clockwise on the screen, since y points down
+        let clockwise = [[0.0, 0.0], [4.0, 0.0], [0.0, 4.0]];
+        let counter_clockwise = [[0.0, 0.0], [0.0, 4.0], [4.0, 0.0]];
+        let facing = |positions, cull_mode, front_face| {
+            Triangle::setup(positions, &state(cull_mode, front_face)).map(
+                |triangle| triangle.front_facing,
+            )
+        };
+        let (ccw, cw) = (vk::FRONT_FACE_COUNTER_CLOCKWISE, vk::FRONT_FACE_CLOCKWISE);
+        assert_eq!(facing(clockwise, vk::CULL_MODE_NONE, ccw), Some(false));
+        assert_eq!(facing(clockwise, vk::CULL_MODE_NONE, cw), Some(true));
+        assert_eq!(facing(counter_clockwise, vk::CULL_MODE_NONE, ccw), Some(true));
+        assert_eq!(facing(clockwise, vk::CULL_MODE_BACK_BIT, ccw), None);
+        assert_eq!(facing(clockwise, vk::CULL_MODE_FRONT_BIT, ccw), Some(false));
+        assert_eq!(facing(counter_clockwise, vk::CULL_MODE_BACK_BIT, ccw), Some(true));
+        assert_eq!(facing(counter_clockwise, vk::CULL_MODE_FRONT_BIT, ccw), None);
+        assert_eq!(facing(counter_clockwise, vk::CULL_MODE_FRONT_AND_BACK, cw), None);
+    }
+
+    #[test]
+    fn clips_into_quads() {
+        let triangle = setup([[-8.0, -8.0], [64.0, -8.0], [-8.0, 64.0]]).unwrap();
+        let clip = Rect {
+            left: 1,
+            top: 1,
+            right: 4,
+            bottom: 3,
+        };
+        let mut quads = Vec::new();
+        triangle.rasterize(&clip, 1, |quad| quads.push((quad.x, quad.y, quad.coverage)));
+        assert_eq!(quads, vec![(0, 0, 0b1000), (2, 0, 0b1100), (0, 2, 0b0010), (2, 2, 0b0011)]);
+
+        let scissor = vk::Rect2D {
+            offset: vk::Offset2D { x: 2, y: 0 },
+            extent: vk::Extent2D {
+                width: 8,
+                height: 2,
+            },
+        };
+        let clip = clip.intersect(&Rect::from_vk(&scissor));
+        quads.clear();
+        triangle.rasterize(&clip, 1, |quad| quads.push((quad.x, quad.y, quad.coverage)));
+        assert_eq!(quads, vec![(2, 0, 0b1100)]);
+        assert!(Rect::from_vk(&scissor).intersect(&Rect { top: 2, ..clip }).is_empty());
+    }
+
+    #[test]
+    fn keeps_the_far_edges_of_rects_left_of_the_origin() {
+        let rect = |x, y, width, height| {
+            Rect::from_vk(&vk::Rect2D {
+                offset: vk::Offset2D { x: x, y: y },
+                extent: vk::Extent2D {
+                    width: width,
+                    height: height,
+                },
+            })
+        };
+        let shifted = rect(-4, -2, 8, 8);
+        assert_eq!((shifted.left, shifted.top, shifted.right, shifted.bottom), (0, 0, 4, 6));
+        assert!(rect(-8, 0, 4, 4).is_empty());
+        assert_eq!(rect(-1, 0, u32::MAX, 1).right, u32::MAX - 1);
+    }
+
+    #[test]
+    fn interpolates_at_pixel_centers() {
+        let positions = [[0.0, 0.0], [0.0, 8.0], [8.0, 0.0]];
+        let triangle = setup(positions).unwrap();
+        for &(x, y) in &[(0, 0), (3, 1), (1, 5)] {
+            let weights = triangle.barycentrics(x, y);
+            assert!((weights.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+            for axis in 0..2 {
+                let value: f32 = weights.iter().zip(&positions).map(|(w, p)| w * p[axis]).sum();
+                let center = [x, y][axis] as f32 + 0.5;
+                assert!((value - center).abs() < 1e-5, "{:?} at {:?}", weights, (x, y));
+            }
+        }
+    }
+}
@@ -0,0 +1,995 @@
+//! Render passes, their subpasses and the framebuffers they render to.
+//!
+//! Subpasses run one after the other: all work of a subpass is done before the next one starts,
+//! and commands before and after a render pass instance run before and after all of it, which
+//! satisfies every dependency a render pass can declare. Images are linear in every layout, so
+//! layout transitions have nothing to do either, except that contents in the undefined layout
+//! are undefined.
+//!
+//! An attachment is loaded at the start of the first subpass that uses it and stored at the end
+//! of the last one. Color attachments are resolved at the end of every subpass that has resolve
+//! attachments for them.
+//!
+//! If `RUSTERIZER_GARBAGE` is set, attachments are filled with a garbage pattern wherever their
+//! contents become undefined: after a DONT_CARE load or store, on a load from the undefined
+//! layout, and in subpasses between two uses that do not preserve them. Applications that rely
+//! on such contents then render garbage rather than what happens to be left over.
+use std::env;
+use std::ops::Range;
+use std::sync::OnceLock;
+
+use ffi_types as vk;
+use format::Format;
+use image::{ImageView, SAMPLE_COUNTS};
+use pipeline::array;
+
+/// Environment variable that turns the garbage fill on if it is set to anything but "0".
+pub const GARBAGE_VARIABLE: &str = "RUSTERIZER_GARBAGE";
+
+/// Repeats over the bytes of a texel whose contents are undefined.
+const GARBAGE: [u8; 4] = [0xde, 0xad, 0xbe, 0xef];
+
+/// `maxFramebufferWidth` and `maxFramebufferHeight`.
+pub const MAX_FRAMEBUFFER_SIZE: u32 = 1 << 14;
+pub const MAX_FRAMEBUFFER_LAYERS: u32 = 1 << 11;
+pub const MAX_COLOR_ATTACHMENTS: u32 = 8;
+
+/// Whether `RUSTERIZER_GARBAGE` asks for undefined contents to be filled with garbage.
+pub fn fill_garbage() -> bool {
+    static GARBAGE: OnceLock<bool> = OnceLock::new();
+    *GARBAGE.get_or_init(|| match env::var_os(GARBAGE_VARIABLE) {
+        Some(value) => value != "0",
+        None => false,
+    })
+}
+
+/// An attachment of a render pass.
+#[derive(Debug)]
+struct Attachment {
+    format: Format,
+    vk_format: vk::Format,
+    samples: u32,
+    load_op: vk::AttachmentLoadOp,
+    store_op: vk::AttachmentStoreOp,
+    stencil_load_op: vk::AttachmentLoadOp,
+    stencil_store_op: vk::AttachmentStoreOp,
+    initial_layout: vk::ImageLayout,
+    /// The first and the last subpass that use the attachment, `None` if none does.
+    uses: Option<(usize, usize)>,
+}
+
+impl Attachment {
+    /// The aspects of the attachment, each with its load and store operation.
+    fn operations(
+        &self,
+    ) -> Vec<(vk::ImageAspectFlags, vk::AttachmentLoadOp, vk::AttachmentStoreOp)> {
+        let aspects = self.format.aspects();
+        let mut operations = Vec::new();
+        if aspects & vk::IMAGE_ASPECT_COLOR_BIT != 0 {
+            operations.push((vk::IMAGE_ASPECT_COLOR_BIT, self.load_op, self.store_op));
+        }
+        if aspects & vk::IMAGE_ASPECT_DEPTH_BIT != 0 {
+            operations.push((vk::IMAGE_ASPECT_DEPTH_BIT, self.load_op, self.store_op));
+        }
+        if aspects & vk::IMAGE_ASPECT_STENCIL_BIT != 0 {
+            operations.push((
+                vk::IMAGE_ASPECT_STENCIL_BIT,
+                self.stencil_load_op,
+                self.stencil_store_op,
+            ));
+        }
+        operations
+    }
+
+    /// Whether the attachment is cleared on its first use.
+    fn clears(&self) -> bool {
+        self.operations().iter().any(|&(_, load_op, _)| {
+            load_op == vk::ATTACHMENT_LOAD_OP_CLEAR
+        })
+    }
+}
+
+/// The attachments a subpass uses, by their index in the render pass. Unused references are
+/// `None`.
+#[derive(Debug)]
+struct Subpass {
+    inputs: Vec<Option<usize>>,
+    colors: Vec<Option<usize>>,
+    /// Empty, or a resolve attachment for every color attachment.
+    resolves: Vec<Option<usize>>,
+    depth_stencil: Option<usize>,
+    preserves: Vec<usize>,
+}
+
+impl Subpass {
+    fn uses(&self, attachment: usize) -> bool {
+        self.inputs
+            .iter()
+            .chain(&self.colors)
+            .chain(&self.resolves)
+            .chain(Some(&self.depth_stencil))
+            .any(|&used| used == Some(attachment))
+    }
+}
+
+#[derive(Debug)]
+pub struct RenderPass {
+    attachments: Vec<Attachment>,
+    subpasses: Vec<Subpass>,
+}
+
+impl RenderPass {
+    pub fn from_create_info(
+        create_info: &vk::RenderPassCreateInfo,
+        alloc: *const vk::AllocationCallbacks,
+    ) -> Result<Self, vk::Result> {
+        debug_assert_eq!(create_info.sType, vk::STRUCTURE_TYPE_RENDER_PASS_CREATE_INFO);
+        if !alloc.is_null() {
+            warn!("Custom allocators not supported by driver");
+            return Err(vk::ERROR_INITIALIZATION_FAILED);
+        }
+        let descriptions = unsafe { array(create_info.pAttachments, create_info.attachmentCount) };
+        let mut attachments = Vec::with_capacity(descriptions.len());
+        for description in descriptions {
+            attachments.push(attachment(description)?);
+        }
+        let descriptions = unsafe { array(create_info.pSubpasses, create_info.subpassCount) };
+        if descriptions.is_empty() {
+            warn!("Render pass has no subpasses");
+            return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+        }
+        let mut subpasses = Vec::with_capacity(descriptions.len());
+        for description in descriptions {
+            subpasses.push(subpass(description, &attachments)?);
+        }
+        let count = subpasses.len() as u32;
+        let dependencies =
+            unsafe { array(create_info.pDependencies, create_info.dependencyCount) };
+        for dependency in dependencies {
+            let (source, destination) = (dependency.srcSubpass, dependency.dstSubpass);
+            let valid = match (source, destination) {
+                (vk::SUBPASS_EXTERNAL, vk::SUBPASS_EXTERNAL) => false,
+                (vk::SUBPASS_EXTERNAL, subpass) |
+                (subpass, vk::SUBPASS_EXTERNAL) => subpass < count,
+                // Later subpasses cannot be waited for by earlier ones
+                _ => source <= destination && destination < count,
+            };
+            if !valid {
+                warn!("Dependency of subpass {} on {} is invalid", destination, source);
+                return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+            }
+        }
+
+        for (index, subpass) in subpasses.iter().enumerate() {
+            for (attachment_index, attachment) in attachments.iter_mut().enumerate() {
+                if subpass.uses(attachment_index) {
+                    let first = attachment.uses.map_or(index, |(first, _)| first);
+                    attachment.uses = Some((first, index));
+                }
+            }
+        }
+        Ok(RenderPass {
+            attachments: attachments,
+            subpasses: subpasses,
+        })
+    }
+
+    /// The number of color attachments of subpass `index` and the samples of the attachments
+    /// it renders to, `None` for those if it renders to none. `None` if there is no such subpass.
+    pub fn subpass_targets(&self, index: u32) -> Option<(usize, Option<u32>)> {
+        let subpass = self.subpasses.get(index as usize)?;
+        let samples = subpass
+            .colors
+            .iter()
+            .chain(Some(&subpass.depth_stencil))
+            .filter_map(|&attachment| attachment)
+            .map(|attachment| self.attachments[attachment].samples)
+            .next();
+        Some((subpass.colors.len(), samples))
+    }
+
+    /// Encodes the clear values of the attachments that are cleared as texels of their formats.
+    /// Other attachments get no texel. `None` if there is no value for one that is cleared.
+    pub fn clear_texels(&self, values: &[vk::ClearValue]) -> Option<Vec<Vec<u8>>> {
+        let mut texels = Vec::with_capacity(self.attachments.len());
+        for (index, attachment) in self.attachments.iter().enumerate() {
+            if !attachment.clears() {
+                texels.push(Vec::new());
+                continue;
+            }
+            let value = values.get(index)?;
+            texels.push(match attachment.format {
+                Format::Color { .. } => {
+                    attachment.format.encode_color(*value.as_color().as_uint32())
+                }
+                Format::DepthStencil { .. } => {
+                    let value = value.as_depth_stencil();
+                    attachment.format.encode_depth_stencil(value.depth, value.stencil)
+                }
+            });
+        }
+        Some(texels)
+    }
+}
+
+fn attachment(description: &vk::AttachmentDescription) -> Result<Attachment, vk::Result> {
+    let format = match Format::from_vk(description.format) {
+        Some(format) => format,
+        None => {
+            warn!("Format {} not supported by driver", description.format);
+            return Err(vk::ERROR_FORMAT_NOT_SUPPORTED);
+        }
+    };
+    let samples = description.samples;
+    let load_ops = vk::ATTACHMENT_LOAD_OP_LOAD..vk::ATTACHMENT_LOAD_OP_DONT_CARE + 1;
+    let store_ops = vk::ATTACHMENT_STORE_OP_STORE..vk::ATTACHMENT_STORE_OP_DONT_CARE + 1;
+    if samples & SAMPLE_COUNTS == 0 || samples & (samples - 1) != 0 ||
+        !load_ops.contains(&description.loadOp) ||
+        !load_ops.contains(&description.stencilLoadOp) ||
+        !store_ops.contains(&description.storeOp) ||
+        !store_ops.contains(&description.stencilStoreOp) ||
+        !is_defined(description.finalLayout)
+    {
+        warn!("Attachment of format {} is invalid", description.format);
+        return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+    }
+    Ok(Attachment {
+        format: format,
+        vk_format: description.format,
+        samples: samples,
+        load_op: description.loadOp,
+        store_op: description.storeOp,
+        stencil_load_op: description.stencilLoadOp,
+        stencil_store_op: description.stencilStoreOp,
+        initial_layout: description.initialLayout,
+        uses: None,
+    })
+}
+
+/// Whether attachments can be used in `layout`, which is not one that leaves their contents
+/// undefined.
+fn is_defined(layout: vk::ImageLayout) -> bool {
+    layout != vk::IMAGE_LAYOUT_UNDEFINED && layout != vk::IMAGE_LAYOUT_PREINITIALIZED
+}
+
+fn subpass(
+    description: &vk::SubpassDescription,
+    attachments: &[Attachment],
+) -> Result<Subpass, vk::Result> {
+    if description.pipelineBindPoint != vk::PIPELINE_BIND_POINT_GRAPHICS ||
+        description.colorAttachmentCount > MAX_COLOR_ATTACHMENTS
+    {
+        warn!(
+            "Subpass for bind point {} with {} color attachments is invalid",
+            description.pipelineBindPoint,
+            description.colorAttachmentCount
+        );
+        return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+    }
+    let references = |pointer, count| -> Result<Vec<Option<usize>>, vk::Result> {
+        let references = unsafe { array(pointer, count) };
+        references.iter().map(|reference| resolve_reference(reference, attachments)).collect()
+    };
+    let count = description.colorAttachmentCount;
+    let subpass = Subpass {
+        inputs: references(description.pInputAttachments, description.inputAttachmentCount)?,
+        colors: references(description.pColorAttachments, count)?,
+        resolves: if description.pResolveAttachments.is_null() {
+            Vec::new()
+        } else {
+            references(description.pResolveAttachments, count)?
+        },
+        depth_stencil: match unsafe { description.pDepthStencilAttachment.as_ref() } {
+            Some(reference) => resolve_reference(reference, attachments)?,
+            None => None,
+        },
+        preserves: unsafe {
+            array(description.pPreserveAttachments, description.preserveAttachmentCount)
+        }.iter()
+            .map(|&index| index as usize)
+            .collect(),
+    };
+
+    let is_color = |index: usize| match attachments[index].format {
+        Format::Color { .. } => true,
+        Format::DepthStencil { .. } => false,
+    };
+    let colors = subpass.colors.iter().filter_map(|&color| color);
+    let mut samples = colors.clone().chain(subpass.depth_stencil).map(|index| {
+        attachments[index].samples
+    });
+    let first_samples = samples.next();
+    if !colors.clone().all(&is_color) || subpass.depth_stencil.is_some_and(is_color) ||
+        samples.any(|samples| Some(samples) != first_samples)
+    {
+        warn!("Attachments of subpass do not match their formats or sample counts");
+        return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+    }
+    for (&color, &resolve) in subpass.colors.iter().zip(&subpass.resolves) {
+        let resolve = match resolve {
+            Some(resolve) => &attachments[resolve],
+            None => continue,
+        };
+        let color = match color {
+            Some(color) => &attachments[color],
+            None => {
+                warn!("Subpass resolves an unused color attachment");
+                return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+            }
+        };
+        if color.samples == vk::SAMPLE_COUNT_1_BIT || resolve.samples != vk::SAMPLE_COUNT_1_BIT ||
+            color.vk_format != resolve.vk_format
+        {
+            warn!(
+                "Attachment of format {} cannot be resolved to one of format {}",
+                color.vk_format,
+                resolve.vk_format
+            );
+            return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+        }
+    }
+    for &preserve in &subpass.preserves {
+        if preserve >= attachments.len() || subpass.uses(preserve) {
+            warn!("Subpass cannot preserve attachment {}", preserve);
+            return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+        }
+    }
+    Ok(subpass)
+}
+
+/// The index of the attachment `reference` refers to, `None` if it is unused.
+fn resolve_reference(
+    reference: &vk::AttachmentReference,
+    attachments: &[Attachment],
+) -> Result<Option<usize>, vk::Result> {
+    if reference.attachment == vk::ATTACHMENT_UNUSED {
+        return Ok(None);
+    }
+    if reference.attachment as usize >= attachments.len() || !is_defined(reference.layout) {
+        warn!(
+            "Reference to attachment {} in layout {} is invalid",
+            reference.attachment,
+            reference.layout
+        );
+        return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+    }
+    Ok(Some(reference.attachment as usize))
+}
+
+/// The image views a render pass renders to.
+#[derive(Debug)]
+pub struct Framebuffer {
+    attachments: Vec<*const ImageView>,
+    pub width: u32,
+    pub height: u32,
+    pub layers: u32,
+}
+
+impl Framebuffer {
+    pub fn from_create_info(
+        create_info: &vk::FramebufferCreateInfo,
+        alloc: *const vk::AllocationCallbacks,
+    ) -> Result<Self, vk::Result> {
+        debug_assert_eq!(create_info.sType, vk::STRUCTURE_TYPE_FRAMEBUFFER_CREATE_INFO);
+        if !alloc.is_null() {
+            warn!("Custom allocators not supported by driver");
+            return Err(vk::ERROR_INITIALIZATION_FAILED);
+        }
+        let render_pass = unsafe { &*(create_info.renderPass as *const RenderPass) };
+        let (width, height, layers) = (create_info.width, create_info.height, create_info.layers);
+        if width == 0 || height == 0 || layers == 0 || width > MAX_FRAMEBUFFER_SIZE ||
+            height > MAX_FRAMEBUFFER_SIZE || layers > MAX_FRAMEBUFFER_LAYERS
+        {
+            warn!("Framebuffer of {}x{} pixels and {} layers is invalid", width, height, layers);
+            return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+        }
+        let views = unsafe { array(create_info.pAttachments, create_info.attachmentCount) };
+        if views.len() != render_pass.attachments.len() {
+            warn!(
+                "Framebuffer has {} attachments, its render pass {}",
+                views.len(),
+                render_pass.attachments.len()
+            );
+            return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+        }
+        let mut attachments = Vec::with_capacity(views.len());
+        for (&view, attachment) in views.iter().zip(&render_pass.attachments) {
+            let view = view as *const ImageView;
+            let (view_width, view_height) = unsafe { &*view }.extent();
+            let fits = {
+                let view = unsafe { &*view };
+                view.vk_format == attachment.vk_format && view.samples() == attachment.samples &&
+                    view.levels == 1 && view_width >= width &&
+                    view_height >= height && view.layers >= layers
+            };
+            if !fits {
+                warn!("Image view does not fit attachment of format {}", attachment.vk_format);
+                return Err(vk::ERROR_OUT_OF_HOST_MEMORY);
+            }
+            attachments.push(view);
+        }
+        Ok(Framebuffer {
+            attachments: attachments,
+            width: width,
+            height: height,
+            layers: layers,
+        })
+    }
+}
+
+/// A render pass that a command buffer is running.
+#[derive(Debug)]
+pub struct RenderPassInstance {
+    render_pass: *const RenderPass,
+    framebuffer: *const Framebuffer,
+    area: vk::Rect2D,
+    /// What `RenderPass::clear_texels` returned for the clear values.
+    clear_texels: Vec<Vec<u8>>,
+    subpass: usize,
+    garbage: bool,
+}
+
+impl RenderPassInstance {
+    /// Starts the first subpass of `render_pass`, whose attachments are those of
+    /// `framebuffer`. `garbage` fills undefined contents with garbage.
+    ///
+    /// Unsafe because the render pass, the framebuffer and its attachments have to be alive
+    /// until the instance ends.
+    pub unsafe fn begin(
+        render_pass: *const RenderPass,
+        framebuffer: *const Framebuffer,
+        area: vk::Rect2D,
+        clear_texels: Vec<Vec<u8>>,
+        garbage: bool,
+    ) -> Self {
+        let instance = RenderPassInstance {
+            render_pass: render_pass,
+            framebuffer: framebuffer,
+            area: area,
+            clear_texels: clear_texels,
+            subpass: 0,
+            garbage: garbage,
+        };
+        instance.start_subpass();
+        instance
+    }
+
+    /// Ends the current subpass and starts the next one.
+    pub unsafe fn next_subpass(&mut self) {
+        if self.subpass + 1 == (*self.render_pass).subpasses.len() {
+            warn!("Skipping next subpass after the last one");
+            return;
+        }
+        self.end_subpass();
+        self.subpass += 1;
+        self.start_subpass();
+    }
+
+    pub fn area(&self) -> vk::Rect2D {
+        self.area
+    }
+
+    /// The views the current subpass renders to: one per color attachment, `None` for unused
+    /// ones, and the depth/stencil attachment.
+    pub unsafe fn targets(&self) -> (Vec<Option<&ImageView>>, Option<&ImageView>) {
+        let subpass = &(&*self.render_pass).subpasses[self.subpass];
+        let framebuffer = &*self.framebuffer;
+        let view = |attachment: Option<usize>| {
+            attachment.map(|attachment| &*framebuffer.attachments[attachment])
+        };
+        let colors = subpass.colors.iter().map(|&color| view(color)).collect();
+        (colors, view(subpass.depth_stencil))
+    }
+
+    /// Ends the last subpass.
+    pub unsafe fn end(self) {
+        if self.subpass + 1 != (*self.render_pass).subpasses.len() {
+            warn!("Render pass ends in subpass {}, not the last one", self.subpass);
+        }
+        self.end_subpass();
+    }
+
+    unsafe fn start_subpass(&self) {
+        let render_pass = &*self.render_pass;
+        let subpass = &render_pass.subpasses[self.subpass];
+        for (index, attachment) in render_pass.attachments.iter().enumerate() {
+            let (first, last) = match attachment.uses {
+                Some(uses) => uses,
+                None => continue,
+            };
+            if first == self.subpass {
+                for (aspect, load_op, _) in attachment.operations() {
+                    let bytes = attachment.format.aspect_bytes(aspect);
+                    match load_op {
+                        vk::ATTACHMENT_LOAD_OP_CLEAR => {
+                            self.fill(index, bytes, &self.clear_texels[index]);
+                        }
+                        vk::ATTACHMENT_LOAD_OP_LOAD
+                            if attachment.initial_layout != vk::IMAGE_LAYOUT_UNDEFINED => (),
+                        _ => self.fill_garbage(index, bytes),
+                    }
+                }
+            } else if first < self.subpass && self.subpass < last && !subpass.uses(index) &&
+                       !subpass.preserves.contains(&index)
+            {
+                self.fill_garbage(index, 0..attachment.format.size());
+            }
+        }
+    }
+
+    unsafe fn end_subpass(&self) {
+        let render_pass = &*self.render_pass;
+        let framebuffer = &*self.framebuffer;
+        let subpass = &render_pass.subpasses[self.subpass];
+        for (&color, &resolve) in subpass.colors.iter().zip(&subpass.resolves) {
+            if let (Some(color), Some(resolve)) = (color, resolve) {
+                let destination = &*framebuffer.attachments[resolve];
+                (*framebuffer.attachments[color]).resolve(
+                    destination,
+                    &self.area,
+                    framebuffer.layers,
+                );
+            }
+        }
+        for (index, attachment) in render_pass.attachments.iter().enumerate() {
+            if attachment.uses.map(|(_, last)| last) != Some(self.subpass) {
+                continue;
+            }
+            for (aspect, _, store_op) in attachment.operations() {
+                if store_op == vk::ATTACHMENT_STORE_OP_DONT_CARE {
+                    self.fill_garbage(index, attachment.format.aspect_bytes(aspect));
+                }
+            }
+        }
+    }
+
+    /// Writes `bytes` of `texel` to the render area of attachment `index`.
+    unsafe fn fill(&self, index: usize, bytes: Range<usize>, texel: &[u8]) {
+        let framebuffer = &*self.framebuffer;
+        (*framebuffer.attachments[index]).fill(&self.area, framebuffer.layers, bytes, texel);
+    }
+
+    /// Fills `bytes` of the texels of attachment `index` with garbage, if that is turned on.
+    unsafe fn fill_garbage(&self, index: usize, bytes: Range<usize>) {
+        if !self.garbage {
+            return;
+        }
+        let render_pass = &*self.render_pass;
+        let size = render_pass.attachments[index].format.size();
+        let texel: Vec<u8> = GARBAGE.iter().cycle().take(size).cloned().collect();
+        self.fill(index, bytes, &texel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+    use std::slice;
+    use image::Image;
+    use memory::DeviceMemory;
+    use super::*;
+
+    /// A 4x4 image of its own memory, which starts out as 0x11 bytes, and a view of it.
+    struct Target {
+        image: Box<Image>,
+        view: Box<ImageView>,
+        _memory: DeviceMemory,
+    }
+
+    impl Target {
+        fn new(format: vk::Format, samples: u32) -> Self {
+            let create_info = vk::ImageCreateInfo {
+                sType: vk::STRUCTURE_TYPE_IMAGE_CREATE_INFO,
+                pNext: ptr::null(),
+                flags: 0,
+                imageType: vk::IMAGE_TYPE_2D,
+                format: format,
+                extent: vk::Extent3D {
+                    width: 4,
+                    height: 4,
+                    depth: 1,
+                },
+                mipLevels: 1,
+                arrayLayers: 1,
+                samples: samples,
+                tiling: vk::IMAGE_TILING_OPTIMAL,
+                usage: vk::IMAGE_USAGE_COLOR_ATTACHMENT_BIT,
+                sharingMode: vk::SHARING_MODE_EXCLUSIVE,
+                queueFamilyIndexCount: 0,
+                pQueueFamilyIndices: ptr::null(),
+                initialLayout: vk::IMAGE_LAYOUT_UNDEFINED,
+            };
+            let mut image = Box::new(Image::from_create_info(&create_info, ptr::null()).unwrap());
+            let allocate_info = vk::MemoryAllocateInfo {
+                sType: vk::STRUCTURE_TYPE_MEMORY_ALLOCATE_INFO,
+                pNext: ptr::null(),
+                allocationSize: image.memory_requirements().size,
+                memoryTypeIndex: 0,
+            };
+            let memory = DeviceMemory::from_allocate_info(&allocate_info, ptr::null()).unwrap();
+            unsafe { ptr::write_bytes(memory.at(0), 0x11, memory.size()) };
+            image.bind(&memory, 0).unwrap();
+            let create_info = vk::ImageViewCreateInfo {
+                sType: vk::STRUCTURE_TYPE_IMAGE_VIEW_CREATE_INFO,
+                pNext: ptr::null(),
+                flags: 0,
+                image: &*image as *const Image as vk::Image,
+                viewType: vk::IMAGE_VIEW_TYPE_2D,
+                format: format,
+                components: vk::ComponentMapping {
+                    r: vk::COMPONENT_SWIZZLE_IDENTITY,
+                    g: vk::COMPONENT_SWIZZLE_IDENTITY,
+                    b: vk::COMPONENT_SWIZZLE_IDENTITY,
+                    a: vk::COMPONENT_SWIZZLE_IDENTITY,
+                },
+                subresourceRange: vk::ImageSubresourceRange {
+                    aspectMask: vk::IMAGE_ASPECT_COLOR_BIT,
+                    baseMipLevel: 0,
+                    levelCount: 1,
+                    baseArrayLayer: 0,
+                    layerCount: 1,
+                },
+            };
+            let view = Box::new(ImageView::from_create_info(&create_info, ptr::null()).unwrap());
+            Target {
+                image: image,
+                view: view,
+                _memory: memory,
+            }
+        }
+
+        /// The bytes of all samples of pixel `(x, y)`.
+        fn pixel(&self, x: u32, y: u32) -> &[u8] {
+            let size = self.image.format.size() * self.image.samples as usize;
+            unsafe { slice::from_raw_parts(self.image.texel(0, 0, x, y, 0), size) }
+        }
+
+        fn pixel_mut(&mut self, x: u32, y: u32) -> &mut [u8] {
+            let size = self.image.format.size() * self.image.samples as usize;
+            unsafe { slice::from_raw_parts_mut(self.image.texel(0, 0, x, y, 0), size) }
+        }
+    }
+
+    fn attachment_description(
+        samples: u32,
+        load_op: vk::AttachmentLoadOp,
+        store_op: vk::AttachmentStoreOp,
+    ) -> vk::AttachmentDescription {
+        vk::AttachmentDescription {
+            flags: 0,
+            format: vk::FORMAT_R8G8B8A8_UNORM,
+            samples: samples,
+            loadOp: load_op,
+            storeOp: store_op,
+            stencilLoadOp: vk::ATTACHMENT_LOAD_OP_DONT_CARE,
+            stencilStoreOp: vk::ATTACHMENT_STORE_OP_DONT_CARE,
+            initialLayout: vk::IMAGE_LAYOUT_GENERAL,
+            finalLayout: vk::IMAGE_LAYOUT_GENERAL,
+        }
+    }
+
+    /// References to the attachments `indices` in the color attachment layout.
+    fn references(indices: &[u32]) -> Vec<vk::AttachmentReference> {
+        indices
+            .iter()
+            .map(|&index| {
+                vk::AttachmentReference {
+                    attachment: index,
+                    layout: vk::IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
+                }
+            })
+            .collect()
+    }
+
+    fn subpass_description(
+        colors: &[vk::AttachmentReference],
+        resolves: &[vk::AttachmentReference],
+        preserves: &[u32],
+    ) -> vk::SubpassDescription {
+        vk::SubpassDescription {
+            flags: 0,
+            pipelineBindPoint: vk::PIPELINE_BIND_POINT_GRAPHICS,
+            inputAttachmentCount: 0,
+            pInputAttachments: ptr::null(),
+            colorAttachmentCount: colors.len() as u32,
+            pColorAttachments: colors.as_ptr(),
+            pResolveAttachments: if resolves.is_empty() {
+                ptr::null()
+            } else {
+                resolves.as_ptr()
+            },
+            pDepthStencilAttachment: ptr::null(),
+            preserveAttachmentCount: preserves.len() as u32,
+            pPreserveAttachments: preserves.as_ptr(),
+        }
+    }
+
+    fn create_render_pass(
+        attachments: &[vk::AttachmentDescription],
+        subpasses: &[vk::SubpassDescription],
+        dependencies: &[vk::SubpassDependency],
+    ) -> Result<RenderPass, vk::Result> {
+        let create_info = vk::RenderPassCreateInfo {
+            sType: vk::STRUCTURE_TYPE_RENDER_PASS_CREATE_INFO,
+            pNext: ptr::null(),
+            flags: 0,
+            attachmentCount: attachments.len() as u32,
+            pAttachments: attachments.as_ptr(),
+            subpassCount: subpasses.len() as u32,
+            pSubpasses: subpasses.as_ptr(),
+            dependencyCount: dependencies.len() as u32,
+            pDependencies: dependencies.as_ptr(),
+        };
+        RenderPass::from_create_info(&create_info, ptr::null())
+    }
+
+    fn create_framebuffer(
+        render_pass: &RenderPass,
+        targets: &[&Target],
+    ) -> Result<Framebuffer, vk::Result> {
+        let views: Vec<vk::ImageView> = targets
+            .iter()
+            .map(|target| &*target.view as *const ImageView as vk::ImageView)
+            .collect();
+        let create_info = vk::FramebufferCreateInfo {
+            sType: vk::STRUCTURE_TYPE_FRAMEBUFFER_CREATE_INFO,
+            pNext: ptr::null(),
+            flags: 0,
+            renderPass: render_pass as *const RenderPass as vk::RenderPass,
+            attachmentCount: views.len() as u32,
+            pAttachments: views.as_ptr(),
+            width: 4,
+            height: 4,
+            layers: 1,
+        };
+        Framebuffer::from_create_info(&create_info, ptr::null())
+    }
+
+    fn area(x: i32, y: i32, width: u32, height: u32) -> vk::Rect2D {
+        vk::Rect2D {
+            offset: vk::Offset2D { x: x, y: y },
+            extent: vk::Extent2D {
+                width: width,
+                height: height,
+            },
+        }
+    }
+
+    fn green() -> vk::ClearValue {
+        vk::ClearValue::color(vk::ClearColorValue::float32([0.0, 1.0, 0.0, 1.0]))
+    }
+
+    #[test]
+    fn loads_clears_and_stores() {
+        let attachments = [
+            attachment_description(
+                vk::SAMPLE_COUNT_1_BIT,
+                vk::ATTACHMENT_LOAD_OP_CLEAR,
+                vk::ATTACHMENT_STORE_OP_STORE,
+            ),
+            attachment_description(
+                vk::SAMPLE_COUNT_1_BIT,
+                vk::ATTACHMENT_LOAD_OP_LOAD,
+                vk::ATTACHMENT_STORE_OP_STORE,
+            ),
+            attachment_description(
+                vk::SAMPLE_COUNT_1_BIT,
+                vk::ATTACHMENT_LOAD_OP_DONT_CARE,
+                vk::ATTACHMENT_STORE_OP_DONT_CARE,
+            ),
+        ];
+        let colors = references(&[0, 1, 2]);
+        let subpasses = [subpass_description(&colors, &[], &[])];
+        let render_pass = create_render_pass(&attachments, &subpasses, &[]).unwrap();
+        let values = [green(), green(), green()];
+        let clear_texels = render_pass.clear_texels(&values).unwrap();
+        // Only the cleared attachment needs a value
+        assert_eq!(clear_texels, vec![vec![0, 255, 0, 255], vec![], vec![]]);
+        assert_eq!(render_pass.clear_texels(&[]), None);
+
+        for &garbage in &[false, true] {
+            let mut targets = [
+                Target::new(vk::FORMAT_R8G8B8A8_UNORM, vk::SAMPLE_COUNT_1_BIT),
+                Target::new(vk::FORMAT_R8G8B8A8_UNORM, vk::SAMPLE_COUNT_1_BIT),
+                Target::new(vk::FORMAT_R8G8B8A8_UNORM, vk::SAMPLE_COUNT_1_BIT),
+            ];
+            let framebuffer =
+                create_framebuffer(&render_pass, &[&targets[0], &targets[1], &targets[2]]).unwrap();
+            let dont_care = if garbage { GARBAGE } else { [0x11; 4] };
+            unsafe {
+                let instance = RenderPassInstance::begin(
+                    &render_pass,
+                    &framebuffer,
+                    area(1, 1, 2, 2),
+                    clear_texels.clone(),
+                    garbage,
+                );
+                assert_eq!(targets[0].pixel(1, 2), [0, 255, 0, 255]);
+                assert_eq!(targets[1].pixel(1, 2), [0x11; 4]);
+                assert_eq!(targets[2].pixel(1, 2), dont_care);
+                targets[2].pixel_mut(1, 2).copy_from_slice(&[1, 2, 3, 4]);
+                instance.end();
+            }
+            // Nothing outside of the render area changes
+            for target in &targets {
+                assert_eq!(target.pixel(0, 2), [0x11; 4]);
+                assert_eq!(target.pixel(3, 3), [0x11; 4]);
+            }
+            assert_eq!(targets[0].pixel(2, 2), [0, 255, 0, 255]);
+            let stored = if garbage { GARBAGE } else { [1, 2, 3, 4] };
+            assert_eq!(targets[2].pixel(1, 2), stored);
+        }
+    }
+
+    #[test]
+    fn resolves_and_preserves() {
+        let multisampled = attachment_description(
+            vk::SAMPLE_COUNT_4_BIT,
+            vk::ATTACHMENT_LOAD_OP_CLEAR,
+            vk::ATTACHMENT_STORE_OP_DONT_CARE,
+        );
+        let resolved = attachment_description(
+            vk::SAMPLE_COUNT_1_BIT,
+            vk::ATTACHMENT_LOAD_OP_DONT_CARE,
+            vk::ATTACHMENT_STORE_OP_STORE,
+        );
+        let loaded = |initial_layout| {
+            let mut description = attachment_description(
+                vk::SAMPLE_COUNT_1_BIT,
+                vk::ATTACHMENT_LOAD_OP_LOAD,
+                vk::ATTACHMENT_STORE_OP_STORE,
+            );
+            description.initialLayout = initial_layout;
+            description
+        };
+        let general = vk::IMAGE_LAYOUT_GENERAL;
+        let attachments = [multisampled, resolved, loaded(general), loaded(general)];
+        // The loaded attachments are used in the first and the last subpass, but only the
+        // first one of them is preserved in between
+        let (outer, inner, resolves) = (references(&[2, 3]), references(&[0]), references(&[1]));
+        let subpasses = [
+            subpass_description(&outer, &[], &[]),
+            subpass_description(&inner, &resolves, &[2]),
+            subpass_description(&outer, &[], &[]),
+        ];
+        let render_pass = create_render_pass(&attachments, &subpasses, &[]).unwrap();
+        let mut targets = [
+            Target::new(vk::FORMAT_R8G8B8A8_UNORM, vk::SAMPLE_COUNT_4_BIT),
+            Target::new(vk::FORMAT_R8G8B8A8_UNORM, vk::SAMPLE_COUNT_1_BIT),
+            Target::new(vk::FORMAT_R8G8B8A8_UNORM, vk::SAMPLE_COUNT_1_BIT),
+            Target::new(vk::FORMAT_R8G8B8A8_UNORM, vk::SAMPLE_COUNT_1_BIT),
+        ];
+        let framebuffer =
+            create_framebuffer(&render_pass, &[&targets[0], &targets[1], &targets[2], &targets[3]])
+                .unwrap();
+        let clear_texels = render_pass.clear_texels(&[green()]).unwrap();
+        unsafe {
+            let mut instance = RenderPassInstance::begin(
+                &render_pass,
+                &framebuffer,
+                area(0, 0, 4, 4),
+                clear_texels,
+                true,
+            );
+            // Attachments are cleared when they are first used
+            assert_eq!(targets[0].pixel(0, 0), [0x11; 16]);
+            instance.next_subpass();
+            assert_eq!(targets[0].pixel(3, 3), [0, 255, 0, 255].repeat(4).as_slice());
+            assert_eq!(targets[2].pixel(0, 0), [0x11; 4]);
+            assert_eq!(targets[3].pixel(0, 0), GARBAGE);
+            for (sample, &red) in [0, 100, 100, 200].iter().enumerate() {
+                targets[0].pixel_mut(0, 0)[sample * 4] = red;
+            }
+            instance.next_subpass();
+            assert_eq!(targets[0].pixel(0, 0), GARBAGE.repeat(4).as_slice());
+            assert_eq!(targets[1].pixel(0, 0), [100, 255, 0, 255]);
+            assert_eq!(targets[1].pixel(1, 0), [0, 255, 0, 255]);
+            instance.end();
+        }
+        assert_eq!(targets[1].pixel(0, 0), [100, 255, 0, 255]);
+
+        // Loads from the undefined layout load garbage
+        let undefined = vk::IMAGE_LAYOUT_UNDEFINED;
+        let attachments = [loaded(undefined), loaded(undefined)];
+        let colors = references(&[0, 1]);
+        let subpasses = [subpass_description(&colors, &[], &[])];
+        let render_pass = create_render_pass(&attachments, &subpasses, &[]).unwrap();
+        let framebuffer = create_framebuffer(&render_pass, &[&targets[2], &targets[3]]).unwrap();
+        unsafe {
+            RenderPassInstance::begin(&render_pass, &framebuffer, area(0, 0, 1, 1), vec![], true)
+                .end();
+        }
+        assert_eq!(targets[2].pixel(0, 0), GARBAGE);
+    }
+
+    #[test]
+    fn validates_render_passes() {
+        let single = || {
+            attachment_description(
+                vk::SAMPLE_COUNT_1_BIT,
+                vk::ATTACHMENT_LOAD_OP_LOAD,
+                vk::ATTACHMENT_STORE_OP_STORE,
+            )
+        };
+        let multisampled = || {
+            attachment_description(
+                vk::SAMPLE_COUNT_4_BIT,
+                vk::ATTACHMENT_LOAD_OP_LOAD,
+                vk::ATTACHMENT_STORE_OP_STORE,
+            )
+        };
+        let (first, second) = (references(&[0]), references(&[1]));
+        let subpass = || subpass_description(&first, &[], &[]);
+        assert!(create_render_pass(&[single()], &[subpass()], &[]).is_ok());
+        assert_eq!(
+            create_render_pass(&[single()], &[], &[]).err(),
+            Some(vk::ERROR_OUT_OF_HOST_MEMORY)
+        );
+
+        let mut unsupported = single();
+        unsupported.format = vk::FORMAT_R8G8B8_UNORM;
+        assert_eq!(
+            create_render_pass(&[unsupported], &[subpass()], &[]).err(),
+            Some(vk::ERROR_FORMAT_NOT_SUPPORTED)
+        );
+        // Attachments with a single sample cannot be resolved
+        let resolving = || subpass_description(&first, &second, &[]);
+        assert_eq!(
+            create_render_pass(&[single(), single()], &[resolving()], &[]).err(),
+            Some(vk::ERROR_OUT_OF_HOST_MEMORY)
+        );
+        assert!(create_render_pass(&[multisampled(), single()], &[resolving()], &[]).is_ok());
+        // Color attachments of a subpass have the same sample count
+        let both = references(&[0, 1]);
+        let subpasses = [subpass_description(&both, &[], &[])];
+        assert_eq!(
+            create_render_pass(&[multisampled(), single()], &subpasses, &[]).err(),
+            Some(vk::ERROR_OUT_OF_HOST_MEMORY)
+        );
+        assert_eq!(
+            create_render_pass(&[single()], &[subpass_description(&first, &[], &[0])], &[]).err(),
+            Some(vk::ERROR_OUT_OF_HOST_MEMORY)
+        );
+
+        let dependency = |source, destination| {
+            vk::SubpassDependency {
+                srcSubpass: source,
+                dstSubpass: destination,
+                srcStageMask: vk::PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT,
+                dstStageMask: vk::PIPELINE_STAGE_FRAGMENT_SHADER_BIT,
+                srcAccessMask: vk::ACCESS_COLOR_ATTACHMENT_WRITE_BIT,
+                dstAccessMask: vk::ACCESS_INPUT_ATTACHMENT_READ_BIT,
+                dependencyFlags: vk::DEPENDENCY_BY_REGION_BIT,
+            }
+        };
+        let subpasses = [subpass(), subpass()];
+        for &(source, destination, valid) in
+            &[
+                (0, 1, true),
+                (vk::SUBPASS_EXTERNAL, 0, true),
+                (1, vk::SUBPASS_EXTERNAL, true),
+                (1, 0, false),
+                (0, 2, false),
+                (vk::SUBPASS_EXTERNAL, vk::SUBPASS_EXTERNAL, false),
+            ]
+        {
+            let dependencies = [dependency(source, destination)];
+            let created = create_render_pass(&[single()], &subpasses, &dependencies);
+            assert_eq!(created.is_ok(), valid, "{} to {}", source, destination);
+        }
+
+        // Framebuffers take views of the formats and sample counts of the attachments
+        let render_pass = create_render_pass(&[single()], &[subpass()], &[]).unwrap();
+        let target = Target::new(vk::FORMAT_R8G8B8A8_UNORM, vk::SAMPLE_COUNT_1_BIT);
+        assert!(create_framebuffer(&render_pass, &[&target]).is_ok());
+        let target = Target::new(vk::FORMAT_R8G8B8A8_UNORM, vk::SAMPLE_COUNT_4_BIT);
+        assert_eq!(
+            create_framebuffer(&render_pass, &[&target]).err(),
+            Some(vk::ERROR_OUT_OF_HOST_MEMORY)
+        );
+        assert_eq!(
+            create_framebuffer(&render_pass, &[]).err(),
+            Some(vk::ERROR_OUT_OF_HOST_MEMORY)
+        );
+    }
+}
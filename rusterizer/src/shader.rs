@@ -0,0 +1,410 @@
+//! Shader modules and the code compiled from them.
+use std::collections::HashMap;
+use std::slice;
+use std::sync::Arc;
+
+use rspirv::mr;
+use spirv_headers::ExecutionModel;
+#[cfg(feature = "cranelift")]
+use spirv_llvm::CraneliftModule;
+#[cfg(feature = "interpreter")]
+use spirv_llvm::Interpreter;
+#[cfg(feature = "llvm")]
+use spirv_llvm::{JitModule, LlvmModule, NativeCode, Target};
+use spirv_llvm::{self, Backend, EntryPoint, EntryPointInfo, ExecutionModes, ShaderCode};
+use ffi_types as vk;
+use debug_report::DebugReport;
+use hash;
+#[cfg(feature = "llvm")]
+use hash::Sha256;
+use pipeline_cache::PipelineCache;
+#[cfg(feature = "llvm")]
+use shader_cache::{self, ShaderCache};
+
+/// A parsed SPIR-V module. Compilation is deferred until a pipeline is created from it.
+pub struct ShaderModule {
+    module: Arc<mr::Module>,
+    /// SHA-256 of the SPIR-V code, which names the module in pipeline caches.
+    hash: [u8; 32],
+    debug_report: Arc<DebugReport>,
+    /// The shader cache on disk that native code is kept in, if it is on.
+    #[cfg(feature = "llvm")]
+    disk: Option<&'static ShaderCache>,
+}
+
+impl ShaderModule {
+    pub fn from_create_info(
+        create_info: &vk::ShaderModuleCreateInfo,
+        alloc: *const vk::AllocationCallbacks,
+        debug_report: Arc<DebugReport>,
+    ) -> Result<Self, vk::Result> {
+        debug_assert_eq!(
+            create_info.sType,
+            vk::STRUCTURE_TYPE_SHADER_MODULE_CREATE_INFO
+        );
+        debug_assert!(create_info.pNext.is_null());
+        if !alloc.is_null() {
+            warn!("Custom allocators not supported by driver");
+            return Err(vk::ERROR_INITIALIZATION_FAILED);
+        }
+        // codeSize is in bytes, but always a multiple of 4
+        let code = unsafe { slice::from_raw_parts(create_info.pCode, create_info.codeSize / 4) };
+        let bytes =
+            unsafe { slice::from_raw_parts(create_info.pCode as *const u8, code.len() * 4) };
+        match mr::load_words(code) {
+            Ok(module) => Ok(ShaderModule {
+                module: Arc::new(module),
+                hash: hash::sha256(bytes),
+                debug_report: debug_report,
+                #[cfg(feature = "llvm")]
+                disk: shader_cache::shader_cache(),
+            }),
+            Err(err) => {
+                let message = format!("Could not parse SPIR-V module: {:?}", err);
+                warn!("{}", message);
+                debug_report.message(
+                    vk::DEBUG_REPORT_ERROR_BIT_EXT,
+                    vk::DEBUG_REPORT_OBJECT_TYPE_SHADER_MODULE_EXT,
+                    0,
+                    "rusterizer",
+                    &message,
+                );
+                Err(vk::ERROR_INVALID_SHADER_NV)
+            }
+        }
+    }
+
+    /// Warns about `message` in the log and the debug callbacks of the application.
+    fn report(&self, flags: vk::DebugReportFlagsEXT, message: &str) {
+        warn!("{}", message);
+        self.debug_report.message(
+            flags,
+            vk::DEBUG_REPORT_OBJECT_TYPE_SHADER_MODULE_EXT,
+            self as *const _ as u64,
+            "spirv_llvm",
+            message,
+        );
+    }
+
+    /// Compiles the module for the backend `SPIRV_LLVM_BACKEND` picks, native code by default,
+    /// with the specialization constants set to the values in `specialization`. Native code is
+    /// taken from `cache` if it has it, and added to it otherwise.
+    ///
+    /// Errors are passed on to the debug callbacks, the pipeline then fails to compile with
+    /// `ERROR_INVALID_SHADER_NV`.
+    pub fn compile(
+        &self,
+        specialization: Option<&vk::SpecializationInfo>,
+        cache: Option<&PipelineCache>,
+    ) -> Result<Shader, vk::Result> {
+        let values = specialization.map_or_else(HashMap::new, |info| unsafe {
+            specialization_values(info)
+        });
+        let module = match specialization {
+            Some(_) => {
+                let module = spirv_llvm::specialize(&self.module, &values).map_err(|err| {
+                    self.report(
+                        vk::DEBUG_REPORT_ERROR_BIT_EXT,
+                        &format!("Could not specialize SPIR-V module: {}", err),
+                    );
+                    vk::ERROR_INVALID_SHADER_NV
+                })?;
+                Arc::new(module)
+            }
+            None => self.module.clone(),
+        };
+        let entry_points = spirv_llvm::validate(&module).map_err(|err| {
+            self.report(
+                vk::DEBUG_REPORT_ERROR_BIT_EXT,
+                &format!("Unsupported SPIR-V module: {}", err),
+            );
+            vk::ERROR_INVALID_SHADER_NV
+        })?;
+        let code = match Backend::from_env() {
+            #[cfg(feature = "llvm")]
+            Backend::Llvm => ShaderCode::Jit(self.compile_native(&module, &values, cache)?),
+            #[cfg(feature = "cranelift")]
+            Backend::Cranelift => {
+                let module = CraneliftModule::new(module).map_err(|err| {
+                    self.report(
+                        vk::DEBUG_REPORT_ERROR_BIT_EXT,
+                        &format!("Could not compile SPIR-V module with Cranelift: {}", err),
+                    );
+                    vk::ERROR_INVALID_SHADER_NV
+                })?;
+                ShaderCode::Cranelift(module)
+            }
+            #[cfg(feature = "interpreter")]
+            Backend::Interpreter => {
+                let interpreter = Interpreter::new(module).map_err(|err| {
+                    self.report(
+                        vk::DEBUG_REPORT_ERROR_BIT_EXT,
+                        &format!("Could not interpret SPIR-V module: {}", err),
+                    );
+                    vk::ERROR_INVALID_SHADER_NV
+                })?;
+                ShaderCode::Interpreted(interpreter)
+            }
+        };
+        Ok(Shader {
+            code: code,
+            entry_points: entry_points,
+        })
+    }
+
+    /// Compiles the module to native code for the host, running as many invocations at once
+    /// as the vector registers of the host CPU fit.
+    ///
+    /// With a cache, or the shader cache on disk, the code goes through `NativeCode`, which can
+    /// be kept and loaded again without LLVM. Code found on disk is added to `cache` as well.
+    /// Without either, or if this host cannot load such code, MCJIT compiles it.
+    #[cfg(feature = "llvm")]
+    fn compile_native(
+        &self,
+        module: &mr::Module,
+        values: &HashMap<u32, Vec<u8>>,
+        cache: Option<&PipelineCache>,
+    ) -> Result<JitModule, vk::Result> {
+        let disk = self.disk;
+        if (cache.is_none() && disk.is_none()) || !NativeCode::supported() {
+            let llvm = self.translate(module)?;
+            return JitModule::new(llvm).map_err(|err| self.compile_error(&err));
+        }
+        let key = self.cache_key(values);
+        let cached = match cache.and_then(|cache| cache.get(&key)) {
+            Some(bytes) => Some(bytes),
+            None => {
+                let bytes = disk.and_then(|disk| disk.get(&key));
+                if let (Some(cache), Some(bytes)) = (cache, bytes.as_ref()) {
+                    cache.insert(key, bytes.clone());
+                }
+                bytes
+            }
+        };
+        if let Some(bytes) = cached {
+            match NativeCode::from_bytes(&bytes).map(|code| JitModule::load(&code)) {
+                Some(Ok(jit)) => return Ok(jit),
+                Some(Err(err)) => {
+                    warn!("Could not load cached shader, compiling it again: {}", err)
+                }
+                None => warn!("Cached shader is not native code, compiling it again"),
+            }
+        }
+        let llvm = self.translate(module)?;
+        let code = NativeCode::compile(llvm).map_err(|err| self.compile_error(&err))?;
+        let bytes = code.to_bytes();
+        if let Some(disk) = disk {
+            disk.insert(&key, &bytes);
+        }
+        if let Some(cache) = cache {
+            cache.insert(key, bytes);
+        }
+        JitModule::load(&code).map_err(|err| self.compile_error(&err))
+    }
+
+    /// Translates the module to LLVM IR, vectorized if possible.
+    #[cfg(feature = "llvm")]
+    fn translate(&self, module: &mr::Module) -> Result<LlvmModule, vk::Result> {
+        let lanes = spirv_llvm::simd_lanes();
+        match spirv_llvm::spirv_to_llvm_simd(module, lanes) {
+            Ok(llvm) => Ok(llvm),
+            Err(err) => {
+                self.report(
+                    vk::DEBUG_REPORT_PERFORMANCE_WARNING_BIT_EXT,
+                    &format!(
+                        "Could not vectorize shader, running one invocation at a time: {}",
+                        err
+                    ),
+                );
+                spirv_llvm::spirv_to_llvm(module).map_err(|err| {
+                    self.report(
+                        vk::DEBUG_REPORT_ERROR_BIT_EXT,
+                        &format!("Could not translate SPIR-V module: {}", err),
+                    );
+                    vk::ERROR_INVALID_SHADER_NV
+                })
+            }
+        }
+    }
+
+    #[cfg(feature = "llvm")]
+    fn compile_error(&self, err: &spirv_llvm::TranspilerError) -> vk::Result {
+        self.report(
+            vk::DEBUG_REPORT_ERROR_BIT_EXT,
+            &format!("Could not compile shader: {}", err),
+        );
+        vk::ERROR_INVALID_SHADER_NV
+    }
+
+    /// The key of the native code of the module in pipeline caches: a hash of the module, the
+    /// specialization constants and the target and optimization level the code is compiled
+    /// for, as the CPU features decide which instructions the code may use.
+    #[cfg(feature = "llvm")]
+    fn cache_key(&self, values: &HashMap<u32, Vec<u8>>) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(&self.hash);
+        let mut ids: Vec<_> = values.keys().collect();
+        ids.sort();
+        hasher.update_u64(ids.len() as u64);
+        for id in ids {
+            hasher.update_u64(u64::from(*id));
+            hasher.update_u64(values[id].len() as u64);
+            hasher.update(&values[id]);
+        }
+        let target = Target::from_env();
+        for text in &[target.triple, target.cpu, target.features] {
+            hasher.update_u64(text.len() as u64);
+            hasher.update(text.as_bytes());
+        }
+        hasher.update_u64(u64::from(spirv_llvm::opt_level_from_env()));
+        hasher.finish()
+    }
+}
+
+/// Collects the values of `VkSpecializationInfo` by SpecId.
+///
+/// The pointers of `info` have to be valid, as Vulkan requires of the application.
+unsafe fn specialization_values(info: &vk::SpecializationInfo) -> HashMap<u32, Vec<u8>> {
+    let mut values = HashMap::new();
+    if info.mapEntryCount == 0 {
+        return values;
+    }
+    let entries = slice::from_raw_parts(info.pMapEntries, info.mapEntryCount as usize);
+    // pData may be null without any data
+    let data = if info.dataSize == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(info.pData as *const u8, info.dataSize)
+    };
+    for entry in entries {
+        let start = entry.offset as usize;
+        match start.checked_add(entry.size) {
+            Some(end) if end <= data.len() => {
+                values.insert(entry.constantID, data[start..end].to_vec());
+            }
+            _ => warn!("Value of specialization constant {} is out of bounds", entry.constantID),
+        }
+    }
+    values
+}
+
+/// A shader module compiled for one of the backends.
+pub struct Shader {
+    code: ShaderCode,
+    entry_points: Vec<EntryPointInfo>,
+}
+
+impl Shader {
+    /// Returns the entry point `name` of the given stage, which can be called to run the shader.
+    pub fn entry_point<'a>(
+        &'a self,
+        name: &str,
+        stage: vk::ShaderStageFlagBits,
+    ) -> Option<EntryPoint<'a>> {
+        self.info(name, stage)?;
+        self.code.entry_point(name)
+    }
+
+    /// Returns what the execution modes of entry point `name` ask of the pipeline.
+    pub fn execution_modes(
+        &self,
+        name: &str,
+        stage: vk::ShaderStageFlagBits,
+    ) -> Option<&ExecutionModes> {
+        self.info(name, stage).map(|info| &info.modes)
+    }
+
+    /// Returns the entry point `name` of the given stage as `spirv_llvm::validate` describes it.
+    pub fn info(&self, name: &str, stage: vk::ShaderStageFlagBits) -> Option<&EntryPointInfo> {
+        let model = match stage {
+            vk::SHADER_STAGE_VERTEX_BIT => ExecutionModel::Vertex,
+            vk::SHADER_STAGE_FRAGMENT_BIT => ExecutionModel::Fragment,
+            vk::SHADER_STAGE_COMPUTE_BIT => ExecutionModel::GLCompute,
+            _ => {
+                warn!("Shader stage {:#x} not supported", stage);
+                return None;
+            }
+        };
+        self.entry_points.iter().find(
+            |info| info.name == name && info.model == model,
+        )
+    }
+}
+
+#[cfg(all(test, feature = "llvm"))]
+mod tests {
+    use std::ptr;
+    use super::*;
+
+    const SHADER: &[u8] = include_bytes!("../../spirv_llvm/tests/corpus/shared.comp.spv");
+
+    fn module() -> ShaderModule {
+        let words: Vec<u32> = SHADER
+            .chunks(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect();
+        let create_info = vk::ShaderModuleCreateInfo {
+            sType: vk::STRUCTURE_TYPE_SHADER_MODULE_CREATE_INFO,
+            pNext: ptr::null(),
+            flags: 0,
+            codeSize: SHADER.len(),
+            pCode: words.as_ptr(),
+        };
+        let mut module =
+            ShaderModule::from_create_info(&create_info, ptr::null(), Arc::default()).unwrap();
+        // Only the pipeline cache may have the code
+        module.disk = None;
+        module
+    }
+
+    #[test]
+    fn compiles_from_pipeline_cache() {
+        if !NativeCode::supported() || Backend::from_env() != Backend::Llvm {
+            return;
+        }
+        let cache = PipelineCache::default();
+        module().compile(None, Some(&cache)).unwrap();
+        let (data, _) = cache.data(usize::MAX);
+        // One entry after the header
+        assert!(data.len() > 32);
+
+        // The code is found again by a cache loaded from the data
+        let create_info = vk::PipelineCacheCreateInfo {
+            sType: vk::STRUCTURE_TYPE_PIPELINE_CACHE_CREATE_INFO,
+            pNext: ptr::null(),
+            flags: 0,
+            initialDataSize: data.len(),
+            pInitialData: data.as_ptr() as *const _,
+        };
+        let loaded = PipelineCache::from_create_info(&create_info, ptr::null()).unwrap();
+        let shader = module().compile(None, Some(&loaded)).unwrap();
+        assert!(shader.entry_point("main", vk::SHADER_STAGE_COMPUTE_BIT).is_some());
+        assert_eq!(loaded.data(usize::MAX).0, data);
+    }
+
+    #[test]
+    fn bounds_specialization_values() {
+        let entry = |id, offset, size| {
+            vk::SpecializationMapEntry {
+                constantID: id,
+                offset: offset,
+                size: size,
+            }
+        };
+        let entries = [entry(0, 0, 4), entry(1, 4, 4), entry(2, !0, 2), entry(3, 2, !0)];
+        let data = [1u8, 2, 3, 4, 5, 6];
+        let mut info = vk::SpecializationInfo {
+            mapEntryCount: entries.len() as u32,
+            pMapEntries: entries.as_ptr(),
+            dataSize: data.len(),
+            pData: data.as_ptr() as *const _,
+        };
+        let values = unsafe { specialization_values(&info) };
+        assert_eq!(values.len(), 1);
+        assert_eq!(values[&0], [1, 2, 3, 4]);
+
+        info.dataSize = 0;
+        info.pData = ptr::null();
+        assert!(unsafe { specialization_values(&info) }.is_empty());
+    }
+}
@@ -0,0 +1,382 @@
+//! The shader cache on disk, which keeps native code across runs of applications that do not
+//! use pipeline caches, or start out with empty ones.
+//!
+//! Every shader is a file named by its pipeline cache key, in a directory named by a hash of
+//! the `pipelineCacheUUID` and the target CPU, so that code of another build of the driver or
+//! another machine sharing the cache is never loaded. A file starts with the SHA-256 of the
+//! code, entries that do not match it are deleted. Files are written to a temporary file first
+//! and renamed into place, so that readers never see half of one. Once the cache is larger
+//! than its limit, the files that were used the longest time ago are deleted. Eviction only
+//! touches directories and files named like the ones the cache makes, since the cache may be
+//! put into a directory that holds other files too.
+use std::env;
+use std::ffi::OsStr;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Mutex, OnceLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::SystemTime;
+
+use spirv_llvm::Target;
+use hash::{self, Sha256};
+use pipeline_cache;
+
+/// Environment variable that moves the cache to another directory, or turns it off if it is
+/// "0".
+pub const CACHE_VARIABLE: &str = "RUSTERIZER_SHADER_CACHE";
+/// Environment variable that limits the size of the cache, in bytes or with a suffix of K, M or
+/// G.
+pub const CACHE_SIZE_VARIABLE: &str = "RUSTERIZER_SHADER_CACHE_SIZE";
+
+/// Size of the cache if `RUSTERIZER_SHADER_CACHE_SIZE` does not say otherwise.
+pub const DEFAULT_CACHE_SIZE: u64 = 256 << 20;
+
+/// Shaders compiled by earlier runs, or by other threads of this one.
+#[derive(Debug)]
+pub struct ShaderCache {
+    /// Holds the directories of all driver builds, which share the size limit.
+    root: PathBuf,
+    /// The directory of this build of the driver and target.
+    directory: PathBuf,
+    max_size: u64,
+    /// Bytes in the cache when it was last counted, plus what this process wrote since. `None`
+    /// until the first insert counts them.
+    size: Mutex<Option<u64>>,
+}
+
+/// The cache `RUSTERIZER_SHADER_CACHE` asks for, by default in `$XDG_CACHE_HOME/rusterizer` or
+/// `~/.cache/rusterizer`. `None` if it is turned off or there is no home directory.
+pub fn shader_cache() -> Option<&'static ShaderCache> {
+    static CACHE: OnceLock<Option<ShaderCache>> = OnceLock::new();
+    CACHE
+        .get_or_init(|| {
+            let root = match variable(CACHE_VARIABLE) {
+                Some(ref value) if value == "0" => return None,
+                Some(value) => PathBuf::from(value),
+                None => {
+                    let cache = variable("XDG_CACHE_HOME").map(PathBuf::from).or_else(|| {
+                        variable("HOME").map(|home| Path::new(&home).join(".cache"))
+                    })?;
+                    cache.join("rusterizer")
+                }
+            };
+            let max_size = match variable(CACHE_SIZE_VARIABLE) {
+                Some(value) => {
+                    parse_size(&value).unwrap_or_else(|| {
+                        warn!("Ignoring shader cache size {}", value);
+                        DEFAULT_CACHE_SIZE
+                    })
+                }
+                None => DEFAULT_CACHE_SIZE,
+            };
+            Some(ShaderCache::new(root, max_size))
+        })
+        .as_ref()
+}
+
+impl ShaderCache {
+    /// A cache in `root`, which is created once the first shader is written to it.
+    pub fn new(root: PathBuf, max_size: u64) -> Self {
+        let target = Target::from_env();
+        let mut hasher = Sha256::new();
+        hasher.update(&pipeline_cache::uuid());
+        for text in &[target.triple, target.cpu, target.features] {
+            hasher.update_u64(text.len() as u64);
+            hasher.update(text.as_bytes());
+        }
+        let version = hex(&hasher.finish()[..16]);
+        ShaderCache {
+            directory: root.join(version),
+            root: root,
+            max_size: max_size,
+            size: Mutex::new(None),
+        }
+    }
+
+    /// Returns the code cached for `key`, and marks it as used.
+    pub fn get(&self, key: &[u8; 32]) -> Option<Vec<u8>> {
+        let path = self.directory.join(hex(key));
+        let mut data = fs::read(&path).ok()?;
+        if data.len() < 32 || hash::sha256(&data[32..]) != data[..32] {
+            warn!("Deleting corrupt shader cache entry {}", path.display());
+            let _ = fs::remove_file(&path);
+            return None;
+        }
+        let used = OpenOptions::new().write(true).open(&path).and_then(|file| {
+            file.set_modified(SystemTime::now())
+        });
+        if let Err(err) = used {
+            debug!("Could not mark {} as used: {}", path.display(), err);
+        }
+        data.drain(..32);
+        Some(data)
+    }
+
+    /// Writes `code` to the cache, and makes room for it if the cache grows too large.
+    pub fn insert(&self, key: &[u8; 32], code: &[u8]) {
+        if let Err(err) = self.write(key, code) {
+            warn!(
+                "Could not write to shader cache {}: {}",
+                self.directory.display(),
+                err
+            );
+            return;
+        }
+        // The files are only counted again once the entries of this process could have filled
+        // the cache, other processes make room for theirs themselves
+        let written = 32 + code.len() as u64;
+        let mut size = self.size.lock().unwrap();
+        *size = match *size {
+            Some(size) if size + written <= self.max_size => Some(size + written),
+            _ => Some(self.evict()),
+        };
+    }
+
+    fn write(&self, key: &[u8; 32], code: &[u8]) -> io::Result<()> {
+        // Threads and processes writing the same entry at once each use a file of their own
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        fs::create_dir_all(&self.directory)?;
+        let path = self.directory.join(hex(key));
+        let temporary = self.directory.join(format!(
+            "{}.{}.{}.tmp",
+            hex(key),
+            process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let written = File::create(&temporary).and_then(|mut file| {
+            file.write_all(&hash::sha256(code))?;
+            file.write_all(code)?;
+            // The data has to be on disk before the rename is, or a crash could leave an
+            // entry with garbage behind
+            file.sync_all()
+        });
+        let renamed = written.and_then(|_| fs::rename(&temporary, &path));
+        if renamed.is_err() {
+            let _ = fs::remove_file(&temporary);
+        }
+        renamed
+    }
+
+    /// Deletes the least recently used files of all driver builds until the cache fits into
+    /// its size limit again, and returns the size of what is left.
+    fn evict(&self) -> u64 {
+        let mut files = Vec::new();
+        let mut size = 0;
+        let directories = match fs::read_dir(&self.root) {
+            Ok(directories) => directories,
+            Err(_) => return 0,
+        };
+        for directory in directories.filter_map(Result::ok) {
+            if !is_version(&directory.file_name()) {
+                continue;
+            }
+            let entries = match fs::read_dir(directory.path()) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries.filter_map(Result::ok) {
+                if !is_entry(&entry.file_name()) {
+                    continue;
+                }
+                if let Ok(metadata) = entry.metadata() {
+                    if !metadata.is_file() {
+                        continue;
+                    }
+                    let used = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                    size += metadata.len();
+                    files.push((used, metadata.len(), entry.path()));
+                }
+            }
+        }
+        if size <= self.max_size {
+            return size;
+        }
+        files.sort();
+        for (_, length, path) in files {
+            if size <= self.max_size {
+                break;
+            }
+            if fs::remove_file(&path).is_ok() {
+                size -= length;
+            }
+            // Directories of other builds go away with their last file
+            if let Some(directory) = path.parent() {
+                if directory != self.directory {
+                    let _ = fs::remove_dir(directory);
+                }
+            }
+        }
+        size
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn is_hex(text: &str, digits: usize) -> bool {
+    text.len() == digits &&
+        text.bytes().all(|byte| {
+            byte.is_ascii_digit() || b"abcdef".contains(&byte)
+        })
+}
+
+/// Whether `name` is that of the directory of a driver build.
+fn is_version(name: &OsStr) -> bool {
+    match name.to_str() {
+        Some(name) => is_hex(name, 32),
+        None => false,
+    }
+}
+
+/// Whether `name` is that of an entry, or of the temporary file one is written to.
+fn is_entry(name: &OsStr) -> bool {
+    let name = match name.to_str() {
+        Some(name) => name,
+        None => return false,
+    };
+    let mut parts = name.split('.');
+    if !is_hex(parts.next().unwrap_or(""), 64) {
+        return false;
+    }
+    let rest = parts.collect::<Vec<_>>();
+    match *rest {
+        [] => true,
+        [pid, n, "tmp"] => {
+            [pid, n].iter().all(|number| {
+                !number.is_empty() && number.bytes().all(|byte| byte.is_ascii_digit())
+            })
+        }
+        _ => false,
+    }
+}
+
+/// Parses a size such as `1048576`, `1024K` or `1M`.
+fn parse_size(value: &str) -> Option<u64> {
+    let (number, shift) = match value.char_indices().last()? {
+        (index, 'K') | (index, 'k') => (&value[..index], 10),
+        (index, 'M') | (index, 'm') => (&value[..index], 20),
+        (index, 'G') | (index, 'g') => (&value[..index], 30),
+        _ => (value, 0),
+    };
+    let number: u64 = number.trim().parse().ok()?;
+    number.checked_mul(1 << shift)
+}
+
+/// The value of the environment variable `name`, unless it is unset, empty or not Unicode.
+fn variable(name: &str) -> Option<String> {
+    env::var(name).ok().map(|value| value.trim().to_owned()).filter(
+        |value| !value.is_empty(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::*;
+
+    /// A cache in a directory of its own, deleted when the test is done.
+    struct TestCache(ShaderCache);
+
+    impl TestCache {
+        fn new(name: &str, max_size: u64) -> Self {
+            let root = env::temp_dir().join(format!("rusterizer-{}-{}", name, process::id()));
+            let _ = fs::remove_dir_all(&root);
+            TestCache(ShaderCache::new(root, max_size))
+        }
+
+        fn path(&self, key: &[u8; 32]) -> PathBuf {
+            self.0.directory.join(hex(key))
+        }
+    }
+
+    impl Drop for TestCache {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0.root);
+        }
+    }
+
+    #[test]
+    fn keeps_code_and_deletes_corrupt_entries() {
+        let cache = TestCache::new("corrupt", DEFAULT_CACHE_SIZE);
+        assert_eq!(cache.0.get(&[1; 32]), None);
+        cache.0.insert(&[1; 32], &[1, 2, 3]);
+        cache.0.insert(&[2; 32], &[4, 5]);
+        assert_eq!(cache.0.get(&[1; 32]), Some(vec![1, 2, 3]));
+        assert_eq!(cache.0.get(&[2; 32]), Some(vec![4, 5]));
+        // Nothing but the entries is left behind
+        assert_eq!(fs::read_dir(&cache.0.directory).unwrap().count(), 2);
+
+        let mut data = fs::read(cache.path(&[1; 32])).unwrap();
+        data[33] ^= 1;
+        fs::write(cache.path(&[1; 32]), &data).unwrap();
+        assert_eq!(cache.0.get(&[1; 32]), None);
+        assert!(!cache.path(&[1; 32]).exists());
+        assert_eq!(cache.0.get(&[2; 32]), Some(vec![4, 5]));
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        // Room for two entries of 32 + 8 bytes
+        let cache = TestCache::new("evict", 80);
+        let old = SystemTime::now() - Duration::from_secs(60);
+        let stale = cache.0.root.join(hex(&[0; 16]));
+        let entry = stale.join(hex(&[0; 32]));
+        fs::create_dir_all(&stale).unwrap();
+        fs::write(&entry, [0; 48]).unwrap();
+        File::open(&entry).unwrap().set_modified(old).unwrap();
+
+        cache.0.insert(&[1; 32], &[1; 8]);
+        // The entry of another build was used the longest time ago
+        assert!(!stale.exists());
+        cache.0.insert(&[2; 32], &[2; 8]);
+        for (key, age) in &[([1; 32], 30), ([2; 32], 10)] {
+            let file = OpenOptions::new().write(true).open(cache.path(key)).unwrap();
+            file.set_modified(SystemTime::now() - Duration::from_secs(*age)).unwrap();
+        }
+        // Using the older entry keeps it, so the other one goes
+        assert_eq!(cache.0.get(&[1; 32]), Some(vec![1; 8]));
+        cache.0.insert(&[3; 32], &[3; 8]);
+        assert_eq!(cache.0.get(&[2; 32]), None);
+        assert_eq!(cache.0.get(&[1; 32]), Some(vec![1; 8]));
+        assert_eq!(cache.0.get(&[3; 32]), Some(vec![3; 8]));
+    }
+
+    #[test]
+    fn leaves_other_files_alone() {
+        let cache = TestCache::new("others", 40);
+        let old = SystemTime::now() - Duration::from_secs(60);
+        let other = cache.0.root.join("other");
+        fs::create_dir_all(&other).unwrap();
+        fs::create_dir_all(&cache.0.directory).unwrap();
+        let paths = [
+            other.join(hex(&[0; 32])),
+            cache.0.root.join(hex(&[0; 32])),
+            cache.0.directory.join("notes"),
+            cache.0.directory.join(format!("{}.tmp", hex(&[0; 32]))),
+        ];
+        for path in &paths {
+            fs::write(path, [0; 48]).unwrap();
+            File::open(path).unwrap().set_modified(old).unwrap();
+        }
+        let temporary = cache.0.directory.join(format!("{}.1.2.tmp", hex(&[0; 32])));
+        fs::write(&temporary, [0; 48]).unwrap();
+        File::open(&temporary).unwrap().set_modified(old).unwrap();
+
+        cache.0.insert(&[1; 32], &[1; 8]);
+        // Only the leftover temporary file is the cache's to delete
+        assert!(paths.iter().all(|path| path.exists()));
+        assert!(!temporary.exists());
+        assert_eq!(cache.0.get(&[1; 32]), Some(vec![1; 8]));
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("1048576"), Some(1 << 20));
+        assert_eq!(parse_size("64K"), Some(64 << 10));
+        assert_eq!(parse_size("2 G"), Some(2 << 30));
+        assert_eq!(parse_size("lots"), None);
+    }
+}
@@ -0,0 +1,332 @@
+//! Sort-middle rendering: triangles are sorted into bins of the screen tiles they touch, then the
+//! tiles are rasterized and shaded in parallel.
+//!
+//! Every tile is drawn by a single thread, which goes through the triangles of its bin in the
+//! order they were added, so that blending and depth tests see the fragments of every pixel in
+//! API order. Tiles are handed out to one thread per CPU, as many as `RUSTERIZER_THREADS` asks
+//! for. With one thread, all tiles are drawn on the calling thread row by row, which makes
+//! rendering deterministic for debugging. `RUSTERIZER_TILE_SIZE` sets the width and height of
+//! the tiles in pixels.
+use std::cmp;
+use std::env;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use batch::Quad;
+use rasterizer::{Rect, Triangle};
+
+/// Environment variable with the number of threads that draw tiles, 0 for one per CPU.
+pub const THREADS_VARIABLE: &str = "RUSTERIZER_THREADS";
+/// Environment variable with the size of tiles, a power of two from `MIN_TILE_SIZE` up to
+/// `MAX_TILE_SIZE`.
+pub const TILE_SIZE_VARIABLE: &str = "RUSTERIZER_TILE_SIZE";
+
+pub const DEFAULT_TILE_SIZE: u32 = 64;
+pub const MIN_TILE_SIZE: u32 = 16;
+pub const MAX_TILE_SIZE: u32 = 1024;
+
+/// How triangles are binned and drawn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// Width and height of a tile in pixels.
+    pub tile_size: u32,
+    /// Threads that draw tiles, 0 for one per CPU.
+    pub threads: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            tile_size: DEFAULT_TILE_SIZE,
+            threads: 0,
+        }
+    }
+}
+
+/// The configuration `RUSTERIZER_TILE_SIZE` and `RUSTERIZER_THREADS` ask for.
+pub fn config() -> Config {
+    static CONFIG: OnceLock<Config> = OnceLock::new();
+    *CONFIG.get_or_init(|| {
+        let mut config = Config::default();
+        if let Ok(value) = env::var(TILE_SIZE_VARIABLE) {
+            match parse_tile_size(&value) {
+                Some(tile_size) => config.tile_size = tile_size,
+                None => warn!("Ignoring tile size {}", value),
+            }
+        }
+        if let Ok(value) = env::var(THREADS_VARIABLE) {
+            match value.trim().parse() {
+                Ok(threads) => config.threads = threads,
+                Err(_) => warn!("Ignoring thread count {}", value),
+            }
+        }
+        config
+    })
+}
+
+fn parse_tile_size(value: &str) -> Option<u32> {
+    let size: u32 = value.trim().parse().ok()?;
+    if size.is_power_of_two() && (MIN_TILE_SIZE..=MAX_TILE_SIZE).contains(&size) {
+        Some(size)
+    } else {
+        None
+    }
+}
+
+/// The triangles of a draw, sorted into the tiles of the pixels they may cover. Every triangle
+/// comes with `T`, whatever shading its fragments needs.
+#[derive(Debug)]
+pub struct Bins<T> {
+    config: Config,
+    /// The pixels that are drawn, from the scissor and the render area.
+    clip: Rect,
+    /// Samples per pixel.
+    samples: u32,
+    /// The column and row of the first tile, and the tiles in a row.
+    first: (u32, u32),
+    columns: u32,
+    triangles: Vec<(Triangle, T)>,
+    /// The indices of the triangles of every tile, row by row.
+    bins: Vec<Vec<u32>>,
+}
+
+impl<T: Sync> Bins<T> {
+    /// Empty bins for the tiles with pixels in `clip`, which have `samples` samples. Tiles are
+    /// aligned to multiples of their size, so that quads never cross them.
+    pub fn new(clip: Rect, samples: u32, config: Config) -> Self {
+        debug_assert!(config.tile_size.is_power_of_two() && config.tile_size >= 2);
+        let size = config.tile_size;
+        let (first, columns, rows) = if clip.is_empty() {
+            ((0, 0), 0, 0)
+        } else {
+            let first = (clip.left / size, clip.top / size);
+            let last = ((clip.right - 1) / size, (clip.bottom - 1) / size);
+            (first, last.0 - first.0 + 1, last.1 - first.1 + 1)
+        };
+        Bins {
+            config: config,
+            clip: clip,
+            samples: samples,
+            first: first,
+            columns: columns,
+            triangles: Vec::new(),
+            bins: (0..columns * rows).map(|_| Vec::new()).collect(),
+        }
+    }
+
+    /// Adds `triangle` to the bins of all tiles it may cover.
+    pub fn push(&mut self, triangle: Triangle, data: T) {
+        let bounds = triangle.bounds().intersect(&self.clip);
+        if bounds.is_empty() {
+            return;
+        }
+        let index = self.triangles.len() as u32;
+        let size = self.config.tile_size;
+        for row in bounds.top / size..(bounds.bottom - 1) / size + 1 {
+            for column in bounds.left / size..(bounds.right - 1) / size + 1 {
+                let tile = (row - self.first.1) * self.columns + column - self.first.0;
+                self.bins[tile as usize].push(index);
+            }
+        }
+        self.triangles.push((triangle, data));
+    }
+
+    /// The pixels of the tile at `index`.
+    fn tile(&self, index: usize) -> Rect {
+        let size = self.config.tile_size;
+        let column = self.first.0 + index as u32 % self.columns;
+        let row = self.first.1 + index as u32 / self.columns;
+        let tile = Rect {
+            left: column * size,
+            top: row * size,
+            right: column.saturating_add(1).saturating_mul(size),
+            bottom: row.saturating_add(1).saturating_mul(size),
+        };
+        tile.intersect(&self.clip)
+    }
+
+    /// Rasterizes all triangles and calls `shade` with the quads each of them covers in a tile,
+    /// and returns once all are done. Quads of a tile are shaded on a single thread, in the
+    /// order their triangles were added.
+    pub fn draw<F>(&self, shade: F)
+    where
+        F: Fn(&Triangle, &T, &[Quad]) + Sync,
+    {
+        let draw_tile = |index: usize| {
+            let tile = self.tile(index);
+            let mut quads = Vec::new();
+            for &triangle in &self.bins[index] {
+                let (ref triangle, ref data) = self.triangles[triangle as usize];
+                quads.clear();
+                triangle.rasterize(&tile, self.samples, |quad| quads.push(quad));
+                if !quads.is_empty() {
+                    shade(triangle, data, &quads);
+                }
+            }
+        };
+        let tiles: Vec<usize> = (0..self.bins.len())
+            .filter(|&index| !self.bins[index].is_empty())
+            .collect();
+        let threads = match self.config.threads {
+            0 => thread::available_parallelism().map_or(1, |threads| threads.get()),
+            threads => threads,
+        };
+        let threads = cmp::min(threads, tiles.len());
+        if threads <= 1 {
+            for &index in &tiles {
+                draw_tile(index);
+            }
+            return;
+        }
+
+        let next = AtomicUsize::new(0);
+        thread::scope(|scope| for _ in 0..threads {
+            let (next, tiles, draw_tile) = (&next, &tiles, &draw_tile);
+            scope.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                match tiles.get(index) {
+                    Some(&tile) => draw_tile(tile),
+                    None => break,
+                }
+            });
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicU32;
+    use ffi_types as vk;
+    use pipeline::RasterizationState;
+    use super::*;
+
+    fn setup(positions: [[f32; 2]; 3]) -> Option<Triangle> {
+        let state = RasterizationState {
+            depth_clamp: false,
+            discard: false,
+            cull_mode: vk::CULL_MODE_NONE,
+            front_face: vk::FRONT_FACE_COUNTER_CLOCKWISE,
+            depth_bias: None,
+            line_width: 1.0,
+        };
+        Triangle::setup(positions, &state)
+    }
+
+    const CLIP: Rect = Rect {
+        left: 3,
+        top: 5,
+        right: 97,
+        bottom: 70,
+    };
+
+    /// Overlapping triangles all over `CLIP` and beyond.
+    fn triangles() -> Vec<Triangle> {
+        let mut state = 12345u32;
+        let mut random = || {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 8) as f32 / (1 << 24) as f32 * 110.0 - 5.0
+        };
+        (0..200)
+            .map(|_| [[random(), random()], [random(), random()], [random(), random()]])
+            .filter_map(setup)
+            .collect()
+    }
+
+    /// Draws the triangles into an image of `CLIP`, where every pixel ends up with the index of
+    /// the last triangle that covers it, plus one.
+    fn draw(triangles: &[Triangle], config: Config) -> Vec<u32> {
+        let width = CLIP.right as usize;
+        let pixels: Vec<AtomicU32> = (0..width * CLIP.bottom as usize)
+            .map(|_| AtomicU32::new(0))
+            .collect();
+        let mut bins = Bins::new(CLIP, 1, config);
+        for (index, &triangle) in triangles.iter().enumerate() {
+            bins.push(triangle, index as u32 + 1);
+        }
+        bins.draw(|_, &index, quads| for quad in quads {
+            for fragment in (0..4).filter(|&fragment| quad.covers(fragment)) {
+                let (x, y) = quad.fragment(fragment);
+                assert!((CLIP.left..CLIP.right).contains(&x));
+                assert!((CLIP.top..CLIP.bottom).contains(&y));
+                let pixel = &pixels[y as usize * width + x as usize];
+                // Each pixel is only drawn by the thread of its tile, and in order
+                assert!(pixel.load(Ordering::Relaxed) < index);
+                pixel.store(index, Ordering::Relaxed);
+            }
+        });
+        pixels.into_iter().map(AtomicU32::into_inner).collect()
+    }
+
+    #[test]
+    fn keeps_primitive_order_per_pixel() {
+        let triangles = triangles();
+        let mut expected = vec![0; CLIP.right as usize * CLIP.bottom as usize];
+        for (index, triangle) in triangles.iter().enumerate() {
+            triangle.rasterize(&CLIP, 1, |quad| {
+                for fragment in (0..4).filter(|&fragment| quad.covers(fragment)) {
+                    let (x, y) = quad.fragment(fragment);
+                    expected[(y * CLIP.right + x) as usize] = index as u32 + 1;
+                }
+            });
+        }
+        assert!(expected.iter().filter(|&&index| index != 0).count() > 5000);
+
+        for &tile_size in &[MIN_TILE_SIZE, 32, MAX_TILE_SIZE] {
+            for &threads in &[1, 4, 0] {
+                let config = Config {
+                    tile_size: tile_size,
+                    threads: threads,
+                };
+                assert!(draw(&triangles, config) == expected, "{:?}", config);
+            }
+        }
+    }
+
+    #[test]
+    fn bins_by_bounds() {
+        let config = Config {
+            tile_size: 16,
+            threads: 1,
+        };
+        let mut bins = Bins::new(CLIP, 1, config);
+        assert_eq!((bins.first, bins.columns, bins.bins.len()), ((0, 0), 7, 35));
+        assert_eq!(bins.tile(0), Rect { left: 3, top: 5, right: 16, bottom: 16 });
+        assert_eq!(bins.tile(34), Rect { left: 96, top: 64, right: 97, bottom: 70 });
+
+        bins.push(setup([[20.0, 20.0], [40.0, 20.0], [20.0, 36.0]]).unwrap(), ());
+        // Nothing of it is in the clip rectangle
+        bins.push(setup([[100.0, 0.0], [120.0, 0.0], [100.0, 20.0]]).unwrap(), ());
+        bins.push(setup([[0.0, 0.0], [8.0, 0.0], [0.0, 100.0]]).unwrap(), ());
+        let binned: Vec<(usize, Vec<u32>)> = bins.bins
+            .iter()
+            .cloned()
+            .enumerate()
+            .filter(|&(_, ref bin)| !bin.is_empty())
+            .collect();
+        assert_eq!(
+            binned,
+            vec![
+                (0, vec![1]),
+                (7, vec![1]),
+                (8, vec![0]),
+                (9, vec![0]),
+                (14, vec![1]),
+                (15, vec![0]),
+                (16, vec![0]),
+                (21, vec![1]),
+                (28, vec![1]),
+            ]
+        );
+        assert!(Bins::<()>::new(Rect { right: 3, ..CLIP }, 1, config).bins.is_empty());
+    }
+
+    #[test]
+    fn parses_tile_sizes() {
+        assert_eq!(parse_tile_size("32"), Some(32));
+        assert_eq!(parse_tile_size(" 1024 "), Some(1024));
+        assert_eq!(parse_tile_size("48"), None);
+        assert_eq!(parse_tile_size("8"), None);
+        assert_eq!(parse_tile_size("2048"), None);
+    }
+}
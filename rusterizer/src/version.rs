@@ -1,5 +1,4 @@
 use std::fmt;
-use std::convert::Into;
 
 #[derive(PartialEq, Eq, PartialOrd, Copy, Clone)]
 pub struct Version {
@@ -0,0 +1,319 @@
+//! The calling convention of compiled shaders.
+//!
+//! Every entry point is compiled to an `extern "C" fn(*mut Invocation)`. The caller fills in the
+//! inputs of one invocation, calls the shader and reads back its outputs:
+//!
+//! * Input and Output variables live in `Invocation::inputs` and `Invocation::outputs`, one
+//!   16 byte `Slot` per Location. The Component decoration selects the 32 bit word within a
+//!   slot. Arrays, matrices and structs take one slot per element, column or member (two for
+//!   64 bit vectors of more than two components).
+//! * Built-in variables live in `Invocation::builtins`.
+//! * Descriptors and push constants are shared by all invocations of a draw or dispatch and
+//!   reached through `Invocation::resources`. So are the Workgroup variables, which the caller
+//!   provides memory for, and the barrier that OpControlBarrier waits at.
+//!
+//! Entry points compiled for SIMD lanes are `BatchFn`s instead and run a whole array of
+//! invocations at once.
+//!
+//! The transpiler takes all offsets from the types in this module, so they are the only
+//! definition of the interface.
+use std::mem;
+use std::os::raw::c_void;
+use std::ptr;
+
+use spirv_headers::BuiltIn;
+
+/// Number of Locations available to the inputs and outputs of a shader.
+pub const MAX_LOCATIONS: usize = 32;
+pub const MAX_CLIP_DISTANCES: usize = 8;
+pub const MAX_CULL_DISTANCES: usize = 8;
+pub const MAX_DESCRIPTOR_SETS: usize = 8;
+
+/// A four component vector, aligned the way LLVM expects vectors to be.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[repr(C, align(16))]
+pub struct Vec4<T>(pub [T; 4]);
+
+/// One Location of the shader interface: four 32 bit components of any type.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[repr(C, align(16))]
+pub struct Slot(pub [u32; 4]);
+
+impl Slot {
+    pub fn from_f32(value: [f32; 4]) -> Self {
+        Slot(
+            [
+                value[0].to_bits(),
+                value[1].to_bits(),
+                value[2].to_bits(),
+                value[3].to_bits(),
+            ],
+        )
+    }
+
+    pub fn to_f32(&self) -> [f32; 4] {
+        [
+            f32::from_bits(self.0[0]),
+            f32::from_bits(self.0[1]),
+            f32::from_bits(self.0[2]),
+            f32::from_bits(self.0[3]),
+        ]
+    }
+}
+
+/// Values of the BuiltIn decorated variables of a shader.
+///
+/// Booleans are stored as 0 or 1 in a `u32`.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct BuiltIns {
+    // Vertex shader inputs
+    pub vertex_index: i32,
+    pub instance_index: i32,
+    pub base_vertex: i32,
+    pub base_instance: i32,
+    pub draw_index: i32,
+
+    // Vertex shader outputs
+    pub position: Vec4<f32>,
+    pub point_size: f32,
+    pub clip_distance: [f32; MAX_CLIP_DISTANCES],
+    pub cull_distance: [f32; MAX_CULL_DISTANCES],
+
+    // Fragment shader inputs
+    pub frag_coord: Vec4<f32>,
+    pub point_coord: Vec4<f32>,
+    pub front_facing: u32,
+    pub helper_invocation: u32,
+    pub primitive_id: i32,
+    pub sample_id: i32,
+    pub sample_position: Vec4<f32>,
+    pub sample_mask_in: [i32; 1],
+
+    // Fragment shader outputs
+    pub frag_depth: f32,
+    pub sample_mask: [i32; 1],
+
+    // Compute shader inputs
+    pub num_workgroups: Vec4<u32>,
+    pub workgroup_id: Vec4<u32>,
+    pub local_invocation_id: Vec4<u32>,
+    pub global_invocation_id: Vec4<u32>,
+    pub local_invocation_index: u32,
+}
+
+/// Called by OpControlBarrier with `Resources::barrier_data`. Returns once every invocation of
+/// the workgroup called it.
+pub type BarrierFn = unsafe extern "C" fn(*mut c_void);
+
+/// Resources shared by all invocations of a draw or dispatch.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct Resources {
+    /// Bound descriptor sets. `descriptor_sets[set][binding][element]` is the address of a
+    /// resource, e.g. the first byte of a uniform buffer. Unused sets may be null.
+    pub descriptor_sets: [*const *const *mut u8; MAX_DESCRIPTOR_SETS],
+    pub push_constants: *const u8,
+    /// Zeroed memory of `EntryPoint::workgroup_memory` bytes that holds the Workgroup variables
+    /// of the workgroup the invocations belong to. Aligned to 16 bytes.
+    pub workgroup_memory: *mut u8,
+    /// Waits for the other invocations of the workgroup. OpControlBarrier does nothing if this
+    /// is None, which is right if a single call runs the whole workgroup, e.g. outside of
+    /// compute shaders.
+    pub barrier: Option<BarrierFn>,
+    pub barrier_data: *mut c_void,
+}
+
+impl Default for Resources {
+    fn default() -> Self {
+        Resources {
+            descriptor_sets: [ptr::null(); MAX_DESCRIPTOR_SETS],
+            push_constants: ptr::null(),
+            workgroup_memory: ptr::null_mut(),
+            barrier: None,
+            barrier_data: ptr::null_mut(),
+        }
+    }
+}
+
+/// State of a single shader invocation.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct Invocation {
+    pub resources: *const Resources,
+    /// Set to 1 by fragment shaders that discard the fragment.
+    pub killed: u32,
+    pub builtins: BuiltIns,
+    pub inputs: [Slot; MAX_LOCATIONS],
+    pub outputs: [Slot; MAX_LOCATIONS],
+}
+
+impl Invocation {
+    pub fn new(resources: &Resources) -> Self {
+        Invocation {
+            resources: resources,
+            killed: 0,
+            builtins: BuiltIns::default(),
+            inputs: [Slot::default(); MAX_LOCATIONS],
+            outputs: [Slot::default(); MAX_LOCATIONS],
+        }
+    }
+}
+
+/// Signature of a compiled entry point.
+pub type ShaderFn = unsafe extern "C" fn(*mut Invocation);
+
+/// Signature of an entry point compiled to run several invocations at once, one per SIMD lane.
+///
+/// Takes an array of as many invocations as there are lanes and a mask with a bit for every
+/// lane. Lanes whose bit is clear do not run, but their invocations still have to be valid
+/// memory. The resources of the first invocation are used for all of them.
+pub type BatchFn = unsafe extern "C" fn(*mut Invocation, u32);
+
+macro_rules! offset_of {
+    ($ty:ty, $($field:ident).+) => {
+        unsafe {
+            let value: $ty = mem::zeroed();
+            &value.$($field).+ as *const _ as usize - &value as *const _ as usize
+        }
+    }
+}
+
+/// Byte offset of `Invocation::resources`.
+pub fn resources_offset() -> usize {
+    offset_of!(Invocation, resources)
+}
+
+pub fn killed_offset() -> usize {
+    offset_of!(Invocation, killed)
+}
+
+/// Byte offset of the slot of `location` in the inputs or outputs of an `Invocation`.
+pub fn location_offset(output: bool, location: u32) -> Option<usize> {
+    if location as usize >= MAX_LOCATIONS {
+        return None;
+    }
+    let slots = if output {
+        offset_of!(Invocation, outputs)
+    } else {
+        offset_of!(Invocation, inputs)
+    };
+    Some(slots + location as usize * mem::size_of::<Slot>())
+}
+
+/// Byte offset of the variable of `builtin` in an `Invocation`, if it is supported.
+pub fn builtin_offset(builtin: BuiltIn) -> Option<usize> {
+    let offset = match builtin {
+        BuiltIn::VertexIndex => offset_of!(Invocation, builtins.vertex_index),
+        BuiltIn::InstanceIndex => offset_of!(Invocation, builtins.instance_index),
+        BuiltIn::BaseVertex => offset_of!(Invocation, builtins.base_vertex),
+        BuiltIn::BaseInstance => offset_of!(Invocation, builtins.base_instance),
+        BuiltIn::DrawIndex => offset_of!(Invocation, builtins.draw_index),
+        BuiltIn::Position => offset_of!(Invocation, builtins.position),
+        BuiltIn::PointSize => offset_of!(Invocation, builtins.point_size),
+        BuiltIn::ClipDistance => offset_of!(Invocation, builtins.clip_distance),
+        BuiltIn::CullDistance => offset_of!(Invocation, builtins.cull_distance),
+        BuiltIn::FragCoord => offset_of!(Invocation, builtins.frag_coord),
+        BuiltIn::PointCoord => offset_of!(Invocation, builtins.point_coord),
+        BuiltIn::FrontFacing => offset_of!(Invocation, builtins.front_facing),
+        BuiltIn::HelperInvocation => offset_of!(Invocation, builtins.helper_invocation),
+        BuiltIn::PrimitiveId => offset_of!(Invocation, builtins.primitive_id),
+        BuiltIn::SampleId => offset_of!(Invocation, builtins.sample_id),
+        BuiltIn::SamplePosition => offset_of!(Invocation, builtins.sample_position),
+        BuiltIn::FragDepth => offset_of!(Invocation, builtins.frag_depth),
+        // SampleMask is an input and an output, depending on the storage class
+        BuiltIn::SampleMask => return None,
+        BuiltIn::NumWorkgroups => offset_of!(Invocation, builtins.num_workgroups),
+        BuiltIn::WorkgroupId => offset_of!(Invocation, builtins.workgroup_id),
+        BuiltIn::LocalInvocationId => offset_of!(Invocation, builtins.local_invocation_id),
+        BuiltIn::GlobalInvocationId => offset_of!(Invocation, builtins.global_invocation_id),
+        BuiltIn::LocalInvocationIndex => {
+            offset_of!(Invocation, builtins.local_invocation_index)
+        }
+        _ => return None,
+    };
+    Some(offset)
+}
+
+/// Byte offset of the SampleMask variable of the given direction.
+pub fn sample_mask_offset(output: bool) -> usize {
+    if output {
+        offset_of!(Invocation, builtins.sample_mask)
+    } else {
+        offset_of!(Invocation, builtins.sample_mask_in)
+    }
+}
+
+/// Byte offset of `Resources::descriptor_sets[set]`.
+pub fn descriptor_set_offset(set: u32) -> Option<usize> {
+    if set as usize >= MAX_DESCRIPTOR_SETS {
+        return None;
+    }
+    let sets = offset_of!(Resources, descriptor_sets);
+    Some(sets + set as usize * mem::size_of::<*const *const *mut u8>())
+}
+
+pub fn push_constants_offset() -> usize {
+    offset_of!(Resources, push_constants)
+}
+
+/// Byte offset of `Resources::workgroup_memory`.
+pub fn workgroup_memory_offset() -> usize {
+    offset_of!(Resources, workgroup_memory)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vectors_are_aligned() {
+        for &builtin in &[
+            BuiltIn::Position,
+            BuiltIn::FragCoord,
+            BuiltIn::PointCoord,
+            BuiltIn::SamplePosition,
+            BuiltIn::NumWorkgroups,
+            BuiltIn::WorkgroupId,
+            BuiltIn::LocalInvocationId,
+            BuiltIn::GlobalInvocationId,
+        ]
+        {
+            assert_eq!(builtin_offset(builtin).unwrap() % 16, 0);
+        }
+        assert_eq!(location_offset(false, 0).unwrap() % 16, 0);
+        assert_eq!(location_offset(true, 0).unwrap() % 16, 0);
+    }
+
+    #[test]
+    fn offsets_match_fields() {
+        let resources = Resources::default();
+        let mut invocation = Invocation::new(&resources);
+        let base = &invocation as *const _ as usize;
+        assert_eq!(
+            builtin_offset(BuiltIn::VertexIndex).unwrap(),
+            &invocation.builtins.vertex_index as *const _ as usize - base
+        );
+        assert_eq!(
+            location_offset(true, 3).unwrap(),
+            &invocation.outputs[3] as *const _ as usize - base
+        );
+        assert_eq!(
+            sample_mask_offset(false),
+            &invocation.builtins.sample_mask_in as *const _ as usize - base
+        );
+        assert_eq!(location_offset(false, MAX_LOCATIONS as u32), None);
+
+        invocation.inputs[1] = Slot::from_f32([1.0, -2.0, 0.5, 4.0]);
+        assert_eq!(invocation.inputs[1].to_f32(), [1.0, -2.0, 0.5, 4.0]);
+    }
+
+    #[test]
+    fn descriptor_sets_are_consecutive() {
+        let first = descriptor_set_offset(0).unwrap();
+        assert_eq!(descriptor_set_offset(2).unwrap(), first + 2 * mem::size_of::<usize>());
+        assert_eq!(descriptor_set_offset(MAX_DESCRIPTOR_SETS as u32), None);
+        assert!(push_constants_offset() >= first + MAX_DESCRIPTOR_SETS * mem::size_of::<usize>());
+        assert_eq!(workgroup_memory_offset(), push_constants_offset() + mem::size_of::<usize>());
+    }
+}
@@ -0,0 +1,225 @@
+//! The ways shaders can be run, and entry points that hide which one is used.
+//!
+//! Shaders are compiled to native code with LLVM by default. Cranelift compiles much faster
+//! but produces slower code, which suits pipelines that are created while an application runs.
+//! The interpreter runs shaders without compiling them, which is slow but works everywhere and
+//! serves as a reference for the compiled code. Each backend is behind a cargo feature of the
+//! same name.
+use std::env;
+#[cfg(any(feature = "llvm", feature = "cranelift"))]
+use std::marker::PhantomData;
+#[cfg(any(feature = "llvm", feature = "cranelift"))]
+use std::mem;
+use std::slice;
+
+#[cfg(feature = "interpreter")]
+use spirv_headers::Word;
+
+use abi::Invocation;
+#[cfg(any(feature = "llvm", feature = "cranelift"))]
+use abi::{BatchFn, ShaderFn};
+#[cfg(feature = "cranelift")]
+use cranelift::CraneliftModule;
+#[cfg(feature = "interpreter")]
+use interp::Interpreter;
+#[cfg(feature = "llvm")]
+use jit::JitModule;
+
+/// Environment variable that picks the backend, by the name `Backend::from_name` accepts.
+pub const BACKEND_VARIABLE: &str = "SPIRV_LLVM_BACKEND";
+
+/// A way to run shaders.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// Compile to native code with LLVM.
+    #[cfg(feature = "llvm")]
+    Llvm,
+    /// Compile to native code with Cranelift.
+    #[cfg(feature = "cranelift")]
+    Cranelift,
+    /// Interpret the SPIR-V module directly.
+    #[cfg(feature = "interpreter")]
+    Interpreter,
+}
+
+impl Backend {
+    /// Returns the backend called `name` ("llvm", "cranelift" or "interpreter"), if it was
+    /// built in.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            #[cfg(feature = "llvm")]
+            "llvm" => Some(Backend::Llvm),
+            #[cfg(feature = "cranelift")]
+            "cranelift" => Some(Backend::Cranelift),
+            #[cfg(feature = "interpreter")]
+            "interpreter" => Some(Backend::Interpreter),
+            _ => None,
+        }
+    }
+
+    /// Returns the backend that `SPIRV_LLVM_BACKEND` asks for, or the default if it is not set
+    /// or names a backend that was not built in.
+    pub fn from_env() -> Self {
+        env::var(BACKEND_VARIABLE)
+            .ok()
+            .and_then(|name| Backend::from_name(&name))
+            .unwrap_or_default()
+    }
+}
+
+impl Default for Backend {
+    /// LLVM if it was built in, as its code is the fastest, otherwise Cranelift.
+    fn default() -> Self {
+        #[cfg(feature = "llvm")]
+        {
+            Backend::Llvm
+        }
+        #[cfg(all(not(feature = "llvm"), feature = "cranelift"))]
+        {
+            Backend::Cranelift
+        }
+        #[cfg(not(any(feature = "llvm", feature = "cranelift")))]
+        {
+            Backend::Interpreter
+        }
+    }
+}
+
+/// The code of a module, ready to run on one of the backends.
+// There is one per compiled shader, boxing the interpreter would only add an indirection
+#[allow(clippy::large_enum_variant)]
+pub enum ShaderCode {
+    #[cfg(feature = "llvm")]
+    Jit(JitModule),
+    #[cfg(feature = "cranelift")]
+    Cranelift(CraneliftModule),
+    #[cfg(feature = "interpreter")]
+    Interpreted(Interpreter),
+}
+
+impl ShaderCode {
+    /// Returns the entry point that was declared with the given name.
+    pub fn entry_point<'a>(&'a self, name: &str) -> Option<EntryPoint<'a>> {
+        match *self {
+            #[cfg(feature = "llvm")]
+            ShaderCode::Jit(ref jit) => jit.entry_point(name),
+            #[cfg(feature = "cranelift")]
+            ShaderCode::Cranelift(ref module) => module.entry_point(name),
+            #[cfg(feature = "interpreter")]
+            ShaderCode::Interpreted(ref interpreter) => interpreter.entry_point(name),
+        }
+    }
+}
+
+/// An entry point of a `JitModule`, `CraneliftModule` or `Interpreter`.
+#[derive(Clone, Copy)]
+pub struct EntryPoint<'a> {
+    function: Function<'a>,
+    lanes: u32,
+    workgroup_memory: usize,
+}
+
+#[derive(Clone, Copy)]
+enum Function<'a> {
+    /// Address of a compiled function, a `ShaderFn` or `BatchFn` depending on the lanes.
+    /// Borrows the module that owns the machine code.
+    #[cfg(any(feature = "llvm", feature = "cranelift"))]
+    Native(usize, PhantomData<&'a ()>),
+    #[cfg(feature = "interpreter")]
+    Interpreted(&'a Interpreter, Word),
+}
+
+impl<'a> EntryPoint<'a> {
+    #[cfg(any(feature = "llvm", feature = "cranelift"))]
+    pub(crate) fn native(function: usize, lanes: u32, workgroup_memory: usize) -> Self {
+        EntryPoint {
+            function: Function::Native(function, PhantomData),
+            lanes: lanes,
+            workgroup_memory: workgroup_memory,
+        }
+    }
+
+    #[cfg(feature = "interpreter")]
+    pub(crate) fn interpreted(interpreter: &'a Interpreter, function: Word) -> Self {
+        EntryPoint {
+            function: Function::Interpreted(interpreter, function),
+            lanes: 1,
+            workgroup_memory: interpreter.workgroup_memory,
+        }
+    }
+
+    /// Number of invocations the entry point runs at once.
+    pub fn lanes(&self) -> u32 {
+        self.lanes
+    }
+
+    /// Bytes of memory every workgroup needs for the Workgroup variables of the module, see
+    /// `Resources::workgroup_memory`.
+    pub fn workgroup_memory(&self) -> usize {
+        self.workgroup_memory
+    }
+
+    /// Runs one invocation of the shader.
+    ///
+    /// Unsafe because the shader accesses whatever memory its resources point to.
+    pub unsafe fn call(&self, invocation: &mut Invocation) {
+        self.call_batch(slice::from_mut(invocation), 1)
+    }
+
+    /// Runs the invocations whose bit is set in `mask`, `lanes()` at a time. All of them have to
+    /// share the same resources.
+    ///
+    /// Unsafe because the shader accesses whatever memory its resources point to.
+    pub unsafe fn call_batch(&self, invocations: &mut [Invocation], mask: u32) {
+        match self.function {
+            #[cfg(any(feature = "llvm", feature = "cranelift"))]
+            Function::Native(function, _) => call_native(function, self.lanes, invocations, mask),
+            #[cfg(feature = "interpreter")]
+            Function::Interpreted(interpreter, function) => {
+                for (index, invocation) in invocations.iter_mut().enumerate().take(32) {
+                    if mask & (1 << index) != 0 {
+                        interpreter.call(function, invocation);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Runs the invocations whose bit is set in `mask` with the compiled entry point `function`,
+/// `lanes` at a time, see `EntryPoint::call_batch`.
+#[cfg(any(feature = "llvm", feature = "cranelift"))]
+unsafe fn call_native(
+    function: usize,
+    lanes: u32,
+    invocations: &mut [Invocation],
+    mask: u32,
+) {
+    if lanes == 1 {
+        let function: ShaderFn = mem::transmute(function);
+        for (index, invocation) in invocations.iter_mut().enumerate().take(32) {
+            if mask & (1 << index) != 0 {
+                function(invocation);
+            }
+        }
+        return;
+    }
+    let function: BatchFn = mem::transmute(function);
+    let lanes = lanes as usize;
+    for (chunk, invocations) in invocations.chunks_mut(lanes).enumerate().take(32 / lanes) {
+        let present = (1u64 << invocations.len()) - 1;
+        let chunk_mask = (u64::from(mask) >> (chunk * lanes) & present) as u32;
+        if chunk_mask == 0 {
+            continue;
+        }
+        if invocations.len() == lanes {
+            function(invocations.as_mut_ptr(), chunk_mask);
+        } else {
+            // The shader touches all lanes, even masked off ones
+            let mut batch = [invocations[0]; 16];
+            batch[..invocations.len()].copy_from_slice(invocations);
+            function(batch.as_mut_ptr(), chunk_mask);
+            invocations.copy_from_slice(&batch[..invocations.len()]);
+        }
+    }
+}
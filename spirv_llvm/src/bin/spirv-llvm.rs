@@ -0,0 +1,153 @@
+//! Translates a SPIR-V module to LLVM, to debug the transpiler outside of an application.
+extern crate rspirv;
+extern crate spirv_llvm;
+
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::process;
+
+const USAGE: &str = "usage: spirv-llvm [options] <input.spv>
+
+options:
+    -o <file>         write the output to <file> instead of stdout
+    --emit <kind>     ir (textual LLVM IR, the default), bc (bitcode) or obj (native object)
+    --lanes <n>       run 1 (the default), 4, 8 or 16 invocations at once
+    -O<level>         optimization level, 0 (the default) to 3
+    --entry <name>    only keep the entry point <name>
+    --target <triple> generate code for <triple> instead of the host
+    --cpu <name>      generate code for the LLVM CPU <name>
+    --features <list> use the LLVM target features <list>, e.g. +avx2,+fma, on a
+                      generic CPU unless --cpu names one
+
+The CPU and features default to SPIRV_LLVM_TARGET_CPU and SPIRV_LLVM_TARGET_FEATURES, or
+the host if those are not set.";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Emit {
+    Ir,
+    Bitcode,
+    Object,
+}
+
+struct Options {
+    input: String,
+    output: Option<String>,
+    emit: Emit,
+    lanes: u32,
+    opt_level: u32,
+    entry: Option<String>,
+    target: spirv_llvm::Target,
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut input = None;
+    let mut cpu = None;
+    let mut options = Options {
+        input: String::new(),
+        output: None,
+        emit: Emit::Ir,
+        lanes: 1,
+        opt_level: 0,
+        entry: None,
+        target: spirv_llvm::Target::from_env(),
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name));
+        match arg.as_str() {
+            "-o" => options.output = Some(value("-o")?),
+            "--emit" => {
+                options.emit = match value("--emit")?.as_str() {
+                    "ir" => Emit::Ir,
+                    "bc" => Emit::Bitcode,
+                    "obj" => Emit::Object,
+                    kind => return Err(format!("unknown output kind {}", kind)),
+                }
+            }
+            "--lanes" => {
+                let lanes = value("--lanes")?;
+                options.lanes = lanes.parse().map_err(
+                    |_| format!("invalid lane count {}", lanes),
+                )?;
+            }
+            "--entry" => options.entry = Some(value("--entry")?),
+            "--target" => options.target.triple = value("--target")?,
+            "--cpu" => cpu = Some(value("--cpu")?),
+            "--features" => {
+                options.target.features = value("--features")?;
+                // The host CPU would bring its own features along
+                options.target.cpu = "generic".to_owned();
+            }
+            "-h" | "--help" => return Err(String::new()),
+            _ if arg.starts_with("-O") => {
+                options.opt_level = match arg[2..].parse() {
+                    Ok(level) if level <= 3 => level,
+                    _ => return Err(format!("invalid optimization level {}", arg)),
+                }
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ if input.is_none() => input = Some(arg),
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+    options.input = input.ok_or_else(|| "no input file".to_owned())?;
+    if let Some(cpu) = cpu {
+        options.target.cpu = cpu;
+    }
+    Ok(options)
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let bytes = fs::read(&options.input).map_err(|error| {
+        format!("could not read {}: {}", options.input, error)
+    })?;
+    let spirv_mod = rspirv::mr::load_bytes(bytes).map_err(|error| {
+        format!("{}: invalid SPIR-V: {:?}", options.input, error)
+    })?;
+    let mut module = spirv_llvm::spirv_to_llvm_simd(&spirv_mod, options.lanes)
+        .map_err(|error| format!("{}: {}", options.input, error))?;
+
+    if let Some(ref entry) = options.entry {
+        if !module.select_entry_point(entry) {
+            return Err(format!("{} has no entry point {}", options.input, entry));
+        }
+    }
+    let error = |error: spirv_llvm::TranspilerError| error.to_string();
+    module.set_target(&options.target).map_err(error)?;
+    module.optimize(options.opt_level).map_err(error)?;
+
+    let output = match options.emit {
+        Emit::Ir => module.to_string().into_bytes(),
+        Emit::Bitcode => module.to_bitcode(),
+        Emit::Object => module.to_object().map_err(error)?,
+    };
+    match options.output {
+        Some(ref path) => {
+            fs::write(path, &output).map_err(|error| {
+                format!("could not write {}: {}", path, error)
+            })
+        }
+        None => {
+            let stdout = io::stdout();
+            let mut stdout = stdout.lock();
+            stdout.write_all(&output).map_err(|error| error.to_string())
+        }
+    }
+}
+
+fn main() {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            if !message.is_empty() {
+                eprintln!("error: {}", message);
+            }
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(message) = run(&options) {
+        eprintln!("error: {}", message);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,201 @@
+//! Control flow analysis for functions that run several invocations at once.
+//!
+//! Invocations running in lockstep cannot branch on their own. Instead every block runs for the
+//! invocations that reach it, in an order where blocks come after all blocks branching to them.
+//! Loops are the exception: their blocks run over and over, until no invocation takes a back
+//! edge to the header anymore.
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// A step in the order blocks run in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Item {
+    Block(usize),
+    /// A loop with the given header, whose body runs in the given order.
+    Loop(usize, Vec<Item>),
+}
+
+/// The structure of the control flow graph of a function.
+pub struct Cfg {
+    pub reachable: Vec<bool>,
+    /// Edges from the body of a loop back to its header.
+    pub back_edges: HashSet<(usize, usize)>,
+    /// Whether a block is part of a loop, and may run more than once.
+    pub in_loop: Vec<bool>,
+    /// The order reachable blocks run in.
+    pub order: Vec<Item>,
+}
+
+impl Cfg {
+    /// Analyzes the graph of blocks `0..successors.len()`, where block 0 is the entry. Returns
+    /// None if the graph is irreducible.
+    pub fn new(successors: &[Vec<usize>]) -> Option<Self> {
+        let count = successors.len();
+        if count == 0 {
+            return None;
+        }
+
+        // Depth first search, every edge to a block on the stack closes a loop
+        let mut reachable = vec![false; count];
+        let mut on_stack = vec![false; count];
+        let mut back_edges = HashSet::new();
+        let mut stack = vec![(0, 0)];
+        reachable[0] = true;
+        on_stack[0] = true;
+        while let Some(&(block, next)) = stack.last() {
+            match successors[block].get(next).cloned() {
+                Some(successor) => {
+                    let top = stack.len() - 1;
+                    stack[top].1 += 1;
+                    if on_stack[successor] {
+                        back_edges.insert((block, successor));
+                    } else if !reachable[successor] {
+                        reachable[successor] = true;
+                        on_stack[successor] = true;
+                        stack.push((successor, 0));
+                    }
+                }
+                None => {
+                    on_stack[block] = false;
+                    stack.pop();
+                }
+            }
+        }
+
+        let mut predecessors = vec![Vec::new(); count];
+        for block in (0..count).filter(|&block| reachable[block]) {
+            for &successor in &successors[block] {
+                predecessors[successor].push(block);
+            }
+        }
+
+        // The body of a loop are all blocks that reach a back edge without passing the header
+        let mut loops: HashMap<usize, BTreeSet<usize>> = HashMap::new();
+        for &(latch, header) in &back_edges {
+            let body = loops.entry(header).or_default();
+            body.insert(header);
+            let mut pending = vec![latch];
+            while let Some(block) = pending.pop() {
+                if body.insert(block) {
+                    pending.extend(predecessors[block].iter().cloned());
+                }
+            }
+        }
+        // Loops have to nest, otherwise there is no order to run them in
+        for (a, a_body) in &loops {
+            for (b, b_body) in &loops {
+                let nested = a == b || a_body.is_disjoint(b_body) || a_body.is_subset(b_body) ||
+                    b_body.is_subset(a_body);
+                if !nested {
+                    return None;
+                }
+            }
+        }
+
+        let mut in_loop = vec![false; count];
+        for body in loops.values() {
+            for &block in body {
+                in_loop[block] = true;
+            }
+        }
+        let all = (0..count).filter(|&block| reachable[block]).collect();
+        let order = {
+            let analysis = Analysis {
+                successors: successors,
+                back_edges: &back_edges,
+                loops: &loops,
+            };
+            analysis.order(&all, 0, None)?
+        };
+        Some(Cfg {
+            reachable: reachable,
+            back_edges: back_edges,
+            in_loop: in_loop,
+            order: order,
+        })
+    }
+}
+
+struct Analysis<'a> {
+    successors: &'a [Vec<usize>],
+    back_edges: &'a HashSet<(usize, usize)>,
+    loops: &'a HashMap<usize, BTreeSet<usize>>,
+}
+
+impl<'a> Analysis<'a> {
+    /// Orders the blocks of `region`, which is entered through `first`. `header` is set if the
+    /// region is the body of a loop.
+    fn order(
+        &self,
+        region: &BTreeSet<usize>,
+        first: usize,
+        header: Option<usize>,
+    ) -> Option<Vec<Item>> {
+        // Loops nested in the region are ordered as a whole, through their header
+        let nested = self.loops
+            .iter()
+            .filter(|&(&loop_header, _)| region.contains(&loop_header) && Some(loop_header) != header)
+            .collect::<Vec<_>>();
+        let representative = |block: usize| {
+            nested
+                .iter()
+                .filter(|&&(_, body)| body.contains(&block))
+                .max_by_key(|&&(_, body)| body.len())
+                .map(|&(&loop_header, _)| loop_header)
+                .unwrap_or(block)
+        };
+
+        let nodes = region.iter().map(|&block| representative(block)).collect::<BTreeSet<_>>();
+        let mut edges = HashSet::new();
+        for &block in region {
+            for &successor in &self.successors[block] {
+                if !region.contains(&successor) ||
+                    (Some(successor) == header && self.back_edges.contains(&(block, successor)))
+                {
+                    continue;
+                }
+                let (from, to) = (representative(block), representative(successor));
+                if from != to {
+                    edges.insert((from, to));
+                }
+            }
+        }
+
+        // Kahn's algorithm, picking blocks in the order of the module where there is a choice
+        let mut in_degrees = nodes.iter().map(|&node| (node, 0)).collect::<HashMap<_, _>>();
+        for &(_, to) in &edges {
+            *in_degrees.get_mut(&to)? += 1;
+        }
+        let mut ready = nodes
+            .iter()
+            .cloned()
+            .filter(|node| in_degrees[node] == 0)
+            .collect::<BTreeSet<_>>();
+        let mut items = Vec::new();
+        while let Some(&node) = ready.iter().next() {
+            ready.remove(&node);
+            if items.is_empty() && node != first {
+                return None;
+            }
+            items.push(match self.loops.get(&node) {
+                Some(body) if Some(node) != header => {
+                    Item::Loop(node, self.order(body, node, Some(node))?)
+                }
+                _ => Item::Block(node),
+            });
+            for &(from, to) in &edges {
+                if from == node {
+                    let in_degree = in_degrees.get_mut(&to)?;
+                    *in_degree -= 1;
+                    if *in_degree == 0 {
+                        ready.insert(to);
+                    }
+                }
+            }
+        }
+        if items.len() == nodes.len() {
+            Some(items)
+        } else {
+            None
+        }
+    }
+}
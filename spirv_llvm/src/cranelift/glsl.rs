@@ -0,0 +1,565 @@
+//! The GLSL.std.450 extended instruction set, computed like `interp::glsl` does.
+use std::f64::consts::PI;
+
+use cranelift_codegen::ir::condcodes::{FloatCC, IntCC};
+use cranelift_codegen::ir::{self, types, InstBuilder};
+use spirv_headers::Word;
+
+use glsl_op::*;
+use interp::Type;
+use TranspilerError;
+
+use super::translate::{int_type, Translator};
+
+impl<'a, 'b> Translator<'a, 'b> {
+    /// Translates the pure instruction `instruction` with result type `ty`, see
+    /// `interp::glsl::evaluate`. `args` are the ids and scalars of its operands.
+    pub fn glsl(
+        &mut self,
+        instruction: u32,
+        ty: Word,
+        args: &[(Word, Vec<ir::Value>)],
+    ) -> Result<Vec<ir::Value>, TranspilerError> {
+        let arg = |index: usize| {
+            args.get(index).map(|&(_, ref scalars)| scalars.as_slice()).ok_or_else(|| {
+                TranspilerError::UnsupportedExtInst(NAME.to_owned(), instruction)
+            })
+        };
+        let value = match instruction {
+            ROUND => self.unary_helper("round", arg(0)?)?,
+            // Ties go to the even neighbour
+            ROUND_EVEN => self.map(arg(0)?, &mut |t, x| t.ins().nearest(x)),
+            TRUNC => self.map(arg(0)?, &mut |t, x| t.ins().trunc(x)),
+            F_ABS => self.map(arg(0)?, &mut |t, x| t.ins().fabs(x)),
+            S_ABS => self.map(arg(0)?, &mut |t, x| t.ins().iabs(x)),
+            F_SIGN => {
+                self.map(arg(0)?, &mut |t, x| {
+                    let ty = t.value_type(x);
+                    let zero = t.zero(ty);
+                    let (one, minus_one) = (t.fconst(ty, 1.0), t.fconst(ty, -1.0));
+                    let positive = t.ins().fcmp(FloatCC::GreaterThan, x, zero);
+                    let negative = t.ins().fcmp(FloatCC::LessThan, x, zero);
+                    let sign = t.ins().select(negative, minus_one, zero);
+                    t.ins().select(positive, one, sign)
+                })
+            }
+            S_SIGN => {
+                self.map(arg(0)?, &mut |t, x| {
+                    let ty = t.value_type(x);
+                    let one = t.ins().iconst(ty, 1);
+                    let minus_one = t.minus_one(ty);
+                    let x = t.ins().smin(x, one);
+                    t.ins().smax(x, minus_one)
+                })
+            }
+            FLOOR => self.map(arg(0)?, &mut |t, x| t.ins().floor(x)),
+            CEIL => self.map(arg(0)?, &mut |t, x| t.ins().ceil(x)),
+            FRACT => {
+                self.map(arg(0)?, &mut |t, x| {
+                    let floor = t.ins().floor(x);
+                    t.ins().fsub(x, floor)
+                })
+            }
+            RADIANS => self.map(arg(0)?, &mut |t, x| t.scale(x, PI / 180.0)),
+            DEGREES => self.map(arg(0)?, &mut |t, x| t.scale(x, 180.0 / PI)),
+            SIN => self.unary_helper("sin", arg(0)?)?,
+            COS => self.unary_helper("cos", arg(0)?)?,
+            TAN => self.unary_helper("tan", arg(0)?)?,
+            ASIN => self.unary_helper("asin", arg(0)?)?,
+            ACOS => self.unary_helper("acos", arg(0)?)?,
+            ATAN => self.unary_helper("atan", arg(0)?)?,
+            SINH => self.unary_helper("sinh", arg(0)?)?,
+            COSH => self.unary_helper("cosh", arg(0)?)?,
+            TANH => self.unary_helper("tanh", arg(0)?)?,
+            ASINH => self.unary_helper("asinh", arg(0)?)?,
+            ACOSH => self.unary_helper("acosh", arg(0)?)?,
+            ATANH => self.unary_helper("atanh", arg(0)?)?,
+            ATAN2 => self.binary_helper("atan2", arg(0)?, arg(1)?)?,
+            POW => self.binary_helper("pow", arg(0)?, arg(1)?)?,
+            EXP => self.unary_helper("exp", arg(0)?)?,
+            LOG => self.unary_helper("log", arg(0)?)?,
+            EXP2 => self.unary_helper("exp2", arg(0)?)?,
+            LOG2 => self.unary_helper("log2", arg(0)?)?,
+            SQRT => self.map(arg(0)?, &mut |t, x| t.ins().sqrt(x)),
+            INVERSE_SQRT => {
+                self.map(arg(0)?, &mut |t, x| {
+                    let root = t.ins().sqrt(x);
+                    let one = t.fconst(t.value_type(x), 1.0);
+                    t.ins().fdiv(one, root)
+                })
+            }
+            DETERMINANT => {
+                let columns = self.matrix_columns(args[0].0, arg(0)?)?;
+                vec![self.determinant(&columns)]
+            }
+            MATRIX_INVERSE => {
+                let columns = self.matrix_columns(args[0].0, arg(0)?)?;
+                self.matrix_inverse(&columns)
+            }
+            MODF_STRUCT => {
+                let (mut fract, whole) = self.modf(arg(0)?);
+                fract.extend(whole);
+                fract
+            }
+            F_MIN | N_MIN => self.zip(arg(0)?, arg(1)?, &mut |t, a, b| t.float_min(a, b)),
+            F_MAX | N_MAX => self.zip(arg(0)?, arg(1)?, &mut |t, a, b| t.float_max(a, b)),
+            U_MIN => self.zip(arg(0)?, arg(1)?, &mut |t, a, b| t.ins().umin(a, b)),
+            S_MIN => self.zip(arg(0)?, arg(1)?, &mut |t, a, b| t.ins().smin(a, b)),
+            U_MAX => self.zip(arg(0)?, arg(1)?, &mut |t, a, b| t.ins().umax(a, b)),
+            S_MAX => self.zip(arg(0)?, arg(1)?, &mut |t, a, b| t.ins().smax(a, b)),
+            F_CLAMP | N_CLAMP => {
+                self.zip3(arg(0)?, arg(1)?, arg(2)?, &mut |t, x, low, high| {
+                    let lower = t.float_max(x, low);
+                    t.float_min(lower, high)
+                })
+            }
+            U_CLAMP => {
+                self.zip3(arg(0)?, arg(1)?, arg(2)?, &mut |t, x, low, high| {
+                    let x = t.ins().umax(x, low);
+                    t.ins().umin(x, high)
+                })
+            }
+            S_CLAMP => {
+                self.zip3(arg(0)?, arg(1)?, arg(2)?, &mut |t, x, low, high| {
+                    let x = t.ins().smax(x, low);
+                    t.ins().smin(x, high)
+                })
+            }
+            F_MIX => {
+                // x * (1 - a) + y * a, as the spec defines it
+                self.zip3(arg(0)?, arg(1)?, arg(2)?, &mut |t, x, y, a| {
+                    let one = t.fconst(t.value_type(a), 1.0);
+                    let inverse = t.ins().fsub(one, a);
+                    let x = t.ins().fmul(x, inverse);
+                    let y = t.ins().fmul(y, a);
+                    t.ins().fadd(x, y)
+                })
+            }
+            STEP => {
+                self.zip(arg(0)?, arg(1)?, &mut |t, edge, x| {
+                    let ty = t.value_type(x);
+                    let (zero, one) = (t.zero(ty), t.fconst(ty, 1.0));
+                    let below = t.ins().fcmp(FloatCC::LessThan, x, edge);
+                    t.ins().select(below, zero, one)
+                })
+            }
+            SMOOTH_STEP => {
+                self.zip3(arg(0)?, arg(1)?, arg(2)?, &mut |t, edge0, edge1, x| {
+                    let ty = t.value_type(x);
+                    let (zero, one) = (t.zero(ty), t.fconst(ty, 1.0));
+                    let numerator = t.ins().fsub(x, edge0);
+                    let denominator = t.ins().fsub(edge1, edge0);
+                    let scaled = t.ins().fdiv(numerator, denominator);
+                    let scaled = t.float_max(scaled, zero);
+                    let scaled = t.float_min(scaled, one);
+                    let (two, three) = (t.fconst(ty, 2.0), t.fconst(ty, 3.0));
+                    let twice = t.ins().fmul(two, scaled);
+                    let factor = t.ins().fsub(three, twice);
+                    let squared = t.ins().fmul(scaled, scaled);
+                    t.ins().fmul(squared, factor)
+                })
+            }
+            FMA => {
+                // Single precision goes through double precision like in the interpreter
+                self.zip3(arg(0)?, arg(1)?, arg(2)?, &mut |t, a, b, c| match t.value_type(a) {
+                    types::F32 => {
+                        let a = t.ins().fpromote(types::F64, a);
+                        let b = t.ins().fpromote(types::F64, b);
+                        let c = t.ins().fpromote(types::F64, c);
+                        let result = t.ins().fma(a, b, c);
+                        t.ins().fdemote(types::F32, result)
+                    }
+                    _ => t.ins().fma(a, b, c),
+                })
+            }
+            FREXP_STRUCT => {
+                let width = match *self.types().get(ty)? {
+                    Type::Struct { ref members } if members.len() == 2 => {
+                        self.types().scalar_width(members[1])
+                    }
+                    _ => {
+                        let name = NAME.to_owned();
+                        return Err(TranspilerError::UnsupportedExtInst(name, instruction));
+                    }
+                };
+                let (mut mantissa, exponent) = self.frexp(arg(0)?, int_type(width)?)?;
+                mantissa.extend(exponent);
+                mantissa
+            }
+            LDEXP => {
+                let mut result = Vec::new();
+                for (&x, &exponent) in arg(0)?.iter().zip(arg(1)?) {
+                    let exponent = self.sign_extend(exponent, types::I64);
+                    let low = self.ins().iconst(types::I64, i64::from(i32::MIN));
+                    let high = self.ins().iconst(types::I64, i64::from(i32::MAX));
+                    let exponent = self.ins().smax(exponent, low);
+                    let exponent = self.ins().smin(exponent, high);
+                    let exponent = self.ins().ireduce(types::I32, exponent);
+                    result.push(self.float_helper("ldexp", &[x, exponent])?);
+                }
+                result
+            }
+            PACK_SNORM_4X8 => vec![self.pack_norm(arg(0)?, true, 8)?],
+            PACK_UNORM_4X8 => vec![self.pack_norm(arg(0)?, false, 8)?],
+            PACK_SNORM_2X16 => vec![self.pack_norm(arg(0)?, true, 16)?],
+            PACK_UNORM_2X16 => vec![self.pack_norm(arg(0)?, false, 16)?],
+            PACK_HALF_2X16 => {
+                let mut packed = self.ins().iconst(types::I32, 0);
+                for (index, &x) in arg(0)?.iter().enumerate() {
+                    let half = self.helper("__spirv_f32_to_f16", &[x], types::I32)?;
+                    let half = self.ins().ishl_imm(half, 16 * index as i64);
+                    packed = self.ins().bor(packed, half);
+                }
+                vec![packed]
+            }
+            UNPACK_SNORM_2X16 => self.unpack_norm(arg(0)?[0], true, 16, ty)?,
+            UNPACK_UNORM_2X16 => self.unpack_norm(arg(0)?[0], false, 16, ty)?,
+            UNPACK_SNORM_4X8 => self.unpack_norm(arg(0)?[0], true, 8, ty)?,
+            UNPACK_UNORM_4X8 => self.unpack_norm(arg(0)?[0], false, 8, ty)?,
+            UNPACK_HALF_2X16 => {
+                let packed = arg(0)?[0];
+                let mut result = Vec::new();
+                for index in 0..2 {
+                    let half = self.ins().ushr_imm(packed, 16 * index);
+                    let half = self.ins().band_imm(half, 0xffff);
+                    result.push(self.helper("__spirv_f16_to_f32", &[half], types::F32)?);
+                }
+                result
+            }
+            // Both only reinterpret bits, low word first
+            PACK_DOUBLE_2X32 | UNPACK_DOUBLE_2X32 => self.bitcast(arg(0)?, ty)?,
+            LENGTH => vec![self.length(arg(0)?)],
+            DISTANCE => {
+                let difference = self.zip(arg(0)?, arg(1)?, &mut |t, a, b| t.ins().fsub(a, b));
+                vec![self.length(&difference)]
+            }
+            CROSS => {
+                let (x, y) = (arg(0)?, arg(1)?);
+                let mut result = Vec::new();
+                for index in 0..3 {
+                    let (next, last) = ((index + 1) % 3, (index + 2) % 3);
+                    let a = self.ins().fmul(x[next], y[last]);
+                    let b = self.ins().fmul(x[last], y[next]);
+                    result.push(self.ins().fsub(a, b));
+                }
+                result
+            }
+            NORMALIZE => {
+                let length = self.length(arg(0)?);
+                self.map(arg(0)?, &mut |t, x| t.ins().fdiv(x, length))
+            }
+            FACE_FORWARD => {
+                let d = self.dot(arg(2)?, arg(1)?);
+                let zero = self.zero(self.value_type(d));
+                let negative = self.ins().fcmp(FloatCC::LessThan, d, zero);
+                self.map(arg(0)?, &mut |t, n| {
+                    let negated = t.ins().fneg(n);
+                    t.ins().select(negative, n, negated)
+                })
+            }
+            REFLECT => {
+                // i - 2 * dot(n, i) * n
+                let (i, n) = (arg(0)?, arg(1)?);
+                let d = self.dot(n, i);
+                let two = self.fconst(self.value_type(d), 2.0);
+                let scale = self.ins().fmul(two, d);
+                self.zip(i, n, &mut |t, i, n| {
+                    let n = t.ins().fmul(scale, n);
+                    t.ins().fsub(i, n)
+                })
+            }
+            REFRACT => self.refract(arg(0)?, arg(1)?, arg(2)?[0])?,
+            FIND_I_LSB => {
+                self.map(arg(0)?, &mut |t, x| {
+                    let none = t.ins().icmp_imm(IntCC::Equal, x, 0);
+                    let lsb = t.ins().ctz(x);
+                    let minus_one = t.minus_one(t.value_type(x));
+                    t.ins().select(none, minus_one, lsb)
+                })
+            }
+            FIND_U_MSB => self.map(arg(0)?, &mut |t, x| t.find_msb(x)),
+            FIND_S_MSB => {
+                // Negative numbers look for the highest 0 bit, so flip them
+                self.map(arg(0)?, &mut |t, x| {
+                    let bits = t.value_type(x).bits();
+                    let sign = t.ins().sshr_imm(x, i64::from(bits - 1));
+                    let x = t.ins().bxor(x, sign);
+                    t.find_msb(x)
+                })
+            }
+            _ => return Err(TranspilerError::UnsupportedExtInst(NAME.to_owned(), instruction)),
+        };
+        Ok(value)
+    }
+
+    /// Splits `x` into a fraction and a whole number, both with the sign of `x`.
+    pub fn modf(&mut self, x: &[ir::Value]) -> (Vec<ir::Value>, Vec<ir::Value>) {
+        let whole = self.map(x, &mut |t, x| t.ins().trunc(x));
+        let fract = self.zip(x, &whole, &mut |t, x, whole| t.ins().fsub(x, whole));
+        (fract, whole)
+    }
+
+    /// Splits `x` into a mantissa and an exponent of integer type `exponent_type`.
+    pub fn frexp(
+        &mut self,
+        x: &[ir::Value],
+        exponent_type: ir::Type,
+    ) -> Result<(Vec<ir::Value>, Vec<ir::Value>), TranspilerError> {
+        let (mut mantissa, mut exponent) = (Vec::new(), Vec::new());
+        for &x in x {
+            mantissa.push(self.float_helper("frexp_mantissa", &[x])?);
+            let bits = self.value_type(x).bits();
+            let name = format!("__spirv_frexp_exponent_f{}", bits);
+            let value = self.helper(&name, &[x], types::I32)?;
+            exponent.push(self.sign_extend(value, exponent_type));
+        }
+        Ok((mantissa, exponent))
+    }
+
+    /// Calls the runtime helper `__spirv_<base>_f32` or `__spirv_<base>_f64`, depending on the
+    /// type of the first argument, which is also the type of the result.
+    pub fn float_helper(
+        &mut self,
+        base: &str,
+        args: &[ir::Value],
+    ) -> Result<ir::Value, TranspilerError> {
+        let ty = self.value_type(args[0]);
+        self.helper(&format!("__spirv_{}_f{}", base, ty.bits()), args, ty)
+    }
+
+    fn unary_helper(
+        &mut self,
+        base: &str,
+        x: &[ir::Value],
+    ) -> Result<Vec<ir::Value>, TranspilerError> {
+        x.iter().map(|&x| self.float_helper(base, &[x])).collect()
+    }
+
+    fn binary_helper(
+        &mut self,
+        base: &str,
+        x: &[ir::Value],
+        y: &[ir::Value],
+    ) -> Result<Vec<ir::Value>, TranspilerError> {
+        x.iter().zip(y).map(|(&x, &y)| self.float_helper(base, &[x, y])).collect()
+    }
+
+    /// A float constant of type `ty`, rounded to its precision.
+    fn fconst(&mut self, ty: ir::Type, value: f64) -> ir::Value {
+        match ty {
+            types::F64 => self.ins().f64const(value),
+            _ => self.ins().f32const(value as f32),
+        }
+    }
+
+    /// The integer -1 of type `ty`, with all bits set.
+    fn minus_one(&mut self, ty: ir::Type) -> ir::Value {
+        let zero = self.zero(ty);
+        self.ins().bnot(zero)
+    }
+
+    fn scale(&mut self, x: ir::Value, factor: f64) -> ir::Value {
+        let factor = self.fconst(self.value_type(x), factor);
+        self.ins().fmul(x, factor)
+    }
+
+    /// The smaller of two floats, or the other one if one is NaN, like `f64::min`.
+    fn float_min(&mut self, a: ir::Value, b: ir::Value) -> ir::Value {
+        let less = self.ins().fcmp(FloatCC::LessThan, a, b);
+        let min = self.ins().select(less, a, b);
+        let nan = self.ins().fcmp(FloatCC::Unordered, b, b);
+        self.ins().select(nan, a, min)
+    }
+
+    /// The larger of two floats, or the other one if one is NaN, like `f64::max`.
+    fn float_max(&mut self, a: ir::Value, b: ir::Value) -> ir::Value {
+        let greater = self.ins().fcmp(FloatCC::GreaterThan, a, b);
+        let max = self.ins().select(greater, a, b);
+        let nan = self.ins().fcmp(FloatCC::Unordered, b, b);
+        self.ins().select(nan, a, max)
+    }
+
+    /// The length of a vector, or the absolute value of a scalar.
+    fn length(&mut self, x: &[ir::Value]) -> ir::Value {
+        if x.len() == 1 {
+            return self.ins().fabs(x[0]);
+        }
+        let squared = self.dot(x, x);
+        self.ins().sqrt(squared)
+    }
+
+    /// Returns the highest set bit of `x`, or -1 if there is none.
+    fn find_msb(&mut self, x: ir::Value) -> ir::Value {
+        let ty = self.value_type(x);
+        let none = self.ins().icmp_imm(IntCC::Equal, x, 0);
+        let zeros = self.ins().clz(x);
+        let top = self.ins().iconst(ty, i64::from(ty.bits() - 1));
+        let msb = self.ins().isub(top, zeros);
+        let minus_one = self.minus_one(ty);
+        self.ins().select(none, minus_one, msb)
+    }
+
+    fn refract(
+        &mut self,
+        i: &[ir::Value],
+        n: &[ir::Value],
+        eta: ir::Value,
+    ) -> Result<Vec<ir::Value>, TranspilerError> {
+        // k = 1 - eta * eta * (1 - dot(n, i) * dot(n, i))
+        // k < 0 ? 0 : eta * i - (eta * dot(n, i) + sqrt(k)) * n
+        let d = self.dot(n, i);
+        let ty = self.value_type(d);
+        let eta = match (self.value_type(eta), ty) {
+            (types::F32, types::F64) => self.ins().fpromote(ty, eta),
+            (types::F64, types::F32) => self.ins().fdemote(ty, eta),
+            _ => eta,
+        };
+        let (zero, one) = (self.zero(ty), self.fconst(ty, 1.0));
+        let squared = self.ins().fmul(d, d);
+        let k = self.ins().fsub(one, squared);
+        let eta_squared = self.ins().fmul(eta, eta);
+        let k = self.ins().fmul(eta_squared, k);
+        let k = self.ins().fsub(one, k);
+        let total = self.ins().fcmp(FloatCC::LessThan, k, zero);
+        let root = self.ins().sqrt(k);
+        let factor = self.ins().fmul(eta, d);
+        let factor = self.ins().fadd(factor, root);
+        Ok(self.zip(i, n, &mut |t, i, n| {
+            let i = t.ins().fmul(eta, i);
+            let n = t.ins().fmul(factor, n);
+            let refracted = t.ins().fsub(i, n);
+            t.ins().select(total, zero, refracted)
+        }))
+    }
+
+    /// Converts floats to normalized integers and packs them into one 32-bit integer.
+    fn pack_norm(
+        &mut self,
+        x: &[ir::Value],
+        signed: bool,
+        bits: u32,
+    ) -> Result<ir::Value, TranspilerError> {
+        let (min, scale) = if signed {
+            (-1.0, ((1u64 << (bits - 1)) - 1) as f64)
+        } else {
+            (0.0, ((1u64 << bits) - 1) as f64)
+        };
+        let mut packed = self.ins().iconst(types::I32, 0);
+        for (index, &x) in x.iter().enumerate() {
+            let (min, one, scale) = (
+                self.fconst(types::F32, min),
+                self.fconst(types::F32, 1.0),
+                self.fconst(types::F32, scale),
+            );
+            let clamped = self.float_max(x, min);
+            let clamped = self.float_min(clamped, one);
+            let scaled = self.ins().fmul(clamped, scale);
+            let rounded = self.float_helper("round", &[scaled])?;
+            let int = self.ins().fcvt_to_sint_sat(types::I32, rounded);
+            let int = self.ins().band_imm(int, ((1u64 << bits) - 1) as i64);
+            let int = self.ins().ishl_imm(int, i64::from(bits) * index as i64);
+            packed = self.ins().bor(packed, int);
+        }
+        Ok(packed)
+    }
+
+    /// Unpacks normalized integers into floats of the component type of the vector type `ty`.
+    fn unpack_norm(
+        &mut self,
+        packed: ir::Value,
+        signed: bool,
+        bits: u32,
+        ty: Word,
+    ) -> Result<Vec<ir::Value>, TranspilerError> {
+        let float = self.float_type(ty)?;
+        let mut result = Vec::new();
+        for index in 0..32 / bits {
+            let int = self.ins().ushr_imm(packed, i64::from(bits * index));
+            let x = if signed {
+                let int = self.ins().ishl_imm(int, i64::from(32 - bits));
+                let int = self.ins().sshr_imm(int, i64::from(32 - bits));
+                let x = self.ins().fcvt_from_sint(types::F64, int);
+                let scale = self.fconst(types::F64, ((1u64 << (bits - 1)) - 1) as f64);
+                let x = self.ins().fdiv(x, scale);
+                // The most negative integer would end up below -1
+                let minus_one = self.fconst(types::F64, -1.0);
+                self.float_max(x, minus_one)
+            } else {
+                let int = self.ins().band_imm(int, ((1u64 << bits) - 1) as i64);
+                let x = self.ins().fcvt_from_uint(types::F64, int);
+                let scale = self.fconst(types::F64, ((1u64 << bits) - 1) as f64);
+                self.ins().fdiv(x, scale)
+            };
+            result.push(match float {
+                types::F64 => x,
+                _ => self.ins().fdemote(float, x),
+            });
+        }
+        Ok(result)
+    }
+
+    /// Splits the scalars of the matrix `id` into its columns.
+    fn matrix_columns(
+        &self,
+        id: Word,
+        matrix: &[ir::Value],
+    ) -> Result<Vec<Vec<ir::Value>>, TranspilerError> {
+        let columns = self.columns(self.type_of(id)?)?;
+        Ok(matrix.chunks(matrix.len() / columns).map(|column| column.to_vec()).collect())
+    }
+
+    /// Computes the determinant by Laplace expansion, in the same order as the interpreter.
+    fn determinant(&mut self, m: &[Vec<ir::Value>]) -> ir::Value {
+        if m.len() == 1 {
+            return m[0][0];
+        }
+        let mut result = None;
+        for row in 0..m.len() {
+            let minor = self.determinant(&minor(m, 0, row));
+            let term = self.ins().fmul(m[0][row], minor);
+            result = Some(match result {
+                None => term,
+                Some(sum) if row % 2 == 0 => self.ins().fadd(sum, term),
+                Some(sum) => self.ins().fsub(sum, term),
+            });
+        }
+        result.expect("empty matrix")
+    }
+
+    fn matrix_inverse(&mut self, m: &[Vec<ir::Value>]) -> Vec<ir::Value> {
+        let n = m.len();
+        let determinant = self.determinant(m);
+        let one = self.fconst(self.value_type(determinant), 1.0);
+        let inverse_determinant = self.ins().fdiv(one, determinant);
+        let mut result = Vec::new();
+        for column in 0..n {
+            for row in 0..n {
+                // inverse[column][row] = cofactor(row, column) / determinant
+                let cofactor = self.determinant(&minor(m, row, column));
+                let cofactor = if (row + column) % 2 == 1 {
+                    self.ins().fneg(cofactor)
+                } else {
+                    cofactor
+                };
+                result.push(self.ins().fmul(cofactor, inverse_determinant));
+            }
+        }
+        result
+    }
+}
+
+/// Returns the matrix `m` without column `column` and row `row`.
+fn minor(m: &[Vec<ir::Value>], column: usize, row: usize) -> Vec<Vec<ir::Value>> {
+    m.iter()
+        .enumerate()
+        .filter(|&(index, _)| index != column)
+        .map(|(_, components)| {
+            components
+                .iter()
+                .enumerate()
+                .filter(|&(index, _)| index != row)
+                .map(|(_, &component)| component)
+                .collect()
+        })
+        .collect()
+}
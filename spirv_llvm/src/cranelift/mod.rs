@@ -0,0 +1,205 @@
+//! Compiles modules to native code with Cranelift.
+//!
+//! The module is analyzed like for the interpreter, so both accept the same modules and report
+//! the same errors, then every function is translated to Cranelift IR one invocation at a time.
+//! Entry points are `ShaderFn`s, like the ones LLVM compiles with a single lane.
+mod glsl;
+mod ops;
+mod translate;
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::Arc;
+
+use cranelift_codegen::ir::{AbiParam, UserFuncName};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::FunctionBuilderContext;
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, FuncId, Linkage, Module as ModuleTrait};
+use rspirv::mr::Module;
+use spirv_headers::Op;
+
+use interp::{Interpreter, Layout};
+use runtime;
+use trans::*;
+use {EntryPoint, TranspilerError};
+
+use self::translate::{scalars, Shared};
+
+/// A module compiled to native code with Cranelift.
+///
+/// The machine code lives as long as the `CraneliftModule`, entry points borrow from it.
+pub struct CraneliftModule {
+    /// Only None while dropping.
+    jit: Option<JITModule>,
+    /// Addresses of the entry points, which are `ShaderFn`s.
+    functions: HashMap<String, usize>,
+    /// Bytes of memory the Workgroup variables take up, laid out like the interpreter does.
+    workgroup_memory: usize,
+}
+
+// The JIT module is only touched while compiling, afterwards the module is read only.
+unsafe impl Send for CraneliftModule {}
+unsafe impl Sync for CraneliftModule {}
+
+impl CraneliftModule {
+    /// Compiles `module` for the CPU we are running on.
+    pub fn new(module: Arc<Module>) -> Result<Self, TranspilerError> {
+        let interpreter = Interpreter::new(module)?;
+        let mut flags = settings::builder();
+        flags.set("opt_level", "speed").map_err(codegen_error)?;
+        flags.set("is_pic", "false").map_err(codegen_error)?;
+        flags.set("use_colocated_libcalls", "false").map_err(codegen_error)?;
+        let isa = cranelift_native::builder()
+            .map_err(codegen_error)?
+            .finish(settings::Flags::new(flags))
+            .map_err(codegen_error)?;
+        let mut builder = JITBuilder::with_isa(isa, default_libcall_names());
+        builder.symbols(runtime::symbols().into_iter().map(|(name, address)| {
+            (name, address as *const u8)
+        }));
+        let mut compiled = CraneliftModule {
+            jit: Some(JITModule::new(builder)),
+            functions: HashMap::new(),
+            workgroup_memory: interpreter.workgroup_memory,
+        };
+        compiled.compile(&interpreter)?;
+        Ok(compiled)
+    }
+
+    fn compile(&mut self, interpreter: &Interpreter) -> Result<(), TranspilerError> {
+        let module = &interpreter.module;
+        let types = &interpreter.types;
+        // Every variable starts at a multiple of 16 bytes, which is enough for any type
+        let mut private = Vec::new();
+        let mut private_size = 0;
+        for &(ty, _) in &interpreter.private {
+            private.push(private_size);
+            private_size += round_up(types.size(ty, Layout::Natural)?);
+        }
+
+        let mut layouts = HashMap::new();
+        let mut global_types = HashMap::new();
+        for inst in &module.types_global_values {
+            if inst.class.opcode == Op::TypePointer {
+                let storage_class = operand_storage_class(inst, 0)?;
+                layouts.insert(result_id(inst)?, Layout::for_storage_class(storage_class));
+            }
+            if let (Some(id), Some(ty)) = (inst.result_id, inst.result_type) {
+                global_types.insert(id, ty);
+            }
+        }
+        let kills = module.functions.iter().any(|function| {
+            function.basic_blocks.iter().any(|block| {
+                block.instructions.iter().any(|inst| inst.class.opcode == Op::Kill)
+            })
+        });
+
+        let jit = self.jit.as_mut().expect("module was dropped");
+        let mut functions = HashMap::new();
+        let mut signatures = HashMap::new();
+        for (&id, function) in &interpreter.functions {
+            let definition = &module.functions[function.index];
+            let def = definition.def.as_ref().ok_or(
+                TranspilerError::InvalidInstruction(Op::Function),
+            )?;
+            let mut signature = jit.make_signature();
+            signature.params.push(AbiParam::new(jit.target_config().pointer_type()));
+            signature.params.push(AbiParam::new(jit.target_config().pointer_type()));
+            for parameter in &definition.parameters {
+                for ty in scalars(types, result_type(parameter)?)? {
+                    signature.params.push(AbiParam::new(ty));
+                }
+            }
+            for ty in scalars(types, result_type(def)?)? {
+                signature.returns.push(AbiParam::new(ty));
+            }
+            let name = format!("function{}", id);
+            let func_id = jit.declare_function(&name, Linkage::Local, &signature).map_err(
+                codegen_error,
+            )?;
+            functions.insert(id, func_id);
+            signatures.insert(id, signature);
+        }
+        let mut entry_points: Vec<(&String, FuncId, _)> = Vec::new();
+        let mut entry_signature = jit.make_signature();
+        entry_signature.params.push(AbiParam::new(jit.target_config().pointer_type()));
+        for (index, (name, &function)) in interpreter.entry_points.iter().enumerate() {
+            let func_id = jit.declare_function(
+                &format!("entry{}", index),
+                Linkage::Local,
+                &entry_signature,
+            ).map_err(codegen_error)?;
+            entry_points.push((name, func_id, function));
+        }
+
+        let shared = Shared {
+            interpreter: interpreter,
+            layouts: layouts,
+            global_types: global_types,
+            functions: functions,
+            private: private,
+            private_size: private_size,
+            kills: kills,
+        };
+        let mut context = jit.make_context();
+        let mut builder_context = FunctionBuilderContext::new();
+        for (&id, &func_id) in &shared.functions {
+            context.func.signature = signatures[&id].clone();
+            context.func.name = UserFuncName::user(0, func_id.as_u32());
+            translate::translate_function(
+                &shared,
+                jit,
+                &mut context.func,
+                &mut builder_context,
+                id,
+            )?;
+            jit.define_function(func_id, &mut context).map_err(codegen_error)?;
+            jit.clear_context(&mut context);
+        }
+        for &(_, func_id, function) in &entry_points {
+            context.func.signature = entry_signature.clone();
+            context.func.name = UserFuncName::user(0, func_id.as_u32());
+            translate::translate_entry_point(
+                &shared,
+                jit,
+                &mut context.func,
+                &mut builder_context,
+                function,
+            )?;
+            jit.define_function(func_id, &mut context).map_err(codegen_error)?;
+            jit.clear_context(&mut context);
+        }
+        jit.finalize_definitions().map_err(codegen_error)?;
+        for (name, func_id, _) in entry_points {
+            let address = jit.get_finalized_function(func_id) as usize;
+            self.functions.insert(name.clone(), address);
+        }
+        Ok(())
+    }
+
+    /// Returns the entry point that was declared with the given name.
+    pub fn entry_point<'a>(&'a self, name: &str) -> Option<EntryPoint<'a>> {
+        self.functions.get(name).map(|&function| {
+            EntryPoint::native(function, 1, self.workgroup_memory)
+        })
+    }
+}
+
+impl Drop for CraneliftModule {
+    fn drop(&mut self) {
+        if let Some(jit) = self.jit.take() {
+            // Nobody can call the entry points anymore, they borrow from us
+            unsafe { jit.free_memory() };
+        }
+    }
+}
+
+/// Rounds `size` up to a multiple of 16.
+fn round_up(size: u32) -> u32 {
+    (size + 15) / 16 * 16
+}
+
+fn codegen_error<E: Display>(error: E) -> TranspilerError {
+    TranspilerError::CodegenFailed(error.to_string())
+}
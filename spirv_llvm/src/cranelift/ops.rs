@@ -0,0 +1,676 @@
+//! Instructions whose result only depends on the values of their operands, computed like
+//! `interp::ops` does.
+use cranelift_codegen::ir::condcodes::{FloatCC, IntCC};
+use cranelift_codegen::ir::{self, types, InstBuilder, MemFlags};
+use spirv_headers::{Op, Word};
+
+use interp::Type;
+use TranspilerError;
+
+use super::translate::{int_type, scalars, Translator};
+
+impl<'a, 'b> Translator<'a, 'b> {
+    /// Translates `opcode` with result type `ty`. `args` are the ids and scalars of the id
+    /// operands of the instruction and `literals` its literal operands.
+    pub fn operation(
+        &mut self,
+        opcode: Op,
+        ty: Word,
+        args: &[(Word, Vec<ir::Value>)],
+        literals: &[u32],
+    ) -> Result<Vec<ir::Value>, TranspilerError> {
+        let arg = |index: usize| {
+            args.get(index).map(|&(_, ref scalars)| scalars.as_slice()).ok_or(
+                TranspilerError::InvalidInstruction(opcode),
+            )
+        };
+        let value = match opcode {
+            // Integers
+            Op::IAdd => self.zip(arg(0)?, arg(1)?, &mut |t, a, b| t.ins().iadd(a, b)),
+            Op::ISub => self.zip(arg(0)?, arg(1)?, &mut |t, a, b| t.ins().isub(a, b)),
+            Op::IMul => self.zip(arg(0)?, arg(1)?, &mut |t, a, b| t.ins().imul(a, b)),
+            // Division by zero is undefined, but must not trap
+            Op::UDiv => {
+                self.zip(arg(0)?, arg(1)?, &mut |t, a, b| {
+                    let zero = t.ins().icmp_imm(IntCC::Equal, b, 0);
+                    let divisor = t.safe_divisor(zero, b);
+                    let quotient = t.ins().udiv(a, divisor);
+                    t.or_zero(zero, quotient)
+                })
+            }
+            Op::UMod => {
+                self.zip(arg(0)?, arg(1)?, &mut |t, a, b| {
+                    let zero = t.ins().icmp_imm(IntCC::Equal, b, 0);
+                    let divisor = t.safe_divisor(zero, b);
+                    t.ins().urem(a, divisor)
+                })
+            }
+            Op::SDiv => {
+                self.zip(arg(0)?, arg(1)?, &mut |t, a, b| {
+                    let (zero, minus_one, divisor) = t.signed_divisor(b);
+                    let quotient = t.ins().sdiv(a, divisor);
+                    // Wrap around like the hardware for the most negative number divided by -1
+                    let negated = t.ins().ineg(a);
+                    let quotient = t.ins().select(minus_one, negated, quotient);
+                    t.or_zero(zero, quotient)
+                })
+            }
+            Op::SRem => {
+                self.zip(arg(0)?, arg(1)?, &mut |t, a, b| {
+                    let (_, _, divisor) = t.signed_divisor(b);
+                    t.ins().srem(a, divisor)
+                })
+            }
+            Op::SMod => {
+                // The remainder takes the sign of the divisor
+                self.zip(arg(0)?, arg(1)?, &mut |t, a, b| {
+                    let (_, _, divisor) = t.signed_divisor(b);
+                    let rem = t.ins().srem(a, divisor);
+                    let nonzero = t.ins().icmp_imm(IntCC::NotEqual, rem, 0);
+                    let signs = t.ins().bxor(rem, b);
+                    let differ = t.ins().icmp_imm(IntCC::SignedLessThan, signs, 0);
+                    let fix = t.ins().band(nonzero, differ);
+                    let sum = t.ins().iadd(rem, b);
+                    t.ins().select(fix, sum, rem)
+                })
+            }
+            Op::SNegate => self.map(arg(0)?, &mut |t, a| t.ins().ineg(a)),
+            Op::IAddCarry => {
+                self.extended(arg(0)?, arg(1)?, &mut |t, a, b| {
+                    let sum = t.ins().iadd(a, b);
+                    let carry = t.ins().icmp(IntCC::UnsignedLessThan, sum, a);
+                    (sum, carry)
+                })
+            }
+            Op::ISubBorrow => {
+                self.extended(arg(0)?, arg(1)?, &mut |t, a, b| {
+                    let difference = t.ins().isub(a, b);
+                    let borrow = t.ins().icmp(IntCC::UnsignedLessThan, a, b);
+                    (difference, borrow)
+                })
+            }
+            Op::UMulExtended => {
+                self.extended(arg(0)?, arg(1)?, &mut |t, a, b| {
+                    (t.ins().imul(a, b), t.ins().umulhi(a, b))
+                })
+            }
+            Op::SMulExtended => {
+                self.extended(arg(0)?, arg(1)?, &mut |t, a, b| {
+                    (t.ins().imul(a, b), t.ins().smulhi(a, b))
+                })
+            }
+
+            // Floats
+            Op::FAdd => self.zip(arg(0)?, arg(1)?, &mut |t, a, b| t.ins().fadd(a, b)),
+            Op::FSub => self.zip(arg(0)?, arg(1)?, &mut |t, a, b| t.ins().fsub(a, b)),
+            Op::FMul => self.zip(arg(0)?, arg(1)?, &mut |t, a, b| t.ins().fmul(a, b)),
+            Op::FDiv => self.zip(arg(0)?, arg(1)?, &mut |t, a, b| t.ins().fdiv(a, b)),
+            Op::FRem => {
+                let mut result = Vec::new();
+                for (&a, &b) in arg(0)?.iter().zip(arg(1)?) {
+                    result.push(self.float_helper("fmod", &[a, b])?);
+                }
+                result
+            }
+            Op::FMod => {
+                // The remainder takes the sign of the divisor
+                let mut result = Vec::new();
+                for (&a, &b) in arg(0)?.iter().zip(arg(1)?) {
+                    let rem = self.float_helper("fmod", &[a, b])?;
+                    let zero = self.zero(self.value_type(a));
+                    let nonzero = self.ins().fcmp(FloatCC::NotEqual, rem, zero);
+                    let rem_negative = self.ins().fcmp(FloatCC::LessThan, rem, zero);
+                    let b_negative = self.ins().fcmp(FloatCC::LessThan, b, zero);
+                    let differ = self.ins().bxor(rem_negative, b_negative);
+                    let fix = self.ins().band(nonzero, differ);
+                    let sum = self.ins().fadd(rem, b);
+                    result.push(self.ins().select(fix, sum, rem));
+                }
+                result
+            }
+            Op::FNegate => self.map(arg(0)?, &mut |t, a| t.ins().fneg(a)),
+            Op::VectorTimesScalar | Op::MatrixTimesScalar => {
+                let scalar = arg(1)?[0];
+                self.map(arg(0)?, &mut |t, a| t.ins().fmul(a, scalar))
+            }
+            Op::VectorTimesMatrix => {
+                let vector = arg(0)?;
+                arg(1)?
+                    .chunks(vector.len())
+                    .map(|column| self.dot(vector, column))
+                    .collect()
+            }
+            Op::MatrixTimesVector => self.matrix_times_vector(arg(0)?, arg(1)?),
+            Op::MatrixTimesMatrix => {
+                let columns = self.columns(ty)?;
+                let (matrix, other) = (arg(0)?, arg(1)?);
+                let rows = other.len() / columns;
+                let mut result = Vec::new();
+                for column in other.chunks(rows) {
+                    result.extend(self.matrix_times_vector(matrix, column));
+                }
+                result
+            }
+            Op::OuterProduct => {
+                let a = arg(0)?;
+                let mut result = Vec::new();
+                for &b in arg(1)? {
+                    result.extend(self.map(a, &mut |t, a| t.ins().fmul(a, b)));
+                }
+                result
+            }
+            Op::Dot => vec![self.dot(arg(0)?, arg(1)?)],
+
+            // Bits
+            Op::ShiftRightLogical => self.zip(arg(0)?, arg(1)?, &mut |t, a, b| t.ins().ushr(a, b)),
+            Op::ShiftRightArithmetic => {
+                self.zip(arg(0)?, arg(1)?, &mut |t, a, b| t.ins().sshr(a, b))
+            }
+            Op::ShiftLeftLogical => self.zip(arg(0)?, arg(1)?, &mut |t, a, b| t.ins().ishl(a, b)),
+            Op::BitwiseOr | Op::LogicalOr => {
+                self.zip(arg(0)?, arg(1)?, &mut |t, a, b| t.ins().bor(a, b))
+            }
+            Op::BitwiseXor | Op::LogicalNotEqual => {
+                self.zip(arg(0)?, arg(1)?, &mut |t, a, b| t.ins().bxor(a, b))
+            }
+            Op::BitwiseAnd | Op::LogicalAnd => {
+                self.zip(arg(0)?, arg(1)?, &mut |t, a, b| t.ins().band(a, b))
+            }
+            Op::Not => self.map(arg(0)?, &mut |t, a| t.ins().bnot(a)),
+            Op::BitFieldInsert => {
+                let (offset, count) = (arg(2)?[0], arg(3)?[0]);
+                self.zip(arg(0)?, arg(1)?, &mut |t, base, insert| {
+                    let ty = t.value_type(base);
+                    let (offset, count) = (t.resize(offset, ty), t.resize(count, ty));
+                    let field = t.low_bits(count);
+                    let field = t.ins().ishl(field, offset);
+                    let kept = t.ins().band_not(base, field);
+                    let inserted = t.ins().ishl(insert, offset);
+                    let inserted = t.ins().band(inserted, field);
+                    t.ins().bor(kept, inserted)
+                })
+            }
+            Op::BitFieldUExtract => {
+                let (offset, count) = (arg(1)?[0], arg(2)?[0]);
+                self.map(arg(0)?, &mut |t, base| {
+                    let ty = t.value_type(base);
+                    let (offset, count) = (t.resize(offset, ty), t.resize(count, ty));
+                    let shifted = t.ins().ushr(base, offset);
+                    let mask = t.low_bits(count);
+                    t.ins().band(shifted, mask)
+                })
+            }
+            Op::BitFieldSExtract => {
+                let (offset, count) = (arg(1)?[0], arg(2)?[0]);
+                self.map(arg(0)?, &mut |t, base| {
+                    // Move the field to the top, then shift it back down with its sign
+                    let ty = t.value_type(base);
+                    let (offset, count) = (t.resize(offset, ty), t.resize(count, ty));
+                    let width = t.ins().iconst(ty, i64::from(ty.bits()));
+                    let top = t.ins().isub(width, count);
+                    let left = t.ins().isub(top, offset);
+                    let shifted = t.ins().ishl(base, left);
+                    let field = t.ins().sshr(shifted, top);
+                    let empty = t.ins().icmp_imm(IntCC::Equal, count, 0);
+                    t.or_zero(empty, field)
+                })
+            }
+            Op::BitReverse => self.map(arg(0)?, &mut |t, a| t.ins().bitrev(a)),
+            Op::BitCount => {
+                let result = int_type(self.types().scalar_width(ty))?;
+                self.map(arg(0)?, &mut |t, a| {
+                    let count = t.ins().popcnt(a);
+                    t.resize(count, result)
+                })
+            }
+
+            // Logic
+            Op::LogicalEqual => {
+                self.zip(arg(0)?, arg(1)?, &mut |t, a, b| t.ins().icmp(IntCC::Equal, a, b))
+            }
+            Op::LogicalNot => self.map(arg(0)?, &mut |t, a| t.ins().bxor_imm(a, 1)),
+            Op::Select => {
+                let (condition, a, b) = (arg(0)?, arg(1)?, arg(2)?);
+                let mut result = Vec::new();
+                for (index, (&a, &b)) in a.iter().zip(b).enumerate() {
+                    // A scalar condition selects whole vectors, arrays and structs
+                    let condition = if condition.len() == 1 {
+                        condition[0]
+                    } else {
+                        condition[index]
+                    };
+                    result.push(self.ins().select(condition, a, b));
+                }
+                result
+            }
+            Op::Any => vec![self.fold(arg(0)?, &mut |t, a, b| t.ins().bor(a, b))],
+            Op::All => vec![self.fold(arg(0)?, &mut |t, a, b| t.ins().band(a, b))],
+            Op::IsNan => self.map(arg(0)?, &mut |t, a| t.ins().fcmp(FloatCC::Unordered, a, a)),
+            Op::IsInf => {
+                self.map(arg(0)?, &mut |t, a| {
+                    let magnitude = t.ins().fabs(a);
+                    let infinity = match t.value_type(a) {
+                        types::F32 => t.ins().f32const(f32::INFINITY),
+                        _ => t.ins().f64const(f64::INFINITY),
+                    };
+                    t.ins().fcmp(FloatCC::Equal, magnitude, infinity)
+                })
+            }
+            Op::IEqual | Op::INotEqual | Op::UGreaterThan | Op::SGreaterThan |
+            Op::UGreaterThanEqual | Op::SGreaterThanEqual | Op::ULessThan | Op::SLessThan |
+            Op::ULessThanEqual | Op::SLessThanEqual => {
+                let condition = int_condition(opcode);
+                self.zip(arg(0)?, arg(1)?, &mut |t, a, b| t.ins().icmp(condition, a, b))
+            }
+            Op::FOrdEqual | Op::FUnordEqual | Op::FOrdNotEqual | Op::FUnordNotEqual |
+            Op::FOrdLessThan | Op::FUnordLessThan | Op::FOrdGreaterThan |
+            Op::FUnordGreaterThan | Op::FOrdLessThanEqual | Op::FUnordLessThanEqual |
+            Op::FOrdGreaterThanEqual | Op::FUnordGreaterThanEqual | Op::Ordered |
+            Op::Unordered => {
+                let condition = float_condition(opcode);
+                self.zip(arg(0)?, arg(1)?, &mut |t, a, b| t.ins().fcmp(condition, a, b))
+            }
+
+            // Conversion
+            Op::ConvertFToU | Op::ConvertFToS => {
+                // Convert through 64 bits and drop the high bits like the interpreter
+                let result = int_type(self.types().scalar_width(ty))?;
+                self.map(arg(0)?, &mut |t, a| {
+                    let int = if opcode == Op::ConvertFToU {
+                        t.ins().fcvt_to_uint_sat(types::I64, a)
+                    } else {
+                        t.ins().fcvt_to_sint_sat(types::I64, a)
+                    };
+                    t.resize(int, result)
+                })
+            }
+            Op::ConvertSToF | Op::ConvertUToF => {
+                let result = self.float_type(ty)?;
+                self.map(arg(0)?, &mut |t, a| {
+                    if opcode == Op::ConvertSToF {
+                        let a = t.sign_extend(a, types::I64);
+                        t.ins().fcvt_from_sint(result, a)
+                    } else {
+                        let a = t.resize(a, types::I64);
+                        t.ins().fcvt_from_uint(result, a)
+                    }
+                })
+            }
+            Op::UConvert => {
+                let result = int_type(self.types().scalar_width(ty))?;
+                self.map(arg(0)?, &mut |t, a| t.resize(a, result))
+            }
+            Op::SConvert => {
+                let result = int_type(self.types().scalar_width(ty))?;
+                self.map(arg(0)?, &mut |t, a| t.sign_extend(a, result))
+            }
+            Op::FConvert => {
+                let result = self.float_type(ty)?;
+                self.map(arg(0)?, &mut |t, a| {
+                    let from = t.value_type(a);
+                    if from == result {
+                        a
+                    } else if from.bits() < result.bits() {
+                        t.ins().fpromote(result, a)
+                    } else {
+                        t.ins().fdemote(result, a)
+                    }
+                })
+            }
+            Op::QuantizeToF16 => {
+                let mut result = Vec::new();
+                for &a in arg(0)? {
+                    let half = self.helper("__spirv_f32_to_f16", &[a], types::I32)?;
+                    result.push(self.helper("__spirv_f16_to_f32", &[half], types::F32)?);
+                }
+                result
+            }
+            Op::Bitcast => self.bitcast(arg(0)?, ty)?,
+
+            // Composites
+            Op::VectorExtractDynamic => {
+                let (vector, index) = (arg(0)?, arg(1)?[0]);
+                // Out of bounds reads are undefined, they give 0 like in the interpreter
+                let mut result = self.zero(self.value_type(vector[0]));
+                for (position, &component) in vector.iter().enumerate() {
+                    let hit = self.ins().icmp_imm(IntCC::Equal, index, position as i64);
+                    result = self.ins().select(hit, component, result);
+                }
+                vec![result]
+            }
+            Op::VectorInsertDynamic => {
+                let (vector, component, index) = (arg(0)?, arg(1)?[0], arg(2)?[0]);
+                let mut result = Vec::new();
+                for (position, &old) in vector.iter().enumerate() {
+                    let hit = self.ins().icmp_imm(IntCC::Equal, index, position as i64);
+                    result.push(self.ins().select(hit, component, old));
+                }
+                result
+            }
+            Op::VectorShuffle => {
+                let mut components = arg(0)?.to_vec();
+                components.extend_from_slice(arg(1)?);
+                let ty = self.value_type(components[0]);
+                let mut result = Vec::new();
+                for &index in literals {
+                    match components.get(index as usize) {
+                        Some(&component) => result.push(component),
+                        None => result.push(self.zero(ty)),
+                    }
+                }
+                result
+            }
+            // Vectors may be constructed from smaller vectors, which is all the same here
+            Op::CompositeConstruct => args.iter().flat_map(|&(_, ref arg)| arg.clone()).collect(),
+            Op::CompositeExtract => {
+                let (start, count) = self.component(self.type_of(args[0].0)?, literals)?;
+                arg(0)?[start..start + count].to_vec()
+            }
+            Op::CompositeInsert => {
+                let (start, count) = self.component(self.type_of(args[1].0)?, literals)?;
+                let mut composite = arg(1)?.to_vec();
+                composite[start..start + count].copy_from_slice(arg(0)?);
+                composite
+            }
+            Op::CopyObject => arg(0)?.to_vec(),
+            Op::Transpose => {
+                let matrix = arg(0)?;
+                let columns = self.columns(ty)?;
+                let rows = matrix.len() / columns;
+                let mut result = Vec::new();
+                for column in 0..columns {
+                    for row in 0..rows {
+                        result.push(matrix[row * columns + column]);
+                    }
+                }
+                result
+            }
+            _ => return Err(TranspilerError::UnsupportedInstruction(opcode)),
+        };
+        Ok(value)
+    }
+
+    /// Returns the first scalar and the number of scalars of the part of a value of type `ty`
+    /// that `path` selects, for CompositeExtract and CompositeInsert.
+    fn component(&self, mut ty: Word, path: &[u32]) -> Result<(usize, usize), TranspilerError> {
+        let types = self.types();
+        let mut start = 0;
+        for &index in path {
+            let invalid = TranspilerError::InvalidInstruction(Op::CompositeExtract);
+            match *types.get(ty)? {
+                Type::Struct { ref members } => {
+                    for &member in members.iter().take(index as usize) {
+                        start += scalars(types, member)?.len();
+                    }
+                    ty = *members.get(index as usize).ok_or(invalid)?;
+                }
+                Type::Vector { component: element, count } |
+                Type::Matrix { column: element, count } |
+                Type::Array { element, length: count } => {
+                    if index >= count {
+                        return Err(invalid);
+                    }
+                    start += index as usize * scalars(types, element)?.len();
+                    ty = element;
+                }
+                _ => return Err(invalid),
+            }
+        }
+        Ok((start, scalars(types, ty)?.len()))
+    }
+
+    /// Returns the number of columns of the matrix type `ty`.
+    pub fn columns(&self, ty: Word) -> Result<usize, TranspilerError> {
+        match *self.types().get(ty)? {
+            Type::Matrix { count, .. } => Ok(count as usize),
+            _ => Err(TranspilerError::InvalidInstruction(Op::TypeMatrix)),
+        }
+    }
+
+    /// The float type of the scalar type `ty` or of the components of the vector type `ty`.
+    pub fn float_type(&self, ty: Word) -> Result<ir::Type, TranspilerError> {
+        super::translate::float_type(self.types().scalar_width(ty))
+    }
+
+    pub fn map(
+        &mut self,
+        a: &[ir::Value],
+        f: &mut dyn FnMut(&mut Self, ir::Value) -> ir::Value,
+    ) -> Vec<ir::Value> {
+        a.iter().map(|&a| f(self, a)).collect()
+    }
+
+    pub fn zip(
+        &mut self,
+        a: &[ir::Value],
+        b: &[ir::Value],
+        f: &mut dyn FnMut(&mut Self, ir::Value, ir::Value) -> ir::Value,
+    ) -> Vec<ir::Value> {
+        a.iter().zip(b).map(|(&a, &b)| f(self, a, b)).collect()
+    }
+
+    pub fn zip3(
+        &mut self,
+        a: &[ir::Value],
+        b: &[ir::Value],
+        c: &[ir::Value],
+        f: &mut dyn FnMut(&mut Self, ir::Value, ir::Value, ir::Value) -> ir::Value,
+    ) -> Vec<ir::Value> {
+        a.iter()
+            .zip(b)
+            .zip(c)
+            .map(|((&a, &b), &c)| f(self, a, b, c))
+            .collect()
+    }
+
+    fn fold(
+        &mut self,
+        a: &[ir::Value],
+        f: &mut dyn FnMut(&mut Self, ir::Value, ir::Value) -> ir::Value,
+    ) -> ir::Value {
+        let mut result = a[0];
+        for &a in &a[1..] {
+            result = f(self, result, a);
+        }
+        result
+    }
+
+    /// Computes the instructions that return a struct of a low and a high result, component by
+    /// component.
+    fn extended(
+        &mut self,
+        a: &[ir::Value],
+        b: &[ir::Value],
+        f: &mut dyn FnMut(&mut Self, ir::Value, ir::Value) -> (ir::Value, ir::Value),
+    ) -> Vec<ir::Value> {
+        let mut low = Vec::new();
+        let mut high = Vec::new();
+        for (&a, &b) in a.iter().zip(b) {
+            let (result, carry) = f(self, a, b);
+            let ty = self.value_type(result);
+            low.push(result);
+            high.push(self.resize(carry, ty));
+        }
+        low.extend(high);
+        low
+    }
+
+    /// Replaces a divisor of 0 by 1.
+    fn safe_divisor(&mut self, zero: ir::Value, divisor: ir::Value) -> ir::Value {
+        let ty = self.value_type(divisor);
+        let one = self.ins().iconst(ty, 1);
+        self.ins().select(zero, one, divisor)
+    }
+
+    /// Returns whether `divisor` is 0 and whether it is -1, and `divisor` with both replaced
+    /// by 1, which Cranelift divides by without trapping.
+    fn signed_divisor(&mut self, divisor: ir::Value) -> (ir::Value, ir::Value, ir::Value) {
+        let zero = self.ins().icmp_imm(IntCC::Equal, divisor, 0);
+        let minus_one = self.ins().icmp_imm(IntCC::Equal, divisor, -1);
+        let either = self.ins().bor(zero, minus_one);
+        (zero, minus_one, self.safe_divisor(either, divisor))
+    }
+
+    /// Returns 0 if `condition` holds, `value` otherwise.
+    fn or_zero(&mut self, condition: ir::Value, value: ir::Value) -> ir::Value {
+        let ty = self.value_type(value);
+        let zero = self.ins().iconst(ty, 0);
+        self.ins().select(condition, zero, value)
+    }
+
+    /// Returns a mask of the lowest `count` bits, where `count` may be the full width.
+    fn low_bits(&mut self, count: ir::Value) -> ir::Value {
+        let ty = self.value_type(count);
+        let ones = self.ins().iconst(ty, mask(ty));
+        let width = self.ins().iconst(ty, i64::from(ty.bits()));
+        let shift = self.ins().isub(width, count);
+        let bits = self.ins().ushr(ones, shift);
+        // Shifts are modulo the width, so shifting by the width would keep all bits
+        let empty = self.ins().icmp_imm(IntCC::Equal, count, 0);
+        self.or_zero(empty, bits)
+    }
+
+    /// Zero extends or truncates the integer `value` to `ty`.
+    pub fn resize(&mut self, value: ir::Value, ty: ir::Type) -> ir::Value {
+        let from = self.value_type(value);
+        if from == ty {
+            value
+        } else if from.bits() < ty.bits() {
+            self.ins().uextend(ty, value)
+        } else {
+            self.ins().ireduce(ty, value)
+        }
+    }
+
+    /// Sign extends or truncates the integer `value` to `ty`.
+    pub fn sign_extend(&mut self, value: ir::Value, ty: ir::Type) -> ir::Value {
+        let from = self.value_type(value);
+        if from.bits() < ty.bits() {
+            self.ins().sextend(ty, value)
+        } else {
+            self.resize(value, ty)
+        }
+    }
+
+    /// The dot product of two float vectors, or the product of two floats.
+    pub fn dot(&mut self, a: &[ir::Value], b: &[ir::Value]) -> ir::Value {
+        let products = self.zip(a, b, &mut |t, a, b| t.ins().fmul(a, b));
+        self.fold(&products, &mut |t, a, b| t.ins().fadd(a, b))
+    }
+
+    fn matrix_times_vector(
+        &mut self,
+        matrix: &[ir::Value],
+        vector: &[ir::Value],
+    ) -> Vec<ir::Value> {
+        let rows = matrix.len() / vector.len();
+        let mut result: Option<Vec<ir::Value>> = None;
+        for (column, &scale) in matrix.chunks(rows).zip(vector) {
+            let term = self.map(column, &mut |t, a| t.ins().fmul(a, scale));
+            result = Some(match result {
+                None => term,
+                Some(sum) => self.zip(&sum, &term, &mut |t, a, b| t.ins().fadd(a, b)),
+            });
+        }
+        result.unwrap_or_default()
+    }
+
+    /// Reinterprets the bits of `value` as type `ty`. Vectors are reinterpreted as their
+    /// components one after the other, the first one in the lowest bits.
+    pub fn bitcast(
+        &mut self,
+        value: &[ir::Value],
+        ty: Word,
+    ) -> Result<Vec<ir::Value>, TranspilerError> {
+        let targets = scalars(self.types(), ty)?;
+        let from = self.value_type(value[0]).bits();
+        let to = targets[0].bits();
+        let ints = value
+            .iter()
+            .map(|&scalar| {
+                let ty = self.value_type(scalar);
+                if ty.is_float() {
+                    self.ins().bitcast(ty.as_int(), MemFlags::new(), scalar)
+                } else {
+                    scalar
+                }
+            })
+            .collect::<Vec<_>>();
+        let int = targets[0].as_int();
+        let mut parts = Vec::new();
+        if from < to {
+            // Put several scalars together
+            for chunk in ints.chunks((to / from) as usize) {
+                let mut part = self.ins().uextend(int, chunk[0]);
+                for (index, &scalar) in chunk.iter().enumerate().skip(1) {
+                    let scalar = self.ins().uextend(int, scalar);
+                    let shifted = self.ins().ishl_imm(scalar, (index as u32 * from) as i64);
+                    part = self.ins().bor(part, shifted);
+                }
+                parts.push(part);
+            }
+        } else if from > to {
+            // Split every scalar up
+            for scalar in ints {
+                for index in 0..from / to {
+                    let shifted = self.ins().ushr_imm(scalar, i64::from(index * to));
+                    parts.push(self.ins().ireduce(int, shifted));
+                }
+            }
+        } else {
+            parts = ints;
+        }
+        Ok(parts
+            .into_iter()
+            .zip(targets)
+            .map(|(part, ty)| if ty.is_float() {
+                self.ins().bitcast(ty, MemFlags::new(), part)
+            } else {
+                part
+            })
+            .collect())
+    }
+}
+
+/// All bits of the integer type `ty` set, as an immediate.
+fn mask(ty: ir::Type) -> i64 {
+    if ty.bits() == 64 {
+        -1
+    } else {
+        (1i64 << ty.bits()) - 1
+    }
+}
+
+fn int_condition(opcode: Op) -> IntCC {
+    match opcode {
+        Op::IEqual => IntCC::Equal,
+        Op::INotEqual => IntCC::NotEqual,
+        Op::UGreaterThan => IntCC::UnsignedGreaterThan,
+        Op::SGreaterThan => IntCC::SignedGreaterThan,
+        Op::UGreaterThanEqual => IntCC::UnsignedGreaterThanOrEqual,
+        Op::SGreaterThanEqual => IntCC::SignedGreaterThanOrEqual,
+        Op::ULessThan => IntCC::UnsignedLessThan,
+        Op::SLessThan => IntCC::SignedLessThan,
+        Op::ULessThanEqual => IntCC::UnsignedLessThanOrEqual,
+        _ => IntCC::SignedLessThanOrEqual,
+    }
+}
+
+fn float_condition(opcode: Op) -> FloatCC {
+    match opcode {
+        Op::FOrdEqual => FloatCC::Equal,
+        Op::FUnordEqual => FloatCC::UnorderedOrEqual,
+        Op::FOrdNotEqual => FloatCC::OrderedNotEqual,
+        Op::FUnordNotEqual => FloatCC::NotEqual,
+        Op::FOrdLessThan => FloatCC::LessThan,
+        Op::FUnordLessThan => FloatCC::UnorderedOrLessThan,
+        Op::FOrdGreaterThan => FloatCC::GreaterThan,
+        Op::FUnordGreaterThan => FloatCC::UnorderedOrGreaterThan,
+        Op::FOrdLessThanEqual => FloatCC::LessThanOrEqual,
+        Op::FUnordLessThanEqual => FloatCC::UnorderedOrLessThanOrEqual,
+        Op::FOrdGreaterThanEqual => FloatCC::GreaterThanOrEqual,
+        Op::FUnordGreaterThanEqual => FloatCC::UnorderedOrGreaterThanOrEqual,
+        Op::Ordered => FloatCC::Ordered,
+        _ => FloatCC::Unordered,
+    }
+}
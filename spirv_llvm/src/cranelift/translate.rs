@@ -0,0 +1,1155 @@
+//! Translates the functions of a module to Cranelift IR, one invocation at a time.
+//!
+//! Values are split into their scalars: a vec4 is four values, a struct the scalars of all of
+//! its members. Booleans are I8s holding 0 or 1, pointers are addresses along with the type and
+//! layout they point to, like `interp::value::Pointer::Memory`. Private, Function and Workgroup
+//! variables live in memory in the natural layout, Workgroup variables at the offsets the
+//! interpreter picks.
+use std::collections::{HashMap, HashSet};
+use std::slice;
+
+use cranelift_codegen::ir::immediates::Offset32;
+use cranelift_codegen::ir::{self, types, AbiParam, FuncRef, InstBuilder, MemFlags, StackSlotData,
+                            StackSlotKind, TrapCode};
+use cranelift_frontend::{FuncInstBuilder, FunctionBuilder, FunctionBuilderContext, Switch};
+use cranelift_jit::JITModule;
+use cranelift_module::{FuncId, Linkage, Module};
+use rspirv::mr::{BasicBlock, Function, Instruction, Operand};
+use spirv_headers::{Op, Word};
+
+use abi;
+use glsl_op;
+use interp::{self, ExtInstSet, Global, Interpreter, Layout, Lines, Type, Types};
+use trans::*;
+use TranspilerError;
+
+use super::codegen_error;
+
+/// What the functions of a module share.
+pub struct Shared<'a> {
+    pub interpreter: &'a Interpreter,
+    /// Layout of what pointers of every pointer type point to, by their storage class.
+    pub layouts: HashMap<Word, Layout>,
+    /// Result types of constants and global variables.
+    pub global_types: HashMap<Word, Word>,
+    pub functions: HashMap<Word, FuncId>,
+    /// Offset of every Private variable in the memory an entry point sets aside for them.
+    pub private: Vec<u32>,
+    pub private_size: u32,
+    /// Whether any function discards the fragment, so callers have to check for it.
+    pub kills: bool,
+}
+
+/// The value of an id in generated code.
+#[derive(Clone)]
+pub enum Val {
+    Scalars(Vec<ir::Value>),
+    Pointer(Pointer),
+}
+
+/// Where a pointer points to.
+#[derive(Clone)]
+pub enum Pointer {
+    /// A value of type `ty` laid out according to `layout` at `address`.
+    Memory {
+        address: ir::Value,
+        ty: Word,
+        layout: Layout,
+    },
+    /// An array of buffers of type `ty`, see `interp::value::Pointer::Buffers`.
+    Buffers {
+        descriptors: ir::Value,
+        ty: Word,
+        layout: Layout,
+    },
+    /// The built-in block variable with the given id.
+    BuiltInBlock(Word),
+}
+
+pub struct Translator<'a, 'b> {
+    pub shared: &'a Shared<'a>,
+    jit: &'a mut JITModule,
+    pub builder: FunctionBuilder<'b>,
+    invocation: ir::Value,
+    /// Memory of the Private variables.
+    private: ir::Value,
+    /// Results of the instructions translated so far and the parameters, by id.
+    values: HashMap<Word, Val>,
+    /// Result types of the instructions and parameters of the function.
+    result_types: HashMap<Word, Word>,
+    blocks: HashMap<Word, ir::Block>,
+    /// Phis of every block, by label.
+    phis: HashMap<Word, Vec<&'a Instruction>>,
+    /// Label of the block being translated, which phis refer to.
+    label: Word,
+    returns: Vec<ir::Type>,
+    callees: HashMap<Word, FuncRef>,
+    helpers: HashMap<String, FuncRef>,
+}
+
+/// Translates the function `id` of the module, which takes the `Invocation`, the memory of the
+/// Private variables and its parameters.
+pub fn translate_function(
+    shared: &Shared,
+    jit: &mut JITModule,
+    func: &mut ir::Function,
+    context: &mut FunctionBuilderContext,
+    id: Word,
+) -> Result<(), TranspilerError> {
+    let interpreter = shared.interpreter;
+    let definition = &interpreter.module.functions[interpreter.functions[&id].index];
+    let mut translator = Translator::new(shared, jit, func, context);
+    let entry = translator.current_block()?;
+    let params = translator.builder.block_params(entry).to_vec();
+    translator.invocation = params[0];
+    translator.private = params[1];
+    translator.returns = translator.builder
+        .func
+        .signature
+        .returns
+        .iter()
+        .map(|param| param.value_type)
+        .collect();
+    translator.function(definition, &params[2..])?;
+    translator.builder.seal_all_blocks();
+    translator.builder.finalize();
+    Ok(())
+}
+
+/// Translates an entry point, a `ShaderFn` that initializes the Private variables and calls
+/// `function`.
+pub fn translate_entry_point(
+    shared: &Shared,
+    jit: &mut JITModule,
+    func: &mut ir::Function,
+    context: &mut FunctionBuilderContext,
+    function: Word,
+) -> Result<(), TranspilerError> {
+    let mut translator = Translator::new(shared, jit, func, context);
+    let entry = translator.current_block()?;
+    translator.invocation = translator.builder.block_params(entry)[0];
+    translator.private = if shared.private_size > 0 {
+        let slot = translator.builder.create_sized_stack_slot(StackSlotData::new(
+            StackSlotKind::ExplicitSlot,
+            shared.private_size,
+            4,
+        ));
+        translator.ins().stack_addr(types::I64, slot, 0)
+    } else {
+        translator.ins().iconst(types::I64, 0)
+    };
+    let interpreter = shared.interpreter;
+    for (&(ty, ref initializer), &offset) in interpreter.private.iter().zip(&shared.private) {
+        let value = initializer.clone().unwrap_or_else(|| interpreter.types.zero(ty));
+        let mut scalars = Vec::new();
+        translator.constant(&value, &mut scalars)?;
+        let private = translator.private;
+        translator.store(private, offset as i64, ty, Layout::Natural, &mut scalars.iter())?;
+    }
+    let callee = translator.callee(function)?;
+    let args = [translator.invocation, translator.private];
+    translator.ins().call(callee, &args);
+    translator.ins().return_(&[]);
+    translator.builder.seal_all_blocks();
+    translator.builder.finalize();
+    Ok(())
+}
+
+/// Appends the types of the scalars a value of type `ty` is made of to `scalars`.
+pub fn push_scalars(
+    types: &Types,
+    ty: Word,
+    scalars: &mut Vec<ir::Type>,
+) -> Result<(), TranspilerError> {
+    match *types.get(ty)? {
+        Type::Void => (),
+        Type::Bool => scalars.push(types::I8),
+        Type::Int { width } => scalars.push(int_type(width)?),
+        Type::Float { width } => scalars.push(float_type(width)?),
+        Type::Vector { component: element, count } |
+        Type::Matrix { column: element, count } |
+        Type::Array { element, length: count } => {
+            for _ in 0..count {
+                push_scalars(types, element, scalars)?;
+            }
+        }
+        Type::Struct { ref members } => {
+            for &member in members {
+                push_scalars(types, member, scalars)?;
+            }
+        }
+        Type::Pointer { .. } => scalars.push(types::I64),
+        Type::Opaque { size } => {
+            for _ in 0..size / 8 {
+                scalars.push(types::I64);
+            }
+        }
+        Type::RuntimeArray { .. } | Type::Function => {
+            return Err(TranspilerError::UnsupportedType(types.opcode(ty)?))
+        }
+    }
+    Ok(())
+}
+
+pub fn scalars(types: &Types, ty: Word) -> Result<Vec<ir::Type>, TranspilerError> {
+    let mut scalars = Vec::new();
+    push_scalars(types, ty, &mut scalars)?;
+    Ok(scalars)
+}
+
+pub fn int_type(width: u32) -> Result<ir::Type, TranspilerError> {
+    match width {
+        8 => Ok(types::I8),
+        16 => Ok(types::I16),
+        32 => Ok(types::I32),
+        64 => Ok(types::I64),
+        _ => Err(TranspilerError::UnsupportedType(Op::TypeInt)),
+    }
+}
+
+/// Half floats are not supported, Cranelift has no arithmetic for them.
+pub fn float_type(width: u32) -> Result<ir::Type, TranspilerError> {
+    match width {
+        32 => Ok(types::F32),
+        64 => Ok(types::F64),
+        _ => Err(TranspilerError::UnsupportedType(Op::TypeFloat)),
+    }
+}
+
+/// Flags of all loads and stores. Shaders only access valid memory, but nothing is aligned
+/// beyond what the layout gives.
+fn flags() -> MemFlags {
+    let mut flags = MemFlags::new();
+    flags.set_notrap();
+    flags
+}
+
+/// The id of the OpLabel that starts `block`.
+fn label(block: &BasicBlock) -> Result<Word, TranspilerError> {
+    let label = block.label.as_ref().ok_or(TranspilerError::InvalidInstruction(Op::Label))?;
+    result_id(label)
+}
+
+impl<'a, 'b> Translator<'a, 'b> {
+    fn new(
+        shared: &'a Shared<'a>,
+        jit: &'a mut JITModule,
+        func: &'b mut ir::Function,
+        context: &'b mut FunctionBuilderContext,
+    ) -> Self {
+        let mut builder = FunctionBuilder::new(func, context);
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        let undefined = builder.ins().iconst(types::I64, 0);
+        Translator {
+            shared: shared,
+            jit: jit,
+            builder: builder,
+            invocation: undefined,
+            private: undefined,
+            values: HashMap::new(),
+            result_types: HashMap::new(),
+            blocks: HashMap::new(),
+            phis: HashMap::new(),
+            label: 0,
+            returns: Vec::new(),
+            callees: HashMap::new(),
+            helpers: HashMap::new(),
+        }
+    }
+
+    pub fn ins<'c>(&'c mut self) -> FuncInstBuilder<'c, 'b> {
+        self.builder.ins()
+    }
+
+    pub fn types(&self) -> &'a Types {
+        &self.shared.interpreter.types
+    }
+
+    pub fn value_type(&self, value: ir::Value) -> ir::Type {
+        self.builder.func.dfg.value_type(value)
+    }
+
+    fn function(
+        &mut self,
+        definition: &'a Function,
+        params: &[ir::Value],
+    ) -> Result<(), TranspilerError> {
+        let mut params = params.iter().cloned();
+        for parameter in &definition.parameters {
+            let (id, ty) = (result_id(parameter)?, result_type(parameter)?);
+            let count = scalars(self.types(), ty)?.len();
+            let scalars = params.by_ref().take(count).collect();
+            let value = self.val(ty, scalars)?;
+            self.values.insert(id, value);
+            self.result_types.insert(id, ty);
+        }
+        for block in &definition.basic_blocks {
+            let label = label(block)?;
+            let cl_block = self.builder.create_block();
+            let mut phis = Vec::new();
+            for inst in &block.instructions {
+                if let (Some(id), Some(ty)) = (inst.result_id, inst.result_type) {
+                    self.result_types.insert(id, ty);
+                }
+                if inst.class.opcode == Op::Phi {
+                    for ty in scalars(self.types(), result_type(inst)?)? {
+                        self.builder.append_block_param(cl_block, ty);
+                    }
+                    phis.push(inst);
+                }
+            }
+            self.blocks.insert(label, cl_block);
+            self.phis.insert(label, phis);
+        }
+        let first = match definition.basic_blocks.first() {
+            Some(block) => self.blocks[&label(block)?],
+            None => return Err(TranspilerError::InvalidInstruction(Op::Function)),
+        };
+        self.ins().jump(first, &[]);
+
+        let mut lines = Lines::new(&self.shared.interpreter.module)?;
+        for block in &definition.basic_blocks {
+            // OpLine only reaches to the end of its block
+            lines.line = None;
+            self.label = label(block)?;
+            let cl_block = self.blocks[&self.label];
+            self.builder.switch_to_block(cl_block);
+            let mut params = self.builder.block_params(cl_block).to_vec().into_iter();
+            for phi in self.phis[&self.label].clone() {
+                let ty = result_type(phi)?;
+                let count = scalars(self.types(), ty)?.len();
+                let value = self.val(ty, params.by_ref().take(count).collect())?;
+                self.values.insert(result_id(phi)?, value);
+            }
+            for inst in &block.instructions {
+                lines
+                    .track(inst)
+                    .and_then(|()| self.instruction(inst))
+                    .map_err(|error| error.at(lines.location(inst)))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Wraps the scalars of a value of type `ty`, which may be a pointer.
+    fn val(&self, ty: Word, scalars: Vec<ir::Value>) -> Result<Val, TranspilerError> {
+        Ok(match *self.types().get(ty)? {
+            Type::Pointer { pointee } => {
+                Val::Pointer(Pointer::Memory {
+                    address: scalars[0],
+                    ty: pointee,
+                    layout: self.shared.layouts.get(&ty).cloned().unwrap_or(Layout::Natural),
+                })
+            }
+            _ => Val::Scalars(scalars),
+        })
+    }
+
+    /// The block instructions are added to, which only functions without any blocks lack.
+    fn current_block(&self) -> Result<ir::Block, TranspilerError> {
+        self.builder.current_block().ok_or(
+            TranspilerError::InvalidInstruction(Op::Function),
+        )
+    }
+
+    /// Returns the type of the result of `id`.
+    pub fn type_of(&self, id: Word) -> Result<Word, TranspilerError> {
+        self.result_types
+            .get(&id)
+            .or_else(|| self.shared.global_types.get(&id))
+            .cloned()
+            .ok_or(TranspilerError::UndefinedId(id))
+    }
+
+    fn instruction(&mut self, inst: &Instruction) -> Result<(), TranspilerError> {
+        let opcode = inst.class.opcode;
+        let value = match opcode {
+            Op::Nop | Op::Line | Op::NoLine | Op::LoopMerge | Op::SelectionMerge | Op::Phi => {
+                return Ok(())
+            }
+
+            // Control flow
+            Op::Branch => {
+                let (target, args) = self.target(operand_id(inst, 0)?)?;
+                self.ins().jump(target, &args);
+                return Ok(());
+            }
+            Op::BranchConditional => {
+                let condition = self.scalars(operand_id(inst, 0)?)?[0];
+                let (then, then_args) = self.target(operand_id(inst, 1)?)?;
+                let (otherwise, otherwise_args) = self.target(operand_id(inst, 2)?)?;
+                self.ins().brif(condition, then, &then_args, otherwise, &otherwise_args);
+                return Ok(());
+            }
+            Op::Switch => {
+                self.switch(inst)?;
+                return Ok(());
+            }
+            Op::Return => {
+                self.ins().return_(&[]);
+                return Ok(());
+            }
+            Op::ReturnValue => {
+                let value = self.scalars(operand_id(inst, 0)?)?;
+                self.ins().return_(&value);
+                return Ok(());
+            }
+            Op::Unreachable => {
+                self.ins().trap(TrapCode::unwrap_user(1));
+                return Ok(());
+            }
+            Op::Kill => {
+                self.kill();
+                return Ok(());
+            }
+            Op::FunctionCall => self.call(inst)?,
+
+            // Barriers
+            Op::ControlBarrier => {
+                let resources = self.resources();
+                self.call_helper("__spirv_control_barrier", &[resources], &[])?;
+                return Ok(());
+            }
+            Op::MemoryBarrier => {
+                self.ins().fence();
+                return Ok(());
+            }
+
+            // Memory
+            Op::Variable => {
+                let ty = self.pointee(result_type(inst)?)?;
+                let size = self.types().size(ty, Layout::Natural)?;
+                let slot = self.builder.create_sized_stack_slot(StackSlotData::new(
+                    StackSlotKind::ExplicitSlot,
+                    size,
+                    4,
+                ));
+                let address = self.ins().stack_addr(types::I64, slot, 0);
+                let mut value = match inst.operands.get(1) {
+                    Some(_) => self.scalars(operand_id(inst, 1)?)?,
+                    None => Vec::new(),
+                };
+                if value.is_empty() {
+                    // Start out with 0 like the interpreter
+                    self.constant(&self.types().zero(ty), &mut value)?;
+                }
+                self.store(address, 0, ty, Layout::Natural, &mut value.iter())?;
+                Val::Pointer(Pointer::Memory {
+                    address: address,
+                    ty: ty,
+                    layout: Layout::Natural,
+                })
+            }
+            Op::Load => {
+                let pointer = self.pointer(operand_id(inst, 0)?)?;
+                Val::Scalars(self.load_pointer(&pointer)?)
+            }
+            Op::Store => {
+                let pointer = self.pointer(operand_id(inst, 0)?)?;
+                let value = self.scalars(operand_id(inst, 1)?)?;
+                self.store_pointer(&pointer, &value)?;
+                return Ok(());
+            }
+            Op::CopyMemory => {
+                let target = self.pointer(operand_id(inst, 0)?)?;
+                let source = self.pointer(operand_id(inst, 1)?)?;
+                let value = self.load_pointer(&source)?;
+                self.store_pointer(&target, &value)?;
+                return Ok(());
+            }
+            Op::AccessChain | Op::InBoundsAccessChain => {
+                let base = self.pointer(operand_id(inst, 0)?)?;
+                Val::Pointer(self.access_chain(base, &operand_ids(inst, 1)?)?)
+            }
+            Op::Undef => {
+                let mut value = Vec::new();
+                self.constant(&self.types().zero(result_type(inst)?), &mut value)?;
+                Val::Scalars(value)
+            }
+            Op::ExtInst => {
+                match self.shared.interpreter.ext_inst_set(inst)? {
+                    ExtInstSet::Glsl450 => Val::Scalars(self.ext_inst(inst)?),
+                    ExtInstSet::NonSemantic => return Ok(()),
+                }
+            }
+
+            _ => {
+                let ty = result_type(inst)?;
+                let mut args = Vec::new();
+                let mut literals = Vec::new();
+                for operand in &inst.operands {
+                    match *operand {
+                        Operand::IdRef(arg) => args.push((arg, self.scalars(arg)?)),
+                        Operand::LiteralInt32(literal) => literals.push(literal),
+                        _ => return Err(TranspilerError::InvalidInstruction(opcode)),
+                    }
+                }
+                let value = self.operation(opcode, ty, &args, &literals)?;
+                self.val(ty, value)?
+            }
+        };
+        self.values.insert(result_id(inst)?, value);
+        Ok(())
+    }
+
+    /// Returns the block of the label `target` and the values of its phis when coming from the
+    /// block being translated.
+    fn target(&mut self, target: Word) -> Result<(ir::Block, Vec<ir::Value>), TranspilerError> {
+        let block = *self.blocks.get(&target).ok_or(
+            TranspilerError::UndefinedId(target),
+        )?;
+        let mut args = Vec::new();
+        for phi in self.phis[&target].clone() {
+            let value = phi.operands
+                .chunks(2)
+                .filter_map(|pair| match *pair {
+                    [Operand::IdRef(value), Operand::IdRef(parent)] if parent == self.label => {
+                        Some(value)
+                    }
+                    _ => None,
+                })
+                .next()
+                .ok_or(TranspilerError::InvalidInstruction(Op::Phi))?;
+            args.extend(self.scalars(value)?);
+        }
+        Ok((block, args))
+    }
+
+    fn switch(&mut self, inst: &Instruction) -> Result<(), TranspilerError> {
+        let selector = self.scalars(operand_id(inst, 0)?)?[0];
+        let width = self.value_type(selector).bits();
+        let mask = if width == 64 { !0 } else { (1u64 << width) - 1 };
+        let (default, default_args) = self.target(operand_id(inst, 1)?)?;
+        let default = self.edge(default, default_args)?;
+        let mut switch = Switch::new();
+        let mut literals = HashSet::new();
+        for case in inst.operands[2..].chunks(2) {
+            let literal = match case[0] {
+                Operand::LiteralInt32(literal) => u64::from(literal),
+                Operand::LiteralInt64(literal) => literal,
+                _ => return Err(TranspilerError::InvalidInstruction(Op::Switch)),
+            };
+            let target = match case.get(1) {
+                Some(&Operand::IdRef(target)) => target,
+                _ => return Err(TranspilerError::InvalidInstruction(Op::Switch)),
+            };
+            // Literals are as wide as the selector, the first case wins like in the interpreter
+            if literals.insert(literal & mask) {
+                let (block, args) = self.target(target)?;
+                let block = self.edge(block, args)?;
+                switch.set_entry(u128::from(literal & mask), block);
+            }
+        }
+        switch.emit(&mut self.builder, selector, default);
+        Ok(())
+    }
+
+    /// Returns a block that jumps to `block` with `args`, as switches cannot pass arguments.
+    fn edge(
+        &mut self,
+        block: ir::Block,
+        args: Vec<ir::Value>,
+    ) -> Result<ir::Block, TranspilerError> {
+        if args.is_empty() {
+            return Ok(block);
+        }
+        let current = self.current_block()?;
+        let edge = self.builder.create_block();
+        self.builder.switch_to_block(edge);
+        self.ins().jump(block, &args);
+        self.builder.switch_to_block(current);
+        Ok(edge)
+    }
+
+    /// Discards the fragment: sets `Invocation::killed` and returns all the way up.
+    fn kill(&mut self) {
+        let one = self.ins().iconst(types::I32, 1);
+        let invocation = self.invocation;
+        self.ins().store(flags(), one, invocation, abi::killed_offset() as i32);
+        let returns = self.returns.clone();
+        let values = returns
+            .into_iter()
+            .map(|ty| self.zero(ty))
+            .collect::<Vec<_>>();
+        self.ins().return_(&values);
+    }
+
+    pub fn zero(&mut self, ty: ir::Type) -> ir::Value {
+        match ty {
+            types::F32 => self.ins().f32const(0.0),
+            types::F64 => self.ins().f64const(0.0),
+            _ => self.ins().iconst(ty, 0),
+        }
+    }
+
+    fn call(&mut self, inst: &Instruction) -> Result<Val, TranspilerError> {
+        let function = operand_id(inst, 0)?;
+        let callee = self.callee(function)?;
+        let mut args = vec![self.invocation, self.private];
+        for arg in operand_ids(inst, 1)? {
+            let scalars = self.scalars(arg)?;
+            args.extend(scalars);
+        }
+        let call = self.ins().call(callee, &args);
+        let results = self.builder.inst_results(call).to_vec();
+        if self.shared.kills {
+            // Stop right away if the callee discarded the fragment
+            let invocation = self.invocation;
+            let offset = abi::killed_offset() as i32;
+            let killed = self.ins().load(types::I32, flags(), invocation, offset);
+            let kill = self.builder.create_block();
+            let next = self.builder.create_block();
+            self.ins().brif(killed, kill, &[], next, &[]);
+            self.builder.switch_to_block(kill);
+            self.kill();
+            self.builder.switch_to_block(next);
+        }
+        self.val(result_type(inst)?, results)
+    }
+
+    /// Returns a reference to the module function `function` for calling it.
+    fn callee(&mut self, function: Word) -> Result<FuncRef, TranspilerError> {
+        if let Some(&callee) = self.callees.get(&function) {
+            return Ok(callee);
+        }
+        let id = *self.shared.functions.get(&function).ok_or(
+            TranspilerError::UndefinedId(function),
+        )?;
+        let callee = self.jit.declare_func_in_func(id, self.builder.func);
+        self.callees.insert(function, callee);
+        Ok(callee)
+    }
+
+    /// Calls the runtime helper `name`, see `runtime`.
+    pub fn helper(
+        &mut self,
+        name: &str,
+        args: &[ir::Value],
+        result: ir::Type,
+    ) -> Result<ir::Value, TranspilerError> {
+        let call = self.call_helper(name, args, &[result])?;
+        Ok(self.builder.inst_results(call)[0])
+    }
+
+    /// Calls the runtime helper `name`, which returns `results`.
+    fn call_helper(
+        &mut self,
+        name: &str,
+        args: &[ir::Value],
+        results: &[ir::Type],
+    ) -> Result<ir::Inst, TranspilerError> {
+        let helper = match self.helpers.get(name) {
+            Some(&helper) => helper,
+            None => {
+                let mut signature = self.jit.make_signature();
+                for &arg in args {
+                    signature.params.push(AbiParam::new(self.value_type(arg)));
+                }
+                for &result in results {
+                    signature.returns.push(AbiParam::new(result));
+                }
+                let id = self.jit.declare_function(name, Linkage::Import, &signature).map_err(
+                    codegen_error,
+                )?;
+                let helper = self.jit.declare_func_in_func(id, self.builder.func);
+                self.helpers.insert(name.to_owned(), helper);
+                helper
+            }
+        };
+        Ok(self.ins().call(helper, args))
+    }
+
+    /// Returns the value of `id`, which may be a result of the function, a constant or a global
+    /// variable.
+    fn value(&mut self, id: Word) -> Result<Val, TranspilerError> {
+        if let Some(value) = self.values.get(&id) {
+            return Ok(value.clone());
+        }
+        let interpreter = self.shared.interpreter;
+        if let Some(value) = interpreter.constants.get(&id) {
+            let mut scalars = Vec::new();
+            self.constant(value, &mut scalars)?;
+            return Ok(Val::Scalars(scalars));
+        }
+        match interpreter.globals.get(&id) {
+            Some(global) => Ok(Val::Pointer(self.global_pointer(id, global)?)),
+            None => Err(TranspilerError::UndefinedId(id)),
+        }
+    }
+
+    /// Returns the scalars of `id`, the address for pointers.
+    pub fn scalars(&mut self, id: Word) -> Result<Vec<ir::Value>, TranspilerError> {
+        match self.value(id)? {
+            Val::Scalars(scalars) => Ok(scalars),
+            Val::Pointer(Pointer::Memory { address, .. }) => Ok(vec![address]),
+            // Built-in blocks and arrays of buffers are not in one place
+            Val::Pointer(_) => Err(TranspilerError::UnsupportedType(Op::TypePointer)),
+        }
+    }
+
+    pub fn pointer(&mut self, id: Word) -> Result<Pointer, TranspilerError> {
+        match self.value(id)? {
+            Val::Pointer(pointer) => Ok(pointer),
+            Val::Scalars(_) => Err(TranspilerError::InvalidInstruction(Op::TypePointer)),
+        }
+    }
+
+    fn pointee(&self, pointer: Word) -> Result<Word, TranspilerError> {
+        match *self.types().get(pointer)? {
+            Type::Pointer { pointee } => Ok(pointee),
+            _ => Err(TranspilerError::InvalidInstruction(Op::TypePointer)),
+        }
+    }
+
+    /// Appends the scalars of a constant to `scalars`.
+    pub fn constant(
+        &mut self,
+        value: &interp::Value,
+        scalars: &mut Vec<ir::Value>,
+    ) -> Result<(), TranspilerError> {
+        let scalar = match *value {
+            interp::Value::Bool(value) => self.ins().iconst(types::I8, value as i64),
+            interp::Value::Int(bits, width) => {
+                let ty = int_type(width)?;
+                self.ins().iconst(ty, bits as i64)
+            }
+            interp::Value::Float(value, width) => {
+                match float_type(width)? {
+                    types::F32 => self.ins().f32const(value as f32),
+                    _ => self.ins().f64const(value),
+                }
+            }
+            interp::Value::Composite(ref components) => {
+                for component in components {
+                    self.constant(component, scalars)?;
+                }
+                return Ok(());
+            }
+            interp::Value::Opaque(ref bytes) => {
+                for chunk in bytes.chunks(8) {
+                    let bits = chunk.iter().rev().fold(0u64, |bits, &byte| {
+                        bits << 8 | u64::from(byte)
+                    });
+                    let scalar = self.ins().iconst(types::I64, bits as i64);
+                    scalars.push(scalar);
+                }
+                return Ok(());
+            }
+            interp::Value::Pointer(_) => {
+                return Err(TranspilerError::UnsupportedType(Op::TypePointer))
+            }
+        };
+        scalars.push(scalar);
+        Ok(())
+    }
+
+    /// Returns a pointer to the global variable `id`, like `Execution::global_pointer`.
+    fn global_pointer(&mut self, id: Word, global: &Global) -> Result<Pointer, TranspilerError> {
+        let shared = self.shared;
+        let invocation = self.invocation;
+        let pointer = match *global {
+            Global::Private(index) => {
+                let private = self.private;
+                Pointer::Memory {
+                    address: self.ins().iadd_imm(private, i64::from(shared.private[index])),
+                    ty: shared.interpreter.private[index].0,
+                    layout: Layout::Natural,
+                }
+            }
+            Global::Workgroup { offset, ty } => {
+                let resources = self.resources();
+                let memory_offset = abi::workgroup_memory_offset() as i32;
+                let memory = self.ins().load(types::I64, flags(), resources, memory_offset);
+                Pointer::Memory {
+                    address: self.ins().iadd_imm(memory, offset as i64),
+                    ty: ty,
+                    layout: Layout::Natural,
+                }
+            }
+            Global::Invocation { offset, ty, layout } => {
+                Pointer::Memory {
+                    address: self.ins().iadd_imm(invocation, offset as i64),
+                    ty: ty,
+                    layout: layout,
+                }
+            }
+            Global::BuiltInBlock(_) => Pointer::BuiltInBlock(id),
+            Global::PushConstants { ty } => {
+                let resources = self.resources();
+                let offset = abi::push_constants_offset() as i32;
+                Pointer::Memory {
+                    address: self.ins().load(types::I64, flags(), resources, offset),
+                    ty: ty,
+                    layout: Layout::Explicit { matrix_stride: 0 },
+                }
+            }
+            Global::Descriptor {
+                set,
+                binding,
+                buffer,
+                ty,
+                layout,
+            } => {
+                let resources = self.resources();
+                let offset = abi::descriptor_set_offset(set).ok_or(
+                    TranspilerError::InvalidLayout(id),
+                )?;
+                let set = self.ins().load(types::I64, flags(), resources, offset as i32);
+                let descriptors = self.ins().load(types::I64, flags(), set, binding as i32 * 8);
+                let arrayed = match *self.types().get(ty)? {
+                    Type::Array { .. } | Type::RuntimeArray { .. } => true,
+                    _ => false,
+                };
+                if !buffer {
+                    // Arrays of images and samplers are laid out like their descriptors
+                    Pointer::Memory {
+                        address: descriptors,
+                        ty: ty,
+                        layout: Layout::Natural,
+                    }
+                } else if arrayed {
+                    // Every buffer has a descriptor of its own, the access chain picks one
+                    Pointer::Buffers {
+                        descriptors: descriptors,
+                        ty: ty,
+                        layout: layout,
+                    }
+                } else {
+                    Pointer::Memory {
+                        address: self.ins().load(types::I64, flags(), descriptors, 0),
+                        ty: ty,
+                        layout: layout,
+                    }
+                }
+            }
+        };
+        Ok(pointer)
+    }
+
+    fn resources(&mut self) -> ir::Value {
+        let invocation = self.invocation;
+        let offset = abi::resources_offset() as i32;
+        self.ins().load(types::I64, flags(), invocation, offset)
+    }
+
+    fn access_chain(
+        &mut self,
+        base: Pointer,
+        indices: &[Word],
+    ) -> Result<Pointer, TranspilerError> {
+        let types = self.types();
+        let (mut address, mut ty, mut layout, indices) = match base {
+            Pointer::Memory {
+                address,
+                ty,
+                layout,
+            } => (address, ty, layout, indices),
+            Pointer::Buffers {
+                descriptors,
+                ty,
+                layout,
+            } => {
+                let element = match *types.get(ty)? {
+                    Type::Array { element, .. } | Type::RuntimeArray { element } => element,
+                    _ => return Err(TranspilerError::InvalidInstruction(Op::AccessChain)),
+                };
+                let index = self.index(indices[0])?;
+                let offset = self.ins().imul_imm(index, 8);
+                let descriptor = self.ins().iadd(descriptors, offset);
+                let buffer = self.ins().load(types::I64, flags(), descriptor, 0);
+                (buffer, element, layout, &indices[1..])
+            }
+            Pointer::BuiltInBlock(variable) => {
+                let member = self.constant_index(indices[0]).ok_or(
+                    TranspilerError::InvalidInstruction(Op::AccessChain),
+                )?;
+                let (offset, member) = self.builtin_member(variable, member as u32)?;
+                let invocation = self.invocation;
+                let address = self.ins().iadd_imm(invocation, offset as i64);
+                (address, member, Layout::Natural, &indices[1..])
+            }
+        };
+        for &index in indices {
+            match self.constant_index(index) {
+                Some(index) => {
+                    let (offset, element, element_layout) = types.element(ty, layout, index)?;
+                    address = self.ins().iadd_imm(address, offset);
+                    ty = element;
+                    layout = element_layout;
+                }
+                None => {
+                    if let Type::Struct { .. } = *types.get(ty)? {
+                        return Err(TranspilerError::InvalidInstruction(Op::AccessChain));
+                    }
+                    let (stride, element, element_layout) = types.element(ty, layout, 1)?;
+                    let index = self.index(index)?;
+                    let offset = self.ins().imul_imm(index, stride);
+                    address = self.ins().iadd(address, offset);
+                    ty = element;
+                    layout = element_layout;
+                }
+            }
+        }
+        Ok(Pointer::Memory {
+            address: address,
+            ty: ty,
+            layout: layout,
+        })
+    }
+
+    /// Returns the value of `id` if it is an integer constant. Indices are signed.
+    fn constant_index(&self, id: Word) -> Option<i64> {
+        match self.shared.interpreter.constants.get(&id) {
+            Some(value @ &interp::Value::Int(..)) => Some(value.signed()),
+            _ => None,
+        }
+    }
+
+    /// Returns the index `id` as a 64-bit integer.
+    fn index(&mut self, id: Word) -> Result<ir::Value, TranspilerError> {
+        let index = self.scalars(id)?[0];
+        if self.value_type(index) == types::I64 {
+            Ok(index)
+        } else {
+            Ok(self.ins().sextend(types::I64, index))
+        }
+    }
+
+    /// Returns the offset in the `Invocation` and the type of a member of a built-in block.
+    fn builtin_member(
+        &self,
+        variable: Word,
+        member: u32,
+    ) -> Result<(usize, Word), TranspilerError> {
+        // Interpreter::new rejected access chains to unsupported members
+        match self.builtin_members(variable)?.get(member as usize) {
+            Some(&(Some(offset), ty)) => Ok((offset, ty)),
+            _ => Err(TranspilerError::InvalidInstruction(Op::AccessChain)),
+        }
+    }
+
+    /// Returns the offset in the `Invocation` of every member of a built-in block, None for
+    /// the ones that are not supported, along with their types.
+    fn builtin_members(
+        &self,
+        variable: Word,
+    ) -> Result<Vec<(Option<usize>, Word)>, TranspilerError> {
+        let interpreter = self.shared.interpreter;
+        let offsets = match interpreter.globals.get(&variable) {
+            Some(&Global::BuiltInBlock(ref offsets)) => offsets,
+            _ => return Err(TranspilerError::UndefinedId(variable)),
+        };
+        let pointee = interpreter.pointee(variable)?;
+        match *self.types().get(pointee)? {
+            Type::Struct { ref members } => {
+                Ok(offsets.iter().cloned().zip(members.iter().cloned()).collect())
+            }
+            _ => Err(TranspilerError::InvalidInstruction(Op::Variable)),
+        }
+    }
+
+    pub fn load_pointer(&mut self, pointer: &Pointer) -> Result<Vec<ir::Value>, TranspilerError> {
+        let mut scalars = Vec::new();
+        match *pointer {
+            Pointer::Memory {
+                address,
+                ty,
+                layout,
+            } => self.load(address, 0, ty, layout, &mut scalars)?,
+            Pointer::BuiltInBlock(variable) => {
+                for (offset, ty) in self.builtin_members(variable)? {
+                    match offset {
+                        Some(offset) => {
+                            let invocation = self.invocation;
+                            self.load(invocation, offset as i64, ty, Layout::Natural, &mut scalars)?
+                        }
+                        // Built-ins that are not supported read as 0
+                        None => self.constant(&self.types().zero(ty), &mut scalars)?,
+                    }
+                }
+            }
+            Pointer::Buffers { .. } => {
+                return Err(TranspilerError::UnsupportedInstruction(Op::Load))
+            }
+        }
+        Ok(scalars)
+    }
+
+    pub fn store_pointer(
+        &mut self,
+        pointer: &Pointer,
+        value: &[ir::Value],
+    ) -> Result<(), TranspilerError> {
+        let mut scalars = value.iter();
+        match *pointer {
+            Pointer::Memory {
+                address,
+                ty,
+                layout,
+            } => self.store(address, 0, ty, layout, &mut scalars),
+            Pointer::BuiltInBlock(variable) => {
+                for (offset, ty) in self.builtin_members(variable)? {
+                    match offset {
+                        Some(offset) => {
+                            let invocation = self.invocation;
+                            let offset = offset as i64;
+                            self.store(invocation, offset, ty, Layout::Natural, &mut scalars)?
+                        }
+                        None => {
+                            let count = self::scalars(self.types(), ty)?.len();
+                            scalars.by_ref().take(count).count();
+                        }
+                    }
+                }
+                Ok(())
+            }
+            Pointer::Buffers { .. } => Err(TranspilerError::UnsupportedInstruction(Op::Store)),
+        }
+    }
+
+    /// Loads a value of type `ty` laid out according to `layout` from `address + offset`, like
+    /// `Types::load`.
+    fn load(
+        &mut self,
+        address: ir::Value,
+        offset: i64,
+        ty: Word,
+        layout: Layout,
+        scalars: &mut Vec<ir::Value>,
+    ) -> Result<(), TranspilerError> {
+        let types = self.types();
+        let scalar_ty = match *types.get(ty)? {
+            Type::Bool => {
+                let offset = Offset32::new(offset as i32);
+                let byte = self.ins().load(types::I8, flags(), address, offset);
+                scalars.push(self.ins().band_imm(byte, 1));
+                return Ok(());
+            }
+            Type::Int { width } => int_type(width)?,
+            Type::Float { width } => float_type(width)?,
+            Type::Vector { count, .. } |
+            Type::Matrix { count, .. } |
+            Type::Array { length: count, .. } => {
+                for index in 0..count {
+                    let (element_offset, element, layout) =
+                        types.element(ty, layout, i64::from(index))?;
+                    self.load(address, offset + element_offset, element, layout, scalars)?;
+                }
+                return Ok(());
+            }
+            Type::Struct { ref members } => {
+                for member in 0..members.len() {
+                    let (member_offset, member, layout) =
+                        types.element(ty, layout, member as i64)?;
+                    self.load(address, offset + member_offset, member, layout, scalars)?;
+                }
+                return Ok(());
+            }
+            Type::Opaque { size } => {
+                for index in 0..i64::from(size / 8) {
+                    let offset = Offset32::new((offset + index * 8) as i32);
+                    scalars.push(self.ins().load(types::I64, flags(), address, offset));
+                }
+                return Ok(());
+            }
+            _ => return Err(TranspilerError::UnsupportedType(types.opcode(ty)?)),
+        };
+        scalars.push(self.ins().load(scalar_ty, flags(), address, Offset32::new(offset as i32)));
+        Ok(())
+    }
+
+    /// Stores the next scalars as a value of type `ty` laid out according to `layout` at
+    /// `address + offset`, the inverse of `load`.
+    fn store(
+        &mut self,
+        address: ir::Value,
+        offset: i64,
+        ty: Word,
+        layout: Layout,
+        scalars: &mut slice::Iter<ir::Value>,
+    ) -> Result<(), TranspilerError> {
+        let types = self.types();
+        match *types.get(ty)? {
+            Type::Bool | Type::Int { .. } | Type::Float { .. } => {
+                let scalar = *scalars.next().ok_or(
+                    TranspilerError::InvalidInstruction(Op::Store),
+                )?;
+                self.ins().store(flags(), scalar, address, Offset32::new(offset as i32));
+            }
+            Type::Vector { count, .. } |
+            Type::Matrix { count, .. } |
+            Type::Array { length: count, .. } => {
+                for index in 0..count {
+                    let (element_offset, element, layout) =
+                        types.element(ty, layout, i64::from(index))?;
+                    self.store(address, offset + element_offset, element, layout, scalars)?;
+                }
+            }
+            Type::Struct { ref members } => {
+                for member in 0..members.len() {
+                    let (member_offset, member, layout) =
+                        types.element(ty, layout, member as i64)?;
+                    self.store(address, offset + member_offset, member, layout, scalars)?;
+                }
+            }
+            Type::Opaque { size } => {
+                for index in 0..i64::from(size / 8) {
+                    let scalar = *scalars.next().ok_or(
+                        TranspilerError::InvalidInstruction(Op::Store),
+                    )?;
+                    let offset = Offset32::new((offset + index * 8) as i32);
+                    self.ins().store(flags(), scalar, address, offset);
+                }
+            }
+            _ => return Err(TranspilerError::UnsupportedType(types.opcode(ty)?)),
+        }
+        Ok(())
+    }
+
+    /// Translates an instruction of GLSL.std.450. The ones that access memory are handled here,
+    /// the others by `glsl`.
+    fn ext_inst(&mut self, inst: &Instruction) -> Result<Vec<ir::Value>, TranspilerError> {
+        let ty = result_type(inst)?;
+        let instruction = operand_u32(inst, 1)?;
+        let ids = operand_ids(inst, 2)?;
+        match instruction {
+            glsl_op::MODF => {
+                let x = self.scalars(ids[0])?;
+                let (fract, whole) = self.modf(&x);
+                let pointer = self.pointer(ids[1])?;
+                self.store_pointer(&pointer, &whole)?;
+                Ok(fract)
+            }
+            glsl_op::FREXP => {
+                let x = self.scalars(ids[0])?;
+                // The exponent has whatever integer type the pointer points to
+                let pointer = self.pointer(ids[1])?;
+                let exponent_ty = match pointer {
+                    Pointer::Memory { ty, .. } => ty,
+                    _ => return Err(TranspilerError::InvalidInstruction(Op::ExtInst)),
+                };
+                let width = self.types().scalar_width(exponent_ty);
+                let (mantissa, exponent) = self.frexp(&x, int_type(width)?)?;
+                self.store_pointer(&pointer, &exponent)?;
+                Ok(mantissa)
+            }
+            // We only ever rasterize with one sample at the pixel center, which is where the
+            // interpolants already are.
+            glsl_op::INTERPOLATE_AT_CENTROID |
+            glsl_op::INTERPOLATE_AT_SAMPLE |
+            glsl_op::INTERPOLATE_AT_OFFSET => {
+                let pointer = self.pointer(ids[0])?;
+                self.load_pointer(&pointer)
+            }
+            _ => {
+                let mut args = Vec::new();
+                for id in ids {
+                    args.push((id, self.scalars(id)?));
+                }
+                self.glsl(instruction, ty, &args)
+            }
+        }
+    }
+}
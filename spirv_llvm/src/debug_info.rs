@@ -0,0 +1,290 @@
+//! DWARF debug info for translated modules, so that debuggers can step through the high level
+//! source of JIT'd shaders.
+//!
+//! Debug info is only emitted if `SPIRV_LLVM_DEBUG_INFO` is set, as it slows down compilation.
+//! MCJIT registers every object it loads with the GDB JIT interface, which is where gdb finds
+//! the line tables. lldb needs `settings set plugin.jit-loader.gdb.enable on` to look there.
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+use std::ptr;
+
+use llvm_sys::core::*;
+use llvm_sys::debuginfo::*;
+use llvm_sys::prelude::*;
+use llvm_sys::LLVMModuleFlagBehavior;
+
+use SourceLine;
+
+/// Environment variable that turns on debug info when set to anything but "" or "0".
+pub const DEBUG_INFO_VARIABLE: &str = "SPIRV_LLVM_DEBUG_INFO";
+
+const DW_ATE_BOOLEAN: LLVMDWARFTypeEncoding = 0x02;
+const DW_ATE_FLOAT: LLVMDWARFTypeEncoding = 0x04;
+const DW_ATE_SIGNED: LLVMDWARFTypeEncoding = 0x05;
+const DW_ATE_UNSIGNED: LLVMDWARFTypeEncoding = 0x08;
+
+/// Whether `SPIRV_LLVM_DEBUG_INFO` asks for debug info.
+pub fn enabled() -> bool {
+    match env::var_os(DEBUG_INFO_VARIABLE) {
+        Some(value) => !value.is_empty() && value != "0",
+        None => false,
+    }
+}
+
+/// The kinds of scalars a debugger can show.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Bool,
+    Signed,
+    Unsigned,
+    Float,
+}
+
+/// Builds the debug info of one module.
+pub struct DebugInfo {
+    ctx: LLVMContextRef,
+    builder: LLVMDIBuilderRef,
+    /// The file OpSource names, which functions without any OpLine are blamed on.
+    source: String,
+    files: HashMap<String, LLVMMetadataRef>,
+    subroutine_type: LLVMMetadataRef,
+    /// Subprogram of the function being translated and its scope for every other file that
+    /// OpLine refers to.
+    subprogram: LLVMMetadataRef,
+    scopes: HashMap<String, LLVMMetadataRef>,
+    /// The position of the instructions being translated.
+    location: LLVMMetadataRef,
+}
+
+impl DebugInfo {
+    /// Starts the debug info of `module`. `source` is the file OpSource names, if any.
+    pub unsafe fn new(ctx: LLVMContextRef, module: LLVMModuleRef, source: Option<&str>) -> Self {
+        add_module_flag(ctx, module, "Debug Info Version", LLVMDebugMetadataVersion());
+        add_module_flag(ctx, module, "Dwarf Version", 4);
+        let builder = LLVMCreateDIBuilder(module);
+        let mut debug_info = DebugInfo {
+            ctx: ctx,
+            builder: builder,
+            source: source.unwrap_or("<spirv>").to_owned(),
+            files: HashMap::new(),
+            subroutine_type: ptr::null_mut(),
+            subprogram: ptr::null_mut(),
+            scopes: HashMap::new(),
+            location: ptr::null_mut(),
+        };
+        // DWARF has no language code for shading languages, C is close enough for debuggers
+        let source = debug_info.source.clone();
+        let file = debug_info.file(&source);
+        let producer = "spirv_llvm";
+        LLVMDIBuilderCreateCompileUnit(
+            builder,
+            LLVMDWARFSourceLanguage::LLVMDWARFSourceLanguageC,
+            file,
+            producer.as_ptr() as *const _,
+            producer.len(),
+            0,
+            ptr::null(),
+            0,
+            0,
+            ptr::null(),
+            0,
+            LLVMDWARFEmissionKind::LLVMDWARFEmissionKindFull,
+            0,
+            0,
+            0,
+            ptr::null(),
+            0,
+            ptr::null(),
+            0,
+        );
+        // Shaders have no parameters a debugger could make sense of
+        debug_info.subroutine_type =
+            LLVMDIBuilderCreateSubroutineType(builder, file, ptr::null_mut(), 0, LLVMDIFlagZero);
+        debug_info
+    }
+
+    /// Returns the descriptor of the file at `path`.
+    unsafe fn file(&mut self, path: &str) -> LLVMMetadataRef {
+        if let Some(&file) = self.files.get(path) {
+            return file;
+        }
+        let (directory, name) = {
+            let path = Path::new(path);
+            let name = path.file_name().map(|name| name.to_string_lossy().into_owned());
+            let directory = path.parent().map(|parent| parent.to_string_lossy().into_owned());
+            (directory.unwrap_or_default(), name.unwrap_or_default())
+        };
+        let file = LLVMDIBuilderCreateFile(
+            self.builder,
+            name.as_ptr() as *const _,
+            name.len(),
+            directory.as_ptr() as *const _,
+            directory.len(),
+        );
+        self.files.insert(path.to_owned(), file);
+        file
+    }
+
+    /// Attaches a subprogram to `function`, which the instructions built from now on belong to.
+    /// `line` is where the function starts, if known.
+    pub unsafe fn start_function(
+        &mut self,
+        builder: LLVMBuilderRef,
+        function: LLVMValueRef,
+        name: &str,
+        line: Option<&SourceLine>,
+    ) {
+        let path = match line.and_then(|line| line.file.as_ref()) {
+            Some(path) => path.clone(),
+            None => self.source.clone(),
+        };
+        let file = self.file(&path);
+        let line_number = line.map_or(0, |line| line.line);
+        let mut linkage_name_length = 0;
+        let linkage_name = LLVMGetValueName2(function, &mut linkage_name_length);
+        self.subprogram = LLVMDIBuilderCreateFunction(
+            self.builder,
+            file,
+            name.as_ptr() as *const _,
+            name.len(),
+            linkage_name,
+            linkage_name_length,
+            file,
+            line_number,
+            self.subroutine_type,
+            0,
+            1,
+            line_number,
+            LLVMDIFlagPrototyped,
+            0,
+        );
+        LLVMSetSubprogram(function, self.subprogram);
+        self.scopes.clear();
+        self.scopes.insert(path, self.subprogram);
+        self.set_location(builder, None);
+    }
+
+    /// Gives the instructions built from now on the source position `line`. Every instruction
+    /// gets a location, as LLVM insists on one for calls, so those without an OpLine are put
+    /// on line 0.
+    pub unsafe fn set_location(&mut self, builder: LLVMBuilderRef, line: Option<&SourceLine>) {
+        let (line_number, column) = line.map_or((0, 0), |line| (line.line, line.column));
+        let scope = match line.and_then(|line| line.file.as_ref()) {
+            Some(path) => self.scope(path),
+            None => self.subprogram,
+        };
+        self.location =
+            LLVMDIBuilderCreateDebugLocation(self.ctx, line_number, column, scope, ptr::null_mut());
+        LLVMSetCurrentDebugLocation2(builder, self.location);
+    }
+
+    /// Returns the scope of the current function for lines in the file at `path`.
+    unsafe fn scope(&mut self, path: &str) -> LLVMMetadataRef {
+        if let Some(&scope) = self.scopes.get(path) {
+            return scope;
+        }
+        let file = self.file(path);
+        let scope = LLVMDIBuilderCreateLexicalBlockFile(self.builder, self.subprogram, file, 0);
+        self.scopes.insert(path.to_owned(), scope);
+        scope
+    }
+
+    /// Describes a scalar type of `bits` bits.
+    pub unsafe fn basic_type(
+        &mut self,
+        name: &str,
+        bits: u32,
+        encoding: Encoding,
+    ) -> LLVMMetadataRef {
+        let encoding = match encoding {
+            Encoding::Bool => DW_ATE_BOOLEAN,
+            Encoding::Signed => DW_ATE_SIGNED,
+            Encoding::Unsigned => DW_ATE_UNSIGNED,
+            Encoding::Float => DW_ATE_FLOAT,
+        };
+        LLVMDIBuilderCreateBasicType(
+            self.builder,
+            name.as_ptr() as *const _,
+            name.len(),
+            u64::from(bits),
+            encoding,
+            LLVMDIFlagZero,
+        )
+    }
+
+    /// Describes a vector or array of `count` elements that takes up `bits` bits.
+    pub unsafe fn array_type(
+        &mut self,
+        element: LLVMMetadataRef,
+        count: u32,
+        bits: u64,
+        vector: bool,
+    ) -> LLVMMetadataRef {
+        let mut subscripts = [LLVMDIBuilderGetOrCreateSubrange(self.builder, 0, i64::from(count))];
+        let subscripts = subscripts.as_mut_ptr();
+        if vector {
+            LLVMDIBuilderCreateVectorType(self.builder, bits, 0, element, subscripts, 1)
+        } else {
+            LLVMDIBuilderCreateArrayType(self.builder, bits, 0, element, subscripts, 1)
+        }
+    }
+
+    /// Declares the local variable `name` of type `ty`, which lives at `storage`. The
+    /// declaration goes to the end of `block`, so it has to be made right after `storage`.
+    pub unsafe fn declare_variable(
+        &mut self,
+        storage: LLVMValueRef,
+        name: &str,
+        ty: LLVMMetadataRef,
+        line: Option<&SourceLine>,
+        block: LLVMBasicBlockRef,
+    ) {
+        let scope = LLVMDILocationGetScope(self.location);
+        let file = LLVMDIScopeGetFile(scope);
+        let variable = LLVMDIBuilderCreateAutoVariable(
+            self.builder,
+            scope,
+            name.as_ptr() as *const _,
+            name.len(),
+            file,
+            line.map_or(0, |line| line.line),
+            ty,
+            1,
+            LLVMDIFlagZero,
+            0,
+        );
+        let expression = LLVMDIBuilderCreateExpression(self.builder, ptr::null_mut(), 0);
+        LLVMDIBuilderInsertDeclareAtEnd(
+            self.builder,
+            storage,
+            variable,
+            expression,
+            self.location,
+            block,
+        );
+    }
+
+    /// Resolves everything that was left open, which has to happen before the module is
+    /// verified.
+    pub unsafe fn finalize(&mut self) {
+        LLVMDIBuilderFinalize(self.builder);
+    }
+}
+
+impl Drop for DebugInfo {
+    fn drop(&mut self) {
+        unsafe { LLVMDisposeDIBuilder(self.builder) };
+    }
+}
+
+unsafe fn add_module_flag(ctx: LLVMContextRef, module: LLVMModuleRef, key: &str, value: u32) {
+    let value = LLVMConstInt(LLVMInt32TypeInContext(ctx), u64::from(value), 0);
+    LLVMAddModuleFlag(
+        module,
+        LLVMModuleFlagBehavior::LLVMModuleFlagBehaviorWarning,
+        key.as_ptr() as *const _,
+        key.len(),
+        LLVMValueAsMetadata(value),
+    );
+}
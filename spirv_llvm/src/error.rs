@@ -0,0 +1,180 @@
+//! Errors of the transpiler and where in a module they occurred.
+use std::error::Error;
+use std::fmt;
+
+use spirv_headers::{AddressingModel, BuiltIn, Capability, Decoration, ExecutionMode,
+                    ExecutionModel, MemoryModel, Op, StorageClass, Word};
+
+#[derive(Debug)]
+pub enum TranspilerError {
+    NoHeader,
+    InvalidMagicNumber,
+    /// The module is for a SPIR-V version newer than we know, given as major and minor.
+    UnsupportedVersion(u8, u8),
+    NoMemoryModelProvided,
+    UnsupportedAddressingModel(AddressingModel),
+    UnsupportedMemoryModel(MemoryModel),
+    /// The module declares a capability we cannot translate code for.
+    UnsupportedCapability(Capability),
+    /// The module uses an OpExtension we do not know.
+    UnsupportedExtension(String),
+    /// An entry point is for a shader stage we cannot run.
+    UnsupportedExecutionModel(ExecutionModel),
+    UnsupportedExecutionMode(ExecutionMode),
+    /// An instruction is missing operands or has operands of the wrong kind.
+    InvalidInstruction(Op),
+    /// An id is used without being defined before.
+    UndefinedId(Word),
+    UnsupportedInstruction(Op),
+    UnsupportedType(Op),
+    UnsupportedStorageClass(StorageClass),
+    UnsupportedDecoration(Decoration),
+    /// A variable is decorated with a built-in the shader ABI has no place for.
+    UnsupportedBuiltIn(BuiltIn),
+    /// The module imports an extended instruction set we do not know.
+    UnsupportedExtInstSet(String),
+    /// An extended instruction set has no instruction with the given number.
+    UnsupportedExtInst(String, u32),
+    /// The decorations that place the given type or variable in memory (Offset, ArrayStride,
+    /// MatrixStride) or in the shader interface (Location, DescriptorSet, Binding) are missing
+    /// or invalid.
+    InvalidLayout(Word),
+    /// The value given for the specialization constant with the given SpecId does not have the
+    /// size of its type.
+    InvalidSpecialization(u32),
+    /// Invocations can only run 1, 4, 8 or 16 at a time.
+    UnsupportedLanes(u32),
+    /// The control flow of a function has no structure that lanes can follow in lockstep.
+    IrreducibleControlFlow,
+    /// The generated LLVM module failed verification, which is a bug in the transpiler. Holds
+    /// the report of the LLVM verifier.
+    VerificationFailed(String),
+    /// LLVM or Cranelift could not compile the module to native code.
+    CodegenFailed(String),
+    /// The error occurred while translating the instruction at the given location.
+    At(Location, Box<TranspilerError>),
+}
+
+impl TranspilerError {
+    /// Attaches the instruction the error occurred at. Errors that already know their location
+    /// keep it, as it is the more precise one.
+    pub fn at(self, location: Location) -> Self {
+        match self {
+            TranspilerError::At(..) => self,
+            error => TranspilerError::At(location, Box::new(error)),
+        }
+    }
+
+    pub fn location(&self) -> Option<&Location> {
+        match *self {
+            TranspilerError::At(ref location, _) => Some(location),
+            _ => None,
+        }
+    }
+
+    /// The error itself, without its location.
+    pub fn kind(&self) -> &TranspilerError {
+        match *self {
+            TranspilerError::At(_, ref error) => error,
+            _ => self,
+        }
+    }
+}
+
+impl fmt::Display for TranspilerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use TranspilerError::*;
+        match *self {
+            NoHeader => write!(f, "module has no header"),
+            InvalidMagicNumber => write!(f, "module does not start with the SPIR-V magic number"),
+            UnsupportedVersion(major, minor) => {
+                write!(f, "unsupported SPIR-V version {}.{}", major, minor)
+            }
+            NoMemoryModelProvided => write!(f, "module has no OpMemoryModel"),
+            UnsupportedAddressingModel(model) => {
+                write!(f, "unsupported addressing model {:?}", model)
+            }
+            UnsupportedMemoryModel(model) => write!(f, "unsupported memory model {:?}", model),
+            UnsupportedCapability(capability) => {
+                write!(f, "unsupported capability {:?}", capability)
+            }
+            UnsupportedExtension(ref name) => write!(f, "unsupported extension {}", name),
+            UnsupportedExecutionModel(model) => {
+                write!(f, "unsupported execution model {:?}", model)
+            }
+            UnsupportedExecutionMode(mode) => write!(f, "unsupported execution mode {:?}", mode),
+            InvalidInstruction(opcode) => write!(f, "malformed Op{:?}", opcode),
+            UndefinedId(id) => write!(f, "%{} is used but not defined", id),
+            UnsupportedInstruction(opcode) => write!(f, "unsupported instruction Op{:?}", opcode),
+            UnsupportedType(opcode) => write!(f, "unsupported type Op{:?}", opcode),
+            UnsupportedStorageClass(class) => write!(f, "unsupported storage class {:?}", class),
+            UnsupportedDecoration(decoration) => {
+                write!(f, "unsupported decoration {:?}", decoration)
+            }
+            UnsupportedBuiltIn(builtin) => write!(f, "unsupported built-in {:?}", builtin),
+            UnsupportedExtInstSet(ref name) => {
+                write!(f, "unsupported extended instruction set {}", name)
+            }
+            UnsupportedExtInst(ref set, instruction) => {
+                write!(f, "{} has no instruction {}", set, instruction)
+            }
+            InvalidLayout(id) => write!(f, "missing or invalid layout decorations on %{}", id),
+            InvalidSpecialization(spec_id) => {
+                write!(f, "value of specialization constant {} has the wrong size", spec_id)
+            }
+            UnsupportedLanes(lanes) => write!(f, "cannot run {} invocations at once", lanes),
+            IrreducibleControlFlow => write!(f, "irreducible control flow"),
+            VerificationFailed(ref report) => write!(f, "generated invalid LLVM IR: {}", report),
+            CodegenFailed(ref message) => write!(f, "code generation failed: {}", message),
+            At(ref location, ref error) => write!(f, "{} at {}", error, location),
+        }
+    }
+}
+
+impl Error for TranspilerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            TranspilerError::At(_, ref error) => Some(&**error),
+            _ => None,
+        }
+    }
+}
+
+/// The instruction a `TranspilerError` occurred at.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Location {
+    pub opcode: Op,
+    pub result_id: Option<Word>,
+    /// The position in the high level source that the last OpLine before the instruction
+    /// gave, if any.
+    pub line: Option<SourceLine>,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Op{:?}", self.opcode)?;
+        if let Some(id) = self.result_id {
+            write!(f, " %{}", id)?;
+        }
+        if let Some(ref line) = self.line {
+            write!(f, " ({})", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// A position in the source a module was compiled from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceLine {
+    /// The OpString naming the file, if it is defined.
+    pub file: Option<String>,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl fmt::Display for SourceLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let file = self.file.as_deref().unwrap_or("<unknown>");
+        write!(f, "{}:{}:{}", file, self.line, self.column)
+    }
+}
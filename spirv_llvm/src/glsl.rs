@@ -0,0 +1,630 @@
+//! Translation of the GLSL.std.450 extended instruction set.
+//!
+//! Most instructions map to LLVM intrinsics or a handful of plain instructions. Functions without
+//! a precise intrinsic call into `runtime`, component by component.
+use llvm_sys::core::*;
+use llvm_sys::prelude::*;
+use llvm_sys::{LLVMIntPredicate, LLVMRealPredicate, LLVMTypeKind};
+use spirv_headers::Op;
+
+use glsl_op::*;
+use ir::*;
+use TranspilerError;
+
+pub use glsl_op::NAME;
+
+/// Translates GLSL.std.450 instruction `instruction` with result type `ty`, for values holding
+/// `lanes` invocations.
+///
+/// Pointer arguments (the outputs of Modf and Frexp, the interpolants) must point to memory in
+/// the natural layout of their pointee. They are only supported with a single lane, see
+/// `struct_variant` and `is_interpolation` for what to do instead.
+pub unsafe fn trans_glsl_inst(
+    builder: LLVMBuilderRef,
+    module: LLVMModuleRef,
+    instruction: u32,
+    ty: LLVMTypeRef,
+    args: &[LLVMValueRef],
+    lanes: u32,
+) -> Result<LLVMValueRef, TranspilerError> {
+    let arg = |index: usize| {
+        args.get(index).cloned().ok_or(
+            TranspilerError::InvalidInstruction(Op::ExtInst),
+        )
+    };
+    let b = builder;
+    let intrinsic = |name: &str, args: &[LLVMValueRef]| call_intrinsic(b, module, name, args);
+    let helper = |name: &str, args: &[LLVMValueRef]| call_helper(b, module, name, args, ty);
+
+    let value = match instruction {
+        ROUND => intrinsic("llvm.round", &[arg(0)?]),
+        ROUND_EVEN => intrinsic("llvm.nearbyint", &[arg(0)?]),
+        TRUNC => intrinsic("llvm.trunc", &[arg(0)?]),
+        F_ABS => intrinsic("llvm.fabs", &[arg(0)?]),
+        S_ABS => {
+            let x = arg(0)?;
+            let negative = int_compare(b, LLVMIntPredicate::LLVMIntSLT, x, LLVMConstNull(ty));
+            LLVMBuildSelect(b, negative, LLVMBuildNeg(b, x, NONAME), x, NONAME)
+        }
+        F_SIGN => {
+            let x = arg(0)?;
+            let zero = LLVMConstNull(ty);
+            let positive = real_compare(b, LLVMRealPredicate::LLVMRealOGT, x, zero);
+            let negative = real_compare(b, LLVMRealPredicate::LLVMRealOLT, x, zero);
+            let sign = LLVMBuildSelect(b, negative, real(ty, -1.0), zero, NONAME);
+            LLVMBuildSelect(b, positive, real(ty, 1.0), sign, NONAME)
+        }
+        S_SIGN => {
+            let x = arg(0)?;
+            let zero = LLVMConstNull(ty);
+            let positive = int_compare(b, LLVMIntPredicate::LLVMIntSGT, x, zero);
+            let negative = int_compare(b, LLVMIntPredicate::LLVMIntSLT, x, zero);
+            let sign = LLVMBuildSelect(b, negative, LLVMConstAllOnes(ty), zero, NONAME);
+            LLVMBuildSelect(b, positive, int(ty, 1), sign, NONAME)
+        }
+        FLOOR => intrinsic("llvm.floor", &[arg(0)?]),
+        CEIL => intrinsic("llvm.ceil", &[arg(0)?]),
+        FRACT => {
+            let x = arg(0)?;
+            LLVMBuildFSub(b, x, intrinsic("llvm.floor", &[x]), NONAME)
+        }
+        RADIANS => LLVMBuildFMul(b, arg(0)?, real(ty, ::std::f64::consts::PI / 180.0), NONAME),
+        DEGREES => LLVMBuildFMul(b, arg(0)?, real(ty, 180.0 / ::std::f64::consts::PI), NONAME),
+        SIN => intrinsic("llvm.sin", &[arg(0)?]),
+        COS => intrinsic("llvm.cos", &[arg(0)?]),
+        TAN => helper("tan", &[arg(0)?]),
+        ASIN => helper("asin", &[arg(0)?]),
+        ACOS => helper("acos", &[arg(0)?]),
+        ATAN => helper("atan", &[arg(0)?]),
+        SINH => helper("sinh", &[arg(0)?]),
+        COSH => helper("cosh", &[arg(0)?]),
+        TANH => helper("tanh", &[arg(0)?]),
+        ASINH => helper("asinh", &[arg(0)?]),
+        ACOSH => helper("acosh", &[arg(0)?]),
+        ATANH => helper("atanh", &[arg(0)?]),
+        ATAN2 => helper("atan2", &[arg(0)?, arg(1)?]),
+        POW => intrinsic("llvm.pow", &[arg(0)?, arg(1)?]),
+        EXP => intrinsic("llvm.exp", &[arg(0)?]),
+        LOG => intrinsic("llvm.log", &[arg(0)?]),
+        EXP2 => intrinsic("llvm.exp2", &[arg(0)?]),
+        LOG2 => intrinsic("llvm.log2", &[arg(0)?]),
+        SQRT => intrinsic("llvm.sqrt", &[arg(0)?]),
+        INVERSE_SQRT => LLVMBuildFDiv(b, real(ty, 1.0), intrinsic("llvm.sqrt", &[arg(0)?]), NONAME),
+        DETERMINANT => determinant(b, &matrix_components(b, arg(0)?, lanes)),
+        MATRIX_INVERSE => matrix_inverse(b, arg(0)?, lanes),
+        MODF => {
+            let x = arg(0)?;
+            let whole = intrinsic("llvm.trunc", &[x]);
+            store(b, arg(1)?, whole);
+            LLVMBuildFSub(b, x, whole, NONAME)
+        }
+        MODF_STRUCT => {
+            let x = arg(0)?;
+            let whole = intrinsic("llvm.trunc", &[x]);
+            let fract = LLVMBuildFSub(b, x, whole, NONAME);
+            build_aggregate(b, ty, &[fract, whole])
+        }
+        F_MIN | N_MIN => intrinsic("llvm.minnum", &[arg(0)?, arg(1)?]),
+        F_MAX | N_MAX => intrinsic("llvm.maxnum", &[arg(0)?, arg(1)?]),
+        U_MIN => int_select(b, LLVMIntPredicate::LLVMIntULT, arg(0)?, arg(1)?),
+        S_MIN => int_select(b, LLVMIntPredicate::LLVMIntSLT, arg(0)?, arg(1)?),
+        U_MAX => int_select(b, LLVMIntPredicate::LLVMIntUGT, arg(0)?, arg(1)?),
+        S_MAX => int_select(b, LLVMIntPredicate::LLVMIntSGT, arg(0)?, arg(1)?),
+        F_CLAMP | N_CLAMP => {
+            let lower = intrinsic("llvm.maxnum", &[arg(0)?, arg(1)?]);
+            intrinsic("llvm.minnum", &[lower, arg(2)?])
+        }
+        U_CLAMP => {
+            let lower = int_select(b, LLVMIntPredicate::LLVMIntUGT, arg(0)?, arg(1)?);
+            int_select(b, LLVMIntPredicate::LLVMIntULT, lower, arg(2)?)
+        }
+        S_CLAMP => {
+            let lower = int_select(b, LLVMIntPredicate::LLVMIntSGT, arg(0)?, arg(1)?);
+            int_select(b, LLVMIntPredicate::LLVMIntSLT, lower, arg(2)?)
+        }
+        F_MIX => {
+            // x * (1 - a) + y * a, as the spec defines it
+            let (x, y, a) = (arg(0)?, arg(1)?, arg(2)?);
+            let inverse = LLVMBuildFSub(b, real(ty, 1.0), a, NONAME);
+            let x = LLVMBuildFMul(b, x, inverse, NONAME);
+            let y = LLVMBuildFMul(b, y, a, NONAME);
+            LLVMBuildFAdd(b, x, y, NONAME)
+        }
+        STEP => {
+            let below = real_compare(b, LLVMRealPredicate::LLVMRealOLT, arg(1)?, arg(0)?);
+            LLVMBuildSelect(b, below, LLVMConstNull(ty), real(ty, 1.0), NONAME)
+        }
+        SMOOTH_STEP => {
+            let (edge0, edge1, x) = (arg(0)?, arg(1)?, arg(2)?);
+            let t = LLVMBuildFDiv(
+                b,
+                LLVMBuildFSub(b, x, edge0, NONAME),
+                LLVMBuildFSub(b, edge1, edge0, NONAME),
+                NONAME,
+            );
+            let t = intrinsic("llvm.maxnum", &[t, LLVMConstNull(ty)]);
+            let t = intrinsic("llvm.minnum", &[t, real(ty, 1.0)]);
+            let factor = LLVMBuildFSub(
+                b,
+                real(ty, 3.0),
+                LLVMBuildFMul(b, real(ty, 2.0), t, NONAME),
+                NONAME,
+            );
+            LLVMBuildFMul(b, LLVMBuildFMul(b, t, t, NONAME), factor, NONAME)
+        }
+        FMA => intrinsic("llvm.fma", &[arg(0)?, arg(1)?, arg(2)?]),
+        FREXP => {
+            let x = arg(0)?;
+            let exponent_ty = pointee_register_type(LLVMTypeOf(arg(1)?));
+            let exponent = call_helper(b, module, "frexp_exponent", &[x], exponent_ty);
+            store(b, arg(1)?, exponent);
+            helper("frexp_mantissa", &[x])
+        }
+        FREXP_STRUCT => {
+            let x = arg(0)?;
+            let mantissa_ty = LLVMTypeOf(x);
+            let exponent_ty = LLVMStructGetTypeAtIndex(ty, 1);
+            let mantissa = call_helper(b, module, "frexp_mantissa", &[x], mantissa_ty);
+            let exponent = call_helper(b, module, "frexp_exponent", &[x], exponent_ty);
+            build_aggregate(b, ty, &[mantissa, exponent])
+        }
+        LDEXP => {
+            let exponent = arg(1)?;
+            let i32_ty = LLVMInt32TypeInContext(LLVMGetTypeContext(ty));
+            let i32_ty = if is_vector(ty) {
+                LLVMVectorType(i32_ty, LLVMGetVectorSize(ty))
+            } else {
+                i32_ty
+            };
+            let exponent = int_cast(b, exponent, i32_ty, true);
+            helper("ldexp", &[arg(0)?, exponent])
+        }
+        PACK_SNORM_4X8 => pack_norm(b, module, arg(0)?, true, 8, ty, lanes),
+        PACK_UNORM_4X8 => pack_norm(b, module, arg(0)?, false, 8, ty, lanes),
+        PACK_SNORM_2X16 => pack_norm(b, module, arg(0)?, true, 16, ty, lanes),
+        PACK_UNORM_2X16 => pack_norm(b, module, arg(0)?, false, 16, ty, lanes),
+        PACK_HALF_2X16 => {
+            let x = arg(0)?;
+            let half = LLVMHalfTypeInContext(LLVMGetTypeContext(ty));
+            let half = LLVMVectorType(half, LLVMGetVectorSize(LLVMTypeOf(x)));
+            let half = interleave(b, LLVMBuildFPTrunc(b, x, half, NONAME), lanes);
+            LLVMBuildBitCast(b, half, ty, NONAME)
+        }
+        UNPACK_SNORM_2X16 => unpack_norm(b, module, arg(0)?, true, 16, ty, lanes),
+        UNPACK_UNORM_2X16 => unpack_norm(b, module, arg(0)?, false, 16, ty, lanes),
+        UNPACK_SNORM_4X8 => unpack_norm(b, module, arg(0)?, true, 8, ty, lanes),
+        UNPACK_UNORM_4X8 => unpack_norm(b, module, arg(0)?, false, 8, ty, lanes),
+        UNPACK_HALF_2X16 => {
+            let half = LLVMHalfTypeInContext(LLVMGetTypeContext(ty));
+            let half = LLVMVectorType(half, LLVMGetVectorSize(ty));
+            let half = deinterleave(b, LLVMBuildBitCast(b, arg(0)?, half, NONAME), lanes);
+            LLVMBuildFPExt(b, half, ty, NONAME)
+        }
+        // Both only reinterpret bits (little endian, like every host we run on)
+        PACK_DOUBLE_2X32 => LLVMBuildBitCast(b, interleave(b, arg(0)?, lanes), ty, NONAME),
+        UNPACK_DOUBLE_2X32 => deinterleave(b, LLVMBuildBitCast(b, arg(0)?, ty, NONAME), lanes),
+        LENGTH => length(b, module, arg(0)?, lanes),
+        DISTANCE => {
+            let difference = LLVMBuildFSub(b, arg(0)?, arg(1)?, NONAME);
+            length(b, module, difference, lanes)
+        }
+        CROSS => {
+            let (x, y) = (arg(0)?, arg(1)?);
+            let x_yzx = swizzle(b, x, &[1, 2, 0], lanes);
+            let x_zxy = swizzle(b, x, &[2, 0, 1], lanes);
+            let y_yzx = swizzle(b, y, &[1, 2, 0], lanes);
+            let y_zxy = swizzle(b, y, &[2, 0, 1], lanes);
+            LLVMBuildFSub(
+                b,
+                LLVMBuildFMul(b, x_yzx, y_zxy, NONAME),
+                LLVMBuildFMul(b, x_zxy, y_yzx, NONAME),
+                NONAME,
+            )
+        }
+        NORMALIZE => {
+            let x = arg(0)?;
+            let length = splat(b, length(b, module, x, lanes), ty, lanes);
+            LLVMBuildFDiv(b, x, length, NONAME)
+        }
+        FACE_FORWARD => {
+            let (n, i, n_ref) = (arg(0)?, arg(1)?, arg(2)?);
+            let d = dot(b, n_ref, i, lanes);
+            let zero = LLVMConstNull(LLVMTypeOf(d));
+            let facing = real_compare(b, LLVMRealPredicate::LLVMRealOLT, d, zero);
+            select(b, facing, n, LLVMBuildFNeg(b, n, NONAME), lanes)
+        }
+        REFLECT => {
+            // i - 2 * dot(n, i) * n
+            let (i, n) = (arg(0)?, arg(1)?);
+            let d = dot(b, n, i, lanes);
+            let scale = LLVMBuildFMul(b, real(LLVMTypeOf(d), 2.0), d, NONAME);
+            let scaled = LLVMBuildFMul(b, splat(b, scale, ty, lanes), n, NONAME);
+            LLVMBuildFSub(b, i, scaled, NONAME)
+        }
+        REFRACT => refract(b, module, arg(0)?, arg(1)?, arg(2)?, lanes),
+        FIND_I_LSB => {
+            let x = arg(0)?;
+            let zero = LLVMConstNull(ty);
+            let trailing = intrinsic("llvm.cttz", &[x, const_bool(ty, false)]);
+            let empty = int_compare(b, LLVMIntPredicate::LLVMIntEQ, x, zero);
+            LLVMBuildSelect(b, empty, LLVMConstAllOnes(ty), trailing, NONAME)
+        }
+        FIND_U_MSB => find_msb(b, module, arg(0)?),
+        FIND_S_MSB => {
+            // Negative numbers look for the highest 0 bit, so flip them
+            let x = arg(0)?;
+            let width = LLVMGetIntTypeWidth(scalar_type(ty)) as u64;
+            let sign = LLVMBuildAShr(b, x, int(ty, width - 1), NONAME);
+            find_msb(b, module, LLVMBuildXor(b, x, sign, NONAME))
+        }
+        // We only ever rasterize with one sample at the pixel center, which is where the
+        // interpolants already are.
+        // TODO InterpolateAtOffset needs derivatives to move away from the center
+        INTERPOLATE_AT_CENTROID | INTERPOLATE_AT_SAMPLE | INTERPOLATE_AT_OFFSET => {
+            let pointer = LLVMBuildBitCast(b, arg(0)?, LLVMPointerType(ty, 0), NONAME);
+            LLVMBuildLoad(b, pointer, NONAME)
+        }
+        _ => {
+            return Err(TranspilerError::UnsupportedExtInst(
+                NAME.to_owned(),
+                instruction,
+            ))
+        }
+    };
+    Ok(value)
+}
+
+/// Returns the variant of `instruction` that returns a struct instead of storing through a
+/// pointer, for Modf and Frexp.
+pub fn struct_variant(instruction: u32) -> Option<u32> {
+    match instruction {
+        MODF => Some(MODF_STRUCT),
+        FREXP => Some(FREXP_STRUCT),
+        _ => None,
+    }
+}
+
+/// Whether `instruction` is one of the InterpolateAt* instructions, which read their first
+/// argument through a pointer.
+pub fn is_interpolation(instruction: u32) -> bool {
+    match instruction {
+        INTERPOLATE_AT_CENTROID | INTERPOLATE_AT_SAMPLE | INTERPOLATE_AT_OFFSET => true,
+        _ => false,
+    }
+}
+
+/// Returns the float constant `value` of type `ty` (broadcast for vectors).
+unsafe fn real(ty: LLVMTypeRef, value: f64) -> LLVMValueRef {
+    const_splat(LLVMConstReal(scalar_type(ty), value), ty)
+}
+
+/// Returns the integer constant `value` of type `ty` (broadcast for vectors).
+unsafe fn int(ty: LLVMTypeRef, value: u64) -> LLVMValueRef {
+    const_splat(LLVMConstInt(scalar_type(ty), value, 0), ty)
+}
+
+unsafe fn const_bool(ty: LLVMTypeRef, value: bool) -> LLVMValueRef {
+    LLVMConstInt(LLVMInt1TypeInContext(LLVMGetTypeContext(ty)), value as u64, 0)
+}
+
+unsafe fn int_compare(
+    builder: LLVMBuilderRef,
+    predicate: LLVMIntPredicate,
+    a: LLVMValueRef,
+    b: LLVMValueRef,
+) -> LLVMValueRef {
+    LLVMBuildICmp(builder, predicate, a, b, NONAME)
+}
+
+unsafe fn real_compare(
+    builder: LLVMBuilderRef,
+    predicate: LLVMRealPredicate,
+    a: LLVMValueRef,
+    b: LLVMValueRef,
+) -> LLVMValueRef {
+    LLVMBuildFCmp(builder, predicate, a, b, NONAME)
+}
+
+/// Selects `a` where `a <predicate> b` holds and `b` elsewhere, i.e. min or max.
+unsafe fn int_select(
+    builder: LLVMBuilderRef,
+    predicate: LLVMIntPredicate,
+    a: LLVMValueRef,
+    b: LLVMValueRef,
+) -> LLVMValueRef {
+    let condition = int_compare(builder, predicate, a, b);
+    LLVMBuildSelect(builder, condition, a, b, NONAME)
+}
+
+/// Stores `value` through the pointer argument `pointer`.
+unsafe fn store(builder: LLVMBuilderRef, pointer: LLVMValueRef, value: LLVMValueRef) {
+    let pointer = LLVMBuildBitCast(
+        builder,
+        pointer,
+        LLVMPointerType(LLVMTypeOf(value), 0),
+        NONAME,
+    );
+    LLVMBuildStore(builder, value, pointer);
+}
+
+/// Returns the register type of what `pointer` points to.
+unsafe fn pointee_register_type(pointer: LLVMTypeRef) -> LLVMTypeRef {
+    let pointee = LLVMGetElementType(pointer);
+    if LLVMGetTypeKind(pointee) == LLVMTypeKind::LLVMArrayTypeKind {
+        // A vector in explicitly laid out memory
+        LLVMVectorType(LLVMGetElementType(pointee), LLVMGetArrayLength(pointee))
+    } else {
+        pointee
+    }
+}
+
+/// Calls runtime helper `name` for every component of `args`, returning a value of type `ty`.
+///
+/// The helper is picked by the float type of the first argument. Half precision goes through
+/// the single precision helper.
+unsafe fn call_helper(
+    builder: LLVMBuilderRef,
+    module: LLVMModuleRef,
+    name: &str,
+    args: &[LLVMValueRef],
+    ty: LLVMTypeRef,
+) -> LLVMValueRef {
+    let ctx = LLVMGetModuleContext(module);
+    let float = LLVMFloatTypeInContext(ctx);
+    let arg_scalar = scalar_type(LLVMTypeOf(args[0]));
+    let is_half = LLVMGetTypeKind(arg_scalar) == LLVMTypeKind::LLVMHalfTypeKind;
+    let ret_scalar = scalar_type(ty);
+    let ret_is_half = LLVMGetTypeKind(ret_scalar) == LLVMTypeKind::LLVMHalfTypeKind;
+
+    let params = args.iter()
+        .map(|&arg| {
+            let scalar = scalar_type(LLVMTypeOf(arg));
+            if LLVMGetTypeKind(scalar) == LLVMTypeKind::LLVMHalfTypeKind {
+                float
+            } else {
+                scalar
+            }
+        })
+        .collect::<Vec<_>>();
+    let ret = if ret_is_half { float } else { ret_scalar };
+    let suffix = if is_half {
+        "f32".to_owned()
+    } else {
+        type_suffix(arg_scalar)
+    };
+    let function = declare(module, &format!("__spirv_{}_{}", name, suffix), ret, &params);
+
+    // Every element is a separate call, no matter which lane it belongs to
+    let arg_components = args.iter()
+        .map(|&arg| components(builder, arg, 1))
+        .collect::<Vec<_>>();
+    let results = (0..arg_components[0].len())
+        .map(|index| {
+            let mut call_args = arg_components
+                .iter()
+                .map(|components| {
+                    let component = components[index];
+                    if LLVMTypeOf(component) == LLVMHalfTypeInContext(ctx) {
+                        LLVMBuildFPExt(builder, component, float, NONAME)
+                    } else {
+                        component
+                    }
+                })
+                .collect::<Vec<_>>();
+            let result = LLVMBuildCall(
+                builder,
+                function,
+                call_args.as_mut_ptr(),
+                call_args.len() as u32,
+                NONAME,
+            );
+            if ret_is_half {
+                LLVMBuildFPTrunc(builder, result, ret_scalar, NONAME)
+            } else {
+                result
+            }
+        })
+        .collect::<Vec<_>>();
+    from_components(builder, ty, &results, 1)
+}
+
+unsafe fn length(
+    builder: LLVMBuilderRef,
+    module: LLVMModuleRef,
+    x: LLVMValueRef,
+    lanes: u32,
+) -> LLVMValueRef {
+    let ty = LLVMTypeOf(x);
+    if is_vector(ty) && LLVMGetVectorSize(ty) != lanes {
+        call_intrinsic(builder, module, "llvm.sqrt", &[dot(builder, x, x, lanes)])
+    } else {
+        call_intrinsic(builder, module, "llvm.fabs", &[x])
+    }
+}
+
+/// Returns the highest set bit of `x`, or -1 if there is none.
+unsafe fn find_msb(builder: LLVMBuilderRef, module: LLVMModuleRef, x: LLVMValueRef) -> LLVMValueRef {
+    // With is_zero_undef unset, ctlz(0) is the width, making the result -1
+    let ty = LLVMTypeOf(x);
+    let leading = call_intrinsic(builder, module, "llvm.ctlz", &[x, const_bool(ty, false)]);
+    let width = LLVMGetIntTypeWidth(scalar_type(ty)) as u64;
+    LLVMBuildSub(builder, int(ty, width - 1), leading, NONAME)
+}
+
+unsafe fn swizzle(
+    builder: LLVMBuilderRef,
+    vector: LLVMValueRef,
+    indices: &[u32],
+    lanes: u32,
+) -> LLVMValueRef {
+    let mut mask = Vec::new();
+    for &index in indices {
+        mask.extend((0..lanes).map(|lane| index * lanes + lane));
+    }
+    shuffle(builder, vector, &mask)
+}
+
+unsafe fn refract(
+    builder: LLVMBuilderRef,
+    module: LLVMModuleRef,
+    i: LLVMValueRef,
+    n: LLVMValueRef,
+    eta: LLVMValueRef,
+    lanes: u32,
+) -> LLVMValueRef {
+    // k = 1 - eta * eta * (1 - dot(n, i) * dot(n, i))
+    // k < 0 ? 0 : eta * i - (eta * dot(n, i) + sqrt(k)) * n
+    let b = builder;
+    let ty = LLVMTypeOf(i);
+    let d = dot(b, n, i, lanes);
+    let scalar = LLVMTypeOf(d);
+    let eta = LLVMBuildFPCast(b, eta, scalar, NONAME);
+    let one = real(scalar, 1.0);
+    let k = LLVMBuildFSub(b, one, LLVMBuildFMul(b, d, d, NONAME), NONAME);
+    let k = LLVMBuildFMul(b, LLVMBuildFMul(b, eta, eta, NONAME), k, NONAME);
+    let k = LLVMBuildFSub(b, one, k, NONAME);
+    let root = call_intrinsic(b, module, "llvm.sqrt", &[k]);
+    let factor = LLVMBuildFAdd(b, LLVMBuildFMul(b, eta, d, NONAME), root, NONAME);
+    let refracted = LLVMBuildFSub(
+        b,
+        LLVMBuildFMul(b, splat(b, eta, ty, lanes), i, NONAME),
+        LLVMBuildFMul(b, splat(b, factor, ty, lanes), n, NONAME),
+        NONAME,
+    );
+    let total_reflection = real_compare(b, LLVMRealPredicate::LLVMRealOLT, k, LLVMConstNull(scalar));
+    select(b, total_reflection, LLVMConstNull(ty), refracted, lanes)
+}
+
+/// Converts floats to normalized integers and packs them into one integer of type `ty`.
+unsafe fn pack_norm(
+    builder: LLVMBuilderRef,
+    module: LLVMModuleRef,
+    value: LLVMValueRef,
+    signed: bool,
+    bits: u32,
+    ty: LLVMTypeRef,
+    lanes: u32,
+) -> LLVMValueRef {
+    let value_ty = LLVMTypeOf(value);
+    let (min, scale) = if signed {
+        (-1.0, ((1u64 << (bits - 1)) - 1) as f64)
+    } else {
+        (0.0, ((1u64 << bits) - 1) as f64)
+    };
+    let clamped = call_intrinsic(builder, module, "llvm.maxnum", &[value, real(value_ty, min)]);
+    let clamped = call_intrinsic(builder, module, "llvm.minnum", &[clamped, real(value_ty, 1.0)]);
+    let scaled = LLVMBuildFMul(builder, clamped, real(value_ty, scale), NONAME);
+    let rounded = call_intrinsic(builder, module, "llvm.round", &[scaled]);
+    let int_ty = LLVMVectorType(
+        LLVMIntTypeInContext(LLVMGetTypeContext(ty), bits),
+        LLVMGetVectorSize(value_ty),
+    );
+    let ints = if signed {
+        LLVMBuildFPToSI(builder, rounded, int_ty, NONAME)
+    } else {
+        LLVMBuildFPToUI(builder, rounded, int_ty, NONAME)
+    };
+    LLVMBuildBitCast(builder, interleave(builder, ints, lanes), ty, NONAME)
+}
+
+/// Unpacks normalized integers from `value` into floats of type `ty`.
+unsafe fn unpack_norm(
+    builder: LLVMBuilderRef,
+    module: LLVMModuleRef,
+    value: LLVMValueRef,
+    signed: bool,
+    bits: u32,
+    ty: LLVMTypeRef,
+    lanes: u32,
+) -> LLVMValueRef {
+    let int_ty = LLVMVectorType(
+        LLVMIntTypeInContext(LLVMGetTypeContext(ty), bits),
+        LLVMGetVectorSize(ty),
+    );
+    let ints = deinterleave(builder, LLVMBuildBitCast(builder, value, int_ty, NONAME), lanes);
+    if signed {
+        let floats = LLVMBuildSIToFP(builder, ints, ty, NONAME);
+        let scale = ((1u64 << (bits - 1)) - 1) as f64;
+        let scaled = LLVMBuildFDiv(builder, floats, real(ty, scale), NONAME);
+        // The most negative integer would end up below -1
+        call_intrinsic(builder, module, "llvm.maxnum", &[scaled, real(ty, -1.0)])
+    } else {
+        let floats = LLVMBuildUIToFP(builder, ints, ty, NONAME);
+        let scale = ((1u64 << bits) - 1) as f64;
+        LLVMBuildFDiv(builder, floats, real(ty, scale), NONAME)
+    }
+}
+
+/// Returns the scalar components of a matrix, indexed by column and row.
+unsafe fn matrix_components(
+    builder: LLVMBuilderRef,
+    matrix: LLVMValueRef,
+    lanes: u32,
+) -> Vec<Vec<LLVMValueRef>> {
+    columns(builder, matrix)
+        .into_iter()
+        .map(|column| components(builder, column, lanes))
+        .collect()
+}
+
+/// Returns the matrix `m` without column `column` and row `row`.
+fn minor(m: &[Vec<LLVMValueRef>], column: usize, row: usize) -> Vec<Vec<LLVMValueRef>> {
+    m.iter()
+        .enumerate()
+        .filter(|&(index, _)| index != column)
+        .map(|(_, components)| {
+            components
+                .iter()
+                .enumerate()
+                .filter(|&(index, _)| index != row)
+                .map(|(_, &component)| component)
+                .collect()
+        })
+        .collect()
+}
+
+/// Computes the determinant by Laplace expansion, which is cheap enough for 4x4 matrices.
+unsafe fn determinant(builder: LLVMBuilderRef, m: &[Vec<LLVMValueRef>]) -> LLVMValueRef {
+    if m.len() == 1 {
+        return m[0][0];
+    }
+    let mut result = None;
+    for row in 0..m.len() {
+        let cofactor = determinant(builder, &minor(m, 0, row));
+        let term = LLVMBuildFMul(builder, m[0][row], cofactor, NONAME);
+        result = Some(match result {
+            None => term,
+            Some(sum) if row % 2 == 0 => LLVMBuildFAdd(builder, sum, term, NONAME),
+            Some(sum) => LLVMBuildFSub(builder, sum, term, NONAME),
+        });
+    }
+    result.unwrap()
+}
+
+/// Inverts a matrix through its adjugate.
+unsafe fn matrix_inverse(builder: LLVMBuilderRef, matrix: LLVMValueRef, lanes: u32) -> LLVMValueRef {
+    let ty = LLVMTypeOf(matrix);
+    let column_ty = LLVMGetElementType(ty);
+    let m = matrix_components(builder, matrix, lanes);
+    let n = m.len();
+    let det = determinant(builder, &m);
+    let inverse_determinant = LLVMBuildFDiv(builder, real(LLVMTypeOf(det), 1.0), det, NONAME);
+    let columns = (0..n)
+        .map(|column| {
+            let components = (0..n)
+                .map(|row| {
+                    // inverse[column][row] = cofactor(row, column) / determinant
+                    let cofactor = determinant(builder, &minor(&m, row, column));
+                    let cofactor = if (row + column) % 2 == 1 {
+                        LLVMBuildFNeg(builder, cofactor, NONAME)
+                    } else {
+                        cofactor
+                    };
+                    LLVMBuildFMul(builder, cofactor, inverse_determinant, NONAME)
+                })
+                .collect::<Vec<_>>();
+            from_components(builder, column_ty, &components, lanes)
+        })
+        .collect::<Vec<_>>();
+    build_aggregate(builder, ty, &columns)
+}
@@ -0,0 +1,105 @@
+//! Numbers of the instructions of the GLSL.std.450 extended instruction set, shared by the
+//! backends.
+
+/// Name under which modules import the instruction set.
+pub const NAME: &str = "GLSL.std.450";
+
+pub const ROUND: u32 = 1;
+pub const ROUND_EVEN: u32 = 2;
+pub const TRUNC: u32 = 3;
+pub const F_ABS: u32 = 4;
+pub const S_ABS: u32 = 5;
+pub const F_SIGN: u32 = 6;
+pub const S_SIGN: u32 = 7;
+pub const FLOOR: u32 = 8;
+pub const CEIL: u32 = 9;
+pub const FRACT: u32 = 10;
+pub const RADIANS: u32 = 11;
+pub const DEGREES: u32 = 12;
+pub const SIN: u32 = 13;
+pub const COS: u32 = 14;
+pub const TAN: u32 = 15;
+pub const ASIN: u32 = 16;
+pub const ACOS: u32 = 17;
+pub const ATAN: u32 = 18;
+pub const SINH: u32 = 19;
+pub const COSH: u32 = 20;
+pub const TANH: u32 = 21;
+pub const ASINH: u32 = 22;
+pub const ACOSH: u32 = 23;
+pub const ATANH: u32 = 24;
+pub const ATAN2: u32 = 25;
+pub const POW: u32 = 26;
+pub const EXP: u32 = 27;
+pub const LOG: u32 = 28;
+pub const EXP2: u32 = 29;
+pub const LOG2: u32 = 30;
+pub const SQRT: u32 = 31;
+pub const INVERSE_SQRT: u32 = 32;
+pub const DETERMINANT: u32 = 33;
+pub const MATRIX_INVERSE: u32 = 34;
+pub const MODF: u32 = 35;
+pub const MODF_STRUCT: u32 = 36;
+pub const F_MIN: u32 = 37;
+pub const U_MIN: u32 = 38;
+pub const S_MIN: u32 = 39;
+pub const F_MAX: u32 = 40;
+pub const U_MAX: u32 = 41;
+pub const S_MAX: u32 = 42;
+pub const F_CLAMP: u32 = 43;
+pub const U_CLAMP: u32 = 44;
+pub const S_CLAMP: u32 = 45;
+pub const F_MIX: u32 = 46;
+/// IMix only existed in a draft of the instruction set, no compiler emits it.
+const I_MIX: u32 = 47;
+pub const STEP: u32 = 48;
+pub const SMOOTH_STEP: u32 = 49;
+pub const FMA: u32 = 50;
+pub const FREXP: u32 = 51;
+pub const FREXP_STRUCT: u32 = 52;
+pub const LDEXP: u32 = 53;
+pub const PACK_SNORM_4X8: u32 = 54;
+pub const PACK_UNORM_4X8: u32 = 55;
+pub const PACK_SNORM_2X16: u32 = 56;
+pub const PACK_UNORM_2X16: u32 = 57;
+pub const PACK_HALF_2X16: u32 = 58;
+pub const PACK_DOUBLE_2X32: u32 = 59;
+pub const UNPACK_SNORM_2X16: u32 = 60;
+pub const UNPACK_UNORM_2X16: u32 = 61;
+pub const UNPACK_HALF_2X16: u32 = 62;
+pub const UNPACK_SNORM_4X8: u32 = 63;
+pub const UNPACK_UNORM_4X8: u32 = 64;
+pub const UNPACK_DOUBLE_2X32: u32 = 65;
+pub const LENGTH: u32 = 66;
+pub const DISTANCE: u32 = 67;
+pub const CROSS: u32 = 68;
+pub const NORMALIZE: u32 = 69;
+pub const FACE_FORWARD: u32 = 70;
+pub const REFLECT: u32 = 71;
+pub const REFRACT: u32 = 72;
+pub const FIND_I_LSB: u32 = 73;
+pub const FIND_S_MSB: u32 = 74;
+pub const FIND_U_MSB: u32 = 75;
+pub const INTERPOLATE_AT_CENTROID: u32 = 76;
+pub const INTERPOLATE_AT_SAMPLE: u32 = 77;
+pub const INTERPOLATE_AT_OFFSET: u32 = 78;
+pub const N_MIN: u32 = 79;
+pub const N_MAX: u32 = 80;
+pub const N_CLAMP: u32 = 81;
+
+/// Whether the instruction set has an instruction with the number `instruction`.
+pub fn is_known(instruction: u32) -> bool {
+    (ROUND..=N_CLAMP).contains(&instruction) && instruction != I_MIX
+}
+
+/// Number of operands the known instruction `instruction` takes.
+pub fn operand_count(instruction: u32) -> usize {
+    match instruction {
+        ATAN2 | POW | MODF | F_MIN | U_MIN | S_MIN | F_MAX | U_MAX | S_MAX | STEP | FREXP |
+        LDEXP | DISTANCE | CROSS | REFLECT | INTERPOLATE_AT_SAMPLE | INTERPOLATE_AT_OFFSET |
+        N_MIN | N_MAX => 2,
+        F_CLAMP | U_CLAMP | S_CLAMP | F_MIX | I_MIX | SMOOTH_STEP | FMA | FACE_FORWARD |
+        REFRACT | N_CLAMP => 3,
+        _ => 1,
+    }
+}
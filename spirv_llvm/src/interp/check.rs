@@ -0,0 +1,462 @@
+//! Type checks for the instructions that `Types::evaluate` and `glsl::evaluate` compute, so that
+//! they only ever see the values they expect.
+use spirv_headers::{Op, Word};
+
+use glsl_op::*;
+
+use super::{Type, Types};
+
+/// What a scalar, or the components of a vector, hold.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    Bool,
+    Int,
+    Float,
+}
+
+impl Types {
+    /// Whether values of type `a` are values of type `b`, even if the type was declared twice.
+    pub fn same(&self, a: Word, b: Word) -> bool {
+        if a == b {
+            return true;
+        }
+        let (a, b) = match (self.get(a), self.get(b)) {
+            (Ok(a), Ok(b)) => (a, b),
+            _ => return false,
+        };
+        match (a, b) {
+            (&Type::Vector { component: a, count: m }, &Type::Vector { component: b, count: n }) |
+            (&Type::Matrix { column: a, count: m }, &Type::Matrix { column: b, count: n }) |
+            (&Type::Array { element: a, length: m }, &Type::Array { element: b, length: n }) => {
+                m == n && self.same(a, b)
+            }
+            (&Type::RuntimeArray { element: a }, &Type::RuntimeArray { element: b }) |
+            (&Type::Pointer { pointee: a }, &Type::Pointer { pointee: b }) => self.same(a, b),
+            (&Type::Struct { members: ref a }, &Type::Struct { members: ref b }) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(&a, &b)| self.same(a, b))
+            }
+            (a, b) => a == b,
+        }
+    }
+
+    /// Returns the kind, width and number of components of a scalar or vector type, 1 for
+    /// scalars. Booleans have no width.
+    fn shape(&self, ty: Word) -> Option<(Kind, u32, u32)> {
+        let (scalar, count) = match *self.get(ty).ok()? {
+            Type::Vector { component, count } => (component, count),
+            _ => (ty, 1),
+        };
+        match *self.get(scalar).ok()? {
+            Type::Bool => Some((Kind::Bool, 0, count)),
+            Type::Int { width } => Some((Kind::Int, width, count)),
+            Type::Float { width } => Some((Kind::Float, width, count)),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of components of `ty` if it is a scalar or vector of `kind`.
+    fn count(&self, ty: Word, kind: Kind) -> Option<u32> {
+        match self.shape(ty) {
+            Some((shape, _, count)) if shape == kind => Some(count),
+            _ => None,
+        }
+    }
+
+    /// Whether `ty` can be the column of a matrix, a vector of floats.
+    pub fn is_column(&self, ty: Word) -> bool {
+        self.count(ty, Kind::Float).map_or(false, |count| count > 1)
+    }
+
+    /// Whether `ty` is a single integer.
+    fn is_index(&self, ty: Word) -> bool {
+        self.count(ty, Kind::Int) == Some(1)
+    }
+
+    /// The component type of a vector, or `ty` itself for anything else.
+    fn scalar(&self, ty: Word) -> Word {
+        match self.get(ty) {
+            Ok(&Type::Vector { component, .. }) => component,
+            _ => ty,
+        }
+    }
+
+    /// Whether `ty` is a vector of `count` components of type `component`.
+    fn is_vector(&self, ty: Word, component: Word, count: u32) -> bool {
+        match self.get(ty) {
+            Ok(&Type::Vector { component: other, count: length }) => {
+                length == count && self.same(other, component)
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the column type and the number of rows and columns of a matrix.
+    fn matrix(&self, ty: Word) -> Option<(Word, u32, u32)> {
+        match *self.get(ty).ok()? {
+            Type::Matrix { column, count } => {
+                let rows = self.count(column, Kind::Float)?;
+                Some((column, rows, count))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn pointee(&self, ty: Word) -> Option<Word> {
+        match *self.get(ty).ok()? {
+            Type::Pointer { pointee } => Some(pointee),
+            _ => None,
+        }
+    }
+
+    /// Whether `ty` is a struct of two members of the types `a` and `b`, like the results of
+    /// IAddCarry or FrexpStruct.
+    fn is_pair(&self, ty: Word, a: Word, b: Word) -> bool {
+        match self.get(ty) {
+            Ok(&Type::Struct { ref members }) => {
+                members.len() == 2 && self.same(members[0], a) && self.same(members[1], b)
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the type of the member or element of `ty` that the indices in `path` select.
+    fn path_type(&self, mut ty: Word, path: &[u32]) -> Option<Word> {
+        for &index in path {
+            ty = match *self.get(ty).ok()? {
+                Type::Vector { component: element, count: length } |
+                Type::Matrix { column: element, count: length } |
+                Type::Array { element, length } if index < length => element,
+                Type::Struct { ref members } => *members.get(index as usize)?,
+                _ => return None,
+            };
+        }
+        Some(ty)
+    }
+
+    /// Whether the types of the operands `args` and the result type `ty` are the ones the pure
+    /// instruction `opcode` computes with. `literals` are its literal operands.
+    pub fn check_pure(&self, opcode: Op, ty: Word, args: &[Word], literals: &[u32]) -> bool {
+        // Operands and result are all of one type
+        let like_result = |count: usize, kind: Kind| {
+            args.len() == count && self.count(ty, kind).is_some() &&
+                args.iter().all(|&arg| self.same(arg, ty))
+        };
+        // A scalar or vector operand of `kind` with as many components as the result of `other`
+        let like_count = |arg: Word, kind: Kind, other: Kind| {
+            self.count(arg, kind).is_some() && self.count(arg, kind) == self.count(ty, other)
+        };
+        match opcode {
+            Op::IAdd | Op::ISub | Op::IMul | Op::UDiv | Op::SDiv | Op::UMod | Op::SRem |
+            Op::SMod | Op::BitwiseOr | Op::BitwiseXor | Op::BitwiseAnd => like_result(2, Kind::Int),
+            Op::SNegate | Op::Not | Op::BitReverse => like_result(1, Kind::Int),
+            Op::ShiftRightLogical | Op::ShiftRightArithmetic | Op::ShiftLeftLogical => {
+                // The shift may have a different width than the base
+                args.len() == 2 && self.count(ty, Kind::Int).is_some() &&
+                    self.same(args[0], ty) && like_count(args[1], Kind::Int, Kind::Int)
+            }
+            Op::BitCount => args.len() == 1 && like_count(args[0], Kind::Int, Kind::Int),
+            Op::BitFieldInsert => {
+                args.len() == 4 && self.count(ty, Kind::Int).is_some() &&
+                    self.same(args[0], ty) && self.same(args[1], ty) &&
+                    self.is_index(args[2]) && self.is_index(args[3])
+            }
+            Op::BitFieldSExtract | Op::BitFieldUExtract => {
+                args.len() == 3 && self.count(ty, Kind::Int).is_some() &&
+                    self.same(args[0], ty) && self.is_index(args[1]) &&
+                    self.is_index(args[2])
+            }
+            Op::IAddCarry | Op::ISubBorrow | Op::UMulExtended | Op::SMulExtended => {
+                args.len() == 2 && self.count(args[0], Kind::Int).is_some() &&
+                    self.same(args[0], args[1]) && self.is_pair(ty, args[0], args[0])
+            }
+
+            Op::FAdd | Op::FSub | Op::FMul | Op::FDiv | Op::FRem | Op::FMod => {
+                like_result(2, Kind::Float)
+            }
+            Op::FNegate | Op::QuantizeToF16 => like_result(1, Kind::Float),
+            Op::VectorTimesScalar => {
+                args.len() == 2 && self.count(ty, Kind::Float).map_or(false, |count| count > 1) &&
+                    self.same(args[0], ty) && self.same(args[1], self.scalar(ty))
+            }
+            Op::MatrixTimesScalar => {
+                args.len() == 2 && self.same(args[0], ty) &&
+                    self.matrix(ty).map_or(false, |(column, _, _)| {
+                        self.same(args[1], self.scalar(column))
+                    })
+            }
+            Op::VectorTimesMatrix => {
+                args.len() == 2 &&
+                    self.matrix(args[1]).map_or(false, |(column, _, columns)| {
+                        self.same(args[0], column) &&
+                            self.is_vector(ty, self.scalar(column), columns)
+                    })
+            }
+            Op::MatrixTimesVector => {
+                args.len() == 2 &&
+                    self.matrix(args[0]).map_or(false, |(column, _, columns)| {
+                        self.is_vector(args[1], self.scalar(column), columns) &&
+                            self.same(ty, column)
+                    })
+            }
+            Op::MatrixTimesMatrix => {
+                args.len() == 2 &&
+                    match (self.matrix(args[0]), self.matrix(args[1]), self.matrix(ty)) {
+                        (Some((a, _, inner)), Some((b, rows, columns)), Some((c, _, count))) => {
+                            rows == inner && columns == count &&
+                                self.same(self.scalar(a), self.scalar(b)) && self.same(a, c)
+                        }
+                        _ => false,
+                    }
+            }
+            Op::OuterProduct => {
+                args.len() == 2 &&
+                    self.matrix(ty).map_or(false, |(column, _, columns)| {
+                        self.same(args[0], column) &&
+                            self.is_vector(args[1], self.scalar(column), columns)
+                    })
+            }
+            Op::Dot => {
+                args.len() == 2 &&
+                    self.count(args[0], Kind::Float).map_or(false, |count| count > 1) &&
+                    self.same(args[0], args[1]) && self.same(ty, self.scalar(args[0]))
+            }
+
+            Op::LogicalOr | Op::LogicalAnd | Op::LogicalEqual | Op::LogicalNotEqual => {
+                like_result(2, Kind::Bool)
+            }
+            Op::LogicalNot => like_result(1, Kind::Bool),
+            Op::Select => {
+                // Vector conditions select component by component
+                args.len() == 3 && self.same(args[1], ty) && self.same(args[2], ty) &&
+                    match self.count(args[0], Kind::Bool) {
+                        Some(1) => true,
+                        Some(count) => self.shape(ty).map(|(_, _, length)| length) == Some(count),
+                        None => false,
+                    }
+            }
+            Op::Any | Op::All => {
+                args.len() == 1 &&
+                    self.count(args[0], Kind::Bool).map_or(false, |count| count > 1) &&
+                    self.count(ty, Kind::Bool) == Some(1)
+            }
+            Op::IsNan | Op::IsInf => args.len() == 1 && like_count(args[0], Kind::Float, Kind::Bool),
+            Op::IEqual | Op::INotEqual | Op::UGreaterThan | Op::SGreaterThan |
+            Op::UGreaterThanEqual | Op::SGreaterThanEqual | Op::ULessThan | Op::SLessThan |
+            Op::ULessThanEqual | Op::SLessThanEqual => {
+                args.len() == 2 && like_count(args[0], Kind::Int, Kind::Bool) &&
+                    self.same(args[0], args[1])
+            }
+            Op::FOrdEqual | Op::FUnordEqual | Op::FOrdNotEqual | Op::FUnordNotEqual |
+            Op::FOrdLessThan | Op::FUnordLessThan | Op::FOrdGreaterThan |
+            Op::FUnordGreaterThan | Op::FOrdLessThanEqual | Op::FUnordLessThanEqual |
+            Op::FOrdGreaterThanEqual | Op::FUnordGreaterThanEqual | Op::Ordered |
+            Op::Unordered => {
+                args.len() == 2 && like_count(args[0], Kind::Float, Kind::Bool) &&
+                    self.same(args[0], args[1])
+            }
+
+            Op::ConvertFToU | Op::ConvertFToS => {
+                args.len() == 1 && like_count(args[0], Kind::Float, Kind::Int)
+            }
+            Op::ConvertSToF | Op::ConvertUToF => {
+                args.len() == 1 && like_count(args[0], Kind::Int, Kind::Float)
+            }
+            Op::UConvert | Op::SConvert => {
+                args.len() == 1 && like_count(args[0], Kind::Int, Kind::Int)
+            }
+            Op::FConvert => args.len() == 1 && like_count(args[0], Kind::Float, Kind::Float),
+            Op::Bitcast => {
+                // Numbers and vectors of numbers of the same size, pointers are not supported
+                args.len() == 1 &&
+                    match (self.shape(args[0]), self.shape(ty)) {
+                        (Some((from, width, count)), Some((to, other_width, other_count))) => {
+                            from != Kind::Bool && to != Kind::Bool &&
+                                width * count == other_width * other_count
+                        }
+                        _ => false,
+                    }
+            }
+
+            Op::VectorExtractDynamic => {
+                args.len() == 2 && self.is_vector(args[0], ty, self.count_of(args[0])) &&
+                    self.is_index(args[1])
+            }
+            Op::VectorInsertDynamic => {
+                args.len() == 3 && self.is_vector(ty, args[1], self.count_of(ty)) &&
+                    self.same(args[0], ty) && self.is_index(args[2])
+            }
+            Op::VectorShuffle => {
+                args.len() == 2 && {
+                    let component = self.scalar(args[0]);
+                    self.is_vector(args[0], component, self.count_of(args[0])) &&
+                        self.is_vector(args[1], component, self.count_of(args[1])) &&
+                        self.is_vector(ty, component, literals.len() as u32)
+                }
+            }
+            Op::CompositeConstruct => self.check_construct(ty, args),
+            Op::CompositeExtract => {
+                args.len() == 1 &&
+                    self.path_type(args[0], literals).map_or(false, |element| {
+                        self.same(element, ty)
+                    })
+            }
+            Op::CompositeInsert => {
+                args.len() == 2 && self.same(args[1], ty) &&
+                    self.path_type(ty, literals).map_or(false, |element| {
+                        self.same(element, args[0])
+                    })
+            }
+            Op::CopyObject => args.len() == 1 && self.same(args[0], ty),
+            Op::Transpose => {
+                args.len() == 1 &&
+                    match (self.matrix(args[0]), self.matrix(ty)) {
+                        (Some((a, rows, columns)), Some((b, other_rows, other_columns))) => {
+                            rows == other_columns && columns == other_rows &&
+                                self.same(self.scalar(a), self.scalar(b))
+                        }
+                        _ => false,
+                    }
+            }
+            _ => false,
+        }
+    }
+
+    /// The number of components of a vector, 0 for anything else.
+    fn count_of(&self, ty: Word) -> u32 {
+        match self.get(ty) {
+            Ok(&Type::Vector { count, .. }) => count,
+            _ => 0,
+        }
+    }
+
+    /// Whether the constituents `args` make up a composite of type `ty`. Vectors may be made of
+    /// smaller vectors as well.
+    pub fn check_construct(&self, ty: Word, args: &[Word]) -> bool {
+        match *self.get(ty).unwrap_or(&Type::Void) {
+            Type::Vector { component, count } => {
+                let mut total = 0;
+                for &arg in args {
+                    total += if self.same(arg, component) {
+                        1
+                    } else if self.is_vector(arg, component, self.count_of(arg)) {
+                        self.count_of(arg)
+                    } else {
+                        return false;
+                    };
+                }
+                total == count
+            }
+            Type::Matrix { column: element, count: length } |
+            Type::Array { element, length } => {
+                args.len() == length as usize && args.iter().all(|&arg| self.same(arg, element))
+            }
+            Type::Struct { ref members } => {
+                args.len() == members.len() &&
+                    args.iter().zip(members).all(|(&arg, &member)| self.same(arg, member))
+            }
+            _ => false,
+        }
+    }
+
+    /// Like `check_pure`, for the GLSL.std.450 instruction `instruction`.
+    ///
+    /// Takes at least as many operands as the instruction has.
+    pub fn check_glsl(&self, instruction: u32, ty: Word, args: &[Word]) -> bool {
+        let args = &args[..operand_count(instruction)];
+        let like_result = |kind: Kind| {
+            self.count(ty, kind).is_some() && args.iter().all(|&arg| self.same(arg, ty))
+        };
+        match instruction {
+            ROUND | ROUND_EVEN | TRUNC | F_ABS | F_SIGN | FLOOR | CEIL | FRACT | RADIANS |
+            DEGREES | SIN | COS | TAN | ASIN | ACOS | ATAN | SINH | COSH | TANH | ASINH |
+            ACOSH | ATANH | ATAN2 | POW | EXP | LOG | EXP2 | LOG2 | SQRT | INVERSE_SQRT |
+            F_MIN | F_MAX | N_MIN | N_MAX | F_CLAMP | N_CLAMP | F_MIX | STEP | SMOOTH_STEP |
+            FMA | NORMALIZE | FACE_FORWARD | REFLECT => like_result(Kind::Float),
+            S_ABS | S_SIGN | U_MIN | S_MIN | U_MAX | S_MAX | U_CLAMP | S_CLAMP | FIND_I_LSB |
+            FIND_S_MSB | FIND_U_MSB => like_result(Kind::Int),
+            CROSS => like_result(Kind::Float) && self.count_of(ty) == 3,
+            DETERMINANT => {
+                self.matrix(args[0]).map_or(false, |(column, rows, columns)| {
+                    rows == columns && self.same(ty, self.scalar(column))
+                })
+            }
+            MATRIX_INVERSE => {
+                self.same(args[0], ty) &&
+                    self.matrix(ty).map_or(false, |(_, rows, columns)| rows == columns)
+            }
+            MODF => {
+                self.count(ty, Kind::Float).is_some() && self.same(args[0], ty) &&
+                    self.pointee(args[1]).map_or(false, |pointee| self.same(pointee, ty))
+            }
+            MODF_STRUCT => {
+                self.count(args[0], Kind::Float).is_some() && self.is_pair(ty, args[0], args[0])
+            }
+            FREXP => {
+                self.count(ty, Kind::Float).is_some() && self.same(args[0], ty) &&
+                    self.pointee(args[1]).map_or(false, |pointee| {
+                        self.count(pointee, Kind::Int) == self.count(ty, Kind::Float)
+                    })
+            }
+            FREXP_STRUCT => {
+                self.count(args[0], Kind::Float).is_some() &&
+                    match self.get(ty) {
+                        Ok(&Type::Struct { ref members }) if members.len() == 2 => {
+                            self.same(members[0], args[0]) &&
+                                self.count(members[1], Kind::Int) ==
+                                    self.count(args[0], Kind::Float)
+                        }
+                        _ => false,
+                    }
+            }
+            LDEXP => {
+                self.count(ty, Kind::Float).is_some() && self.same(args[0], ty) &&
+                    self.count(args[1], Kind::Int) == self.count(ty, Kind::Float)
+            }
+            PACK_SNORM_4X8 | PACK_UNORM_4X8 => {
+                self.count(args[0], Kind::Float) == Some(4) &&
+                    self.shape(ty) == Some((Kind::Int, 32, 1))
+            }
+            PACK_SNORM_2X16 | PACK_UNORM_2X16 | PACK_HALF_2X16 => {
+                self.count(args[0], Kind::Float) == Some(2) &&
+                    self.shape(ty) == Some((Kind::Int, 32, 1))
+            }
+            PACK_DOUBLE_2X32 => {
+                self.shape(args[0]) == Some((Kind::Int, 32, 2)) &&
+                    self.shape(ty) == Some((Kind::Float, 64, 1))
+            }
+            UNPACK_SNORM_2X16 | UNPACK_UNORM_2X16 => {
+                self.is_index(args[0]) && self.count(ty, Kind::Float) == Some(2)
+            }
+            UNPACK_SNORM_4X8 | UNPACK_UNORM_4X8 => {
+                self.is_index(args[0]) && self.count(ty, Kind::Float) == Some(4)
+            }
+            UNPACK_HALF_2X16 => {
+                self.is_index(args[0]) && self.shape(ty) == Some((Kind::Float, 32, 2))
+            }
+            UNPACK_DOUBLE_2X32 => {
+                self.shape(args[0]) == Some((Kind::Float, 64, 1)) &&
+                    self.shape(ty) == Some((Kind::Int, 32, 2))
+            }
+            LENGTH => {
+                self.count(args[0], Kind::Float).is_some() && self.same(ty, self.scalar(args[0]))
+            }
+            DISTANCE => {
+                self.count(args[0], Kind::Float).is_some() && self.same(args[0], args[1]) &&
+                    self.same(ty, self.scalar(args[0]))
+            }
+            REFRACT => {
+                self.count(ty, Kind::Float).is_some() && self.same(args[0], ty) &&
+                    self.same(args[1], ty) && self.count(args[2], Kind::Float) == Some(1)
+            }
+            INTERPOLATE_AT_CENTROID | INTERPOLATE_AT_SAMPLE | INTERPOLATE_AT_OFFSET => {
+                let operand = match instruction {
+                    INTERPOLATE_AT_SAMPLE => self.is_index(args[1]),
+                    INTERPOLATE_AT_OFFSET => self.count(args[1], Kind::Float) == Some(2),
+                    _ => true,
+                };
+                operand && self.pointee(args[0]).map_or(false, |pointee| self.same(pointee, ty))
+            }
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,530 @@
+//! Runs the functions of a module, one instruction at a time.
+use std::collections::HashMap;
+use std::sync::atomic::{self, Ordering};
+
+use rspirv::mr::{Instruction, Operand};
+use spirv_headers::{Op, Word};
+
+use abi::Invocation;
+use glsl_op;
+use runtime;
+use trans::*;
+
+use super::memory::Layout;
+use super::ops;
+use super::value::{mask, Pointer, Value};
+use super::{glsl, ExtInstSet, Global, Interpreter, Type};
+
+/// Whether the interpreter can run `opcode` inside a function.
+pub fn is_supported(opcode: Op) -> bool {
+    match opcode {
+        // Control flow
+        Op::Nop | Op::Line | Op::NoLine | Op::LoopMerge | Op::SelectionMerge | Op::Phi |
+        Op::Branch | Op::BranchConditional | Op::Switch | Op::Return | Op::ReturnValue |
+        Op::Unreachable | Op::FunctionCall | Op::Kill |
+        // Barriers
+        Op::ControlBarrier | Op::MemoryBarrier |
+        // Memory
+        Op::Variable | Op::Load | Op::Store | Op::CopyMemory | Op::AccessChain |
+        Op::InBoundsAccessChain | Op::Undef | Op::ExtInst => true,
+        opcode => ops::is_pure(opcode),
+    }
+}
+
+/// Runs the entry point `function` for `invocation`.
+///
+/// Unsafe because the shader accesses whatever memory its resources point to.
+pub unsafe fn run(interpreter: &Interpreter, function: Word, invocation: &mut Invocation) {
+    // Private variables come first, function variables are added on top as functions run
+    let private = interpreter
+        .private
+        .iter()
+        .map(|&(ty, ref initializer)| {
+            initializer.clone().unwrap_or_else(|| interpreter.types.zero(ty))
+        })
+        .collect();
+    let mut execution = Execution {
+        interpreter: interpreter,
+        invocation: invocation,
+        variables: private,
+    };
+    if let Flow::Kill = execution.call(function, Vec::new()) {
+        (*execution.invocation).killed = 1;
+    }
+}
+
+/// How a function finished.
+enum Flow {
+    Return(Option<Value>),
+    Kill,
+    /// Reached OpUnreachable, which is undefined behaviour. The invocation ends right away with
+    /// the outputs it wrote so far.
+    Unreachable,
+}
+
+/// What to do after an instruction.
+enum Step {
+    Next,
+    Branch(Word),
+    Finish(Flow),
+}
+
+/// The state of a running invocation.
+struct Execution<'a> {
+    interpreter: &'a Interpreter,
+    /// Raw, as shaders write to it through pointers as well.
+    invocation: *mut Invocation,
+    /// Private and Function variables.
+    variables: Vec<Value>,
+}
+
+/// Results of the instructions of a function call, by id.
+type Frame = HashMap<Word, Value>;
+
+impl<'a> Execution<'a> {
+    unsafe fn call(&mut self, id: Word, args: Vec<Value>) -> Flow {
+        let interpreter = self.interpreter;
+        let function = &interpreter.functions[&id];
+        let definition = &interpreter.module.functions[function.index];
+        let mut frame = Frame::new();
+        for (parameter, arg) in definition.parameters.iter().zip(args) {
+            frame.insert(parameter.result_id.expect("parameter without id"), arg);
+        }
+        let variables = self.variables.len();
+
+        let mut previous = None;
+        let mut block = 0;
+        let flow = 'blocks: loop {
+            let basic_block = &definition.basic_blocks[block];
+            let label = basic_block.label.as_ref().and_then(|label| label.result_id);
+            // Phis all take their values from before the branch, so evaluate them together
+            let phis = basic_block
+                .instructions
+                .iter()
+                .filter(|inst| inst.class.opcode == Op::Phi)
+                .map(|inst| (inst.result_id.expect("phi without id"), self.phi(&frame, inst, previous)))
+                .collect::<Vec<_>>();
+            frame.extend(phis);
+            for inst in &basic_block.instructions {
+                match self.step(&mut frame, inst) {
+                    Step::Next => (),
+                    Step::Branch(target) => {
+                        previous = label;
+                        block = function.blocks[&target];
+                        continue 'blocks;
+                    }
+                    Step::Finish(flow) => break 'blocks flow,
+                }
+            }
+            unreachable!("Interpreter::new checked that block %{:?} ends in a branch", label);
+        };
+        self.variables.truncate(variables);
+        flow
+    }
+
+    unsafe fn phi(&self, frame: &Frame, inst: &Instruction, previous: Option<Word>) -> Value {
+        for pair in inst.operands.chunks(2) {
+            if let [Operand::IdRef(value), Operand::IdRef(parent)] = *pair {
+                if Some(parent) == previous {
+                    return self.value(frame, value);
+                }
+            }
+        }
+        unreachable!("Interpreter::new checked phi %{:?} for block %{:?}", inst.result_id, previous)
+    }
+
+    unsafe fn step(&mut self, frame: &mut Frame, inst: &Instruction) -> Step {
+        let opcode = inst.class.opcode;
+        let id = |index: usize| operand_id(inst, index).expect("missing operand");
+        let value = match opcode {
+            Op::Nop | Op::Line | Op::NoLine | Op::LoopMerge | Op::SelectionMerge | Op::Phi => {
+                return Step::Next
+            }
+
+            // Control flow
+            Op::Branch => return Step::Branch(id(0)),
+            Op::BranchConditional => {
+                let condition = self.value(frame, id(0)).to_bool();
+                return Step::Branch(if condition { id(1) } else { id(2) });
+            }
+            Op::Switch => {
+                let selector = self.value(frame, id(0));
+                let width = selector.width();
+                for case in inst.operands[2..].chunks(2) {
+                    // Interpreter::new rejects cases of other operands
+                    let (literal, target) = match *case {
+                        [Operand::LiteralInt32(literal), Operand::IdRef(target)] => {
+                            (u64::from(literal), target)
+                        }
+                        [Operand::LiteralInt64(literal), Operand::IdRef(target)] => {
+                            (literal, target)
+                        }
+                        _ => continue,
+                    };
+                    // Literals are as wide as the selector
+                    if literal & mask(width) == selector.bits() {
+                        return Step::Branch(target);
+                    }
+                }
+                return Step::Branch(id(1));
+            }
+            Op::Return => return Step::Finish(Flow::Return(None)),
+            Op::ReturnValue => return Step::Finish(Flow::Return(Some(self.value(frame, id(0))))),
+            Op::Unreachable => return Step::Finish(Flow::Unreachable),
+            Op::Kill => return Step::Finish(Flow::Kill),
+            Op::FunctionCall => {
+                let args = operand_ids(inst, 1)
+                    .expect("invalid OpFunctionCall")
+                    .into_iter()
+                    .map(|arg| self.value(frame, arg))
+                    .collect();
+                match self.call(id(0), args) {
+                    Flow::Return(Some(value)) => value,
+                    Flow::Return(None) => return Step::Next,
+                    // Stop right away if the callee discarded the fragment or went astray
+                    flow => return Step::Finish(flow),
+                }
+            }
+
+            // Barriers
+            Op::ControlBarrier => {
+                runtime::__spirv_control_barrier((*self.invocation).resources);
+                return Step::Next;
+            }
+            Op::MemoryBarrier => {
+                atomic::fence(Ordering::SeqCst);
+                return Step::Next;
+            }
+
+            // Memory
+            Op::Variable => {
+                let initial = match inst.operands.get(1) {
+                    Some(_) => self.value(frame, id(1)),
+                    None => {
+                        let pointer = result_type(inst).expect("variable without type");
+                        match *self.interpreter.types.expect(pointer) {
+                            Type::Pointer { pointee } => self.interpreter.types.zero(pointee),
+                            ref ty => unreachable!("Interpreter::new checked {:?}", ty),
+                        }
+                    }
+                };
+                self.variables.push(initial);
+                Value::Pointer(Pointer::Variable {
+                    index: self.variables.len() - 1,
+                    path: Vec::new(),
+                })
+            }
+            Op::Load => {
+                let pointer = self.value(frame, id(0)).into_pointer();
+                self.load(&pointer)
+            }
+            Op::Store => {
+                let pointer = self.value(frame, id(0)).into_pointer();
+                let value = self.value(frame, id(1));
+                self.store(&pointer, value);
+                return Step::Next;
+            }
+            Op::CopyMemory => {
+                let target = self.value(frame, id(0)).into_pointer();
+                let source = self.value(frame, id(1)).into_pointer();
+                let value = self.load(&source);
+                self.store(&target, value);
+                return Step::Next;
+            }
+            Op::AccessChain | Op::InBoundsAccessChain => {
+                let base = self.value(frame, id(0)).into_pointer();
+                let indices = operand_ids(inst, 1)
+                    .expect("invalid access chain")
+                    .into_iter()
+                    .map(|index| self.value(frame, index).signed())
+                    .collect::<Vec<_>>();
+                Value::Pointer(self.access_chain(base, &indices))
+            }
+            Op::Undef => self.interpreter.types.zero(result_type(inst).expect("missing type")),
+            Op::ExtInst => {
+                match self.interpreter.ext_inst_set(inst).expect("undefined set") {
+                    ExtInstSet::Glsl450 => self.ext_inst(frame, inst),
+                    ExtInstSet::NonSemantic => return Step::Next,
+                }
+            }
+
+            _ => {
+                let mut args = Vec::new();
+                let mut literals = Vec::new();
+                for operand in &inst.operands {
+                    match *operand {
+                        Operand::IdRef(arg) => args.push(self.value(frame, arg)),
+                        Operand::LiteralInt32(literal) => literals.push(literal),
+                        // Interpreter::new rejects other operands
+                        _ => (),
+                    }
+                }
+                let ty = result_type(inst).expect("missing type");
+                // Every supported opcode without a case of its own is pure
+                match self.interpreter.types.evaluate(opcode, ty, &args, &literals) {
+                    Some(value) => value,
+                    None => unreachable!("Op{:?} is not pure", opcode),
+                }
+            }
+        };
+        frame.insert(inst.result_id.expect("missing result id"), value);
+        Step::Next
+    }
+
+    unsafe fn ext_inst(&mut self, frame: &Frame, inst: &Instruction) -> Value {
+        let ty = result_type(inst).expect("missing type");
+        let instruction = operand_u32(inst, 1).expect("missing instruction");
+        let args = operand_ids(inst, 2)
+            .expect("invalid OpExtInst")
+            .into_iter()
+            .map(|arg| self.value(frame, arg))
+            .collect::<Vec<_>>();
+        match instruction {
+            glsl_op::MODF => {
+                let (fract, whole) = glsl::modf(&args[0]);
+                self.store(args[1].clone().into_pointer(), whole);
+                fract
+            }
+            glsl_op::FREXP => {
+                // The exponent has whatever integer type the pointer points to
+                let pointer = args[1].clone().into_pointer();
+                let width = match self.load(&pointer) {
+                    Value::Composite(components) => components[0].width(),
+                    exponent => exponent.width(),
+                };
+                let (mantissa, exponent) = glsl::frexp(&args[0], width);
+                self.store(&pointer, exponent);
+                mantissa
+            }
+            // We only ever rasterize with one sample at the pixel center, which is where the
+            // interpolants already are.
+            glsl_op::INTERPOLATE_AT_CENTROID |
+            glsl_op::INTERPOLATE_AT_SAMPLE |
+            glsl_op::INTERPOLATE_AT_OFFSET => self.load(&args[0].clone().into_pointer()),
+            _ => glsl::evaluate(&self.interpreter.types, instruction, ty, &args),
+        }
+    }
+
+    /// Returns the value of `id`, which may be a result of the running function, a constant or
+    /// a global variable.
+    unsafe fn value(&self, frame: &Frame, id: Word) -> Value {
+        if let Some(value) = frame.get(&id) {
+            return value.clone();
+        }
+        if let Some(value) = self.interpreter.constants.get(&id) {
+            return value.clone();
+        }
+        match self.interpreter.globals.get(&id) {
+            Some(global) => Value::Pointer(self.global_pointer(id, global)),
+            None => unreachable!("Interpreter::new checked that %{} is defined", id),
+        }
+    }
+
+    /// Returns a pointer to the global variable `id` for the running invocation.
+    unsafe fn global_pointer(&self, id: Word, global: &Global) -> Pointer {
+        let resources = (*self.invocation).resources;
+        match *global {
+            Global::Private(index) => {
+                Pointer::Variable {
+                    index: index,
+                    path: Vec::new(),
+                }
+            }
+            Global::Workgroup { offset, ty } => {
+                Pointer::Memory {
+                    address: (*resources).workgroup_memory as usize + offset,
+                    ty: ty,
+                    layout: Layout::Natural,
+                }
+            }
+            Global::Invocation { offset, ty, layout } => {
+                Pointer::Memory {
+                    address: self.invocation as usize + offset,
+                    ty: ty,
+                    layout: layout,
+                }
+            }
+            Global::BuiltInBlock(_) => Pointer::BuiltInBlock { variable: id },
+            Global::PushConstants { ty } => {
+                Pointer::Memory {
+                    address: (*resources).push_constants as usize,
+                    ty: ty,
+                    layout: Layout::Explicit { matrix_stride: 0 },
+                }
+            }
+            Global::Descriptor {
+                set,
+                binding,
+                buffer,
+                ty,
+                layout,
+            } => {
+                let descriptors = *(*resources).descriptor_sets[set as usize].offset(
+                    binding as isize,
+                );
+                let element = match *self.interpreter.types.expect(ty) {
+                    Type::Array { element, .. } | Type::RuntimeArray { element } => Some(element),
+                    _ => None,
+                };
+                if !buffer {
+                    // Arrays of images and samplers are laid out like their descriptors
+                    Pointer::Memory {
+                        address: descriptors as usize,
+                        ty: ty,
+                        layout: Layout::Natural,
+                    }
+                } else if let Some(element) = element {
+                    // Every buffer has a descriptor of its own, the access chain picks one
+                    Pointer::Buffers {
+                        descriptors: descriptors as usize,
+                        element: element,
+                        layout: layout,
+                    }
+                } else {
+                    Pointer::Memory {
+                        address: *descriptors as usize,
+                        ty: ty,
+                        layout: layout,
+                    }
+                }
+            }
+        }
+    }
+
+    unsafe fn access_chain(&self, base: Pointer, indices: &[i64]) -> Pointer {
+        let types = &self.interpreter.types;
+        let (mut address, mut ty, mut layout, indices) = match base {
+            Pointer::Variable { index, mut path } => {
+                path.extend(indices.iter().map(|&index| index as u32));
+                return Pointer::Variable {
+                    index: index,
+                    path: path,
+                };
+            }
+            Pointer::Memory {
+                address,
+                ty,
+                layout,
+            } => (address, ty, layout, indices),
+            Pointer::Buffers {
+                descriptors,
+                element,
+                layout,
+            } => {
+                let buffer = *(descriptors as *const usize).offset(indices[0] as isize);
+                (buffer, element, layout, &indices[1..])
+            }
+            Pointer::BuiltInBlock { variable } => {
+                let (offset, member) = self.builtin_member(variable, indices[0] as u32);
+                let address = self.invocation as usize + offset;
+                (address, member, Layout::Natural, &indices[1..])
+            }
+        };
+        for &index in indices {
+            let (offset, element, element_layout) =
+                types.element(ty, layout, index).expect("invalid access chain");
+            address = (address as isize).wrapping_add(offset as isize) as usize;
+            ty = element;
+            layout = element_layout;
+        }
+        Pointer::Memory {
+            address: address,
+            ty: ty,
+            layout: layout,
+        }
+    }
+
+    /// Returns the offset in the `Invocation` and the type of a member of a built-in block.
+    fn builtin_member(&self, variable: Word, member: u32) -> (usize, Word) {
+        let offset = match self.interpreter.globals[&variable] {
+            Global::BuiltInBlock(ref members) => members[member as usize],
+            ref global => unreachable!("{:?} is not a built-in block", global),
+        };
+        let pointee = self.interpreter.pointee(variable).expect("undefined variable");
+        let ty = match *self.interpreter.types.expect(pointee) {
+            Type::Struct { ref members } => members[member as usize],
+            ref ty => unreachable!("{:?} is not a struct", ty),
+        };
+        // Interpreter::new rejected access chains to unsupported members
+        (offset.expect("unsupported built-in"), ty)
+    }
+
+    unsafe fn load(&self, pointer: &Pointer) -> Value {
+        match *pointer {
+            Pointer::Memory {
+                address,
+                ty,
+                layout,
+            } => self.interpreter.types.load(address, ty, layout),
+            Pointer::Variable { index, ref path } => self.variables[index].at(path).clone(),
+            Pointer::BuiltInBlock { variable } => {
+                let members = self.builtin_members(variable);
+                Value::Composite(
+                    members
+                        .into_iter()
+                        .map(|(member, ty)| match member {
+                            Some(member) => self.load(&member),
+                            // Built-ins that are not supported read as 0
+                            None => self.interpreter.types.zero(ty),
+                        })
+                        .collect(),
+                )
+            }
+            Pointer::Buffers { .. } => {
+                unreachable!("Interpreter::new rejects loading buffer arrays")
+            }
+        }
+    }
+
+    unsafe fn store<P: ::std::borrow::Borrow<Pointer>>(&mut self, pointer: P, value: Value) {
+        match *pointer.borrow() {
+            Pointer::Memory {
+                address,
+                ty,
+                layout,
+            } => self.interpreter.types.store(address, &value, ty, layout),
+            Pointer::Variable { index, ref path } => {
+                *self.variables[index].at_mut(path) = value;
+            }
+            Pointer::BuiltInBlock { variable } => {
+                let members = self.builtin_members(variable);
+                for ((member, _), value) in members.into_iter().zip(value.into_components()) {
+                    if let Some(member) = member {
+                        self.store(member, value);
+                    }
+                }
+            }
+            Pointer::Buffers { .. } => {
+                unreachable!("Interpreter::new rejects storing buffer arrays")
+            }
+        }
+    }
+
+    /// Returns pointers to the supported members of a built-in block, and the type of every
+    /// member.
+    fn builtin_members(&self, variable: Word) -> Vec<(Option<Pointer>, Word)> {
+        let offsets = match self.interpreter.globals[&variable] {
+            Global::BuiltInBlock(ref offsets) => offsets,
+            ref global => unreachable!("{:?} is not a built-in block", global),
+        };
+        let pointee = self.interpreter.pointee(variable).expect("undefined variable");
+        let members = match *self.interpreter.types.expect(pointee) {
+            Type::Struct { ref members } => members,
+            ref ty => unreachable!("{:?} is not a struct", ty),
+        };
+        offsets
+            .iter()
+            .zip(members)
+            .map(|(offset, &ty)| {
+                let pointer = offset.map(|offset| {
+                    Pointer::Memory {
+                        address: self.invocation as usize + offset,
+                        ty: ty,
+                        layout: Layout::Natural,
+                    }
+                });
+                (pointer, ty)
+            })
+            .collect()
+    }
+}
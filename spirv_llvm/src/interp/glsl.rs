@@ -0,0 +1,432 @@
+//! The GLSL.std.450 extended instruction set, computed like `glsl::trans_glsl_inst` does.
+use std::f64::consts::PI;
+
+use spirv_headers::Word;
+
+use glsl_op::*;
+use runtime;
+
+use super::ops::{add, dot, float_op, multiply};
+use super::value::{map, sign_extend, zip, zip3, Value};
+use super::{Type, Types};
+
+/// Evaluates the pure instruction `instruction` with result type `ty`.
+///
+/// Modf, Frexp and the InterpolateAt* instructions access memory, so the caller handles them
+/// with `modf`, `frexp` and a load.
+pub fn evaluate(types: &Types, instruction: u32, ty: Word, args: &[Value]) -> Value {
+    let arg = |index: usize| {
+        args.get(index).unwrap_or_else(|| {
+            panic!("missing operand {} of {} {}", index, NAME, instruction)
+        })
+    };
+    match instruction {
+        ROUND => unary(arg(0), f32::round, f64::round),
+        ROUND_EVEN => {
+            // Ties go to the even neighbour, like nearbyint in the default rounding mode
+            unary(arg(0), round_even_f32, round_even_f64)
+        }
+        TRUNC => unary(arg(0), f32::trunc, f64::trunc),
+        F_ABS => unary(arg(0), f32::abs, f64::abs),
+        S_ABS => map(arg(0), &mut |x| Value::int(x.signed().wrapping_abs() as u64, x.width())),
+        F_SIGN => {
+            map(arg(0), &mut |x| {
+                let value = x.to_f64();
+                let sign = if value > 0.0 {
+                    1.0
+                } else if value < 0.0 {
+                    -1.0
+                } else {
+                    0.0
+                };
+                Value::Float(sign, x.width())
+            })
+        }
+        S_SIGN => map(arg(0), &mut |x| Value::int(x.signed().signum() as u64, x.width())),
+        FLOOR => unary(arg(0), f32::floor, f64::floor),
+        CEIL => unary(arg(0), f32::ceil, f64::ceil),
+        FRACT => map(arg(0), &mut |x| Value::float(x.to_f64() - x.to_f64().floor(), x.width())),
+        RADIANS => map(arg(0), &mut |x| scale(x, PI / 180.0)),
+        DEGREES => map(arg(0), &mut |x| scale(x, 180.0 / PI)),
+        SIN => unary(arg(0), f32::sin, f64::sin),
+        COS => unary(arg(0), f32::cos, f64::cos),
+        TAN => unary(arg(0), f32::tan, f64::tan),
+        ASIN => unary(arg(0), f32::asin, f64::asin),
+        ACOS => unary(arg(0), f32::acos, f64::acos),
+        ATAN => unary(arg(0), f32::atan, f64::atan),
+        SINH => unary(arg(0), f32::sinh, f64::sinh),
+        COSH => unary(arg(0), f32::cosh, f64::cosh),
+        TANH => unary(arg(0), f32::tanh, f64::tanh),
+        ASINH => unary(arg(0), f32::asinh, f64::asinh),
+        ACOSH => unary(arg(0), f32::acosh, f64::acosh),
+        ATANH => unary(arg(0), f32::atanh, f64::atanh),
+        ATAN2 => binary(arg(0), arg(1), f32::atan2, f64::atan2),
+        POW => binary(arg(0), arg(1), f32::powf, f64::powf),
+        EXP => unary(arg(0), f32::exp, f64::exp),
+        LOG => unary(arg(0), f32::ln, f64::ln),
+        EXP2 => unary(arg(0), f32::exp2, f64::exp2),
+        LOG2 => unary(arg(0), f32::log2, f64::log2),
+        SQRT => unary(arg(0), f32::sqrt, f64::sqrt),
+        INVERSE_SQRT => {
+            map(arg(0), &mut |x| {
+                let root = Value::float(x.to_f64().sqrt(), x.width());
+                Value::float(1.0 / root.to_f64(), x.width())
+            })
+        }
+        DETERMINANT => determinant(&columns(arg(0))),
+        MATRIX_INVERSE => matrix_inverse(arg(0)),
+        MODF_STRUCT => {
+            let (fract, whole) = modf(arg(0));
+            Value::Composite(vec![fract, whole])
+        }
+        F_MIN | N_MIN => float_op(arg(0), arg(1), &mut f64::min),
+        F_MAX | N_MAX => float_op(arg(0), arg(1), &mut f64::max),
+        U_MIN => zip(arg(0), arg(1), &mut |a, b| if a.bits() < b.bits() { a } else { b }.clone()),
+        S_MIN => zip(arg(0), arg(1), &mut |a, b| if a.signed() < b.signed() { a } else { b }.clone()),
+        U_MAX => zip(arg(0), arg(1), &mut |a, b| if a.bits() > b.bits() { a } else { b }.clone()),
+        S_MAX => zip(arg(0), arg(1), &mut |a, b| if a.signed() > b.signed() { a } else { b }.clone()),
+        F_CLAMP | N_CLAMP => {
+            let lower = float_op(arg(0), arg(1), &mut f64::max);
+            float_op(&lower, arg(2), &mut f64::min)
+        }
+        U_CLAMP => {
+            zip3(arg(0), arg(1), arg(2), &mut |x, low, high| {
+                let x = if x.bits() > low.bits() { x } else { low };
+                if x.bits() < high.bits() { x } else { high }.clone()
+            })
+        }
+        S_CLAMP => {
+            zip3(arg(0), arg(1), arg(2), &mut |x, low, high| {
+                let x = if x.signed() > low.signed() { x } else { low };
+                if x.signed() < high.signed() { x } else { high }.clone()
+            })
+        }
+        F_MIX => {
+            // x * (1 - a) + y * a, as the spec defines it
+            zip3(arg(0), arg(1), arg(2), &mut |x, y, a| {
+                let inverse = Value::float(1.0 - a.to_f64(), a.width());
+                add(&multiply(x, &inverse), &multiply(y, a))
+            })
+        }
+        STEP => {
+            zip(arg(0), arg(1), &mut |edge, x| {
+                let step = if x.to_f64() < edge.to_f64() { 0.0 } else { 1.0 };
+                Value::Float(step, x.width())
+            })
+        }
+        SMOOTH_STEP => {
+            zip3(arg(0), arg(1), arg(2), &mut |edge0, edge1, x| {
+                let width = x.width();
+                let t = Value::float(
+                    Value::float(x.to_f64() - edge0.to_f64(), width).to_f64() /
+                        Value::float(edge1.to_f64() - edge0.to_f64(), width).to_f64(),
+                    width,
+                );
+                // Clamps like maxnum and minnum in compiled code, which turn NaN into 0
+                #[allow(clippy::manual_clamp)]
+                let t = Value::Float(t.to_f64().max(0.0).min(1.0), width);
+                let factor = Value::float(3.0 - multiply(&Value::Float(2.0, width), &t).to_f64(), width);
+                multiply(&multiply(&t, &t), &factor)
+            })
+        }
+        FMA => {
+            zip3(arg(0), arg(1), arg(2), &mut |a, b, c| {
+                Value::float(a.to_f64().mul_add(b.to_f64(), c.to_f64()), a.width())
+            })
+        }
+        FREXP_STRUCT => {
+            let exponent_width = match *types.expect(ty) {
+                Type::Struct { ref members } => types.scalar_width(members[1]),
+                ref ty => panic!("{:?} is not a struct", ty),
+            };
+            let (mantissa, exponent) = frexp(arg(0), exponent_width);
+            Value::Composite(vec![mantissa, exponent])
+        }
+        LDEXP => {
+            zip(arg(0), arg(1), &mut |x, exponent| {
+                let exponent = exponent.signed().max(i64::from(i32::MIN)).min(i64::from(i32::MAX));
+                Value::float(runtime::ldexp(x.to_f64(), exponent as i32), x.width())
+            })
+        }
+        PACK_SNORM_4X8 => pack_norm(arg(0), true, 8),
+        PACK_UNORM_4X8 => pack_norm(arg(0), false, 8),
+        PACK_SNORM_2X16 => pack_norm(arg(0), true, 16),
+        PACK_UNORM_2X16 => pack_norm(arg(0), false, 16),
+        PACK_HALF_2X16 => {
+            let bits = arg(0).components().iter().enumerate().fold(0, |bits, (index, x)| {
+                let half = super::value::f16_from_f32(x.to_f64() as f32);
+                bits | u64::from(half) << (16 * index)
+            });
+            Value::Int(bits, 32)
+        }
+        UNPACK_SNORM_2X16 => unpack_norm(arg(0), true, 16, types.scalar_width(ty)),
+        UNPACK_UNORM_2X16 => unpack_norm(arg(0), false, 16, types.scalar_width(ty)),
+        UNPACK_SNORM_4X8 => unpack_norm(arg(0), true, 8, types.scalar_width(ty)),
+        UNPACK_UNORM_4X8 => unpack_norm(arg(0), false, 8, types.scalar_width(ty)),
+        UNPACK_HALF_2X16 => {
+            let bits = arg(0).bits();
+            Value::Composite(
+                (0..2)
+                    .map(|index| {
+                        let half = (bits >> (16 * index)) as u16;
+                        Value::Float(f64::from(super::value::f16_to_f32(half)), 32)
+                    })
+                    .collect(),
+            )
+        }
+        // Both only reinterpret bits, low word first
+        PACK_DOUBLE_2X32 => types.bitcast(arg(0), ty),
+        UNPACK_DOUBLE_2X32 => types.bitcast(arg(0), ty),
+        LENGTH => length(arg(0)),
+        DISTANCE => length(&float_op(arg(0), arg(1), &mut |a, b| a - b)),
+        CROSS => {
+            let (x, y) = (arg(0).components(), arg(1).components());
+            Value::Composite(
+                (0..3)
+                    .map(|index| {
+                        let (next, last) = ((index + 1) % 3, (index + 2) % 3);
+                        let a = multiply(&x[next], &y[last]);
+                        let b = multiply(&x[last], &y[next]);
+                        Value::float(a.to_f64() - b.to_f64(), a.width())
+                    })
+                    .collect(),
+            )
+        }
+        NORMALIZE => {
+            let length = length(arg(0));
+            map(arg(0), &mut |x| Value::float(x.to_f64() / length.to_f64(), x.width()))
+        }
+        FACE_FORWARD => {
+            let n = arg(0);
+            if dot(arg(2), arg(1)).to_f64() < 0.0 {
+                n.clone()
+            } else {
+                map(n, &mut |x| Value::Float(-x.to_f64(), x.width()))
+            }
+        }
+        REFLECT => {
+            // i - 2 * dot(n, i) * n
+            let (i, n) = (arg(0), arg(1));
+            let d = dot(n, i);
+            let scale = multiply(&Value::Float(2.0, d.width()), &d);
+            zip(i, n, &mut |i, n| {
+                Value::float(i.to_f64() - multiply(&scale, n).to_f64(), i.width())
+            })
+        }
+        REFRACT => refract(arg(0), arg(1), arg(2)),
+        FIND_I_LSB => {
+            map(arg(0), &mut |x| {
+                let bits = x.bits();
+                let lsb = if bits == 0 { !0 } else { u64::from(bits.trailing_zeros()) };
+                Value::int(lsb, x.width())
+            })
+        }
+        FIND_U_MSB => map(arg(0), &mut |x| find_msb(x.bits(), x.width())),
+        FIND_S_MSB => {
+            // Negative numbers look for the highest 0 bit, so flip them
+            map(arg(0), &mut |x| {
+                let sign = if x.signed() < 0 { !0 } else { 0 };
+                find_msb(x.bits() ^ sign, x.width())
+            })
+        }
+        _ => panic!("{} {} is not a pure instruction", NAME, instruction),
+    }
+}
+
+/// Splits `x` into a fraction and a whole number, both with the sign of `x`.
+pub fn modf(x: &Value) -> (Value, Value) {
+    let whole = map(x, &mut |x| Value::Float(x.to_f64().trunc(), x.width()));
+    let fract = zip(x, &whole, &mut |x, whole| {
+        Value::float(x.to_f64() - whole.to_f64(), x.width())
+    });
+    (fract, whole)
+}
+
+/// Splits `x` into a mantissa and an exponent of the given integer width.
+pub fn frexp(x: &Value, exponent_width: u32) -> (Value, Value) {
+    let mantissa = map(x, &mut |x| Value::Float(runtime::frexp(x.to_f64()).0, x.width()));
+    let exponent = map(x, &mut |x| {
+        Value::int(runtime::frexp(x.to_f64()).1 as u64, exponent_width)
+    });
+    (mantissa, exponent)
+}
+
+/// Applies a float function that comes in single and double precision. Half precision goes
+/// through single precision like the runtime helpers.
+fn unary(x: &Value, f: fn(f32) -> f32, g: fn(f64) -> f64) -> Value {
+    map(x, &mut |x| {
+        let value = match x.width() {
+            64 => g(x.to_f64()),
+            _ => f64::from(f(x.to_f64() as f32)),
+        };
+        Value::float(value, x.width())
+    })
+}
+
+fn binary(x: &Value, y: &Value, f: fn(f32, f32) -> f32, g: fn(f64, f64) -> f64) -> Value {
+    zip(x, y, &mut |x, y| {
+        let value = match x.width() {
+            64 => g(x.to_f64(), y.to_f64()),
+            _ => f64::from(f(x.to_f64() as f32, y.to_f64() as f32)),
+        };
+        Value::float(value, x.width())
+    })
+}
+
+fn round_even_f32(x: f32) -> f32 {
+    round_even_f64(f64::from(x)) as f32
+}
+
+fn round_even_f64(x: f64) -> f64 {
+    let rounded = x.round();
+    if (x - x.trunc()).abs() == 0.5 {
+        2.0 * (x / 2.0).round()
+    } else {
+        rounded
+    }
+}
+
+fn scale(x: &Value, factor: f64) -> Value {
+    multiply(x, &Value::float(factor, x.width()))
+}
+
+/// The length of a vector, or the absolute value of a scalar.
+fn length(x: &Value) -> Value {
+    match *x {
+        Value::Composite(_) => {
+            let squared = dot(x, x);
+            Value::float(squared.to_f64().sqrt(), squared.width())
+        }
+        _ => Value::Float(x.to_f64().abs(), x.width()),
+    }
+}
+
+/// Returns the highest set bit of `bits`, or -1 if there is none.
+fn find_msb(bits: u64, width: u32) -> Value {
+    let msb = match bits {
+        0 => !0,
+        _ => u64::from(63 - bits.leading_zeros()),
+    };
+    Value::int(msb, width)
+}
+
+fn refract(i: &Value, n: &Value, eta: &Value) -> Value {
+    // k = 1 - eta * eta * (1 - dot(n, i) * dot(n, i))
+    // k < 0 ? 0 : eta * i - (eta * dot(n, i) + sqrt(k)) * n
+    let d = dot(n, i);
+    let width = d.width();
+    let eta = Value::float(eta.to_f64(), width);
+    let one = Value::Float(1.0, width);
+    let k = Value::float(1.0 - multiply(&d, &d).to_f64(), width);
+    let k = multiply(&multiply(&eta, &eta), &k);
+    let k = Value::float(one.to_f64() - k.to_f64(), width);
+    if k.to_f64() < 0.0 {
+        return i.zeroed();
+    }
+    let root = Value::float(k.to_f64().sqrt(), width);
+    let factor = add(&multiply(&eta, &d), &root);
+    zip(i, n, &mut |i, n| {
+        Value::float(multiply(&eta, i).to_f64() - multiply(&factor, n).to_f64(), i.width())
+    })
+}
+
+/// Converts floats to normalized integers and packs them into one 32-bit integer.
+fn pack_norm(value: &Value, signed: bool, bits: u32) -> Value {
+    let (min, scale) = if signed {
+        (-1.0, ((1u64 << (bits - 1)) - 1) as f64)
+    } else {
+        (0.0, ((1u64 << bits) - 1) as f64)
+    };
+    let packed = value.components().iter().enumerate().fold(0, |packed, (index, x)| {
+        let x = x.to_f64() as f32;
+        let scaled = x.max(min).min(1.0) * scale as f32;
+        let int = scaled.round() as i64 as u64 & ((1 << bits) - 1);
+        packed | int << (bits as usize * index)
+    });
+    Value::Int(packed, 32)
+}
+
+/// Unpacks normalized integers into floats of the given width.
+fn unpack_norm(value: &Value, signed: bool, bits: u32, width: u32) -> Value {
+    let packed = value.bits();
+    Value::Composite(
+        (0..32 / bits)
+            .map(|index| {
+                let int = (packed >> (bits * index)) & ((1 << bits) - 1);
+                let x = if signed {
+                    let scale = ((1u64 << (bits - 1)) - 1) as f64;
+                    // The most negative integer would end up below -1
+                    (sign_extend(int, bits) as f64 / scale).max(-1.0)
+                } else {
+                    int as f64 / ((1u64 << bits) - 1) as f64
+                };
+                Value::float(x, width)
+            })
+            .collect(),
+    )
+}
+
+/// Returns the columns of a matrix as lists of components.
+fn columns(matrix: &Value) -> Vec<Vec<Value>> {
+    matrix.components().iter().map(|column| column.components().to_vec()).collect()
+}
+
+/// Returns the matrix `m` without column `column` and row `row`.
+fn minor(m: &[Vec<Value>], column: usize, row: usize) -> Vec<Vec<Value>> {
+    m.iter()
+        .enumerate()
+        .filter(|&(index, _)| index != column)
+        .map(|(_, components)| {
+            components
+                .iter()
+                .enumerate()
+                .filter(|&(index, _)| index != row)
+                .map(|(_, component)| component.clone())
+                .collect()
+        })
+        .collect()
+}
+
+/// Computes the determinant by Laplace expansion, in the same order as the compiled code.
+fn determinant(m: &[Vec<Value>]) -> Value {
+    if m.len() == 1 {
+        return m[0][0].clone();
+    }
+    let mut result: Option<Value> = None;
+    for row in 0..m.len() {
+        let term = multiply(&m[0][row], &determinant(&minor(m, 0, row)));
+        result = Some(match result {
+            None => term,
+            Some(sum) if row % 2 == 0 => add(&sum, &term),
+            Some(sum) => Value::float(sum.to_f64() - term.to_f64(), sum.width()),
+        });
+    }
+    result.expect("empty matrix")
+}
+
+fn matrix_inverse(matrix: &Value) -> Value {
+    let m = columns(matrix);
+    let n = m.len();
+    let det = determinant(&m);
+    let inverse_determinant = Value::float(1.0 / det.to_f64(), det.width());
+    Value::Composite(
+        (0..n)
+            .map(|column| {
+                Value::Composite(
+                    (0..n)
+                        .map(|row| {
+                            // inverse[column][row] = cofactor(row, column) / determinant
+                            let cofactor = determinant(&minor(&m, row, column));
+                            let cofactor = if (row + column) % 2 == 1 {
+                                Value::Float(-cofactor.to_f64(), cofactor.width())
+                            } else {
+                                cofactor
+                            };
+                            multiply(&cofactor, &inverse_determinant)
+                        })
+                        .collect(),
+                )
+            })
+            .collect(),
+    )
+}
@@ -0,0 +1,426 @@
+//! Values in memory that is shared with the application, laid out like the transpiler does.
+use std::ptr;
+
+use spirv_headers::{Decoration, Op, StorageClass, Word};
+
+use super::value::{f16_from_f32, f16_to_f32, Value};
+use super::{Type, Types};
+use TranspilerError;
+
+/// How a type is laid out in memory, see `transpiler::Layout`.
+///
+/// Private and Function variables are not in memory at all for the interpreter, so `Natural`
+/// only applies to built-in variables, Workgroup variables and the descriptors of images and
+/// samplers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layout {
+    /// The layout LLVM picks on the host.
+    Natural,
+    /// Offsets and strides given by decorations. `matrix_stride` is the MatrixStride of the
+    /// struct member the type is (part of), or 0 if there is none.
+    Explicit { matrix_stride: u32 },
+    /// Every array element, matrix column and struct member starts at a new Location slot.
+    Location,
+}
+
+impl Layout {
+    pub fn for_storage_class(storage_class: StorageClass) -> Self {
+        match storage_class {
+            StorageClass::Uniform |
+            StorageClass::StorageBuffer |
+            StorageClass::PushConstant |
+            StorageClass::PhysicalStorageBuffer => Layout::Explicit { matrix_stride: 0 },
+            _ => Layout::Natural,
+        }
+    }
+}
+
+impl Types {
+    /// Returns the size in bytes of `ty` laid out according to `layout`. Fails like the
+    /// transpiler if the decorations do not give a valid layout.
+    pub fn size(&self, ty: Word, layout: Layout) -> Result<u32, TranspilerError> {
+        let size = match *self.get(ty)? {
+            Type::Bool if layout == Layout::Natural => 1,
+            Type::Int { width } |
+            Type::Float { width } => width / 8,
+            Type::Vector { component, count } => {
+                let size = self.size(component, layout)? * count;
+                if layout == Layout::Natural {
+                    size.next_power_of_two()
+                } else {
+                    size
+                }
+            }
+            Type::Matrix { count, .. } |
+            Type::Array { length: count, .. } => self.stride(ty, layout)? * count,
+            Type::RuntimeArray { .. } => {
+                self.stride(ty, layout)?;
+                0
+            }
+            Type::Struct { ref members } => {
+                if layout == Layout::Natural {
+                    let mut size = 0;
+                    for &member in members {
+                        let align = self.alignment(member)?;
+                        size = (size + align - 1) / align * align + self.size(member, layout)?;
+                    }
+                    let align = self.alignment(ty)?;
+                    return Ok((size + align - 1) / align * align);
+                }
+                let mut members = (0..members.len() as u32)
+                    .map(|member| {
+                        let (offset, member_ty, member_layout) =
+                            self.member(ty, member, layout)?;
+                        Ok((offset, self.size(member_ty, member_layout)?))
+                    })
+                    .collect::<Result<Vec<_>, TranspilerError>>()?;
+                members.sort();
+                let mut size = 0;
+                for (offset, member_size) in members {
+                    if offset < size {
+                        return Err(TranspilerError::InvalidLayout(ty));
+                    }
+                    size = offset + member_size;
+                }
+                size
+            }
+            Type::Opaque { size } if layout == Layout::Natural => size,
+            Type::Bool => return Err(TranspilerError::UnsupportedType(Op::TypeBool)),
+            _ => return Err(TranspilerError::UnsupportedType(self.opcode(ty)?)),
+        };
+        Ok(size)
+    }
+
+    /// Alignment of `ty` in the natural layout.
+    fn alignment(&self, ty: Word) -> Result<u32, TranspilerError> {
+        match *self.get(ty)? {
+            Type::Matrix { column: element, .. } |
+            Type::Array { element, .. } |
+            Type::RuntimeArray { element } => self.alignment(element),
+            Type::Struct { ref members } => {
+                members.iter().try_fold(1, |align, &member| {
+                    Ok(align.max(self.alignment(member)?))
+                })
+            }
+            Type::Opaque { .. } => Ok(8),
+            _ => self.size(ty, Layout::Natural),
+        }
+    }
+
+    /// Returns the distance in bytes between the columns of a matrix or the elements of an
+    /// array.
+    pub fn stride(&self, ty: Word, layout: Layout) -> Result<u32, TranspilerError> {
+        let (element, stride) = match *self.get(ty)? {
+            Type::Matrix { column, .. } => {
+                let size = self.size(column, layout)?;
+                let stride = match layout {
+                    Layout::Natural => size,
+                    Layout::Location => 16 * self.locations(column)?,
+                    Layout::Explicit { matrix_stride } if matrix_stride != 0 => matrix_stride,
+                    Layout::Explicit { .. } => {
+                        // No stride given, assume vec3 columns are aligned like vec4
+                        match *self.get(column)? {
+                            Type::Vector { count: 3, .. } => size / 3 * 4,
+                            _ => size,
+                        }
+                    }
+                };
+                (column, stride)
+            }
+            Type::Array { element, .. } |
+            Type::RuntimeArray { element } => {
+                let stride = match layout {
+                    Layout::Natural => self.size(element, layout)?,
+                    Layout::Location => 16 * self.locations(element)?,
+                    Layout::Explicit { .. } => {
+                        match self.decorations.literal(ty, Decoration::ArrayStride) {
+                            Some(stride) => stride,
+                            None => self.size(element, layout)?,
+                        }
+                    }
+                };
+                (element, stride)
+            }
+            _ => return Err(TranspilerError::InvalidLayout(ty)),
+        };
+        if stride < self.size(element, layout)? {
+            return Err(TranspilerError::InvalidLayout(ty));
+        }
+        Ok(stride)
+    }
+
+    /// Returns the offset, type and layout of member `member` of the struct `ty`.
+    pub fn member(
+        &self,
+        ty: Word,
+        member: u32,
+        layout: Layout,
+    ) -> Result<(u32, Word, Layout), TranspilerError> {
+        let members = match *self.get(ty)? {
+            Type::Struct { ref members } => members,
+            _ => return Err(TranspilerError::InvalidLayout(ty)),
+        };
+        let member_ty = *members.get(member as usize).ok_or(
+            TranspilerError::InvalidLayout(ty),
+        )?;
+        let offset = match layout {
+            Layout::Natural => {
+                let mut offset = 0;
+                for (index, &other) in members.iter().enumerate() {
+                    let align = self.alignment(other)?;
+                    offset = (offset + align - 1) / align * align;
+                    if index == member as usize {
+                        break;
+                    }
+                    offset += self.size(other, layout)?;
+                }
+                return Ok((offset, member_ty, layout));
+            }
+            Layout::Location => {
+                let mut offset = 0;
+                for &other in &members[..member as usize] {
+                    offset += 16 * self.locations(other)?;
+                }
+                offset
+            }
+            Layout::Explicit { .. } => {
+                self.decorations
+                    .member_literal(ty, member, Decoration::Offset)
+                    .ok_or(TranspilerError::InvalidLayout(ty))?
+            }
+        };
+        if self.decorations.has_member(ty, member, Decoration::RowMajor) {
+            return Err(TranspilerError::UnsupportedDecoration(Decoration::RowMajor));
+        }
+        let member_layout = match layout {
+            Layout::Explicit { .. } => {
+                let matrix_stride = self.decorations
+                    .member_literal(ty, member, Decoration::MatrixStride)
+                    .unwrap_or(0);
+                Layout::Explicit { matrix_stride: matrix_stride }
+            }
+            _ => layout,
+        };
+        Ok((offset, member_ty, member_layout))
+    }
+
+    /// Returns the number of Locations a variable of type `ty` occupies in the shader
+    /// interface.
+    pub fn locations(&self, ty: Word) -> Result<u32, TranspilerError> {
+        let locations = match *self.get(ty)? {
+            Type::Vector { component, count } => {
+                // 64 bit vectors with more than two components take two Locations
+                match *self.get(component)? {
+                    Type::Int { width: 64 } |
+                    Type::Float { width: 64 } if count > 2 => 2,
+                    _ => 1,
+                }
+            }
+            Type::Matrix { column: element, count } |
+            Type::Array { element, length: count } => count * self.locations(element)?,
+            Type::Struct { ref members } => {
+                let mut locations = 0;
+                for &member in members {
+                    locations += self.locations(member)?;
+                }
+                locations
+            }
+            _ => 1,
+        };
+        Ok(locations)
+    }
+
+    /// Returns the offset in bytes, type and layout of the member, column, element or
+    /// component `index` of `ty`, for access chains.
+    pub fn element(
+        &self,
+        ty: Word,
+        layout: Layout,
+        index: i64,
+    ) -> Result<(i64, Word, Layout), TranspilerError> {
+        match *self.get(ty)? {
+            Type::Struct { .. } => {
+                let (offset, member, layout) = self.member(ty, index as u32, layout)?;
+                Ok((offset as i64, member, layout))
+            }
+            Type::Matrix { column: element, .. } |
+            Type::Array { element, .. } |
+            Type::RuntimeArray { element } => {
+                Ok((index * self.stride(ty, layout)? as i64, element, layout))
+            }
+            Type::Vector { component, .. } => {
+                Ok((index * self.scalar_size(component)? as i64, component, layout))
+            }
+            _ => Err(TranspilerError::InvalidInstruction(Op::AccessChain)),
+        }
+    }
+
+    /// Size of a scalar, which vectors are made of without any padding.
+    fn scalar_size(&self, ty: Word) -> Result<u32, TranspilerError> {
+        self.size(ty, Layout::Natural)
+    }
+
+    /// Reads a value of type `ty` laid out according to `layout` from `address`.
+    ///
+    /// Unsafe because `address` has to point to enough readable memory. Panics if the layout
+    /// is invalid, which `Interpreter::new` already ruled out.
+    pub unsafe fn load(&self, address: usize, ty: Word, layout: Layout) -> Value {
+        match *self.expect(ty) {
+            Type::Bool => Value::Bool(ptr::read(address as *const u8) & 1 != 0),
+            Type::Int { width } => Value::Int(read_bits(address, width), width),
+            Type::Float { width } => {
+                let value = bits_to_float(read_bits(address, width), width);
+                Value::Float(value, width)
+            }
+            Type::Vector { component, count } => {
+                let size = self.scalar_size(component).expect("invalid layout") as usize;
+                Value::Composite(
+                    (0..count as usize)
+                        .map(|index| self.load(address + index * size, component, layout))
+                        .collect(),
+                )
+            }
+            Type::Matrix { count, .. } |
+            Type::Array { length: count, .. } => {
+                Value::Composite(
+                    (0..count)
+                        .map(|index| {
+                            let (offset, element, layout) =
+                                self.element(ty, layout, index as i64).expect("invalid layout");
+                            self.load(address + offset as usize, element, layout)
+                        })
+                        .collect(),
+                )
+            }
+            Type::Struct { ref members } => {
+                Value::Composite(
+                    (0..members.len() as u32)
+                        .map(|member| {
+                            let (offset, member, layout) =
+                                self.member(ty, member, layout).expect("invalid layout");
+                            self.load(address + offset as usize, member, layout)
+                        })
+                        .collect(),
+                )
+            }
+            Type::Opaque { size } => {
+                let mut bytes = vec![0; size as usize];
+                ptr::copy_nonoverlapping(address as *const u8, bytes.as_mut_ptr(), bytes.len());
+                Value::Opaque(bytes)
+            }
+            ref ty => panic!("cannot load a value of type {:?}", ty),
+        }
+    }
+
+    /// Writes `value` of type `ty` laid out according to `layout` to `address`, the inverse of
+    /// `load`.
+    pub unsafe fn store(&self, address: usize, value: &Value, ty: Word, layout: Layout) {
+        match (self.expect(ty), value) {
+            (&Type::Bool, &Value::Bool(value)) => ptr::write(address as *mut u8, value as u8),
+            (&Type::Int { width }, &Value::Int(bits, _)) => write_bits(address, bits, width),
+            (&Type::Float { width }, &Value::Float(value, _)) => {
+                write_bits(address, float_to_bits(value, width), width)
+            }
+            (&Type::Vector { component, .. }, &Value::Composite(ref components)) => {
+                let size = self.scalar_size(component).expect("invalid layout") as usize;
+                for (index, value) in components.iter().enumerate() {
+                    self.store(address + index * size, value, component, layout);
+                }
+            }
+            (&Type::Matrix { .. }, &Value::Composite(ref elements)) |
+            (&Type::Array { .. }, &Value::Composite(ref elements)) |
+            (&Type::Struct { .. }, &Value::Composite(ref elements)) => {
+                for (index, value) in elements.iter().enumerate() {
+                    let (offset, element, layout) =
+                        self.element(ty, layout, index as i64).expect("invalid layout");
+                    self.store(address + offset as usize, value, element, layout);
+                }
+            }
+            (&Type::Opaque { .. }, &Value::Opaque(ref bytes)) => {
+                ptr::copy_nonoverlapping(bytes.as_ptr(), address as *mut u8, bytes.len());
+            }
+            (ty, value) => panic!("cannot store {:?} as {:?}", value, ty),
+        }
+    }
+
+    /// Reinterprets the bits of `value` as type `ty`, for OpBitcast. Vectors are reinterpreted
+    /// as their components one after the other, the first one in the lowest bits.
+    pub fn bitcast(&self, value: &Value, ty: Word) -> Value {
+        let mut bytes = Vec::new();
+        to_bytes(value, &mut bytes);
+        self.read_bytes(&bytes, ty)
+    }
+
+    fn read_bytes(&self, bytes: &[u8], ty: Word) -> Value {
+        let bits = |width: u32| {
+            bytes[..width as usize / 8].iter().rev().fold(0u64, |bits, &byte| {
+                bits << 8 | u64::from(byte)
+            })
+        };
+        match *self.expect(ty) {
+            Type::Int { width } => Value::Int(bits(width), width),
+            Type::Float { width } => Value::Float(bits_to_float(bits(width), width), width),
+            Type::Vector { component, count } => {
+                let size = bytes.len() / count as usize;
+                Value::Composite(
+                    bytes
+                        .chunks(size)
+                        .map(|bytes| self.read_bytes(bytes, component))
+                        .collect(),
+                )
+            }
+            ref ty => panic!("cannot bitcast to {:?}", ty),
+        }
+    }
+}
+
+fn to_bytes(value: &Value, bytes: &mut Vec<u8>) {
+    let (bits, width) = match *value {
+        Value::Int(bits, width) => (bits, width),
+        Value::Float(value, width) => (float_to_bits(value, width), width),
+        Value::Composite(ref components) => {
+            for component in components {
+                to_bytes(component, bytes);
+            }
+            return;
+        }
+        ref value => panic!("cannot bitcast {:?}", value),
+    };
+    for byte in 0..width / 8 {
+        bytes.push((bits >> (byte * 8)) as u8);
+    }
+}
+
+unsafe fn read_bits(address: usize, width: u32) -> u64 {
+    match width {
+        8 => u64::from(ptr::read(address as *const u8)),
+        16 => u64::from(ptr::read_unaligned(address as *const u16)),
+        32 => u64::from(ptr::read_unaligned(address as *const u32)),
+        _ => ptr::read_unaligned(address as *const u64),
+    }
+}
+
+unsafe fn write_bits(address: usize, bits: u64, width: u32) {
+    match width {
+        8 => ptr::write(address as *mut u8, bits as u8),
+        16 => ptr::write_unaligned(address as *mut u16, bits as u16),
+        32 => ptr::write_unaligned(address as *mut u32, bits as u32),
+        _ => ptr::write_unaligned(address as *mut u64, bits),
+    }
+}
+
+pub fn bits_to_float(bits: u64, width: u32) -> f64 {
+    match width {
+        16 => f16_to_f32(bits as u16) as f64,
+        32 => f32::from_bits(bits as u32) as f64,
+        _ => f64::from_bits(bits),
+    }
+}
+
+pub fn float_to_bits(value: f64, width: u32) -> u64 {
+    match width {
+        16 => u64::from(f16_from_f32(value as f32)),
+        32 => u64::from((value as f32).to_bits()),
+        _ => value.to_bits(),
+    }
+}
@@ -0,0 +1,1062 @@
+//! An interpreter that runs SPIR-V modules directly, without compiling them.
+//!
+//! It follows the shader ABI of `abi` exactly like compiled code does, so the two can stand in
+//! for each other: the interpreter runs shaders where LLVM is not available, and is a reference
+//! to check compiled code against. Every invocation runs on its own, one instruction at a time.
+//!
+//! The Cranelift backend compiles from the analysis of `Interpreter::new`, so both accept the
+//! same modules.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use rspirv::mr::{Instruction, Module, Operand};
+use spirv_headers::*;
+
+use abi::{self, Invocation};
+use glsl_op;
+use trans::*;
+use validate::validate;
+use {EntryPoint, Location, SourceLine, TranspilerError};
+
+pub use self::memory::Layout;
+#[cfg(feature = "cranelift")]
+pub use self::value::{f16_from_f32, f16_to_f32};
+pub use self::value::Value;
+
+mod check;
+mod exec;
+mod glsl;
+mod memory;
+mod ops;
+mod value;
+
+/// A SPIR-V type, as far as the interpreter cares.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Type {
+    Void,
+    Bool,
+    Int { width: u32 },
+    Float { width: u32 },
+    Vector { component: Word, count: u32 },
+    /// A matrix, made of `count` column vectors.
+    Matrix { column: Word, count: u32 },
+    Array { element: Word, length: u32 },
+    RuntimeArray { element: Word },
+    Struct { members: Vec<Word> },
+    Pointer { pointee: Word },
+    Function,
+    /// Images, samplers and sampled images, whose descriptors take `size` bytes.
+    Opaque { size: u32 },
+}
+
+/// The types of a module along with the decorations that lay them out in memory.
+pub struct Types {
+    types: HashMap<Word, (Op, Type)>,
+    decorations: Decorations,
+}
+
+impl Types {
+    pub fn get(&self, id: Word) -> Result<&Type, TranspilerError> {
+        self.types.get(&id).map(|&(_, ref ty)| ty).ok_or(
+            TranspilerError::UndefinedId(id),
+        )
+    }
+
+    /// Like `get`, for types that are known to exist because the module was checked.
+    pub fn expect(&self, id: Word) -> &Type {
+        self.get(id).unwrap_or_else(|_| panic!("%{} is not a type", id))
+    }
+
+    /// Returns the instruction that declared the type `id`.
+    pub fn opcode(&self, id: Word) -> Result<Op, TranspilerError> {
+        self.types.get(&id).map(|&(opcode, _)| opcode).ok_or(
+            TranspilerError::UndefinedId(id),
+        )
+    }
+
+    /// Returns a value of type `id` with all bits 0, which is what variables without an
+    /// initializer start with.
+    pub fn zero(&self, id: Word) -> Value {
+        match *self.expect(id) {
+            Type::Bool => Value::Bool(false),
+            Type::Int { width } => Value::Int(0, width),
+            Type::Float { width } => Value::Float(0.0, width),
+            Type::Vector { component: element, count: length } |
+            Type::Matrix { column: element, count: length } |
+            Type::Array { element, length } => {
+                Value::Composite(vec![self.zero(element); length as usize])
+            }
+            Type::Struct { ref members } => {
+                Value::Composite(members.iter().map(|&member| self.zero(member)).collect())
+            }
+            Type::Pointer { pointee } => {
+                Value::Pointer(value::Pointer::Memory {
+                    address: 0,
+                    ty: pointee,
+                    layout: Layout::Natural,
+                })
+            }
+            Type::Opaque { size } => Value::Opaque(vec![0; size as usize]),
+            Type::Void | Type::RuntimeArray { .. } | Type::Function => Value::Composite(Vec::new()),
+        }
+    }
+}
+
+/// Where a module scope variable lives.
+#[derive(Clone, Debug)]
+pub enum Global {
+    /// The Private variable with the given index, which every invocation has a copy of.
+    Private(usize),
+    /// At a fixed offset in the memory of the workgroup, see `Resources::workgroup_memory`.
+    Workgroup { offset: usize, ty: Word },
+    /// At a fixed offset in the `Invocation`.
+    Invocation {
+        offset: usize,
+        ty: Word,
+        layout: Layout,
+    },
+    /// A block of built-in variables, such as gl_PerVertex. Holds the offset in the `Invocation`
+    /// of every member, or None if the built-in is not supported.
+    BuiltInBlock(Vec<Option<usize>>),
+    PushConstants { ty: Word },
+    /// Bound through a descriptor set. For buffers the descriptor is the address of the buffer,
+    /// for images and samplers it is the value of the variable.
+    Descriptor {
+        set: u32,
+        binding: u32,
+        buffer: bool,
+        ty: Word,
+        layout: Layout,
+    },
+}
+
+/// Extended instruction sets the interpreter understands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExtInstSet {
+    Glsl450,
+    /// Sets named `NonSemantic.*`, which do not change the meaning of a module.
+    NonSemantic,
+}
+
+/// A function of the module, with the index of every block by label.
+pub struct Function {
+    pub index: usize,
+    pub blocks: HashMap<Word, usize>,
+}
+
+/// A module prepared to be interpreted.
+///
+/// Everything the transpiler would reject is rejected up front, with the same errors, so a
+/// module either runs on both backends or on neither.
+pub struct Interpreter {
+    pub(crate) module: Arc<Module>,
+    pub(crate) types: Types,
+    ext_inst_sets: HashMap<Word, ExtInstSet>,
+    /// Values of all constants, specialization constants with their default value.
+    pub(crate) constants: HashMap<Word, Value>,
+    /// The type of every constant and module scope variable.
+    value_types: HashMap<Word, Word>,
+    pub(crate) globals: HashMap<Word, Global>,
+    /// Type and initializer of every Private variable.
+    pub(crate) private: Vec<(Word, Option<Value>)>,
+    /// Bytes of memory the Workgroup variables take up.
+    pub(crate) workgroup_memory: usize,
+    pub(crate) functions: HashMap<Word, Function>,
+    pub(crate) entry_points: HashMap<String, Word>,
+}
+
+impl Interpreter {
+    pub fn new(module: Arc<Module>) -> Result<Self, TranspilerError> {
+        validate(&module)?;
+        let mut lines = Lines::new(&module)?;
+        let mut interpreter = Interpreter {
+            module: module.clone(),
+            types: Types {
+                types: HashMap::new(),
+                decorations: Decorations::from_annotations(&module.annotations)?,
+            },
+            ext_inst_sets: HashMap::new(),
+            constants: HashMap::new(),
+            value_types: HashMap::new(),
+            globals: HashMap::new(),
+            private: Vec::new(),
+            workgroup_memory: 0,
+            functions: HashMap::new(),
+            entry_points: HashMap::new(),
+        };
+        for import in &module.ext_inst_imports {
+            let name = operand_str(import, 0).map_err(|error| error.at(lines.location(import)))?;
+            let set = if name == glsl_op::NAME {
+                ExtInstSet::Glsl450
+            } else if name.starts_with("NonSemantic.") {
+                ExtInstSet::NonSemantic
+            } else {
+                let error = TranspilerError::UnsupportedExtInstSet(name.to_owned());
+                return Err(error.at(lines.location(import)));
+            };
+            interpreter.ext_inst_sets.insert(result_id(import)?, set);
+        }
+
+        for inst in &module.types_global_values {
+            lines.track(inst)?;
+            interpreter.add_global_value(inst).map_err(
+                |error| error.at(lines.location(inst)),
+            )?;
+        }
+
+        for entry_point in &module.entry_points {
+            let name = operand_str(entry_point, 2)?.to_owned();
+            interpreter.entry_points.insert(name, operand_id(entry_point, 1)?);
+        }
+        for (index, function) in module.functions.iter().enumerate() {
+            let def = function.def.as_ref().ok_or(
+                TranspilerError::InvalidInstruction(Op::Function),
+            )?;
+            lines.line = None;
+            let blocks = interpreter.check_function(index, &mut lines).map_err(|error| {
+                lines.line = None;
+                error.at(lines.location(def))
+            })?;
+            interpreter.functions.insert(
+                result_id(def)?,
+                Function {
+                    index: index,
+                    blocks: blocks,
+                },
+            );
+        }
+        Ok(interpreter)
+    }
+
+    /// Returns the entry point that was declared with the given name.
+    pub fn entry_point<'a>(&'a self, name: &str) -> Option<EntryPoint<'a>> {
+        self.entry_points.get(name).map(|&function| {
+            EntryPoint::interpreted(self, function)
+        })
+    }
+
+    /// Runs one invocation of the entry point `function`.
+    ///
+    /// Unsafe because the shader accesses whatever memory its resources point to.
+    pub(crate) unsafe fn call(&self, function: Word, invocation: &mut Invocation) {
+        exec::run(self, function, invocation)
+    }
+
+    /// Collects a type, constant or global variable.
+    fn add_global_value(&mut self, inst: &Instruction) -> Result<(), TranspilerError> {
+        let opcode = inst.class.opcode;
+        match opcode {
+            Op::TypeVoid | Op::TypeBool | Op::TypeInt | Op::TypeFloat | Op::TypeVector |
+            Op::TypeMatrix | Op::TypeArray | Op::TypeRuntimeArray | Op::TypeStruct |
+            Op::TypePointer | Op::TypeFunction | Op::TypeImage | Op::TypeSampler |
+            Op::TypeSampledImage => {
+                let ty = self.new_type(inst)?;
+                self.types.types.insert(result_id(inst)?, (opcode, ty));
+            }
+            Op::ConstantTrue | Op::ConstantFalse | Op::Constant | Op::ConstantComposite |
+            Op::ConstantNull | Op::SpecConstantTrue | Op::SpecConstantFalse |
+            Op::SpecConstant | Op::SpecConstantComposite | Op::SpecConstantOp => {
+                let value = self.new_constant(inst)?;
+                self.constants.insert(result_id(inst)?, value);
+            }
+            Op::Variable => self.add_global_variable(inst)?,
+            Op::Undef => {
+                let value = self.types.zero(result_type(inst)?);
+                self.constants.insert(result_id(inst)?, value);
+            }
+            Op::Line | Op::NoLine => (),
+            Op::ExtInst if self.ext_inst_set(inst)? == ExtInstSet::NonSemantic => (),
+            _ => return Err(TranspilerError::UnsupportedInstruction(opcode)),
+        }
+        if let (Some(id), Some(ty)) = (inst.result_id, inst.result_type) {
+            self.value_types.insert(id, ty);
+        }
+        Ok(())
+    }
+
+    fn new_type(&self, inst: &Instruction) -> Result<Type, TranspilerError> {
+        // Make sure referenced types exist
+        let ty = |index: usize| {
+            operand_id(inst, index).and_then(|id| self.types.get(id).map(|_| id))
+        };
+        let ty = match inst.class.opcode {
+            Op::TypeVoid => Type::Void,
+            Op::TypeBool => Type::Bool,
+            Op::TypeInt => Type::Int { width: operand_u32(inst, 0)? },
+            Op::TypeFloat => {
+                match operand_u32(inst, 0)? {
+                    width @ 16 | width @ 32 | width @ 64 => Type::Float { width: width },
+                    _ => return Err(TranspilerError::UnsupportedType(Op::TypeFloat)),
+                }
+            }
+            Op::TypeVector => {
+                let component = ty(0)?;
+                let count = operand_u32(inst, 1)?;
+                let scalar = match *self.types.get(component)? {
+                    Type::Bool | Type::Int { .. } | Type::Float { .. } => true,
+                    _ => false,
+                };
+                if !scalar || !(2..=4).contains(&count) {
+                    return Err(TranspilerError::InvalidInstruction(Op::TypeVector));
+                }
+                Type::Vector {
+                    component: component,
+                    count: count,
+                }
+            }
+            Op::TypeMatrix => {
+                let column = ty(0)?;
+                let count = operand_u32(inst, 1)?;
+                if !self.types.is_column(column) || !(2..=4).contains(&count) {
+                    return Err(TranspilerError::InvalidInstruction(Op::TypeMatrix));
+                }
+                Type::Matrix {
+                    column: column,
+                    count: count,
+                }
+            }
+            Op::TypeArray => {
+                Type::Array {
+                    element: ty(0)?,
+                    length: self.constant_u32(operand_id(inst, 1)?)?,
+                }
+            }
+            Op::TypeRuntimeArray => Type::RuntimeArray { element: ty(0)? },
+            Op::TypeStruct => {
+                Type::Struct {
+                    members: (0..inst.operands.len())
+                        .map(&ty)
+                        .collect::<Result<_, _>>()?,
+                }
+            }
+            Op::TypePointer => {
+                let pointee = ty(1)?;
+                let layout = Layout::for_storage_class(operand_storage_class(inst, 0)?);
+                if layout != Layout::Natural {
+                    // Rejects types that cannot be laid out
+                    self.types.size(pointee, layout)?;
+                }
+                Type::Pointer { pointee: pointee }
+            }
+            Op::TypeFunction => Type::Function,
+            Op::TypeImage | Op::TypeSampler => Type::Opaque { size: 8 },
+            // An image and a sampler
+            Op::TypeSampledImage => Type::Opaque { size: 16 },
+            opcode => return Err(TranspilerError::UnsupportedType(opcode)),
+        };
+        Ok(ty)
+    }
+
+    /// Returns the value of the integer constant `id`, e.g. the length of an array.
+    fn constant_u32(&self, id: Word) -> Result<u32, TranspilerError> {
+        match self.constants.get(&id) {
+            Some(&Value::Int(bits, _)) => Ok(bits as u32),
+            Some(_) => Err(TranspilerError::InvalidInstruction(Op::Constant)),
+            None => Err(TranspilerError::UndefinedId(id)),
+        }
+    }
+
+    /// Returns the type of the constant or module scope variable `id`.
+    fn value_type(&self, id: Word) -> Result<Word, TranspilerError> {
+        self.value_types.get(&id).cloned().ok_or(TranspilerError::UndefinedId(id))
+    }
+
+    fn constant(&self, id: Word) -> Result<Value, TranspilerError> {
+        self.constants.get(&id).cloned().ok_or(
+            TranspilerError::UndefinedId(id),
+        )
+    }
+
+    /// Evaluates constants and specialization constants.
+    ///
+    /// Specialization constants take their default value, `specialize` replaces them with the
+    /// values of a pipeline beforehand.
+    fn new_constant(&self, inst: &Instruction) -> Result<Value, TranspilerError> {
+        let opcode = inst.class.opcode;
+        let ty = result_type(inst)?;
+        let value = match opcode {
+            Op::ConstantTrue | Op::SpecConstantTrue => Value::Bool(true),
+            Op::ConstantFalse | Op::SpecConstantFalse => Value::Bool(false),
+            Op::Constant | Op::SpecConstant => {
+                let (float, width) = match *self.types.get(ty)? {
+                    Type::Int { width } => (false, width),
+                    Type::Float { width } => (true, width),
+                    _ => return Err(TranspilerError::InvalidInstruction(opcode)),
+                };
+                match (inst.operands.get(0), float) {
+                    // Half floats are given by their bit pattern
+                    (Some(&Operand::LiteralInt32(bits)), true) => {
+                        Value::Float(memory::bits_to_float(u64::from(bits), width), width)
+                    }
+                    (Some(&Operand::LiteralInt32(bits)), false) => {
+                        Value::int(u64::from(bits), width)
+                    }
+                    (Some(&Operand::LiteralInt64(bits)), true) => {
+                        Value::Float(memory::bits_to_float(bits, width), width)
+                    }
+                    (Some(&Operand::LiteralInt64(bits)), false) => Value::int(bits, width),
+                    (Some(&Operand::LiteralFloat32(value)), _) => {
+                        Value::float(f64::from(value), width)
+                    }
+                    (Some(&Operand::LiteralFloat64(value)), _) => Value::float(value, width),
+                    _ => return Err(TranspilerError::InvalidInstruction(opcode)),
+                }
+            }
+            Op::ConstantComposite | Op::SpecConstantComposite => {
+                let types = operand_ids(inst, 0)?
+                    .into_iter()
+                    .map(|id| self.value_type(id))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if !self.types.check_construct(ty, &types) {
+                    return Err(TranspilerError::InvalidInstruction(opcode));
+                }
+                Value::Composite(
+                    operand_ids(inst, 0)?
+                        .into_iter()
+                        .map(|id| self.constant(id))
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            Op::ConstantNull => {
+                self.types.get(ty)?;
+                self.types.zero(ty)
+            }
+            Op::SpecConstantOp => {
+                let opcode = match inst.operands.get(0) {
+                    Some(&Operand::LiteralSpecConstantOpInteger(opcode)) => opcode,
+                    _ => return Err(TranspilerError::InvalidInstruction(opcode)),
+                };
+                if !ops::is_spec_constant_op(opcode) {
+                    return Err(TranspilerError::UnsupportedInstruction(opcode));
+                }
+                let mut args = Vec::new();
+                let mut types = Vec::new();
+                let mut literals = Vec::new();
+                for operand in &inst.operands[1..] {
+                    match *operand {
+                        Operand::IdRef(id) => {
+                            args.push(self.constant(id)?);
+                            types.push(self.value_type(id)?);
+                        }
+                        Operand::LiteralInt32(literal) => literals.push(literal),
+                        _ => return Err(TranspilerError::InvalidInstruction(inst.class.opcode)),
+                    }
+                }
+                if !self.types.check_pure(opcode, ty, &types, &literals) {
+                    return Err(TranspilerError::InvalidInstruction(inst.class.opcode));
+                }
+                self.types.evaluate(opcode, ty, &args, &literals).ok_or(
+                    TranspilerError::UnsupportedInstruction(opcode),
+                )?
+            }
+            _ => return Err(TranspilerError::UnsupportedInstruction(opcode)),
+        };
+        Ok(value)
+    }
+
+    /// Places a module scope OpVariable, like `SpirvTranspiler::trans_global_variable`.
+    fn add_global_variable(&mut self, inst: &Instruction) -> Result<(), TranspilerError> {
+        let id = result_id(inst)?;
+        let pointee = match *self.types.get(result_type(inst)?)? {
+            Type::Pointer { pointee } => pointee,
+            _ => return Err(TranspilerError::InvalidInstruction(Op::Variable)),
+        };
+        let storage_class = operand_storage_class(inst, 0)?;
+        let initializer = match inst.operands.get(1) {
+            Some(_) => {
+                let id = operand_id(inst, 1)?;
+                if !self.types.same(self.value_type(id)?, pointee) {
+                    return Err(TranspilerError::InvalidInstruction(Op::Variable));
+                }
+                Some(self.constant(id)?)
+            }
+            None => None,
+        };
+
+        let global = match storage_class {
+            StorageClass::Private => {
+                self.private.push((pointee, initializer));
+                Global::Private(self.private.len() - 1)
+            }
+            StorageClass::Workgroup => {
+                // The memory starts out zeroed, validate rejects other initializers. Every
+                // variable starts at a multiple of 16 bytes, which is enough for any type.
+                let offset = self.workgroup_memory;
+                let size = self.types.size(pointee, Layout::Natural)? as usize;
+                self.workgroup_memory += (size + 15) & !15;
+                Global::Workgroup {
+                    offset: offset,
+                    ty: pointee,
+                }
+            }
+            StorageClass::Input => self.io_variable(id, pointee, false)?,
+            StorageClass::Output => self.io_variable(id, pointee, true)?,
+            StorageClass::Uniform |
+            StorageClass::UniformConstant |
+            StorageClass::StorageBuffer => {
+                let decorations = &self.types.decorations;
+                let set = decorations.literal(id, Decoration::DescriptorSet).ok_or(
+                    TranspilerError::InvalidLayout(id),
+                )?;
+                let binding = decorations.literal(id, Decoration::Binding).ok_or(
+                    TranspilerError::InvalidLayout(id),
+                )?;
+                if abi::descriptor_set_offset(set).is_none() {
+                    return Err(TranspilerError::InvalidLayout(id));
+                }
+                Global::Descriptor {
+                    set: set,
+                    binding: binding,
+                    buffer: storage_class != StorageClass::UniformConstant,
+                    ty: pointee,
+                    layout: Layout::for_storage_class(storage_class),
+                }
+            }
+            StorageClass::PushConstant => Global::PushConstants { ty: pointee },
+            _ => return Err(TranspilerError::UnsupportedStorageClass(storage_class)),
+        };
+        self.globals.insert(id, global);
+        Ok(())
+    }
+
+    /// Places an Input or Output variable in the `Invocation`.
+    fn io_variable(
+        &self,
+        id: Word,
+        pointee: Word,
+        output: bool,
+    ) -> Result<Global, TranspilerError> {
+        let decorations = &self.types.decorations;
+        if let Some(builtin) = decorations.builtin(id) {
+            let offset = builtin_offset(builtin, output).ok_or(
+                TranspilerError::UnsupportedBuiltIn(builtin),
+            )?;
+            return Ok(Global::Invocation {
+                offset: offset,
+                ty: pointee,
+                layout: Layout::Natural,
+            });
+        }
+        let ty = self.types.get(pointee)?;
+        if let Type::Struct { ref members } = *ty {
+            if decorations.member_builtin(pointee, 0).is_some() {
+                let members = (0..members.len() as u32)
+                    .map(|member| {
+                        decorations.member_builtin(pointee, member).and_then(|builtin| {
+                            builtin_offset(builtin, output)
+                        })
+                    })
+                    .collect();
+                return Ok(Global::BuiltInBlock(members));
+            }
+        }
+
+        let location = decorations.literal(id, Decoration::Location).ok_or(
+            TranspilerError::InvalidLayout(id),
+        )?;
+        let component = decorations.literal(id, Decoration::Component).unwrap_or(0);
+        if location + self.types.locations(pointee)? > abi::MAX_LOCATIONS as u32 ||
+            component > 3
+        {
+            return Err(TranspilerError::InvalidLayout(id));
+        }
+        let offset = abi::location_offset(output, location).ok_or(
+            TranspilerError::InvalidLayout(id),
+        )? + 4 * component as usize;
+        // Scalars and vectors at the start of their slot are packed the same in both layouts
+        let layout = match *ty {
+            Type::Int { .. } | Type::Float { .. } | Type::Vector { .. } if component == 0 => {
+                Layout::Natural
+            }
+            _ => Layout::Location,
+        };
+        self.types.size(pointee, layout)?;
+        Ok(Global::Invocation {
+            offset: offset,
+            ty: pointee,
+            layout: layout,
+        })
+    }
+
+    pub(crate) fn ext_inst_set(&self, inst: &Instruction) -> Result<ExtInstSet, TranspilerError> {
+        let set = operand_id(inst, 0)?;
+        self.ext_inst_sets.get(&set).cloned().ok_or(
+            TranspilerError::UndefinedId(set),
+        )
+    }
+
+    /// Rejects functions with instructions the interpreter cannot run, and returns the index of
+    /// every block by its label.
+    ///
+    /// Everything that would go wrong while running is caught here, so that invocations never
+    /// have to fail: blocks end in a branch to a block of the function, every phi has a value
+    /// for each block that branches to it, ids are defined before they are used and operands
+    /// have the types their instructions compute with.
+    fn check_function(
+        &self,
+        index: usize,
+        lines: &mut Lines,
+    ) -> Result<HashMap<Word, usize>, TranspilerError> {
+        let function = &self.module.functions[index];
+        let return_type = function.def.as_ref().and_then(|def| def.result_type).ok_or(
+            TranspilerError::InvalidInstruction(Op::Function),
+        )?;
+        let mut blocks = HashMap::new();
+        // The block and position of every result, None for parameters
+        let mut defs = HashMap::new();
+        // The type of every result and parameter
+        let mut types = HashMap::new();
+        for parameter in &function.parameters {
+            defs.insert(result_id(parameter)?, None);
+            types.insert(result_id(parameter)?, result_type(parameter)?);
+        }
+        for (index, block) in function.basic_blocks.iter().enumerate() {
+            let label = block.label.as_ref().ok_or(
+                TranspilerError::InvalidInstruction(Op::Label),
+            )?;
+            blocks.insert(result_id(label)?, index);
+            for (position, inst) in block.instructions.iter().enumerate() {
+                if let Some(id) = inst.result_id {
+                    defs.insert(id, Some((index, position)));
+                }
+                if let (Some(id), Some(ty)) = (inst.result_id, inst.result_type) {
+                    types.insert(id, ty);
+                }
+            }
+        }
+        let mut successors = Vec::new();
+        for block in &function.basic_blocks {
+            let terminator = match block.instructions.last() {
+                Some(inst) if is_terminator(inst.class.opcode) => inst,
+                Some(inst) => return Err(TranspilerError::InvalidInstruction(inst.class.opcode)),
+                None => return Err(TranspilerError::InvalidInstruction(Op::Label)),
+            };
+            let targets = branch_targets(terminator)?
+                .into_iter()
+                .map(|target| {
+                    blocks.get(&target).cloned().ok_or(
+                        TranspilerError::UndefinedId(target),
+                    )
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            successors.push(targets);
+        }
+        let dominators = dominators(&successors);
+        let mut predecessors = vec![Vec::new(); successors.len()];
+        for (block, targets) in successors.iter().enumerate() {
+            if dominators[block].is_some() {
+                for &target in targets {
+                    predecessors[target].push(block);
+                }
+            }
+        }
+
+        // Whether the value `id` is there at `position` in `block`. Blocks that cannot be
+        // reached never run, so anything defined will do for them.
+        let defined = |id: Word, block: usize, position: usize| {
+            if self.constants.contains_key(&id) || self.globals.contains_key(&id) {
+                return true;
+            }
+            match defs.get(&id) {
+                Some(&Some((def_block, def_position))) => {
+                    if dominators[block].is_none() {
+                        true
+                    } else if def_block == block {
+                        def_position < position
+                    } else {
+                        dominates(&dominators, def_block, block)
+                    }
+                }
+                Some(&None) => true,
+                None => false,
+            }
+        };
+        for (index, block) in function.basic_blocks.iter().enumerate() {
+            // OpLine only reaches to the end of its block
+            lines.line = None;
+            for (position, inst) in block.instructions.iter().enumerate() {
+                let checked = lines.track(inst).and_then(|()| {
+                    self.check_instruction(inst, &types, return_type)
+                });
+                let checked = checked.and_then(|()| {
+                    if inst.class.opcode != Op::Phi {
+                        let mut operands = value_operands(inst);
+                        return match operands.find(|&id| !defined(id, index, position)) {
+                            Some(id) => Err(TranspilerError::UndefinedId(id)),
+                            None => Ok(()),
+                        };
+                    }
+                    // The entry block is not branched to, its phis would have no values
+                    if index == 0 {
+                        return Err(TranspilerError::InvalidInstruction(Op::Phi));
+                    }
+                    let mut parents = Vec::new();
+                    for pair in inst.operands.chunks(2) {
+                        let (value, parent) = match *pair {
+                            [Operand::IdRef(value), Operand::IdRef(parent)] => (value, parent),
+                            _ => return Err(TranspilerError::InvalidInstruction(Op::Phi)),
+                        };
+                        // The value is used at the end of the block the branch comes from
+                        let parent = *blocks.get(&parent).ok_or(
+                            TranspilerError::UndefinedId(parent),
+                        )?;
+                        let end = function.basic_blocks[parent].instructions.len();
+                        if !defined(value, parent, end) {
+                            return Err(TranspilerError::UndefinedId(value));
+                        }
+                        parents.push(parent);
+                    }
+                    if predecessors[index].iter().any(|parent| !parents.contains(parent)) {
+                        return Err(TranspilerError::InvalidInstruction(Op::Phi));
+                    }
+                    Ok(())
+                });
+                checked.map_err(|error| error.at(lines.location(inst)))?;
+            }
+        }
+        Ok(blocks)
+    }
+
+    /// Checks an instruction of a function whose results and parameters have the types `types`
+    /// and which returns `return_type`.
+    ///
+    /// Operands have to have the types the instruction computes with, since the values of
+    /// invocations are not checked again while they run.
+    fn check_instruction(
+        &self,
+        inst: &Instruction,
+        types: &HashMap<Word, Word>,
+        return_type: Word,
+    ) -> Result<(), TranspilerError> {
+        let opcode = inst.class.opcode;
+        if !exec::is_supported(opcode) {
+            return Err(TranspilerError::UnsupportedInstruction(opcode));
+        }
+        if let Some(ty) = inst.result_type {
+            self.types.get(ty)?;
+        }
+        let type_of = |id: Word| match types.get(&id) {
+            Some(&ty) => Ok(ty),
+            None => self.value_type(id),
+        };
+        let pointee = |id: Word| {
+            self.types.pointee(type_of(id)?).ok_or(TranspilerError::InvalidInstruction(opcode))
+        };
+        let valid = match opcode {
+            Op::ExtInst if self.ext_inst_set(inst)? == ExtInstSet::Glsl450 => {
+                let instruction = operand_u32(inst, 1)?;
+                if !glsl_op::is_known(instruction) {
+                    return Err(TranspilerError::UnsupportedExtInst(
+                        glsl_op::NAME.to_owned(),
+                        instruction,
+                    ));
+                }
+                let args = operand_ids(inst, 2)?;
+                if args.len() < glsl_op::operand_count(instruction) {
+                    return Err(TranspilerError::InvalidInstruction(opcode));
+                }
+                let args = args.into_iter().map(&type_of).collect::<Result<Vec<_>, _>>()?;
+                self.types.check_glsl(instruction, result_type(inst)?, &args)
+            }
+            Op::BranchConditional => {
+                match *self.types.get(type_of(operand_id(inst, 0)?)?)? {
+                    Type::Bool => true,
+                    _ => false,
+                }
+            }
+            Op::Switch => {
+                let valid = inst.operands.len() % 2 == 0 &&
+                    inst.operands[2..].chunks(2).all(|case| match *case {
+                        [Operand::LiteralInt32(_), Operand::IdRef(_)] |
+                        [Operand::LiteralInt64(_), Operand::IdRef(_)] => true,
+                        _ => false,
+                    });
+                valid &&
+                    match *self.types.get(type_of(operand_id(inst, 0)?)?)? {
+                        Type::Int { .. } => true,
+                        _ => false,
+                    }
+            }
+            Op::ReturnValue => self.types.same(type_of(operand_id(inst, 0)?)?, return_type),
+            Op::Phi => {
+                let ty = result_type(inst)?;
+                let mut valid = true;
+                for &id in operand_ids(inst, 0)?.iter().step_by(2) {
+                    valid &= self.types.same(type_of(id)?, ty);
+                }
+                valid
+            }
+            Op::FunctionCall => {
+                let callee = operand_id(inst, 0)?;
+                let callee = self.module
+                    .functions
+                    .iter()
+                    .find(|function| {
+                        function.def.as_ref().and_then(|def| def.result_id) == Some(callee)
+                    })
+                    .ok_or(TranspilerError::UndefinedId(callee))?;
+                let args = operand_ids(inst, 1)?;
+                let ty = result_type(inst)?;
+                let returns = callee.def.as_ref().and_then(|def| def.result_type);
+                let mut valid = args.len() == callee.parameters.len() &&
+                    returns.map_or(false, |returns| self.types.same(returns, ty));
+                for (&arg, parameter) in args.iter().zip(&callee.parameters) {
+                    valid &= self.types.same(type_of(arg)?, result_type(parameter)?);
+                }
+                valid
+            }
+            Op::Variable => {
+                let pointee = self.types.pointee(result_type(inst)?).ok_or(
+                    TranspilerError::InvalidInstruction(opcode),
+                )?;
+                match inst.operands.get(1) {
+                    Some(_) => self.types.same(type_of(operand_id(inst, 1)?)?, pointee),
+                    None => true,
+                }
+            }
+            Op::Load | Op::Store | Op::CopyMemory => {
+                // Arrays of buffers only make sense to index into
+                for &id in &operand_ids(inst, 0)? {
+                    if self.is_buffer_array(id) {
+                        return Err(TranspilerError::UnsupportedInstruction(opcode));
+                    }
+                }
+                let target = pointee(operand_id(inst, 0)?)?;
+                match opcode {
+                    Op::Load => self.types.same(target, result_type(inst)?),
+                    Op::Store => self.types.same(type_of(operand_id(inst, 1)?)?, target),
+                    _ => self.types.same(pointee(operand_id(inst, 1)?)?, target),
+                }
+            }
+            Op::AccessChain | Op::InBoundsAccessChain => {
+                // Members of built-in blocks have to be supported where they are used
+                let base = operand_id(inst, 0)?;
+                if let Some(&Global::BuiltInBlock(ref members)) = self.globals.get(&base) {
+                    let member = self.constant_u32(operand_id(inst, 1)?)?;
+                    if members.get(member as usize).cloned().and_then(|offset| offset).is_none() {
+                        let pointee = self.pointee(base)?;
+                        let decorations = &self.types.decorations;
+                        return Err(match decorations.member_builtin(pointee, member) {
+                            Some(builtin) => TranspilerError::UnsupportedBuiltIn(builtin),
+                            None => TranspilerError::InvalidInstruction(opcode),
+                        });
+                    }
+                }
+                if self.is_buffer_array(base) && inst.operands.len() < 2 {
+                    return Err(TranspilerError::InvalidInstruction(opcode));
+                }
+                pointee(base)?;
+                let mut valid = true;
+                for &index in &operand_ids(inst, 1)? {
+                    valid &= match *self.types.get(type_of(index)?)? {
+                        Type::Int { .. } => true,
+                        _ => false,
+                    };
+                }
+                valid
+            }
+            _ if ops::is_pure(opcode) => {
+                let mut args = Vec::new();
+                let mut literals = Vec::new();
+                for operand in &inst.operands {
+                    match *operand {
+                        Operand::IdRef(id) => args.push(type_of(id)?),
+                        Operand::LiteralInt32(literal) => literals.push(literal),
+                        _ => return Err(TranspilerError::InvalidInstruction(opcode)),
+                    }
+                }
+                self.types.check_pure(opcode, result_type(inst)?, &args, &literals)
+            }
+            _ => true,
+        };
+        if !valid {
+            return Err(TranspilerError::InvalidInstruction(opcode));
+        }
+        Ok(())
+    }
+
+    /// Whether `id` is a variable with a descriptor for every buffer of an array.
+    fn is_buffer_array(&self, id: Word) -> bool {
+        match self.globals.get(&id) {
+            Some(&Global::Descriptor { buffer: true, ty, .. }) => {
+                match self.types.get(ty) {
+                    Ok(&Type::Array { .. }) | Ok(&Type::RuntimeArray { .. }) => true,
+                    _ => false,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the pointee type of the global variable `id`.
+    pub(crate) fn pointee(&self, id: Word) -> Result<Word, TranspilerError> {
+        let variable = self.module
+            .types_global_values
+            .iter()
+            .find(|inst| inst.result_id == Some(id))
+            .ok_or(TranspilerError::UndefinedId(id))?;
+        match *self.types.get(result_type(variable)?)? {
+            Type::Pointer { pointee } => Ok(pointee),
+            _ => Err(TranspilerError::InvalidInstruction(Op::Variable)),
+        }
+    }
+}
+
+/// Keeps track of the source position of instructions for errors, see
+/// `SpirvTranspiler::track_line`.
+pub struct Lines {
+    strings: HashMap<Word, String>,
+    pub line: Option<SourceLine>,
+}
+
+impl Lines {
+    /// Collects the file names of `module`.
+    pub fn new(module: &Module) -> Result<Self, TranspilerError> {
+        let mut strings = HashMap::new();
+        for debug in &module.debugs {
+            if debug.class.opcode == Op::String {
+                strings.insert(result_id(debug)?, operand_str(debug, 0)?.to_owned());
+            }
+        }
+        Ok(Lines {
+            strings: strings,
+            line: None,
+        })
+    }
+
+    pub fn track(&mut self, inst: &Instruction) -> Result<(), TranspilerError> {
+        match inst.class.opcode {
+            Op::Line => {
+                let file = operand_id(inst, 0)?;
+                self.line = Some(SourceLine {
+                    file: self.strings.get(&file).cloned(),
+                    line: operand_u32(inst, 1)?,
+                    column: operand_u32(inst, 2)?,
+                });
+            }
+            Op::NoLine => self.line = None,
+            _ => (),
+        }
+        Ok(())
+    }
+
+    pub fn location(&self, inst: &Instruction) -> Location {
+        Location {
+            opcode: inst.class.opcode,
+            result_id: inst.result_id,
+            line: self.line.clone(),
+        }
+    }
+}
+
+/// Returns the offset of a built-in variable in the `Invocation`.
+fn builtin_offset(builtin: BuiltIn, output: bool) -> Option<usize> {
+    if builtin == BuiltIn::SampleMask {
+        Some(abi::sample_mask_offset(output))
+    } else {
+        abi::builtin_offset(builtin)
+    }
+}
+
+/// The ids `inst` uses as values, leaving out labels, functions and extended instruction sets.
+fn value_operands<'a>(inst: &'a Instruction) -> impl Iterator<Item = Word> + 'a {
+    let operands = match inst.class.opcode {
+        Op::Line | Op::Branch | Op::LoopMerge | Op::SelectionMerge => &[],
+        Op::BranchConditional | Op::Switch => &inst.operands[..1],
+        Op::FunctionCall | Op::ExtInst => &inst.operands[1..],
+        _ => &inst.operands[..],
+    };
+    operands.iter().filter_map(|operand| match *operand {
+        Operand::IdRef(id) => Some(id),
+        _ => None,
+    })
+}
+
+/// Returns the immediate dominator of every block of the graph `successors`, whose entry is
+/// block 0 and dominates itself. None for blocks that cannot be reached.
+fn dominators(successors: &[Vec<usize>]) -> Vec<Option<usize>> {
+    let count = successors.len();
+    let mut dominators = vec![None; count];
+    if count == 0 {
+        return dominators;
+    }
+
+    // Reverse postorder, every block comes after its dominators
+    let mut order = Vec::with_capacity(count);
+    let mut visited = vec![false; count];
+    let mut stack = vec![(0, 0)];
+    visited[0] = true;
+    while let Some(&(block, next)) = stack.last() {
+        match successors[block].get(next).cloned() {
+            Some(successor) => {
+                let top = stack.len() - 1;
+                stack[top].1 += 1;
+                if !visited[successor] {
+                    visited[successor] = true;
+                    stack.push((successor, 0));
+                }
+            }
+            None => {
+                order.push(block);
+                stack.pop();
+            }
+        }
+    }
+    order.reverse();
+    let mut rank = vec![0; count];
+    let mut predecessors = vec![Vec::new(); count];
+    for (index, &block) in order.iter().enumerate() {
+        rank[block] = index;
+        for &successor in &successors[block] {
+            predecessors[successor].push(block);
+        }
+    }
+
+    // "A Simple, Fast Dominance Algorithm" by Cooper, Harvey and Kennedy
+    dominators[0] = Some(0);
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &block in &order[1..] {
+            let mut dominator: Option<usize> = None;
+            for &predecessor in &predecessors[block] {
+                if dominators[predecessor].is_none() {
+                    continue;
+                }
+                dominator = Some(match dominator {
+                    None => predecessor,
+                    Some(mut other) => {
+                        let mut predecessor = predecessor;
+                        while predecessor != other {
+                            while rank[predecessor] > rank[other] {
+                                predecessor = dominators[predecessor].unwrap_or(0);
+                            }
+                            while rank[other] > rank[predecessor] {
+                                other = dominators[other].unwrap_or(0);
+                            }
+                        }
+                        other
+                    }
+                });
+            }
+            if dominators[block] != dominator {
+                dominators[block] = dominator;
+                changed = true;
+            }
+        }
+    }
+    dominators
+}
+
+/// Whether every path to block `b` goes through block `a`.
+fn dominates(dominators: &[Option<usize>], a: usize, mut b: usize) -> bool {
+    loop {
+        if a == b {
+            return true;
+        }
+        match dominators[b] {
+            Some(dominator) if dominator != b => b = dominator,
+            _ => return false,
+        }
+    }
+}
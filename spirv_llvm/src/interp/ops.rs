@@ -0,0 +1,510 @@
+//! Instructions whose result only depends on the values of their operands.
+use std::cmp::Ordering;
+
+use spirv_headers::{Op, Word};
+
+use super::value::{map, mask, sign_extend, zip, zip3, Value};
+use super::{Type, Types};
+
+/// Whether OpSpecConstantOp supports `opcode`, the same set the transpiler can fold.
+pub fn is_spec_constant_op(opcode: Op) -> bool {
+    match opcode {
+        Op::IAdd | Op::ISub | Op::IMul | Op::UDiv | Op::SDiv | Op::UMod | Op::SRem |
+        Op::ShiftRightLogical | Op::ShiftRightArithmetic | Op::ShiftLeftLogical |
+        Op::BitwiseOr | Op::LogicalOr | Op::BitwiseXor | Op::LogicalNotEqual |
+        Op::BitwiseAnd | Op::LogicalAnd | Op::Not | Op::LogicalNot | Op::SNegate |
+        Op::LogicalEqual | Op::Select | Op::IEqual | Op::INotEqual | Op::ULessThan |
+        Op::SLessThan | Op::UGreaterThan | Op::SGreaterThan | Op::ULessThanEqual |
+        Op::SLessThanEqual | Op::UGreaterThanEqual | Op::SGreaterThanEqual | Op::UConvert |
+        Op::SConvert | Op::CompositeExtract | Op::CompositeInsert | Op::VectorShuffle => true,
+        _ => false,
+    }
+}
+
+/// Whether `opcode` computes its result from its operands alone, so that `Types::evaluate`
+/// takes care of it.
+pub fn is_pure(opcode: Op) -> bool {
+    match opcode {
+        // Arithmetic
+        Op::IAdd | Op::FAdd | Op::ISub | Op::FSub | Op::IMul | Op::FMul | Op::UDiv | Op::SDiv |
+        Op::FDiv | Op::UMod | Op::SRem | Op::SMod | Op::FRem | Op::FMod | Op::SNegate |
+        Op::FNegate | Op::VectorTimesScalar | Op::MatrixTimesScalar | Op::VectorTimesMatrix |
+        Op::MatrixTimesVector | Op::MatrixTimesMatrix | Op::OuterProduct | Op::Dot |
+        Op::IAddCarry | Op::ISubBorrow | Op::UMulExtended | Op::SMulExtended |
+        // Bits
+        Op::ShiftRightLogical | Op::ShiftRightArithmetic | Op::ShiftLeftLogical |
+        Op::BitwiseOr | Op::BitwiseXor | Op::BitwiseAnd | Op::Not | Op::BitFieldInsert |
+        Op::BitFieldSExtract | Op::BitFieldUExtract | Op::BitReverse | Op::BitCount |
+        // Logic
+        Op::LogicalOr | Op::LogicalAnd | Op::LogicalEqual | Op::LogicalNotEqual |
+        Op::LogicalNot | Op::Select | Op::Any | Op::All | Op::IsNan | Op::IsInf | Op::IEqual |
+        Op::INotEqual | Op::UGreaterThan | Op::SGreaterThan | Op::UGreaterThanEqual |
+        Op::SGreaterThanEqual | Op::ULessThan | Op::SLessThan | Op::ULessThanEqual |
+        Op::SLessThanEqual | Op::FOrdEqual | Op::FUnordEqual | Op::FOrdNotEqual |
+        Op::FUnordNotEqual | Op::FOrdLessThan | Op::FUnordLessThan | Op::FOrdGreaterThan |
+        Op::FUnordGreaterThan | Op::FOrdLessThanEqual | Op::FUnordLessThanEqual |
+        Op::FOrdGreaterThanEqual | Op::FUnordGreaterThanEqual | Op::Ordered | Op::Unordered |
+        // Conversion
+        Op::ConvertFToU | Op::ConvertFToS | Op::ConvertSToF | Op::ConvertUToF | Op::UConvert |
+        Op::SConvert | Op::FConvert | Op::QuantizeToF16 | Op::Bitcast |
+        // Composites
+        Op::VectorExtractDynamic | Op::VectorInsertDynamic | Op::VectorShuffle |
+        Op::CompositeConstruct | Op::CompositeExtract | Op::CompositeInsert | Op::CopyObject |
+        Op::Transpose => true,
+        _ => false,
+    }
+}
+
+impl Types {
+    /// Evaluates `opcode` with result type `ty`. `args` are the values of the id operands of
+    /// the instruction and `literals` its literal operands. Returns None if the result of
+    /// `opcode` does not only depend on its operands.
+    pub fn evaluate(&self, opcode: Op, ty: Word, args: &[Value], literals: &[u32]) -> Option<Value> {
+        let arg = |index: usize| {
+            args.get(index).unwrap_or_else(|| {
+                panic!("missing operand {} of Op{:?}", index, opcode)
+            })
+        };
+        let value = match opcode {
+            // Integers
+            Op::IAdd => int_op(arg(0), arg(1), &mut |a, b| a.wrapping_add(b)),
+            Op::ISub => int_op(arg(0), arg(1), &mut |a, b| a.wrapping_sub(b)),
+            Op::IMul => int_op(arg(0), arg(1), &mut |a, b| a.wrapping_mul(b)),
+            // Division by zero is undefined, so any result will do
+            Op::UDiv => int_op(arg(0), arg(1), &mut |a, b| a.checked_div(b).unwrap_or(0)),
+            Op::UMod => int_op(arg(0), arg(1), &mut |a, b| a.checked_rem(b).unwrap_or(0)),
+            Op::SDiv => signed_op(arg(0), arg(1), &mut |a, b| a.checked_div(b).unwrap_or(0)),
+            Op::SRem => signed_op(arg(0), arg(1), &mut |a, b| a.checked_rem(b).unwrap_or(0)),
+            Op::SMod => {
+                // The remainder takes the sign of the divisor
+                signed_op(arg(0), arg(1), &mut |a, b| {
+                    let rem = a.checked_rem(b).unwrap_or(0);
+                    if rem != 0 && (rem ^ b) < 0 { rem + b } else { rem }
+                })
+            }
+            Op::SNegate => map(arg(0), &mut |a| Value::int(a.bits().wrapping_neg(), a.width())),
+            Op::IAddCarry => {
+                extended(arg(0), arg(1), &mut |a, b, width| {
+                    let sum = a.wrapping_add(b) & mask(width);
+                    (sum, (sum < a) as u64)
+                })
+            }
+            Op::ISubBorrow => {
+                extended(arg(0), arg(1), &mut |a, b, _| (a.wrapping_sub(b), (a < b) as u64))
+            }
+            Op::UMulExtended => {
+                extended(arg(0), arg(1), &mut |a, b, width| {
+                    let product = u128::from(a) * u128::from(b);
+                    (product as u64, (product >> width) as u64)
+                })
+            }
+            Op::SMulExtended => {
+                extended(arg(0), arg(1), &mut |a, b, width| {
+                    let a = i128::from(sign_extend(a, width));
+                    let b = i128::from(sign_extend(b, width));
+                    let product = a * b;
+                    (product as u64, (product >> width) as u64)
+                })
+            }
+
+            // Floats
+            Op::FAdd => float_op(arg(0), arg(1), &mut |a, b| a + b),
+            Op::FSub => float_op(arg(0), arg(1), &mut |a, b| a - b),
+            Op::FMul => float_op(arg(0), arg(1), &mut |a, b| a * b),
+            Op::FDiv => float_op(arg(0), arg(1), &mut |a, b| a / b),
+            Op::FRem => float_op(arg(0), arg(1), &mut |a, b| a % b),
+            Op::FMod => {
+                // The remainder takes the sign of the divisor
+                float_op(arg(0), arg(1), &mut |a, b| {
+                    let rem = a % b;
+                    if rem != 0.0 && (rem < 0.0) != (b < 0.0) { rem + b } else { rem }
+                })
+            }
+            Op::FNegate => map(arg(0), &mut |a| Value::Float(-a.to_f64(), a.width())),
+            Op::VectorTimesScalar => {
+                let scalar = arg(1);
+                map(arg(0), &mut |a| multiply(a, scalar))
+            }
+            Op::MatrixTimesScalar => {
+                let scalar = arg(1);
+                map(arg(0), &mut |a| multiply(a, scalar))
+            }
+            Op::VectorTimesMatrix => {
+                let vector = arg(0);
+                Value::Composite(
+                    arg(1)
+                        .components()
+                        .iter()
+                        .map(|column| dot(vector, column))
+                        .collect(),
+                )
+            }
+            Op::MatrixTimesVector => matrix_times_vector(arg(0), arg(1)),
+            Op::MatrixTimesMatrix => {
+                let matrix = arg(0);
+                Value::Composite(
+                    arg(1)
+                        .components()
+                        .iter()
+                        .map(|column| matrix_times_vector(matrix, column))
+                        .collect(),
+                )
+            }
+            Op::OuterProduct => {
+                let a = arg(0);
+                Value::Composite(
+                    arg(1)
+                        .components()
+                        .iter()
+                        .map(|b| map(a, &mut |a| multiply(a, b)))
+                        .collect(),
+                )
+            }
+            Op::Dot => dot(arg(0), arg(1)),
+
+            // Bits
+            Op::ShiftRightLogical | Op::ShiftRightArithmetic | Op::ShiftLeftLogical => {
+                zip(arg(0), arg(1), &mut |base, shift| {
+                    let width = base.width();
+                    // The shift amount may have a different width than the base
+                    let shift = shift.bits() & mask(width);
+                    let bits = base.bits();
+                    let result = match opcode {
+                        _ if shift >= u64::from(width) => {
+                            // Undefined, but the sign is a sensible result for arithmetic shifts
+                            if opcode == Op::ShiftRightArithmetic && base.signed() < 0 {
+                                !0
+                            } else {
+                                0
+                            }
+                        }
+                        Op::ShiftRightLogical => bits >> shift,
+                        Op::ShiftRightArithmetic => (base.signed() >> shift) as u64,
+                        _ => bits << shift,
+                    };
+                    Value::int(result, width)
+                })
+            }
+            Op::BitwiseOr => int_op(arg(0), arg(1), &mut |a, b| a | b),
+            Op::BitwiseXor => int_op(arg(0), arg(1), &mut |a, b| a ^ b),
+            Op::BitwiseAnd => int_op(arg(0), arg(1), &mut |a, b| a & b),
+            Op::Not => map(arg(0), &mut |a| Value::int(!a.bits(), a.width())),
+            Op::BitFieldInsert => {
+                let (offset, count) = (arg(2).bits(), arg(3).bits());
+                zip(arg(0), arg(1), &mut |base, insert| {
+                    if count == 0 {
+                        return base.clone();
+                    }
+                    let field = low_bits(count).wrapping_shl(offset as u32);
+                    let bits = (base.bits() & !field) | (insert.bits().wrapping_shl(offset as u32) & field);
+                    Value::int(bits, base.width())
+                })
+            }
+            Op::BitFieldSExtract | Op::BitFieldUExtract => {
+                let (offset, count) = (arg(1).bits(), arg(2).bits());
+                map(arg(0), &mut |base| {
+                    let width = base.width();
+                    if count == 0 {
+                        return Value::Int(0, width);
+                    }
+                    let field = base.bits().wrapping_shr(offset as u32) & low_bits(count);
+                    let bits = if opcode == Op::BitFieldSExtract {
+                        sign_extend(field, count as u32) as u64
+                    } else {
+                        field
+                    };
+                    Value::int(bits, width)
+                })
+            }
+            Op::BitReverse => {
+                map(arg(0), &mut |a| {
+                    Value::Int(a.bits().reverse_bits() >> (64 - a.width()), a.width())
+                })
+            }
+            Op::BitCount => {
+                let width = self.scalar_width(ty);
+                map(arg(0), &mut |a| Value::Int(u64::from(a.bits().count_ones()), width))
+            }
+
+            // Logic
+            Op::LogicalOr => bool_op(arg(0), arg(1), &mut |a, b| a || b),
+            Op::LogicalAnd => bool_op(arg(0), arg(1), &mut |a, b| a && b),
+            Op::LogicalEqual => bool_op(arg(0), arg(1), &mut |a, b| a == b),
+            Op::LogicalNotEqual => bool_op(arg(0), arg(1), &mut |a, b| a != b),
+            Op::LogicalNot => map(arg(0), &mut |a| Value::Bool(!a.to_bool())),
+            Op::Select => {
+                match *arg(0) {
+                    Value::Bool(condition) => {
+                        if condition { arg(1).clone() } else { arg(2).clone() }
+                    }
+                    // Vector conditions select component by component
+                    ref condition => {
+                        zip3(condition, arg(1), arg(2), &mut |condition, a, b| {
+                            if condition.to_bool() { a.clone() } else { b.clone() }
+                        })
+                    }
+                }
+            }
+            Op::Any => Value::Bool(arg(0).components().iter().any(Value::to_bool)),
+            Op::All => Value::Bool(arg(0).components().iter().all(Value::to_bool)),
+            Op::IsNan => map(arg(0), &mut |a| Value::Bool(a.to_f64().is_nan())),
+            Op::IsInf => map(arg(0), &mut |a| Value::Bool(a.to_f64().is_infinite())),
+            Op::IEqual | Op::INotEqual | Op::UGreaterThan | Op::UGreaterThanEqual |
+            Op::ULessThan | Op::ULessThanEqual => {
+                zip(arg(0), arg(1), &mut |a, b| {
+                    Value::Bool(compare(opcode, a.bits().cmp(&b.bits())))
+                })
+            }
+            Op::SGreaterThan | Op::SGreaterThanEqual | Op::SLessThan | Op::SLessThanEqual => {
+                zip(arg(0), arg(1), &mut |a, b| {
+                    Value::Bool(compare(opcode, a.signed().cmp(&b.signed())))
+                })
+            }
+            Op::FOrdEqual | Op::FUnordEqual | Op::FOrdNotEqual | Op::FUnordNotEqual |
+            Op::FOrdLessThan | Op::FUnordLessThan | Op::FOrdGreaterThan |
+            Op::FUnordGreaterThan | Op::FOrdLessThanEqual | Op::FUnordLessThanEqual |
+            Op::FOrdGreaterThanEqual | Op::FUnordGreaterThanEqual | Op::Ordered |
+            Op::Unordered => {
+                zip(arg(0), arg(1), &mut |a, b| {
+                    Value::Bool(compare_floats(opcode, a.to_f64(), b.to_f64()))
+                })
+            }
+
+            // Conversion
+            Op::ConvertFToU => {
+                let width = self.scalar_width(ty);
+                map(arg(0), &mut |a| Value::int(a.to_f64() as u64, width))
+            }
+            Op::ConvertFToS => {
+                let width = self.scalar_width(ty);
+                map(arg(0), &mut |a| Value::int(a.to_f64() as i64 as u64, width))
+            }
+            Op::ConvertSToF => {
+                let width = self.scalar_width(ty);
+                map(arg(0), &mut |a| {
+                    let value = match width {
+                        64 => a.signed() as f64,
+                        _ => f64::from(a.signed() as f32),
+                    };
+                    Value::float(value, width)
+                })
+            }
+            Op::ConvertUToF => {
+                let width = self.scalar_width(ty);
+                map(arg(0), &mut |a| {
+                    let value = match width {
+                        64 => a.bits() as f64,
+                        _ => f64::from(a.bits() as f32),
+                    };
+                    Value::float(value, width)
+                })
+            }
+            Op::UConvert => {
+                let width = self.scalar_width(ty);
+                map(arg(0), &mut |a| Value::int(a.bits(), width))
+            }
+            Op::SConvert => {
+                let width = self.scalar_width(ty);
+                map(arg(0), &mut |a| Value::int(a.signed() as u64, width))
+            }
+            Op::FConvert => {
+                let width = self.scalar_width(ty);
+                map(arg(0), &mut |a| Value::float(a.to_f64(), width))
+            }
+            Op::QuantizeToF16 => {
+                map(arg(0), &mut |a| {
+                    Value::float(Value::float(a.to_f64(), 16).to_f64(), a.width())
+                })
+            }
+            Op::Bitcast => self.bitcast(arg(0), ty),
+
+            // Composites
+            Op::VectorExtractDynamic => {
+                let components = arg(0).components();
+                match components.get(arg(1).bits() as usize) {
+                    Some(component) => component.clone(),
+                    // Out of bounds reads are undefined
+                    None => components[0].zeroed(),
+                }
+            }
+            Op::VectorInsertDynamic => {
+                let mut vector = arg(0).clone();
+                if let Value::Composite(ref mut components) = vector {
+                    if let Some(component) = components.get_mut(arg(2).bits() as usize) {
+                        *component = arg(1).clone();
+                    }
+                }
+                vector
+            }
+            Op::VectorShuffle => {
+                let mut components = arg(0).components().to_vec();
+                components.extend_from_slice(arg(1).components());
+                let undefined = components[0].zeroed();
+                Value::Composite(
+                    literals
+                        .iter()
+                        .map(|&index| {
+                            components.get(index as usize).unwrap_or(&undefined).clone()
+                        })
+                        .collect(),
+                )
+            }
+            Op::CompositeConstruct => {
+                match *self.expect(ty) {
+                    // Vectors may be constructed from smaller vectors
+                    Type::Vector { .. } => {
+                        let mut components = Vec::new();
+                        for arg in args {
+                            match *arg {
+                                Value::Composite(ref parts) => components.extend_from_slice(parts),
+                                ref scalar => components.push(scalar.clone()),
+                            }
+                        }
+                        Value::Composite(components)
+                    }
+                    _ => Value::Composite(args.to_vec()),
+                }
+            }
+            Op::CompositeExtract => arg(0).at(literals).clone(),
+            Op::CompositeInsert => {
+                let mut composite = arg(1).clone();
+                *composite.at_mut(literals) = arg(0).clone();
+                composite
+            }
+            Op::CopyObject => arg(0).clone(),
+            Op::Transpose => {
+                let columns = arg(0).components();
+                let rows = columns[0].components().len();
+                Value::Composite(
+                    (0..rows)
+                        .map(|row| {
+                            Value::Composite(
+                                columns
+                                    .iter()
+                                    .map(|column| column.components()[row].clone())
+                                    .collect(),
+                            )
+                        })
+                        .collect(),
+                )
+            }
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    /// Returns the width of the scalar type `ty` or of the components of the vector type `ty`.
+    pub fn scalar_width(&self, ty: Word) -> u32 {
+        match *self.expect(ty) {
+            Type::Vector { component, .. } => self.scalar_width(component),
+            Type::Int { width } | Type::Float { width } => width,
+            ref ty => panic!("{:?} is not a number", ty),
+        }
+    }
+}
+
+/// Returns a mask of the lowest `count` bits, where `count` may be the full width.
+fn low_bits(count: u64) -> u64 {
+    if count >= 64 { !0 } else { (1 << count) - 1 }
+}
+
+fn int_op<F: FnMut(u64, u64) -> u64>(a: &Value, b: &Value, f: &mut F) -> Value {
+    zip(a, b, &mut |a, b| Value::int(f(a.bits(), b.bits()), a.width()))
+}
+
+fn signed_op<F: FnMut(i64, i64) -> i64>(a: &Value, b: &Value, f: &mut F) -> Value {
+    zip(a, b, &mut |a, b| {
+        // Wrap around like the hardware for the most negative number divided by -1
+        let width = a.width();
+        let result = if a.signed() == i64::MIN && b.signed() == -1 {
+            a.signed()
+        } else {
+            f(a.signed(), b.signed())
+        };
+        Value::int(result as u64, width)
+    })
+}
+
+pub fn float_op<F: FnMut(f64, f64) -> f64>(a: &Value, b: &Value, f: &mut F) -> Value {
+    zip(a, b, &mut |a, b| Value::float(f(a.to_f64(), b.to_f64()), a.width()))
+}
+
+fn bool_op<F: FnMut(bool, bool) -> bool>(a: &Value, b: &Value, f: &mut F) -> Value {
+    zip(a, b, &mut |a, b| Value::Bool(f(a.to_bool(), b.to_bool())))
+}
+
+/// Computes the instructions that return a struct of a low and a high result, component by
+/// component.
+fn extended<F: FnMut(u64, u64, u32) -> (u64, u64)>(a: &Value, b: &Value, f: &mut F) -> Value {
+    let mut high = Vec::new();
+    let low = zip(a, b, &mut |a, b| {
+        let width = a.width();
+        let (low, carry) = f(a.bits(), b.bits(), width);
+        high.push(Value::int(carry, width));
+        Value::int(low, width)
+    });
+    let high = match low {
+        Value::Composite(_) => Value::Composite(high),
+        _ => high.remove(0),
+    };
+    Value::Composite(vec![low, high])
+}
+
+/// Multiplies the float `a` by the float `b`.
+pub fn multiply(a: &Value, b: &Value) -> Value {
+    Value::float(a.to_f64() * b.to_f64(), a.width())
+}
+
+pub fn add(a: &Value, b: &Value) -> Value {
+    Value::float(a.to_f64() + b.to_f64(), a.width())
+}
+
+/// The dot product of two float vectors, or the product of two floats.
+pub fn dot(a: &Value, b: &Value) -> Value {
+    let (a, b) = match (a, b) {
+        (&Value::Composite(ref a), &Value::Composite(ref b)) => (a, b),
+        (a, b) => return multiply(a, b),
+    };
+    let mut products = a.iter().zip(b).map(|(a, b)| multiply(a, b));
+    let first = products.next().expect("empty vector");
+    products.fold(first, |sum, product| add(&sum, &product))
+}
+
+fn matrix_times_vector(matrix: &Value, vector: &Value) -> Value {
+    let mut terms = matrix.components().iter().zip(vector.components()).map(
+        |(column, scale)| map(column, &mut |component| multiply(component, scale)),
+    );
+    let first = terms.next().expect("empty matrix");
+    terms.fold(first, |sum, term| zip(&sum, &term, &mut |a, b| add(a, b)))
+}
+
+fn compare(opcode: Op, ordering: Ordering) -> bool {
+    match opcode {
+        Op::IEqual => ordering == Ordering::Equal,
+        Op::INotEqual => ordering != Ordering::Equal,
+        Op::UGreaterThan | Op::SGreaterThan => ordering == Ordering::Greater,
+        Op::UGreaterThanEqual | Op::SGreaterThanEqual => ordering != Ordering::Less,
+        Op::ULessThan | Op::SLessThan => ordering == Ordering::Less,
+        _ => ordering != Ordering::Greater,
+    }
+}
+
+fn compare_floats(opcode: Op, a: f64, b: f64) -> bool {
+    let unordered = a.is_nan() || b.is_nan();
+    match opcode {
+        Op::FOrdEqual => a == b,
+        Op::FUnordEqual => unordered || a == b,
+        Op::FOrdNotEqual => !unordered && a != b,
+        Op::FUnordNotEqual => a != b,
+        Op::FOrdLessThan => a < b,
+        Op::FUnordLessThan => unordered || a < b,
+        Op::FOrdGreaterThan => a > b,
+        Op::FUnordGreaterThan => unordered || a > b,
+        Op::FOrdLessThanEqual => a <= b,
+        Op::FUnordLessThanEqual => unordered || a <= b,
+        Op::FOrdGreaterThanEqual => a >= b,
+        Op::FUnordGreaterThanEqual => unordered || a >= b,
+        Op::Ordered => !unordered,
+        _ => unordered,
+    }
+}
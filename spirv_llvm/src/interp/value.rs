@@ -0,0 +1,291 @@
+//! Values the interpreter computes with.
+use std::cmp;
+
+use spirv_headers::Word;
+
+use super::memory::Layout;
+
+/// The value of a SPIR-V id, or of what a variable holds.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    /// The bits of an integer of the given width, zero extended.
+    Int(u64, u32),
+    /// A float of the given width. Results are rounded to the width after every operation, so
+    /// they match what the hardware computes.
+    Float(f64, u32),
+    /// Vectors, matrices, arrays and structs.
+    Composite(Vec<Value>),
+    Pointer(Pointer),
+    /// The descriptor of an image, sampler or sampled image, which shaders only pass around.
+    Opaque(Vec<u8>),
+}
+
+/// Where a pointer points to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Pointer {
+    /// A value of type `ty` laid out according to `layout` in memory that is shared with the
+    /// application: the `Invocation`, a resource or the memory of the Workgroup variables.
+    Memory {
+        address: usize,
+        ty: Word,
+        layout: Layout,
+    },
+    /// A Private or Function variable of the running invocation, or the part of it that the
+    /// member and element indices `path` select.
+    Variable { index: usize, path: Vec<u32> },
+    /// An array of buffers of type `element`, where every buffer has a descriptor of its own.
+    /// Holds the address of the first descriptor.
+    Buffers {
+        descriptors: usize,
+        element: Word,
+        layout: Layout,
+    },
+    /// A block of built-in variables like gl_PerVertex, whose members are spread over the
+    /// `Invocation`.
+    BuiltInBlock { variable: Word },
+}
+
+impl Value {
+    /// An integer of the given width, with the bits above the width dropped.
+    pub fn int(bits: u64, width: u32) -> Self {
+        Value::Int(bits & mask(width), width)
+    }
+
+    /// A float of the given width, rounded to it.
+    pub fn float(value: f64, width: u32) -> Self {
+        Value::Float(round(value, width), width)
+    }
+
+    pub fn to_bool(&self) -> bool {
+        match *self {
+            Value::Bool(value) => value,
+            _ => panic!("expected a boolean, got {:?}", self),
+        }
+    }
+
+    /// The bits of an integer.
+    pub fn bits(&self) -> u64 {
+        match *self {
+            Value::Int(bits, _) => bits,
+            _ => panic!("expected an integer, got {:?}", self),
+        }
+    }
+
+    /// An integer, interpreted as signed.
+    pub fn signed(&self) -> i64 {
+        sign_extend(self.bits(), self.width())
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        match *self {
+            Value::Float(value, _) => value,
+            _ => panic!("expected a float, got {:?}", self),
+        }
+    }
+
+    /// The width of an integer or float.
+    pub fn width(&self) -> u32 {
+        match *self {
+            Value::Int(_, width) | Value::Float(_, width) => width,
+            _ => panic!("expected a number, got {:?}", self),
+        }
+    }
+
+    pub fn components(&self) -> &[Value] {
+        match *self {
+            Value::Composite(ref components) => components,
+            _ => panic!("expected a composite, got {:?}", self),
+        }
+    }
+
+    pub fn into_components(self) -> Vec<Value> {
+        match self {
+            Value::Composite(components) => components,
+            value => panic!("expected a composite, got {:?}", value),
+        }
+    }
+
+    pub fn into_pointer(self) -> Pointer {
+        match self {
+            Value::Pointer(pointer) => pointer,
+            value => panic!("expected a pointer, got {:?}", value),
+        }
+    }
+
+    /// A value of the same type with all bits 0, for undefined results.
+    pub fn zeroed(&self) -> Self {
+        match *self {
+            Value::Bool(_) => Value::Bool(false),
+            Value::Int(_, width) => Value::Int(0, width),
+            Value::Float(_, width) => Value::Float(0.0, width),
+            Value::Composite(ref components) => {
+                Value::Composite(components.iter().map(Value::zeroed).collect())
+            }
+            Value::Pointer(_) => self.clone(),
+            Value::Opaque(ref bytes) => Value::Opaque(vec![0; bytes.len()]),
+        }
+    }
+
+    /// Returns the member or element of a composite that the indices in `path` select.
+    /// Indices out of bounds are undefined behaviour and select the last element instead.
+    pub fn at(&self, path: &[u32]) -> &Value {
+        let mut value = self;
+        for &index in path {
+            match *value {
+                Value::Composite(ref components) if !components.is_empty() => {
+                    value = &components[cmp::min(index as usize, components.len() - 1)];
+                }
+                _ => break,
+            }
+        }
+        value
+    }
+
+    pub fn at_mut(&mut self, path: &[u32]) -> &mut Value {
+        let (index, rest) = match path.split_first() {
+            Some((&index, rest)) => (index as usize, rest),
+            None => return self,
+        };
+        let last = match *self {
+            Value::Composite(ref components) if !components.is_empty() => components.len() - 1,
+            _ => return self,
+        };
+        match *self {
+            Value::Composite(ref mut components) => components[cmp::min(index, last)].at_mut(rest),
+            ref mut value => value,
+        }
+    }
+}
+
+/// Applies `f` to every scalar of `a`.
+pub fn map<F: FnMut(&Value) -> Value>(a: &Value, f: &mut F) -> Value {
+    match *a {
+        Value::Composite(ref a) => Value::Composite(a.iter().map(|a| map(a, f)).collect()),
+        ref a => f(a),
+    }
+}
+
+/// Applies `f` to the corresponding scalars of `a` and `b`.
+pub fn zip<F: FnMut(&Value, &Value) -> Value>(a: &Value, b: &Value, f: &mut F) -> Value {
+    match (a, b) {
+        (&Value::Composite(ref a), &Value::Composite(ref b)) => {
+            Value::Composite(a.iter().zip(b).map(|(a, b)| zip(a, b, f)).collect())
+        }
+        (a, b) => f(a, b),
+    }
+}
+
+/// Applies `f` to the corresponding scalars of `a`, `b` and `c`.
+pub fn zip3<F>(a: &Value, b: &Value, c: &Value, f: &mut F) -> Value
+where
+    F: FnMut(&Value, &Value, &Value) -> Value,
+{
+    match (a, b, c) {
+        (&Value::Composite(ref a), &Value::Composite(ref b), &Value::Composite(ref c)) => {
+            Value::Composite(
+                a.iter()
+                    .zip(b)
+                    .zip(c)
+                    .map(|((a, b), c)| zip3(a, b, c, f))
+                    .collect(),
+            )
+        }
+        (a, b, c) => f(a, b, c),
+    }
+}
+
+/// Returns a mask of the lowest `width` bits.
+pub fn mask(width: u32) -> u64 {
+    if width >= 64 {
+        !0
+    } else {
+        (1 << width) - 1
+    }
+}
+
+pub fn sign_extend(bits: u64, width: u32) -> i64 {
+    if width >= 64 {
+        bits as i64
+    } else {
+        let shift = 64 - width;
+        ((bits << shift) as i64) >> shift
+    }
+}
+
+/// Rounds `value` to a float of the given width.
+pub fn round(value: f64, width: u32) -> f64 {
+    match width {
+        16 => f16_to_f32(f16_from_f32(value as f32)) as f64,
+        32 => value as f32 as f64,
+        _ => value,
+    }
+}
+
+/// Converts `value` to the bits of a half precision float, rounding to nearest even.
+pub fn f16_from_f32(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent == 0xff {
+        // Infinity stays infinite, NaNs stay quiet NaNs
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    let (half, rest, halfway) = if exponent <= 0 {
+        // Denormal, or too small for even that
+        if exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        (mantissa >> shift, mantissa & ((1 << shift) - 1), 1 << (shift - 1))
+    } else {
+        (((exponent as u32) << 10) | (mantissa >> 13), mantissa & 0x1fff, 0x1000)
+    };
+    // A carry out of the mantissa correctly bumps the exponent, up to infinity
+    let rounded = if rest > halfway || (rest == halfway && half & 1 != 0) {
+        half + 1
+    } else {
+        half
+    };
+    sign | rounded as u16
+}
+
+/// Converts the bits of a half precision float to single precision, which is exact.
+pub fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+    let magnitude = match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => (1024.0 + mantissa) * 2f32.powi(exponent - 25),
+    };
+    sign * magnitude
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn half_floats_round_to_nearest_even() {
+        assert_eq!(f16_from_f32(1.0), 0x3c00);
+        assert_eq!(f16_from_f32(-2.5), 0xc100);
+        assert_eq!(f16_from_f32(65504.0), 0x7bff);
+        assert_eq!(f16_from_f32(65520.0), 0x7c00);
+        assert_eq!(f16_from_f32(2f32.powi(-24)), 0x0001);
+        assert_eq!(f16_from_f32(1.0 + 2f32.powi(-11)), 0x3c00);
+        assert_eq!(f16_from_f32(1.0 + 3.0 * 2f32.powi(-11)), 0x3c02);
+        assert_eq!(f16_to_f32(0x3555), 0.333_251_95);
+        assert_eq!(f16_to_f32(0x0001), 2f32.powi(-24));
+        assert!(f16_to_f32(f16_from_f32(f32::NAN)).is_nan());
+    }
+}
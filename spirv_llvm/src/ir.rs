@@ -0,0 +1,690 @@
+//! Helpers for emitting the LLVM IR that SPIR-V instructions expand to.
+//!
+//! SPIR-V has a few operations LLVM has no instruction for (matrix arithmetic, dot products,
+//! extended arithmetic, ...). These are built from plain LLVM instructions here. All functions
+//! expect the builder to be positioned where the code should go.
+//!
+//! Functions taking `lanes` also work on values that hold several invocations at once. Such a
+//! value stores every scalar as a vector of `lanes` elements, and every SPIR-V vector as one
+//! long LLVM vector whose element `component * lanes + lane` is `component` of invocation
+//! `lane`. With one lane, values are laid out as usual.
+use std::ffi::CString;
+use std::os::raw::c_char;
+
+use llvm_sys::core::*;
+use llvm_sys::prelude::*;
+use llvm_sys::{LLVMIntPredicate, LLVMOpcode, LLVMRealPredicate, LLVMTypeKind};
+use spirv_headers::Op;
+
+/// Name for values that do not need one.
+pub const NONAME: *const c_char = b"\0" as *const u8 as *const c_char;
+
+pub type BinaryBuilder = unsafe extern "C" fn(LLVMBuilderRef,
+                                              LLVMValueRef,
+                                              LLVMValueRef,
+                                              *const c_char)
+                                              -> LLVMValueRef;
+
+/// Returns the builder for SPIR-V instructions that map to a single binary LLVM instruction.
+pub fn binary_builder(opcode: Op) -> Option<BinaryBuilder> {
+    let build: BinaryBuilder = match opcode {
+        Op::IAdd => LLVMBuildAdd,
+        Op::FAdd => LLVMBuildFAdd,
+        Op::ISub => LLVMBuildSub,
+        Op::FSub => LLVMBuildFSub,
+        Op::IMul => LLVMBuildMul,
+        Op::FMul => LLVMBuildFMul,
+        Op::UDiv => LLVMBuildUDiv,
+        Op::SDiv => LLVMBuildSDiv,
+        Op::FDiv => LLVMBuildFDiv,
+        Op::UMod => LLVMBuildURem,
+        Op::SRem => LLVMBuildSRem,
+        Op::FRem => LLVMBuildFRem,
+        Op::BitwiseOr | Op::LogicalOr => LLVMBuildOr,
+        Op::BitwiseXor => LLVMBuildXor,
+        Op::BitwiseAnd | Op::LogicalAnd => LLVMBuildAnd,
+        _ => return None,
+    };
+    Some(build)
+}
+
+/// Returns the predicate of SPIR-V integer and boolean comparisons.
+pub fn int_predicate(opcode: Op) -> Option<LLVMIntPredicate> {
+    use llvm_sys::LLVMIntPredicate::*;
+    let predicate = match opcode {
+        Op::IEqual | Op::LogicalEqual => LLVMIntEQ,
+        Op::INotEqual | Op::LogicalNotEqual => LLVMIntNE,
+        Op::UGreaterThan => LLVMIntUGT,
+        Op::SGreaterThan => LLVMIntSGT,
+        Op::UGreaterThanEqual => LLVMIntUGE,
+        Op::SGreaterThanEqual => LLVMIntSGE,
+        Op::ULessThan => LLVMIntULT,
+        Op::SLessThan => LLVMIntSLT,
+        Op::ULessThanEqual => LLVMIntULE,
+        Op::SLessThanEqual => LLVMIntSLE,
+        _ => return None,
+    };
+    Some(predicate)
+}
+
+/// Returns the predicate of SPIR-V floating point comparisons.
+pub fn real_predicate(opcode: Op) -> Option<LLVMRealPredicate> {
+    use llvm_sys::LLVMRealPredicate::*;
+    let predicate = match opcode {
+        Op::FOrdEqual => LLVMRealOEQ,
+        Op::FUnordEqual => LLVMRealUEQ,
+        Op::FOrdNotEqual => LLVMRealONE,
+        Op::FUnordNotEqual => LLVMRealUNE,
+        Op::FOrdLessThan => LLVMRealOLT,
+        Op::FUnordLessThan => LLVMRealULT,
+        Op::FOrdGreaterThan => LLVMRealOGT,
+        Op::FUnordGreaterThan => LLVMRealUGT,
+        Op::FOrdLessThanEqual => LLVMRealOLE,
+        Op::FUnordLessThanEqual => LLVMRealULE,
+        Op::FOrdGreaterThanEqual => LLVMRealOGE,
+        Op::FUnordGreaterThanEqual => LLVMRealUGE,
+        Op::Ordered => LLVMRealORD,
+        Op::Unordered => LLVMRealUNO,
+        _ => return None,
+    };
+    Some(predicate)
+}
+
+/// Returns the LLVM cast of SPIR-V conversions that do not depend on the operand width.
+pub fn cast_opcode(opcode: Op) -> Option<LLVMOpcode> {
+    let cast = match opcode {
+        Op::ConvertFToU => LLVMOpcode::LLVMFPToUI,
+        Op::ConvertFToS => LLVMOpcode::LLVMFPToSI,
+        Op::ConvertSToF => LLVMOpcode::LLVMSIToFP,
+        Op::ConvertUToF => LLVMOpcode::LLVMUIToFP,
+        Op::Bitcast => LLVMOpcode::LLVMBitCast,
+        _ => return None,
+    };
+    Some(cast)
+}
+
+pub unsafe fn is_vector(ty: LLVMTypeRef) -> bool {
+    LLVMGetTypeKind(ty) == LLVMTypeKind::LLVMVectorTypeKind
+}
+
+/// Returns the component type of a vector, or `ty` itself for scalars.
+pub unsafe fn scalar_type(ty: LLVMTypeRef) -> LLVMTypeRef {
+    if is_vector(ty) { LLVMGetElementType(ty) } else { ty }
+}
+
+/// Returns a 32 bit integer constant.
+pub unsafe fn const_u32(ctx: LLVMContextRef, value: u32) -> LLVMValueRef {
+    LLVMConstInt(LLVMInt32TypeInContext(ctx), value as u64, 0)
+}
+
+/// Returns the constant `value` broadcast to `ty`, if `ty` is a vector type.
+pub unsafe fn const_splat(value: LLVMValueRef, ty: LLVMTypeRef) -> LLVMValueRef {
+    if !is_vector(ty) {
+        return value;
+    }
+    let mut elements = vec![value; LLVMGetVectorSize(ty) as usize];
+    LLVMConstVector(elements.as_mut_ptr(), elements.len() as u32)
+}
+
+/// Broadcasts the scalar `value` to `ty`, if `ty` is a vector type.
+pub unsafe fn splat(
+    builder: LLVMBuilderRef,
+    value: LLVMValueRef,
+    ty: LLVMTypeRef,
+    lanes: u32,
+) -> LLVMValueRef {
+    if !is_vector(ty) || LLVMTypeOf(value) == ty {
+        return value;
+    }
+    let ctx = LLVMGetTypeContext(ty);
+    let value = if lanes == 1 {
+        let single = LLVMGetUndef(LLVMVectorType(LLVMTypeOf(value), 1));
+        LLVMBuildInsertElement(builder, single, value, const_u32(ctx, 0), NONAME)
+    } else {
+        value
+    };
+    let indices = (0..LLVMGetVectorSize(ty)).map(|index| index % lanes).collect::<Vec<_>>();
+    shuffle(builder, value, &indices)
+}
+
+/// Picks the elements at `indices` out of the vector `value`.
+pub unsafe fn shuffle(builder: LLVMBuilderRef, value: LLVMValueRef, indices: &[u32]) -> LLVMValueRef {
+    let ctx = LLVMGetTypeContext(LLVMTypeOf(value));
+    let mut mask = indices.iter().map(|&index| const_u32(ctx, index)).collect::<Vec<_>>();
+    let mask = LLVMConstVector(mask.as_mut_ptr(), mask.len() as u32);
+    LLVMBuildShuffleVector(builder, value, LLVMGetUndef(LLVMTypeOf(value)), mask, NONAME)
+}
+
+/// Returns component `index` of a vector.
+pub unsafe fn component(
+    builder: LLVMBuilderRef,
+    value: LLVMValueRef,
+    index: u32,
+    lanes: u32,
+) -> LLVMValueRef {
+    if lanes == 1 {
+        let index = const_u32(LLVMGetTypeContext(LLVMTypeOf(value)), index);
+        return LLVMBuildExtractElement(builder, value, index, NONAME);
+    }
+    let indices = (0..lanes).map(|lane| index * lanes + lane).collect::<Vec<_>>();
+    shuffle(builder, value, &indices)
+}
+
+/// Returns the components of a vector, or the value itself for scalars.
+pub unsafe fn components(
+    builder: LLVMBuilderRef,
+    value: LLVMValueRef,
+    lanes: u32,
+) -> Vec<LLVMValueRef> {
+    let ty = LLVMTypeOf(value);
+    if !is_vector(ty) || LLVMGetVectorSize(ty) == lanes {
+        return vec![value];
+    }
+    (0..LLVMGetVectorSize(ty) / lanes)
+        .map(|index| component(builder, value, index, lanes))
+        .collect()
+}
+
+/// Builds a value of type `ty` from its components, the inverse of `components`.
+pub unsafe fn from_components(
+    builder: LLVMBuilderRef,
+    ty: LLVMTypeRef,
+    components: &[LLVMValueRef],
+    lanes: u32,
+) -> LLVMValueRef {
+    if !is_vector(ty) || LLVMGetVectorSize(ty) == lanes {
+        return components[0];
+    }
+    let ctx = LLVMGetTypeContext(ty);
+    if lanes != 1 {
+        // Append one component after the other
+        let mut vector = components[0];
+        for &component in &components[1..] {
+            let size = LLVMGetVectorSize(LLVMTypeOf(vector));
+            let wide = resize_vector(builder, component, size);
+            let mut mask = (0..size + lanes)
+                .map(|index| const_u32(ctx, index))
+                .collect::<Vec<_>>();
+            let mask = LLVMConstVector(mask.as_mut_ptr(), mask.len() as u32);
+            vector = LLVMBuildShuffleVector(builder, vector, wide, mask, NONAME);
+        }
+        return vector;
+    }
+    let mut vector = LLVMGetUndef(ty);
+    for (index, &component) in components.iter().enumerate() {
+        vector = LLVMBuildInsertElement(
+            builder,
+            vector,
+            component,
+            const_u32(ctx, index as u32),
+            NONAME,
+        );
+    }
+    vector
+}
+
+/// Combines all components of `value` with `build`, e.g. to sum up a vector.
+pub unsafe fn reduce(
+    builder: LLVMBuilderRef,
+    value: LLVMValueRef,
+    build: BinaryBuilder,
+    lanes: u32,
+) -> LLVMValueRef {
+    let components = components(builder, value, lanes);
+    let mut result = components[0];
+    for &component in &components[1..] {
+        result = build(builder, result, component, NONAME);
+    }
+    result
+}
+
+/// Returns the dot product of two float vectors.
+pub unsafe fn dot(
+    builder: LLVMBuilderRef,
+    a: LLVMValueRef,
+    b: LLVMValueRef,
+    lanes: u32,
+) -> LLVMValueRef {
+    let product = LLVMBuildFMul(builder, a, b, NONAME);
+    reduce(builder, product, LLVMBuildFAdd, lanes)
+}
+
+/// Picks `a` where `condition` is true and `b` elsewhere. With several lanes, `condition` may
+/// hold a single bool per lane for vectors and aggregates.
+pub unsafe fn select(
+    builder: LLVMBuilderRef,
+    condition: LLVMValueRef,
+    a: LLVMValueRef,
+    b: LLVMValueRef,
+    lanes: u32,
+) -> LLVMValueRef {
+    let ty = LLVMTypeOf(a);
+    if lanes == 1 || ty == LLVMTypeOf(condition) {
+        return LLVMBuildSelect(builder, condition, a, b, NONAME);
+    }
+    match LLVMGetTypeKind(ty) {
+        LLVMTypeKind::LLVMVectorTypeKind => {
+            let bool_ty = LLVMInt1TypeInContext(LLVMGetTypeContext(ty));
+            let condition_ty = LLVMVectorType(bool_ty, LLVMGetVectorSize(ty));
+            let condition = splat(builder, condition, condition_ty, lanes);
+            LLVMBuildSelect(builder, condition, a, b, NONAME)
+        }
+        LLVMTypeKind::LLVMArrayTypeKind |
+        LLVMTypeKind::LLVMStructTypeKind => {
+            let count = if LLVMGetTypeKind(ty) == LLVMTypeKind::LLVMArrayTypeKind {
+                LLVMGetArrayLength(ty)
+            } else {
+                LLVMCountStructElementTypes(ty)
+            };
+            let members = (0..count)
+                .map(|index| {
+                    let a = LLVMBuildExtractValue(builder, a, index, NONAME);
+                    let b = LLVMBuildExtractValue(builder, b, index, NONAME);
+                    select(builder, condition, a, b, lanes)
+                })
+                .collect::<Vec<_>>();
+            build_aggregate(builder, ty, &members)
+        }
+        _ => LLVMBuildSelect(builder, condition, a, b, NONAME),
+    }
+}
+
+/// Casts the integer (vector) `value` to `ty`, extending or truncating as needed.
+pub unsafe fn int_cast(
+    builder: LLVMBuilderRef,
+    value: LLVMValueRef,
+    ty: LLVMTypeRef,
+    signed: bool,
+) -> LLVMValueRef {
+    let from = LLVMGetIntTypeWidth(scalar_type(LLVMTypeOf(value)));
+    let to = LLVMGetIntTypeWidth(scalar_type(ty));
+    if from < to {
+        if signed {
+            LLVMBuildSExt(builder, value, ty, NONAME)
+        } else {
+            LLVMBuildZExt(builder, value, ty, NONAME)
+        }
+    } else if from > to {
+        LLVMBuildTrunc(builder, value, ty, NONAME)
+    } else {
+        LLVMBuildBitCast(builder, value, ty, NONAME)
+    }
+}
+
+/// Returns the declaration of function `name`, declaring it on first use. Used for intrinsics
+/// and runtime helpers.
+pub unsafe fn declare(
+    module: LLVMModuleRef,
+    name: &str,
+    ret: LLVMTypeRef,
+    params: &[LLVMTypeRef],
+) -> LLVMValueRef {
+    let name = CString::new(name).unwrap();
+    let function = LLVMGetNamedFunction(module, name.as_ptr());
+    if !function.is_null() {
+        return function;
+    }
+    let mut params = params.to_vec();
+    let ty = LLVMFunctionType(ret, params.as_mut_ptr(), params.len() as u32, 0);
+    LLVMAddFunction(module, name.as_ptr(), ty)
+}
+
+/// Returns the suffix overloaded intrinsics use for `ty`, e.g. `v4f32`.
+pub unsafe fn type_suffix(ty: LLVMTypeRef) -> String {
+    let scalar = match LLVMGetTypeKind(scalar_type(ty)) {
+        LLVMTypeKind::LLVMHalfTypeKind => "f16".to_owned(),
+        LLVMTypeKind::LLVMFloatTypeKind => "f32".to_owned(),
+        LLVMTypeKind::LLVMDoubleTypeKind => "f64".to_owned(),
+        _ => format!("i{}", LLVMGetIntTypeWidth(scalar_type(ty))),
+    };
+    if is_vector(ty) {
+        format!("v{}{}", LLVMGetVectorSize(ty), scalar)
+    } else {
+        scalar
+    }
+}
+
+/// Calls the overloaded intrinsic `name` whose parameters and result all have the type of the
+/// first argument, e.g. `llvm.ctpop`.
+pub unsafe fn call_intrinsic(
+    builder: LLVMBuilderRef,
+    module: LLVMModuleRef,
+    name: &str,
+    args: &[LLVMValueRef],
+) -> LLVMValueRef {
+    let ty = LLVMTypeOf(args[0]);
+    let name = format!("{}.{}", name, type_suffix(ty));
+    let params = args.iter().map(|&arg| LLVMTypeOf(arg)).collect::<Vec<_>>();
+    let function = declare(module, &name, ty, &params);
+    let mut args = args.to_vec();
+    LLVMBuildCall(
+        builder,
+        function,
+        args.as_mut_ptr(),
+        args.len() as u32,
+        NONAME,
+    )
+}
+
+/// Extracts the element at `indices` from a (nested) composite.
+pub unsafe fn composite_extract(
+    builder: LLVMBuilderRef,
+    composite: LLVMValueRef,
+    indices: &[u32],
+    lanes: u32,
+) -> LLVMValueRef {
+    let mut value = composite;
+    for &index in indices {
+        value = if is_vector(LLVMTypeOf(value)) {
+            component(builder, value, index, lanes)
+        } else {
+            LLVMBuildExtractValue(builder, value, index, NONAME)
+        };
+    }
+    value
+}
+
+/// Returns a copy of `composite` with the element at `indices` replaced by `object`.
+pub unsafe fn composite_insert(
+    builder: LLVMBuilderRef,
+    composite: LLVMValueRef,
+    object: LLVMValueRef,
+    indices: &[u32],
+    lanes: u32,
+) -> LLVMValueRef {
+    if indices.is_empty() {
+        return object;
+    }
+    let ty = LLVMTypeOf(composite);
+    if is_vector(ty) && lanes == 1 {
+        let index = const_u32(LLVMGetTypeContext(ty), indices[0]);
+        return LLVMBuildInsertElement(builder, composite, object, index, NONAME);
+    }
+    if is_vector(ty) {
+        let mut components = components(builder, composite, lanes);
+        components[indices[0] as usize] = object;
+        return from_components(builder, ty, &components, lanes);
+    }
+    let element = LLVMBuildExtractValue(builder, composite, indices[0], NONAME);
+    let element = composite_insert(builder, element, object, &indices[1..], lanes);
+    LLVMBuildInsertValue(builder, composite, element, indices[0], NONAME)
+}
+
+/// Builds an aggregate of type `ty` (array or struct) from its members.
+pub unsafe fn build_aggregate(
+    builder: LLVMBuilderRef,
+    ty: LLVMTypeRef,
+    members: &[LLVMValueRef],
+) -> LLVMValueRef {
+    let mut aggregate = LLVMGetUndef(ty);
+    for (index, &member) in members.iter().enumerate() {
+        aggregate = LLVMBuildInsertValue(builder, aggregate, member, index as u32, NONAME);
+    }
+    aggregate
+}
+
+/// Returns the columns of a matrix.
+pub unsafe fn columns(builder: LLVMBuilderRef, matrix: LLVMValueRef) -> Vec<LLVMValueRef> {
+    let count = LLVMGetArrayLength(LLVMTypeOf(matrix));
+    (0..count)
+        .map(|index| LLVMBuildExtractValue(builder, matrix, index, NONAME))
+        .collect()
+}
+
+pub unsafe fn matrix_times_scalar(
+    builder: LLVMBuilderRef,
+    matrix: LLVMValueRef,
+    scalar: LLVMValueRef,
+    lanes: u32,
+) -> LLVMValueRef {
+    let columns = columns(builder, matrix)
+        .into_iter()
+        .map(|column| {
+            let scalar = splat(builder, scalar, LLVMTypeOf(column), lanes);
+            LLVMBuildFMul(builder, column, scalar, NONAME)
+        })
+        .collect::<Vec<_>>();
+    build_aggregate(builder, LLVMTypeOf(matrix), &columns)
+}
+
+pub unsafe fn matrix_times_vector(
+    builder: LLVMBuilderRef,
+    matrix: LLVMValueRef,
+    vector: LLVMValueRef,
+    lanes: u32,
+) -> LLVMValueRef {
+    let components = components(builder, vector, lanes);
+    let mut result = None;
+    for (column, component) in columns(builder, matrix).into_iter().zip(components) {
+        let component = splat(builder, component, LLVMTypeOf(column), lanes);
+        let product = LLVMBuildFMul(builder, column, component, NONAME);
+        result = Some(match result {
+            Some(sum) => LLVMBuildFAdd(builder, sum, product, NONAME),
+            None => product,
+        });
+    }
+    result.unwrap()
+}
+
+pub unsafe fn vector_times_matrix(
+    builder: LLVMBuilderRef,
+    vector: LLVMValueRef,
+    matrix: LLVMValueRef,
+    ty: LLVMTypeRef,
+    lanes: u32,
+) -> LLVMValueRef {
+    let components = columns(builder, matrix)
+        .into_iter()
+        .map(|column| dot(builder, vector, column, lanes))
+        .collect::<Vec<_>>();
+    from_components(builder, ty, &components, lanes)
+}
+
+pub unsafe fn matrix_times_matrix(
+    builder: LLVMBuilderRef,
+    left: LLVMValueRef,
+    right: LLVMValueRef,
+    ty: LLVMTypeRef,
+    lanes: u32,
+) -> LLVMValueRef {
+    let columns = columns(builder, right)
+        .into_iter()
+        .map(|column| matrix_times_vector(builder, left, column, lanes))
+        .collect::<Vec<_>>();
+    build_aggregate(builder, ty, &columns)
+}
+
+pub unsafe fn outer_product(
+    builder: LLVMBuilderRef,
+    left: LLVMValueRef,
+    right: LLVMValueRef,
+    ty: LLVMTypeRef,
+    lanes: u32,
+) -> LLVMValueRef {
+    let columns = components(builder, right, lanes)
+        .into_iter()
+        .map(|component| {
+            let component = splat(builder, component, LLVMTypeOf(left), lanes);
+            LLVMBuildFMul(builder, left, component, NONAME)
+        })
+        .collect::<Vec<_>>();
+    build_aggregate(builder, ty, &columns)
+}
+
+pub unsafe fn transpose(
+    builder: LLVMBuilderRef,
+    matrix: LLVMValueRef,
+    ty: LLVMTypeRef,
+    lanes: u32,
+) -> LLVMValueRef {
+    let columns = columns(builder, matrix)
+        .into_iter()
+        .map(|column| components(builder, column, lanes))
+        .collect::<Vec<_>>();
+    let column_ty = LLVMGetElementType(ty);
+    let rows = (0..LLVMGetArrayLength(ty) as usize)
+        .map(|row| {
+            let components = columns.iter().map(|column| column[row]).collect::<Vec<_>>();
+            from_components(builder, column_ty, &components, lanes)
+        })
+        .collect::<Vec<_>>();
+    build_aggregate(builder, ty, &rows)
+}
+
+/// Integer remainder whose sign follows the divisor (OpSMod).
+pub unsafe fn smod(builder: LLVMBuilderRef, a: LLVMValueRef, b: LLVMValueRef) -> LLVMValueRef {
+    let zero = LLVMConstNull(LLVMTypeOf(a));
+    let rem = LLVMBuildSRem(builder, a, b, NONAME);
+    let nonzero = LLVMBuildICmp(builder, LLVMIntPredicate::LLVMIntNE, rem, zero, NONAME);
+    let signs = LLVMBuildXor(builder, rem, b, NONAME);
+    let differ = LLVMBuildICmp(builder, LLVMIntPredicate::LLVMIntSLT, signs, zero, NONAME);
+    let fix = LLVMBuildAnd(builder, nonzero, differ, NONAME);
+    let fixed = LLVMBuildAdd(builder, rem, b, NONAME);
+    LLVMBuildSelect(builder, fix, fixed, rem, NONAME)
+}
+
+/// Float remainder whose sign follows the divisor (OpFMod).
+pub unsafe fn fmod(builder: LLVMBuilderRef, a: LLVMValueRef, b: LLVMValueRef) -> LLVMValueRef {
+    let zero = LLVMConstNull(LLVMTypeOf(a));
+    let rem = LLVMBuildFRem(builder, a, b, NONAME);
+    let nonzero = LLVMBuildFCmp(builder, LLVMRealPredicate::LLVMRealONE, rem, zero, NONAME);
+    let rem_negative = LLVMBuildFCmp(builder, LLVMRealPredicate::LLVMRealOLT, rem, zero, NONAME);
+    let b_negative = LLVMBuildFCmp(builder, LLVMRealPredicate::LLVMRealOLT, b, zero, NONAME);
+    let differ = LLVMBuildXor(builder, rem_negative, b_negative, NONAME);
+    let fix = LLVMBuildAnd(builder, nonzero, differ, NONAME);
+    let fixed = LLVMBuildFAdd(builder, rem, b, NONAME);
+    LLVMBuildSelect(builder, fix, fixed, rem, NONAME)
+}
+
+/// Returns a mask with the lowest `count` bits set. `count` may be the full width.
+pub unsafe fn low_bits(builder: LLVMBuilderRef, count: LLVMValueRef) -> LLVMValueRef {
+    let ty = LLVMTypeOf(count);
+    let width = LLVMConstInt(scalar_type(ty), LLVMGetIntTypeWidth(scalar_type(ty)) as u64, 0);
+    let one = const_splat(LLVMConstInt(scalar_type(ty), 1, 0), ty);
+    let all = LLVMConstAllOnes(ty);
+    let full = LLVMBuildICmp(
+        builder,
+        LLVMIntPredicate::LLVMIntEQ,
+        count,
+        const_splat(width, ty),
+        NONAME,
+    );
+    let shifted = LLVMBuildShl(builder, one, count, NONAME);
+    let mask = LLVMBuildSub(builder, shifted, one, NONAME);
+    LLVMBuildSelect(builder, full, all, mask, NONAME)
+}
+
+/// Selects `zero` in all components where `count` is 0, to not depend on shifts by the full
+/// width, which LLVM leaves undefined.
+unsafe fn unless_empty(
+    builder: LLVMBuilderRef,
+    count: LLVMValueRef,
+    value: LLVMValueRef,
+    zero: LLVMValueRef,
+) -> LLVMValueRef {
+    let empty = LLVMBuildICmp(
+        builder,
+        LLVMIntPredicate::LLVMIntEQ,
+        count,
+        LLVMConstNull(LLVMTypeOf(count)),
+        NONAME,
+    );
+    LLVMBuildSelect(builder, empty, zero, value, NONAME)
+}
+
+/// OpBitFieldInsert. `offset` and `count` must already have the type of `base`.
+pub unsafe fn bit_field_insert(
+    builder: LLVMBuilderRef,
+    base: LLVMValueRef,
+    insert: LLVMValueRef,
+    offset: LLVMValueRef,
+    count: LLVMValueRef,
+) -> LLVMValueRef {
+    let mask = LLVMBuildShl(builder, low_bits(builder, count), offset, NONAME);
+    let insert = LLVMBuildShl(builder, insert, offset, NONAME);
+    let insert = LLVMBuildAnd(builder, insert, mask, NONAME);
+    let inverted = LLVMBuildNot(builder, mask, NONAME);
+    let base_bits = LLVMBuildAnd(builder, base, inverted, NONAME);
+    let result = LLVMBuildOr(builder, base_bits, insert, NONAME);
+    unless_empty(builder, count, result, base)
+}
+
+/// OpBitFieldUExtract and OpBitFieldSExtract. `offset` and `count` must already have the type
+/// of `base`.
+pub unsafe fn bit_field_extract(
+    builder: LLVMBuilderRef,
+    base: LLVMValueRef,
+    offset: LLVMValueRef,
+    count: LLVMValueRef,
+    signed: bool,
+) -> LLVMValueRef {
+    let ty = LLVMTypeOf(base);
+    let result = if signed {
+        let width = LLVMGetIntTypeWidth(scalar_type(ty)) as u64;
+        let width = const_splat(LLVMConstInt(scalar_type(ty), width, 0), ty);
+        let left = LLVMBuildSub(builder, width, offset, NONAME);
+        let left = LLVMBuildSub(builder, left, count, NONAME);
+        let right = LLVMBuildSub(builder, width, count, NONAME);
+        let shifted = LLVMBuildShl(builder, base, left, NONAME);
+        LLVMBuildAShr(builder, shifted, right, NONAME)
+    } else {
+        let shifted = LLVMBuildLShr(builder, base, offset, NONAME);
+        LLVMBuildAnd(builder, shifted, low_bits(builder, count), NONAME)
+    };
+    unless_empty(builder, count, result, LLVMConstNull(ty))
+}
+
+/// Widens or narrows the vector `value` to `size` components for shuffling.
+pub unsafe fn resize_vector(
+    builder: LLVMBuilderRef,
+    value: LLVMValueRef,
+    size: u32,
+) -> LLVMValueRef {
+    let ty = LLVMTypeOf(value);
+    let current = LLVMGetVectorSize(ty);
+    if current == size {
+        return value;
+    }
+    let ctx = LLVMGetTypeContext(ty);
+    let i32_ty = LLVMInt32TypeInContext(ctx);
+    let mut mask = (0..size)
+        .map(|index| if index < current {
+            const_u32(ctx, index)
+        } else {
+            LLVMGetUndef(i32_ty)
+        })
+        .collect::<Vec<_>>();
+    let mask = LLVMConstVector(mask.as_mut_ptr(), size);
+    LLVMBuildShuffleVector(builder, value, LLVMGetUndef(ty), mask, NONAME)
+}
+
+/// Reorders a vector holding `lanes` invocations so that the components of every invocation
+/// are next to each other, e.g. to bitcast them to a single wider scalar.
+pub unsafe fn interleave(builder: LLVMBuilderRef, value: LLVMValueRef, lanes: u32) -> LLVMValueRef {
+    let size = LLVMGetVectorSize(LLVMTypeOf(value));
+    if lanes == 1 || size == lanes {
+        return value;
+    }
+    let count = size / lanes;
+    let indices = (0..size)
+        .map(|index| (index % count) * lanes + index / count)
+        .collect::<Vec<_>>();
+    shuffle(builder, value, &indices)
+}
+
+/// The inverse of `interleave`.
+pub unsafe fn deinterleave(
+    builder: LLVMBuilderRef,
+    value: LLVMValueRef,
+    lanes: u32,
+) -> LLVMValueRef {
+    let size = LLVMGetVectorSize(LLVMTypeOf(value));
+    if lanes == 1 || size == lanes {
+        return value;
+    }
+    let count = size / lanes;
+    let indices = (0..size)
+        .map(|index| (index % lanes) * count + index / lanes)
+        .collect::<Vec<_>>();
+    shuffle(builder, value, &indices)
+}
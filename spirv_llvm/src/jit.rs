@@ -0,0 +1,285 @@
+//! Compiles transpiled modules to native code for the host and hands out their entry points.
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::mem;
+use std::ptr;
+use std::sync::{Once, ONCE_INIT};
+
+use llvm_sys::core::*;
+use llvm_sys::execution_engine::*;
+use llvm_sys::prelude::*;
+use llvm_sys::support::LLVMAddSymbol;
+use llvm_sys::target::{LLVM_InitializeNativeAsmPrinter, LLVM_InitializeNativeTarget};
+
+use debug_info;
+use object::{self, LoadedObject};
+use runtime;
+use target::opt_level_from_env;
+use {EntryPoint, LlvmModule, Target, TranspilerError};
+
+static INIT: Once = ONCE_INIT;
+
+/// A module compiled to native code.
+///
+/// The machine code lives as long as the `JitModule`, entry points borrow from it.
+pub struct JitModule {
+    code: Code,
+    /// Addresses of the entry points, which are `ShaderFn`s or `BatchFn`s depending on `lanes`.
+    functions: HashMap<String, usize>,
+    lanes: u32,
+    workgroup_memory: usize,
+}
+
+/// Where the machine code of a `JitModule` lives.
+enum Code {
+    /// Compiled by MCJIT, which owns the module.
+    Engine(LLVMContextRef, LLVMExecutionEngineRef),
+    /// Loaded from the object file of a `NativeCode`, which is only kept to be unmapped when
+    /// the module is dropped.
+    Object { _object: LoadedObject },
+}
+
+// The engine is only touched while compiling, afterwards the module is read only.
+unsafe impl Send for JitModule {}
+unsafe impl Sync for JitModule {}
+
+impl JitModule {
+    /// Compiles `module` for the CPU we are running on.
+    pub fn new(mut module: LlvmModule) -> Result<Self, TranspilerError> {
+        INIT.call_once(|| unsafe { init() });
+        let opt_level = prepare(&mut module)?;
+        let lanes = module.lanes();
+        let workgroup_memory = module.workgroup_memory();
+        let entry_points = module.entry_points();
+        let (ctx, module) = module.into_raw();
+        unsafe {
+            let mut options = mem::zeroed::<LLVMMCJITCompilerOptions>();
+            LLVMInitializeMCJITCompilerOptions(&mut options, mem::size_of_val(&options));
+            options.OptLevel = opt_level;
+            // MCJIT registers every object it loads with the GDB JIT interface, which is how
+            // debuggers find the debug info of the module
+            let mut engine = ptr::null_mut();
+            let mut error = ptr::null_mut();
+            if LLVMCreateMCJITCompilerForModule(
+                &mut engine,
+                module,
+                &mut options,
+                mem::size_of_val(&options),
+                &mut error,
+            ) != 0
+            {
+                // The module is only owned by the engine once it was created
+                let message = CStr::from_ptr(error).to_string_lossy().into_owned();
+                LLVMDisposeMessage(error);
+                LLVMDisposeModule(module);
+                LLVMContextDispose(ctx);
+                return Err(TranspilerError::CodegenFailed(message));
+            }
+            let mut jit = JitModule {
+                code: Code::Engine(ctx, engine),
+                functions: HashMap::new(),
+                lanes: lanes,
+                workgroup_memory: workgroup_memory,
+            };
+            // Resolving the first function finalizes the whole module, so do it now while we
+            // still have exclusive access to the engine.
+            for name in entry_points {
+                let c_name = CString::new(name.as_str()).unwrap_or_default();
+                let address = LLVMGetFunctionAddress(engine, c_name.as_ptr());
+                if address == 0 {
+                    return Err(TranspilerError::CodegenFailed(
+                        format!("could not resolve function {}", name),
+                    ));
+                }
+                jit.functions.insert(name, address as usize);
+            }
+            Ok(jit)
+        }
+    }
+
+    /// Loads code that `NativeCode::compile` compiled before, which does not involve LLVM.
+    pub fn load(code: &NativeCode) -> Result<Self, TranspilerError> {
+        let helpers: HashMap<_, _> = runtime::symbols().into_iter().collect();
+        let object = LoadedObject::load(&code.object, |name| helpers.get(name).cloned())
+            .map_err(TranspilerError::CodegenFailed)?;
+        let mut functions = HashMap::new();
+        for name in &code.entry_points {
+            let address = object.symbol(name).ok_or_else(|| {
+                TranspilerError::CodegenFailed(format!("could not resolve function {}", name))
+            })?;
+            functions.insert(name.clone(), address);
+        }
+        Ok(JitModule {
+            code: Code::Object { _object: object },
+            functions: functions,
+            lanes: code.lanes,
+            workgroup_memory: code.workgroup_memory,
+        })
+    }
+
+    /// Returns the entry point that was declared with the given name.
+    pub fn entry_point<'a>(&'a self, name: &str) -> Option<EntryPoint<'a>> {
+        self.functions.get(name).map(|&function| {
+            EntryPoint::native(function, self.lanes, self.workgroup_memory)
+        })
+    }
+}
+
+impl Drop for JitModule {
+    fn drop(&mut self) {
+        if let Code::Engine(ctx, engine) = self.code {
+            unsafe {
+                // Also disposes the module
+                LLVMDisposeExecutionEngine(engine);
+                LLVMContextDispose(ctx);
+            }
+        }
+    }
+}
+
+/// Sets the target of `module` and optimizes it, returning the optimization level.
+fn prepare(module: &mut LlvmModule) -> Result<u32, TranspilerError> {
+    // Allow the backend to use every instruction set extension of the host, unless the
+    // environment pins them down
+    module.set_target(&Target::from_env())?;
+    // Optimized code is hard to step through, variables end up in registers or vanish
+    let opt_level = if debug_info::enabled() {
+        0
+    } else {
+        opt_level_from_env()
+    };
+    module.optimize(opt_level)?;
+    Ok(opt_level)
+}
+
+/// The machine code of a module as a relocatable object file, together with what it takes to
+/// call it. Unlike a `JitModule` it can be stored, e.g. in a pipeline cache, and loaded again
+/// with `JitModule::load` without going through LLVM.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NativeCode {
+    pub object: Vec<u8>,
+    /// Names of the entry points, which the object defines as global symbols.
+    pub entry_points: Vec<String>,
+    pub lanes: u32,
+    pub workgroup_memory: usize,
+}
+
+/// Tells serialized `NativeCode` apart from other data.
+const NATIVE_CODE_MAGIC: &[u8; 4] = b"SPNC";
+
+impl NativeCode {
+    /// Whether native code can be loaded on this host. Debuggers only find the debug info of
+    /// code that MCJIT loaded, so there is no native code while `SPIRV_LLVM_DEBUG_INFO` is set.
+    pub fn supported() -> bool {
+        object::supported() && !debug_info::enabled()
+    }
+
+    /// Compiles `module` for the CPU we are running on, like `JitModule::new`.
+    pub fn compile(mut module: LlvmModule) -> Result<Self, TranspilerError> {
+        INIT.call_once(|| unsafe { init() });
+        prepare(&mut module)?;
+        Ok(NativeCode {
+            object: module.to_object()?,
+            entry_points: module.entry_points(),
+            lanes: module.lanes(),
+            workgroup_memory: module.workgroup_memory(),
+        })
+    }
+
+    /// Serializes the code into a byte string that `from_bytes` reads back.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = NATIVE_CODE_MAGIC.to_vec();
+        push_u64(&mut bytes, u64::from(self.lanes));
+        push_u64(&mut bytes, self.workgroup_memory as u64);
+        push_u64(&mut bytes, self.entry_points.len() as u64);
+        for name in &self.entry_points {
+            push_u64(&mut bytes, name.len() as u64);
+            bytes.extend_from_slice(name.as_bytes());
+        }
+        push_u64(&mut bytes, self.object.len() as u64);
+        bytes.extend_from_slice(&self.object);
+        bytes
+    }
+
+    /// Reads code that `to_bytes` serialized, `None` if `bytes` are not such code.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut reader = Reader(bytes);
+        if reader.bytes(4)? != NATIVE_CODE_MAGIC {
+            return None;
+        }
+        let lanes = reader.u64()? as u32;
+        let workgroup_memory = reader.u64()? as usize;
+        let count = reader.u64()?;
+        let mut entry_points = Vec::new();
+        for _ in 0..count {
+            let length = reader.u64()? as usize;
+            entry_points.push(String::from_utf8(reader.bytes(length)?.to_vec()).ok()?);
+        }
+        let length = reader.u64()? as usize;
+        let object = reader.bytes(length)?.to_vec();
+        if !reader.0.is_empty() {
+            return None;
+        }
+        Some(NativeCode {
+            object: object,
+            entry_points: entry_points,
+            lanes: lanes,
+            workgroup_memory: workgroup_memory,
+        })
+    }
+}
+
+fn push_u64(bytes: &mut Vec<u8>, value: u64) {
+    for index in 0..8 {
+        bytes.push((value >> (index * 8)) as u8);
+    }
+}
+
+/// Reads serialized `NativeCode` from the front of a byte string.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Option<&'a [u8]> {
+        if count > self.0.len() {
+            return None;
+        }
+        let (bytes, rest) = self.0.split_at(count);
+        self.0 = rest;
+        Some(bytes)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        let bytes = self.bytes(8)?;
+        Some(bytes.iter().rev().fold(0, |value, &byte| value << 8 | u64::from(byte)))
+    }
+}
+
+unsafe fn init() {
+    LLVMLinkInMCJIT();
+    LLVM_InitializeNativeTarget();
+    LLVM_InitializeNativeAsmPrinter();
+    for (name, address) in runtime::symbols() {
+        let name = CString::new(name).unwrap_or_default();
+        LLVMAddSymbol(name.as_ptr(), address as *mut _);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn native_code_round_trips() {
+        let code = NativeCode {
+            object: vec![1, 2, 3],
+            entry_points: vec!["main".to_owned(), "other".to_owned()],
+            lanes: 8,
+            workgroup_memory: 272,
+        };
+        let bytes = code.to_bytes();
+        assert_eq!(NativeCode::from_bytes(&bytes), Some(code));
+        assert_eq!(NativeCode::from_bytes(&bytes[..bytes.len() - 1]), None);
+        assert_eq!(NativeCode::from_bytes(&[bytes.as_slice(), &[0]].concat()), None);
+        assert_eq!(NativeCode::from_bytes(b"garbage"), None);
+    }
+}
@@ -1,22 +1,108 @@
+// The code keeps to the idioms of Rust 2015, and to the standard library and the LLVM API of
+// its time
+#![allow(
+    clippy::redundant_field_names,
+    clippy::match_like_matches_macro,
+    clippy::needless_borrowed_reference,
+    clippy::get_first,
+    clippy::missing_safety_doc,
+    clippy::manual_div_ceil,
+    clippy::manual_is_multiple_of,
+    clippy::manual_repeat_n,
+    clippy::unnecessary_map_or,
+    deprecated
+)]
+
 extern crate rspirv;
+#[cfg(feature = "cranelift")]
+extern crate cranelift_codegen;
+#[cfg(feature = "cranelift")]
+extern crate cranelift_frontend;
+#[cfg(feature = "cranelift")]
+extern crate cranelift_jit;
+#[cfg(feature = "cranelift")]
+extern crate cranelift_module;
+#[cfg(feature = "cranelift")]
+extern crate cranelift_native;
+#[cfg(feature = "llvm")]
+extern crate libc;
+#[cfg(feature = "llvm")]
 extern crate llvm_sys;
 extern crate spirv_headers;
 
+#[cfg(not(any(feature = "llvm", feature = "interpreter")))]
+compile_error!("at least one of the features \"llvm\" and \"interpreter\" has to be enabled");
+
+pub mod abi;
+mod backend;
+#[cfg(feature = "llvm")]
+mod cfg;
+#[cfg(feature = "cranelift")]
+mod cranelift;
+#[cfg(feature = "llvm")]
+mod debug_info;
+mod error;
+#[cfg(feature = "llvm")]
+mod glsl;
+mod glsl_op;
+#[cfg(feature = "interpreter")]
+mod interp;
+#[cfg(feature = "llvm")]
+mod ir;
+#[cfg(feature = "llvm")]
+mod jit;
+#[cfg(feature = "llvm")]
+mod module;
+#[cfg(feature = "llvm")]
+mod object;
+mod runtime;
+mod specialize;
+#[cfg(feature = "llvm")]
+mod target;
+#[cfg(feature = "llvm")]
 mod transpiler;
 mod trans;
+mod validate;
 
+#[cfg(feature = "llvm")]
 use transpiler::SpirvTranspiler;
 
-pub fn spirv_to_llvm(spirv_mod: &rspirv::mr::Module) -> Result<(), TranspilerError> {
-    let transpiler = SpirvTranspiler::new(spirv_mod)?;
-    unimplemented!()
+pub use backend::{Backend, EntryPoint, ShaderCode, BACKEND_VARIABLE};
+#[cfg(feature = "cranelift")]
+pub use cranelift::CraneliftModule;
+#[cfg(feature = "llvm")]
+pub use debug_info::DEBUG_INFO_VARIABLE;
+pub use error::{Location, SourceLine, TranspilerError};
+#[cfg(feature = "interpreter")]
+pub use interp::Interpreter;
+#[cfg(feature = "llvm")]
+pub use jit::{JitModule, NativeCode};
+#[cfg(feature = "llvm")]
+pub use module::LlvmModule;
+pub use specialize::specialize;
+#[cfg(feature = "llvm")]
+pub use target::{host_features, opt_level_from_env, simd_lanes, Target, DEFAULT_OPT_LEVEL,
+                 OPT_LEVEL_VARIABLE, TARGET_CPU_VARIABLE, TARGET_FEATURES_VARIABLE};
+pub use validate::{validate, DepthMode, EntryPointInfo, ExecutionModes, InterfaceVariable,
+                   Interpolation, ScalarType};
+
+#[cfg(feature = "llvm")]
+pub fn spirv_to_llvm(spirv_mod: &rspirv::mr::Module) -> Result<LlvmModule, TranspilerError> {
+    let mut transpiler = SpirvTranspiler::new(spirv_mod, 1)?;
+    transpiler.transpile()?;
+    Ok(transpiler.into_module())
 }
 
-pub enum TranspilerError {
-    NoHeader,
-    InvalidMagicNumber,
-    NoMemoryModelProvided,
-    UnsupportedAddressingModel,
+/// Like `spirv_to_llvm`, but compiles the entry points to run `lanes` invocations at once, one
+/// per SIMD lane. See `abi::BatchFn`.
+#[cfg(feature = "llvm")]
+pub fn spirv_to_llvm_simd(
+    spirv_mod: &rspirv::mr::Module,
+    lanes: u32,
+) -> Result<LlvmModule, TranspilerError> {
+    let mut transpiler = SpirvTranspiler::new(spirv_mod, lanes)?;
+    transpiler.transpile()?;
+    Ok(transpiler.into_module())
 }
 
 #[cfg(test)]
@@ -0,0 +1,302 @@
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::mem;
+use std::os::raw::c_char;
+use std::ptr;
+use std::slice;
+
+use llvm_sys::analysis::{LLVMVerifierFailureAction, LLVMVerifyModule};
+use llvm_sys::bit_writer::LLVMWriteBitcodeToMemoryBuffer;
+use llvm_sys::core::*;
+use llvm_sys::prelude::*;
+use llvm_sys::target::{LLVM_InitializeNativeAsmPrinter, LLVM_InitializeNativeTarget,
+                       LLVMABISizeOfType, LLVMDisposeTargetData, LLVMGetModuleDataLayout,
+                       LLVMSetModuleDataLayout};
+use llvm_sys::target_machine::*;
+use llvm_sys::transforms::instcombine::LLVMAddInstructionCombiningPass;
+use llvm_sys::transforms::pass_manager_builder::*;
+use llvm_sys::transforms::scalar::LLVMAddCFGSimplificationPass;
+use llvm_sys::transforms::vectorize::{LLVMAddLoopVectorizePass, LLVMAddSLPVectorizePass};
+use llvm_sys::LLVMLinkage;
+
+use transpiler::WORKGROUP_TYPE;
+
+use {Target, TranspilerError};
+
+/// An LLVM module together with the context it lives in.
+///
+/// Every module gets a context of its own, so dropping the module disposes both.
+pub struct LlvmModule {
+    ctx: LLVMContextRef,
+    module: LLVMModuleRef,
+    lanes: u32,
+    /// What the module is compiled for, once `set_target` was called.
+    target: Option<Target>,
+}
+
+impl LlvmModule {
+    /// Creates an empty module in a new context.
+    pub fn new(name: &str) -> Self {
+        LlvmModule::with_lanes(name, 1)
+    }
+
+    /// Creates an empty module for entry points that run `lanes` invocations at once.
+    pub fn with_lanes(name: &str, lanes: u32) -> Self {
+        let name = CString::new(name).unwrap_or_default();
+        unsafe {
+            let ctx = LLVMContextCreate();
+            let module = LLVMModuleCreateWithNameInContext(name.as_ptr(), ctx);
+            LlvmModule {
+                ctx: ctx,
+                module: module,
+                lanes: lanes,
+                target: None,
+            }
+        }
+    }
+
+    pub fn context(&self) -> LLVMContextRef {
+        self.ctx
+    }
+
+    /// Number of invocations the entry points run at once.
+    pub fn lanes(&self) -> u32 {
+        self.lanes
+    }
+
+    /// Bytes of memory the Workgroup variables take up, see `abi::Resources::workgroup_memory`.
+    /// Depends on the data layout, so call `set_target` first.
+    pub fn workgroup_memory(&self) -> usize {
+        let name = CString::new(WORKGROUP_TYPE).unwrap();
+        unsafe {
+            let ty = LLVMGetTypeByName(self.module, name.as_ptr());
+            if ty.is_null() {
+                return 0;
+            }
+            LLVMABISizeOfType(LLVMGetModuleDataLayout(self.module), ty) as usize
+        }
+    }
+
+    pub fn as_raw(&self) -> LLVMModuleRef {
+        self.module
+    }
+
+    /// Gives up ownership of the context and the module.
+    pub fn into_raw(mut self) -> (LLVMContextRef, LLVMModuleRef) {
+        self.target = None;
+        let raw = (self.ctx, self.module);
+        mem::forget(self);
+        raw
+    }
+
+    /// Runs the LLVM verifier over the module, returning its report on failure.
+    pub fn verify(&self) -> Result<(), String> {
+        unsafe {
+            let mut message = ptr::null_mut();
+            let failed = LLVMVerifyModule(
+                self.module,
+                LLVMVerifierFailureAction::LLVMReturnStatusAction,
+                &mut message,
+            );
+            let report = if message.is_null() {
+                String::new()
+            } else {
+                let report = CStr::from_ptr(message).to_string_lossy().into_owned();
+                LLVMDisposeMessage(message);
+                report
+            };
+            if failed != 0 { Err(report) } else { Ok(()) }
+        }
+    }
+
+    /// Runs the LLVM optimizations of `opt_level`, 0 to 3, over the module.
+    ///
+    /// The transpiler keeps values that cross blocks on the stack and leaves cleaning that up to
+    /// LLVM, so code is only fast from level 1 on, where mem2reg, instcombine and the CFG
+    /// simplification run. Level 2 adds inlining, GVN, loop unrolling and vectorization, level 3
+    /// inlines more eagerly. Vectorization only knows the vector registers to use if
+    /// `set_target` was called first.
+    pub fn optimize(&mut self, opt_level: u32) -> Result<(), TranspilerError> {
+        unsafe {
+            let machine = match self.target {
+                Some(ref target) => {
+                    Some(target.create_machine(opt_level, LLVMRelocMode::LLVMRelocDefault)?)
+                }
+                None => None,
+            };
+            let builder = LLVMPassManagerBuilderCreate();
+            LLVMPassManagerBuilderSetOptLevel(builder, opt_level);
+            match opt_level {
+                0 | 1 => (),
+                2 => LLVMPassManagerBuilderUseInlinerWithThreshold(builder, 275),
+                _ => LLVMPassManagerBuilderUseInlinerWithThreshold(builder, 1000),
+            }
+            let passes = LLVMCreatePassManager();
+            if let Some(machine) = machine {
+                // Tells the vectorizers about the vector registers of the target
+                LLVMAddAnalysisPasses(machine, passes);
+            }
+            LLVMPassManagerBuilderPopulateModulePassManager(builder, passes);
+            LLVMPassManagerBuilderDispose(builder);
+            // The C API cannot turn on the vectorizers of the pass manager builder
+            if opt_level >= 2 {
+                LLVMAddLoopVectorizePass(passes);
+                LLVMAddSLPVectorizePass(passes);
+                LLVMAddInstructionCombiningPass(passes);
+                LLVMAddCFGSimplificationPass(passes);
+            }
+            LLVMRunPassManager(passes, self.module);
+            LLVMDisposePassManager(passes);
+            if let Some(machine) = machine {
+                LLVMDisposeTargetMachine(machine);
+            }
+        }
+        Ok(())
+    }
+
+    /// Compiles the module for `target`: sets its triple and data layout, and lets every
+    /// function use the CPU and features of `target`.
+    pub fn set_target(&mut self, target: &Target) -> Result<(), TranspilerError> {
+        unsafe {
+            LLVM_InitializeNativeTarget();
+            LLVM_InitializeNativeAsmPrinter();
+            let machine = target.create_machine(0, LLVMRelocMode::LLVMRelocDefault)?;
+            let triple = CString::new(target.triple.as_str()).unwrap_or_default();
+            LLVMSetTarget(self.module, triple.as_ptr());
+            let layout = LLVMCreateTargetDataLayout(machine);
+            LLVMSetModuleDataLayout(self.module, layout);
+            LLVMDisposeTargetData(layout);
+            LLVMDisposeTargetMachine(machine);
+        }
+        self.add_function_attribute("target-cpu", &target.cpu);
+        self.add_function_attribute("target-features", &target.features);
+        self.target = Some(target.clone());
+        Ok(())
+    }
+
+    /// Sets the string attribute `name` of every function to `value`.
+    fn add_function_attribute(&mut self, name: &str, value: &str) {
+        let name = CString::new(name).unwrap_or_default();
+        let value = CString::new(value).unwrap_or_default();
+        for function in self.functions() {
+            unsafe { LLVMAddTargetDependentFunctionAttr(function, name.as_ptr(), value.as_ptr()) };
+        }
+    }
+
+    /// Names of the entry points, which are the only functions visible from the outside.
+    pub fn entry_points(&self) -> Vec<String> {
+        self.functions()
+            .into_iter()
+            .filter(|&function| unsafe {
+                LLVMGetLinkage(function) == LLVMLinkage::LLVMExternalLinkage
+            })
+            .map(|function| unsafe {
+                CStr::from_ptr(LLVMGetValueName(function))
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect()
+    }
+
+    /// Drops every entry point but `name`, returning false if there is no such entry point.
+    /// Functions only the dropped entry points called go away once the module is optimized.
+    pub fn select_entry_point(&mut self, name: &str) -> bool {
+        let entry_points = self.entry_points();
+        if !entry_points.iter().any(|entry_point| entry_point == name) {
+            return false;
+        }
+        for entry_point in entry_points.iter().filter(|&entry_point| entry_point != name) {
+            let entry_point = CString::new(entry_point.as_str()).unwrap_or_default();
+            unsafe {
+                // Nothing calls entry points, so they can go right away
+                LLVMDeleteFunction(LLVMGetNamedFunction(self.module, entry_point.as_ptr()));
+            }
+        }
+        true
+    }
+
+    /// Serializes the module to LLVM bitcode.
+    pub fn to_bitcode(&self) -> Vec<u8> {
+        unsafe { into_bytes(LLVMWriteBitcodeToMemoryBuffer(self.module)) }
+    }
+
+    /// Compiles the module to an object file for the target of `set_target`, or the host if
+    /// there is none, in the format of its platform.
+    pub fn to_object(&mut self) -> Result<Vec<u8>, TranspilerError> {
+        if self.target.is_none() {
+            self.set_target(&Target::host())?;
+        }
+        let target = self.target.clone().expect("target was just set");
+        unsafe {
+            let machine = target.create_machine(2, LLVMRelocMode::LLVMRelocPIC)?;
+            let mut error = ptr::null_mut();
+            let mut buffer = ptr::null_mut();
+            let failed = LLVMTargetMachineEmitToMemoryBuffer(
+                machine,
+                self.module,
+                LLVMCodeGenFileType::LLVMObjectFile,
+                &mut error,
+                &mut buffer,
+            );
+            LLVMDisposeTargetMachine(machine);
+            if failed != 0 {
+                return Err(TranspilerError::CodegenFailed(take_message(error)));
+            }
+            Ok(into_bytes(buffer))
+        }
+    }
+
+    /// The functions defined in the module.
+    fn functions(&self) -> Vec<LLVMValueRef> {
+        let mut functions = Vec::new();
+        unsafe {
+            let mut function = LLVMGetFirstFunction(self.module);
+            while !function.is_null() {
+                if LLVMIsDeclaration(function) == 0 {
+                    functions.push(function);
+                }
+                function = LLVMGetNextFunction(function);
+            }
+        }
+        functions
+    }
+}
+
+/// Copies the contents of `buffer` and disposes it.
+unsafe fn into_bytes(buffer: LLVMMemoryBufferRef) -> Vec<u8> {
+    let start = LLVMGetBufferStart(buffer) as *const u8;
+    let bytes = slice::from_raw_parts(start, LLVMGetBufferSize(buffer)).to_vec();
+    LLVMDisposeMemoryBuffer(buffer);
+    bytes
+}
+
+/// Copies an error message of LLVM and disposes it.
+pub unsafe fn take_message(message: *mut c_char) -> String {
+    if message.is_null() {
+        return String::new();
+    }
+    let string = CStr::from_ptr(message).to_string_lossy().into_owned();
+    LLVMDisposeMessage(message);
+    string
+}
+
+/// Prints the module as textual IR.
+impl fmt::Display for LlvmModule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        unsafe {
+            let ir = LLVMPrintModuleToString(self.module);
+            let result = f.write_str(&CStr::from_ptr(ir).to_string_lossy());
+            LLVMDisposeMessage(ir);
+            result
+        }
+    }
+}
+
+impl Drop for LlvmModule {
+    fn drop(&mut self) {
+        unsafe {
+            LLVMDisposeModule(self.module);
+            LLVMContextDispose(self.ctx);
+        }
+    }
+}
@@ -0,0 +1,430 @@
+//! Loads relocatable object files that LLVM compiled earlier, so that cached code runs without
+//! going through LLVM again.
+//!
+//! Only what LLVM emits for shaders is supported: ELF objects for x86-64, compiled as position
+//! independent code. Other hosts keep compiling every time, see `NativeCode::supported`.
+use std::cmp;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::ptr;
+
+use libc;
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const ET_REL: u16 = 1;
+const EM_X86_64: u16 = 62;
+
+const SHT_SYMTAB: u32 = 2;
+const SHT_RELA: u32 = 4;
+const SHT_NOBITS: u32 = 8;
+const SHF_WRITE: u64 = 1;
+const SHF_ALLOC: u64 = 2;
+
+const SHN_UNDEF: u16 = 0;
+const SHN_ABS: u16 = 0xfff1;
+const STB_LOCAL: u8 = 0;
+const STT_SECTION: u8 = 3;
+
+const R_X86_64_NONE: u32 = 0;
+const R_X86_64_64: u32 = 1;
+const R_X86_64_PC32: u32 = 2;
+const R_X86_64_PLT32: u32 = 4;
+const R_X86_64_GOTPCREL: u32 = 9;
+const R_X86_64_32: u32 = 10;
+const R_X86_64_32S: u32 = 11;
+const R_X86_64_PC64: u32 = 24;
+const R_X86_64_GOTPCRELX: u32 = 41;
+const R_X86_64_REX_GOTPCRELX: u32 = 42;
+
+/// Bytes of a stub that jumps to the address in a GOT slot, `jmp [rip + disp32]` and padding.
+const STUB_SIZE: usize = 16;
+
+/// Whether objects of the host can be loaded.
+pub fn supported() -> bool {
+    cfg!(all(target_arch = "x86_64", target_os = "linux"))
+}
+
+/// The code and data of an object file, relocated and mapped executable.
+pub struct LoadedObject {
+    memory: *mut u8,
+    size: usize,
+    /// Addresses of the global symbols the object defines.
+    symbols: HashMap<String, usize>,
+}
+
+// The memory is read only once the object is loaded, except for writable sections, which only
+// the shader itself touches.
+unsafe impl Send for LoadedObject {}
+unsafe impl Sync for LoadedObject {}
+
+impl LoadedObject {
+    /// Maps the allocated sections of `object` into memory and relocates them. Symbols the object
+    /// does not define are looked up with `resolve`, then in the process.
+    pub fn load<F>(object: &[u8], resolve: F) -> Result<Self, String>
+    where
+        F: Fn(&str) -> Option<usize>,
+    {
+        let elf = Elf::parse(object)?;
+        let symbols = elf.symbols()?;
+        let relocations = elf.relocations()?;
+        let allocated = |section: &Section| section.flags & SHF_ALLOC != 0;
+
+        // Every symbol that is accessed through the GOT, or called without being defined, gets a
+        // GOT slot, and a stub that jumps to the address in the slot
+        let mut imports = Vec::new();
+        let mut import_index = HashMap::new();
+        for relocation in &relocations {
+            if !allocated(&elf.sections[relocation.section]) {
+                continue;
+            }
+            let symbol = symbols.get(relocation.symbol).ok_or("relocation of unknown symbol")?;
+            let needs_slot = match relocation.kind {
+                R_X86_64_GOTPCREL | R_X86_64_GOTPCRELX | R_X86_64_REX_GOTPCRELX => true,
+                R_X86_64_PLT32 | R_X86_64_PC32 => symbol.section == SHN_UNDEF,
+                _ => false,
+            };
+            if needs_slot && !import_index.contains_key(&relocation.symbol) {
+                import_index.insert(relocation.symbol, imports.len());
+                imports.push(relocation.symbol);
+            }
+        }
+
+        // Code and read only data first, followed by the GOT and the stubs, then the writable
+        // sections on pages of their own
+        let mut layout = vec![None; elf.sections.len()];
+        let mut size = 0;
+        for (index, section) in elf.sections.iter().enumerate() {
+            if allocated(section) && section.flags & SHF_WRITE == 0 {
+                size = round_up(size, cmp::max(section.align, 1) as usize);
+                layout[index] = Some(size);
+                size += section.size as usize;
+            }
+        }
+        let got = round_up(size, 16);
+        let stubs = got + imports.len() * 8;
+        let read_only_end = round_up(stubs + imports.len() * STUB_SIZE, page_size());
+        size = read_only_end;
+        for (index, section) in elf.sections.iter().enumerate() {
+            if allocated(section) && section.flags & SHF_WRITE != 0 {
+                size = round_up(size, cmp::max(section.align, 1) as usize);
+                layout[index] = Some(size);
+                size += section.size as usize;
+            }
+        }
+        let size = cmp::max(round_up(size, page_size()), page_size());
+
+        let memory = unsafe {
+            let memory = libc::mmap(
+                ptr::null_mut(),
+                size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            if memory == libc::MAP_FAILED {
+                return Err("could not map memory for the object".to_owned());
+            }
+            memory as *mut u8
+        };
+        let mut loaded = LoadedObject {
+            memory: memory,
+            size: size,
+            symbols: HashMap::new(),
+        };
+
+        for (index, section) in elf.sections.iter().enumerate() {
+            if let Some(offset) = layout[index] {
+                if section.kind != SHT_NOBITS {
+                    let data = elf.section_data(section)?;
+                    let start = unsafe { memory.add(offset) };
+                    unsafe { ptr::copy_nonoverlapping(data.as_ptr(), start, data.len()) };
+                }
+            }
+        }
+
+        // Addresses of the symbols, None for the ones of sections that were not loaded
+        let address = |symbol: &Symbol| -> Result<Option<usize>, String> {
+            match symbol.section {
+                SHN_UNDEF => {
+                    let found = resolve(&symbol.name).or_else(|| unsafe { lookup(&symbol.name) });
+                    match found {
+                        Some(address) => Ok(Some(address)),
+                        None => Err(format!("undefined symbol {}", symbol.name)),
+                    }
+                }
+                SHN_ABS => Ok(Some(symbol.value as usize)),
+                section => Ok(layout.get(section as usize).cloned().and_then(|offset| {
+                    offset.map(|offset| memory as usize + offset + symbol.value as usize)
+                })),
+            }
+        };
+        for (slot, &symbol) in imports.iter().enumerate() {
+            let target = address(&symbols[symbol])?.ok_or("import of a section not loaded")?;
+            unsafe {
+                let slot = memory.add(got + slot * 8);
+                ptr::write_unaligned(slot as *mut u64, target as u64);
+            }
+        }
+        for index in 0..imports.len() {
+            let slot = memory as usize + got + index * 8;
+            let stub = unsafe { memory.add(stubs + index * STUB_SIZE) };
+            // jmp [rip + disp32] to the GOT slot, which is just as far away for every stub
+            let displacement = slot as i64 - (stub as i64 + 6);
+            unsafe {
+                ptr::copy_nonoverlapping([0xff, 0x25].as_ptr(), stub, 2);
+                ptr::write_unaligned(stub.add(2) as *mut i32, displacement as i32);
+            }
+        }
+
+        for relocation in &relocations {
+            let offset = match layout[relocation.section] {
+                Some(offset) => offset + relocation.offset as usize,
+                None => continue,
+            };
+            let place = unsafe { memory.add(offset) };
+            let p = place as i64;
+            let symbol = &symbols[relocation.symbol];
+            let a = relocation.addend;
+            let slot = import_index.get(&relocation.symbol).map(|&index| {
+                (memory as usize + got + index * 8) as i64
+            });
+            let stub = import_index.get(&relocation.symbol).map(|&index| {
+                (memory as usize + stubs + index * STUB_SIZE) as i64
+            });
+            let s = || -> Result<i64, String> {
+                match address(symbol)? {
+                    Some(address) => Ok(address as i64),
+                    None => Err(format!("relocation against section not loaded: {}", symbol.name)),
+                }
+            };
+            unsafe {
+                match relocation.kind {
+                    R_X86_64_NONE => (),
+                    R_X86_64_64 => ptr::write_unaligned(place as *mut i64, s()? + a),
+                    R_X86_64_PC64 => ptr::write_unaligned(place as *mut i64, s()? + a - p),
+                    R_X86_64_PC32 | R_X86_64_PLT32 => {
+                        let target = match stub {
+                            Some(stub) if symbol.section == SHN_UNDEF => stub,
+                            _ => s()?,
+                        };
+                        write_i32(place, target + a - p)?;
+                    }
+                    R_X86_64_GOTPCREL | R_X86_64_GOTPCRELX | R_X86_64_REX_GOTPCRELX => {
+                        let slot = slot.expect("every GOT access has a slot");
+                        write_i32(place, slot + a - p)?;
+                    }
+                    R_X86_64_32 => {
+                        let value = s()? + a;
+                        if value < 0 || value > i64::from(u32::MAX) {
+                            return Err("absolute 32 bit relocation out of range".to_owned());
+                        }
+                        ptr::write_unaligned(place as *mut u32, value as u32);
+                    }
+                    R_X86_64_32S => write_i32(place, s()? + a)?,
+                    kind => return Err(format!("unsupported relocation type {}", kind)),
+                }
+            }
+        }
+
+        for symbol in symbols.iter().filter(|symbol| symbol.binding != STB_LOCAL) {
+            if symbol.section != SHN_UNDEF && symbol.kind != STT_SECTION {
+                if let Some(address) = address(symbol)? {
+                    loaded.symbols.insert(symbol.name.clone(), address);
+                }
+            }
+        }
+
+        let protected = unsafe {
+            libc::mprotect(
+                memory as *mut libc::c_void,
+                read_only_end,
+                libc::PROT_READ | libc::PROT_EXEC,
+            )
+        };
+        if protected != 0 {
+            return Err("could not make the code executable".to_owned());
+        }
+        Ok(loaded)
+    }
+
+    /// Address of the global symbol `name`.
+    pub fn symbol(&self, name: &str) -> Option<usize> {
+        self.symbols.get(name).cloned()
+    }
+}
+
+impl Drop for LoadedObject {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.memory as *mut libc::c_void, self.size) };
+    }
+}
+
+/// Looks up a symbol of the process, such as `memcpy`.
+unsafe fn lookup(name: &str) -> Option<usize> {
+    let name = CString::new(name).ok()?;
+    let address = libc::dlsym(libc::RTLD_DEFAULT, name.as_ptr());
+    if address.is_null() {
+        None
+    } else {
+        Some(address as usize)
+    }
+}
+
+unsafe fn write_i32(place: *mut u8, value: i64) -> Result<(), String> {
+    if value < i64::from(i32::MIN) || value > i64::from(i32::MAX) {
+        return Err("relative relocation out of range".to_owned());
+    }
+    ptr::write_unaligned(place as *mut i32, value as i32);
+    Ok(())
+}
+
+fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+fn round_up(value: usize, alignment: usize) -> usize {
+    (value + alignment - 1) / alignment * alignment
+}
+
+/// The parts of an ELF file a loader needs.
+struct Elf<'a> {
+    data: &'a [u8],
+    sections: Vec<Section>,
+}
+
+#[derive(Clone, Debug)]
+struct Section {
+    kind: u32,
+    flags: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+    info: u32,
+    align: u64,
+}
+
+#[derive(Clone, Debug)]
+struct Symbol {
+    name: String,
+    binding: u8,
+    kind: u8,
+    section: u16,
+    value: u64,
+}
+
+struct Relocation {
+    /// The section the relocation applies to.
+    section: usize,
+    offset: u64,
+    symbol: usize,
+    kind: u32,
+    addend: i64,
+}
+
+impl<'a> Elf<'a> {
+    fn parse(data: &'a [u8]) -> Result<Self, String> {
+        if data.len() < 64 || &data[..4] != ELF_MAGIC || data[4] != ELFCLASS64 ||
+            data[5] != ELFDATA2LSB
+        {
+            return Err("not a 64 bit little endian ELF file".to_owned());
+        }
+        if read_u16(data, 16)? != ET_REL || read_u16(data, 18)? != EM_X86_64 {
+            return Err("not a relocatable object for x86-64".to_owned());
+        }
+        let offset = read_u64(data, 0x28)? as usize;
+        let entry_size = read_u16(data, 0x3a)? as usize;
+        let count = read_u16(data, 0x3c)? as usize;
+        if entry_size < 64 {
+            return Err("invalid section header size".to_owned());
+        }
+        let mut sections = Vec::with_capacity(count);
+        for index in 0..count {
+            let header = offset + index * entry_size;
+            sections.push(Section {
+                kind: read_u32(data, header + 4)?,
+                flags: read_u64(data, header + 8)?,
+                offset: read_u64(data, header + 24)?,
+                size: read_u64(data, header + 32)?,
+                link: read_u32(data, header + 40)?,
+                info: read_u32(data, header + 44)?,
+                align: read_u64(data, header + 48)?,
+            });
+        }
+        Ok(Elf {
+            data: data,
+            sections: sections,
+        })
+    }
+
+    fn section_data(&self, section: &Section) -> Result<&'a [u8], String> {
+        let start = section.offset as usize;
+        self.data
+            .get(start..start + section.size as usize)
+            .ok_or_else(|| "section out of bounds".to_owned())
+    }
+
+    fn symbols(&self) -> Result<Vec<Symbol>, String> {
+        let table = match self.sections.iter().find(|section| section.kind == SHT_SYMTAB) {
+            Some(table) => table,
+            None => return Ok(Vec::new()),
+        };
+        let data = self.section_data(table)?;
+        let names = self.sections.get(table.link as usize).ok_or("missing string table")?;
+        let names = self.section_data(names)?;
+        let mut symbols = Vec::with_capacity(data.len() / 24);
+        for entry in data.chunks(24).filter(|entry| entry.len() == 24) {
+            let name = read_u32(entry, 0)? as usize;
+            let end = names[name.min(names.len())..]
+                .iter()
+                .position(|&byte| byte == 0)
+                .ok_or("unterminated symbol name")?;
+            symbols.push(Symbol {
+                name: String::from_utf8_lossy(&names[name..name + end]).into_owned(),
+                binding: entry[4] >> 4,
+                kind: entry[4] & 0xf,
+                section: read_u16(entry, 6)?,
+                value: read_u64(entry, 8)?,
+            });
+        }
+        Ok(symbols)
+    }
+
+    fn relocations(&self) -> Result<Vec<Relocation>, String> {
+        let mut relocations = Vec::new();
+        for section in self.sections.iter().filter(|section| section.kind == SHT_RELA) {
+            let target = section.info as usize;
+            if target >= self.sections.len() {
+                return Err("relocations for a missing section".to_owned());
+            }
+            for entry in self.section_data(section)?.chunks(24).filter(|entry| entry.len() == 24) {
+                let info = read_u64(entry, 8)?;
+                relocations.push(Relocation {
+                    section: target,
+                    offset: read_u64(entry, 0)?,
+                    symbol: (info >> 32) as usize,
+                    kind: info as u32,
+                    addend: read_u64(entry, 16)? as i64,
+                });
+            }
+        }
+        Ok(relocations)
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, String> {
+    match data.get(offset..offset + 2) {
+        Some(bytes) => Ok(u16::from(bytes[0]) | u16::from(bytes[1]) << 8),
+        None => Err("unexpected end of object".to_owned()),
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, String> {
+    Ok(u32::from(read_u16(data, offset)?) | u32::from(read_u16(data, offset + 2)?) << 16)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64, String> {
+    Ok(u64::from(read_u32(data, offset)?) | u64::from(read_u32(data, offset + 4)?) << 32)
+}
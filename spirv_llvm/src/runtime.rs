@@ -0,0 +1,208 @@
+//! Runtime helpers for operations LLVM has no intrinsic for, or Cranelift no instruction.
+//!
+//! Generated code calls these by their (unmangled) name, so whoever executes it has to resolve
+//! them to the functions in here. Half precision variants do not exist, shaders convert to
+//! single precision and back around the call.
+use std::sync::atomic::{self, Ordering};
+
+use abi::Resources;
+
+macro_rules! unary_helpers {
+    ($($name_f32:ident, $name_f64:ident => $method:ident;)*) => {
+        $(
+            #[no_mangle]
+            pub extern "C" fn $name_f32(x: f32) -> f32 {
+                x.$method()
+            }
+
+            #[no_mangle]
+            pub extern "C" fn $name_f64(x: f64) -> f64 {
+                x.$method()
+            }
+        )*
+    }
+}
+
+unary_helpers! {
+    __spirv_tan_f32, __spirv_tan_f64 => tan;
+    __spirv_asin_f32, __spirv_asin_f64 => asin;
+    __spirv_acos_f32, __spirv_acos_f64 => acos;
+    __spirv_atan_f32, __spirv_atan_f64 => atan;
+    __spirv_sinh_f32, __spirv_sinh_f64 => sinh;
+    __spirv_cosh_f32, __spirv_cosh_f64 => cosh;
+    __spirv_tanh_f32, __spirv_tanh_f64 => tanh;
+    __spirv_asinh_f32, __spirv_asinh_f64 => asinh;
+    __spirv_acosh_f32, __spirv_acosh_f64 => acosh;
+    __spirv_atanh_f32, __spirv_atanh_f64 => atanh;
+    // Only Cranelift needs these, LLVM has intrinsics for them
+    __spirv_sin_f32, __spirv_sin_f64 => sin;
+    __spirv_cos_f32, __spirv_cos_f64 => cos;
+    __spirv_exp_f32, __spirv_exp_f64 => exp;
+    __spirv_log_f32, __spirv_log_f64 => ln;
+    __spirv_exp2_f32, __spirv_exp2_f64 => exp2;
+    __spirv_log2_f32, __spirv_log2_f64 => log2;
+    __spirv_round_f32, __spirv_round_f64 => round;
+}
+
+#[no_mangle]
+pub extern "C" fn __spirv_atan2_f32(y: f32, x: f32) -> f32 {
+    y.atan2(x)
+}
+
+#[no_mangle]
+pub extern "C" fn __spirv_atan2_f64(y: f64, x: f64) -> f64 {
+    y.atan2(x)
+}
+
+#[no_mangle]
+pub extern "C" fn __spirv_pow_f32(x: f32, y: f32) -> f32 {
+    x.powf(y)
+}
+
+#[no_mangle]
+pub extern "C" fn __spirv_pow_f64(x: f64, y: f64) -> f64 {
+    x.powf(y)
+}
+
+/// The remainder of OpFRem, with the sign of `x`.
+#[no_mangle]
+pub extern "C" fn __spirv_fmod_f32(x: f32, y: f32) -> f32 {
+    x % y
+}
+
+#[no_mangle]
+pub extern "C" fn __spirv_fmod_f64(x: f64, y: f64) -> f64 {
+    x % y
+}
+
+/// Rounds `x` to half precision and returns its bits, for packing half floats.
+#[cfg(feature = "cranelift")]
+#[no_mangle]
+pub extern "C" fn __spirv_f32_to_f16(x: f32) -> u32 {
+    u32::from(::interp::f16_from_f32(x))
+}
+
+#[cfg(feature = "cranelift")]
+#[no_mangle]
+pub extern "C" fn __spirv_f16_to_f32(bits: u32) -> f32 {
+    ::interp::f16_to_f32(bits as u16)
+}
+
+/// Splits `x` into a mantissa in [0.5, 1) and a power of two. Zero, infinity and NaN are returned
+/// unchanged with an exponent of 0.
+pub fn frexp(x: f64) -> (f64, i32) {
+    if x == 0.0 || !x.is_finite() {
+        return (x, 0);
+    }
+    if !x.is_normal() {
+        // Scale denormals into the normal range first
+        let (mantissa, exponent) = frexp(x * 2f64.powi(54));
+        return (mantissa, exponent - 54);
+    }
+    let bits = x.to_bits();
+    let exponent = ((bits >> 52) & 0x7ff) as i32 - 1022;
+    let mantissa = f64::from_bits((bits & !(0x7ff << 52)) | (1022 << 52));
+    (mantissa, exponent)
+}
+
+/// Returns `x * 2^exponent` without overflowing in the factor.
+pub fn ldexp(mut x: f64, mut exponent: i32) -> f64 {
+    while exponent > 1023 && x.is_finite() && x != 0.0 {
+        x *= 2f64.powi(1023);
+        exponent -= 1023;
+    }
+    while exponent < -1022 && x.is_finite() && x != 0.0 {
+        x *= 2f64.powi(-1022);
+        exponent += 1022;
+    }
+    x * 2f64.powi(exponent.clamp(-1022, 1023))
+}
+
+// Every single precision number is a normal double, and all results are exact in double
+// precision, so the single precision variants can go through the double ones.
+
+#[no_mangle]
+pub extern "C" fn __spirv_frexp_mantissa_f32(x: f32) -> f32 {
+    frexp(x as f64).0 as f32
+}
+
+#[no_mangle]
+pub extern "C" fn __spirv_frexp_mantissa_f64(x: f64) -> f64 {
+    frexp(x).0
+}
+
+#[no_mangle]
+pub extern "C" fn __spirv_frexp_exponent_f32(x: f32) -> i32 {
+    frexp(x as f64).1
+}
+
+#[no_mangle]
+pub extern "C" fn __spirv_frexp_exponent_f64(x: f64) -> i32 {
+    frexp(x).1
+}
+
+#[no_mangle]
+pub extern "C" fn __spirv_ldexp_f32(x: f32, exponent: i32) -> f32 {
+    ldexp(x as f64, exponent) as f32
+}
+
+#[no_mangle]
+pub extern "C" fn __spirv_ldexp_f64(x: f64, exponent: i32) -> f64 {
+    ldexp(x, exponent)
+}
+
+/// Waits at an OpControlBarrier for the other invocations of the workgroup, see
+/// `Resources::barrier`. Every execution scope waits for the whole workgroup, which is the
+/// widest scope a compute shader can wait for.
+///
+/// Unsafe because `resources` has to point to valid `Resources`.
+#[no_mangle]
+pub unsafe extern "C" fn __spirv_control_barrier(resources: *const Resources) {
+    // Other workgroups may run on other threads
+    atomic::fence(Ordering::SeqCst);
+    if let Some(barrier) = (*resources).barrier {
+        barrier((*resources).barrier_data);
+    }
+}
+
+#[cfg(any(feature = "llvm", feature = "cranelift"))]
+macro_rules! symbols {
+    ($($name:ident),*) => {
+        vec![$((stringify!($name), $name as *const () as usize)),*]
+    }
+}
+
+/// Names and addresses of all helpers, for registering them with a JIT.
+#[cfg(any(feature = "llvm", feature = "cranelift"))]
+pub fn symbols() -> Vec<(&'static str, usize)> {
+    #[cfg_attr(not(feature = "cranelift"), allow(unused_mut))]
+    let mut symbols = symbols!(
+        __spirv_tan_f32, __spirv_tan_f64,
+        __spirv_asin_f32, __spirv_asin_f64,
+        __spirv_acos_f32, __spirv_acos_f64,
+        __spirv_atan_f32, __spirv_atan_f64,
+        __spirv_sinh_f32, __spirv_sinh_f64,
+        __spirv_cosh_f32, __spirv_cosh_f64,
+        __spirv_tanh_f32, __spirv_tanh_f64,
+        __spirv_asinh_f32, __spirv_asinh_f64,
+        __spirv_acosh_f32, __spirv_acosh_f64,
+        __spirv_atanh_f32, __spirv_atanh_f64,
+        __spirv_atan2_f32, __spirv_atan2_f64,
+        __spirv_frexp_mantissa_f32, __spirv_frexp_mantissa_f64,
+        __spirv_frexp_exponent_f32, __spirv_frexp_exponent_f64,
+        __spirv_ldexp_f32, __spirv_ldexp_f64,
+        __spirv_sin_f32, __spirv_sin_f64,
+        __spirv_cos_f32, __spirv_cos_f64,
+        __spirv_exp_f32, __spirv_exp_f64,
+        __spirv_log_f32, __spirv_log_f64,
+        __spirv_exp2_f32, __spirv_exp2_f64,
+        __spirv_log2_f32, __spirv_log2_f64,
+        __spirv_round_f32, __spirv_round_f64,
+        __spirv_pow_f32, __spirv_pow_f64,
+        __spirv_fmod_f32, __spirv_fmod_f64,
+        __spirv_control_barrier
+    );
+    #[cfg(feature = "cranelift")]
+    symbols.extend(symbols!(__spirv_f32_to_f16, __spirv_f16_to_f32));
+    symbols
+}
@@ -0,0 +1,123 @@
+//! Gives specialization constants the values the application picked when creating a pipeline.
+//!
+//! Specialized modules only have plain constants left, apart from OpSpecConstantOp, which every
+//! backend folds from the constants it refers to. Branches on specialization constants thus end
+//! up branching on constants, which LLVM eliminates before the code is compiled.
+use std::collections::HashMap;
+
+use rspirv::mr::{Instruction, Module, Operand};
+use spirv_headers::*;
+use trans::*;
+use {Location, TranspilerError};
+
+/// Returns a copy of `module` in which the specialization constants are replaced by constants.
+///
+/// `values` holds the bytes of the value of every specialization constant that is overridden,
+/// by SpecId, laid out like in `VkSpecializationInfo`: booleans take 4 bytes and are true if
+/// not 0, all other scalars take as many bytes as they are wide. Constants without a value
+/// keep their default.
+pub fn specialize(
+    module: &Module,
+    values: &HashMap<u32, Vec<u8>>,
+) -> Result<Module, TranspilerError> {
+    let mut spec_ids = HashMap::new();
+    for inst in &module.annotations {
+        if inst.class.opcode == Op::Decorate &&
+            inst.operands.get(1) == Some(&Operand::Decoration(Decoration::SpecId))
+        {
+            let id = operand_id(inst, 0).map_err(|error| error.at(location(inst)))?;
+            let spec_id = operand_u32(inst, 2).map_err(|error| error.at(location(inst)))?;
+            spec_ids.insert(id, spec_id);
+        }
+    }
+
+    let mut types = HashMap::new();
+    for inst in &module.types_global_values {
+        if let (Some(id), None) = (inst.result_id, inst.result_type) {
+            types.insert(id, inst);
+        }
+    }
+
+    let mut specialized = module.clone();
+    // The SpecIds would decorate plain constants now
+    specialized.annotations.retain(|inst| {
+        inst.class.opcode != Op::Decorate ||
+            inst.operands.get(1) != Some(&Operand::Decoration(Decoration::SpecId))
+    });
+    for inst in &mut specialized.types_global_values {
+        let value = match inst.result_id.and_then(|id| spec_ids.get(&id)) {
+            Some(spec_id) => values.get(spec_id).map(|value| (*spec_id, value)),
+            None => None,
+        };
+        specialize_constant(inst, value, &types).map_err(|error| error.at(location(inst)))?;
+    }
+    Ok(specialized)
+}
+
+/// Turns `inst` into a constant if it is a specialization constant, taking `value` if given.
+fn specialize_constant(
+    inst: &mut Instruction,
+    value: Option<(u32, &Vec<u8>)>,
+    types: &HashMap<Word, &Instruction>,
+) -> Result<(), TranspilerError> {
+    let opcode = match inst.class.opcode {
+        Op::SpecConstantTrue | Op::SpecConstantFalse => {
+            let default = inst.class.opcode == Op::SpecConstantTrue;
+            let value = match value {
+                Some((spec_id, value)) => bytes_to_u64(spec_id, value, 32)? != 0,
+                None => default,
+            };
+            if value { Op::ConstantTrue } else { Op::ConstantFalse }
+        }
+        Op::SpecConstant => {
+            if let Some((spec_id, value)) = value {
+                let ty = result_type(inst)?;
+                let ty = types.get(&ty).ok_or(TranspilerError::UndefinedId(ty))?;
+                let float = match ty.class.opcode {
+                    Op::TypeInt => false,
+                    Op::TypeFloat => true,
+                    _ => return Err(TranspilerError::InvalidInstruction(Op::SpecConstant)),
+                };
+                let width = operand_u32(ty, 0)?;
+                let bits = bytes_to_u64(spec_id, value, width)?;
+                // Narrower values are given by their bit pattern, like in the binary
+                inst.operands = vec![
+                    match (float, width) {
+                        (true, 64) => Operand::LiteralFloat64(f64::from_bits(bits)),
+                        (false, 64) => Operand::LiteralInt64(bits),
+                        _ => Operand::LiteralInt32(bits as u32),
+                    },
+                ];
+            }
+            Op::Constant
+        }
+        Op::SpecConstantComposite => Op::ConstantComposite,
+        _ => return Ok(()),
+    };
+    inst.class = rspirv::grammar::CoreInstructionTable::get(opcode);
+    Ok(())
+}
+
+/// Reads the `width` bits wide value of specialization constant `spec_id`, which the
+/// application wrote in the byte order of the host.
+fn bytes_to_u64(spec_id: u32, bytes: &[u8], width: u32) -> Result<u64, TranspilerError> {
+    if bytes.len() * 8 != width as usize {
+        return Err(TranspilerError::InvalidSpecialization(spec_id));
+    }
+    let mut buffer = [0; 8];
+    Ok(if cfg!(target_endian = "little") {
+        buffer[..bytes.len()].copy_from_slice(bytes);
+        u64::from_le_bytes(buffer)
+    } else {
+        buffer[8 - bytes.len()..].copy_from_slice(bytes);
+        u64::from_be_bytes(buffer)
+    })
+}
+
+fn location(inst: &Instruction) -> Location {
+    Location {
+        opcode: inst.class.opcode,
+        result_id: inst.result_id,
+        line: None,
+    }
+}
@@ -0,0 +1,184 @@
+//! The machine LLVM generates code for, and how hard it optimizes that code.
+//!
+//! By default code is compiled for the host, using every vector extension it has. Machines of a
+//! build farm differ in those, so `SPIRV_LLVM_TARGET_FEATURES` and `SPIRV_LLVM_TARGET_CPU`
+//! pin them down to get the same code everywhere.
+use std::env;
+use std::ffi::CString;
+use std::ptr;
+
+use llvm_sys::target_machine::*;
+
+use module::take_message;
+use TranspilerError;
+
+/// Environment variable that sets the LLVM optimization level, 0 to 3.
+pub const OPT_LEVEL_VARIABLE: &str = "SPIRV_LLVM_OPT_LEVEL";
+/// Environment variable that replaces the detected target features by an LLVM feature string,
+/// such as `+sse4.1,+avx2,+fma`.
+pub const TARGET_FEATURES_VARIABLE: &str = "SPIRV_LLVM_TARGET_FEATURES";
+/// Environment variable that names the LLVM CPU to generate code for, such as `skylake`.
+pub const TARGET_CPU_VARIABLE: &str = "SPIRV_LLVM_TARGET_CPU";
+
+/// The optimization level if `SPIRV_LLVM_OPT_LEVEL` does not say otherwise.
+pub const DEFAULT_OPT_LEVEL: u32 = 2;
+
+/// What LLVM generates code for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Target {
+    /// The LLVM target triple, e.g. `x86_64-unknown-linux-gnu`.
+    pub triple: String,
+    /// The LLVM name of the CPU to tune for, `generic` for none in particular.
+    pub cpu: String,
+    /// LLVM target features on top of the ones of `cpu`, e.g. `+avx2,+fma`.
+    pub features: String,
+}
+
+impl Target {
+    /// The CPU we are running on, with all the extensions it supports.
+    pub fn host() -> Self {
+        unsafe {
+            Target {
+                triple: take_message(LLVMGetDefaultTargetTriple()),
+                cpu: take_message(LLVMGetHostCPUName()),
+                features: host_features(),
+            }
+        }
+    }
+
+    /// The host, with the CPU and features `SPIRV_LLVM_TARGET_CPU` and
+    /// `SPIRV_LLVM_TARGET_FEATURES` ask for. Overriding the features without naming a CPU
+    /// picks a generic one, as the host CPU would bring its own features along.
+    pub fn from_env() -> Self {
+        let mut target = Target::host();
+        if let Some(features) = variable(TARGET_FEATURES_VARIABLE) {
+            target.features = features;
+            target.cpu = "generic".to_owned();
+        }
+        if let Some(cpu) = variable(TARGET_CPU_VARIABLE) {
+            target.cpu = cpu;
+        }
+        target
+    }
+
+    /// Whether `features` turns on `feature`, e.g. `avx2`.
+    pub fn has_feature(&self, feature: &str) -> bool {
+        // Later entries override earlier ones
+        let mut enabled = false;
+        for entry in self.features.split(',').map(str::trim) {
+            if entry.get(1..) == Some(feature) {
+                enabled = entry.starts_with('+');
+            }
+        }
+        enabled
+    }
+
+    /// Number of invocations that fit into the widest vector registers of the target: 16 with
+    /// AVX-512, 8 with AVX2 and 4 otherwise.
+    pub fn simd_lanes(&self) -> u32 {
+        if self.has_feature("avx512f") {
+            16
+        } else if self.has_feature("avx2") {
+            8
+        } else {
+            4
+        }
+    }
+
+    /// Creates an LLVM target machine for code of `opt_level`, which the caller disposes.
+    pub(crate) unsafe fn create_machine(
+        &self,
+        opt_level: u32,
+        reloc: LLVMRelocMode,
+    ) -> Result<LLVMTargetMachineRef, TranspilerError> {
+        let triple = CString::new(self.triple.as_str()).unwrap_or_default();
+        let cpu = CString::new(self.cpu.as_str()).unwrap_or_default();
+        let features = CString::new(self.features.as_str()).unwrap_or_default();
+        let mut target = ptr::null_mut();
+        let mut error = ptr::null_mut();
+        if LLVMGetTargetFromTriple(triple.as_ptr(), &mut target, &mut error) != 0 {
+            return Err(TranspilerError::CodegenFailed(take_message(error)));
+        }
+        let level = match opt_level {
+            0 => LLVMCodeGenOptLevel::LLVMCodeGenLevelNone,
+            1 => LLVMCodeGenOptLevel::LLVMCodeGenLevelLess,
+            2 => LLVMCodeGenOptLevel::LLVMCodeGenLevelDefault,
+            _ => LLVMCodeGenOptLevel::LLVMCodeGenLevelAggressive,
+        };
+        Ok(LLVMCreateTargetMachine(
+            target,
+            triple.as_ptr(),
+            cpu.as_ptr(),
+            features.as_ptr(),
+            level,
+            reloc,
+            LLVMCodeModel::LLVMCodeModelDefault,
+        ))
+    }
+}
+
+/// The optimization level `SPIRV_LLVM_OPT_LEVEL` asks for, `DEFAULT_OPT_LEVEL` if it is not set
+/// or not a level from 0 to 3.
+pub fn opt_level_from_env() -> u32 {
+    match variable(OPT_LEVEL_VARIABLE).map(|level| level.parse()) {
+        Some(Ok(level)) if level <= 3 => level,
+        _ => DEFAULT_OPT_LEVEL,
+    }
+}
+
+/// Number of invocations compiled code runs at once for the target `Target::from_env` picks.
+pub fn simd_lanes() -> u32 {
+    Target::from_env().simd_lanes()
+}
+
+/// LLVM feature string of the vector extensions the host supports.
+pub fn host_features() -> String {
+    let mut features: Vec<&str> = Vec::new();
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        macro_rules! detect {
+            ($($feature:tt),*) => {
+                $(
+                    if is_x86_feature_detected!($feature) {
+                        features.push(concat!("+", $feature));
+                    }
+                )*
+            }
+        }
+        detect!(
+            "sse3", "ssse3", "sse4.1", "sse4.2", "popcnt", "avx", "avx2", "fma", "f16c", "bmi2",
+            "lzcnt", "avx512f", "avx512dq", "avx512bw", "avx512vl"
+        );
+    }
+    features.join(",")
+}
+
+/// The value of the environment variable `name`, unless it is unset, empty or not Unicode.
+fn variable(name: &str) -> Option<String> {
+    env::var(name).ok().map(|value| value.trim().to_owned()).filter(
+        |value| !value.is_empty(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(features: &str) -> Target {
+        Target {
+            triple: "x86_64-unknown-linux-gnu".to_owned(),
+            cpu: "generic".to_owned(),
+            features: features.to_owned(),
+        }
+    }
+
+    #[test]
+    fn lanes_follow_features() {
+        assert_eq!(target("").simd_lanes(), 4);
+        assert_eq!(target("+sse4.1,+avx2,+fma").simd_lanes(), 8);
+        assert_eq!(target("+avx2, +avx512f").simd_lanes(), 16);
+        // Later entries win
+        assert_eq!(target("+avx2,+avx512f,-avx512f").simd_lanes(), 8);
+        assert!(!target("+avx512fp16").has_feature("avx512f"));
+    }
+}
@@ -1 +1,256 @@
+//! Helpers shared by the translation routines of `SpirvTranspiler`.
+//!
+//! Everything in here is free of LLVM state: operand decoding and collection of the decorations
+//! declared in the annotation section of a module.
+use std::collections::HashMap;
 
+use rspirv::mr::{Instruction, Operand};
+use spirv_headers::*;
+use TranspilerError;
+
+/// Returns the id stored in operand `index` of `inst`.
+pub fn operand_id(inst: &Instruction, index: usize) -> Result<Word, TranspilerError> {
+    match inst.operands.get(index) {
+        Some(&Operand::IdRef(id)) |
+        Some(&Operand::IdScope(id)) |
+        Some(&Operand::IdMemorySemantics(id)) => Ok(id),
+        _ => Err(TranspilerError::InvalidInstruction(inst.class.opcode)),
+    }
+}
+
+/// Returns the ids stored in all operands of `inst`, starting at `start`.
+pub fn operand_ids(inst: &Instruction, start: usize) -> Result<Vec<Word>, TranspilerError> {
+    (start..inst.operands.len())
+        .map(|index| operand_id(inst, index))
+        .collect()
+}
+
+/// Returns the 32 bit literal stored in operand `index` of `inst`.
+pub fn operand_u32(inst: &Instruction, index: usize) -> Result<u32, TranspilerError> {
+    match inst.operands.get(index) {
+        Some(&Operand::LiteralInt32(value)) |
+        Some(&Operand::LiteralExtInstInteger(value)) => Ok(value),
+        _ => Err(TranspilerError::InvalidInstruction(inst.class.opcode)),
+    }
+}
+
+/// Returns the string literal stored in operand `index` of `inst`.
+pub fn operand_str(inst: &Instruction, index: usize) -> Result<&str, TranspilerError> {
+    match inst.operands.get(index) {
+        Some(&Operand::LiteralString(ref value)) => Ok(value),
+        _ => Err(TranspilerError::InvalidInstruction(inst.class.opcode)),
+    }
+}
+
+/// Returns the storage class stored in operand `index` of `inst`.
+pub fn operand_storage_class(
+    inst: &Instruction,
+    index: usize,
+) -> Result<StorageClass, TranspilerError> {
+    match inst.operands.get(index) {
+        Some(&Operand::StorageClass(storage_class)) => Ok(storage_class),
+        _ => Err(TranspilerError::InvalidInstruction(inst.class.opcode)),
+    }
+}
+
+/// Returns the result id of `inst`, which must be present.
+pub fn result_id(inst: &Instruction) -> Result<Word, TranspilerError> {
+    inst.result_id.ok_or(
+        TranspilerError::InvalidInstruction(inst.class.opcode),
+    )
+}
+
+/// Returns the result type id of `inst`, which must be present.
+pub fn result_type(inst: &Instruction) -> Result<Word, TranspilerError> {
+    inst.result_type.ok_or(
+        TranspilerError::InvalidInstruction(inst.class.opcode),
+    )
+}
+
+/// Whether `opcode` ends a block.
+pub fn is_terminator(opcode: Op) -> bool {
+    match opcode {
+        Op::Branch | Op::BranchConditional | Op::Switch | Op::Return | Op::ReturnValue |
+        Op::Kill | Op::Unreachable => true,
+        _ => false,
+    }
+}
+
+/// Returns the labels `inst` branches to.
+pub fn branch_targets(inst: &Instruction) -> Result<Vec<Word>, TranspilerError> {
+    let targets = match inst.class.opcode {
+        Op::Branch => vec![operand_id(inst, 0)?],
+        Op::BranchConditional => vec![operand_id(inst, 1)?, operand_id(inst, 2)?],
+        Op::Switch => {
+            let mut targets = vec![operand_id(inst, 1)?];
+            let mut index = 3;
+            while index < inst.operands.len() {
+                targets.push(operand_id(inst, index)?);
+                index += 2;
+            }
+            targets
+        }
+        _ => Vec::new(),
+    };
+    Ok(targets)
+}
+
+/// The decorations of a target, with their operands.
+type DecorationList = Vec<(Decoration, Vec<Operand>)>;
+
+/// All decorations of a module, indexed by their target.
+///
+/// Decoration groups are resolved while collecting, so lookups never have to care about
+/// `OpGroupDecorate` or `OpGroupMemberDecorate`.
+#[derive(Default)]
+pub struct Decorations {
+    ids: HashMap<Word, DecorationList>,
+    members: HashMap<(Word, u32), DecorationList>,
+}
+
+impl Decorations {
+    pub fn from_annotations(annotations: &[Instruction]) -> Result<Self, TranspilerError> {
+        let mut decorations = Decorations::default();
+        let mut groups: HashMap<Word, DecorationList> = HashMap::new();
+        let mut group_ids = Vec::new();
+
+        // Decoration groups are declared before they are applied, so a group's decorations are
+        // complete once we reach its OpDecorationGroup. We still collect everything first to not
+        // depend on that.
+        for inst in annotations {
+            match inst.class.opcode {
+                Op::Decorate => {
+                    let target = operand_id(inst, 0)?;
+                    let decoration = operand_decoration(inst, 1)?;
+                    let entry = (decoration, inst.operands[2..].to_vec());
+                    decorations.ids.entry(target).or_insert_with(Vec::new).push(entry);
+                }
+                Op::MemberDecorate => {
+                    let target = operand_id(inst, 0)?;
+                    let member = operand_u32(inst, 1)?;
+                    let decoration = operand_decoration(inst, 2)?;
+                    let entry = (decoration, inst.operands[3..].to_vec());
+                    decorations
+                        .members
+                        .entry((target, member))
+                        .or_insert_with(Vec::new)
+                        .push(entry);
+                }
+                Op::DecorationGroup => group_ids.push(result_id(inst)?),
+                _ => (),
+            }
+        }
+
+        for group in group_ids {
+            let group_decorations = decorations.ids.remove(&group).unwrap_or_default();
+            groups.insert(group, group_decorations);
+        }
+
+        for inst in annotations {
+            match inst.class.opcode {
+                Op::GroupDecorate => {
+                    let group = operand_id(inst, 0)?;
+                    let group_decorations = groups.get(&group).cloned().unwrap_or_default();
+                    for target in operand_ids(inst, 1)? {
+                        decorations
+                            .ids
+                            .entry(target)
+                            .or_insert_with(Vec::new)
+                            .extend(group_decorations.iter().cloned());
+                    }
+                }
+                Op::GroupMemberDecorate => {
+                    let group = operand_id(inst, 0)?;
+                    let group_decorations = groups.get(&group).cloned().unwrap_or_default();
+                    let mut index = 1;
+                    while index + 1 < inst.operands.len() {
+                        let target = operand_id(inst, index)?;
+                        let member = operand_u32(inst, index + 1)?;
+                        decorations
+                            .members
+                            .entry((target, member))
+                            .or_insert_with(Vec::new)
+                            .extend(group_decorations.iter().cloned());
+                        index += 2;
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Ok(decorations)
+    }
+
+    /// Returns the operands of `decoration` on `id`, if `id` is decorated with it.
+    pub fn get(&self, id: Word, decoration: Decoration) -> Option<&[Operand]> {
+        self.ids.get(&id).and_then(|decorations| {
+            find_decoration(decorations, decoration)
+        })
+    }
+
+    /// Returns the operands of `decoration` on member `member` of struct `id`.
+    pub fn get_member(&self, id: Word, member: u32, decoration: Decoration) -> Option<&[Operand]> {
+        self.members.get(&(id, member)).and_then(|decorations| {
+            find_decoration(decorations, decoration)
+        })
+    }
+
+    pub fn has_member(&self, id: Word, member: u32, decoration: Decoration) -> bool {
+        self.get_member(id, member, decoration).is_some()
+    }
+
+    /// Returns the single literal operand of `decoration` on `id` (e.g. `Location`).
+    pub fn literal(&self, id: Word, decoration: Decoration) -> Option<u32> {
+        self.get(id, decoration).and_then(first_literal)
+    }
+
+    /// Returns the single literal operand of `decoration` on a struct member (e.g. `Offset`).
+    pub fn member_literal(&self, id: Word, member: u32, decoration: Decoration) -> Option<u32> {
+        self.get_member(id, member, decoration).and_then(
+            first_literal,
+        )
+    }
+
+    /// Returns the BuiltIn decoration of `id`.
+    pub fn builtin(&self, id: Word) -> Option<BuiltIn> {
+        self.get(id, Decoration::BuiltIn).and_then(first_builtin)
+    }
+
+    /// Returns the BuiltIn decoration of member `member` of struct `id`.
+    pub fn member_builtin(&self, id: Word, member: u32) -> Option<BuiltIn> {
+        self.get_member(id, member, Decoration::BuiltIn).and_then(
+            first_builtin,
+        )
+    }
+}
+
+fn operand_decoration(inst: &Instruction, index: usize) -> Result<Decoration, TranspilerError> {
+    match inst.operands.get(index) {
+        Some(&Operand::Decoration(decoration)) => Ok(decoration),
+        _ => Err(TranspilerError::InvalidInstruction(inst.class.opcode)),
+    }
+}
+
+fn find_decoration(
+    decorations: &[(Decoration, Vec<Operand>)],
+    decoration: Decoration,
+) -> Option<&[Operand]> {
+    decorations
+        .iter()
+        .find(|&&(dec, _)| dec == decoration)
+        .map(|&(_, ref operands)| operands.as_slice())
+}
+
+fn first_literal(operands: &[Operand]) -> Option<u32> {
+    match operands.first() {
+        Some(&Operand::LiteralInt32(value)) => Some(value),
+        _ => None,
+    }
+}
+
+fn first_builtin(operands: &[Operand]) -> Option<BuiltIn> {
+    match operands.first() {
+        Some(&Operand::BuiltIn(builtin)) => Some(builtin),
+        _ => None,
+    }
+}
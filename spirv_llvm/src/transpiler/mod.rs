@@ -0,0 +1,2396 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::iter;
+use std::ptr;
+
+use rspirv;
+use rspirv::mr::*;
+use spirv_headers::*;
+use llvm_sys::core::*;
+use llvm_sys::prelude::*;
+use llvm_sys::target::{LLVMABISizeOfType, LLVMGetModuleDataLayout};
+use llvm_sys::{LLVMAtomicOrdering, LLVMAttributeFunctionIndex, LLVMIntPredicate, LLVMLinkage,
+               LLVMRealPredicate, LLVMTypeKind};
+use abi;
+use debug_info::{self, DebugInfo, Encoding};
+use glsl;
+use ir::*;
+use trans::*;
+use validate::validate;
+use {LlvmModule, Location, SourceLine, TranspilerError};
+
+use self::simd::{Address, Slots};
+
+mod simd;
+
+/// Name of the LLVM struct type that holds the Workgroup variables of a module.
+pub const WORKGROUP_TYPE: &str = "spirv.Workgroup";
+
+/// How a SPIR-V type is laid out in memory.
+///
+/// Values in registers always use the same LLVM type, but memory that is shared with the
+/// application (uniform and storage buffers, push constants) follows the offsets and strides the
+/// module declares via decorations. Everything else uses the natural layout LLVM picks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Layout {
+    Natural,
+    /// Explicit layout. `matrix_stride` is the MatrixStride of the struct member the type is
+    /// (part of), or 0 if there is none.
+    Explicit { matrix_stride: u32 },
+    /// Layout of Input and Output variables, which start every array element, matrix column
+    /// and struct member at a new Location slot of the `Invocation`.
+    Location,
+}
+
+impl Layout {
+    pub fn for_storage_class(storage_class: StorageClass) -> Self {
+        match storage_class {
+            StorageClass::Uniform |
+            StorageClass::StorageBuffer |
+            StorageClass::PushConstant |
+            StorageClass::PhysicalStorageBuffer => Layout::Explicit { matrix_stride: 0 },
+            _ => Layout::Natural,
+        }
+    }
+}
+
+/// Extended instruction sets the transpiler understands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExtInstSet {
+    Glsl450,
+    /// Sets named `NonSemantic.*`, whose instructions can be dropped without changing the
+    /// meaning of a module.
+    NonSemantic,
+}
+
+/// Where a module scope variable that is not an LLVM global lives.
+#[derive(Clone, Debug)]
+enum Interface {
+    /// At a fixed offset in the `Invocation`.
+    Invocation(usize),
+    /// A block of built-in variables, such as gl_PerVertex. Holds the offset in the `Invocation`
+    /// of every member, or None if the built-in is not supported.
+    BuiltInBlock(Vec<Option<usize>>),
+    PushConstants,
+    /// The member with the given index of the `spirv.Workgroup` struct, which lives in the
+    /// memory `Resources::workgroup_memory` points to.
+    Workgroup(u32),
+    /// Bound through a descriptor set. For buffers the descriptor is the address of the buffer,
+    /// for images and samplers it is the value of the variable.
+    Descriptor {
+        set: u32,
+        binding: u32,
+        buffer: bool,
+    },
+}
+
+/// LLVM type of a SPIR-V type in memory.
+#[derive(Clone, Copy)]
+pub struct MemType {
+    pub ty: LLVMTypeRef,
+    /// Size in bytes. Only tracked for explicitly laid out types.
+    pub size: u32,
+    /// Set for arrays and matrices whose elements are wrapped in a `{ element, padding }` struct
+    /// to honor a stride that is larger than the element.
+    pub padded: bool,
+}
+
+/// Translates a SPIR-V module into an LLVM module.
+pub struct SpirvTranspiler<'a> {
+    spirv_mod: &'a rspirv::mr::Module,
+
+    /// Owns `ctx` and `module` until it is handed out by `into_module`.
+    llvm: Option<LlvmModule>,
+    ctx: LLVMContextRef,
+    module: LLVMModuleRef,
+    builder: LLVMBuilderRef,
+
+    decorations: Decorations,
+    names: HashMap<Word, &'a str>,
+    /// Contents of every OpString, which OpLine uses to name files.
+    strings: HashMap<Word, &'a str>,
+    /// The source position of the instruction being translated, set by OpLine.
+    line: Option<SourceLine>,
+    /// Only set if `SPIRV_LLVM_DEBUG_INFO` asks for debug info.
+    debug: Option<DebugInfo>,
+    ext_inst_sets: HashMap<Word, ExtInstSet>,
+    /// Defining instruction of every type, constant and global variable.
+    defs: HashMap<Word, &'a Instruction>,
+    /// LLVM type of every SPIR-V type when held in a register.
+    types: HashMap<Word, LLVMTypeRef>,
+    mem_types: HashMap<(Word, Layout), MemType>,
+    /// LLVM field index of every member of an explicitly laid out struct. Padding fields make
+    /// these differ from the SPIR-V member indices.
+    member_indices: HashMap<Word, Vec<u32>>,
+    /// The LLVM value of every SPIR-V result id translated so far.
+    values: HashMap<Word, LLVMValueRef>,
+    /// Pointee type and layout of every pointer. Access chains into explicitly laid out memory
+    /// may end up with a different layout than their SPIR-V type implies.
+    pointers: HashMap<Word, (Word, Layout)>,
+    /// LLVM block of every label of the function being translated.
+    blocks: HashMap<Word, LLVMBasicBlockRef>,
+    /// LLVM block holding the terminator of every SPIR-V block, which is where phis have to
+    /// expect control to come from.
+    block_ends: HashMap<Word, LLVMBasicBlockRef>,
+
+    /// Input, Output and resource variables, which are reached through the `Invocation` an
+    /// entry point is called with.
+    interface: Vec<(Word, Interface)>,
+    /// Private variables and their initializers. Every invocation has its own copy of them, a
+    /// struct that the entry point allocates and passes to every function it calls.
+    private: Vec<(Word, Option<LLVMValueRef>)>,
+    private_type: LLVMTypeRef,
+    /// Struct of all Workgroup variables, by their index in `Interface::Workgroup`.
+    workgroup_type: LLVMTypeRef,
+    entry_points: HashMap<Word, &'a str>,
+    /// Set if the module discards fragments, in which case every call has to check whether
+    /// the callee did so.
+    kills: bool,
+
+    /// The function being translated, its `Invocation` and private variables.
+    function: LLVMValueRef,
+    invocation: LLVMValueRef,
+    private_variables: LLVMValueRef,
+    /// Address of the first descriptor of arrayed descriptor bindings.
+    descriptor_arrays: HashMap<Word, LLVMValueRef>,
+
+    /// Number of invocations every function runs at once, one per SIMD lane. With a single lane
+    /// the transpiler emits plain scalar code.
+    lanes: u32,
+    /// LLVM type of every SPIR-V type in a register that holds all lanes.
+    wide_types: HashMap<Word, LLVMTypeRef>,
+    /// Constants with the value of a single lane, `values` holds them for all lanes.
+    constants: HashMap<Word, LLVMValueRef>,
+    /// Pointers of SIMD functions, which are not LLVM values.
+    addresses: HashMap<Word, Address>,
+    /// The `Invocation` of every lane and the lanes running the current block.
+    invocations: Vec<LLVMValueRef>,
+    mask: LLVMValueRef,
+    /// First block of the function being translated, where its allocas go.
+    entry_block: LLVMBasicBlockRef,
+    /// Memory masked off lanes load from and store to instead of their pointer, by type.
+    dummies: HashMap<LLVMTypeRef, LLVMValueRef>,
+    slots: Slots,
+}
+
+impl<'a> SpirvTranspiler<'a> {
+    /// Creates a transpiler for code that runs `lanes` invocations at once, which may be 1, 4, 8
+    /// or 16.
+    pub fn new(spirv_mod: &'a rspirv::mr::Module, lanes: u32) -> Result<Self, TranspilerError> {
+        match lanes {
+            1 | 4 | 8 | 16 => (),
+            _ => return Err(TranspilerError::UnsupportedLanes(lanes)),
+        }
+        validate(spirv_mod)?;
+
+        let decorations = Decorations::from_annotations(&spirv_mod.annotations)?;
+        let mut names = HashMap::new();
+        let mut strings = HashMap::new();
+        let mut source = None;
+        for debug in &spirv_mod.debugs {
+            match debug.class.opcode {
+                Op::Name => {
+                    names.insert(operand_id(debug, 0)?, operand_str(debug, 1)?);
+                }
+                Op::String => {
+                    strings.insert(result_id(debug)?, operand_str(debug, 0)?);
+                }
+                Op::Source if debug.operands.len() > 2 => source = Some(operand_id(debug, 2)?),
+                _ => (),
+            }
+        }
+
+        let llvm = LlvmModule::with_lanes("spirv_llvm", lanes);
+        let builder = unsafe { LLVMCreateBuilderInContext(llvm.context()) };
+        let debug = if debug_info::enabled() {
+            let source = source.and_then(|file| strings.get(&file).cloned());
+            Some(unsafe { DebugInfo::new(llvm.context(), llvm.as_raw(), source) })
+        } else {
+            None
+        };
+
+        Ok(SpirvTranspiler {
+            spirv_mod: spirv_mod,
+            ctx: llvm.context(),
+            module: llvm.as_raw(),
+            llvm: Some(llvm),
+            builder: builder,
+            decorations: decorations,
+            names: names,
+            strings: strings,
+            line: None,
+            debug: debug,
+            ext_inst_sets: HashMap::new(),
+            defs: HashMap::new(),
+            types: HashMap::new(),
+            mem_types: HashMap::new(),
+            member_indices: HashMap::new(),
+            values: HashMap::new(),
+            pointers: HashMap::new(),
+            blocks: HashMap::new(),
+            block_ends: HashMap::new(),
+            interface: Vec::new(),
+            private: Vec::new(),
+            private_type: ptr::null_mut(),
+            workgroup_type: ptr::null_mut(),
+            entry_points: HashMap::new(),
+            kills: false,
+            function: ptr::null_mut(),
+            invocation: ptr::null_mut(),
+            private_variables: ptr::null_mut(),
+            descriptor_arrays: HashMap::new(),
+            lanes: lanes,
+            wide_types: HashMap::new(),
+            constants: HashMap::new(),
+            addresses: HashMap::new(),
+            invocations: Vec::new(),
+            mask: ptr::null_mut(),
+            entry_block: ptr::null_mut(),
+            dummies: HashMap::new(),
+            slots: Slots::default(),
+        })
+    }
+
+    pub fn transpile(&mut self) -> Result<(), TranspilerError> {
+        // https://github.com/KhronosGroup/SPIRV-LLVM/blob/0d6cd12d350bcaed0634bcb1f260bc3925dfdc23/lib/SPIRV/SPIRVReader.cpp#L2262
+        self.trans_ext_inst_imports()?;
+        self.trans_types_global_values()?;
+        self.trans_function_decls()?;
+        let spirv_mod = self.spirv_mod;
+        for function in &spirv_mod.functions {
+            self.trans_function(function)?;
+        }
+        if let Some(ref mut debug) = self.debug {
+            unsafe { debug.finalize() };
+        }
+        match self.llvm {
+            Some(ref llvm) => llvm.verify().map_err(TranspilerError::VerificationFailed),
+            None => Ok(()),
+        }
+    }
+
+    /// Hands out the translated module.
+    pub fn into_module(mut self) -> LlvmModule {
+        self.llvm.take().expect("module already handed out")
+    }
+
+    pub fn trans_ext_inst_imports(&mut self) -> Result<(), TranspilerError> {
+        let spirv_mod = self.spirv_mod;
+        for import in &spirv_mod.ext_inst_imports {
+            let name = operand_str(import, 0).map_err(|error| error.at(self.location(import)))?;
+            let set = if name == glsl::NAME {
+                ExtInstSet::Glsl450
+            } else if name.starts_with("NonSemantic.") {
+                ExtInstSet::NonSemantic
+            } else {
+                let error = TranspilerError::UnsupportedExtInstSet(name.to_owned());
+                return Err(error.at(self.location(import)));
+            };
+            self.ext_inst_sets.insert(result_id(import)?, set);
+        }
+        Ok(())
+    }
+
+    /// Where `inst` is, for errors that occur while translating it.
+    fn location(&self, inst: &Instruction) -> Location {
+        Location {
+            opcode: inst.class.opcode,
+            result_id: inst.result_id,
+            line: self.line.clone(),
+        }
+    }
+
+    /// Keeps track of the source position of the instructions that follow `inst`.
+    fn track_line(&mut self, inst: &Instruction) -> Result<(), TranspilerError> {
+        match inst.class.opcode {
+            Op::Line => self.line = Some(self.source_line(inst)?),
+            Op::NoLine => self.line = None,
+            _ => (),
+        }
+        if let Some(ref mut debug) = self.debug {
+            unsafe { debug.set_location(self.builder, self.line.as_ref()) };
+        }
+        Ok(())
+    }
+
+    /// The source position an OpLine names.
+    fn source_line(&self, inst: &Instruction) -> Result<SourceLine, TranspilerError> {
+        let file = operand_id(inst, 0)?;
+        Ok(SourceLine {
+            file: self.strings.get(&file).map(|file| file.to_string()),
+            line: operand_u32(inst, 1)?,
+            column: operand_u32(inst, 2)?,
+        })
+    }
+
+    fn ext_inst_set(&self, inst: &Instruction) -> Result<ExtInstSet, TranspilerError> {
+        let set = operand_id(inst, 0)?;
+        self.ext_inst_sets.get(&set).cloned().ok_or(
+            TranspilerError::UndefinedId(set),
+        )
+    }
+
+    pub fn trans_types_global_values(&mut self) -> Result<(), TranspilerError> {
+        let spirv_mod = self.spirv_mod;
+        for type_const_global in &spirv_mod.types_global_values {
+            // Iterated value is either a type, constant or global value
+            self.track_line(type_const_global)?;
+            self.trans_value(type_const_global).map_err(|error| {
+                error.at(self.location(type_const_global))
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Translates a single instruction of the types, constants and global values section.
+    pub fn trans_value(&mut self, inst: &'a Instruction) -> Result<(), TranspilerError> {
+        let opcode = inst.class.opcode;
+        if let Some(id) = inst.result_id {
+            self.defs.insert(id, inst);
+        }
+        match opcode {
+            Op::TypeVoid | Op::TypeBool | Op::TypeInt | Op::TypeFloat | Op::TypeVector |
+            Op::TypeMatrix | Op::TypeArray | Op::TypeRuntimeArray | Op::TypeStruct |
+            Op::TypePointer | Op::TypeFunction | Op::TypeImage | Op::TypeSampler |
+            Op::TypeSampledImage => {
+                self.trans_type(result_id(inst)?)?;
+            }
+            Op::ConstantTrue | Op::ConstantFalse | Op::Constant | Op::ConstantComposite |
+            Op::ConstantNull | Op::SpecConstantTrue | Op::SpecConstantFalse |
+            Op::SpecConstant | Op::SpecConstantComposite | Op::SpecConstantOp => {
+                let id = result_id(inst)?;
+                let value = self.trans_constant(inst)?;
+                self.constants.insert(id, value);
+                let value = if self.lanes > 1 {
+                    unsafe { self.const_broadcast(value, result_type(inst)?)? }
+                } else {
+                    value
+                };
+                self.values.insert(id, value);
+            }
+            Op::Variable => {
+                self.track_pointer(result_id(inst)?, result_type(inst)?)?;
+                self.trans_global_variable(inst)?;
+            }
+            Op::Undef => {
+                let ty = self.register_type(result_type(inst)?)?;
+                let value = unsafe { LLVMGetUndef(ty) };
+                self.values.insert(result_id(inst)?, value);
+            }
+            Op::Line | Op::NoLine => (),
+            Op::ExtInst if self.ext_inst_set(inst)? == ExtInstSet::NonSemantic => (),
+            _ => return Err(TranspilerError::UnsupportedInstruction(opcode)),
+        }
+        Ok(())
+    }
+
+    /// Returns the instruction defining the type, constant or global `id`.
+    pub fn def(&self, id: Word) -> Result<&'a Instruction, TranspilerError> {
+        self.defs.get(&id).cloned().ok_or(
+            TranspilerError::UndefinedId(id),
+        )
+    }
+
+    /// Returns the LLVM value of `id`.
+    pub fn value(&self, id: Word) -> Result<LLVMValueRef, TranspilerError> {
+        self.values.get(&id).cloned().ok_or(
+            TranspilerError::UndefinedId(id),
+        )
+    }
+
+    /// Returns the value of the constant `id` for a single lane.
+    pub fn constant(&self, id: Word) -> Result<LLVMValueRef, TranspilerError> {
+        self.constants.get(&id).cloned().ok_or(
+            TranspilerError::UndefinedId(id),
+        )
+    }
+
+    /// Returns the name given to `id` with OpName, or an empty string.
+    pub fn name(&self, id: Word) -> CString {
+        let name = self.names.get(&id).cloned().unwrap_or("");
+        CString::new(name).unwrap_or_default()
+    }
+
+    /// Returns the value of the integer constant `id`, e.g. the length of an array.
+    pub fn constant_u32(&self, id: Word) -> Result<u32, TranspilerError> {
+        let inst = self.def(id)?;
+        match (inst.class.opcode, inst.operands.get(0)) {
+            (Op::Constant, Some(&Operand::LiteralInt32(value))) |
+            (Op::SpecConstant, Some(&Operand::LiteralInt32(value))) => Ok(value),
+            (Op::Constant, Some(&Operand::LiteralInt64(value))) |
+            (Op::SpecConstant, Some(&Operand::LiteralInt64(value))) => Ok(value as u32),
+            _ => Err(TranspilerError::InvalidInstruction(inst.class.opcode)),
+        }
+    }
+
+    /// Returns the LLVM type of the SPIR-V type `id` when held in a register.
+    pub fn trans_type(&mut self, id: Word) -> Result<LLVMTypeRef, TranspilerError> {
+        if let Some(&ty) = self.types.get(&id) {
+            return Ok(ty);
+        }
+        let inst = self.def(id)?;
+        let ctx = self.ctx;
+        let ty = unsafe {
+            match inst.class.opcode {
+                Op::TypeVoid => LLVMVoidTypeInContext(ctx),
+                Op::TypeBool => LLVMInt1TypeInContext(ctx),
+                Op::TypeInt => LLVMIntTypeInContext(ctx, operand_u32(inst, 0)?),
+                Op::TypeFloat => {
+                    match operand_u32(inst, 0)? {
+                        16 => LLVMHalfTypeInContext(ctx),
+                        32 => LLVMFloatTypeInContext(ctx),
+                        64 => LLVMDoubleTypeInContext(ctx),
+                        _ => return Err(TranspilerError::UnsupportedType(Op::TypeFloat)),
+                    }
+                }
+                Op::TypeVector => {
+                    let component = self.trans_type(operand_id(inst, 0)?)?;
+                    LLVMVectorType(component, operand_u32(inst, 1)?)
+                }
+                Op::TypeMatrix => {
+                    // Matrices are arrays of column vectors
+                    let column = self.trans_type(operand_id(inst, 0)?)?;
+                    LLVMArrayType(column, operand_u32(inst, 1)?)
+                }
+                Op::TypeArray => {
+                    let element = self.trans_type(operand_id(inst, 0)?)?;
+                    LLVMArrayType(element, self.constant_u32(operand_id(inst, 1)?)?)
+                }
+                Op::TypeRuntimeArray => LLVMArrayType(self.trans_type(operand_id(inst, 0)?)?, 0),
+                Op::TypeStruct => {
+                    let mut members = operand_ids(inst, 0)?
+                        .into_iter()
+                        .map(|member| self.trans_type(member))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let ty = LLVMStructCreateNamed(ctx, self.struct_name(id).as_ptr());
+                    LLVMStructSetBody(ty, members.as_mut_ptr(), members.len() as u32, 0);
+                    ty
+                }
+                Op::TypePointer => {
+                    let storage_class = operand_storage_class(inst, 0)?;
+                    let pointee = operand_id(inst, 1)?;
+                    let pointee = self.trans_mem_type(pointee, Layout::for_storage_class(
+                        storage_class,
+                    ))?;
+                    LLVMPointerType(pointee.ty, 0)
+                }
+                Op::TypeFunction => {
+                    let ret = self.trans_type(operand_id(inst, 0)?)?;
+                    let mut params = operand_ids(inst, 1)?
+                        .into_iter()
+                        .map(|param| self.trans_type(param))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    LLVMFunctionType(ret, params.as_mut_ptr(), params.len() as u32, 0)
+                }
+                // Images and samplers are opaque to the shader. They are handed to the runtime,
+                // which knows how to access them.
+                Op::TypeImage => LLVMPointerType(self.opaque_type("spirv.Image"), 0),
+                Op::TypeSampler => LLVMPointerType(self.opaque_type("spirv.Sampler"), 0),
+                Op::TypeSampledImage => {
+                    let image = self.trans_type(operand_id(inst, 0)?)?;
+                    let sampler = LLVMPointerType(self.opaque_type("spirv.Sampler"), 0);
+                    let mut members = [image, sampler];
+                    LLVMStructTypeInContext(ctx, members.as_mut_ptr(), 2, 0)
+                }
+                opcode => return Err(TranspilerError::UnsupportedType(opcode)),
+            }
+        };
+        self.types.insert(id, ty);
+        Ok(ty)
+    }
+
+    /// Returns the LLVM type of the SPIR-V type `id` in a register that holds all lanes. Scalars
+    /// become vectors with an element per lane, and vectors grow accordingly (see `ir`).
+    /// Aggregates are made of wide members.
+    pub fn register_type(&mut self, id: Word) -> Result<LLVMTypeRef, TranspilerError> {
+        if self.lanes == 1 {
+            return self.trans_type(id);
+        }
+        if let Some(&ty) = self.wide_types.get(&id) {
+            return Ok(ty);
+        }
+        let inst = self.def(id)?;
+        let lanes = self.lanes;
+        let ty = unsafe {
+            match inst.class.opcode {
+                Op::TypeVoid => LLVMVoidTypeInContext(self.ctx),
+                Op::TypeBool | Op::TypeInt | Op::TypeFloat | Op::TypeImage | Op::TypeSampler => {
+                    LLVMVectorType(self.trans_type(id)?, lanes)
+                }
+                Op::TypeVector => {
+                    let component = self.trans_type(operand_id(inst, 0)?)?;
+                    LLVMVectorType(component, operand_u32(inst, 1)? * lanes)
+                }
+                Op::TypeMatrix => {
+                    LLVMArrayType(self.register_type(operand_id(inst, 0)?)?, operand_u32(inst, 1)?)
+                }
+                Op::TypeArray => {
+                    let element = self.register_type(operand_id(inst, 0)?)?;
+                    LLVMArrayType(element, self.constant_u32(operand_id(inst, 1)?)?)
+                }
+                Op::TypeRuntimeArray => LLVMArrayType(self.register_type(operand_id(inst, 0)?)?, 0),
+                Op::TypeStruct => {
+                    let mut members = operand_ids(inst, 0)?
+                        .into_iter()
+                        .map(|member| self.register_type(member))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let name = format!("{}.lanes", self.struct_name(id).to_string_lossy());
+                    let name = CString::new(name).unwrap_or_default();
+                    let ty = LLVMStructCreateNamed(self.ctx, name.as_ptr());
+                    LLVMStructSetBody(ty, members.as_mut_ptr(), members.len() as u32, 0);
+                    ty
+                }
+                Op::TypeSampledImage => {
+                    let image = self.register_type(operand_id(inst, 0)?)?;
+                    let sampler = LLVMPointerType(self.opaque_type("spirv.Sampler"), 0);
+                    let mut members = [image, LLVMVectorType(sampler, lanes)];
+                    LLVMStructTypeInContext(self.ctx, members.as_mut_ptr(), 2, 0)
+                }
+                // Only pointers to variables that hold all lanes can be passed to functions
+                Op::TypePointer => {
+                    match operand_storage_class(inst, 0)? {
+                        StorageClass::Function | StorageClass::Private => {
+                            LLVMPointerType(self.register_type(operand_id(inst, 1)?)?, 0)
+                        }
+                        storage_class => {
+                            return Err(TranspilerError::UnsupportedStorageClass(storage_class))
+                        }
+                    }
+                }
+                opcode => return Err(TranspilerError::UnsupportedType(opcode)),
+            }
+        };
+        self.wide_types.insert(id, ty);
+        Ok(ty)
+    }
+
+    /// Returns the types of the members of the composite type `id`.
+    fn member_types(&self, id: Word) -> Result<Vec<Word>, TranspilerError> {
+        let def = self.def(id)?;
+        let count = match def.class.opcode {
+            Op::TypeStruct => return operand_ids(def, 0),
+            Op::TypeVector | Op::TypeMatrix => operand_u32(def, 1)?,
+            Op::TypeArray => self.constant_u32(operand_id(def, 1)?)?,
+            _ => return Err(TranspilerError::InvalidInstruction(def.class.opcode)),
+        };
+        Ok(vec![operand_id(def, 0)?; count as usize])
+    }
+
+    /// Returns the constant `value` of SPIR-V type `ty` for all lanes.
+    unsafe fn const_broadcast(
+        &mut self,
+        value: LLVMValueRef,
+        ty: Word,
+    ) -> Result<LLVMValueRef, TranspilerError> {
+        let wide_ty = self.register_type(ty)?;
+        let lanes = self.lanes as usize;
+        let value = match self.def(ty)?.class.opcode {
+            Op::TypeVector => {
+                let mut elements = Vec::new();
+                for component in 0..LLVMGetVectorSize(LLVMTypeOf(value)) {
+                    let component = LLVMConstExtractElement(value, const_u32(self.ctx, component));
+                    elements.extend(iter::repeat(component).take(lanes));
+                }
+                LLVMConstVector(elements.as_mut_ptr(), elements.len() as u32)
+            }
+            opcode @ Op::TypeMatrix |
+            opcode @ Op::TypeArray |
+            opcode @ Op::TypeStruct => {
+                let mut members = Vec::new();
+                for (index, member_ty) in self.member_types(ty)?.into_iter().enumerate() {
+                    let mut index = [index as u32];
+                    let member = LLVMConstExtractValue(value, index.as_mut_ptr(), 1);
+                    members.push(self.const_broadcast(member, member_ty)?);
+                }
+                let count = members.len() as u32;
+                if opcode == Op::TypeStruct {
+                    LLVMConstNamedStruct(wide_ty, members.as_mut_ptr(), count)
+                } else {
+                    LLVMConstArray(LLVMGetElementType(wide_ty), members.as_mut_ptr(), count)
+                }
+            }
+            _ => {
+                let mut elements = vec![value; lanes];
+                LLVMConstVector(elements.as_mut_ptr(), elements.len() as u32)
+            }
+        };
+        Ok(value)
+    }
+
+    /// Returns the LLVM type of the SPIR-V type `id` in memory laid out according to `layout`.
+    pub fn trans_mem_type(&mut self, id: Word, layout: Layout) -> Result<MemType, TranspilerError> {
+        if layout == Layout::Natural {
+            let ty = self.trans_type(id)?;
+            return Ok(MemType {
+                ty: ty,
+                size: 0,
+                padded: false,
+            });
+        }
+        let inst = self.def(id)?;
+        // Only matrices (and arrays of them) care about the matrix stride, so everything else
+        // shares one explicit type.
+        let layout = match (inst.class.opcode, layout) {
+            (_, Layout::Location) |
+            (Op::TypeMatrix, _) |
+            (Op::TypeArray, _) |
+            (Op::TypeRuntimeArray, _) => layout,
+            _ => Layout::Explicit { matrix_stride: 0 },
+        };
+        if let Some(&mem_type) = self.mem_types.get(&(id, layout)) {
+            return Ok(mem_type);
+        }
+        let matrix_stride = match layout {
+            Layout::Explicit { matrix_stride } => matrix_stride,
+            Layout::Natural | Layout::Location => 0,
+        };
+
+        let mem_type = unsafe {
+            match inst.class.opcode {
+                Op::TypeInt | Op::TypeFloat => {
+                    MemType {
+                        ty: self.trans_type(id)?,
+                        size: operand_u32(inst, 0)? / 8,
+                        padded: false,
+                    }
+                }
+                Op::TypeVector => {
+                    // Vectors are stored as arrays, as LLVM pads vectors to their alignment
+                    // (e.g. a vec3 would occupy 16 bytes).
+                    let component = self.trans_mem_type(operand_id(inst, 0)?, layout)?;
+                    let count = operand_u32(inst, 1)?;
+                    MemType {
+                        ty: LLVMArrayType(component.ty, count),
+                        size: component.size * count,
+                        padded: false,
+                    }
+                }
+                Op::TypeMatrix => {
+                    let column_type = operand_id(inst, 0)?;
+                    let column = self.trans_mem_type(column_type, layout)?;
+                    let columns = operand_u32(inst, 1)?;
+                    let stride = if layout == Layout::Location {
+                        16 * self.locations(column_type)?
+                    } else if matrix_stride != 0 {
+                        matrix_stride
+                    } else {
+                        // No stride given, assume vec3 columns are aligned like vec4
+                        let rows = operand_u32(self.def(column_type)?, 1)?;
+                        if rows == 3 { column.size / 3 * 4 } else { column.size }
+                    };
+                    self.trans_strided_array(id, column, columns, stride)?
+                }
+                Op::TypeArray | Op::TypeRuntimeArray => {
+                    let element_type = operand_id(inst, 0)?;
+                    let element = self.trans_mem_type(element_type, layout)?;
+                    let length = if inst.class.opcode == Op::TypeArray {
+                        self.constant_u32(operand_id(inst, 1)?)?
+                    } else {
+                        0
+                    };
+                    let stride = if layout == Layout::Location {
+                        16 * self.locations(element_type)?
+                    } else {
+                        self.decorations
+                            .literal(id, Decoration::ArrayStride)
+                            .unwrap_or(element.size)
+                    };
+                    self.trans_strided_array(id, element, length, stride)?
+                }
+                Op::TypeStruct => self.trans_explicit_struct(id, inst, layout)?,
+                opcode => return Err(TranspilerError::UnsupportedType(opcode)),
+            }
+        };
+        self.mem_types.insert((id, layout), mem_type);
+        Ok(mem_type)
+    }
+
+    /// Builds an array of `length` elements which are `stride` bytes apart.
+    unsafe fn trans_strided_array(
+        &mut self,
+        id: Word,
+        element: MemType,
+        length: u32,
+        stride: u32,
+    ) -> Result<MemType, TranspilerError> {
+        if stride < element.size {
+            return Err(TranspilerError::InvalidLayout(id));
+        }
+        let padded = stride > element.size;
+        let element_ty = if padded {
+            let mut members = [
+                element.ty,
+                LLVMArrayType(LLVMInt8TypeInContext(self.ctx), stride - element.size),
+            ];
+            LLVMStructTypeInContext(self.ctx, members.as_mut_ptr(), 2, 1)
+        } else {
+            element.ty
+        };
+        Ok(MemType {
+            ty: LLVMArrayType(element_ty, length),
+            size: stride * length,
+            padded: padded,
+        })
+    }
+
+    /// Builds a packed struct that places every member at its Offset decoration, or at the
+    /// next Location for `Layout::Location`.
+    unsafe fn trans_explicit_struct(
+        &mut self,
+        id: Word,
+        inst: &'a Instruction,
+        layout: Layout,
+    ) -> Result<MemType, TranspilerError> {
+        let mut members = Vec::new();
+        let mut location_offset = 0;
+        for (index, member) in operand_ids(inst, 0)?.into_iter().enumerate() {
+            let index = index as u32;
+            if self.decorations.has_member(id, index, Decoration::RowMajor) {
+                return Err(TranspilerError::UnsupportedDecoration(Decoration::RowMajor));
+            }
+            let offset = if layout == Layout::Location {
+                let offset = location_offset;
+                location_offset += 16 * self.locations(member)?;
+                offset
+            } else {
+                self.decorations
+                    .member_literal(id, index, Decoration::Offset)
+                    .ok_or(TranspilerError::InvalidLayout(id))?
+            };
+            let member_layout = self.member_layout(id, index, layout);
+            members.push((offset, index, self.trans_mem_type(member, member_layout)?));
+        }
+        members.sort_by_key(|&(offset, _, _)| offset);
+
+        let mut fields = Vec::new();
+        let mut indices = vec![0; members.len()];
+        let mut size = 0;
+        for (offset, index, member) in members {
+            if offset < size {
+                return Err(TranspilerError::InvalidLayout(id));
+            }
+            if offset > size {
+                fields.push(LLVMArrayType(LLVMInt8TypeInContext(self.ctx), offset - size));
+            }
+            indices[index as usize] = fields.len() as u32;
+            fields.push(member.ty);
+            size = offset + member.size;
+        }
+
+        let suffix = if layout == Layout::Location {
+            "location"
+        } else {
+            "explicit"
+        };
+        let name = format!("{}.{}", self.struct_name(id).to_string_lossy(), suffix);
+        let name = CString::new(name).unwrap_or_default();
+        let ty = LLVMStructCreateNamed(self.ctx, name.as_ptr());
+        LLVMStructSetBody(ty, fields.as_mut_ptr(), fields.len() as u32, 1);
+        self.member_indices.insert(id, indices);
+        Ok(MemType {
+            ty: ty,
+            size: size,
+            padded: false,
+        })
+    }
+
+    /// Returns the layout of `member` of struct `id`, which is laid out according to `layout`.
+    fn member_layout(&self, id: Word, member: u32, layout: Layout) -> Layout {
+        match layout {
+            Layout::Explicit { .. } => {
+                let matrix_stride = self.decorations
+                    .member_literal(id, member, Decoration::MatrixStride)
+                    .unwrap_or(0);
+                Layout::Explicit { matrix_stride: matrix_stride }
+            }
+            _ => layout,
+        }
+    }
+
+    /// Returns the number of Locations a variable of type `id` occupies in the shader
+    /// interface.
+    fn locations(&self, id: Word) -> Result<u32, TranspilerError> {
+        let inst = self.def(id)?;
+        let locations = match inst.class.opcode {
+            Op::TypeVector => {
+                // 64 bit vectors with more than two components take two Locations
+                let component_width = operand_u32(self.def(operand_id(inst, 0)?)?, 0)?;
+                if component_width == 64 && operand_u32(inst, 1)? > 2 {
+                    2
+                } else {
+                    1
+                }
+            }
+            Op::TypeMatrix => operand_u32(inst, 1)? * self.locations(operand_id(inst, 0)?)?,
+            Op::TypeArray => {
+                self.constant_u32(operand_id(inst, 1)?)? * self.locations(operand_id(inst, 0)?)?
+            }
+            Op::TypeStruct => {
+                let mut locations = 0;
+                for member in operand_ids(inst, 0)? {
+                    locations += self.locations(member)?;
+                }
+                locations
+            }
+            _ => 1,
+        };
+        Ok(locations)
+    }
+
+    /// Returns the LLVM field index of `member` of the explicitly laid out struct `id`.
+    pub fn member_index(&self, id: Word, member: u32) -> u32 {
+        self.member_indices
+            .get(&id)
+            .and_then(|indices| indices.get(member as usize).cloned())
+            .unwrap_or(member)
+    }
+
+    fn struct_name(&self, id: Word) -> CString {
+        let name = match self.names.get(&id) {
+            Some(name) if !name.is_empty() => format!("struct.{}", name),
+            _ => format!("struct.{}", id),
+        };
+        CString::new(name).unwrap_or_default()
+    }
+
+    fn opaque_type(&self, name: &str) -> LLVMTypeRef {
+        let name = CString::new(name).unwrap();
+        unsafe {
+            let ty = LLVMGetTypeByName(self.module, name.as_ptr());
+            if ty.is_null() {
+                LLVMStructCreateNamed(self.ctx, name.as_ptr())
+            } else {
+                ty
+            }
+        }
+    }
+
+    /// Translates constants and specialization constants.
+    ///
+    /// Specialization constants are translated with their default value, `specialize` replaces
+    /// them with the values of a pipeline beforehand.
+    pub fn trans_constant(&mut self, inst: &'a Instruction) -> Result<LLVMValueRef, TranspilerError> {
+        let ty_id = result_type(inst)?;
+        let ty = self.trans_type(ty_id)?;
+        let value = unsafe {
+            match inst.class.opcode {
+                Op::ConstantTrue | Op::SpecConstantTrue => LLVMConstInt(ty, 1, 0),
+                Op::ConstantFalse | Op::SpecConstantFalse => LLVMConstInt(ty, 0, 0),
+                Op::Constant | Op::SpecConstant => {
+                    match inst.operands.get(0) {
+                        Some(&Operand::LiteralInt32(value)) => {
+                            if self.def(ty_id)?.class.opcode == Op::TypeFloat {
+                                // Half floats are given by their bit pattern
+                                let width = operand_u32(self.def(ty_id)?, 0)?;
+                                let bits = LLVMIntTypeInContext(self.ctx, width);
+                                LLVMConstBitCast(LLVMConstInt(bits, value as u64, 0), ty)
+                            } else {
+                                LLVMConstInt(ty, value as u64, 0)
+                            }
+                        }
+                        Some(&Operand::LiteralInt64(value)) => LLVMConstInt(ty, value, 0),
+                        Some(&Operand::LiteralFloat32(value)) => LLVMConstReal(ty, value as f64),
+                        Some(&Operand::LiteralFloat64(value)) => LLVMConstReal(ty, value),
+                        _ => return Err(TranspilerError::InvalidInstruction(inst.class.opcode)),
+                    }
+                }
+                Op::ConstantComposite | Op::SpecConstantComposite => {
+                    let mut constituents = operand_ids(inst, 0)?
+                        .into_iter()
+                        .map(|id| self.constant(id))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let count = constituents.len() as u32;
+                    match self.def(ty_id)?.class.opcode {
+                        Op::TypeVector => LLVMConstVector(constituents.as_mut_ptr(), count),
+                        Op::TypeMatrix | Op::TypeArray => {
+                            LLVMConstArray(LLVMGetElementType(ty), constituents.as_mut_ptr(), count)
+                        }
+                        Op::TypeStruct => {
+                            LLVMConstNamedStruct(ty, constituents.as_mut_ptr(), count)
+                        }
+                        _ => return Err(TranspilerError::InvalidInstruction(inst.class.opcode)),
+                    }
+                }
+                Op::ConstantNull => LLVMConstNull(ty),
+                Op::SpecConstantOp => self.trans_spec_constant_op(inst)?,
+                opcode => return Err(TranspilerError::UnsupportedInstruction(opcode)),
+            }
+        };
+        Ok(value)
+    }
+
+    /// Folds an OpSpecConstantOp using the (default or specialized) values of its operands.
+    unsafe fn trans_spec_constant_op(
+        &mut self,
+        inst: &'a Instruction,
+    ) -> Result<LLVMValueRef, TranspilerError> {
+        let opcode = match inst.operands.get(0) {
+            Some(&Operand::LiteralSpecConstantOpInteger(opcode)) => opcode,
+            _ => return Err(TranspilerError::InvalidInstruction(inst.class.opcode)),
+        };
+        let ty = self.trans_type(result_type(inst)?)?;
+        // Literal operands only appear in the operations handled separately below
+        let operand = |index: usize| operand_id(inst, index).and_then(|id| self.constant(id));
+        let literals = |start: usize| -> Result<Vec<u32>, TranspilerError> {
+            (start..inst.operands.len())
+                .map(|index| operand_u32(inst, index))
+                .collect()
+        };
+        let value = match opcode {
+            Op::IAdd => LLVMConstAdd(operand(1)?, operand(2)?),
+            Op::ISub => LLVMConstSub(operand(1)?, operand(2)?),
+            Op::IMul => LLVMConstMul(operand(1)?, operand(2)?),
+            Op::UDiv => LLVMConstUDiv(operand(1)?, operand(2)?),
+            Op::SDiv => LLVMConstSDiv(operand(1)?, operand(2)?),
+            Op::UMod => LLVMConstURem(operand(1)?, operand(2)?),
+            Op::SRem => LLVMConstSRem(operand(1)?, operand(2)?),
+            Op::ShiftRightLogical => LLVMConstLShr(operand(1)?, operand(2)?),
+            Op::ShiftRightArithmetic => LLVMConstAShr(operand(1)?, operand(2)?),
+            Op::ShiftLeftLogical => LLVMConstShl(operand(1)?, operand(2)?),
+            Op::BitwiseOr | Op::LogicalOr => LLVMConstOr(operand(1)?, operand(2)?),
+            Op::BitwiseXor | Op::LogicalNotEqual => LLVMConstXor(operand(1)?, operand(2)?),
+            Op::BitwiseAnd | Op::LogicalAnd => LLVMConstAnd(operand(1)?, operand(2)?),
+            Op::Not | Op::LogicalNot => LLVMConstNot(operand(1)?),
+            Op::SNegate => LLVMConstNeg(operand(1)?),
+            Op::LogicalEqual => LLVMConstNot(LLVMConstXor(operand(1)?, operand(2)?)),
+            Op::Select => LLVMConstSelect(operand(1)?, operand(2)?, operand(3)?),
+            Op::IEqual => LLVMConstICmp(LLVMIntPredicate::LLVMIntEQ, operand(1)?, operand(2)?),
+            Op::INotEqual => LLVMConstICmp(LLVMIntPredicate::LLVMIntNE, operand(1)?, operand(2)?),
+            Op::ULessThan => LLVMConstICmp(LLVMIntPredicate::LLVMIntULT, operand(1)?, operand(2)?),
+            Op::SLessThan => LLVMConstICmp(LLVMIntPredicate::LLVMIntSLT, operand(1)?, operand(2)?),
+            Op::UGreaterThan => {
+                LLVMConstICmp(LLVMIntPredicate::LLVMIntUGT, operand(1)?, operand(2)?)
+            }
+            Op::SGreaterThan => {
+                LLVMConstICmp(LLVMIntPredicate::LLVMIntSGT, operand(1)?, operand(2)?)
+            }
+            Op::ULessThanEqual => {
+                LLVMConstICmp(LLVMIntPredicate::LLVMIntULE, operand(1)?, operand(2)?)
+            }
+            Op::SLessThanEqual => {
+                LLVMConstICmp(LLVMIntPredicate::LLVMIntSLE, operand(1)?, operand(2)?)
+            }
+            Op::UGreaterThanEqual => {
+                LLVMConstICmp(LLVMIntPredicate::LLVMIntUGE, operand(1)?, operand(2)?)
+            }
+            Op::SGreaterThanEqual => {
+                LLVMConstICmp(LLVMIntPredicate::LLVMIntSGE, operand(1)?, operand(2)?)
+            }
+            Op::UConvert => LLVMConstIntCast(operand(1)?, ty, 0),
+            Op::SConvert => LLVMConstIntCast(operand(1)?, ty, 1),
+            Op::CompositeExtract => {
+                let mut indices = literals(2)?;
+                LLVMConstExtractValue(operand(1)?, indices.as_mut_ptr(), indices.len() as u32)
+            }
+            Op::CompositeInsert => {
+                let mut indices = literals(3)?;
+                LLVMConstInsertValue(
+                    operand(2)?,
+                    operand(1)?,
+                    indices.as_mut_ptr(),
+                    indices.len() as u32,
+                )
+            }
+            Op::VectorShuffle => {
+                let i32_ty = LLVMInt32TypeInContext(self.ctx);
+                let mut mask = literals(3)?
+                    .into_iter()
+                    .map(|index| if index == 0xFFFFFFFF {
+                        LLVMGetUndef(i32_ty)
+                    } else {
+                        LLVMConstInt(i32_ty, index as u64, 0)
+                    })
+                    .collect::<Vec<_>>();
+                let mask = LLVMConstVector(mask.as_mut_ptr(), mask.len() as u32);
+                LLVMConstShuffleVector(operand(1)?, operand(2)?, mask)
+            }
+            _ => return Err(TranspilerError::UnsupportedInstruction(opcode)),
+        };
+        Ok(value)
+    }
+
+    /// Translates a module scope OpVariable. Variables are reached through the parameters of a
+    /// function: the `Invocation`, the `Resources` it points to and the private variables.
+    pub fn trans_global_variable(&mut self, inst: &'a Instruction) -> Result<(), TranspilerError> {
+        let id = result_id(inst)?;
+        let pointee = operand_id(self.def(result_type(inst)?)?, 1)?;
+        let storage_class = operand_storage_class(inst, 0)?;
+        let initializer = match inst.operands.get(1) {
+            Some(_) => Some(self.constant(operand_id(inst, 1)?)?),
+            None => None,
+        };
+
+        let interface = match storage_class {
+            StorageClass::Private => {
+                self.private.push((id, initializer));
+                return Ok(());
+            }
+            // The memory starts out zeroed, validate rejects other initializers
+            StorageClass::Workgroup => {
+                let index = self.interface
+                    .iter()
+                    .filter(|variable| match variable.1 {
+                        Interface::Workgroup(_) => true,
+                        _ => false,
+                    })
+                    .count();
+                Interface::Workgroup(index as u32)
+            }
+            StorageClass::Input => self.trans_io_variable(id, pointee, false)?,
+            StorageClass::Output => self.trans_io_variable(id, pointee, true)?,
+            StorageClass::Uniform |
+            StorageClass::UniformConstant |
+            StorageClass::StorageBuffer => {
+                let set = self.decorations
+                    .literal(id, Decoration::DescriptorSet)
+                    .ok_or(TranspilerError::InvalidLayout(id))?;
+                let binding = self.decorations.literal(id, Decoration::Binding).ok_or(
+                    TranspilerError::InvalidLayout(id),
+                )?;
+                if abi::descriptor_set_offset(set).is_none() {
+                    return Err(TranspilerError::InvalidLayout(id));
+                }
+                Interface::Descriptor {
+                    set: set,
+                    binding: binding,
+                    buffer: storage_class != StorageClass::UniformConstant,
+                }
+            }
+            StorageClass::PushConstant => Interface::PushConstants,
+            _ => return Err(TranspilerError::UnsupportedStorageClass(storage_class)),
+        };
+        self.interface.push((id, interface));
+        Ok(())
+    }
+
+    /// Places an Input or Output variable in the `Invocation`.
+    fn trans_io_variable(
+        &mut self,
+        id: Word,
+        pointee: Word,
+        output: bool,
+    ) -> Result<Interface, TranspilerError> {
+        if let Some(builtin) = self.decorations.builtin(id) {
+            let offset = builtin_offset(builtin, output).ok_or(
+                TranspilerError::UnsupportedBuiltIn(builtin),
+            )?;
+            return Ok(Interface::Invocation(offset));
+        }
+        let def = self.def(pointee)?;
+        if def.class.opcode == Op::TypeStruct &&
+            self.decorations.member_builtin(pointee, 0).is_some()
+        {
+            let members = (0..def.operands.len() as u32)
+                .map(|member| {
+                    self.decorations.member_builtin(pointee, member).and_then(
+                        |builtin| {
+                            builtin_offset(builtin, output)
+                        },
+                    )
+                })
+                .collect();
+            return Ok(Interface::BuiltInBlock(members));
+        }
+
+        let location = self.decorations.literal(id, Decoration::Location).ok_or(
+            TranspilerError::InvalidLayout(id),
+        )?;
+        let component = self.decorations.literal(id, Decoration::Component).unwrap_or(0);
+        if location + self.locations(pointee)? > abi::MAX_LOCATIONS as u32 || component > 3 {
+            return Err(TranspilerError::InvalidLayout(id));
+        }
+        let offset = abi::location_offset(output, location).ok_or(
+            TranspilerError::InvalidLayout(id),
+        )? + 4 * component as usize;
+        // Scalars and vectors fit their slot with LLVM's layout, unless they do not start at its
+        // beginning, where LLVM's alignment would be wrong.
+        let natural = component == 0 &&
+            match def.class.opcode {
+                Op::TypeInt | Op::TypeFloat | Op::TypeVector => true,
+                _ => false,
+            };
+        if !natural {
+            self.pointers.insert(id, (pointee, Layout::Location));
+        }
+        Ok(Interface::Invocation(offset))
+    }
+
+    /// Remembers the pointee type of the pointer `id` of SPIR-V type `pointer_type`.
+    fn track_pointer(&mut self, id: Word, pointer_type: Word) -> Result<(), TranspilerError> {
+        let pointer = self.def(pointer_type)?;
+        if pointer.class.opcode == Op::TypePointer {
+            let layout = Layout::for_storage_class(operand_storage_class(pointer, 0)?);
+            self.pointers.insert(id, (operand_id(pointer, 1)?, layout));
+        }
+        Ok(())
+    }
+
+    /// Returns the pointee type and layout of the pointer `id`.
+    fn pointee(&self, id: Word) -> Result<(Word, Layout), TranspilerError> {
+        self.pointers.get(&id).cloned().ok_or(
+            TranspilerError::UndefinedId(id),
+        )
+    }
+
+    /// Returns the LLVM value of the id in operand `index` of `inst`.
+    fn operand(&self, inst: &Instruction, index: usize) -> Result<LLVMValueRef, TranspilerError> {
+        self.value(operand_id(inst, index)?)
+    }
+
+    fn block(&self, id: Word) -> Result<LLVMBasicBlockRef, TranspilerError> {
+        self.blocks.get(&id).cloned().ok_or(
+            TranspilerError::UndefinedId(id),
+        )
+    }
+
+    /// Declares all functions up front, so calls do not depend on the order of definition.
+    ///
+    /// Entry points take a pointer to the `Invocation` they run. All other functions take it as
+    /// their first parameter as well, followed by the private variables of the invocation and
+    /// the parameters of the SPIR-V function.
+    ///
+    /// With several lanes, entry points take a pointer to an array of `lanes` invocations and a
+    /// bit mask of the ones to run, see `abi::BatchFn`. All other functions take the array, the
+    /// private variables of all lanes and a vector of the lanes to run, followed by the
+    /// parameters.
+    pub fn trans_function_decls(&mut self) -> Result<(), TranspilerError> {
+        let spirv_mod = self.spirv_mod;
+        for entry_point in &spirv_mod.entry_points {
+            self.entry_points.insert(operand_id(entry_point, 1)?, operand_str(entry_point, 2)?);
+        }
+        self.kills = spirv_mod.functions.iter().any(|function| {
+            function.basic_blocks.iter().any(|block| {
+                block.instructions.iter().any(
+                    |inst| inst.class.opcode == Op::Kill,
+                )
+            })
+        });
+        self.trans_private_type()?;
+        self.trans_workgroup_type()?;
+
+        for function in &spirv_mod.functions {
+            let def = function.def.as_ref().ok_or(
+                TranspilerError::InvalidInstruction(Op::Function),
+            )?;
+            let id = result_id(def)?;
+            let entry_point = self.entry_points.get(&id).cloned();
+            let ty = self.trans_function_type(operand_id(def, 1)?, entry_point.is_some())
+                .map_err(|error| error.at(self.location(def)))?;
+            let name = match entry_point {
+                Some(name) => CString::new(name).unwrap_or_default(),
+                None => self.name(id),
+            };
+            let control = match def.operands.get(0) {
+                Some(&Operand::FunctionControl(control)) => control,
+                _ => return Err(TranspilerError::InvalidInstruction(Op::Function)),
+            };
+            unsafe {
+                let value = LLVMAddFunction(self.module, name.as_ptr(), ty);
+                // Entry points are what the runtime calls, everything else may be inlined into
+                // them and dropped.
+                if entry_point.is_none() {
+                    LLVMSetLinkage(value, LLVMLinkage::LLVMInternalLinkage);
+                }
+                if control.contains(FunctionControl::INLINE) {
+                    self.add_function_attribute(value, "alwaysinline");
+                }
+                if control.contains(FunctionControl::DONT_INLINE) {
+                    self.add_function_attribute(value, "noinline");
+                }
+                self.values.insert(id, value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds the type of a function, with the hidden parameters described at
+    /// `trans_function_decls`.
+    fn trans_function_type(
+        &mut self,
+        id: Word,
+        entry_point: bool,
+    ) -> Result<LLVMTypeRef, TranspilerError> {
+        let def = self.def(id)?;
+        let mut params = vec![self.byte_pointer_type()];
+        unsafe {
+            if entry_point && self.lanes > 1 {
+                params.push(LLVMInt32TypeInContext(self.ctx));
+                let void = LLVMVoidTypeInContext(self.ctx);
+                return Ok(LLVMFunctionType(void, params.as_mut_ptr(), 2, 0));
+            }
+            if !entry_point {
+                params.push(LLVMPointerType(self.private_type, 0));
+                if self.lanes > 1 {
+                    params.push(LLVMVectorType(LLVMInt1TypeInContext(self.ctx), self.lanes));
+                }
+            }
+        }
+        let ret = self.register_type(operand_id(def, 0)?)?;
+        for param in operand_ids(def, 1)? {
+            params.push(self.register_type(param)?);
+        }
+        Ok(unsafe { LLVMFunctionType(ret, params.as_mut_ptr(), params.len() as u32, 0) })
+    }
+
+    /// Builds the struct holding the private variables of an invocation.
+    fn trans_private_type(&mut self) -> Result<(), TranspilerError> {
+        let mut fields = Vec::new();
+        for &(id, _) in &self.private.clone() {
+            let (pointee, _) = self.pointee(id)?;
+            fields.push(self.register_type(pointee)?);
+        }
+        unsafe {
+            let name = CString::new("spirv.Private").unwrap();
+            self.private_type = LLVMStructCreateNamed(self.ctx, name.as_ptr());
+            LLVMStructSetBody(self.private_type, fields.as_mut_ptr(), fields.len() as u32, 0);
+        }
+        Ok(())
+    }
+
+    /// Builds the struct holding the Workgroup variables, whose size the driver looks up with
+    /// `LlvmModule::workgroup_memory` to allocate memory for them.
+    fn trans_workgroup_type(&mut self) -> Result<(), TranspilerError> {
+        let mut fields = Vec::new();
+        for (id, interface) in self.interface.clone() {
+            if let Interface::Workgroup(_) = interface {
+                let (pointee, layout) = self.pointee(id)?;
+                fields.push(self.trans_mem_type(pointee, layout)?.ty);
+            }
+        }
+        unsafe {
+            let name = CString::new(WORKGROUP_TYPE).unwrap();
+            self.workgroup_type = LLVMStructCreateNamed(self.ctx, name.as_ptr());
+            LLVMStructSetBody(self.workgroup_type, fields.as_mut_ptr(), fields.len() as u32, 0);
+        }
+        Ok(())
+    }
+
+    unsafe fn add_function_attribute(&self, function: LLVMValueRef, name: &str) {
+        let kind = LLVMGetEnumAttributeKindForName(name.as_ptr() as *const _, name.len());
+        let attribute = LLVMCreateEnumAttribute(self.ctx, kind, 0);
+        LLVMAddAttributeAtIndex(function, LLVMAttributeFunctionIndex, attribute);
+    }
+
+    /// Translates the body of a function declared by `trans_function_decls`.
+    pub fn trans_function(&mut self, function: &'a Function) -> Result<(), TranspilerError> {
+        self.line = None;
+        let result = self.start_debug_function(function).and_then(|()| if self.lanes > 1 {
+            unsafe { self.trans_simd_function(function) }
+        } else {
+            self.trans_scalar_function(function)
+        });
+        // Errors outside of any instruction are blamed on the function as a whole
+        self.line = None;
+        match function.def {
+            Some(ref def) => result.map_err(|error| error.at(self.location(def))),
+            None => result,
+        }
+    }
+
+    /// Gives `function` a subprogram if debug info is emitted. It starts at the first OpLine of
+    /// its body.
+    fn start_debug_function(&mut self, function: &'a Function) -> Result<(), TranspilerError> {
+        if self.debug.is_none() {
+            return Ok(());
+        }
+        let def = function.def.as_ref().ok_or(
+            TranspilerError::InvalidInstruction(Op::Function),
+        )?;
+        let id = result_id(def)?;
+        let name = match self.entry_points.get(&id).or_else(|| self.names.get(&id)) {
+            Some(name) => name.to_string(),
+            None => format!("function{}", id),
+        };
+        let line = match function
+            .basic_blocks
+            .iter()
+            .flat_map(|block| &block.instructions)
+            .find(|inst| inst.class.opcode == Op::Line) {
+            Some(inst) => Some(self.source_line(inst)?),
+            None => None,
+        };
+        let llvm_function = self.value(id)?;
+        if let Some(ref mut debug) = self.debug {
+            unsafe { debug.start_function(self.builder, llvm_function, &name, line.as_ref()) };
+        }
+        Ok(())
+    }
+
+    fn trans_scalar_function(&mut self, function: &'a Function) -> Result<(), TranspilerError> {
+        let def = function.def.as_ref().ok_or(
+            TranspilerError::InvalidInstruction(Op::Function),
+        )?;
+        let id = result_id(def)?;
+        let llvm_function = self.value(id)?;
+        let entry_point = self.entry_points.contains_key(&id);
+        let hidden_params = if entry_point { 1 } else { 2 };
+        for (index, param) in function.parameters.iter().enumerate() {
+            let id = result_id(param)?;
+            let value = unsafe { LLVMGetParam(llvm_function, (index + hidden_params) as u32) };
+            self.values.insert(id, value);
+            self.track_pointer(id, result_type(param)?)?;
+        }
+        self.function = llvm_function;
+
+        // Blocks may be branched to before they are translated, so create all of them first
+        self.blocks.clear();
+        self.block_ends.clear();
+        let mut labels = Vec::new();
+        for block in &function.basic_blocks {
+            let label = block.label.as_ref().ok_or(
+                TranspilerError::InvalidInstruction(Op::Label),
+            )?;
+            let id = result_id(label)?;
+            let bb = unsafe {
+                LLVMAppendBasicBlockInContext(self.ctx, llvm_function, self.name(id).as_ptr())
+            };
+            self.blocks.insert(id, bb);
+            labels.push(id);
+        }
+
+        if let Some(&label) = labels.first() {
+            unsafe {
+                LLVMPositionBuilderAtEnd(self.builder, self.blocks[&label]);
+                self.trans_prologue(entry_point)?;
+            }
+        }
+
+        // Phis may refer to values of blocks that come later, so their incoming values are only
+        // added once the whole function is translated.
+        let mut phis = Vec::new();
+        for (block, &label) in function.basic_blocks.iter().zip(&labels) {
+            unsafe { LLVMPositionBuilderAtEnd(self.builder, self.blocks[&label]) };
+            // OpLine only reaches to the end of its block
+            self.line = None;
+            for inst in &block.instructions {
+                if inst.class.opcode == Op::Phi {
+                    phis.push(inst);
+                }
+                self.trans_instruction(inst)?;
+            }
+            let end = unsafe { LLVMGetInsertBlock(self.builder) };
+            self.block_ends.insert(label, end);
+        }
+
+        self.line = None;
+        for phi in phis {
+            self.add_incoming(phi).map_err(|error| error.at(self.location(phi)))?;
+        }
+        Ok(())
+    }
+
+    /// Adds the incoming values of an OpPhi once all blocks are translated.
+    fn add_incoming(&mut self, phi: &'a Instruction) -> Result<(), TranspilerError> {
+        let value = self.value(result_id(phi)?)?;
+        let mut index = 0;
+        while index + 1 < phi.operands.len() {
+            let mut incoming_value = [self.operand(phi, index)?];
+            let parent = operand_id(phi, index + 1)?;
+            let mut incoming_block = [
+                self.block_ends.get(&parent).cloned().ok_or(
+                    TranspilerError::UndefinedId(parent),
+                )?,
+            ];
+            unsafe {
+                LLVMAddIncoming(
+                    value,
+                    incoming_value.as_mut_ptr(),
+                    incoming_block.as_mut_ptr(),
+                    1,
+                )
+            };
+            index += 2;
+        }
+        Ok(())
+    }
+
+    /// Makes the private and interface variables available to the function being translated.
+    unsafe fn trans_prologue(&mut self, entry_point: bool) -> Result<(), TranspilerError> {
+        let builder = self.builder;
+        self.invocation = LLVMGetParam(self.function, 0);
+        let private = if entry_point {
+            let private = LLVMBuildAlloca(builder, self.private_type, NONAME);
+            for (index, &(_, initializer)) in self.private.iter().enumerate() {
+                if let Some(initializer) = initializer {
+                    let field = LLVMBuildStructGEP(builder, private, index as u32, NONAME);
+                    LLVMBuildStore(builder, initializer, field);
+                }
+            }
+            private
+        } else {
+            LLVMGetParam(self.function, 1)
+        };
+        for (index, &(id, _)) in self.private.iter().enumerate() {
+            let field = LLVMBuildStructGEP(builder, private, index as u32, self.name(id).as_ptr());
+            self.values.insert(id, field);
+        }
+        self.private_variables = private;
+
+        let byte_pointer_pointer = LLVMPointerType(self.byte_pointer_type(), 0);
+        let resources = self.invocation_field(abi::resources_offset(), byte_pointer_pointer);
+        let resources = LLVMBuildLoad(builder, resources, NONAME);
+        self.descriptor_arrays.clear();
+        for (id, interface) in self.interface.clone() {
+            let pointer = match interface {
+                Interface::Invocation(offset) => {
+                    let (pointee, layout) = self.pointee(id)?;
+                    let ty = LLVMPointerType(self.trans_mem_type(pointee, layout)?.ty, 0);
+                    self.invocation_field(offset, ty)
+                }
+                // Members are resolved by access chains
+                Interface::BuiltInBlock(_) => continue,
+                _ => {
+                    match self.resource_pointer(resources, id, &interface)? {
+                        Some(pointer) => pointer,
+                        None => continue,
+                    }
+                }
+            };
+            self.values.insert(id, pointer);
+        }
+        Ok(())
+    }
+
+    /// Returns the address of the push constant or descriptor bound variable `id`, given the
+    /// `Resources`. Returns None for arrays of buffers, whose descriptors are remembered for
+    /// access chains to pick from.
+    unsafe fn resource_pointer(
+        &mut self,
+        resources: LLVMValueRef,
+        id: Word,
+        interface: &Interface,
+    ) -> Result<Option<LLVMValueRef>, TranspilerError> {
+        let builder = self.builder;
+        let (pointee, layout) = self.pointee(id)?;
+        let ty = LLVMPointerType(self.trans_mem_type(pointee, layout)?.ty, 0);
+        let pointer = match *interface {
+            Interface::PushConstants => {
+                let offset = abi::push_constants_offset();
+                let byte_pointer_pointer = LLVMPointerType(self.byte_pointer_type(), 0);
+                let pointer = byte_offset(builder, resources, offset, byte_pointer_pointer);
+                LLVMBuildBitCast(builder, LLVMBuildLoad(builder, pointer, NONAME), ty, NONAME)
+            }
+            Interface::Workgroup(index) => {
+                let offset = abi::workgroup_memory_offset();
+                let struct_pointer = LLVMPointerType(self.workgroup_type, 0);
+                let pointer =
+                    byte_offset(builder, resources, offset, LLVMPointerType(struct_pointer, 0));
+                let memory = LLVMBuildLoad(builder, pointer, NONAME);
+                LLVMBuildStructGEP(builder, memory, index, self.name(id).as_ptr())
+            }
+            Interface::Descriptor { set, binding, buffer } => {
+                let descriptors = self.descriptors(resources, set, binding);
+                let arrayed = match self.def(pointee)?.class.opcode {
+                    Op::TypeArray | Op::TypeRuntimeArray => true,
+                    _ => false,
+                };
+                if !buffer {
+                    // Arrays of images and samplers are laid out like their descriptors
+                    LLVMBuildBitCast(builder, descriptors, ty, NONAME)
+                } else if arrayed {
+                    // Every buffer has a descriptor of its own, the access chain picks one
+                    self.descriptor_arrays.insert(id, descriptors);
+                    return Ok(None);
+                } else {
+                    let buffer = LLVMBuildLoad(builder, descriptors, NONAME);
+                    LLVMBuildBitCast(builder, buffer, ty, NONAME)
+                }
+            }
+            Interface::Invocation(_) |
+            Interface::BuiltInBlock(_) => return Err(TranspilerError::InvalidLayout(id)),
+        };
+        Ok(Some(pointer))
+    }
+
+    /// Returns the buffer of descriptor `index` in `descriptors`, as a pointer of type `ty`.
+    unsafe fn descriptor_buffer(
+        &self,
+        descriptors: LLVMValueRef,
+        index: LLVMValueRef,
+        ty: LLVMTypeRef,
+    ) -> LLVMValueRef {
+        let mut index = [index];
+        let descriptor =
+            LLVMBuildInBoundsGEP(self.builder, descriptors, index.as_mut_ptr(), 1, NONAME);
+        let buffer = LLVMBuildLoad(self.builder, descriptor, NONAME);
+        LLVMBuildBitCast(self.builder, buffer, ty, NONAME)
+    }
+
+    /// Returns the address of the first descriptor of a binding, given the `Resources`.
+    unsafe fn descriptors(&self, resources: LLVMValueRef, set: u32, binding: u32) -> LLVMValueRef {
+        let builder = self.builder;
+        let descriptor_ty = LLVMPointerType(self.byte_pointer_type(), 0);
+        let set_ty = LLVMPointerType(descriptor_ty, 0);
+        let offset = abi::descriptor_set_offset(set).unwrap_or(0);
+        let set = LLVMBuildLoad(
+            builder,
+            byte_offset(builder, resources, offset, LLVMPointerType(set_ty, 0)),
+            NONAME,
+        );
+        let mut index = [const_u32(self.ctx, binding)];
+        let binding = LLVMBuildInBoundsGEP(builder, set, index.as_mut_ptr(), 1, NONAME);
+        LLVMBuildLoad(builder, binding, NONAME)
+    }
+
+    /// Returns from the function being translated without a meaningful result.
+    unsafe fn build_early_return(&self) {
+        let function_ty = LLVMGetElementType(LLVMTypeOf(self.function));
+        let ret = LLVMGetReturnType(function_ty);
+        if LLVMGetTypeKind(ret) == LLVMTypeKind::LLVMVoidTypeKind {
+            LLVMBuildRetVoid(self.builder);
+        } else {
+            LLVMBuildRet(self.builder, LLVMGetUndef(ret));
+        }
+    }
+
+    fn byte_pointer_type(&self) -> LLVMTypeRef {
+        unsafe { LLVMPointerType(LLVMInt8TypeInContext(self.ctx), 0) }
+    }
+
+    /// Returns a pointer of type `ty` to the field at `offset` in the `Invocation`.
+    unsafe fn invocation_field(&self, offset: usize, ty: LLVMTypeRef) -> LLVMValueRef {
+        byte_offset(self.builder, self.invocation, offset, ty)
+    }
+
+    /// Translates a single instruction inside a function body.
+    pub fn trans_instruction(&mut self, inst: &'a Instruction) -> Result<(), TranspilerError> {
+        let result = self.track_line(inst).and_then(
+            |()| self.trans_body_instruction(inst),
+        );
+        result.map_err(|error| error.at(self.location(inst)))
+    }
+
+    fn trans_body_instruction(&mut self, inst: &'a Instruction) -> Result<(), TranspilerError> {
+        let opcode = inst.class.opcode;
+        let builder = self.builder;
+        let value = unsafe {
+            if let Some(build) = binary_builder(opcode) {
+                build(builder, self.operand(inst, 0)?, self.operand(inst, 1)?, NONAME)
+            } else if let Some(predicate) = int_predicate(opcode) {
+                let (a, b) = (self.operand(inst, 0)?, self.operand(inst, 1)?);
+                LLVMBuildICmp(builder, predicate, a, b, NONAME)
+            } else if let Some(predicate) = real_predicate(opcode) {
+                let (a, b) = (self.operand(inst, 0)?, self.operand(inst, 1)?);
+                LLVMBuildFCmp(builder, predicate, a, b, NONAME)
+            } else if let Some(cast) = cast_opcode(opcode) {
+                let ty = self.register_type(result_type(inst)?)?;
+                LLVMBuildCast(builder, cast, self.operand(inst, 0)?, ty, NONAME)
+            } else {
+                match self.trans_other_instruction(inst)? {
+                    Some(value) => value,
+                    None => return Ok(()),
+                }
+            }
+        };
+        let id = result_id(inst)?;
+        self.values.insert(id, value);
+        if !self.pointers.contains_key(&id) {
+            self.track_pointer(id, result_type(inst)?)?;
+        }
+        Ok(())
+    }
+
+    /// Translates the instructions that do not map to a single LLVM instruction. Returns the
+    /// result value, if the instruction has one.
+    unsafe fn trans_other_instruction(
+        &mut self,
+        inst: &'a Instruction,
+    ) -> Result<Option<LLVMValueRef>, TranspilerError> {
+        let opcode = inst.class.opcode;
+        let builder = self.builder;
+        let value = match opcode {
+            // Instructions that work differently when running several lanes at once
+            _ if self.lanes > 1 && simd::is_lane_specific(opcode) => {
+                return self.trans_simd_instruction(inst)
+            }
+            Op::Nop | Op::Line | Op::NoLine => return Ok(None),
+            // LLVM finds loops and selections on its own
+            Op::LoopMerge | Op::SelectionMerge => return Ok(None),
+
+            // Control flow
+            Op::Label => return Err(TranspilerError::InvalidInstruction(opcode)),
+            Op::Phi => {
+                let ty = self.register_type(result_type(inst)?)?;
+                LLVMBuildPhi(builder, ty, NONAME)
+            }
+            Op::Branch => {
+                LLVMBuildBr(builder, self.block(operand_id(inst, 0)?)?);
+                return Ok(None);
+            }
+            Op::BranchConditional => {
+                let condition = self.operand(inst, 0)?;
+                let then_block = self.block(operand_id(inst, 1)?)?;
+                let else_block = self.block(operand_id(inst, 2)?)?;
+                LLVMBuildCondBr(builder, condition, then_block, else_block);
+                return Ok(None);
+            }
+            Op::Switch => {
+                let selector = self.operand(inst, 0)?;
+                let default = self.block(operand_id(inst, 1)?)?;
+                let cases = (inst.operands.len() as u32 - 2) / 2;
+                let switch = LLVMBuildSwitch(builder, selector, default, cases);
+                let mut index = 2;
+                while index + 1 < inst.operands.len() {
+                    let literal = match inst.operands[index] {
+                        Operand::LiteralInt32(literal) => literal as u64,
+                        Operand::LiteralInt64(literal) => literal,
+                        _ => return Err(TranspilerError::InvalidInstruction(opcode)),
+                    };
+                    let literal = LLVMConstInt(LLVMTypeOf(selector), literal, 0);
+                    LLVMAddCase(switch, literal, self.block(operand_id(inst, index + 1)?)?);
+                    index += 2;
+                }
+                return Ok(None);
+            }
+            Op::Return => {
+                LLVMBuildRetVoid(builder);
+                return Ok(None);
+            }
+            Op::ReturnValue => {
+                LLVMBuildRet(builder, self.operand(inst, 0)?);
+                return Ok(None);
+            }
+            Op::Unreachable => {
+                LLVMBuildUnreachable(builder);
+                return Ok(None);
+            }
+            Op::FunctionCall => {
+                let function = self.operand(inst, 0)?;
+                let mut args = vec![self.invocation, self.private_variables];
+                for id in operand_ids(inst, 1)? {
+                    args.push(self.value(id)?);
+                }
+                // Void values must not be named, so calls never are
+                let call =
+                    LLVMBuildCall(builder, function, args.as_mut_ptr(), args.len() as u32, NONAME);
+                if self.kills {
+                    // Stop right away if the callee discarded the fragment
+                    let killed_ty = LLVMPointerType(LLVMInt32TypeInContext(self.ctx), 0);
+                    let killed = self.invocation_field(abi::killed_offset(), killed_ty);
+                    let killed = LLVMBuildLoad(builder, killed, NONAME);
+                    let zero = LLVMConstNull(LLVMTypeOf(killed));
+                    let killed =
+                        LLVMBuildICmp(builder, LLVMIntPredicate::LLVMIntNE, killed, zero, NONAME);
+                    let discard = LLVMAppendBasicBlockInContext(self.ctx, self.function, NONAME);
+                    let next = LLVMAppendBasicBlockInContext(self.ctx, self.function, NONAME);
+                    LLVMBuildCondBr(builder, killed, discard, next);
+                    LLVMPositionBuilderAtEnd(builder, discard);
+                    self.build_early_return();
+                    LLVMPositionBuilderAtEnd(builder, next);
+                }
+                call
+            }
+            Op::Kill => {
+                let killed_ty = LLVMPointerType(LLVMInt32TypeInContext(self.ctx), 0);
+                let killed = self.invocation_field(abi::killed_offset(), killed_ty);
+                LLVMBuildStore(builder, const_u32(self.ctx, 1), killed);
+                self.build_early_return();
+                return Ok(None);
+            }
+
+            // Barriers. SIMD functions only run blocks that some lane reaches, and barriers are
+            // in uniform control flow, so every lane waits at the same call.
+            Op::ControlBarrier => {
+                let byte_pointer = self.byte_pointer_type();
+                let offset = abi::resources_offset();
+                let resources = self.invocation_field(offset, LLVMPointerType(byte_pointer, 0));
+                let mut args = [LLVMBuildLoad(builder, resources, NONAME)];
+                let void = LLVMVoidTypeInContext(self.ctx);
+                let name = "__spirv_control_barrier";
+                let barrier = declare(self.module, name, void, &[byte_pointer]);
+                LLVMBuildCall(builder, barrier, args.as_mut_ptr(), 1, NONAME);
+                return Ok(None);
+            }
+            Op::MemoryBarrier => {
+                let ordering = LLVMAtomicOrdering::LLVMAtomicOrderingSequentiallyConsistent;
+                LLVMBuildFence(builder, ordering, 0, NONAME);
+                return Ok(None);
+            }
+
+            // Memory
+            Op::Variable => self.trans_local_variable(inst)?,
+            Op::Load => {
+                let pointer = operand_id(inst, 0)?;
+                let (ty, layout) = self.pointee(pointer)?;
+                self.load(self.value(pointer)?, ty, layout)?
+            }
+            Op::Store => {
+                let pointer = operand_id(inst, 0)?;
+                let (ty, layout) = self.pointee(pointer)?;
+                self.store(self.value(pointer)?, self.operand(inst, 1)?, ty, layout)?;
+                return Ok(None);
+            }
+            Op::CopyMemory => {
+                let target = operand_id(inst, 0)?;
+                let source = operand_id(inst, 1)?;
+                let (ty, target_layout) = self.pointee(target)?;
+                let (_, source_layout) = self.pointee(source)?;
+                let value = self.load(self.value(source)?, ty, source_layout)?;
+                self.store(self.value(target)?, value, ty, target_layout)?;
+                return Ok(None);
+            }
+            Op::AccessChain | Op::InBoundsAccessChain => self.trans_access_chain(inst)?,
+
+            // Arithmetic
+            Op::SNegate => LLVMBuildNeg(builder, self.operand(inst, 0)?, NONAME),
+            Op::FNegate => LLVMBuildFNeg(builder, self.operand(inst, 0)?, NONAME),
+            Op::SMod => smod(builder, self.operand(inst, 0)?, self.operand(inst, 1)?),
+            Op::FMod => fmod(builder, self.operand(inst, 0)?, self.operand(inst, 1)?),
+            Op::VectorTimesScalar => {
+                let vector = self.operand(inst, 0)?;
+                let scalar = splat(builder, self.operand(inst, 1)?, LLVMTypeOf(vector), self.lanes);
+                LLVMBuildFMul(builder, vector, scalar, NONAME)
+            }
+            Op::MatrixTimesScalar => {
+                let (matrix, scalar) = (self.operand(inst, 0)?, self.operand(inst, 1)?);
+                matrix_times_scalar(builder, matrix, scalar, self.lanes)
+            }
+            Op::VectorTimesMatrix => {
+                let ty = self.register_type(result_type(inst)?)?;
+                let (vector, matrix) = (self.operand(inst, 0)?, self.operand(inst, 1)?);
+                vector_times_matrix(builder, vector, matrix, ty, self.lanes)
+            }
+            Op::MatrixTimesVector => {
+                let (matrix, vector) = (self.operand(inst, 0)?, self.operand(inst, 1)?);
+                matrix_times_vector(builder, matrix, vector, self.lanes)
+            }
+            Op::MatrixTimesMatrix => {
+                let ty = self.register_type(result_type(inst)?)?;
+                let (a, b) = (self.operand(inst, 0)?, self.operand(inst, 1)?);
+                matrix_times_matrix(builder, a, b, ty, self.lanes)
+            }
+            Op::OuterProduct => {
+                let ty = self.register_type(result_type(inst)?)?;
+                let (a, b) = (self.operand(inst, 0)?, self.operand(inst, 1)?);
+                outer_product(builder, a, b, ty, self.lanes)
+            }
+            Op::Dot => dot(builder, self.operand(inst, 0)?, self.operand(inst, 1)?, self.lanes),
+            Op::IAddCarry | Op::ISubBorrow | Op::UMulExtended | Op::SMulExtended => {
+                self.trans_extended_arithmetic(inst)?
+            }
+
+            // Bits
+            Op::ShiftRightLogical | Op::ShiftRightArithmetic | Op::ShiftLeftLogical => {
+                let base = self.operand(inst, 0)?;
+                // The shift amount may have a different width than the base
+                let shift = int_cast(builder, self.operand(inst, 1)?, LLVMTypeOf(base), false);
+                match opcode {
+                    Op::ShiftRightLogical => LLVMBuildLShr(builder, base, shift, NONAME),
+                    Op::ShiftRightArithmetic => LLVMBuildAShr(builder, base, shift, NONAME),
+                    _ => LLVMBuildShl(builder, base, shift, NONAME),
+                }
+            }
+            Op::Not => LLVMBuildNot(builder, self.operand(inst, 0)?, NONAME),
+            Op::BitFieldInsert => {
+                let base = self.operand(inst, 0)?;
+                let ty = LLVMTypeOf(base);
+                let offset = self.scalar_int_operand(inst, 2, ty)?;
+                let count = self.scalar_int_operand(inst, 3, ty)?;
+                bit_field_insert(builder, base, self.operand(inst, 1)?, offset, count)
+            }
+            Op::BitFieldSExtract | Op::BitFieldUExtract => {
+                let base = self.operand(inst, 0)?;
+                let ty = LLVMTypeOf(base);
+                let offset = self.scalar_int_operand(inst, 1, ty)?;
+                let count = self.scalar_int_operand(inst, 2, ty)?;
+                let signed = opcode == Op::BitFieldSExtract;
+                bit_field_extract(builder, base, offset, count, signed)
+            }
+            Op::BitReverse => {
+                call_intrinsic(builder, self.module, "llvm.bitreverse", &[self.operand(inst, 0)?])
+            }
+            Op::BitCount => {
+                let ty = self.register_type(result_type(inst)?)?;
+                let count =
+                    call_intrinsic(builder, self.module, "llvm.ctpop", &[self.operand(inst, 0)?]);
+                int_cast(builder, count, ty, false)
+            }
+
+            // Logic
+            Op::LogicalNot => LLVMBuildNot(builder, self.operand(inst, 0)?, NONAME),
+            Op::Select => {
+                let condition = self.operand(inst, 0)?;
+                let (a, b) = (self.operand(inst, 1)?, self.operand(inst, 2)?);
+                select(builder, condition, a, b, self.lanes)
+            }
+            Op::Any => reduce(builder, self.operand(inst, 0)?, LLVMBuildOr, self.lanes),
+            Op::All => reduce(builder, self.operand(inst, 0)?, LLVMBuildAnd, self.lanes),
+            Op::IsNan => {
+                let value = self.operand(inst, 0)?;
+                LLVMBuildFCmp(builder, LLVMRealPredicate::LLVMRealUNO, value, value, NONAME)
+            }
+            Op::IsInf => {
+                let value = self.operand(inst, 0)?;
+                let ty = LLVMTypeOf(value);
+                let infinity = const_splat(LLVMConstReal(scalar_type(ty), f64::INFINITY), ty);
+                let negative = LLVMConstFNeg(infinity);
+                let pos = LLVMBuildFCmp(builder, LLVMRealPredicate::LLVMRealOEQ, value, infinity, NONAME);
+                let neg = LLVMBuildFCmp(builder, LLVMRealPredicate::LLVMRealOEQ, value, negative, NONAME);
+                LLVMBuildOr(builder, pos, neg, NONAME)
+            }
+
+            // Conversion
+            Op::UConvert | Op::SConvert => {
+                let ty = self.register_type(result_type(inst)?)?;
+                int_cast(builder, self.operand(inst, 0)?, ty, opcode == Op::SConvert)
+            }
+            Op::FConvert => {
+                let ty = self.register_type(result_type(inst)?)?;
+                LLVMBuildFPCast(builder, self.operand(inst, 0)?, ty, NONAME)
+            }
+            Op::QuantizeToF16 => {
+                let value = self.operand(inst, 0)?;
+                let ty = LLVMTypeOf(value);
+                let half = LLVMHalfTypeInContext(self.ctx);
+                let half = if is_vector(ty) {
+                    LLVMVectorType(half, LLVMGetVectorSize(ty))
+                } else {
+                    half
+                };
+                let quantized = LLVMBuildFPTrunc(builder, value, half, NONAME);
+                LLVMBuildFPExt(builder, quantized, ty, NONAME)
+            }
+
+            // Composites
+            Op::VectorExtractDynamic => {
+                LLVMBuildExtractElement(
+                    builder,
+                    self.operand(inst, 0)?,
+                    self.operand(inst, 1)?,
+                    NONAME,
+                )
+            }
+            Op::VectorInsertDynamic => {
+                LLVMBuildInsertElement(
+                    builder,
+                    self.operand(inst, 0)?,
+                    self.operand(inst, 1)?,
+                    self.operand(inst, 2)?,
+                    NONAME,
+                )
+            }
+            Op::VectorShuffle => self.trans_vector_shuffle(inst)?,
+            Op::CompositeConstruct => {
+                let ty = self.register_type(result_type(inst)?)?;
+                let constituents = operand_ids(inst, 0)?
+                    .into_iter()
+                    .map(|id| self.value(id))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if is_vector(ty) {
+                    // Vectors may be constructed from smaller vectors
+                    let mut components_ = Vec::new();
+                    for constituent in constituents {
+                        components_.extend(components(builder, constituent, self.lanes));
+                    }
+                    from_components(builder, ty, &components_, self.lanes)
+                } else {
+                    build_aggregate(builder, ty, &constituents)
+                }
+            }
+            Op::CompositeExtract => {
+                let indices = self.literals(inst, 1)?;
+                composite_extract(builder, self.operand(inst, 0)?, &indices, self.lanes)
+            }
+            Op::CompositeInsert => {
+                let indices = self.literals(inst, 2)?;
+                composite_insert(
+                    builder,
+                    self.operand(inst, 1)?,
+                    self.operand(inst, 0)?,
+                    &indices,
+                    self.lanes,
+                )
+            }
+            Op::CopyObject => self.operand(inst, 0)?,
+            Op::Transpose => {
+                let ty = self.register_type(result_type(inst)?)?;
+                transpose(builder, self.operand(inst, 0)?, ty, self.lanes)
+            }
+            Op::Undef => LLVMGetUndef(self.register_type(result_type(inst)?)?),
+
+            Op::ExtInst => {
+                match self.ext_inst_set(inst)? {
+                    ExtInstSet::Glsl450 => {
+                        let ty = self.register_type(result_type(inst)?)?;
+                        let args = operand_ids(inst, 2)?
+                            .into_iter()
+                            .map(|id| self.value(id))
+                            .collect::<Result<Vec<_>, _>>()?;
+                        let instruction = operand_u32(inst, 1)?;
+                        let lanes = self.lanes;
+                        glsl::trans_glsl_inst(builder, self.module, instruction, ty, &args, lanes)?
+                    }
+                    ExtInstSet::NonSemantic => return Ok(None),
+                }
+            }
+
+            _ => return Err(TranspilerError::UnsupportedInstruction(opcode)),
+        };
+        Ok(Some(value))
+    }
+
+    fn literals(&self, inst: &Instruction, start: usize) -> Result<Vec<u32>, TranspilerError> {
+        (start..inst.operands.len())
+            .map(|index| operand_u32(inst, index))
+            .collect()
+    }
+
+    /// Returns the scalar integer in operand `index`, cast and broadcast to `ty`.
+    unsafe fn scalar_int_operand(
+        &self,
+        inst: &Instruction,
+        index: usize,
+        ty: LLVMTypeRef,
+    ) -> Result<LLVMValueRef, TranspilerError> {
+        let value = self.operand(inst, index)?;
+        let value_ty = if self.lanes > 1 {
+            LLVMVectorType(scalar_type(ty), self.lanes)
+        } else {
+            scalar_type(ty)
+        };
+        let value = int_cast(self.builder, value, value_ty, false);
+        Ok(splat(self.builder, value, ty, self.lanes))
+    }
+
+    /// Translates a function scope OpVariable into an alloca. SPIR-V requires these to be at the
+    /// start of the first block, so they end up in LLVM's entry block as well.
+    unsafe fn trans_local_variable(
+        &mut self,
+        inst: &'a Instruction,
+    ) -> Result<LLVMValueRef, TranspilerError> {
+        let id = result_id(inst)?;
+        let pointer = self.def(result_type(inst)?)?;
+        let ty = self.trans_mem_type(operand_id(pointer, 1)?, Layout::Natural)?.ty;
+        let variable = LLVMBuildAlloca(self.builder, ty, self.name(id).as_ptr());
+        if let Some(&name) = self.names.get(&id) {
+            if let Some(debug_ty) = self.debug_type(operand_id(pointer, 1)?)? {
+                let block = LLVMGetInsertBlock(self.builder);
+                if let Some(ref mut debug) = self.debug {
+                    debug.declare_variable(variable, name, debug_ty, self.line.as_ref(), block);
+                }
+            }
+        }
+        if inst.operands.len() > 1 {
+            LLVMBuildStore(self.builder, self.operand(inst, 1)?, variable);
+        }
+        Ok(variable)
+    }
+
+    /// Describes `ty` to debuggers, if debug info is emitted and it is a type debuggers can show:
+    /// scalars, vectors, matrices and arrays of them.
+    unsafe fn debug_type(&mut self, ty: Word) -> Result<Option<LLVMMetadataRef>, TranspilerError> {
+        if self.debug.is_none() {
+            return Ok(None);
+        }
+        let def = self.def(ty)?;
+        let opcode = def.class.opcode;
+        let (element, count) = match opcode {
+            Op::TypeBool | Op::TypeInt | Op::TypeFloat => (None, 0),
+            Op::TypeVector | Op::TypeMatrix | Op::TypeArray => {
+                let count = if opcode == Op::TypeArray {
+                    self.constant_u32(operand_id(def, 1)?)?
+                } else {
+                    operand_u32(def, 1)?
+                };
+                match self.debug_type(operand_id(def, 0)?)? {
+                    Some(element) => (Some(element), count),
+                    None => return Ok(None),
+                }
+            }
+            _ => return Ok(None),
+        };
+        let llvm_ty = self.trans_mem_type(ty, Layout::Natural)?.ty;
+        let bits = LLVMABISizeOfType(LLVMGetModuleDataLayout(self.module), llvm_ty) * 8;
+        let debug = match self.debug {
+            Some(ref mut debug) => debug,
+            None => return Ok(None),
+        };
+        let debug_ty = match (opcode, element) {
+            (_, Some(element)) => debug.array_type(element, count, bits, opcode == Op::TypeVector),
+            (Op::TypeBool, None) => debug.basic_type("bool", 8, Encoding::Bool),
+            (Op::TypeInt, None) => {
+                let width = operand_u32(def, 0)?;
+                if operand_u32(def, 1)? == 0 {
+                    debug.basic_type(&format!("uint{}_t", width), width, Encoding::Unsigned)
+                } else {
+                    debug.basic_type(&format!("int{}_t", width), width, Encoding::Signed)
+                }
+            }
+            (_, None) => {
+                let width = operand_u32(def, 0)?;
+                let name = if width == 64 { "double" } else { "float" };
+                debug.basic_type(name, width, Encoding::Float)
+            }
+        };
+        Ok(Some(debug_ty))
+    }
+
+    /// Translates OpAccessChain into a GEP, skipping over the padding of explicit layouts.
+    unsafe fn trans_access_chain(
+        &mut self,
+        inst: &'a Instruction,
+    ) -> Result<LLVMValueRef, TranspilerError> {
+        let base_id = operand_id(inst, 0)?;
+        let (mut ty, mut layout) = self.pointee(base_id)?;
+        let mut index_ids = operand_ids(inst, 1)?;
+        let base = if let Some(descriptors) = self.descriptor_arrays.get(&base_id).cloned() {
+            // Every element of an array of buffers has a descriptor of its own
+            let index = self.value(first_index(inst, &mut index_ids)?)?;
+            ty = operand_id(self.def(ty)?, 0)?;
+            let buffer_ty = LLVMPointerType(self.trans_mem_type(ty, layout)?.ty, 0);
+            self.descriptor_buffer(descriptors, index, buffer_ty)
+        } else if let Some(members) = self.builtin_block(base_id) {
+            // Members of built-in blocks are spread over the `Invocation`
+            let index = first_index(inst, &mut index_ids)?;
+            let (offset, member_ty) = self.builtin_member(&members, ty, index)?;
+            ty = member_ty;
+            layout = Layout::Natural;
+            let member_ty = LLVMPointerType(self.trans_type(ty)?, 0);
+            self.invocation_field(offset, member_ty)
+        } else {
+            self.value(base_id)?
+        };
+
+        let index_values = index_ids
+            .iter()
+            .map(|&index| self.value(index))
+            .collect::<Result<Vec<_>, _>>()?;
+        let inbounds = inst.class.opcode == Op::InBoundsAccessChain;
+        let (pointer, ty, layout) =
+            self.chain_gep(base, ty, layout, &index_ids, &index_values, inbounds)?;
+        self.pointers.insert(result_id(inst)?, (ty, layout));
+        Ok(pointer)
+    }
+
+    /// Returns the offset in the `Invocation` and the type of the member of the built-in block
+    /// of type `ty` with the offsets `members` that the constant `index` selects.
+    fn builtin_member(
+        &self,
+        members: &[Option<usize>],
+        ty: Word,
+        index: Word,
+    ) -> Result<(usize, Word), TranspilerError> {
+        let member = self.constant_u32(index)?;
+        match members.get(member as usize) {
+            Some(&Some(offset)) => Ok((offset, operand_id(self.def(ty)?, member as usize)?)),
+            _ => {
+                Err(match self.decorations.member_builtin(ty, member) {
+                    Some(builtin) => TranspilerError::UnsupportedBuiltIn(builtin),
+                    None => TranspilerError::InvalidInstruction(Op::AccessChain),
+                })
+            }
+        }
+    }
+
+    /// Builds the GEP of an access chain from `base`, which points to `ty` laid out according
+    /// to `layout`. `index_values` are the values of the indices `index_ids`. Returns the
+    /// pointer along with the type and layout it points to, skipping over the padding of
+    /// explicit layouts.
+    unsafe fn chain_gep(
+        &mut self,
+        base: LLVMValueRef,
+        mut ty: Word,
+        mut layout: Layout,
+        index_ids: &[Word],
+        index_values: &[LLVMValueRef],
+        inbounds: bool,
+    ) -> Result<(LLVMValueRef, Word, Layout), TranspilerError> {
+        let mut indices = vec![const_u32(self.ctx, 0)];
+        for (&index, &value) in index_ids.iter().zip(index_values) {
+            let def = self.def(ty)?;
+            match def.class.opcode {
+                Op::TypeStruct => {
+                    let member = self.constant_u32(index)?;
+                    let mut field = member;
+                    if layout != Layout::Natural {
+                        field = self.member_index(ty, member);
+                        layout = self.member_layout(ty, member, layout);
+                    }
+                    indices.push(const_u32(self.ctx, field));
+                    ty = operand_id(def, member as usize)?;
+                }
+                Op::TypeArray | Op::TypeRuntimeArray | Op::TypeMatrix => {
+                    let padded = self.trans_mem_type(ty, layout)?.padded;
+                    indices.push(value);
+                    if padded {
+                        indices.push(const_u32(self.ctx, 0));
+                    }
+                    ty = operand_id(def, 0)?;
+                }
+                Op::TypeVector => {
+                    indices.push(value);
+                    ty = operand_id(def, 0)?;
+                }
+                _ => return Err(TranspilerError::InvalidInstruction(Op::AccessChain)),
+            }
+        }
+
+        let count = indices.len() as u32;
+        let pointer = if inbounds {
+            LLVMBuildInBoundsGEP(self.builder, base, indices.as_mut_ptr(), count, NONAME)
+        } else {
+            LLVMBuildGEP(self.builder, base, indices.as_mut_ptr(), count, NONAME)
+        };
+        Ok((pointer, ty, layout))
+    }
+
+    /// Returns the offsets of the members of `id`, if it is a block of built-in variables.
+    fn builtin_block(&self, id: Word) -> Option<Vec<Option<usize>>> {
+        self.interface.iter().find(|&&(var, _)| var == id).and_then(
+            |&(_, ref interface)| {
+                match *interface {
+                    Interface::BuiltInBlock(ref members) => Some(members.clone()),
+                    _ => None,
+                }
+            },
+        )
+    }
+
+    /// Loads a value of SPIR-V type `ty` from memory laid out according to `layout`, converting
+    /// it to its register type.
+    unsafe fn load(
+        &mut self,
+        pointer: LLVMValueRef,
+        ty: Word,
+        layout: Layout,
+    ) -> Result<LLVMValueRef, TranspilerError> {
+        if layout == Layout::Natural {
+            return Ok(LLVMBuildLoad(self.builder, pointer, NONAME));
+        }
+        let def = self.def(ty)?;
+        let value = match def.class.opcode {
+            Op::TypeVector => {
+                // The array a vector is stored as has the same size as the vector, only the
+                // alignment differs.
+                let vector_ty = self.trans_type(ty)?;
+                let pointer = LLVMBuildBitCast(
+                    self.builder,
+                    pointer,
+                    LLVMPointerType(vector_ty, 0),
+                    NONAME,
+                );
+                let value = LLVMBuildLoad(self.builder, pointer, NONAME);
+                LLVMSetAlignment(value, self.trans_mem_type(operand_id(def, 0)?, layout)?.size);
+                value
+            }
+            Op::TypeArray | Op::TypeMatrix | Op::TypeStruct => {
+                let register_ty = self.trans_type(ty)?;
+                let mut members = Vec::new();
+                for (element, element_ty, element_layout) in self.elements(ty, layout)? {
+                    let element = self.element_pointer(pointer, &element);
+                    members.push(self.load(element, element_ty, element_layout)?);
+                }
+                build_aggregate(self.builder, register_ty, &members)
+            }
+            Op::TypeRuntimeArray => return Err(TranspilerError::InvalidInstruction(Op::Load)),
+            _ => LLVMBuildLoad(self.builder, pointer, NONAME),
+        };
+        Ok(value)
+    }
+
+    /// Stores `value` of SPIR-V type `ty` to memory laid out according to `layout`, the inverse
+    /// of `load`.
+    unsafe fn store(
+        &mut self,
+        pointer: LLVMValueRef,
+        value: LLVMValueRef,
+        ty: Word,
+        layout: Layout,
+    ) -> Result<(), TranspilerError> {
+        if layout == Layout::Natural {
+            LLVMBuildStore(self.builder, value, pointer);
+            return Ok(());
+        }
+        let def = self.def(ty)?;
+        match def.class.opcode {
+            Op::TypeVector => {
+                let pointer = LLVMBuildBitCast(
+                    self.builder,
+                    pointer,
+                    LLVMPointerType(LLVMTypeOf(value), 0),
+                    NONAME,
+                );
+                let store = LLVMBuildStore(self.builder, value, pointer);
+                LLVMSetAlignment(store, self.trans_mem_type(operand_id(def, 0)?, layout)?.size);
+            }
+            Op::TypeArray | Op::TypeMatrix | Op::TypeStruct => {
+                let elements = self.elements(ty, layout)?;
+                for (index, (element, element_ty, element_layout)) in
+                    elements.into_iter().enumerate()
+                {
+                    let element = self.element_pointer(pointer, &element);
+                    let member = LLVMBuildExtractValue(self.builder, value, index as u32, NONAME);
+                    self.store(element, member, element_ty, element_layout)?;
+                }
+            }
+            Op::TypeRuntimeArray => return Err(TranspilerError::InvalidInstruction(Op::Store)),
+            _ => {
+                LLVMBuildStore(self.builder, value, pointer);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the GEP indices, type and layout of all elements of the explicitly laid out
+    /// aggregate `ty`.
+    fn elements(
+        &mut self,
+        ty: Word,
+        layout: Layout,
+    ) -> Result<Vec<(Vec<u32>, Word, Layout)>, TranspilerError> {
+        let def = self.def(ty)?;
+        let mut elements = Vec::new();
+        if def.class.opcode == Op::TypeStruct {
+            for (member, member_ty) in operand_ids(def, 0)?.into_iter().enumerate() {
+                let member = member as u32;
+                elements.push((
+                    vec![0, self.member_index(ty, member)],
+                    member_ty,
+                    self.member_layout(ty, member, layout),
+                ));
+            }
+        } else {
+            let padded = self.trans_mem_type(ty, layout)?.padded;
+            let length = if def.class.opcode == Op::TypeArray {
+                self.constant_u32(operand_id(def, 1)?)?
+            } else {
+                operand_u32(def, 1)?
+            };
+            let element_ty = operand_id(def, 0)?;
+            for index in 0..length {
+                let indices = if padded {
+                    vec![0, index, 0]
+                } else {
+                    vec![0, index]
+                };
+                elements.push((indices, element_ty, layout));
+            }
+        }
+        Ok(elements)
+    }
+
+    unsafe fn element_pointer(&self, pointer: LLVMValueRef, indices: &[u32]) -> LLVMValueRef {
+        let mut indices = indices
+            .iter()
+            .map(|&index| const_u32(self.ctx, index))
+            .collect::<Vec<_>>();
+        LLVMBuildInBoundsGEP(
+            self.builder,
+            pointer,
+            indices.as_mut_ptr(),
+            indices.len() as u32,
+            NONAME,
+        )
+    }
+
+    /// Translates OpVectorShuffle. LLVM requires both vectors to have the same size, SPIR-V
+    /// does not.
+    unsafe fn trans_vector_shuffle(
+        &mut self,
+        inst: &'a Instruction,
+    ) -> Result<LLVMValueRef, TranspilerError> {
+        let first = self.operand(inst, 0)?;
+        let second = self.operand(inst, 1)?;
+        let first_size = LLVMGetVectorSize(LLVMTypeOf(first));
+        let size = cmp::max(first_size, LLVMGetVectorSize(LLVMTypeOf(second)));
+        let first = resize_vector(self.builder, first, size);
+        let second = resize_vector(self.builder, second, size);
+        let i32_ty = LLVMInt32TypeInContext(self.ctx);
+        let mut mask = self.literals(inst, 2)?
+            .into_iter()
+            .map(|index| if index == 0xFFFFFFFF {
+                LLVMGetUndef(i32_ty)
+            } else if index < first_size {
+                const_u32(self.ctx, index)
+            } else {
+                const_u32(self.ctx, index - first_size + size)
+            })
+            .collect::<Vec<_>>();
+        let mask = LLVMConstVector(mask.as_mut_ptr(), mask.len() as u32);
+        Ok(LLVMBuildShuffleVector(self.builder, first, second, mask, NONAME))
+    }
+
+    /// Translates the instructions returning a struct of a low and a high result.
+    unsafe fn trans_extended_arithmetic(
+        &mut self,
+        inst: &'a Instruction,
+    ) -> Result<LLVMValueRef, TranspilerError> {
+        let builder = self.builder;
+        let result_ty = self.register_type(result_type(inst)?)?;
+        let a = self.operand(inst, 0)?;
+        let b = self.operand(inst, 1)?;
+        let ty = LLVMTypeOf(a);
+        let (low, high) = match inst.class.opcode {
+            Op::IAddCarry => {
+                let sum = LLVMBuildAdd(builder, a, b, NONAME);
+                let carry = LLVMBuildICmp(builder, LLVMIntPredicate::LLVMIntULT, sum, a, NONAME);
+                (sum, LLVMBuildZExt(builder, carry, ty, NONAME))
+            }
+            Op::ISubBorrow => {
+                let difference = LLVMBuildSub(builder, a, b, NONAME);
+                let borrow = LLVMBuildICmp(builder, LLVMIntPredicate::LLVMIntULT, a, b, NONAME);
+                (difference, LLVMBuildZExt(builder, borrow, ty, NONAME))
+            }
+            opcode => {
+                let signed = opcode == Op::SMulExtended;
+                let width = LLVMGetIntTypeWidth(scalar_type(ty));
+                let wide = LLVMIntTypeInContext(self.ctx, width * 2);
+                let wide = if is_vector(ty) {
+                    LLVMVectorType(wide, LLVMGetVectorSize(ty))
+                } else {
+                    wide
+                };
+                let a = int_cast(builder, a, wide, signed);
+                let b = int_cast(builder, b, wide, signed);
+                let product = LLVMBuildMul(builder, a, b, NONAME);
+                let shift = const_splat(LLVMConstInt(scalar_type(wide), width as u64, 0), wide);
+                let high = LLVMBuildLShr(builder, product, shift, NONAME);
+                (
+                    LLVMBuildTrunc(builder, product, ty, NONAME),
+                    LLVMBuildTrunc(builder, high, ty, NONAME),
+                )
+            }
+        };
+        Ok(build_aggregate(builder, result_ty, &[low, high]))
+    }
+}
+
+impl<'a> Drop for SpirvTranspiler<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            LLVMDisposeBuilder(self.builder);
+        }
+    }
+}
+
+/// Removes the first index of an access chain, which selects the descriptor or member of a
+/// built-in block.
+fn first_index(inst: &Instruction, index_ids: &mut Vec<Word>) -> Result<Word, TranspilerError> {
+    if index_ids.is_empty() {
+        return Err(TranspilerError::InvalidInstruction(inst.class.opcode));
+    }
+    Ok(index_ids.remove(0))
+}
+
+/// Returns the offset of a built-in variable in the `Invocation`.
+fn builtin_offset(builtin: BuiltIn, output: bool) -> Option<usize> {
+    if builtin == BuiltIn::SampleMask {
+        Some(abi::sample_mask_offset(output))
+    } else {
+        abi::builtin_offset(builtin)
+    }
+}
+
+/// Returns `pointer`, an i8 pointer, advanced by `offset` bytes and cast to `ty`.
+unsafe fn byte_offset(
+    builder: LLVMBuilderRef,
+    pointer: LLVMValueRef,
+    offset: usize,
+    ty: LLVMTypeRef,
+) -> LLVMValueRef {
+    let ctx = LLVMGetTypeContext(LLVMTypeOf(pointer));
+    let mut index = [LLVMConstInt(LLVMInt64TypeInContext(ctx), offset as u64, 0)];
+    let pointer = LLVMBuildInBoundsGEP(builder, pointer, index.as_mut_ptr(), 1, NONAME);
+    LLVMBuildBitCast(builder, pointer, ty, NONAME)
+}
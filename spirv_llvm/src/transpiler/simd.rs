@@ -0,0 +1,1322 @@
+//! Translation of functions that run several invocations at once, one per SIMD lane.
+//!
+//! Values hold all lanes as described in `ir`. As lanes cannot branch on their own, every block
+//! runs for all lanes that reach it, in the order `cfg` finds, and a mask of these lanes guards
+//! every side effect. Stack slots carry the masks and the values that cross blocks, LLVM turns
+//! them back into registers.
+//!
+//! Function and private variables hold all lanes the way registers do. Other memory is either
+//! the same for all lanes, or reached through a pointer per lane that masked off lanes swap for
+//! a dummy.
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use std::os::raw::c_char;
+
+use rspirv::mr::*;
+use spirv_headers::*;
+use llvm_sys::core::*;
+use llvm_sys::prelude::*;
+use llvm_sys::{LLVMIntPredicate, LLVMTypeKind};
+use abi;
+use cfg::{Cfg, Item};
+use glsl;
+use ir::*;
+use trans::*;
+use TranspilerError;
+
+use super::{byte_offset, first_index, ExtInstSet, Interface, Layout, SpirvTranspiler};
+
+/// Where a pointer of a SIMD function points to, for every lane.
+#[derive(Clone)]
+pub enum Address {
+    /// The same memory for every lane, e.g. a uniform buffer.
+    Shared(LLVMValueRef),
+    /// A pointer for every lane.
+    Lanes(Vec<LLVMValueRef>),
+    /// Part of a function or private variable, which holds all lanes in the register type of
+    /// its SPIR-V type `ty`. `path` leads from the variable to the part.
+    Soa {
+        base: LLVMValueRef,
+        ty: Word,
+        path: Vec<SoaIndex>,
+    },
+}
+
+#[derive(Clone, Copy)]
+pub enum SoaIndex {
+    Constant(u32),
+    /// A different index for every lane.
+    Varying(LLVMValueRef),
+}
+
+/// Stack slots that carry state between the blocks of a SIMD function.
+#[derive(Default)]
+pub struct Slots {
+    /// The lanes that reach a block.
+    masks: HashMap<Word, LLVMValueRef>,
+    /// The lanes that take a back edge to a loop header.
+    continues: HashMap<Word, LLVMValueRef>,
+    /// The incoming value of a phi.
+    phis: HashMap<Word, LLVMValueRef>,
+    /// Values used in a different block than the one defining them.
+    values: HashMap<Word, LLVMValueRef>,
+    /// The return value of every lane.
+    ret: Option<LLVMValueRef>,
+}
+
+/// A point in the straight line code of a SIMD function.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Stop {
+    Block(usize),
+    /// The end of a loop, which runs the header again if any lane continues.
+    Latch(usize),
+}
+
+/// How the blocks of a function depend on each other.
+struct Flow<'a> {
+    blocks: &'a [BasicBlock],
+    labels: Vec<Word>,
+    indices: HashMap<Word, usize>,
+    cfg: Cfg,
+    /// Values every block loads from their slot before it runs.
+    imports: Vec<Vec<Word>>,
+    /// Access chains every block builds again, as pointers cannot be kept in slots.
+    chains: Vec<Vec<&'a Instruction>>,
+    /// Values that need a slot, with their type.
+    exports: HashMap<Word, Word>,
+}
+
+/// Returns whether `opcode` is translated differently when running several lanes at once.
+/// Terminators never get here, they are part of the control flow.
+pub fn is_lane_specific(opcode: Op) -> bool {
+    match opcode {
+        Op::Phi | Op::FunctionCall | Op::Variable | Op::Load | Op::Store | Op::CopyMemory |
+        Op::AccessChain | Op::InBoundsAccessChain | Op::CopyObject |
+        Op::VectorExtractDynamic | Op::VectorInsertDynamic | Op::VectorShuffle | Op::ExtInst => {
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Returns the literal of case `index` of an OpSwitch.
+fn case_literal(inst: &Instruction, index: usize) -> Result<u64, TranspilerError> {
+    match inst.operands.get(index) {
+        Some(&Operand::LiteralInt32(literal)) => Ok(literal as u64),
+        Some(&Operand::LiteralInt64(literal)) => Ok(literal),
+        _ => Err(TranspilerError::InvalidInstruction(inst.class.opcode)),
+    }
+}
+
+/// Lists the stops of `items` in the order they run.
+fn flatten(items: &[Item], stops: &mut Vec<Stop>) {
+    for item in items {
+        match *item {
+            Item::Block(block) => stops.push(Stop::Block(block)),
+            Item::Loop(header, ref body) => {
+                flatten(body, stops);
+                stops.push(Stop::Latch(header));
+            }
+        }
+    }
+}
+
+impl<'a> SpirvTranspiler<'a> {
+    /// Translates the body of a function declared by `trans_function_decls` to code that runs
+    /// all lanes at once.
+    pub unsafe fn trans_simd_function(
+        &mut self,
+        function: &'a Function,
+    ) -> Result<(), TranspilerError> {
+        let def = function.def.as_ref().ok_or(
+            TranspilerError::InvalidInstruction(Op::Function),
+        )?;
+        let id = result_id(def)?;
+        let llvm_function = self.value(id)?;
+        let entry_point = self.entry_points.contains_key(&id);
+        for (index, param) in function.parameters.iter().enumerate() {
+            let id = result_id(param)?;
+            let value = LLVMGetParam(llvm_function, index as u32 + 3);
+            self.track_pointer(id, result_type(param)?)?;
+            match self.pointers.get(&id).cloned() {
+                Some((ty, _)) => {
+                    let address = Address::Soa {
+                        base: value,
+                        ty: ty,
+                        path: Vec::new(),
+                    };
+                    self.addresses.insert(id, address);
+                }
+                None => {
+                    self.values.insert(id, value);
+                }
+            }
+        }
+        self.function = llvm_function;
+        if function.basic_blocks.is_empty() {
+            return Ok(());
+        }
+        let flow = self.analyze(function)?;
+
+        let builder = self.builder;
+        self.entry_block = LLVMAppendBasicBlockInContext(self.ctx, llvm_function, NONAME);
+        LLVMPositionBuilderAtEnd(builder, self.entry_block);
+        self.dummies.clear();
+        self.slots = Slots::default();
+        self.trans_simd_prologue(entry_point)?;
+        self.allocate_slots(&flow)?;
+
+        let mut stops = Vec::new();
+        flatten(&flow.cfg.order, &mut stops);
+        let blocks = stops
+            .iter()
+            .map(|&stop| match stop {
+                Stop::Block(block) => {
+                    let name = self.name(flow.labels[block]);
+                    LLVMAppendBasicBlockInContext(self.ctx, llvm_function, name.as_ptr())
+                }
+                Stop::Latch(_) => LLVMAppendBasicBlockInContext(self.ctx, llvm_function, NONAME),
+            })
+            .collect::<Vec<_>>();
+        let exit = LLVMAppendBasicBlockInContext(self.ctx, llvm_function, NONAME);
+        let after = |position: usize| blocks.get(position + 1).cloned().unwrap_or(exit);
+        LLVMBuildBr(builder, blocks[0]);
+
+        for (position, &stop) in stops.iter().enumerate() {
+            LLVMPositionBuilderAtEnd(builder, blocks[position]);
+            match stop {
+                Stop::Block(block) => {
+                    // If no lane reaches a loop header, the whole loop is skipped
+                    let skip = match stops.iter().position(|&stop| stop == Stop::Latch(block)) {
+                        Some(latch) => after(latch),
+                        None => after(position),
+                    };
+                    self.trans_simd_block(&flow, block, skip)?;
+                    LLVMBuildBr(builder, after(position));
+                }
+                Stop::Latch(header) => {
+                    let start = stops.iter().position(|&stop| stop == Stop::Block(header));
+                    let start = start.map(|start| blocks[start]).unwrap_or(exit);
+                    self.build_latch(flow.labels[header], start, after(position));
+                }
+            }
+        }
+
+        LLVMPositionBuilderAtEnd(builder, exit);
+        match self.slots.ret {
+            Some(slot) => LLVMBuildRet(builder, LLVMBuildLoad(builder, slot, NONAME)),
+            None => LLVMBuildRetVoid(builder),
+        };
+        Ok(())
+    }
+
+    /// Finds the order blocks run in and the values they need from other blocks.
+    fn analyze(&self, function: &'a Function) -> Result<Flow<'a>, TranspilerError> {
+        let blocks = &function.basic_blocks[..];
+        let mut labels = Vec::new();
+        for block in blocks {
+            let label = block.label.as_ref().ok_or(
+                TranspilerError::InvalidInstruction(Op::Label),
+            )?;
+            labels.push(result_id(label)?);
+        }
+        let indices = labels
+            .iter()
+            .enumerate()
+            .map(|(index, &label)| (label, index))
+            .collect::<HashMap<_, _>>();
+        let mut successors = Vec::new();
+        for block in blocks {
+            let mut targets = Vec::new();
+            if let Some(terminator) = block.instructions.last() {
+                for target in branch_targets(terminator)? {
+                    targets.push(*indices.get(&target).ok_or(
+                        TranspilerError::UndefinedId(target),
+                    )?);
+                }
+            }
+            successors.push(targets);
+        }
+        let cfg = Cfg::new(&successors).ok_or(TranspilerError::IrreducibleControlFlow)?;
+
+        // Where every result is defined, and which ids every block uses. Phis use their
+        // incoming values at the end of the block the value comes from.
+        let mut defs = HashMap::new();
+        let mut uses = vec![Vec::new(); blocks.len()];
+        for (index, block) in blocks.iter().enumerate() {
+            for (position, inst) in block.instructions.iter().enumerate() {
+                if let Some(id) = inst.result_id {
+                    defs.insert(id, (index, position, inst));
+                }
+                if inst.class.opcode == Op::Phi {
+                    let mut operand = 0;
+                    while operand + 1 < inst.operands.len() {
+                        let parent = operand_id(inst, operand + 1)?;
+                        if let Some(&parent) = indices.get(&parent) {
+                            uses[parent].push(operand_id(inst, operand)?);
+                        }
+                        operand += 2;
+                    }
+                    continue;
+                }
+                for operand in &inst.operands {
+                    if let Operand::IdRef(id) = *operand {
+                        uses[index].push(id);
+                    }
+                }
+            }
+        }
+
+        let mut imports = vec![Vec::new(); blocks.len()];
+        let mut chains = vec![Vec::new(); blocks.len()];
+        let mut exports = HashMap::new();
+        for index in (0..blocks.len()).filter(|&index| cfg.reachable[index]) {
+            let mut pending = uses[index].clone();
+            let mut seen = HashSet::new();
+            let mut block_imports = Vec::new();
+            let mut block_chains = Vec::new();
+            while let Some(id) = pending.pop() {
+                if !seen.insert(id) {
+                    continue;
+                }
+                let (block, position, inst) = match defs.get(&id) {
+                    Some(&def) if def.0 != index => def,
+                    _ => continue,
+                };
+                let ty = result_type(inst)?;
+                if self.def(ty)?.class.opcode != Op::TypePointer {
+                    block_imports.push((block, position, id));
+                    exports.insert(id, ty);
+                    continue;
+                }
+                match inst.class.opcode {
+                    // Variables live in the entry block, their address is valid everywhere
+                    Op::Variable => (),
+                    Op::AccessChain | Op::InBoundsAccessChain | Op::CopyObject => {
+                        block_chains.push((block, position, inst));
+                        pending.extend(operand_ids(inst, 0)?);
+                    }
+                    opcode => return Err(TranspilerError::UnsupportedInstruction(opcode)),
+                }
+            }
+            block_imports.sort_by_key(|&(block, position, _)| (block, position));
+            block_chains.sort_by_key(|&(block, position, _)| (block, position));
+            imports[index] = block_imports.into_iter().map(|(_, _, id)| id).collect();
+            chains[index] = block_chains.into_iter().map(|(_, _, inst)| inst).collect();
+        }
+
+        Ok(Flow {
+            blocks: blocks,
+            labels: labels,
+            indices: indices,
+            cfg: cfg,
+            imports: imports,
+            chains: chains,
+            exports: exports,
+        })
+    }
+
+    /// Makes the private and interface variables available to the function being translated,
+    /// and finds out which lanes run.
+    unsafe fn trans_simd_prologue(&mut self, entry_point: bool) -> Result<(), TranspilerError> {
+        let builder = self.builder;
+        let function = self.function;
+        self.invocation = LLVMGetParam(function, 0);
+        let size = mem::size_of::<abi::Invocation>();
+        let byte_pointer = self.byte_pointer_type();
+        let invocations = (0..self.lanes as usize)
+            .map(|lane| byte_offset(builder, self.invocation, lane * size, byte_pointer))
+            .collect();
+        self.invocations = invocations;
+
+        let private = if entry_point {
+            let private = LLVMBuildAlloca(builder, self.private_type, NONAME);
+            for (index, (id, initializer)) in self.private.clone().into_iter().enumerate() {
+                if let Some(initializer) = initializer {
+                    let (ty, _) = self.pointee(id)?;
+                    let initializer = self.const_broadcast(initializer, ty)?;
+                    let field = LLVMBuildStructGEP(builder, private, index as u32, NONAME);
+                    LLVMBuildStore(builder, initializer, field);
+                }
+            }
+            // Bit `lane` of the mask parameter is set if the lane runs
+            let i32_lanes = LLVMVectorType(LLVMInt32TypeInContext(self.ctx), self.lanes);
+            let mask = splat(builder, LLVMGetParam(function, 1), i32_lanes, 1);
+            let mut bits = (0..self.lanes)
+                .map(|lane| const_u32(self.ctx, 1 << lane))
+                .collect::<Vec<_>>();
+            let bits = LLVMConstVector(bits.as_mut_ptr(), self.lanes);
+            let mask = LLVMBuildAnd(builder, mask, bits, NONAME);
+            let zero = LLVMConstNull(i32_lanes);
+            self.mask = LLVMBuildICmp(builder, LLVMIntPredicate::LLVMIntNE, mask, zero, NONAME);
+            private
+        } else {
+            self.mask = LLVMGetParam(function, 2);
+            LLVMGetParam(function, 1)
+        };
+        for (index, (id, _)) in self.private.clone().into_iter().enumerate() {
+            let (ty, _) = self.pointee(id)?;
+            let field = LLVMBuildStructGEP(builder, private, index as u32, self.name(id).as_ptr());
+            let address = Address::Soa {
+                base: field,
+                ty: ty,
+                path: Vec::new(),
+            };
+            self.addresses.insert(id, address);
+        }
+        self.private_variables = private;
+
+        // All lanes of a batch share their resources
+        let byte_pointer_pointer = LLVMPointerType(byte_pointer, 0);
+        let resources = self.invocation_field(abi::resources_offset(), byte_pointer_pointer);
+        let resources = LLVMBuildLoad(builder, resources, NONAME);
+        self.descriptor_arrays.clear();
+        for (id, interface) in self.interface.clone() {
+            let address = match interface {
+                Interface::Invocation(offset) => {
+                    let (pointee, layout) = self.pointee(id)?;
+                    let ty = LLVMPointerType(self.trans_mem_type(pointee, layout)?.ty, 0);
+                    let pointers = self.invocations
+                        .iter()
+                        .map(|&invocation| byte_offset(builder, invocation, offset, ty))
+                        .collect();
+                    Address::Lanes(pointers)
+                }
+                // Members are resolved by access chains
+                Interface::BuiltInBlock(_) => continue,
+                _ => {
+                    match self.resource_pointer(resources, id, &interface)? {
+                        Some(pointer) => Address::Shared(pointer),
+                        None => continue,
+                    }
+                }
+            };
+            self.addresses.insert(id, address);
+        }
+        Ok(())
+    }
+
+    /// Allocates the stack slots of the function being translated. Only the mask of the entry
+    /// block starts out with any lanes.
+    unsafe fn allocate_slots(&mut self, flow: &Flow<'a>) -> Result<(), TranspilerError> {
+        let builder = self.builder;
+        let mask_ty = self.mask_type();
+        let none = LLVMConstNull(mask_ty);
+        for (index, block) in flow.blocks.iter().enumerate() {
+            if !flow.cfg.reachable[index] {
+                continue;
+            }
+            let slot = LLVMBuildAlloca(builder, mask_ty, NONAME);
+            let lanes = if index == 0 { self.mask } else { none };
+            LLVMBuildStore(builder, lanes, slot);
+            self.slots.masks.insert(flow.labels[index], slot);
+            for inst in &block.instructions {
+                if inst.class.opcode == Op::Phi {
+                    let ty = self.register_type(result_type(inst)?)?;
+                    let slot = LLVMBuildAlloca(builder, ty, NONAME);
+                    self.slots.phis.insert(result_id(inst)?, slot);
+                }
+            }
+        }
+        for &(_, header) in &flow.cfg.back_edges {
+            let slot = LLVMBuildAlloca(builder, mask_ty, NONAME);
+            LLVMBuildStore(builder, none, slot);
+            self.slots.continues.insert(flow.labels[header], slot);
+        }
+        for (&id, &ty) in &flow.exports {
+            let slot = LLVMBuildAlloca(builder, self.register_type(ty)?, NONAME);
+            self.slots.values.insert(id, slot);
+        }
+        let ret = LLVMGetReturnType(LLVMGetElementType(LLVMTypeOf(self.function)));
+        if LLVMGetTypeKind(ret) != LLVMTypeKind::LLVMVoidTypeKind {
+            self.slots.ret = Some(LLVMBuildAlloca(builder, ret, NONAME));
+        }
+        Ok(())
+    }
+
+    /// Translates block `index` for the lanes that reach it. Branches to `skip` if there are
+    /// none.
+    unsafe fn trans_simd_block(
+        &mut self,
+        flow: &Flow<'a>,
+        index: usize,
+        skip: LLVMBasicBlockRef,
+    ) -> Result<(), TranspilerError> {
+        let builder = self.builder;
+        let slot = self.slots.masks[&flow.labels[index]];
+        let mask = LLVMBuildLoad(builder, slot, NONAME);
+        // Lanes may reach the block again in the next iteration of a loop
+        LLVMBuildStore(builder, LLVMConstNull(LLVMTypeOf(mask)), slot);
+        if index != 0 {
+            let body = LLVMAppendBasicBlockInContext(self.ctx, self.function, NONAME);
+            LLVMBuildCondBr(builder, self.any(mask), body, skip);
+            LLVMPositionBuilderAtEnd(builder, body);
+        }
+        self.mask = mask;
+        // OpLine only reaches to the end of its block
+        self.line = None;
+
+        for &id in &flow.imports[index] {
+            let value = LLVMBuildLoad(builder, self.slots.values[&id], NONAME);
+            self.values.insert(id, value);
+        }
+        for &inst in &flow.chains[index] {
+            self.trans_instruction(inst)?;
+        }
+        for inst in &flow.blocks[index].instructions {
+            if is_terminator(inst.class.opcode) {
+                self.trans_simd_terminator(flow, index, inst).map_err(|error| {
+                    error.at(self.location(inst))
+                })?;
+                continue;
+            }
+            self.trans_instruction(inst)?;
+            let slot = match inst.result_id.and_then(|id| self.slots.values.get(&id)) {
+                Some(&slot) => slot,
+                None => continue,
+            };
+            let mut value = self.value(result_id(inst)?)?;
+            if flow.cfg.in_loop[index] {
+                // Lanes that left the loop earlier keep the value of their last iteration
+                let previous = LLVMBuildLoad(builder, slot, NONAME);
+                value = select(builder, self.mask, value, previous, self.lanes);
+            }
+            LLVMBuildStore(builder, value, slot);
+        }
+        Ok(())
+    }
+
+    /// Passes the lanes running block `index` on to the blocks its terminator `inst` branches
+    /// to.
+    unsafe fn trans_simd_terminator(
+        &mut self,
+        flow: &Flow<'a>,
+        index: usize,
+        inst: &'a Instruction,
+    ) -> Result<(), TranspilerError> {
+        let builder = self.builder;
+        let mask = self.mask;
+        match inst.class.opcode {
+            Op::Branch => self.build_edge(flow, index, operand_id(inst, 0)?, mask)?,
+            Op::BranchConditional => {
+                let condition = self.operand(inst, 0)?;
+                let taken = LLVMBuildAnd(builder, mask, condition, NONAME);
+                let not_taken = LLVMBuildNot(builder, condition, NONAME);
+                let not_taken = LLVMBuildAnd(builder, mask, not_taken, NONAME);
+                self.build_edge(flow, index, operand_id(inst, 1)?, taken)?;
+                self.build_edge(flow, index, operand_id(inst, 2)?, not_taken)?;
+            }
+            Op::Switch => {
+                let selector = self.operand(inst, 0)?;
+                let ty = LLVMTypeOf(selector);
+                let mut default = mask;
+                let mut case = 2;
+                while case + 1 < inst.operands.len() {
+                    let literal = LLVMConstInt(scalar_type(ty), case_literal(inst, case)?, 0);
+                    let literal = const_splat(literal, ty);
+                    let matches =
+                        LLVMBuildICmp(builder, LLVMIntPredicate::LLVMIntEQ, selector, literal, NONAME);
+                    let taken = LLVMBuildAnd(builder, mask, matches, NONAME);
+                    self.build_edge(flow, index, operand_id(inst, case + 1)?, taken)?;
+                    let not_taken = LLVMBuildNot(builder, matches, NONAME);
+                    default = LLVMBuildAnd(builder, default, not_taken, NONAME);
+                    case += 2;
+                }
+                self.build_edge(flow, index, operand_id(inst, 1)?, default)?;
+            }
+            Op::ReturnValue => {
+                if let Some(slot) = self.slots.ret {
+                    let value = self.operand(inst, 0)?;
+                    let previous = LLVMBuildLoad(builder, slot, NONAME);
+                    let value = select(builder, mask, value, previous, self.lanes);
+                    LLVMBuildStore(builder, value, slot);
+                }
+            }
+            Op::Kill => {
+                let killed_ty = LLVMPointerType(LLVMInt32TypeInContext(self.ctx), 0);
+                for lane in 0..self.lanes {
+                    let invocation = self.invocations[lane as usize];
+                    let killed = byte_offset(builder, invocation, abi::killed_offset(), killed_ty);
+                    let killed = self.lane_pointer(killed, lane);
+                    LLVMBuildStore(builder, const_u32(self.ctx, 1), killed);
+                }
+            }
+            // Lanes that return simply stop running
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Passes the lanes `mask` from block `from` on to block `target`, along with the incoming
+    /// values of the phis of `target`.
+    unsafe fn build_edge(
+        &mut self,
+        flow: &Flow<'a>,
+        from: usize,
+        target: Word,
+        mask: LLVMValueRef,
+    ) -> Result<(), TranspilerError> {
+        let builder = self.builder;
+        let to = *flow.indices.get(&target).ok_or(
+            TranspilerError::UndefinedId(target),
+        )?;
+        let slot = if flow.cfg.back_edges.contains(&(from, to)) {
+            self.slots.continues[&target]
+        } else {
+            self.slots.masks[&target]
+        };
+        let lanes = LLVMBuildLoad(builder, slot, NONAME);
+        LLVMBuildStore(builder, LLVMBuildOr(builder, lanes, mask, NONAME), slot);
+
+        for phi in &flow.blocks[to].instructions {
+            if phi.class.opcode != Op::Phi {
+                continue;
+            }
+            let mut operand = 0;
+            while operand + 1 < phi.operands.len() {
+                if operand_id(phi, operand + 1)? == flow.labels[from] {
+                    let value = self.operand(phi, operand)?;
+                    let slot = self.slots.phis[&result_id(phi)?];
+                    let previous = LLVMBuildLoad(builder, slot, NONAME);
+                    let value = select(builder, mask, value, previous, self.lanes);
+                    LLVMBuildStore(builder, value, slot);
+                }
+                operand += 2;
+            }
+        }
+        Ok(())
+    }
+
+    /// Ends an iteration of the loop with the given header, running `header` again for the
+    /// lanes that continue, and going on with `exit` once there are none.
+    unsafe fn build_latch(
+        &mut self,
+        header: Word,
+        start: LLVMBasicBlockRef,
+        exit: LLVMBasicBlockRef,
+    ) {
+        let builder = self.builder;
+        let slot = self.slots.continues[&header];
+        let lanes = LLVMBuildLoad(builder, slot, NONAME);
+        LLVMBuildStore(builder, LLVMConstNull(LLVMTypeOf(lanes)), slot);
+        LLVMBuildStore(builder, lanes, self.slots.masks[&header]);
+        LLVMBuildCondBr(builder, self.any(lanes), start, exit);
+    }
+
+    /// Translates the instructions `is_lane_specific` picks out.
+    pub unsafe fn trans_simd_instruction(
+        &mut self,
+        inst: &'a Instruction,
+    ) -> Result<Option<LLVMValueRef>, TranspilerError> {
+        let builder = self.builder;
+        let lanes = self.lanes;
+        let value = match inst.class.opcode {
+            Op::Phi => {
+                let id = result_id(inst)?;
+                let slot = self.slots.phis.get(&id).cloned().ok_or(
+                    TranspilerError::UndefinedId(id),
+                )?;
+                LLVMBuildLoad(builder, slot, NONAME)
+            }
+            Op::FunctionCall => self.trans_simd_call(inst)?,
+            Op::Variable => {
+                let id = result_id(inst)?;
+                self.track_pointer(id, result_type(inst)?)?;
+                let (ty, _) = self.pointee(id)?;
+                let register_ty = self.register_type(ty)?;
+                let variable = self.entry_alloca(register_ty, self.name(id).as_ptr());
+                if inst.operands.len() > 1 {
+                    LLVMBuildStore(builder, self.operand(inst, 1)?, variable);
+                }
+                let address = Address::Soa {
+                    base: variable,
+                    ty: ty,
+                    path: Vec::new(),
+                };
+                self.addresses.insert(id, address);
+                return Ok(None);
+            }
+            Op::Load => {
+                let pointer = operand_id(inst, 0)?;
+                let (ty, layout) = self.pointee(pointer)?;
+                let address = self.address(pointer)?;
+                self.simd_load(&address, ty, layout)?
+            }
+            Op::Store => {
+                let pointer = operand_id(inst, 0)?;
+                let (ty, layout) = self.pointee(pointer)?;
+                let address = self.address(pointer)?;
+                self.simd_store(&address, self.operand(inst, 1)?, ty, layout)?;
+                return Ok(None);
+            }
+            Op::CopyMemory => {
+                let target = operand_id(inst, 0)?;
+                let source = operand_id(inst, 1)?;
+                let (ty, target_layout) = self.pointee(target)?;
+                let (_, source_layout) = self.pointee(source)?;
+                let value = self.simd_load(&self.address(source)?, ty, source_layout)?;
+                self.simd_store(&self.address(target)?, value, ty, target_layout)?;
+                return Ok(None);
+            }
+            Op::AccessChain | Op::InBoundsAccessChain => {
+                self.trans_simd_access_chain(inst)?;
+                return Ok(None);
+            }
+            Op::CopyObject => {
+                let id = operand_id(inst, 0)?;
+                match self.addresses.get(&id).cloned() {
+                    Some(address) => {
+                        let result = result_id(inst)?;
+                        let pointee = self.pointee(id)?;
+                        self.pointers.insert(result, pointee);
+                        self.addresses.insert(result, address);
+                        return Ok(None);
+                    }
+                    None => self.value(id)?,
+                }
+            }
+
+            Op::VectorExtractDynamic => {
+                let components = components(builder, self.operand(inst, 0)?, lanes);
+                self.select_element(&components, self.operand(inst, 1)?)
+            }
+            Op::VectorInsertDynamic => {
+                let vector = self.operand(inst, 0)?;
+                let component = self.operand(inst, 1)?;
+                let index = self.operand(inst, 2)?;
+                let mut components = components(builder, vector, lanes);
+                for (position, element) in components.iter_mut().enumerate() {
+                    let matches = self.index_matches(index, position);
+                    *element = select(builder, matches, component, *element, lanes);
+                }
+                from_components(builder, LLVMTypeOf(vector), &components, lanes)
+            }
+            Op::VectorShuffle => {
+                let ty = self.register_type(result_type(inst)?)?;
+                let mut sources = components(builder, self.operand(inst, 0)?, lanes);
+                sources.extend(components(builder, self.operand(inst, 1)?, lanes));
+                let undef = LLVMGetUndef(LLVMTypeOf(sources[0]));
+                let picked = self.literals(inst, 2)?
+                    .into_iter()
+                    .map(|index| sources.get(index as usize).cloned().unwrap_or(undef))
+                    .collect::<Vec<_>>();
+                from_components(builder, ty, &picked, lanes)
+            }
+
+            Op::ExtInst => {
+                if self.ext_inst_set(inst)? == ExtInstSet::NonSemantic {
+                    return Ok(None);
+                }
+                self.trans_simd_glsl_inst(inst)?
+            }
+            opcode => return Err(TranspilerError::UnsupportedInstruction(opcode)),
+        };
+        Ok(Some(value))
+    }
+
+    /// Translates a GLSL.std.450 instruction. The ones that take pointers go through the
+    /// addresses of all lanes.
+    unsafe fn trans_simd_glsl_inst(
+        &mut self,
+        inst: &'a Instruction,
+    ) -> Result<LLVMValueRef, TranspilerError> {
+        let builder = self.builder;
+        let ty = self.register_type(result_type(inst)?)?;
+        let instruction = operand_u32(inst, 1)?;
+        if glsl::is_interpolation(instruction) {
+            // Interpolants are read at the pixel center, which is where they already are
+            let pointer = operand_id(inst, 2)?;
+            let (pointee, layout) = self.pointee(pointer)?;
+            return self.simd_load(&self.address(pointer)?, pointee, layout);
+        }
+        if let Some(variant) = glsl::struct_variant(instruction) {
+            // Returns both results in a struct, the second one is stored to the pointer
+            let x = self.operand(inst, 2)?;
+            let pointer = operand_id(inst, 3)?;
+            let (pointee, layout) = self.pointee(pointer)?;
+            let mut members = [ty, self.register_type(pointee)?];
+            let struct_ty = LLVMStructTypeInContext(self.ctx, members.as_mut_ptr(), 2, 0);
+            let results =
+                glsl::trans_glsl_inst(builder, self.module, variant, struct_ty, &[x], self.lanes)?;
+            let second = LLVMBuildExtractValue(builder, results, 1, NONAME);
+            self.simd_store(&self.address(pointer)?, second, pointee, layout)?;
+            return Ok(LLVMBuildExtractValue(builder, results, 0, NONAME));
+        }
+        let args = operand_ids(inst, 2)?
+            .into_iter()
+            .map(|id| self.value(id))
+            .collect::<Result<Vec<_>, _>>()?;
+        glsl::trans_glsl_inst(builder, self.module, instruction, ty, &args, self.lanes)
+    }
+
+    /// Translates OpFunctionCall, passing on the lanes that run. Lanes the callee discards stop
+    /// running in the caller as well.
+    unsafe fn trans_simd_call(&mut self, inst: &'a Instruction) -> Result<LLVMValueRef, TranspilerError> {
+        let builder = self.builder;
+        let function = self.operand(inst, 0)?;
+        let mut args = vec![self.invocation, self.private_variables, self.mask];
+        let mut copies = Vec::new();
+        for id in operand_ids(inst, 1)? {
+            let arg = match self.addresses.get(&id).cloned() {
+                Some(Address::Soa { base, ty, path }) => {
+                    match self.soa_pointer(base, ty, &path)? {
+                        Some(pointer) => pointer,
+                        None => {
+                            // Vector components and dynamically indexed elements have no
+                            // address of their own, the callee gets a copy that is written back
+                            let (pointee, _) = self.pointee(id)?;
+                            let copy_ty = self.register_type(pointee)?;
+                            let copy = self.entry_alloca(copy_ty, NONAME);
+                            LLVMBuildStore(builder, self.soa_load(base, ty, &path)?, copy);
+                            copies.push((id, pointee, copy));
+                            copy
+                        }
+                    }
+                }
+                Some(_) => return Err(TranspilerError::InvalidInstruction(Op::FunctionCall)),
+                None => self.value(id)?,
+            };
+            args.push(arg);
+        }
+        // Void values must not be named, so calls never are
+        let call = LLVMBuildCall(builder, function, args.as_mut_ptr(), args.len() as u32, NONAME);
+        for (id, pointee, copy) in copies {
+            let value = LLVMBuildLoad(builder, copy, NONAME);
+            self.simd_store(&self.address(id)?, value, pointee, Layout::Natural)?;
+        }
+
+        if self.kills {
+            let killed_ty = LLVMPointerType(LLVMInt32TypeInContext(self.ctx), 0);
+            let mut killed_lanes = LLVMConstNull(self.mask_type());
+            for (lane, &invocation) in self.invocations.iter().enumerate() {
+                let killed = byte_offset(builder, invocation, abi::killed_offset(), killed_ty);
+                let killed = LLVMBuildLoad(builder, killed, NONAME);
+                let zero = LLVMConstNull(LLVMTypeOf(killed));
+                let killed = LLVMBuildICmp(builder, LLVMIntPredicate::LLVMIntNE, killed, zero, NONAME);
+                let lane = const_u32(self.ctx, lane as u32);
+                killed_lanes = LLVMBuildInsertElement(builder, killed_lanes, killed, lane, NONAME);
+            }
+            let running = LLVMBuildNot(builder, killed_lanes, NONAME);
+            self.mask = LLVMBuildAnd(builder, self.mask, running, NONAME);
+        }
+        Ok(call)
+    }
+
+    /// Translates OpAccessChain into the address of every lane.
+    unsafe fn trans_simd_access_chain(&mut self, inst: &'a Instruction) -> Result<(), TranspilerError> {
+        let builder = self.builder;
+        let base_id = operand_id(inst, 0)?;
+        let (mut ty, mut layout) = self.pointee(base_id)?;
+        let mut index_ids = operand_ids(inst, 1)?;
+        let base = if let Some(descriptors) = self.descriptor_arrays.get(&base_id).cloned() {
+            // Every element of an array of buffers has a descriptor of its own
+            let index = first_index(inst, &mut index_ids)?;
+            ty = operand_id(self.def(ty)?, 0)?;
+            let buffer_ty = LLVMPointerType(self.trans_mem_type(ty, layout)?.ty, 0);
+            match self.constants.get(&index).cloned() {
+                Some(index) => Address::Shared(self.descriptor_buffer(descriptors, index, buffer_ty)),
+                None => {
+                    // Masked off lanes read the first descriptor, which is always there
+                    let index = self.value(index)?;
+                    let mut buffers = Vec::new();
+                    for lane in 0..self.lanes {
+                        let lane_index = self.lane_value(index, lane);
+                        let zero = LLVMConstNull(LLVMTypeOf(lane_index));
+                        let active = self.lane_active(lane);
+                        let lane_index = LLVMBuildSelect(builder, active, lane_index, zero, NONAME);
+                        buffers.push(self.descriptor_buffer(descriptors, lane_index, buffer_ty));
+                    }
+                    Address::Lanes(buffers)
+                }
+            }
+        } else if let Some(members) = self.builtin_block(base_id) {
+            // Members of built-in blocks are spread over the `Invocation`
+            let index = first_index(inst, &mut index_ids)?;
+            let (offset, member_ty) = self.builtin_member(&members, ty, index)?;
+            ty = member_ty;
+            layout = Layout::Natural;
+            let pointer_ty = LLVMPointerType(self.trans_type(ty)?, 0);
+            let pointers = self.invocations
+                .iter()
+                .map(|&invocation| byte_offset(builder, invocation, offset, pointer_ty))
+                .collect();
+            Address::Lanes(pointers)
+        } else {
+            self.address(base_id)?
+        };
+
+        let inbounds = inst.class.opcode == Op::InBoundsAccessChain;
+        let uniform = index_ids.iter().all(|id| self.constants.contains_key(id));
+        let (address, ty, layout) = match base {
+            Address::Soa {
+                base,
+                ty: base_ty,
+                mut path,
+            } => {
+                for &index in &index_ids {
+                    let def = self.def(ty)?;
+                    path.push(self.soa_index(index)?);
+                    ty = if def.class.opcode == Op::TypeStruct {
+                        operand_id(def, self.constant_u32(index)? as usize)?
+                    } else {
+                        operand_id(def, 0)?
+                    };
+                }
+                let address = Address::Soa {
+                    base: base,
+                    ty: base_ty,
+                    path: path,
+                };
+                (address, ty, layout)
+            }
+            Address::Shared(pointer) if uniform => {
+                let values = index_ids
+                    .iter()
+                    .map(|&index| self.constant(index))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let (pointer, ty, layout) =
+                    self.chain_gep(pointer, ty, layout, &index_ids, &values, inbounds)?;
+                (Address::Shared(pointer), ty, layout)
+            }
+            Address::Shared(pointer) => {
+                let pointers = vec![pointer; self.lanes as usize];
+                self.lanes_chain(&pointers, ty, layout, &index_ids, inbounds)?
+            }
+            Address::Lanes(pointers) => self.lanes_chain(&pointers, ty, layout, &index_ids, inbounds)?,
+        };
+        let id = result_id(inst)?;
+        self.pointers.insert(id, (ty, layout));
+        self.addresses.insert(id, address);
+        Ok(())
+    }
+
+    /// Follows an access chain from the pointer of every lane, with the indices of that lane.
+    unsafe fn lanes_chain(
+        &mut self,
+        pointers: &[LLVMValueRef],
+        ty: Word,
+        layout: Layout,
+        index_ids: &[Word],
+        inbounds: bool,
+    ) -> Result<(Address, Word, Layout), TranspilerError> {
+        let mut lane_pointers = Vec::new();
+        let mut pointee = (ty, layout);
+        for (lane, &pointer) in pointers.iter().enumerate() {
+            let mut values = Vec::new();
+            for &index in index_ids {
+                values.push(match self.constants.get(&index) {
+                    Some(&constant) => constant,
+                    None => self.lane_value(self.value(index)?, lane as u32),
+                });
+            }
+            let (pointer, ty, layout) =
+                self.chain_gep(pointer, ty, layout, index_ids, &values, inbounds)?;
+            lane_pointers.push(pointer);
+            pointee = (ty, layout);
+        }
+        Ok((Address::Lanes(lane_pointers), pointee.0, pointee.1))
+    }
+
+    fn address(&self, id: Word) -> Result<Address, TranspilerError> {
+        self.addresses.get(&id).cloned().ok_or(
+            TranspilerError::UndefinedId(id),
+        )
+    }
+
+    fn soa_index(&self, id: Word) -> Result<SoaIndex, TranspilerError> {
+        if self.constants.contains_key(&id) {
+            if let Ok(index) = self.constant_u32(id) {
+                return Ok(SoaIndex::Constant(index));
+            }
+        }
+        Ok(SoaIndex::Varying(self.value(id)?))
+    }
+
+    /// Loads a value of SPIR-V type `ty` for all lanes, from memory laid out according to
+    /// `layout`.
+    unsafe fn simd_load(
+        &mut self,
+        address: &Address,
+        ty: Word,
+        layout: Layout,
+    ) -> Result<LLVMValueRef, TranspilerError> {
+        match *address {
+            Address::Shared(pointer) => {
+                let value = self.load(pointer, ty, layout)?;
+                self.broadcast(value, ty)
+            }
+            Address::Lanes(ref pointers) => {
+                let mut value = LLVMGetUndef(self.register_type(ty)?);
+                for (lane, &pointer) in pointers.iter().enumerate() {
+                    let lane = lane as u32;
+                    let pointer = self.lane_pointer(pointer, lane);
+                    let lane_value = self.load(pointer, ty, layout)?;
+                    value = self.insert_lane(value, lane, lane_value, ty)?;
+                }
+                Ok(value)
+            }
+            Address::Soa { base, ty, ref path } => self.soa_load(base, ty, path),
+        }
+    }
+
+    /// Stores `value` of SPIR-V type `ty` for the lanes that run, the inverse of `simd_load`.
+    unsafe fn simd_store(
+        &mut self,
+        address: &Address,
+        value: LLVMValueRef,
+        ty: Word,
+        layout: Layout,
+    ) -> Result<(), TranspilerError> {
+        match *address {
+            Address::Shared(pointer) => {
+                // Every lane stores its own value, the last one wins
+                let pointers = Address::Lanes(vec![pointer; self.lanes as usize]);
+                self.simd_store(&pointers, value, ty, layout)
+            }
+            Address::Lanes(ref pointers) => {
+                for (lane, &pointer) in pointers.iter().enumerate() {
+                    let lane = lane as u32;
+                    let pointer = self.lane_pointer(pointer, lane);
+                    let lane_value = self.extract_lane(value, lane, ty)?;
+                    self.store(pointer, lane_value, ty, layout)?;
+                }
+                Ok(())
+            }
+            Address::Soa { base, ty, ref path } => self.soa_store(base, ty, path, value),
+        }
+    }
+
+    /// Loads the part of a variable of SPIR-V type `ty` at `base` that `path` leads to.
+    unsafe fn soa_load(
+        &mut self,
+        base: LLVMValueRef,
+        mut ty: Word,
+        path: &[SoaIndex],
+    ) -> Result<LLVMValueRef, TranspilerError> {
+        let builder = self.builder;
+        // Constant indices are followed in memory, everything after the first varying index
+        // or vector component in a register
+        let mut pointer = base;
+        let mut value = None;
+        for &index in path {
+            let def = self.def(ty)?;
+            let element_ty = self.soa_element_type(ty, index)?;
+            match (index, value) {
+                (SoaIndex::Constant(member), None) if def.class.opcode != Op::TypeVector => {
+                    pointer = self.element_pointer(pointer, &[0, member]);
+                }
+                (_, previous) => {
+                    let aggregate =
+                        previous.unwrap_or_else(|| LLVMBuildLoad(builder, pointer, NONAME));
+                    value = Some(self.soa_element(aggregate, def.class.opcode, index));
+                }
+            }
+            ty = element_ty;
+        }
+        Ok(value.unwrap_or_else(|| LLVMBuildLoad(builder, pointer, NONAME)))
+    }
+
+    /// Stores `value` to the part of a variable that `path` leads to, for the lanes that run.
+    unsafe fn soa_store(
+        &mut self,
+        base: LLVMValueRef,
+        mut ty: Word,
+        mut path: &[SoaIndex],
+        value: LLVMValueRef,
+    ) -> Result<(), TranspilerError> {
+        let builder = self.builder;
+        let mut pointer = base;
+        while let Some((&SoaIndex::Constant(member), rest)) = path.split_first() {
+            if self.def(ty)?.class.opcode == Op::TypeVector {
+                break;
+            }
+            ty = self.soa_element_type(ty, SoaIndex::Constant(member))?;
+            pointer = self.element_pointer(pointer, &[0, member]);
+            path = rest;
+        }
+        let previous = LLVMBuildLoad(builder, pointer, NONAME);
+        let mask = self.mask;
+        let value = self.soa_insert(previous, ty, path, value, mask)?;
+        LLVMBuildStore(builder, value, pointer);
+        Ok(())
+    }
+
+    /// Returns `aggregate` of SPIR-V type `ty` with the part `path` leads to replaced by `value`
+    /// in the lanes `mask`.
+    unsafe fn soa_insert(
+        &mut self,
+        aggregate: LLVMValueRef,
+        ty: Word,
+        path: &[SoaIndex],
+        value: LLVMValueRef,
+        mask: LLVMValueRef,
+    ) -> Result<LLVMValueRef, TranspilerError> {
+        let builder = self.builder;
+        let lanes = self.lanes;
+        let (&index, rest) = match path.split_first() {
+            Some(split) => split,
+            None => return Ok(select(builder, mask, value, aggregate, lanes)),
+        };
+        let element_ty = self.soa_element_type(ty, index)?;
+        if self.def(ty)?.class.opcode == Op::TypeVector {
+            let mut components = components(builder, aggregate, lanes);
+            for (position, component) in components.iter_mut().enumerate() {
+                let component_mask = match index {
+                    SoaIndex::Constant(constant) if constant as usize == position => mask,
+                    SoaIndex::Constant(_) => continue,
+                    SoaIndex::Varying(varying) => {
+                        let matches = self.index_matches(varying, position);
+                        LLVMBuildAnd(builder, mask, matches, NONAME)
+                    }
+                };
+                *component = select(builder, component_mask, value, *component, lanes);
+            }
+            return Ok(from_components(builder, LLVMTypeOf(aggregate), &components, lanes));
+        }
+        match index {
+            SoaIndex::Constant(member) => {
+                let element = LLVMBuildExtractValue(builder, aggregate, member, NONAME);
+                let element = self.soa_insert(element, element_ty, rest, value, mask)?;
+                Ok(LLVMBuildInsertValue(builder, aggregate, element, member, NONAME))
+            }
+            SoaIndex::Varying(varying) => {
+                let mut aggregate = aggregate;
+                for position in 0..LLVMGetArrayLength(LLVMTypeOf(aggregate)) {
+                    let matches = self.index_matches(varying, position as usize);
+                    let element_mask = LLVMBuildAnd(builder, mask, matches, NONAME);
+                    let element = LLVMBuildExtractValue(builder, aggregate, position, NONAME);
+                    let element = self.soa_insert(element, element_ty, rest, value, element_mask)?;
+                    aggregate = LLVMBuildInsertValue(builder, aggregate, element, position, NONAME);
+                }
+                Ok(aggregate)
+            }
+        }
+    }
+
+    /// Returns the type of the element of SPIR-V type `ty` that `index` selects.
+    fn soa_element_type(&self, ty: Word, index: SoaIndex) -> Result<Word, TranspilerError> {
+        let def = self.def(ty)?;
+        match (def.class.opcode, index) {
+            (Op::TypeStruct, SoaIndex::Constant(member)) => operand_id(def, member as usize),
+            (Op::TypeStruct, SoaIndex::Varying(_)) => {
+                Err(TranspilerError::InvalidInstruction(Op::AccessChain))
+            }
+            _ => operand_id(def, 0),
+        }
+    }
+
+    /// Returns the element of `aggregate`, a SPIR-V `opcode` type holding all lanes, that `index`
+    /// selects.
+    unsafe fn soa_element(&self, aggregate: LLVMValueRef, opcode: Op, index: SoaIndex) -> LLVMValueRef {
+        let builder = self.builder;
+        match (opcode, index) {
+            (Op::TypeVector, SoaIndex::Constant(position)) => {
+                component(builder, aggregate, position, self.lanes)
+            }
+            (Op::TypeVector, SoaIndex::Varying(varying)) => {
+                let components = components(builder, aggregate, self.lanes);
+                self.select_element(&components, varying)
+            }
+            (_, SoaIndex::Constant(member)) => {
+                LLVMBuildExtractValue(builder, aggregate, member, NONAME)
+            }
+            (_, SoaIndex::Varying(varying)) => {
+                let elements = (0..LLVMGetArrayLength(LLVMTypeOf(aggregate)))
+                    .map(|position| LLVMBuildExtractValue(builder, aggregate, position, NONAME))
+                    .collect::<Vec<_>>();
+                self.select_element(&elements, varying)
+            }
+        }
+    }
+
+    /// Returns a pointer to the part of a variable that `path` leads to, or None if it is no
+    /// variable of its own in the layout of all lanes.
+    unsafe fn soa_pointer(
+        &mut self,
+        base: LLVMValueRef,
+        mut ty: Word,
+        path: &[SoaIndex],
+    ) -> Result<Option<LLVMValueRef>, TranspilerError> {
+        let mut indices = vec![0];
+        for &index in path {
+            match index {
+                SoaIndex::Constant(member) if self.def(ty)?.class.opcode != Op::TypeVector => {
+                    indices.push(member);
+                    ty = self.soa_element_type(ty, index)?;
+                }
+                _ => return Ok(None),
+            }
+        }
+        Ok(Some(self.element_pointer(base, &indices)))
+    }
+
+    /// Picks `elements[index]` in every lane.
+    unsafe fn select_element(&self, elements: &[LLVMValueRef], index: LLVMValueRef) -> LLVMValueRef {
+        let mut result = elements[0];
+        for (position, &element) in elements.iter().enumerate().skip(1) {
+            let matches = self.index_matches(index, position);
+            result = select(self.builder, matches, element, result, self.lanes);
+        }
+        result
+    }
+
+    /// Returns the lanes where the integer `index` equals `position`.
+    unsafe fn index_matches(&self, index: LLVMValueRef, position: usize) -> LLVMValueRef {
+        let ty = LLVMTypeOf(index);
+        let position = const_splat(LLVMConstInt(scalar_type(ty), position as u64, 0), ty);
+        LLVMBuildICmp(self.builder, LLVMIntPredicate::LLVMIntEQ, index, position, NONAME)
+    }
+
+    /// Returns the value of SPIR-V type `ty` of a single lane for all lanes.
+    unsafe fn broadcast(
+        &mut self,
+        value: LLVMValueRef,
+        ty: Word,
+    ) -> Result<LLVMValueRef, TranspilerError> {
+        let builder = self.builder;
+        let lanes = self.lanes;
+        let def = self.def(ty)?;
+        let value = match def.class.opcode {
+            Op::TypeVector => {
+                let count = operand_u32(def, 1)?;
+                let indices = (0..count * lanes).map(|index| index / lanes).collect::<Vec<_>>();
+                shuffle(builder, value, &indices)
+            }
+            Op::TypeMatrix | Op::TypeArray | Op::TypeStruct => {
+                let register_ty = self.register_type(ty)?;
+                let mut members = Vec::new();
+                for (index, member_ty) in self.member_types(ty)?.into_iter().enumerate() {
+                    let member = LLVMBuildExtractValue(builder, value, index as u32, NONAME);
+                    members.push(self.broadcast(member, member_ty)?);
+                }
+                build_aggregate(builder, register_ty, &members)
+            }
+            _ => splat(builder, value, LLVMVectorType(LLVMTypeOf(value), lanes), 1),
+        };
+        Ok(value)
+    }
+
+    /// Returns the value of `lane` in `value` of SPIR-V type `ty`.
+    unsafe fn extract_lane(
+        &mut self,
+        value: LLVMValueRef,
+        lane: u32,
+        ty: Word,
+    ) -> Result<LLVMValueRef, TranspilerError> {
+        let builder = self.builder;
+        let lanes = self.lanes;
+        let def = self.def(ty)?;
+        let value = match def.class.opcode {
+            Op::TypeVector => {
+                let count = operand_u32(def, 1)?;
+                let indices = (0..count)
+                    .map(|component| component * lanes + lane)
+                    .collect::<Vec<_>>();
+                shuffle(builder, value, &indices)
+            }
+            Op::TypeMatrix | Op::TypeArray | Op::TypeStruct => {
+                let scalar_ty = self.trans_type(ty)?;
+                let mut members = Vec::new();
+                for (index, member_ty) in self.member_types(ty)?.into_iter().enumerate() {
+                    let member = LLVMBuildExtractValue(builder, value, index as u32, NONAME);
+                    members.push(self.extract_lane(member, lane, member_ty)?);
+                }
+                build_aggregate(builder, scalar_ty, &members)
+            }
+            _ => self.lane_value(value, lane),
+        };
+        Ok(value)
+    }
+
+    /// Returns `wide` of SPIR-V type `ty` with `lane` replaced by `value`.
+    unsafe fn insert_lane(
+        &mut self,
+        wide: LLVMValueRef,
+        lane: u32,
+        value: LLVMValueRef,
+        ty: Word,
+    ) -> Result<LLVMValueRef, TranspilerError> {
+        let builder = self.builder;
+        let def = self.def(ty)?;
+        let wide = match def.class.opcode {
+            Op::TypeVector => {
+                let mut wide = wide;
+                for component in 0..operand_u32(def, 1)? {
+                    let index = const_u32(self.ctx, component);
+                    let element = LLVMBuildExtractElement(builder, value, index, NONAME);
+                    let index = const_u32(self.ctx, component * self.lanes + lane);
+                    wide = LLVMBuildInsertElement(builder, wide, element, index, NONAME);
+                }
+                wide
+            }
+            Op::TypeMatrix | Op::TypeArray | Op::TypeStruct => {
+                let mut wide = wide;
+                for (index, member_ty) in self.member_types(ty)?.into_iter().enumerate() {
+                    let index = index as u32;
+                    let member = LLVMBuildExtractValue(builder, wide, index, NONAME);
+                    let member_value = LLVMBuildExtractValue(builder, value, index, NONAME);
+                    let member = self.insert_lane(member, lane, member_value, member_ty)?;
+                    wide = LLVMBuildInsertValue(builder, wide, member, index, NONAME);
+                }
+                wide
+            }
+            _ => {
+                let index = const_u32(self.ctx, lane);
+                LLVMBuildInsertElement(builder, wide, value, index, NONAME)
+            }
+        };
+        Ok(wide)
+    }
+
+    /// Returns element `lane` of a vector with an element per lane.
+    unsafe fn lane_value(&self, value: LLVMValueRef, lane: u32) -> LLVMValueRef {
+        LLVMBuildExtractElement(self.builder, value, const_u32(self.ctx, lane), NONAME)
+    }
+
+    /// Returns whether `lane` runs the current block.
+    unsafe fn lane_active(&self, lane: u32) -> LLVMValueRef {
+        self.lane_value(self.mask, lane)
+    }
+
+    /// Returns `pointer` if `lane` runs, and a dummy to load from and store to otherwise.
+    unsafe fn lane_pointer(&mut self, pointer: LLVMValueRef, lane: u32) -> LLVMValueRef {
+        let ty = LLVMGetElementType(LLVMTypeOf(pointer));
+        let dummy = match self.dummies.get(&ty).cloned() {
+            Some(dummy) => dummy,
+            None => {
+                let dummy = self.entry_alloca(ty, NONAME);
+                self.dummies.insert(ty, dummy);
+                dummy
+            }
+        };
+        LLVMBuildSelect(self.builder, self.lane_active(lane), pointer, dummy, NONAME)
+    }
+
+    /// Returns whether any lane of `mask` is set.
+    unsafe fn any(&self, mask: LLVMValueRef) -> LLVMValueRef {
+        let bits = LLVMIntTypeInContext(self.ctx, self.lanes);
+        let bits = LLVMBuildBitCast(self.builder, mask, bits, NONAME);
+        let zero = LLVMConstNull(LLVMTypeOf(bits));
+        LLVMBuildICmp(self.builder, LLVMIntPredicate::LLVMIntNE, bits, zero, NONAME)
+    }
+
+    /// Type of a mask with a bool per lane.
+    fn mask_type(&self) -> LLVMTypeRef {
+        unsafe { LLVMVectorType(LLVMInt1TypeInContext(self.ctx), self.lanes) }
+    }
+
+    /// Allocates stack memory in the entry block, which is where LLVM can promote it to
+    /// registers.
+    unsafe fn entry_alloca(&self, ty: LLVMTypeRef, name: *const c_char) -> LLVMValueRef {
+        let builder = LLVMCreateBuilderInContext(self.ctx);
+        let first = LLVMGetFirstInstruction(self.entry_block);
+        if first.is_null() {
+            LLVMPositionBuilderAtEnd(builder, self.entry_block);
+        } else {
+            LLVMPositionBuilderBefore(builder, first);
+        }
+        let alloca = LLVMBuildAlloca(builder, ty, name);
+        LLVMDisposeBuilder(builder);
+        alloca
+    }
+}
@@ -0,0 +1,472 @@
+//! Checks that a module only uses what the transpiler supports, before anything is translated.
+//!
+//! Besides rejecting unsupported modules with a precise error, this collects the entry points,
+//! their execution modes and their interfaces, which the pipeline needs to know about to run
+//! them.
+use std::cmp;
+use std::collections::HashMap;
+
+use rspirv::mr::{Instruction, Module, Operand};
+use abi;
+use spirv_headers::*;
+use trans::*;
+use {Location, TranspilerError};
+
+const MAGIC_NUMBER: u32 = 0x07230203;
+
+/// The newest SPIR-V version we accept, as (major, minor).
+const MAX_VERSION: (u8, u8) = (1, 5);
+
+const CAPABILITIES: &[Capability] = &[
+    Capability::Matrix,
+    Capability::Shader,
+    Capability::Float16,
+    Capability::Float64,
+    Capability::Int8,
+    Capability::Int16,
+    Capability::Int64,
+    Capability::ClipDistance,
+    Capability::CullDistance,
+    Capability::SampleRateShading,
+    Capability::DrawParameters,
+    Capability::StorageBuffer16BitAccess,
+    Capability::UniformAndStorageBuffer16BitAccess,
+    Capability::StoragePushConstant16,
+    Capability::StorageInputOutput16,
+    Capability::StorageBuffer8BitAccess,
+    Capability::UniformAndStorageBuffer8BitAccess,
+    Capability::StoragePushConstant8,
+    Capability::VulkanMemoryModel,
+];
+
+/// OpExtension strings of extensions whose capabilities and instructions we support. Extensions
+/// that only add decorations for tools to read are harmless too.
+const EXTENSIONS: &[&str] = &[
+    "SPV_KHR_storage_buffer_storage_class",
+    "SPV_KHR_shader_draw_parameters",
+    "SPV_KHR_16bit_storage",
+    "SPV_KHR_8bit_storage",
+    "SPV_KHR_vulkan_memory_model",
+    "SPV_KHR_non_semantic_info",
+    "SPV_GOOGLE_decorate_string",
+    "SPV_GOOGLE_hlsl_functionality1",
+    "SPV_GOOGLE_user_type",
+];
+
+/// An entry point of a module and what its execution modes ask of the pipeline.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntryPointInfo {
+    pub name: String,
+    pub model: ExecutionModel,
+    pub modes: ExecutionModes,
+    /// The Input variables with a Location, which the previous stage or the vertex attributes
+    /// provide.
+    pub inputs: Vec<InterfaceVariable>,
+    /// The Output variables with a Location.
+    pub outputs: Vec<InterfaceVariable>,
+    /// Whether the module has an OpControlBarrier, so that the invocations of a workgroup have
+    /// to run side by side.
+    pub barriers: bool,
+}
+
+/// An Input or Output variable of an entry point that is not a built-in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InterfaceVariable {
+    pub location: u32,
+    /// The first 32 bit word of its first Location it takes.
+    pub component: u32,
+    /// Number of consecutive Locations it takes.
+    pub locations: u32,
+    /// Number of 32 bit words it takes of each of its Locations, starting at `component`.
+    pub components: u32,
+    /// The type of its components, `None` for structs, whose members may differ.
+    pub scalar: Option<ScalarType>,
+    pub interpolation: Interpolation,
+}
+
+impl InterfaceVariable {
+    /// Whether the variable takes word `component` of Location `location`.
+    pub fn covers(&self, location: u32, component: u32) -> bool {
+        location >= self.location && location < self.location + self.locations &&
+            component >= self.component && component < self.component + self.components
+    }
+}
+
+/// The scalar type an interface variable is made of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScalarType {
+    Float(u32),
+    /// Width and signedness.
+    Int(u32, bool),
+}
+
+/// How fragment shader inputs are interpolated across a primitive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Perspective correct, the default.
+    Smooth,
+    /// Linear in screen space.
+    NoPerspective,
+    /// Taken from the provoking vertex.
+    Flat,
+}
+
+/// The execution modes of an entry point. Modes that do not change how the pipeline runs the
+/// shader, such as ContractionOff, are accepted but not recorded.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExecutionModes {
+    /// Fragment shaders: (0, 0) is the upper left corner, which Vulkan requires.
+    pub origin_upper_left: bool,
+    /// Fragment shaders: FragCoord holds the integer pixel coordinates instead of the centers.
+    pub pixel_center_integer: bool,
+    /// Fragment shaders: depth and stencil tests run before the shader.
+    pub early_fragment_tests: bool,
+    /// Fragment shaders: the shader writes FragDepth.
+    pub depth_replacing: bool,
+    /// Fragment shaders: how the written depth relates to the interpolated one.
+    pub depth: Option<DepthMode>,
+    /// Compute shaders: the number of invocations of a workgroup in x, y and z.
+    pub local_size: Option<[u32; 3]>,
+}
+
+/// A promise of a fragment shader about the depth it writes, which keeps early depth tests
+/// possible.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DepthMode {
+    Greater,
+    Less,
+    Unchanged,
+}
+
+/// Rejects modules the transpiler cannot translate and returns their entry points.
+pub fn validate(module: &Module) -> Result<Vec<EntryPointInfo>, TranspilerError> {
+    check_header(module)?;
+    for inst in &module.capabilities {
+        match inst.operands.first() {
+            Some(&Operand::Capability(capability)) => {
+                if !CAPABILITIES.contains(&capability) {
+                    let error = TranspilerError::UnsupportedCapability(capability);
+                    return Err(error.at(location(inst)));
+                }
+            }
+            _ => return Err(invalid(inst)),
+        }
+    }
+    for inst in &module.extensions {
+        let name = operand_str(inst, 0).map_err(|error| error.at(location(inst)))?;
+        if !EXTENSIONS.contains(&name) {
+            let error = TranspilerError::UnsupportedExtension(name.to_owned());
+            return Err(error.at(location(inst)));
+        }
+    }
+    check_memory_model(module)?;
+    check_workgroup_variables(module)?;
+    entry_points(module)
+}
+
+fn check_header(module: &Module) -> Result<(), TranspilerError> {
+    let header = module.header.as_ref().ok_or(TranspilerError::NoHeader)?;
+    if header.magic_number != MAGIC_NUMBER {
+        return Err(TranspilerError::InvalidMagicNumber);
+    }
+    // The version word is 0 | major | minor | 0
+    let version = ((header.version >> 16) as u8, (header.version >> 8) as u8);
+    if version < (1, 0) || version > MAX_VERSION {
+        return Err(TranspilerError::UnsupportedVersion(version.0, version.1));
+    }
+    Ok(())
+}
+
+fn check_memory_model(module: &Module) -> Result<(), TranspilerError> {
+    let inst = module.memory_model.as_ref().ok_or(
+        TranspilerError::NoMemoryModelProvided,
+    )?;
+    let error = match (inst.operands.first(), inst.operands.get(1)) {
+        // Vulkan only allows Logical addressing, pointers are no values that can be stored
+        (Some(&Operand::AddressingModel(AddressingModel::Logical)),
+         Some(&Operand::MemoryModel(MemoryModel::GLSL450))) |
+        (Some(&Operand::AddressingModel(AddressingModel::Logical)),
+         Some(&Operand::MemoryModel(MemoryModel::Vulkan))) => return Ok(()),
+        (Some(&Operand::AddressingModel(AddressingModel::Logical)),
+         Some(&Operand::MemoryModel(memory_model))) => {
+            TranspilerError::UnsupportedMemoryModel(memory_model)
+        }
+        (Some(&Operand::AddressingModel(addressing_model)), Some(&Operand::MemoryModel(_))) => {
+            TranspilerError::UnsupportedAddressingModel(addressing_model)
+        }
+        _ => TranspilerError::InvalidInstruction(Op::MemoryModel),
+    };
+    Err(error.at(location(inst)))
+}
+
+/// Workgroup memory starts out zeroed for every workgroup, so Workgroup variables may only be
+/// initialized with OpConstantNull.
+fn check_workgroup_variables(module: &Module) -> Result<(), TranspilerError> {
+    let globals = &module.types_global_values;
+    for inst in globals {
+        if inst.class.opcode != Op::Variable ||
+            inst.operands.first() != Some(&Operand::StorageClass(StorageClass::Workgroup))
+        {
+            continue;
+        }
+        let initializer = match inst.operands.get(1) {
+            Some(_) => operand_id(inst, 1).map_err(|error| error.at(location(inst)))?,
+            None => continue,
+        };
+        let null = globals.iter().any(|def| {
+            def.result_id == Some(initializer) && def.class.opcode == Op::ConstantNull
+        });
+        if !null {
+            return Err(invalid(inst));
+        }
+    }
+    Ok(())
+}
+
+fn entry_points(module: &Module) -> Result<Vec<EntryPointInfo>, TranspilerError> {
+    let workgroup_size = workgroup_size(module)?;
+    let decorations = Decorations::from_annotations(&module.annotations)?;
+    let barriers = module.functions.iter().flat_map(|function| &function.basic_blocks).any(
+        |block| block.instructions.iter().any(|inst| inst.class.opcode == Op::ControlBarrier),
+    );
+    let mut globals = HashMap::new();
+    for inst in &module.types_global_values {
+        if let Some(id) = inst.result_id {
+            globals.insert(id, inst);
+        }
+    }
+    let mut entry_points = Vec::with_capacity(module.entry_points.len());
+    for inst in &module.entry_points {
+        let model = match inst.operands.first() {
+            Some(&Operand::ExecutionModel(model)) => model,
+            _ => return Err(invalid(inst)),
+        };
+        match model {
+            ExecutionModel::Vertex | ExecutionModel::Fragment | ExecutionModel::GLCompute => (),
+            _ => {
+                let error = TranspilerError::UnsupportedExecutionModel(model);
+                return Err(error.at(location(inst)));
+            }
+        }
+        let id = operand_id(inst, 1).map_err(|error| error.at(location(inst)))?;
+        let name = operand_str(inst, 2).map_err(|error| error.at(location(inst)))?;
+
+        let mut modes = ExecutionModes::default();
+        let execution_modes = module.execution_modes.iter().filter(|mode| {
+            operand_id(mode, 0).ok() == Some(id)
+        });
+        for inst in execution_modes {
+            add_execution_mode(&mut modes, inst).map_err(
+                |error| error.at(location(inst)),
+            )?;
+        }
+        if model == ExecutionModel::GLCompute && workgroup_size.is_some() {
+            modes.local_size = workgroup_size;
+        }
+
+        let mut inputs = Vec::new();
+        let mut outputs = Vec::new();
+        for interface in operand_ids(inst, 3).map_err(|error| error.at(location(inst)))? {
+            let variable = globals.get(&interface).ok_or_else(|| {
+                TranspilerError::UndefinedId(interface).at(location(inst))
+            })?;
+            let output = match variable.operands.first() {
+                Some(&Operand::StorageClass(StorageClass::Input)) => false,
+                Some(&Operand::StorageClass(StorageClass::Output)) => true,
+                _ => continue,
+            };
+            let variable = interface_variable(variable, &globals, &decorations).map_err(
+                |error| error.at(location(variable)),
+            )?;
+            match variable {
+                Some(variable) if output => outputs.push(variable),
+                Some(variable) => inputs.push(variable),
+                None => (),
+            }
+        }
+        entry_points.push(EntryPointInfo {
+            name: name.to_owned(),
+            model: model,
+            modes: modes,
+            inputs: inputs,
+            outputs: outputs,
+            barriers: barriers,
+        });
+    }
+    Ok(entry_points)
+}
+
+/// Returns the value of the constant decorated with the WorkgroupSize built-in, which takes
+/// precedence over the LocalSize of every compute shader. It is usually a specialization
+/// constant, in which case this is its default value unless the module was specialized.
+fn workgroup_size(module: &Module) -> Result<Option<[u32; 3]>, TranspilerError> {
+    let decoration = module.annotations.iter().find(|inst| {
+        inst.class.opcode == Op::Decorate &&
+            inst.operands.get(1) == Some(&Operand::Decoration(Decoration::BuiltIn)) &&
+            inst.operands.get(2) == Some(&Operand::BuiltIn(BuiltIn::WorkgroupSize))
+    });
+    let id = match decoration {
+        Some(inst) => operand_id(inst, 0).map_err(|error| error.at(location(inst)))?,
+        None => return Ok(None),
+    };
+    let constant = |id: Word| {
+        module.types_global_values.iter().find(|inst| inst.result_id == Some(id)).ok_or(
+            TranspilerError::UndefinedId(id),
+        )
+    };
+    let composite = constant(id)?;
+    let mut size = [0; 3];
+    for (index, size) in size.iter_mut().enumerate() {
+        let component = operand_id(composite, index).and_then(&constant);
+        *size = match component.as_ref().map(|inst| inst.class.opcode) {
+            Ok(Op::Constant) | Ok(Op::SpecConstant) => {
+                let inst = component?;
+                operand_u32(inst, 0).map_err(|error| error.at(location(inst)))?
+            }
+            _ => return Err(invalid(composite)),
+        };
+    }
+    Ok(Some(size))
+}
+
+/// Describes the Input or Output `variable`, unless it is a built-in.
+fn interface_variable(
+    variable: &Instruction,
+    globals: &HashMap<Word, &Instruction>,
+    decorations: &Decorations,
+) -> Result<Option<InterfaceVariable>, TranspilerError> {
+    let id = result_id(variable)?;
+    let def = |id: Word| globals.get(&id).cloned().ok_or(TranspilerError::UndefinedId(id));
+    let pointer = def(result_type(variable)?)?;
+    let mut ty = def(operand_id(pointer, 1)?)?;
+    if decorations.builtin(id).is_some() {
+        return Ok(None);
+    }
+    if ty.class.opcode == Op::TypeStruct &&
+        decorations.member_builtin(result_id(ty)?, 0).is_some()
+    {
+        return Ok(None);
+    }
+    let location = decorations.literal(id, Decoration::Location).ok_or(
+        TranspilerError::InvalidLayout(id),
+    )?;
+    let component = decorations.literal(id, Decoration::Component).unwrap_or(0);
+
+    let locations = locations(ty, &def)?;
+    // Arrays and matrices repeat their element, whose components are what each Location holds
+    while ty.class.opcode == Op::TypeArray || ty.class.opcode == Op::TypeMatrix {
+        ty = def(operand_id(ty, 0)?)?;
+    }
+    let (scalar, count) = match ty.class.opcode {
+        Op::TypeVector => (def(operand_id(ty, 0)?)?, operand_u32(ty, 1)?),
+        _ => (ty, 1),
+    };
+    let (scalar, width) = match scalar.class.opcode {
+        Op::TypeFloat => {
+            let width = operand_u32(scalar, 0)?;
+            (Some(ScalarType::Float(width)), width)
+        }
+        Op::TypeInt => {
+            let width = operand_u32(scalar, 0)?;
+            (Some(ScalarType::Int(width, operand_u32(scalar, 1)? != 0)), width)
+        }
+        Op::TypeStruct => (None, 128),
+        opcode => return Err(TranspilerError::UnsupportedType(opcode)),
+    };
+    let components = cmp::min((count * width + 31) / 32, 4);
+    if location + locations > abi::MAX_LOCATIONS as u32 || component + components > 4 {
+        return Err(TranspilerError::InvalidLayout(id));
+    }
+    let interpolation = if decorations.get(id, Decoration::Flat).is_some() {
+        Interpolation::Flat
+    } else if decorations.get(id, Decoration::NoPerspective).is_some() {
+        Interpolation::NoPerspective
+    } else {
+        Interpolation::Smooth
+    };
+    Ok(Some(InterfaceVariable {
+        location: location,
+        component: component,
+        locations: locations,
+        components: components,
+        scalar: scalar,
+        interpolation: interpolation,
+    }))
+}
+
+/// Number of Locations a variable of type `ty` takes.
+fn locations<'a, F>(ty: &Instruction, def: &F) -> Result<u32, TranspilerError>
+where
+    F: Fn(Word) -> Result<&'a Instruction, TranspilerError>,
+{
+    let locations = match ty.class.opcode {
+        Op::TypeVector => {
+            // 64 bit vectors with more than two components take two Locations
+            let component_width = operand_u32(def(operand_id(ty, 0)?)?, 0)?;
+            if component_width == 64 && operand_u32(ty, 1)? > 2 {
+                2
+            } else {
+                1
+            }
+        }
+        Op::TypeMatrix => operand_u32(ty, 1)? * locations(def(operand_id(ty, 0)?)?, def)?,
+        Op::TypeArray => {
+            let length = def(operand_id(ty, 1)?)?;
+            if length.class.opcode != Op::Constant {
+                return Err(TranspilerError::InvalidInstruction(Op::TypeArray));
+            }
+            operand_u32(length, 0)? * locations(def(operand_id(ty, 0)?)?, def)?
+        }
+        Op::TypeStruct => {
+            let mut locations_of_members = 0;
+            for member in operand_ids(ty, 0)? {
+                locations_of_members += locations(def(member)?, def)?;
+            }
+            locations_of_members
+        }
+        _ => 1,
+    };
+    Ok(locations)
+}
+
+fn add_execution_mode(
+    modes: &mut ExecutionModes,
+    inst: &Instruction,
+) -> Result<(), TranspilerError> {
+    let mode = match inst.operands.get(1) {
+        Some(&Operand::ExecutionMode(mode)) => mode,
+        _ => return Err(TranspilerError::InvalidInstruction(inst.class.opcode)),
+    };
+    match mode {
+        ExecutionMode::OriginUpperLeft => modes.origin_upper_left = true,
+        ExecutionMode::PixelCenterInteger => modes.pixel_center_integer = true,
+        ExecutionMode::EarlyFragmentTests => modes.early_fragment_tests = true,
+        ExecutionMode::DepthReplacing => modes.depth_replacing = true,
+        ExecutionMode::DepthGreater => modes.depth = Some(DepthMode::Greater),
+        ExecutionMode::DepthLess => modes.depth = Some(DepthMode::Less),
+        ExecutionMode::DepthUnchanged => modes.depth = Some(DepthMode::Unchanged),
+        ExecutionMode::LocalSize => {
+            modes.local_size = Some(
+                [operand_u32(inst, 2)?, operand_u32(inst, 3)?, operand_u32(inst, 4)?],
+            )
+        }
+        // We never contract floating point operations anyway
+        ExecutionMode::ContractionOff => (),
+        _ => return Err(TranspilerError::UnsupportedExecutionMode(mode)),
+    }
+    Ok(())
+}
+
+/// Where `inst` is. Debug instructions come after the sections validated here, so there is no
+/// source position yet.
+fn location(inst: &Instruction) -> Location {
+    Location {
+        opcode: inst.class.opcode,
+        result_id: inst.result_id,
+        line: None,
+    }
+}
+
+fn invalid(inst: &Instruction) -> TranspilerError {
+    TranspilerError::InvalidInstruction(inst.class.opcode).at(location(inst))
+}
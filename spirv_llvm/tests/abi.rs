@@ -0,0 +1,118 @@
+//! Runs small shaders on every backend to check that they find their inputs, outputs and
+//! resources where `spirv_llvm::abi` says they are.
+extern crate rspirv;
+extern crate spirv_llvm;
+
+use std::fs;
+use std::ptr;
+#[cfg(any(feature = "cranelift", feature = "interpreter"))]
+use std::sync::Arc;
+
+use spirv_llvm::abi::{Invocation, Resources, Slot};
+#[cfg(feature = "cranelift")]
+use spirv_llvm::CraneliftModule;
+#[cfg(feature = "interpreter")]
+use spirv_llvm::Interpreter;
+#[cfg(feature = "llvm")]
+use spirv_llvm::JitModule;
+use spirv_llvm::ShaderCode;
+
+/// Compiles shader `name` for every backend that was built in.
+fn compile(name: &str) -> Vec<ShaderCode> {
+    let path = format!("{}/tests/shaders/{}", env!("CARGO_MANIFEST_DIR"), name);
+    let bytes = fs::read(&path).expect("missing shader");
+    let module = rspirv::mr::load_bytes(bytes).expect("invalid shader");
+    let mut code = Vec::new();
+    #[cfg(feature = "llvm")]
+    {
+        let llvm = match spirv_llvm::spirv_to_llvm(&module) {
+            Ok(llvm) => llvm,
+            Err(error) => panic!("could not translate {}: {}", name, error),
+        };
+        match JitModule::new(llvm) {
+            Ok(jit) => code.push(ShaderCode::Jit(jit)),
+            Err(error) => panic!("could not compile {}: {}", name, error),
+        }
+    }
+    #[cfg(feature = "cranelift")]
+    match CraneliftModule::new(Arc::new(module.clone())) {
+        Ok(cranelift) => code.push(ShaderCode::Cranelift(cranelift)),
+        Err(error) => panic!("could not compile {} with Cranelift: {}", name, error),
+    }
+    #[cfg(feature = "interpreter")]
+    match Interpreter::new(Arc::new(module)) {
+        Ok(interpreter) => code.push(ShaderCode::Interpreted(interpreter)),
+        Err(error) => panic!("could not interpret {}: {}", name, error),
+    }
+    code
+}
+
+#[test]
+fn vertex_shader_interface() {
+    for code in compile("abi.vert.spv") {
+        // mat4 mvp (scale by 2), float scale[2] with a stride of 16
+        let mut ubo = [0f32; 24];
+        for column in 0..4 {
+            ubo[column * 5] = 2.0;
+        }
+        ubo[20] = 3.0;
+        let mut data = [[0f32; 8]; 2];
+        let mut ssbos = [
+            data[0].as_mut_ptr() as *mut u8,
+            data[1].as_mut_ptr() as *mut u8,
+        ];
+        let mut ubos = [ubo.as_mut_ptr() as *mut u8];
+        let set0 = [ubos.as_mut_ptr() as *const *mut u8];
+        let set1 = [ptr::null(), ptr::null(), ssbos.as_mut_ptr() as *const *mut u8];
+        let push_constants = [0.5f32, 0.0, 0.0, 0.0];
+
+        let mut resources = Resources::default();
+        resources.descriptor_sets[0] = set0.as_ptr();
+        resources.descriptor_sets[1] = set1.as_ptr();
+        resources.push_constants = push_constants.as_ptr() as *const u8;
+
+        let mut invocation = Invocation::new(&resources);
+        invocation.builtins.vertex_index = 5;
+        invocation.builtins.instance_index = 7;
+        invocation.inputs[0] = Slot::from_f32([1.0, 2.0, 3.0, 0.0]);
+        invocation.inputs[1] = Slot::from_f32([0.25, 0.5, 0.0, 0.0]);
+        invocation.inputs[2] = Slot::from_f32([1.0, 2.0, 0.0, 0.0]);
+        invocation.inputs[3] = Slot::from_f32([0.0, 0.0, 4.0, 0.0]);
+
+        let main = code.entry_point("main").expect("no entry point");
+        unsafe { main.call(&mut invocation) };
+
+        assert_eq!(invocation.builtins.position.0, [2.5, 4.0, 6.0, 2.0]);
+        assert_eq!(invocation.builtins.point_size, 7.0);
+        assert_eq!(&invocation.outputs[0].to_f32()[..2], &[1.25, 2.5]);
+        assert_eq!(data[1][5], 7.0);
+        assert_eq!(data[0], [0.0; 8]);
+        assert_eq!(invocation.killed, 0);
+    }
+}
+
+#[test]
+fn fragment_shader_interface() {
+    for code in compile("abi.frag.spv") {
+        let main = code.entry_point("main").expect("no entry point");
+        let resources = Resources::default();
+
+        let mut invocation = Invocation::new(&resources);
+        invocation.builtins.front_facing = 1;
+        invocation.inputs[0] = Slot::from_f32([1.0, 2.0, 3.0, 4.0]);
+        unsafe { main.call(&mut invocation) };
+        assert_eq!(invocation.killed, 0);
+        assert_eq!(invocation.outputs[0].to_f32(), [2.0, 4.0, 6.0, 8.0]);
+
+        // Private variables start over with every invocation
+        invocation.builtins.front_facing = 0;
+        unsafe { main.call(&mut invocation) };
+        assert_eq!(invocation.outputs[0].to_f32(), [2.0; 4]);
+
+        let mut invocation = Invocation::new(&resources);
+        invocation.builtins.frag_coord.0 = [20.5, 0.5, 0.0, 1.0];
+        unsafe { main.call(&mut invocation) };
+        assert_eq!(invocation.killed, 1);
+        assert_eq!(invocation.outputs[0].to_f32(), [0.0; 4]);
+    }
+}
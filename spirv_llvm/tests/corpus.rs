@@ -0,0 +1,407 @@
+//! Runs the shaders in `tests/corpus` on the host and checks what they write against known
+//! results.
+//!
+//! Every shader is a compute shader with a readonly input buffer at binding 0 and a buffer of
+//! 16 byte results at binding 1, both in set 0. The `.comp` files hold the GLSL the `.spv`
+//! files were assembled from, the `.spvasm` files their disassembly. Each shader runs one
+//! invocation at a time in every SIMD width, loaded from an object file, with Cranelift and on
+//! the interpreter, which all have to agree. Shaders that share memory run a whole workgroup,
+//! one thread per invocation.
+// The tests keep to the idioms of Rust 2015 and to the standard library of its time, like the
+// crate they test
+#![allow(clippy::manual_div_ceil)]
+
+extern crate rspirv;
+extern crate spirv_llvm;
+
+use std::collections::HashMap;
+use std::fs;
+use std::os::raw::c_void;
+#[cfg(any(feature = "cranelift", feature = "interpreter"))]
+use std::sync::Arc;
+use std::sync::Barrier;
+use std::thread;
+
+use rspirv::mr::Module;
+use spirv_llvm::abi::{Invocation, Resources};
+#[cfg(feature = "cranelift")]
+use spirv_llvm::CraneliftModule;
+#[cfg(feature = "interpreter")]
+use spirv_llvm::Interpreter;
+#[cfg(feature = "llvm")]
+use spirv_llvm::{JitModule, NativeCode};
+use spirv_llvm::ShaderCode;
+
+#[cfg(feature = "llvm")]
+const LANES: &[u32] = &[1, 4, 8, 16];
+
+/// Compiles shader `name` for every backend that was built in, and describes each.
+fn compile(name: &str, module: Module) -> Vec<(String, ShaderCode)> {
+    let mut code = Vec::new();
+    #[cfg(feature = "llvm")]
+    for &lanes in LANES {
+        let llvm = match spirv_llvm::spirv_to_llvm_simd(&module, lanes) {
+            Ok(llvm) => llvm,
+            Err(error) => panic!("could not translate {} for {} lanes: {}", name, lanes, error),
+        };
+        let jit = match JitModule::new(llvm) {
+            Ok(jit) => jit,
+            Err(error) => panic!("could not compile {} for {} lanes: {}", name, lanes, error),
+        };
+        code.push((format!("{} lanes", lanes), ShaderCode::Jit(jit)));
+    }
+    // Cached code goes through an object file and our own loader instead of MCJIT
+    #[cfg(feature = "llvm")]
+    {
+        if NativeCode::supported() {
+            let llvm = spirv_llvm::spirv_to_llvm_simd(&module, 4).expect("translated before");
+            let native = match NativeCode::compile(llvm) {
+                Ok(native) => NativeCode::from_bytes(&native.to_bytes()).expect("corrupt code"),
+                Err(error) => panic!("could not compile {} to an object: {}", name, error),
+            };
+            let jit = match JitModule::load(&native) {
+                Ok(jit) => jit,
+                Err(error) => panic!("could not load the object of {}: {}", name, error),
+            };
+            code.push(("the loaded object".to_owned(), ShaderCode::Jit(jit)));
+        }
+    }
+    #[cfg(feature = "cranelift")]
+    match CraneliftModule::new(Arc::new(module.clone())) {
+        Ok(cranelift) => code.push(("Cranelift".to_owned(), ShaderCode::Cranelift(cranelift))),
+        Err(error) => panic!("could not compile {} with Cranelift: {}", name, error),
+    }
+    #[cfg(feature = "interpreter")]
+    {
+        let interpreter = match Interpreter::new(Arc::new(module)) {
+            Ok(interpreter) => interpreter,
+            Err(error) => panic!("could not interpret {}: {}", name, error),
+        };
+        code.push(("the interpreter".to_owned(), ShaderCode::Interpreted(interpreter)));
+    }
+    code
+}
+
+fn load(name: &str) -> Module {
+    let path = format!("{}/tests/corpus/{}", env!("CARGO_MANIFEST_DIR"), name);
+    let bytes = fs::read(&path).expect("missing shader");
+    rspirv::mr::load_bytes(bytes).expect("invalid shader")
+}
+
+/// Runs shader `name` on `inputs` and returns its first `count` results.
+fn run(name: &str, inputs: &[u32], count: usize) -> Vec<[u32; 4]> {
+    run_module(name, load(name), inputs, count)
+}
+
+/// Like `run`, for a module that was already loaded.
+fn run_module(name: &str, module: Module, inputs: &[u32], count: usize) -> Vec<[u32; 4]> {
+    let mut expected: Option<Vec<[u32; 4]>> = None;
+    for (backend, code) in compile(name, module) {
+        let main = code.entry_point("main").expect("no entry point");
+
+        let mut inputs = inputs.to_vec();
+        let mut results = vec![[0u32; 4]; count];
+        let mut input_buffers = [inputs.as_mut_ptr() as *mut u8];
+        let mut result_buffers = [results.as_mut_ptr() as *mut u8];
+        let set = [
+            input_buffers.as_mut_ptr() as *const *mut u8,
+            result_buffers.as_mut_ptr() as *const *mut u8,
+        ];
+        let mut resources = Resources::default();
+        resources.descriptor_sets[0] = set.as_ptr();
+        let mut invocation = Invocation::new(&resources);
+        unsafe { main.call(&mut invocation) };
+
+        match expected {
+            Some(ref expected) => {
+                assert_eq!(&results, expected, "{} differs on {}", name, backend)
+            }
+            None => expected = Some(results),
+        }
+    }
+    expected.unwrap()
+}
+
+/// Resources that the threads of a workgroup share.
+struct SharedResources(Resources);
+
+unsafe impl Sync for SharedResources {}
+
+unsafe extern "C" fn wait(barrier: *mut c_void) {
+    (*(barrier as *const Barrier)).wait();
+}
+
+/// Like `run`, but runs `size` invocations of a workgroup at once, each on a thread of its own.
+fn run_workgroup(name: &str, inputs: &[u32], size: u32) -> Vec<[u32; 4]> {
+    let mut expected: Option<Vec<[u32; 4]>> = None;
+    for (backend, code) in compile(name, load(name)) {
+        let main = code.entry_point("main").expect("no entry point");
+
+        let mut inputs = inputs.to_vec();
+        let mut results = vec![[0u32; 4]; size as usize];
+        let mut input_buffers = [inputs.as_mut_ptr() as *mut u8];
+        let mut result_buffers = [results.as_mut_ptr() as *mut u8];
+        let set = [
+            input_buffers.as_mut_ptr() as *const *mut u8,
+            result_buffers.as_mut_ptr() as *const *mut u8,
+        ];
+        let mut workgroup_memory = vec![[0u64; 2]; (main.workgroup_memory() + 15) / 16];
+        let barrier = Barrier::new(size as usize);
+        let mut resources = Resources::default();
+        resources.descriptor_sets[0] = set.as_ptr();
+        resources.workgroup_memory = workgroup_memory.as_mut_ptr() as *mut u8;
+        resources.barrier = Some(wait);
+        resources.barrier_data = &barrier as *const Barrier as *mut c_void;
+        let resources = SharedResources(resources);
+        thread::scope(|scope| {
+            for index in 0..size {
+                let resources = &resources;
+                scope.spawn(move || {
+                    let mut invocation = Invocation::new(&resources.0);
+                    invocation.builtins.local_invocation_index = index;
+                    invocation.builtins.global_invocation_id.0[0] = index;
+                    unsafe { main.call(&mut invocation) };
+                });
+            }
+        });
+
+        match expected {
+            Some(ref expected) => {
+                assert_eq!(&results, expected, "{} differs on {}", name, backend)
+            }
+            None => expected = Some(results),
+        }
+    }
+    expected.unwrap()
+}
+
+fn words(values: &[f32]) -> Vec<u32> {
+    values.iter().map(|value| value.to_bits()).collect()
+}
+
+fn assert_floats(name: &str, results: &[[u32; 4]], expected: &[[f32; 4]]) {
+    for (index, (result, expected)) in results.iter().zip(expected).enumerate() {
+        let result = [
+            f32::from_bits(result[0]),
+            f32::from_bits(result[1]),
+            f32::from_bits(result[2]),
+            f32::from_bits(result[3]),
+        ];
+        let close = result.iter().zip(expected).all(|(&result, &expected)| {
+            (result.is_nan() && expected.is_nan()) ||
+                (result - expected).abs() <= 1e-5 * expected.abs().max(1.0)
+        });
+        assert!(close, "{}: result {} is {:?}, expected {:?}", name, index, result, expected);
+    }
+}
+
+fn assert_ints(name: &str, results: &[[u32; 4]], expected: &[[i32; 4]]) {
+    for (index, (result, expected)) in results.iter().zip(expected).enumerate() {
+        let result = [result[0] as i32, result[1] as i32, result[2] as i32, result[3] as i32];
+        assert_eq!(&result, expected, "{}: result {}", name, index);
+    }
+}
+
+#[test]
+fn float_arithmetic() {
+    let mut inputs = words(&[1.0, -2.0, 3.5, 8.0, 2.0, 4.0, -0.5, 3.0, 0.5]);
+    inputs.resize(12, 0);
+    let results = run("float.comp.spv", &inputs, 8);
+    assert_floats(
+        "float",
+        &results,
+        &[
+            [3.0, 2.0, 3.0, 11.0],
+            [-1.0, -6.0, 4.0, 5.0],
+            [2.0, -8.0, -1.75, 24.0],
+            [0.5, -0.5, -7.0, 8.0 / 3.0],
+            [-1.0, 2.0, -3.5, -8.0],
+            [1.0, 2.0, 0.0, 2.0],
+            [0.5, -1.0, 1.75, 4.0],
+            [16.25, 7.5, 0.0, 1.0],
+        ],
+    );
+}
+
+#[test]
+fn integer_arithmetic() {
+    let inputs = [
+        7, -7i32 as u32, 100, -1i32 as u32,
+        2, 2, -7i32 as u32, 5,
+        10, 0xffff_fff0, 1, 0x8000_0001,
+    ];
+    let results = run("integer.comp.spv", &inputs, 16);
+    assert_ints(
+        "integer",
+        &results,
+        &[
+            [9, -5, 93, 4],
+            [5, -9, 107, -6],
+            [14, -14, -700, -5],
+            [3, -3, -14, 0],
+            [1, 1, -5, 4],
+            [3, 1_431_655_760, 0, 715_827_883],
+            [0, 0, 1, 4],
+            [-7, 7, -100, 1],
+            [28, -28, 400, -4],
+            [3, -4, 50, -1],
+            [0, 0x0fff_ffff, 0, 0x0800_0000],
+            [2, -2, 99, -3],
+            [-8, 6, -101, 0],
+            [3, 30, 3, 32],
+            [3, -4, 2, -1],
+            [0x5000_0000, 0x0fff_ffff, i32::MIN, i32::MIN + 1],
+        ],
+    );
+}
+
+#[test]
+fn comparisons_and_logic() {
+    let mut inputs = words(&[1.0, 5.0, -2.0, f32::NAN, 2.0, 5.0, -3.0, f32::INFINITY]);
+    inputs.extend_from_slice(&[3, 0, 3, -3i32 as u32]);
+    let results = run("logic.comp.spv", &inputs, 6);
+    assert_floats(
+        "logic",
+        &results,
+        &[
+            [1.0, 0.0, 0.0, 0.0],
+            [1.0, 5.0, -3.0, f32::NAN],
+            [1.0, 0.0, 1.0, 0.0],
+            [1.0, 0.0, 1.0, 1.0],
+            [1.0, 0.0, 0.0, 1.0],
+            [-2.0, 2.0, 0.0, 0.0],
+        ],
+    );
+}
+
+#[test]
+fn conversions() {
+    let mut inputs = words(&[2.75, -3.5, 100.5, 7.0]);
+    inputs.extend_from_slice(&[-5i32 as u32, 0, 16_777_217, 42]);
+    inputs.extend_from_slice(&[0xffff_ffff, 1, 3_000_000_000, 0]);
+    let results = run("convert.comp.spv", &inputs, 7);
+    let bits = |values: [f32; 4]| {
+        [
+            values[0].to_bits() as i32,
+            values[1].to_bits() as i32,
+            values[2].to_bits() as i32,
+            values[3].to_bits() as i32,
+        ]
+    };
+    assert_ints(
+        "convert",
+        &results,
+        &[
+            [2, -3, 100, 7],
+            [2, 3, 100, 7],
+            bits([-5.0, 0.0, 16_777_216.0, 42.0]),
+            bits([4_294_967_296.0, 1.0, 3_000_000_000.0, 0.0]),
+            bits([2.75, -3.5, 100.5, 7.0]),
+            [8, (-3.5f32).to_bits() as i32, 0, 0],
+            [-4, 1, 0, 0],
+        ],
+    );
+}
+
+#[test]
+fn composites_and_matrices() {
+    // Column c of m is (4c + 1, 4c + 2, 4c + 3, 4c + 4), n has the columns (1, 2) and (3, 4)
+    let mut values: Vec<f32> = (1..17).map(|value| value as f32).collect();
+    values.extend_from_slice(&[1.0, -1.0, 2.0, 3.0, 1.0, 2.0, 3.0, 4.0]);
+    let results = run("composite.comp.spv", &words(&values), 9);
+    assert_floats(
+        "composite",
+        &results,
+        &[
+            [3.0, 2.0, -1.0, 1.0],
+            [1.0, -1.0, 3.0, 4.0],
+            [53.0, 58.0, 63.0, 68.0],
+            [17.0, 37.0, 57.0, 77.0],
+            [332.0, 368.0, 404.0, 440.0],
+            [26.0, 28.0, 30.0, 32.0],
+            [2.0, 4.0, 3.0, 6.0],
+            [1.0, 9.0, 2.0, 3.0],
+            [-1.0, 2.0, 0.0, 0.0],
+        ],
+    );
+}
+
+#[test]
+fn control_flow() {
+    let results = run("control_flow.comp.spv", &[10, 6], 2);
+    assert_ints("control_flow", &results, &[[25, 8, 10, 20], [30, 15, 0, 0]]);
+}
+
+#[test]
+fn glsl_std_450() {
+    let inputs = words(&[16.0, 4.0, 2.0, 3.0, -1.5, -2.0, 2.25, -2.7]);
+    let results = run("glsl.comp.spv", &inputs, 8);
+    assert_floats(
+        "glsl",
+        &results,
+        &[
+            [4.0, 0.5, 1.5, -1.0],
+            [2.0, 3.0, 0.25, -2.0],
+            [-1.0, -1.0, 1.0, -1.0],
+            [11.625, 2.5, 2.0625, 1.575],
+            [8.0, 8.0, 1.0, 16.492_424],
+            [0.970_142_5, 0.242_535_63, 2.0, 2.25],
+            [13.0, -39.0, -26.0, 0.0],
+            [66.0, 0.5, 18.5, 2.0],
+        ],
+    );
+}
+
+#[test]
+fn variables_and_memory() {
+    let mut inputs = vec![2];
+    inputs.extend(words(&[1.5, 2.0, -1.0, 4.0]));
+    let results = run("memory.comp.spv", &inputs, 2);
+    assert_floats("memory", &results, &[[1.5, 4.0, -3.0, 4.0], [1.5, -3.0, 7.0, 8.0]]);
+}
+
+#[test]
+fn specialization_constants() {
+    let inputs = words(&[1.5]);
+    let module = load("specialization.comp.spv");
+    let entry_points = spirv_llvm::validate(&module).expect("invalid shader");
+    assert_eq!(entry_points[0].modes.local_size, Some([1, 2, 1]));
+    let defaults = spirv_llvm::specialize(&module, &HashMap::new()).expect("invalid shader");
+    let results = run_module("specialization", defaults, &inputs, 1);
+    assert_floats("specialization", &results, &[[3.0, 3.0, 6.0, 1.0]]);
+
+    let mut values = HashMap::new();
+    values.insert(0, 8u32.to_ne_bytes().to_vec());
+    values.insert(1, 1u32.to_ne_bytes().to_vec());
+    values.insert(2, 5i32.to_ne_bytes().to_vec());
+    values.insert(3, 0.5f32.to_ne_bytes().to_vec());
+    let specialized = spirv_llvm::specialize(&module, &values).expect("invalid shader");
+    let entry_points = spirv_llvm::validate(&specialized).expect("invalid shader");
+    assert_eq!(entry_points[0].modes.local_size, Some([8, 2, 1]));
+    let results = run_module("specialization", specialized, &inputs, 1);
+    assert_floats("specialization", &results, &[[-0.75, 5.0, 10.0, 8.0]]);
+
+    // Values have to be as large as the constant they are for
+    values.insert(2, vec![5]);
+    let error = spirv_llvm::specialize(&module, &values).expect_err("accepted a single byte");
+    match *error.kind() {
+        spirv_llvm::TranspilerError::InvalidSpecialization(2) => (),
+        ref kind => panic!("unexpected error {}", kind),
+    }
+}
+
+#[test]
+fn workgroup_memory_and_barriers() {
+    let inputs = words(&[1.0, 2.0, 3.0, 4.0]);
+    let results = run_workgroup("shared.comp.spv", &inputs, 4);
+    assert_floats(
+        "shared",
+        &results,
+        &[
+            [1.0, 2.0, 4.0, 2.5],
+            [2.0, 3.0, 1.0, 3.5],
+            [3.0, 4.0, 2.0, 4.5],
+            [4.0, 1.0, 3.0, 5.5],
+        ],
+    );
+}
@@ -0,0 +1,43 @@
+//! Checks that `SPIRV_LLVM_DEBUG_INFO` maps OpLine and OpName to debug info that JIT'd code can
+//! be debugged with.
+#![cfg(feature = "llvm")]
+extern crate rspirv;
+extern crate spirv_llvm;
+
+use std::env;
+use std::fs;
+
+use spirv_llvm::{JitModule, DEBUG_INFO_VARIABLE};
+
+// The variable is global to the process, so everything that depends on it is in a single test.
+#[test]
+fn debug_info_follows_the_variable() {
+    let path = format!("{}/tests/shaders/lines.frag.spv", env!("CARGO_MANIFEST_DIR"));
+    let bytes = fs::read(&path).expect("missing shader");
+    let module = rspirv::mr::load_bytes(bytes).expect("invalid shader");
+
+    env::remove_var(DEBUG_INFO_VARIABLE);
+    let plain = spirv_llvm::spirv_to_llvm(&module).expect("could not translate").to_string();
+    assert!(!plain.contains("!dbg"), "{}", plain);
+
+    env::set_var(DEBUG_INFO_VARIABLE, "1");
+    let translated = spirv_llvm::spirv_to_llvm(&module).map(|llvm| (llvm.to_string(), llvm));
+    let simd = spirv_llvm::spirv_to_llvm_simd(&module, 4);
+    // Code with debug info is not optimized, so that it can be stepped through
+    let compiled = translated.map(|(ir, llvm)| (ir, JitModule::new(llvm)));
+    env::remove_var(DEBUG_INFO_VARIABLE);
+    let (ir, compiled) = compiled.expect("could not translate with debug info");
+    assert!(ir.contains("!DIFile(filename: \"tri.frag\", directory: \"shaders\")"), "{}", ir);
+    assert!(ir.contains("!DISubprogram(name: \"main\""), "{}", ir);
+    assert!(ir.contains("!DISubprogram(name: \"half_of\""), "{}", ir);
+    assert!(ir.contains("!DILocation(line: 10, column: 11"), "{}", ir);
+    assert!(ir.contains("!DILocalVariable(name: \"color\""), "{}", ir);
+    assert!(ir.contains("!DILocalVariable(name: \"scale\""), "{}", ir);
+
+    let simd = simd.expect("could not translate SIMD code with debug info").to_string();
+    assert!(simd.contains("!DILocation(line: 10, column: 11"), "{}", simd);
+
+    if let Err(error) = compiled {
+        panic!("could not compile with debug info: {}", error);
+    }
+}
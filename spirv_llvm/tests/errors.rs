@@ -0,0 +1,174 @@
+//! Checks that errors point at the offending instruction, whichever backend finds them.
+extern crate rspirv;
+extern crate spirv_headers;
+extern crate spirv_llvm;
+
+use std::fs;
+#[cfg(feature = "interpreter")]
+use std::sync::Arc;
+
+use spirv_headers::Op;
+use spirv_llvm::{SourceLine, TranspilerError};
+
+#[test]
+fn errors_carry_their_location() {
+    let path = format!("{}/tests/shaders/undefined.frag.spv", env!("CARGO_MANIFEST_DIR"));
+    let bytes = fs::read(&path).expect("missing shader");
+    let module = rspirv::mr::load_bytes(bytes).expect("invalid shader");
+    let mut errors = Vec::new();
+    #[cfg(feature = "llvm")]
+    match spirv_llvm::spirv_to_llvm(&module) {
+        Ok(_) => panic!("translated a module with an undefined id"),
+        Err(error) => errors.push(error),
+    }
+    #[cfg(feature = "interpreter")]
+    match spirv_llvm::Interpreter::new(Arc::new(module)) {
+        Ok(_) => panic!("interpreted a module with an undefined id"),
+        Err(error) => errors.push(error),
+    }
+
+    for error in errors {
+        let location = error.location().expect("no location");
+        assert_eq!(location.opcode, Op::FAdd);
+        let id = location.result_id.expect("no result id");
+        let line = SourceLine {
+            file: Some("broken.frag".to_owned()),
+            line: 3,
+            column: 7,
+        };
+        assert_eq!(location.line, Some(line));
+        match *error.kind() {
+            TranspilerError::UndefinedId(_) => (),
+            ref kind => panic!("unexpected error {}", kind),
+        }
+        let suffix = format!("at OpFAdd %{} (broken.frag:3:7)", id);
+        assert!(error.to_string().ends_with(&suffix), "{}", error);
+    }
+}
+
+#[cfg(feature = "interpreter")]
+fn load(path: &str) -> rspirv::mr::Module {
+    let path = format!("{}/tests/{}", env!("CARGO_MANIFEST_DIR"), path);
+    rspirv::mr::load_bytes(fs::read(&path).expect("missing shader")).expect("invalid shader")
+}
+
+#[cfg(feature = "interpreter")]
+#[test]
+fn rejects_values_from_blocks_that_do_not_dominate() {
+    let mut module = load("shaders/divergent.frag.spv");
+    // The first addition to `acc` only happens in the loop, not before the output is built
+    let added = {
+        let main = &module.functions[1];
+        let add = main.basic_blocks
+            .iter()
+            .flat_map(|block| &block.instructions)
+            .find(|inst| inst.class.opcode == Op::FAdd)
+            .expect("no addition");
+        add.result_id.expect("no result id")
+    };
+    for block in &mut module.functions[1].basic_blocks {
+        for inst in &mut block.instructions {
+            if inst.class.opcode == Op::CompositeConstruct {
+                inst.operands[0] = rspirv::mr::Operand::IdRef(added);
+            }
+        }
+    }
+    let error = match spirv_llvm::Interpreter::new(Arc::new(module)) {
+        Ok(_) => panic!("interpreted a value used where it may not be defined"),
+        Err(error) => error,
+    };
+    assert_eq!(error.location().map(|location| location.opcode), Some(Op::CompositeConstruct));
+    match *error.kind() {
+        TranspilerError::UndefinedId(id) if id == added => (),
+        ref kind => panic!("unexpected error {}", kind),
+    }
+}
+
+#[cfg(feature = "interpreter")]
+#[test]
+fn rejects_phis_that_miss_a_predecessor() {
+    let mut module = load("shaders/divergent.frag.spv");
+    for block in &mut module.functions[1].basic_blocks {
+        for inst in &mut block.instructions {
+            if inst.class.opcode == Op::Phi {
+                // Drop the value that comes from the continue block
+                inst.operands.truncate(2);
+            }
+        }
+    }
+    let error = match spirv_llvm::Interpreter::new(Arc::new(module)) {
+        Ok(_) => panic!("interpreted a phi without a value for every predecessor"),
+        Err(error) => error,
+    };
+    assert_eq!(error.location().map(|location| location.opcode), Some(Op::Phi));
+    match *error.kind() {
+        TranspilerError::InvalidInstruction(Op::Phi) => (),
+        ref kind => panic!("unexpected error {}", kind),
+    }
+}
+
+#[cfg(feature = "interpreter")]
+#[test]
+fn rejects_operands_of_the_wrong_type() {
+    let mut module = load("shaders/divergent.frag.spv");
+    // Add the floats as if they were integers
+    for block in &mut module.functions[1].basic_blocks {
+        for inst in &mut block.instructions {
+            if inst.class.opcode == Op::FAdd {
+                inst.class = rspirv::grammar::CoreInstructionTable::get(Op::IAdd);
+            }
+        }
+    }
+    let error = match spirv_llvm::Interpreter::new(Arc::new(module)) {
+        Ok(_) => panic!("interpreted an integer addition of floats"),
+        Err(error) => error,
+    };
+    assert_eq!(error.location().map(|location| location.opcode), Some(Op::IAdd));
+    match *error.kind() {
+        TranspilerError::InvalidInstruction(Op::IAdd) => (),
+        ref kind => panic!("unexpected error {}", kind),
+    }
+}
+
+#[cfg(feature = "interpreter")]
+#[test]
+fn unreachable_ends_the_invocation() {
+    use spirv_llvm::abi::{Invocation, Resources};
+    use spirv_llvm::ShaderCode;
+
+    let mut module = load("corpus/control_flow.comp.spv");
+    {
+        // Stop right after the first result is stored, the last store before that of the second
+        let merge = module.functions[0].basic_blocks.last_mut().expect("no blocks");
+        let stores = merge.instructions
+            .iter()
+            .enumerate()
+            .filter(|&(_, inst)| inst.class.opcode == Op::Store)
+            .map(|(position, _)| position)
+            .collect::<Vec<_>>();
+        let store = stores[stores.len() - 2];
+        merge.instructions.truncate(store + 2);
+        let stop = &mut merge.instructions[store + 1];
+        stop.class = rspirv::grammar::CoreInstructionTable::get(Op::Unreachable);
+        stop.result_type = None;
+        stop.result_id = None;
+        stop.operands.clear();
+    }
+    let interpreter = spirv_llvm::Interpreter::new(Arc::new(module)).expect("rejected");
+    let code = ShaderCode::Interpreted(interpreter);
+    let main = code.entry_point("main").expect("no entry point");
+
+    let mut inputs = [10u32, 6];
+    let mut results = [[0u32; 4]; 2];
+    let mut input_buffers = [inputs.as_mut_ptr() as *mut u8];
+    let mut result_buffers = [results.as_mut_ptr() as *mut u8];
+    let set = [
+        input_buffers.as_mut_ptr() as *const *mut u8,
+        result_buffers.as_mut_ptr() as *const *mut u8,
+    ];
+    let mut resources = Resources::default();
+    resources.descriptor_sets[0] = set.as_ptr();
+    let mut invocation = Invocation::new(&resources);
+    unsafe { main.call(&mut invocation) };
+    assert_eq!(results, [[25, 8, 10, 20], [0, 0, 0, 0]]);
+}
@@ -0,0 +1,87 @@
+//! Checks that shaders compiled for SIMD lanes compute the same as the scalar ones, no matter
+//! how the lanes diverge.
+#![cfg(feature = "llvm")]
+extern crate rspirv;
+extern crate spirv_llvm;
+
+use std::fs;
+
+use spirv_llvm::abi::{Invocation, Resources, Slot};
+use spirv_llvm::JitModule;
+
+fn compile(name: &str, lanes: u32) -> JitModule {
+    let path = format!("{}/tests/shaders/{}", env!("CARGO_MANIFEST_DIR"), name);
+    let bytes = fs::read(&path).expect("missing shader");
+    let module = rspirv::mr::load_bytes(bytes).expect("invalid shader");
+    let llvm = match lanes {
+        1 => spirv_llvm::spirv_to_llvm(&module),
+        _ => spirv_llvm::spirv_to_llvm_simd(&module, lanes),
+    };
+    let llvm = match llvm {
+        Ok(llvm) => llvm,
+        Err(error) => panic!("could not translate {} for {} lanes: {}", name, lanes, error),
+    };
+    match JitModule::new(llvm) {
+        Ok(jit) => jit,
+        Err(error) => panic!("could not compile {} for {} lanes: {}", name, lanes, error),
+    }
+}
+
+/// Runs `invocations` through the shader compiled for every lane width and compares the results
+/// with the scalar shader. Every third invocation is masked off and has to stay untouched.
+fn compare(name: &str, invocations: &[Invocation]) {
+    let mask = (0..invocations.len())
+        .filter(|index| index % 3 != 2)
+        .fold(0u32, |mask, index| mask | 1 << index);
+
+    let scalar = compile(name, 1);
+    let mut expected = invocations.to_vec();
+    unsafe {
+        scalar.entry_point("main").expect("no entry point").call_batch(&mut expected, mask)
+    };
+
+    for &lanes in &[4, 8, 16] {
+        let jit = compile(name, lanes);
+        let main = jit.entry_point("main").expect("no entry point");
+        assert_eq!(main.lanes(), lanes);
+        let mut actual = invocations.to_vec();
+        unsafe { main.call_batch(&mut actual, mask) };
+        for (index, (actual, expected)) in actual.iter().zip(&expected).enumerate() {
+            assert_eq!(actual.outputs, expected.outputs, "{} lanes, invocation {}", lanes, index);
+            assert_eq!(actual.killed, expected.killed, "{} lanes, invocation {}", lanes, index);
+        }
+    }
+}
+
+#[test]
+fn divergent_control_flow() {
+    let resources = Resources::default();
+    let invocations = (0..24)
+        .map(|index| {
+            let mut invocation = Invocation::new(&resources);
+            let trips = (index * 5 % 9) as f32;
+            let step = (index % 4) as f32 * 1.5;
+            let case = (index % 3) as f32;
+            let sign = if index % 7 == 4 { -1.0 } else { 1.0 };
+            invocation.inputs[0] = Slot::from_f32([trips, step, case, sign]);
+            invocation.outputs[0] = Slot::from_f32([-1.0; 4]);
+            invocation
+        })
+        .collect::<Vec<_>>();
+    compare("divergent.frag.spv", &invocations);
+}
+
+#[test]
+fn private_variables_and_discard() {
+    let resources = Resources::default();
+    let invocations = (0..16)
+        .map(|index| {
+            let mut invocation = Invocation::new(&resources);
+            invocation.builtins.frag_coord.0 = [index as f32 * 1.5, 0.5, 0.0, 1.0];
+            invocation.builtins.front_facing = index % 2;
+            invocation.inputs[0] = Slot::from_f32([index as f32, 2.0, 3.0, 4.0]);
+            invocation
+        })
+        .collect::<Vec<_>>();
+    compare("abi.frag.spv", &invocations);
+}
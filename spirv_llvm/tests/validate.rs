@@ -0,0 +1,79 @@
+//! Checks the up-front validation of modules and the summary of their entry points.
+// The tests keep to the idioms of Rust 2015, like the crate they test
+#![allow(clippy::redundant_field_names)]
+
+extern crate rspirv;
+extern crate spirv_headers;
+extern crate spirv_llvm;
+
+use std::fs;
+
+use rspirv::mr::{Module, Operand};
+use spirv_headers::{Capability, ExecutionModel, Op};
+use spirv_llvm::{InterfaceVariable, Interpolation, ScalarType, TranspilerError};
+
+fn load(name: &str) -> Module {
+    let path = format!("{}/tests/shaders/{}", env!("CARGO_MANIFEST_DIR"), name);
+    let bytes = fs::read(&path).expect("missing shader");
+    rspirv::mr::load_bytes(bytes).expect("invalid shader")
+}
+
+#[test]
+fn summarizes_entry_points() {
+    let entry_points = spirv_llvm::validate(&load("abi.frag.spv")).expect("invalid module");
+    assert_eq!(entry_points.len(), 1);
+    assert_eq!(entry_points[0].name, "main");
+    assert_eq!(entry_points[0].model, ExecutionModel::Fragment);
+    assert!(entry_points[0].modes.origin_upper_left);
+    assert_eq!(entry_points[0].modes.local_size, None);
+    assert!(!entry_points[0].barriers);
+}
+
+#[test]
+fn notices_barriers() {
+    let path = format!("{}/tests/corpus/shared.comp.spv", env!("CARGO_MANIFEST_DIR"));
+    let module = rspirv::mr::load_bytes(fs::read(&path).expect("missing shader"))
+        .expect("invalid shader");
+    let entry_points = spirv_llvm::validate(&module).expect("invalid module");
+    assert_eq!(entry_points[0].modes.local_size, Some([4, 1, 1]));
+    assert!(entry_points[0].barriers);
+}
+
+#[test]
+fn lists_the_interface_without_built_ins() {
+    let entry_points = spirv_llvm::validate(&load("abi.vert.spv")).expect("invalid module");
+    let variable = |location, component, locations, components| {
+        InterfaceVariable {
+            location: location,
+            component: component,
+            locations: locations,
+            components: components,
+            scalar: Some(ScalarType::Float(32)),
+            interpolation: Interpolation::Smooth,
+        }
+    };
+    // vec3 pos, vec2 uv[2] and the float w in the third component of Location 3
+    assert_eq!(
+        entry_points[0].inputs,
+        vec![variable(0, 0, 1, 3), variable(1, 0, 2, 2), variable(3, 2, 1, 1)]
+    );
+    assert_eq!(entry_points[0].outputs, vec![variable(0, 0, 1, 2)]);
+    assert!(entry_points[0].inputs[2].covers(3, 2));
+    assert!(!entry_points[0].inputs[2].covers(3, 3));
+}
+
+#[test]
+fn rejects_unsupported_capabilities() {
+    let mut module = load("abi.frag.spv");
+    module.capabilities[0].operands[0] = Operand::Capability(Capability::Geometry);
+    let error = spirv_llvm::validate(&module).expect_err("accepted a geometry shader");
+    assert_eq!(error.location().map(|location| location.opcode), Some(Op::Capability));
+    match *error.kind() {
+        TranspilerError::UnsupportedCapability(Capability::Geometry) => (),
+        ref kind => panic!("unexpected error {}", kind),
+    }
+    #[cfg(feature = "llvm")]
+    assert!(spirv_llvm::spirv_to_llvm(&module).is_err());
+    #[cfg(feature = "interpreter")]
+    assert!(spirv_llvm::Interpreter::new(::std::sync::Arc::new(module)).is_err());
+}